@@ -0,0 +1,23 @@
+// Renders the standard scenes offscreen and prints the average frame times.
+// Run with `cargo bench --bench render`. When built as a test only a single
+// frame of each scene is rendered to check that the scenes still work.
+
+mod scenes;
+
+use scenes::{create_renderer, measure, report, standard_scenes};
+
+const FRAMES: u32 = 100;
+
+fn main() {
+    let frames = if std::env::args().any(|arg| arg == "--bench") {
+        FRAMES
+    } else {
+        1
+    };
+
+    let mut renderer = create_renderer();
+    for (name, scene) in standard_scenes() {
+        let timings = measure(&mut renderer, &scene, frames);
+        report(name, &timings, frames);
+    }
+}
@@ -0,0 +1,175 @@
+// Standard scenes and the timing loop shared by the render bench and the
+// vide-bench example
+
+use std::time::{Duration, Instant};
+
+use glam::{vec2, vec4, Vec4};
+use vide::{Layer, OffscreenRenderer, Quad, Scene, Text};
+use wgpu::{Maintain, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages};
+
+pub const WIDTH: u32 = 1920;
+pub const HEIGHT: u32 = 1080;
+
+pub fn standard_scenes() -> Vec<(&'static str, Scene)> {
+    vec![
+        ("quads_10k", quads(10_000)),
+        ("glyphs_100k", glyphs(100_000)),
+        ("heavy_blur", heavy_blur()),
+        ("deep_layers", deep_layers(500)),
+    ]
+}
+
+// Colors cycling through the hue so that neighbouring primitives differ
+fn color(index: usize) -> Vec4 {
+    let t = index as f32 * 0.618;
+    vec4(
+        0.5 + 0.5 * t.sin(),
+        0.5 + 0.5 * (t + 2.1).sin(),
+        0.5 + 0.5 * (t + 4.2).sin(),
+        1.0,
+    )
+}
+
+// Small rounded quads tiling the surface
+pub fn quads(count: usize) -> Scene {
+    let columns = 125;
+    let mut scene = Scene::new();
+    for index in 0..count {
+        let position = vec2(
+            (index % columns) as f32 * 15.,
+            (index / columns) as f32 * 13. % HEIGHT as f32,
+        );
+        scene.add_quad(Quad::new(position, vec2(12., 10.), color(index)).with_corner_radius(3.));
+    }
+    scene
+}
+
+// Lines of 100 characters, overlapping once the surface is full
+pub fn glyphs(count: usize) -> Scene {
+    let line: String = ('a'..='z').cycle().take(100).collect();
+    let lines_per_column = HEIGHT as usize / 10;
+    let mut scene = Scene::new();
+    for index in 0..count / line.len() {
+        let position = vec2(
+            (index / lines_per_column) as f32 * 60. % WIDTH as f32,
+            (index % lines_per_column + 1) as f32 * 10.,
+        );
+        scene.add_text(Text::new(line.clone(), position, 9., color(index)));
+    }
+    scene
+}
+
+// Overlapping layers which blur everything drawn beneath them
+pub fn heavy_blur() -> Scene {
+    let mut scene = quads(2_000);
+    for index in 0..20 {
+        let offset = index as f32 * 40.;
+        scene.add_layer(
+            Layer::new()
+                .with_clip(vec4(offset, offset, 800., 600.))
+                .with_blur(24.)
+                .with_background(vec4(1., 1., 1., 0.2))
+                .with_quad(Quad::new(
+                    vec2(offset + 20., offset + 20.),
+                    vec2(200., 100.),
+                    color(index),
+                )),
+        );
+    }
+    scene
+}
+
+// Many clipped and scrolled layers stacked on top of each other, like deeply
+// nested scroll views
+pub fn deep_layers(count: usize) -> Scene {
+    let mut scene = Scene::new();
+    for index in 0..count {
+        let inset = (index % 200) as f32 * 2.;
+        scene.add_layer(
+            Layer::new()
+                .with_clip(vec4(
+                    inset,
+                    inset,
+                    WIDTH as f32 - inset * 2.,
+                    HEIGHT as f32 - inset * 2.,
+                ))
+                .with_scroll_offset(vec2(0., index as f32))
+                .with_background(color(index) * vec4(1., 1., 1., 0.5))
+                .with_quad(Quad::new(
+                    vec2(inset + 10., inset + 10. + index as f32),
+                    vec2(100., 20.),
+                    color(index + 1),
+                ))
+                .with_text(Text::new(
+                    format!("Layer {index}"),
+                    vec2(inset + 10., inset + 50. + index as f32),
+                    14.,
+                    vec4(0., 0., 0., 1.),
+                )),
+        );
+    }
+    scene
+}
+
+// Average times of one frame. The cpu time is spent preparing and submitting
+// the frame, the gpu time waiting for the submitted work to finish afterwards.
+pub struct Timings {
+    pub cpu: Duration,
+    pub gpu: Duration,
+}
+
+pub fn create_renderer() -> OffscreenRenderer {
+    smol::block_on(OffscreenRenderer::new(WIDTH, HEIGHT))
+        .unwrap()
+        .with_builtin_drawables()
+}
+
+// Renders the scene once to fill the caches and atlases, then times the given
+// number of frames
+pub fn measure(renderer: &mut OffscreenRenderer, scene: &Scene, frames: u32) -> Timings {
+    let target = create_target(renderer);
+    renderer.renderer.render(scene, &target);
+    renderer.renderer.device.poll(Maintain::Wait);
+
+    let mut cpu = Duration::ZERO;
+    let mut gpu = Duration::ZERO;
+    for _ in 0..frames {
+        let start = Instant::now();
+        renderer.renderer.render(scene, &target);
+        let submitted = Instant::now();
+        renderer.renderer.device.poll(Maintain::Wait);
+        cpu += submitted - start;
+        gpu += submitted.elapsed();
+    }
+
+    let frames = frames.max(1);
+    Timings {
+        cpu: cpu / frames,
+        gpu: gpu / frames,
+    }
+}
+
+fn create_target(renderer: &OffscreenRenderer) -> Texture {
+    renderer.renderer.device.create_texture(&TextureDescriptor {
+        label: Some("Bench Target"),
+        size: wgpu::Extent3d {
+            width: WIDTH,
+            height: HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+pub fn report(name: &str, timings: &Timings, frames: u32) {
+    println!(
+        "{name:<12} cpu {:>8.3} ms  gpu {:>8.3} ms  ({frames} frames)",
+        timings.cpu.as_secs_f64() * 1000.,
+        timings.gpu.as_secs_f64() * 1000.,
+    );
+}
@@ -0,0 +1,79 @@
+use futures::executor::block_on;
+
+use glam::{vec2, vec4};
+use rust_embed::RustEmbed;
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+use vide::{EmbeddedAssets, Quad, Scene, WinitRenderer};
+
+#[derive(RustEmbed)]
+#[folder = "../../test_data/assets"]
+struct Assets;
+
+// Draws a simple scene and keeps drawing it across suspend and resume. On
+// Android the native window is destroyed whenever the app goes to the
+// background, so this exercises releasing and recreating the surface.
+pub fn run(event_loop: EventLoop<()>) {
+    event_loop.set_control_flow(ControlFlow::Wait);
+
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut renderer = block_on(WinitRenderer::new(&window))
+        .unwrap()
+        .with_default_drawables(EmbeddedAssets::<Assets>::new());
+    let mut resumed_count = 0;
+
+    event_loop
+        .run(|event, target| {
+            renderer.handle_event(&window, &event);
+
+            match event {
+                Event::Resumed => {
+                    resumed_count += 1;
+                    log::info!("Resumed {resumed_count} times");
+                }
+                Event::Suspended => {
+                    log::info!("Suspended");
+                }
+                Event::WindowEvent { ref event, .. } => match event {
+                    WindowEvent::CloseRequested => target.exit(),
+                    WindowEvent::RedrawRequested => {
+                        let size = window.inner_size();
+                        let size = vec2(size.width as f32, size.height as f32);
+                        let scene = Scene::new().with_quad(
+                            Quad::new(size * 0.25, size * 0.5, vec4(0.2, 0.4, 1.0, 1.0))
+                                .with_corner_radius(size.min_element() * 0.05),
+                        );
+
+                        let outcome = renderer.draw(&scene);
+                        if outcome.is_fatal() {
+                            log::error!("Could not draw: {outcome:?}");
+                            target.exit();
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        })
+        .expect("Could not run event loop");
+}
+
+#[cfg(target_os = "android")]
+#[no_mangle]
+fn android_main(app: winit::platform::android::activity::AndroidApp) {
+    use winit::{event_loop::EventLoopBuilder, platform::android::EventLoopBuilderExtAndroid};
+
+    android_logger::init_once(
+        android_logger::Config::default().with_max_level(log::LevelFilter::Info),
+    );
+
+    let event_loop = EventLoopBuilder::new()
+        .with_android_app(app)
+        .build()
+        .expect("Couldn't create event loop");
+    run(event_loop);
+}
@@ -0,0 +1,10 @@
+use winit::event_loop::EventLoop;
+
+// Runs the example on desktop platforms so the lifecycle handling can be
+// tested by minimizing and restoring the window
+fn main() {
+    env_logger::init();
+
+    let event_loop = EventLoop::new().expect("Couldn't create event loop");
+    android_example::run(event_loop);
+}
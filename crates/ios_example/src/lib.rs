@@ -0,0 +1,82 @@
+use futures::executor::block_on;
+
+use glam::{vec2, vec4};
+use rust_embed::RustEmbed;
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+use vide::{EmbeddedAssets, Quad, Scene, Text, WinitRenderer};
+
+#[derive(RustEmbed)]
+#[folder = "../../test_data/assets"]
+struct Assets;
+
+// Draws a simple scene in points, so that it has the same size on Retina
+// screens as on others. On iOS and iPadOS winit puts a CAMetalLayer in the
+// view of the window, which the renderer draws to with Metal. The renderer
+// releases its surface when the app goes to the background and its cached
+// glyphs and images on memory warnings.
+pub fn run(event_loop: EventLoop<()>) {
+    event_loop.set_control_flow(ControlFlow::Wait);
+
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut renderer = block_on(WinitRenderer::new(&window))
+        .unwrap()
+        .with_default_drawables(EmbeddedAssets::<Assets>::new())
+        .with_pixels_per_unit(window.scale_factor() as f32);
+
+    event_loop
+        .run(|event, target| {
+            renderer.handle_event(&window, &event);
+
+            match event {
+                Event::Resumed => log::info!("Resumed"),
+                Event::Suspended => log::info!("Suspended"),
+                Event::MemoryWarning => log::warn!("Released cached glyphs and images"),
+                Event::WindowEvent { ref event, .. } => match event {
+                    WindowEvent::CloseRequested => target.exit(),
+                    WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                        renderer.set_pixels_per_unit(*scale_factor as f32);
+                    }
+                    WindowEvent::RedrawRequested => {
+                        let size = window.inner_size().to_logical::<f32>(window.scale_factor());
+                        let size = vec2(size.width, size.height);
+                        let scene = Scene::new()
+                            .with_quad(
+                                Quad::new(size * 0.25, size * 0.5, vec4(0.2, 0.4, 1.0, 1.0))
+                                    .with_corner_radius(size.min_element() * 0.05),
+                            )
+                            .with_text(Text::new(
+                                format!("{} x {} points", size.x, size.y),
+                                size * vec2(0.25, 0.2),
+                                17.,
+                                vec4(0.0, 0.0, 0.0, 1.0),
+                            ));
+
+                        let outcome = renderer.draw(&scene);
+                        if outcome.is_fatal() {
+                            log::error!("Could not draw: {outcome:?}");
+                            target.exit();
+                        }
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        })
+        .expect("Could not run event loop");
+}
+
+// Entry point for the main function of the Xcode project. Never returns, as
+// UIKit owns the main loop.
+#[cfg(target_os = "ios")]
+#[no_mangle]
+pub extern "C" fn start_app() {
+    env_logger::init();
+
+    let event_loop = EventLoop::new().expect("Couldn't create event loop");
+    run(event_loop);
+}
@@ -0,0 +1,10 @@
+use winit::event_loop::EventLoop;
+
+// Runs the example on desktop platforms, where the scale factor can be
+// changed by moving the window between screens
+fn main() {
+    env_logger::init();
+
+    let event_loop = EventLoop::new().expect("Couldn't create event loop");
+    ios_example::run(event_loop);
+}
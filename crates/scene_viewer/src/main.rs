@@ -13,7 +13,7 @@ use winit::{
     window::WindowBuilder,
 };
 
-use vide::{Quad, Scene, WinitRenderer};
+use vide::{EmbeddedAssets, Quad, Scene, WinitRenderer};
 
 #[derive(RustEmbed)]
 #[folder = "assets"]
@@ -51,7 +51,9 @@ fn main() {
         .unwrap();
 
     let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let mut renderer = block_on(WinitRenderer::new(&window)).with_default_drawables::<Assets>();
+    let mut renderer = block_on(WinitRenderer::new(&window))
+        .unwrap()
+        .with_default_drawables(EmbeddedAssets::<Assets>::new());
     let mut mouse_pos: PhysicalPosition<f64> = Default::default();
 
     event_loop
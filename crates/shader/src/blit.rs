@@ -0,0 +1,32 @@
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, image::Image2d, spirv, Sampler};
+
+// Draws a texture stretched over the whole target using a single triangle
+// which covers the viewport
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn blit_vertex(
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_texture_position: &mut Vec2,
+) {
+    let texture_position = vec2(((vert_index << 1) & 2) as f32, (vert_index & 2) as f32);
+    *out_texture_position = texture_position;
+    *out_position = vec4(
+        texture_position.x * 2.0 - 1.0,
+        1.0 - texture_position.y * 2.0,
+        0.0,
+        1.0,
+    );
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn blit_fragment(
+    #[spirv(descriptor_set = 0, binding = 0)] source: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    texture_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    *out_color = source.sample_by_lod(*sampler, texture_position, 0.);
+}
@@ -0,0 +1,120 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, image::Image2d, num_traits::Float, spirv, Sampler};
+
+#[cfg(target_arch = "spirv")]
+use crate::ShaderConstants;
+
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Debug, bytemuck::Pod, bytemuck::Zeroable)
+)]
+#[repr(C)]
+// The rect of a text caret, which blinks and glides to new positions based on
+// the time in the shader constants
+pub struct InstancedCaret {
+    pub color: Vec4,
+    pub top_left: Vec2,
+    pub size: Vec2,
+    // Offset from the top left the caret starts gliding from when it moves
+    pub move_offset: Vec2,
+    // Time the caret last moved. Blinking restarts from this time with the
+    // caret visible.
+    pub moved_at: f32,
+    pub move_duration: f32,
+    // Seconds the caret stays visible and then hidden. Zero disables blinking.
+    pub blink_interval: f32,
+    // One of the CARET_COLOR constants
+    pub color_mode: u32,
+    pub _padding: Vec2,
+}
+
+// Ways the color of a caret is picked. The derived colors are drawn with the
+// alpha of the caret color.
+pub const CARET_COLOR_FIXED: u32 = 0;
+// The inverse of the pixels behind the caret
+pub const CARET_COLOR_INVERT: u32 = 1;
+// Black or white, whichever stands out more from the pixels behind the caret
+pub const CARET_COLOR_CONTRAST: u32 = 2;
+
+// Color of the caret over the backdrop for the color mode
+pub fn caret_color(color: Vec4, color_mode: u32, backdrop: Vec3) -> Vec4 {
+    if color_mode == CARET_COLOR_INVERT {
+        (Vec3::ONE - backdrop).extend(color.w)
+    } else if color_mode == CARET_COLOR_CONTRAST {
+        let luminance = backdrop.dot(vec3(0.2126, 0.7152, 0.0722));
+        let contrast = if luminance > 0.5 { 0.0 } else { 1.0 };
+        Vec3::splat(contrast).extend(color.w)
+    } else {
+        color
+    }
+}
+
+// Fraction of the move which has been completed at the time, eased out so
+// that the caret slows down as it arrives
+pub fn caret_move_progress(time: f32, moved_at: f32, move_duration: f32) -> f32 {
+    if move_duration <= 0.0 {
+        return 1.0;
+    }
+    let t = ((time - moved_at) / move_duration).clamp(0.0, 1.0);
+    let remaining = 1.0 - t;
+    1.0 - remaining * remaining * remaining
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn caret_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] carets: &[InstancedCaret],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+) {
+    *out_instance_index = instance_index;
+
+    let unit_vertex_pos = match vert_index {
+        0 => vec2(0.0, 0.0),
+        1 => vec2(1.0, 0.0),
+        2 => vec2(1.0, 1.0),
+        3 => vec2(0.0, 0.0),
+        4 => vec2(1.0, 1.0),
+        5 => vec2(0.0, 1.0),
+        _ => unreachable!(),
+    };
+
+    let caret = carets[instance_index as usize];
+    let progress = caret_move_progress(constants.time, caret.moved_at, caret.move_duration);
+    let top_left = caret.top_left + caret.move_offset * (1.0 - progress);
+    *out_position = constants.to_clip(top_left + unit_vertex_pos * caret.size);
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn caret_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] carets: &[InstancedCaret],
+    #[spirv(descriptor_set = 1, binding = 0)] surface: &Image2d,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(flat)] instance_index: i32,
+    #[spirv(frag_coord)] surface_position: Vec4,
+    out_color: &mut Vec4,
+) {
+    let caret = carets[instance_index as usize];
+
+    // The surface holds everything drawn beneath the caret
+    let backdrop = surface
+        .sample_by_lod(*sampler, surface_position.xy() / constants.target_size, 0.)
+        .xyz();
+    let mut color = caret_color(caret.color, caret.color_mode, backdrop);
+    if caret.blink_interval > 0.0 {
+        let phase = ((constants.time - caret.moved_at).max(0.0) / caret.blink_interval).floor();
+        if phase - (phase / 2.0).floor() * 2.0 >= 1.0 {
+            color.w = 0.0;
+        }
+    }
+    *out_color = color;
+}
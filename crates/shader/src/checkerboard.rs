@@ -0,0 +1,91 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, num_traits::Float, spirv};
+
+#[cfg(target_arch = "spirv")]
+use crate::ShaderConstants;
+
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Debug, bytemuck::Pod, bytemuck::Zeroable)
+)]
+#[repr(C)]
+// A rect filled with alternating cells of two colors, such as behind
+// transparent images
+pub struct InstancedCheckerboard {
+    // Color of the cell at the top left and of the cells next to it
+    pub first_color: Vec4,
+    pub second_color: Vec4,
+    pub top_left: Vec2,
+    pub size: Vec2,
+    // Logical pixels of the surface, independent of the camera zoom
+    pub cell_size: f32,
+    pub _padding: f32,
+    pub __padding: Vec2,
+}
+
+// Size of the cells in whole surface pixels, so that their edges fall on the
+// pixel grid at any scale factor
+pub fn checkerboard_cell_pixels(cell_size: f32, scale_factor: f32) -> f32 {
+    (cell_size * scale_factor).round().max(1.0)
+}
+
+// Zero in the cells of the first color and one in the others, for a surface
+// position relative to the corner of the checkerboard
+pub fn checkerboard_cell(relative_position: Vec2, cell_pixels: f32) -> f32 {
+    let cell = (relative_position / cell_pixels).floor();
+    let sum = cell.x + cell.y;
+    sum - (sum / 2.0).floor() * 2.0
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn checkerboard_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)]
+    checkerboards: &[InstancedCheckerboard],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+) {
+    *out_instance_index = instance_index;
+
+    let unit_vertex_pos = match vert_index {
+        0 => vec2(0.0, 0.0),
+        1 => vec2(1.0, 0.0),
+        2 => vec2(1.0, 1.0),
+        3 => vec2(0.0, 0.0),
+        4 => vec2(1.0, 1.0),
+        5 => vec2(0.0, 1.0),
+        _ => unreachable!(),
+    };
+
+    let checkerboard = checkerboards[instance_index as usize];
+    *out_position = constants.to_clip(checkerboard.top_left + unit_vertex_pos * checkerboard.size);
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn checkerboard_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)]
+    checkerboards: &[InstancedCheckerboard],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(flat)] instance_index: i32,
+    #[spirv(frag_coord)] surface_position: Vec4,
+    out_color: &mut Vec4,
+) {
+    let checkerboard = checkerboards[instance_index as usize];
+
+    // The cells are laid out on the surface from the pixel nearest the top
+    // left corner, so they stay sharp however the camera zooms
+    let corner = constants.to_surface(checkerboard.top_left).round();
+    let cell_pixels = checkerboard_cell_pixels(checkerboard.cell_size, constants.scale_factor);
+    let value = checkerboard_cell(surface_position.xy() - corner, cell_pixels);
+    *out_color = checkerboard
+        .first_color
+        .lerp(checkerboard.second_color, value);
+}
@@ -0,0 +1,87 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{
+    glam::*,
+    image::{Image2d, Image3d},
+    num_traits::Float,
+    spirv, Sampler,
+};
+
+// How a frame is looked up in a 3D color lookup table
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct ColorGrading {
+    // Input colors mapped onto the first and the last entries of the table,
+    // in xyz
+    pub domain_min: Vec4,
+    pub domain_max: Vec4,
+    // Entries along each side of the table
+    pub size: f32,
+    // Blend from the original colors at zero to the graded ones at one
+    pub strength: f32,
+    // One when the frame stores linear colors which are encoded to sRGB for
+    // the lookup, as tables are made for encoded colors
+    pub srgb: u32,
+    pub _padding: u32,
+}
+
+// Texture coordinate of the color in the table, at the centers of the first
+// and last texels for the ends of the domain so that the lookup interpolates
+// between the entries
+pub fn lut_coordinate(color: Vec3, grading: &ColorGrading) -> Vec3 {
+    let domain = grading.domain_max.xyz() - grading.domain_min.xyz();
+    let relative = ((color - grading.domain_min.xyz()) / domain).clamp(Vec3::ZERO, Vec3::ONE);
+    (relative * (grading.size - 1.0) + 0.5) / grading.size
+}
+
+pub fn linear_to_srgb(color: Vec3) -> Vec3 {
+    let channel = |value: f32| {
+        if value <= 0.0031308 {
+            value * 12.92
+        } else {
+            1.055 * value.powf(1.0 / 2.4) - 0.055
+        }
+    };
+    vec3(channel(color.x), channel(color.y), channel(color.z))
+}
+
+pub fn srgb_to_linear(color: Vec3) -> Vec3 {
+    let channel = |value: f32| {
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    vec3(channel(color.x), channel(color.y), channel(color.z))
+}
+
+// Draws with blit::blit_vertex over the whole frame
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn color_grading_fragment(
+    #[spirv(descriptor_set = 0, binding = 0)] source: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    #[spirv(descriptor_set = 0, binding = 2)] lut: &Image3d,
+    #[spirv(uniform, descriptor_set = 0, binding = 3)] grading: &ColorGrading,
+    texture_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    let color: Vec4 = source.sample_by_lod(*sampler, texture_position, 0.);
+    let encoded = if grading.srgb == 1 {
+        linear_to_srgb(color.xyz())
+    } else {
+        color.xyz()
+    };
+    let graded: Vec4 = lut.sample_by_lod(*sampler, lut_coordinate(encoded, grading), 0.);
+    let graded = encoded.lerp(graded.xyz(), grading.strength);
+    let graded = if grading.srgb == 1 {
+        srgb_to_linear(graded)
+    } else {
+        graded
+    };
+    *out_color = graded.extend(color.w);
+}
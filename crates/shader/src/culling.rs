@@ -0,0 +1,128 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+#[cfg(target_arch = "spirv")]
+use spirv_std::{
+    arch::atomic_i_add,
+    glam::*,
+    memory::{Scope, Semantics},
+    spirv,
+};
+
+use crate::InstancedQuad;
+
+// Threads of the workgroups of the culling passes. Has to match the threads
+// attributes below.
+pub const CULLING_WORKGROUP_SIZE: u32 = 64;
+
+#[derive(Copy, Clone)]
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Debug, bytemuck::Pod, bytemuck::Zeroable, Default)
+)]
+#[repr(C)]
+// Push constants of the quad culling passes
+pub struct QuadCulling {
+    // Scene rect the quads have to overlap to be drawn
+    pub visible_rect: Vec4,
+    // Quads in the instance buffer
+    pub count: u32,
+    pub _padding: [u32; 3],
+}
+
+// Whether the quad including its external blur or outline overlaps the rect.
+// Quads without any area draw nothing.
+pub fn quad_visible(quad: &InstancedQuad, visible_rect: Vec4) -> bool {
+    if quad.size.x <= 0.0 || quad.size.y <= 0.0 {
+        return false;
+    }
+    let extension = Vec2::splat(quad.extension());
+    let top_left = quad.top_left - extension;
+    let bottom_right = quad.top_left + quad.size + extension;
+    top_left.x < visible_rect.x + visible_rect.z
+        && bottom_right.x > visible_rect.x
+        && top_left.y < visible_rect.y + visible_rect.w
+        && bottom_right.y > visible_rect.y
+}
+
+// Marks the visible quads and counts them per workgroup. The counts have to
+// be cleared before.
+#[cfg(target_arch = "spirv")]
+#[spirv(compute(threads(64)))]
+pub fn cull_quads(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(workgroup_id)] group: UVec3,
+    #[spirv(push_constant)] culling: &QuadCulling,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] quads: &[InstancedQuad],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] visible: &mut [u32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] group_counts: &mut [u32],
+) {
+    if id.x >= culling.count {
+        return;
+    }
+    let is_visible = quad_visible(&quads[id.x as usize], culling.visible_rect);
+    visible[id.x as usize] = is_visible as u32;
+    if is_visible {
+        unsafe {
+            atomic_i_add::<u32, { Scope::Device as u32 }, { Semantics::NONE.bits() }>(
+                &mut group_counts[group.x as usize],
+                1,
+            );
+        }
+    }
+}
+
+// Replaces the counts of the workgroups with the offset of their first
+// visible quad in the compacted buffer, and sets the instance count of the
+// indirect draw to the total. Runs on a single thread, which loops over one
+// count per 64 quads.
+#[cfg(target_arch = "spirv")]
+#[spirv(compute(threads(1)))]
+pub fn cull_quads_offsets(
+    #[spirv(push_constant)] culling: &QuadCulling,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] group_counts: &mut [u32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 4)] draw_arguments: &mut [u32],
+) {
+    let groups = (culling.count + CULLING_WORKGROUP_SIZE - 1) / CULLING_WORKGROUP_SIZE;
+    let mut offset = 0;
+    let mut group = 0;
+    while group < groups {
+        let count = group_counts[group as usize];
+        group_counts[group as usize] = offset;
+        offset += count;
+        group += 1;
+    }
+
+    // Vertex count, instance count, first vertex and first instance
+    draw_arguments[0] = 6;
+    draw_arguments[1] = offset;
+    draw_arguments[2] = 0;
+    draw_arguments[3] = 0;
+}
+
+// Copies the visible quads to the compacted buffer, keeping their order so
+// that overlapping quads are still drawn in the order of the layer
+#[cfg(target_arch = "spirv")]
+#[spirv(compute(threads(64)))]
+pub fn compact_quads(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(local_invocation_id)] local_id: UVec3,
+    #[spirv(workgroup_id)] group: UVec3,
+    #[spirv(push_constant)] culling: &QuadCulling,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] quads: &[InstancedQuad],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] visible: &[u32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] group_offsets: &[u32],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] compacted: &mut [InstancedQuad],
+) {
+    if id.x >= culling.count || visible[id.x as usize] == 0 {
+        return;
+    }
+
+    // Visible quads before this one in the workgroup
+    let mut rank = 0;
+    let mut index = id.x - local_id.x;
+    while index < id.x {
+        rank += visible[index as usize];
+        index += 1;
+    }
+    compacted[(group_offsets[group.x as usize] + rank) as usize] = quads[id.x as usize];
+}
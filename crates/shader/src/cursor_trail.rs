@@ -0,0 +1,106 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, num_traits::Float, spirv};
+
+#[cfg(target_arch = "spirv")]
+use crate::ShaderConstants;
+
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Debug, bytemuck::Pod, bytemuck::Zeroable)
+)]
+#[repr(C)]
+// A cursor smearing from its previous rect to the current one. Each corner
+// follows its own spring, with the corners leading the move arriving first,
+// which stretches the rect into a polygon along the way.
+pub struct InstancedCursorTrail {
+    pub color: Vec4,
+    // Top left and size of the previous and current cursor rects
+    pub from: Vec4,
+    pub to: Vec4,
+    pub started_at: f32,
+    pub duration: f32,
+    // Fraction of the duration the leading corners arrive earlier
+    pub trail_size: f32,
+    pub _padding: f32,
+}
+
+// Corners in the order top left, top right, bottom right and bottom left
+pub const CURSOR_TRAIL_CORNERS: [Vec2; 4] = [
+    Vec2::new(0.0, 0.0),
+    Vec2::new(1.0, 0.0),
+    Vec2::new(1.0, 1.0),
+    Vec2::new(0.0, 1.0),
+];
+
+// Position of one corner of the trail at the time. The corner follows a
+// critically damped spring, which settles without overshooting, so the
+// trail always stays within the two rects.
+pub fn cursor_trail_corner(trail: &InstancedCursorTrail, corner: Vec2, time: f32) -> Vec2 {
+    let from = vec2(trail.from.x, trail.from.y) + corner * vec2(trail.from.z, trail.from.w);
+    let to = vec2(trail.to.x, trail.to.y) + corner * vec2(trail.to.z, trail.to.w);
+    if trail.duration <= 0.0 {
+        return to;
+    }
+
+    let movement = to - from;
+    let distance = movement.length();
+    // 1 for the corners leading the move and 0 for the trailing ones
+    let leading = if distance > 0.0 {
+        let direction = movement / distance;
+        ((corner - vec2(0.5, 0.5)).normalize().dot(direction) + 1.0) / 2.0
+    } else {
+        0.0
+    };
+    let duration = trail.duration * (1.0 - trail.trail_size.clamp(0.0, 1.0) * leading);
+
+    let elapsed = (time - trail.started_at).max(0.0);
+    if elapsed >= duration {
+        return to;
+    }
+    // The spring is within a percent of the target at the end of the duration
+    let omega = 6.6 / duration;
+    let x = omega * elapsed;
+    let progress = 1.0 - (1.0 + x) * (-x).exp();
+    from + movement * progress
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn cursor_trail_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] trails: &[InstancedCursorTrail],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+) {
+    *out_instance_index = instance_index;
+
+    let corner_index = match vert_index {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 0,
+        4 => 2,
+        5 => 3,
+        _ => unreachable!(),
+    };
+
+    let trail = trails[instance_index as usize];
+    let corner = cursor_trail_corner(&trail, CURSOR_TRAIL_CORNERS[corner_index], constants.time);
+    *out_position = constants.to_clip(corner);
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn cursor_trail_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] trails: &[InstancedCursorTrail],
+    #[spirv(flat)] instance_index: i32,
+    out_color: &mut Vec4,
+) {
+    *out_color = trails[instance_index as usize].color;
+}
@@ -0,0 +1,139 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, num_traits::Float, spirv};
+
+#[cfg(target_arch = "spirv")]
+use crate::{coverage, ShaderConstants};
+
+pub const UNDERLINE_SINGLE: u32 = 0;
+pub const UNDERLINE_DOUBLE: u32 = 1;
+pub const UNDERLINE_DOTTED: u32 = 2;
+pub const UNDERLINE_DASHED: u32 = 3;
+pub const UNDERLINE_WAVY: u32 = 4;
+// Rounded rect behind the text run
+pub const TEXT_BACKGROUND: u32 = 5;
+
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Debug, bytemuck::Pod, bytemuck::Zeroable)
+)]
+#[repr(C)]
+// A line decorating a text run, drawn as a signed distance field so that it
+// stays crisp at any zoom. The rect is centered on the line and tall enough
+// for the whole style, such as both lines of a double underline or the peaks
+// of a wavy one.
+pub struct InstancedDecoration {
+    pub color: Vec4,
+    pub top_left: Vec2,
+    pub size: Vec2,
+    pub thickness: f32,
+    pub style: u32,
+    // Height of the wave peaks above the center line, or the corner radius of
+    // a background
+    pub amplitude: f32,
+    // Length of a wave, or of a dash and the gap after it
+    pub period: f32,
+}
+
+fn repeat(value: f32, period: f32) -> f32 {
+    value - (value / period).floor() * period
+}
+
+// Signed distance from the position relative to the top left of the rect to
+// the edge of the decoration. Negative inside.
+pub fn decoration_distance(decoration: &InstancedDecoration, position: Vec2) -> f32 {
+    let thickness = decoration.thickness.max(0.0);
+    let half_thickness = thickness / 2.0;
+    let y = position.y - decoration.size.y / 2.0;
+    let period = decoration.period.max(thickness).max(0.001);
+
+    match decoration.style {
+        UNDERLINE_DOUBLE => {
+            // Two lines separated by a gap of the thickness
+            (y.abs() - thickness).abs() - half_thickness
+        }
+        UNDERLINE_DOTTED => {
+            // Round dots spaced by their diameter
+            let x = repeat(position.x, thickness * 2.0) - thickness;
+            vec2(x, y).length() - half_thickness
+        }
+        UNDERLINE_DASHED => {
+            // Dashes covering 60 percent of the period
+            let dash = period * 0.6;
+            let x = repeat(position.x, period);
+            (y.abs() - half_thickness).max((-x).max(x - dash))
+        }
+        UNDERLINE_WAVY => {
+            // Vertical distance to the sine wave divided by the slope, which
+            // approximates the distance perpendicular to the wave
+            let frequency = core::f32::consts::TAU / period;
+            let phase = position.x * frequency;
+            let wave = decoration.amplitude * phase.sin();
+            let slope = decoration.amplitude * frequency * phase.cos();
+            (y - wave).abs() / (1.0 + slope * slope).sqrt() - half_thickness
+        }
+        TEXT_BACKGROUND => {
+            let half_size = decoration.size / 2.0;
+            let radius = decoration.amplitude.clamp(0.0, half_size.min_element());
+            let d = (position - half_size).abs() - (half_size - Vec2::splat(radius));
+            d.max(Vec2::ZERO).length() + d.max_element().min(0.0) - radius
+        }
+        _ => y.abs() - half_thickness,
+    }
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn decoration_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] decorations: &[InstancedDecoration],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+    out_local_position: &mut Vec2,
+) {
+    *out_instance_index = instance_index;
+
+    let unit_vertex_pos = match vert_index {
+        0 => vec2(0.0, 0.0),
+        1 => vec2(1.0, 0.0),
+        2 => vec2(1.0, 1.0),
+        3 => vec2(0.0, 0.0),
+        4 => vec2(1.0, 1.0),
+        5 => vec2(0.0, 1.0),
+        _ => unreachable!(),
+    };
+
+    // Extend the rect vertically to include the antialiasing ramp, and
+    // horizontally too for backgrounds, which have antialiased sides
+    let decoration = decorations[instance_index as usize];
+    let ramp = constants.antialiasing_width / constants.camera_zoom;
+    let outset = if decoration.style == TEXT_BACKGROUND {
+        vec2(ramp, ramp)
+    } else {
+        vec2(0.0, ramp)
+    };
+    let top_left = decoration.top_left - outset;
+    let size = decoration.size + outset * 2.0;
+    *out_local_position = unit_vertex_pos * size - outset;
+    *out_position = constants.to_clip(top_left + unit_vertex_pos * size);
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn decoration_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] decorations: &[InstancedDecoration],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(flat)] instance_index: i32,
+    local_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    let decoration = decorations[instance_index as usize];
+    let distance = decoration_distance(&decoration, local_position);
+    *out_color = decoration.color;
+    out_color.w *= coverage(distance, constants);
+}
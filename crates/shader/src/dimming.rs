@@ -0,0 +1,77 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, image::Image2d, spirv, Sampler};
+
+#[cfg(target_arch = "spirv")]
+use crate::ShaderConstants;
+
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Debug, bytemuck::Pod, bytemuck::Zeroable)
+)]
+#[repr(C)]
+// Desaturates and darkens what has been drawn within the clip of a layer,
+// gradually by the progress which can transition over time
+pub struct InstancedDimming {
+    // Multipliers of the saturation and the brightness when fully dimmed
+    pub saturation: f32,
+    pub brightness: f32,
+    // Progress from unchanged at zero to fully dimmed at one, reached at the
+    // end of the transition
+    pub progress: f32,
+    // Progress the transition starts from
+    pub from_progress: f32,
+    // Time in seconds of the renderer clock the transition starts at
+    pub started_at: f32,
+    // Zero for no transition
+    pub duration: f32,
+    pub _padding: Vec2,
+}
+
+// Progress of the dimming at the time, eased in and out during the
+// transition
+pub fn dimming_progress(dimming: &InstancedDimming, time: f32) -> f32 {
+    if dimming.duration <= 0.0 {
+        return dimming.progress;
+    }
+    let t = ((time - dimming.started_at) / dimming.duration).clamp(0.0, 1.0);
+    let eased = t * t * (3.0 - 2.0 * t);
+    dimming.from_progress + (dimming.progress - dimming.from_progress) * eased
+}
+
+// The color with the saturation and the brightness scaled by the amount of
+// the dimming
+pub fn dim_color(color: Vec3, saturation: f32, brightness: f32, progress: f32) -> Vec3 {
+    let luminance = Vec3::splat(color.dot(vec3(0.2126, 0.7152, 0.0722)));
+    let dimmed = luminance.lerp(color, saturation) * brightness;
+    color.lerp(dimmed.clamp(Vec3::ZERO, Vec3::ONE), progress)
+}
+
+// Drawn with blit::blit_vertex over the whole viewport, limited to the clip of
+// the layer by the scissor rect
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn dimming_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] dimmings: &[InstancedDimming],
+    #[spirv(descriptor_set = 1, binding = 0)] surface: &Image2d,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(frag_coord)] surface_position: Vec4,
+    out_color: &mut Vec4,
+) {
+    let dimming = dimmings[0];
+    // The surface holds everything drawn so far
+    let color: Vec4 =
+        surface.sample_by_lod(*sampler, surface_position.xy() / constants.target_size, 0.);
+    let progress = dimming_progress(&dimming, constants.time);
+    *out_color = dim_color(
+        color.xyz(),
+        dimming.saturation,
+        dimming.brightness,
+        progress,
+    )
+    .extend(color.w);
+}
@@ -1,38 +1,195 @@
 #[cfg(not(target_arch = "spirv"))]
 use glam::*;
 #[cfg(target_arch = "spirv")]
-use spirv_std::{glam::*, image::Image2d, spirv, Sampler};
+use spirv_std::{glam::*, image::Image2d, num_traits::Float, spirv, Sampler};
 
+#[cfg(not(target_arch = "spirv"))]
+use crate::{pack_f16x2, pack_unorm4x8};
 #[cfg(target_arch = "spirv")]
-use crate::ShaderConstants;
+use crate::{procedural_value, sample_pattern, ShaderConstants};
+use crate::{unpack_f16x2, unpack_unorm4x8};
 
 #[derive(Copy, Clone, Default)]
 #[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
 #[repr(C)]
 pub struct InstancedGlyph {
+    // Position of the bottom left of the glyph in surface pixels
     pub bottom_left: Vec2,
     pub atlas_top_left: Vec2,
     pub atlas_size: Vec2,
-    // Need a Vec2 of padding here so that the first 4 fields
-    // Are some multiple of 16 bytes in size.
-    // Vec2s are 8 bytes, Vec4s are 16 bytes.
-    pub _padding: Vec2,
+    // Range along the text, in pixels from the left of the glyph, over which
+    // the glyph fades out. Also pads the first 4 fields to a multiple of 16
+    // bytes, as Vec2s are 8 bytes and Vec4s 16 bytes.
+    pub fade: Vec2,
     pub color: Vec4,
+    // One more than the index of the paint filling the glyph instead of the
+    // color, zero when there's none
+    pub paint: u32,
+    pub _padding: u32,
+    // Scale and rotation in radians of the glyph around its center, for
+    // animating single glyphs of a run
+    pub transform: Vec2,
+}
+
+// Kind of the text paints which fill the glyphs with their color, for runs
+// which only have an outline
+pub const TEXT_PAINT_COLOR: u32 = u32::MAX;
+
+// Rings and directions sampled around each pixel of outlined glyphs for the
+// distance to the glyph
+#[cfg(target_arch = "spirv")]
+const OUTLINE_RINGS: u32 = 8;
+#[cfg(target_arch = "spirv")]
+const OUTLINE_DIRECTIONS: u32 = 12;
+
+// Paint filling the glyphs of a text run, evaluated relative to the line of
+// the run. Either one of the procedural functions blending from the color of
+// the glyph to the secondary color, or a pattern multiplied with the color.
+// Also holds the outline of the run, drawn around the glyphs.
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct InstancedTextPaint {
+    pub secondary_color: Vec4,
+    // Same as the parameters of InstancedProcedural
+    pub parameters: Vec4,
+    // Scene rect of the line of the run, from the ascent to the descent
+    pub bounds: Vec4,
+    // Top left and size of the pattern image in the image atlas. A zero size
+    // means the procedural function is used instead.
+    pub pattern_atlas_rect: Vec4,
+    // Columns of the matrix mapping positions in the line into pattern pixels
+    pub pattern_transform: Vec4,
+    pub outline_color: Vec4,
+    pub pattern_offset: Vec2,
+    pub kind: u32,
+    // Width and softness of the outline in surface pixels
+    pub outline_width: f32,
+    pub outline_softness: f32,
+    pub _padding: [u32; 3],
+}
+
+impl InstancedTextPaint {
+    // Surface pixels the outline reaches past the glyphs
+    pub fn outline_extent(&self) -> f32 {
+        self.outline_width + self.outline_softness
+    }
+}
+
+// Opacity at the position along the text, relative to the left of the glyph,
+// falling linearly from one to zero over the fade range. Glyphs with an empty
+// range don't fade.
+pub fn fade_opacity(fade: Vec2, position: f32) -> f32 {
+    if fade.y <= fade.x {
+        return 1.0;
+    }
+    (1.0 - (position - fade.x) / (fade.y - fade.x)).clamp(0.0, 1.0)
+}
+
+// InstancedGlyph packed into half the size. The position stays full precision
+// for subpixel placement, the atlas rect, the fade range and the transform
+// are stored as halves and the color as 8 bit components.
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct PackedGlyph {
+    pub bottom_left: Vec2,
+    pub atlas_top_left: u32,
+    pub atlas_size: u32,
+    pub color: u32,
+    pub fade: u32,
+    pub paint: u32,
+    pub transform: u32,
+}
+
+impl PackedGlyph {
+    pub fn unpack(&self) -> InstancedGlyph {
+        InstancedGlyph {
+            bottom_left: self.bottom_left,
+            atlas_top_left: unpack_f16x2(self.atlas_top_left),
+            atlas_size: unpack_f16x2(self.atlas_size),
+            fade: unpack_f16x2(self.fade),
+            color: unpack_unorm4x8(self.color),
+            paint: self.paint,
+            _padding: 0,
+            transform: unpack_f16x2(self.transform),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl From<InstancedGlyph> for PackedGlyph {
+    fn from(glyph: InstancedGlyph) -> Self {
+        Self {
+            bottom_left: glyph.bottom_left,
+            atlas_top_left: pack_f16x2(glyph.atlas_top_left),
+            atlas_size: pack_f16x2(glyph.atlas_size),
+            color: pack_unorm4x8(glyph.color),
+            fade: pack_f16x2(glyph.fade),
+            paint: glyph.paint,
+            transform: pack_f16x2(glyph.transform),
+        }
+    }
 }
 
 #[cfg(target_arch = "spirv")]
 #[spirv(vertex)]
 pub fn glyph_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] glyphs: &[PackedGlyph],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] paints: &[InstancedTextPaint],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+    out_atlas_position: &mut Vec2,
+) {
+    *out_instance_index = instance_index;
+    let instance = glyphs[instance_index as usize].unpack();
+    glyph_vertex_position(
+        instance,
+        vert_index,
+        paints,
+        constants,
+        out_position,
+        out_atlas_position,
+    );
+}
+
+// Variant of glyph_vertex reading unpacked instances
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn glyph_vertex_f32(
     #[spirv(instance_index)] instance_index: i32,
     #[spirv(vertex_index)] vert_index: i32,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] glyphs: &[InstancedGlyph],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] paints: &[InstancedTextPaint],
     #[spirv(push_constant)] constants: &ShaderConstants,
     #[spirv(position, invariant)] out_position: &mut Vec4,
     out_instance_index: &mut i32,
     out_atlas_position: &mut Vec2,
 ) {
     *out_instance_index = instance_index;
+    let instance = glyphs[instance_index as usize];
+    glyph_vertex_position(
+        instance,
+        vert_index,
+        paints,
+        constants,
+        out_position,
+        out_atlas_position,
+    );
+}
 
+#[cfg(target_arch = "spirv")]
+fn glyph_vertex_position(
+    instance: InstancedGlyph,
+    vert_index: i32,
+    paints: &[InstancedTextPaint],
+    constants: &ShaderConstants,
+    out_position: &mut Vec4,
+    out_atlas_position: &mut Vec2,
+) {
     let unit_vertex_pos = match vert_index {
         0 => vec2(0.0, 0.0),
         1 => vec2(1.0, 0.0),
@@ -43,23 +200,72 @@ pub fn glyph_vertex(
         _ => unreachable!(),
     };
 
-    let instance = glyphs[instance_index as usize];
+    // Outlined glyphs are extended to include the outline, which samples
+    // the atlas past the glyph
+    let extension = if instance.paint > 0 {
+        (unit_vertex_pos * 2.0 - 1.0) * paints[instance.paint as usize - 1].outline_extent()
+    } else {
+        Vec2::ZERO
+    };
+
+    // Glyphs are rasterized at the zoomed size and positioned in surface
+    // space on the cpu, so only the camera rotation is applied here, after
+    // the transform of the glyph around its center. Rotations turn the other
+    // way when the y axis points up, like the camera.
+    let center = vec2(0.5, -0.5) * instance.atlas_size;
+    let angle = instance.transform.y;
+    let rotation = vec2(angle.cos(), angle.sin() * constants.y_direction);
+    let local_position = center
+        + ((unit_vertex_pos - vec2(0., 1.)) * instance.atlas_size + extension - center)
+            .rotate(rotation)
+            * instance.transform.x;
     let vertex_pixel_pos =
-        instance.bottom_left + (unit_vertex_pos - vec2(0., 1.)) * instance.atlas_size;
+        instance.bottom_left + local_position.rotate(constants.surface_rotation());
 
-    let final_position =
-        vec2(0.0, 2.0) + vertex_pixel_pos / constants.surface_size * vec2(1., -1.) * 2.0 - 1.0;
-    *out_position = final_position.extend(0.0).extend(1.0);
+    *out_position = constants.surface_to_clip(vertex_pixel_pos);
 
-    *out_atlas_position = instance.atlas_top_left / constants.atlas_size
-        + unit_vertex_pos * instance.atlas_size / constants.atlas_size;
+    *out_atlas_position =
+        (instance.atlas_top_left + unit_vertex_pos * instance.atlas_size + extension)
+            / constants.atlas_size;
 }
 
 #[cfg(target_arch = "spirv")]
 #[spirv(fragment)]
 pub fn glyph_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] glyphs: &[PackedGlyph],
+    #[spirv(descriptor_set = 0, binding = 1)] atlas: &Image2d,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] paints: &[InstancedTextPaint],
+    #[spirv(descriptor_set = 0, binding = 3)] image_atlas: &Image2d,
+    #[spirv(descriptor_set = 1, binding = 0)] surface: &Image2d,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(flat)] instance_index: i32,
+    #[spirv(frag_coord)] surface_position: Vec4,
+    atlas_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    let instance = glyphs[instance_index as usize].unpack();
+    *out_color = glyph_color(
+        instance,
+        atlas,
+        paints,
+        image_atlas,
+        surface,
+        sampler,
+        constants,
+        surface_position,
+        atlas_position,
+    );
+}
+
+// Variant of glyph_fragment reading unpacked instances
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn glyph_fragment_f32(
     #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] glyphs: &[InstancedGlyph],
     #[spirv(descriptor_set = 0, binding = 1)] atlas: &Image2d,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] paints: &[InstancedTextPaint],
+    #[spirv(descriptor_set = 0, binding = 3)] image_atlas: &Image2d,
     #[spirv(descriptor_set = 1, binding = 0)] surface: &Image2d,
     #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
     #[spirv(push_constant)] constants: &ShaderConstants,
@@ -68,14 +274,181 @@ pub fn glyph_fragment(
     atlas_position: Vec2,
     out_color: &mut Vec4,
 ) {
-    let glyph = glyphs[instance_index as usize];
+    *out_color = glyph_color(
+        glyphs[instance_index as usize],
+        atlas,
+        paints,
+        image_atlas,
+        surface,
+        sampler,
+        constants,
+        surface_position,
+        atlas_position,
+    );
+}
+
+#[cfg(target_arch = "spirv")]
+fn glyph_color(
+    instance: InstancedGlyph,
+    atlas: &Image2d,
+    paints: &[InstancedTextPaint],
+    image_atlas: &Image2d,
+    surface: &Image2d,
+    sampler: &Sampler,
+    constants: &ShaderConstants,
+    surface_position: Vec4,
+    atlas_position: Vec2,
+) -> Vec4 {
     // Here we have to sample specifically the 0 LOD. I don't
     // fully understand why, but I think it has to do with how
     // the spirv is generated.
     // More details here: https://github.com/gfx-rs/wgpu-rs/issues/912
     let surface_color =
-        surface.sample_by_lod(*sampler, surface_position.xy() / constants.surface_size, 0.);
-    let mask_color = atlas.sample_by_lod(*sampler, atlas_position, 0.);
-    *out_color = glyph.color * glyph.color * mask_color
-        + (1.0 - glyph.color.w * glyph.color.w * mask_color) * surface_color;
+        surface.sample_by_lod(*sampler, surface_position.xy() / constants.target_size, 0.);
+    // The fade scales the coverage of the glyph, which is sampled at the
+    // position relative to the left of the glyph. Outlined glyphs extend
+    // past the glyph in the atlas, where the glyph has no coverage.
+    let pixel_position = atlas_position * constants.atlas_size;
+    let position = pixel_position.x - instance.atlas_top_left.x;
+    let opacity = fade_opacity(instance.fade, position);
+    let mask_color = glyph_mask(atlas, sampler, instance, pixel_position, constants) * opacity;
+    if instance.paint == 0 {
+        let color = instance.color;
+        return color * color * mask_color + (1.0 - color.w * color.w * mask_color) * surface_color;
+    }
+
+    let paint = paints[instance.paint as usize - 1];
+    let color = paint_color(
+        paint,
+        instance.color,
+        image_atlas,
+        sampler,
+        constants,
+        surface_position.xy(),
+    );
+    if paint.outline_extent() <= 0.0 {
+        return color * color * mask_color + (1.0 - color.w * color.w * mask_color) * surface_color;
+    }
+
+    // Outlined glyphs overlap their neighbours, so they are blended by their
+    // coverage instead of drawing over the surface, without subpixel
+    // antialiasing. The outline covers what the glyph doesn't.
+    let coverage = mask_color.max_element();
+    let distance = glyph_distance(atlas, sampler, instance, pixel_position, paint, constants);
+    let outline_distance = distance - paint.outline_width;
+    let outline_alpha = if outline_distance <= 0.0 {
+        1.0
+    } else if paint.outline_softness > 0.0 {
+        // The glow falls off quadratically past the outline width
+        let falloff = (1.0 - outline_distance / paint.outline_softness).clamp(0.0, 1.0);
+        falloff * falloff
+    } else {
+        (1.0 - outline_distance).clamp(0.0, 1.0)
+    };
+    let fill = color * color;
+    let outline = paint.outline_color * paint.outline_color;
+    let fill_alpha = fill.w * coverage;
+    let ring_alpha = outline.w * outline_alpha * (1.0 - coverage) * opacity;
+    let alpha = fill_alpha + ring_alpha;
+    if alpha <= 0.0 {
+        return Vec4::ZERO;
+    }
+    ((fill.xyz() * fill_alpha + outline.xyz() * ring_alpha) / alpha).extend(alpha)
+}
+
+// Coverage of the glyph at the position in atlas pixels, which is zero
+// outside the rect of the glyph
+#[cfg(target_arch = "spirv")]
+fn glyph_mask(
+    atlas: &Image2d,
+    sampler: &Sampler,
+    instance: InstancedGlyph,
+    pixel_position: Vec2,
+    constants: &ShaderConstants,
+) -> Vec4 {
+    let relative = pixel_position - instance.atlas_top_left;
+    if relative.cmplt(Vec2::ZERO).any() || relative.cmpgt(instance.atlas_size).any() {
+        return Vec4::ZERO;
+    }
+    atlas.sample_by_lod(*sampler, pixel_position / constants.atlas_size, 0.)
+}
+
+// Estimated distance in pixels from the position to the edge of the glyph,
+// found from the coverage sampled in rings around it up to the extent of the
+// outline. The edge crosses partly covered pixels about where the coverage
+// is a half. Positions further away than the extent are past it.
+#[cfg(target_arch = "spirv")]
+fn glyph_distance(
+    atlas: &Image2d,
+    sampler: &Sampler,
+    instance: InstancedGlyph,
+    pixel_position: Vec2,
+    paint: InstancedTextPaint,
+    constants: &ShaderConstants,
+) -> f32 {
+    let extent = paint.outline_extent();
+    let coverage = glyph_mask(atlas, sampler, instance, pixel_position, constants).max_element();
+    let mut distance = if coverage > 0.0 {
+        0.5 - coverage
+    } else {
+        extent + 1.0
+    };
+    let mut ring = 1;
+    while ring <= OUTLINE_RINGS && distance > 0.0 {
+        let radius = extent * ring as f32 / OUTLINE_RINGS as f32;
+        for direction in 0..OUTLINE_DIRECTIONS {
+            // Alternate rings are turned by half a step to cover more
+            // directions
+            let angle = (direction as f32 + 0.5 * (ring % 2) as f32) * core::f32::consts::TAU
+                / OUTLINE_DIRECTIONS as f32;
+            let offset = vec2(angle.cos(), angle.sin()) * radius;
+            let sample = glyph_mask(atlas, sampler, instance, pixel_position + offset, constants)
+                .max_element();
+            if sample > 0.0 {
+                distance = distance.min(radius + 0.5 - sample);
+            }
+        }
+        ring += 1;
+    }
+    distance
+}
+
+// Color of the paint at the surface position. The paint is the same for every
+// pixel of the glyph, so the derivatives of the procedural functions are
+// still taken in uniform control flow.
+#[cfg(target_arch = "spirv")]
+fn paint_color(
+    paint: InstancedTextPaint,
+    color: Vec4,
+    image_atlas: &Image2d,
+    sampler: &Sampler,
+    constants: &ShaderConstants,
+    surface_position: Vec2,
+) -> Vec4 {
+    if paint.kind == TEXT_PAINT_COLOR {
+        return color;
+    }
+    let position = constants.from_surface(surface_position);
+    let local_position = constants.orient_in_rect(position - paint.bounds.xy(), paint.bounds.zw());
+    if paint.pattern_atlas_rect.z > 0.0 {
+        let transform = Mat2::from_cols(paint.pattern_transform.xy(), paint.pattern_transform.zw());
+        let pattern_position = transform * local_position + paint.pattern_offset;
+        color
+            * sample_pattern(
+                image_atlas,
+                sampler,
+                paint.pattern_atlas_rect,
+                pattern_position,
+                constants,
+            )
+    } else {
+        let value = procedural_value(
+            paint.kind,
+            paint.parameters,
+            local_position,
+            paint.bounds.zw(),
+            constants,
+        );
+        color.lerp(paint.secondary_color, value.clamp(0.0, 1.0))
+    }
 }
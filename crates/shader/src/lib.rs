@@ -1,18 +1,41 @@
 #![cfg_attr(target_arch = "spirv", no_std)]
 
+mod blit;
+mod caret;
+mod checkerboard;
+mod color_grading;
+mod culling;
+mod cursor_trail;
+mod decoration;
+mod dimming;
 mod glyph;
+mod packing;
 mod path;
+mod procedural;
 mod quad;
+mod shape;
 mod sprite;
+mod video;
 
+pub use caret::*;
+pub use checkerboard::*;
+pub use color_grading::*;
+pub use culling::*;
+pub use cursor_trail::*;
+pub use decoration::*;
+pub use dimming::*;
 pub use glyph::*;
+pub use packing::*;
 pub use path::*;
+pub use procedural::*;
 pub use quad::*;
+pub use shape::*;
 pub use sprite::*;
+pub use video::*;
 
 
 #[cfg(target_arch = "spirv")]
-use spirv_std::glam::*;
+use spirv_std::{arch::Derivative, glam::*, image::Image2d, Sampler};
 
 #[cfg(not(target_arch = "spirv"))]
 use glam::*;
@@ -24,4 +47,230 @@ pub struct ShaderConstants {
     pub surface_size: Vec2,
     pub atlas_size: Vec2,
     pub clip: Vec4,
+    // Size of the whole frame in pixels. Larger than the surface when the
+    // scene is drawn into a viewport of the frame. Textures copied from the
+    // frame are sampled with it.
+    pub target_size: Vec2,
+    // Scene position which is drawn at the top left of the surface
+    pub camera_offset: Vec2,
+    // Cosine and sine of the camera rotation. Precomputed on the cpu
+    // so that the shaders don't need to do any trig.
+    pub camera_rotation: Vec2,
+    pub camera_zoom: f32,
+    // Width in pixels of the antialiasing ramp on sdf edges. Zero, the
+    // default, disables antialiasing entirely for crisp pixel art style edges.
+    pub antialiasing_width: f32,
+    // Scroll position of the current layer. Subtracted from every primitive
+    // position so that scrolling doesn't require rebuilding the layer.
+    pub scroll_offset: Vec2,
+    // Seconds since the renderer clock started, seconds since the previous
+    // frame and the index of the current frame. Allows shaders to animate.
+    pub time: f32,
+    pub delta_time: f32,
+    pub frame_index: u32,
+    // Physical pixels per logical pixel of the display. Screen space effects
+    // such as the background blur are sized in logical pixels, so that they
+    // look the same at every DPI.
+    pub scale_factor: f32,
+    // 1 when the scene y axis points down from the top left of the surface
+    // and -1 when it points up from the bottom left
+    pub y_direction: f32,
+    pub _padding: [f32; 1],
+}
+
+// How primitives are snapped to the pixel grid, see ShaderConstants::snap_rect
+pub const PIXEL_SNAP_NONE: u32 = 0;
+pub const PIXEL_SNAP_ROUND: u32 = 1;
+pub const PIXEL_SNAP_ROUND_HALF: u32 = 2;
+
+// Builder for the constants, so that new fields get a sensible default
+// instead of breaking every place the struct is constructed
+#[cfg(not(target_arch = "spirv"))]
+impl ShaderConstants {
+    // Constants for a surface of the given size viewed through a camera
+    // without offset, rotation or zoom
+    pub fn new(surface_size: Vec2) -> Self {
+        Self {
+            surface_size,
+            atlas_size: Vec2::ZERO,
+            clip: Vec4::ZERO,
+            target_size: surface_size,
+            camera_offset: Vec2::ZERO,
+            camera_rotation: vec2(1.0, 0.0),
+            camera_zoom: 1.0,
+            antialiasing_width: 0.0,
+            scroll_offset: Vec2::ZERO,
+            time: 0.0,
+            delta_time: 0.0,
+            frame_index: 0,
+            scale_factor: 1.0,
+            y_direction: 1.0,
+            _padding: [0.0; 1],
+        }
+    }
+
+    pub fn with_target_size(mut self, target_size: Vec2) -> Self {
+        self.target_size = target_size;
+        self
+    }
+
+    pub fn with_atlas_size(mut self, atlas_size: Vec2) -> Self {
+        self.atlas_size = atlas_size;
+        self
+    }
+
+    pub fn with_clip(mut self, clip: Vec4) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    // The rotation is the cosine and sine of the camera angle
+    pub fn with_camera(mut self, offset: Vec2, rotation: Vec2, zoom: f32) -> Self {
+        self.camera_offset = offset;
+        self.camera_rotation = rotation;
+        self.camera_zoom = zoom;
+        self
+    }
+
+    pub fn with_antialiasing_width(mut self, antialiasing_width: f32) -> Self {
+        self.antialiasing_width = antialiasing_width;
+        self
+    }
+
+    pub fn with_scroll_offset(mut self, scroll_offset: Vec2) -> Self {
+        self.scroll_offset = scroll_offset;
+        self
+    }
+
+    pub fn with_scale_factor(mut self, scale_factor: f32) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    pub fn with_y_direction(mut self, y_direction: f32) -> Self {
+        self.y_direction = y_direction;
+        self
+    }
+
+    pub fn with_time(mut self, time: f32, delta_time: f32, frame_index: u32) -> Self {
+        self.time = time;
+        self.delta_time = delta_time;
+        self.frame_index = frame_index;
+        self
+    }
+}
+
+impl ShaderConstants {
+    // Size of the surface in logical pixels
+    pub fn logical_size(&self) -> Vec2 {
+        self.surface_size / self.scale_factor
+    }
+
+    // Transforms a point in scene coordinates into surface pixel coordinates
+    pub fn to_surface(&self, position: Vec2) -> Vec2 {
+        self.orient(
+            ((position - self.scroll_offset - self.camera_offset) * self.camera_zoom)
+                .rotate(self.camera_rotation),
+        )
+    }
+
+    // Transforms a point in surface pixel coordinates back into scene coordinates
+    pub fn from_surface(&self, position: Vec2) -> Vec2 {
+        let inverse_rotation = self.camera_rotation * vec2(1.0, -1.0);
+        self.orient(position).rotate(inverse_rotation) / self.camera_zoom
+            + self.camera_offset
+            + self.scroll_offset
+    }
+
+    // Mirrors a surface position vertically when the y axis points up, so
+    // that the scene origin ends up at the bottom left. Its own inverse.
+    fn orient(&self, position: Vec2) -> Vec2 {
+        let y = position.y * self.y_direction + self.surface_size.y * (0.5 - 0.5 * self.y_direction);
+        vec2(position.x, y)
+    }
+
+    // Measures a position inside a rect of the given size from the edge of
+    // the rect which is at the top of the surface. Keeps images upright when
+    // the y axis points up.
+    pub fn orient_in_rect(&self, position: Vec2, size: Vec2) -> Vec2 {
+        vec2(position.x, 0.5 * size.y - (0.5 * size.y - position.y) * self.y_direction)
+    }
+
+    // Rotation of the scene as seen on the surface, which turns the other way
+    // when the y axis points up
+    pub fn surface_rotation(&self) -> Vec2 {
+        self.camera_rotation * vec2(1.0, self.y_direction)
+    }
+
+    // Transforms a point in surface pixel coordinates into clip space
+    pub fn surface_to_clip(&self, position: Vec2) -> Vec4 {
+        (vec2(0.0, 2.0) + position / self.surface_size * vec2(1., -1.) * 2.0 - 1.0)
+            .extend(0.0)
+            .extend(1.0)
+    }
+
+    // Transforms a point in scene coordinates all the way into clip space
+    pub fn to_clip(&self, position: Vec2) -> Vec4 {
+        self.surface_to_clip(self.to_surface(position))
+    }
+
+    // Snaps a scene rect to the pixel grid of the surface. PIXEL_SNAP_ROUND
+    // rounds each edge to the nearest pixel boundary. PIXEL_SNAP_ROUND_HALF
+    // rounds the size to whole pixels and the rect to the nearest position
+    // which puts its edges on the grid, so that it keeps its size while
+    // moving. Rects which aren't empty stay at least a pixel wide. Cameras
+    // rotated off the axes leave the rect as is.
+    pub fn snap_rect(&self, top_left: Vec2, size: Vec2, snap: u32) -> (Vec2, Vec2) {
+        if snap == PIXEL_SNAP_NONE || self.camera_rotation.x * self.camera_rotation.y != 0.0 {
+            return (top_left, size);
+        }
+
+        let first = self.to_surface(top_left);
+        let second = self.to_surface(top_left + size);
+        let min = first.min(second);
+        let max = first.max(second);
+        let min_size = Vec2::select((max - min).cmpgt(Vec2::ZERO), Vec2::ONE, Vec2::ZERO);
+        let (min, max) = if snap == PIXEL_SNAP_ROUND {
+            let min = min.round();
+            (min, max.round().max(min + min_size))
+        } else {
+            let snapped_size = (max - min).round().max(min_size);
+            let min = ((min + max - snapped_size) * 0.5).round();
+            (min, min + snapped_size)
+        };
+
+        let first = self.from_surface(min);
+        let second = self.from_surface(max);
+        (first.min(second), (second - first).abs())
+    }
+}
+
+// Computes the coverage of a pixel given its signed distance to a shape edge.
+// Uses screen space derivatives so that the ramp stays the same width in
+// pixels under camera zoom and rotation.
+#[cfg(target_arch = "spirv")]
+pub fn coverage(distance: f32, constants: &ShaderConstants) -> f32 {
+    let ramp_width = distance.fwidth() * constants.antialiasing_width;
+    if ramp_width > 0.0 {
+        (0.5 - distance / ramp_width).clamp(0.0, 1.0)
+    } else if distance <= 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// Samples a pattern image from the image atlas, wrapping the pattern position
+// so that the image tiles infinitely
+#[cfg(target_arch = "spirv")]
+pub fn sample_pattern(
+    atlas: &Image2d,
+    sampler: &Sampler,
+    atlas_rect: Vec4,
+    pattern_position: Vec2,
+    constants: &ShaderConstants,
+) -> Vec4 {
+    let size = atlas_rect.zw();
+    let wrapped = pattern_position - (pattern_position / size).floor() * size;
+    atlas.sample_by_lod(*sampler, (atlas_rect.xy() + wrapped) / constants.atlas_size, 0.)
 }
@@ -0,0 +1,67 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+#[cfg(target_arch = "spirv")]
+use spirv_std::glam::*;
+
+// Packing of instance attributes into fewer bytes. The unpacking is done with
+// integer math so that it behaves the same on the cpu and the gpu.
+
+// Packs a color with components in 0..1 into 8 bits per component, red in the
+// lowest byte
+#[cfg(not(target_arch = "spirv"))]
+pub fn pack_unorm4x8(color: Vec4) -> u32 {
+    let bytes = (color.clamp(Vec4::ZERO, Vec4::ONE) * 255.0 + 0.5).as_uvec4();
+    bytes.x | bytes.y << 8 | bytes.z << 16 | bytes.w << 24
+}
+
+pub fn unpack_unorm4x8(packed: u32) -> Vec4 {
+    vec4(
+        (packed & 0xff) as f32,
+        ((packed >> 8) & 0xff) as f32,
+        ((packed >> 16) & 0xff) as f32,
+        (packed >> 24) as f32,
+    ) / 255.0
+}
+
+// Packs two floats into half precision, x in the low 16 bits. Integers up to
+// 2048 are exact, which covers all positions in the atlases.
+#[cfg(not(target_arch = "spirv"))]
+pub fn pack_f16x2(value: Vec2) -> u32 {
+    f32_to_f16(value.x) | f32_to_f16(value.y) << 16
+}
+
+pub fn unpack_f16x2(packed: u32) -> Vec2 {
+    vec2(f16_to_f32(packed & 0xffff), f16_to_f32(packed >> 16))
+}
+
+// Rounds to the nearest half. Values too small for a normal half become zero
+// and values too large become infinity.
+#[cfg(not(target_arch = "spirv"))]
+fn f32_to_f16(value: f32) -> u32 {
+    let bits = value.to_bits();
+    let sign = (bits >> 16) & 0x8000;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent <= 0 {
+        sign
+    } else if exponent >= 31 {
+        sign | 0x7c00
+    } else {
+        // A carry out of the mantissa correctly bumps the exponent
+        (sign | (exponent as u32) << 10 | mantissa >> 13) + ((mantissa >> 12) & 1)
+    }
+}
+
+fn f16_to_f32(half: u32) -> f32 {
+    let sign = (half & 0x8000) << 16;
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) << 13;
+    let bits = if exponent == 0 {
+        sign
+    } else if exponent == 31 {
+        sign | 0x7f80_0000 | mantissa
+    } else {
+        sign | (exponent + 127 - 15) << 23 | mantissa
+    };
+    f32::from_bits(bits)
+}
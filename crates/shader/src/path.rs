@@ -2,10 +2,10 @@
 use glam::*;
 
 #[cfg(target_arch = "spirv")]
-use spirv_std::{glam::*, spirv};
+use spirv_std::{glam::*, image::Image2d, spirv, Sampler};
 
 #[cfg(target_arch = "spirv")]
-use crate::ShaderConstants;
+use crate::{sample_pattern, ShaderConstants};
 
 #[derive(Copy, Clone)]
 #[cfg_attr(
@@ -16,8 +16,13 @@ use crate::ShaderConstants;
 // NOTE: Keep the ATTRIBS array in sync with this struct
 pub struct PathVertex {
     pub color: Vec4,
+    // Top left and size of the fill pattern in the image atlas. A zero size
+    // means the path has no pattern.
+    pub pattern_atlas_rect: Vec4,
     pub position: Vec2,
-    pub _padding: Vec2,
+    // Position of the vertex in pattern pixels. Computed on the cpu since the
+    // pattern transform is affine and can be interpolated.
+    pub pattern_position: Vec2,
 }
 
 #[cfg(target_arch = "spirv")]
@@ -25,18 +30,39 @@ pub struct PathVertex {
 pub fn path_vertex(
     #[spirv(push_constant)] constants: &ShaderConstants,
     color: Vec4,
+    pattern_atlas_rect: Vec4,
     position: Vec2,
+    pattern_position: Vec2,
     out_color: &mut Vec4,
+    #[spirv(flat)] out_pattern_atlas_rect: &mut Vec4,
+    out_pattern_position: &mut Vec2,
     #[spirv(position, invariant)] out_position: &mut Vec4,
 ) {
     *out_color = color;
-    *out_position = (vec2(0., 2.) + position / constants.surface_size * vec2(1., -1.) * 2.0 - 1.0)
-        .extend(0.)
-        .extend(1.);
+    *out_pattern_atlas_rect = pattern_atlas_rect;
+    *out_pattern_position = pattern_position;
+    *out_position = constants.to_clip(position);
 }
 
 #[cfg(target_arch = "spirv")]
 #[spirv(fragment)]
-pub fn path_fragment(color: Vec4, out_color: &mut Vec4) {
+pub fn path_fragment(
+    #[spirv(descriptor_set = 0, binding = 0)] atlas: &Image2d,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    color: Vec4,
+    #[spirv(flat)] pattern_atlas_rect: Vec4,
+    pattern_position: Vec2,
+    out_color: &mut Vec4,
+) {
     *out_color = color * color;
+    if pattern_atlas_rect.z > 0.0 {
+        *out_color *= sample_pattern(
+            atlas,
+            sampler,
+            pattern_atlas_rect,
+            pattern_position,
+            constants,
+        );
+    }
 }
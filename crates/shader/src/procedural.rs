@@ -0,0 +1,261 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, num_traits::Float, spirv};
+
+#[cfg(target_arch = "spirv")]
+use crate::ShaderConstants;
+
+pub const PROCEDURAL_LINEAR_GRADIENT: u32 = 0;
+pub const PROCEDURAL_RADIAL_GRADIENT: u32 = 1;
+pub const PROCEDURAL_CHECKER: u32 = 2;
+pub const PROCEDURAL_GRID: u32 = 3;
+pub const PROCEDURAL_NOISE: u32 = 4;
+pub const PROCEDURAL_CONIC_GRADIENT: u32 = 5;
+
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+// A rect filled by one of the built in procedural functions. The function
+// produces a value between 0 and 1 which blends between the two colors, or
+// picks the color from the gradient stops of the instance when it has any.
+pub struct InstancedProcedural {
+    pub primary_color: Vec4,
+    pub secondary_color: Vec4,
+    // Meaning depends on the kind:
+    // linear gradient: x = angle in radians
+    // checker: x = cell size
+    // grid: x = cell size, y = line width
+    // noise: x = feature size, y = octaves, z = animation speed
+    // conic gradient: x = start angle, y = sweep, zw = center offset
+    pub parameters: Vec4,
+    pub top_left: Vec2,
+    pub size: Vec2,
+    pub kind: u32,
+    // Range of the stops of the instance in the stop buffer
+    pub first_stop: u32,
+    pub stop_count: u32,
+    pub _padding: f32,
+}
+
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct InstancedGradientStop {
+    pub color: Vec4,
+    pub offset: f32,
+    pub _padding: f32,
+    pub __padding: Vec2,
+}
+
+// Color at the value along the count stops from the first one, which are
+// sorted by their offsets. Values before the first stop or after the last
+// one take its color, and stops at the same offset make a hard edge.
+pub fn gradient_color(stops: &[InstancedGradientStop], first: u32, count: u32, value: f32) -> Vec4 {
+    let mut previous = stops[first as usize];
+    let mut index = 1;
+    while index < count {
+        let stop = stops[(first + index) as usize];
+        if value <= stop.offset {
+            let span = stop.offset - previous.offset;
+            let t = if span > 0.0 {
+                ((value - previous.offset) / span).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+            return previous.color.lerp(stop.color, t);
+        }
+        previous = stop;
+        index += 1;
+    }
+    previous.color
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn procedural_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] procedurals: &[InstancedProcedural],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+    out_local_position: &mut Vec2,
+) {
+    *out_instance_index = instance_index;
+
+    let unit_vertex_pos = match vert_index {
+        0 => vec2(0.0, 0.0),
+        1 => vec2(1.0, 0.0),
+        2 => vec2(1.0, 1.0),
+        3 => vec2(0.0, 0.0),
+        4 => vec2(1.0, 1.0),
+        5 => vec2(0.0, 1.0),
+        _ => unreachable!(),
+    };
+
+    let instance = procedurals[instance_index as usize];
+    *out_local_position = unit_vertex_pos * instance.size;
+    *out_position = constants.to_clip(instance.top_left + *out_local_position);
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn procedural_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] procedurals: &[InstancedProcedural],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] stops: &[InstancedGradientStop],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(flat)] instance_index: i32,
+    local_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    let instance = procedurals[instance_index as usize];
+    let value = procedural_value(
+        instance.kind,
+        instance.parameters,
+        local_position,
+        instance.size,
+        constants,
+    )
+    .clamp(0.0, 1.0);
+    *out_color = if instance.stop_count > 0 {
+        gradient_color(stops, instance.first_stop, instance.stop_count, value)
+    } else {
+        instance.primary_color.lerp(instance.secondary_color, value)
+    };
+}
+
+// Value between 0 and 1 of the procedural function at the position inside a
+// rect of the size, before clamping. Shared with the text paints.
+#[cfg(target_arch = "spirv")]
+pub fn procedural_value(
+    kind: u32,
+    parameters: Vec4,
+    local_position: Vec2,
+    size: Vec2,
+    constants: &ShaderConstants,
+) -> f32 {
+    match kind {
+        PROCEDURAL_LINEAR_GRADIENT => {
+            // Project onto the gradient direction through the center, scaled
+            // so that the gradient spans the whole rect
+            let direction = vec2(parameters.x.cos(), parameters.x.sin());
+            let extent = (size * direction).abs();
+            let length = extent.x + extent.y;
+            0.5 + (local_position - size / 2.0).dot(direction) / length.max(1.0)
+        }
+        PROCEDURAL_RADIAL_GRADIENT => {
+            let relative = (local_position - size / 2.0) / (size / 2.0);
+            relative.length()
+        }
+        PROCEDURAL_CHECKER => {
+            let cell = (local_position / parameters.x.max(1.0)).floor();
+            let sum = cell.x + cell.y;
+            sum - (sum / 2.0).floor() * 2.0
+        }
+        PROCEDURAL_GRID => {
+            let cell_size = parameters.x.max(1.0);
+            let cell_position = local_position - (local_position / cell_size).floor() * cell_size;
+            let distance = cell_position.min(Vec2::splat(cell_size) - cell_position);
+            if distance.min_element() < parameters.y / 2.0 {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        PROCEDURAL_CONIC_GRADIENT => conic_gradient(
+            local_position - size / 2.0 - parameters.zw(),
+            parameters.x,
+            parameters.y,
+            constants,
+        ),
+        _ => {
+            let position =
+                local_position / parameters.x.max(1.0) + Vec2::splat(constants.time * parameters.z);
+            fractal_noise(position, parameters.y.max(1.0) as u32)
+        }
+    }
+}
+
+// Fraction of the sweep from the start angle around the center. Outside of
+// a partial sweep the value is 1, so the only jump is from 1 back to 0 at the
+// start angle, which is antialiased by the distance to the start ray.
+#[cfg(target_arch = "spirv")]
+fn conic_gradient(
+    relative: Vec2,
+    start_angle: f32,
+    sweep: f32,
+    constants: &ShaderConstants,
+) -> f32 {
+    let tau = core::f32::consts::TAU;
+    let direction = if sweep < 0.0 { -1.0 } else { 1.0 };
+    let extent = sweep.abs().clamp(0.0001, tau);
+
+    let angle = (relative.y.atan2(relative.x) - start_angle) * direction;
+    let angle = angle - (angle / tau).floor() * tau;
+    let value = (angle / extent).min(1.0);
+
+    // Signed distance to the start ray, positive on the side the sweep goes
+    let start = vec2(start_angle.cos(), start_angle.sin());
+    let distance = (start.x * relative.y - start.y * relative.x) * direction;
+    let ramp_width = distance.fwidth() * constants.antialiasing_width;
+    if start.dot(relative) <= 0.0 || ramp_width <= 0.0 {
+        return value;
+    }
+    let after_start = (0.5 + distance / ramp_width).clamp(0.0, 1.0);
+    if value > 0.5 {
+        value * (1.0 - after_start)
+    } else {
+        1.0 + (value - 1.0) * after_start
+    }
+}
+
+#[cfg(target_arch = "spirv")]
+fn hash(p: Vec2) -> f32 {
+    let h = p.dot(vec2(127.1, 311.7)).sin() * 43758.547;
+    h - h.floor()
+}
+
+// Contribution of the random gradient at one corner of the noise cell
+#[cfg(target_arch = "spirv")]
+fn corner_gradient(cell: Vec2, f: Vec2, offset: Vec2) -> f32 {
+    let angle = hash(cell + offset) * core::f32::consts::TAU;
+    vec2(angle.cos(), angle.sin()).dot(f - offset)
+}
+
+// Gradient noise in the range -1 to 1
+#[cfg(target_arch = "spirv")]
+fn gradient_noise(p: Vec2) -> f32 {
+    let cell = p.floor();
+    let f = p - cell;
+    let u = f * f * (Vec2::splat(3.0) - 2.0 * f);
+
+    let top_left = corner_gradient(cell, f, vec2(0.0, 0.0));
+    let top_right = corner_gradient(cell, f, vec2(1.0, 0.0));
+    let bottom_left = corner_gradient(cell, f, vec2(0.0, 1.0));
+    let bottom_right = corner_gradient(cell, f, vec2(1.0, 1.0));
+
+    let top = top_left + (top_right - top_left) * u.x;
+    let bottom = bottom_left + (bottom_right - bottom_left) * u.x;
+    (top + (bottom - top) * u.y) * 1.4
+}
+
+// Sums octaves of gradient noise with halving amplitude and doubling
+// frequency, remapped to the range 0 to 1
+#[cfg(target_arch = "spirv")]
+fn fractal_noise(p: Vec2, octaves: u32) -> f32 {
+    let mut value = 0.0;
+    let mut amplitude = 0.5;
+    let mut total = 0.0;
+    let mut position = p;
+    let mut octave = 0;
+    while octave < octaves.min(8) {
+        value += gradient_noise(position) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        position *= 2.0;
+        octave += 1;
+    }
+    0.5 + 0.5 * value / total
+}
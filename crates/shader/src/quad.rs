@@ -3,7 +3,7 @@ use glam::*;
 #[cfg(target_arch = "spirv")]
 use spirv_std::{glam::*, image::Image2d, spirv, Sampler, num_traits::Float};
 #[cfg(target_arch = "spirv")]
-use crate::ShaderConstants;
+use crate::{coverage, sample_pattern, ShaderConstants};
 
 #[cfg(target_arch = "spirv")]
 const UNIT_QUAD_VERTICES: [Vec2; 6] = [
@@ -20,24 +20,52 @@ const UNIT_QUAD_VERTICES: [Vec2; 6] = [
     not(target_arch = "spirv"),
     derive(Debug, bytemuck::Pod, bytemuck::Zeroable, Default)
 )]
-#[repr(C, align(64))]
+#[repr(C)]
 // An axis aligned quad supporting positioning, scaling, corner radius, and optionally an internal blur with
 // the previous layer or an external blur for use with shadows.
 pub struct InstancedQuad {
     pub color: Vec4,
-    pub _padding: Vec4,
+    // Top left and size of the pattern image in the image atlas. A zero size
+    // means the quad has no pattern.
+    pub pattern_atlas_rect: Vec4,
+    // Columns of the matrix mapping quad local positions into pattern pixels
+    pub pattern_transform: Vec4,
+    // Drawn beneath the quad in a band around its edge
+    pub outline_color: Vec4,
     pub top_left: Vec2,
     pub size: Vec2,
-    pub __padding: Vec2,
+    pub pattern_offset: Vec2,
     pub corner_radius: f32,
     // 0: no blur
     // <0: internal blur of the background with kernel radius `blur`
     // >0: external blur of quad edge with radius `blur`
     pub blur: f32,
+    // One of the PIXEL_SNAP constants
+    pub snap: u32,
+    // Width of the outline band outside the edge, and the distance beyond it
+    // over which the outline fades out into a glow
+    pub outline_width: f32,
+    pub outline_softness: f32,
+    pub _padding: u32,
+}
+
+impl InstancedQuad {
+    // Distance outside the quad rect covered by its external blur or outline
+    pub fn extension(&self) -> f32 {
+        (self.blur.max(0.0) * 3.0).max(self.outline_width.max(0.0) + self.outline_softness.max(0.0))
+    }
 }
 
 #[cfg(target_arch = "spirv")]
 impl InstancedQuad {
+    // The quad with its rect snapped to the pixel grid
+    fn snapped(mut self, constants: &ShaderConstants) -> Self {
+        let (top_left, size) = constants.snap_rect(self.top_left, self.size, self.snap);
+        self.top_left = top_left;
+        self.size = size;
+        self
+    }
+
     fn distance(&self, point: Vec2) -> f32 {
         let half_size = self.size / 2.0 - self.corner_radius * Vec2::ONE;
         let relative_point = point - (self.top_left + self.size / 2.0);
@@ -60,20 +88,22 @@ pub fn vertex(
 
     let unit_vertex_pos = UNIT_QUAD_VERTICES[vert_index as usize];
 
-    let quad = quads[instance_index as usize];
-    let blur_extension = quad.blur.max(0.0) * 3.0 * Vec2::ONE;
+    let quad = quads[instance_index as usize].snapped(constants);
+    // Extend the quad to include the external blur or outline and the
+    // antialiasing ramp
+    let blur_extension =
+        (quad.extension() + constants.antialiasing_width / constants.camera_zoom) * Vec2::ONE;
     let vertex_pixel_pos =
         (quad.top_left - blur_extension) + unit_vertex_pos * (quad.size + blur_extension * 2.0);
 
-    let final_position =
-        vec2(0.0, 2.0) + vertex_pixel_pos / constants.surface_size * vec2(1., -1.) * 2.0 - 1.0;
-    *out_position = final_position.extend(0.0).extend(1.0);
+    *out_position = constants.to_clip(vertex_pixel_pos);
 }
 
 #[cfg(target_arch = "spirv")]
 #[spirv(fragment)]
 pub fn fragment(
     #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] quads: &[InstancedQuad],
+    #[spirv(descriptor_set = 0, binding = 1)] atlas: &Image2d,
     #[spirv(descriptor_set = 1, binding = 0)] surface: &Image2d,
     #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
     #[spirv(push_constant)] constants: &ShaderConstants,
@@ -81,9 +111,23 @@ pub fn fragment(
     #[spirv(frag_coord)] surface_position: Vec4,
     out_color: &mut Vec4,
 ) {
-    let quad = quads[instance_index as usize];
+    let quad = quads[instance_index as usize].snapped(constants);
+
+    let position = constants.from_surface(surface_position.xy());
+    let distance = quad.distance(position);
+    let mut color = quad.color;
+    if quad.pattern_atlas_rect.z > 0.0 {
+        let transform = Mat2::from_cols(quad.pattern_transform.xy(), quad.pattern_transform.zw());
+        let local_position = constants.orient_in_rect(position - quad.top_left, quad.size);
+        let pattern_position = transform * local_position + quad.pattern_offset;
+        color *=
+            sample_pattern(atlas, sampler, quad.pattern_atlas_rect, pattern_position, constants);
+    }
 
-    let distance = quad.distance(surface_position.xy());
+    // Derivatives must be computed in uniform control flow
+    let coverage = coverage(distance, constants);
+    let outline_distance = distance - quad.outline_width;
+    let outline_coverage = crate::coverage(outline_distance, constants);
     if quad.blur > 0.0 {
         // Blurs the quad edge. Good for shadows.
         let min_edge = quad.size.min_element();
@@ -93,10 +137,10 @@ pub fn fragment(
         let alpha = scale
             * (compute_erf7(inverse_blur * (min_edge + distance))
                 - compute_erf7(inverse_blur * distance));
-        *out_color = quad.color;
+        *out_color = color;
         out_color.w *= alpha;
     } else {
-        if distance <= 0.0 {
+        if coverage > 0.0 {
             if quad.blur < 0.0 {
                 // Internal box blur sampled from background
                 // Blur the quad background by sampling surrounding pixels
@@ -107,24 +151,77 @@ pub fn fragment(
                 let weight = 1.0 / ((kernel_radius.abs() * 2 + 1).pow(2) as f32);
                 for y in -kernel_radius..=kernel_radius {
                     for x in -kernel_radius..=kernel_radius {
-                        let offset = vec2(x as f32, y as f32);
-                        let sample_pos = (surface_position.xy() + offset) / constants.surface_size;
+                        // The samples are a logical pixel apart
+                        let offset = vec2(x as f32, y as f32) * constants.scale_factor;
+                        let sample_pos = (surface_position.xy() + offset) / constants.target_size;
                         let sample = surface.sample_by_lod(*sampler, sample_pos, 0.);
                         blurred_background += sample * weight;
                     }
                 }
 
-                let alpha = quad.color.w;
+                let alpha = color.w;
                 *out_color =
-                    blurred_background * (1.0 - alpha) + (quad.color.xyz() * alpha).extend(alpha);
+                    blurred_background * (1.0 - alpha) + (color.xyz() * alpha).extend(alpha);
             } else {
-                *out_color = quad.color;
+                *out_color = color;
+            }
+            out_color.w *= coverage;
+        } else {
+            *out_color = Vec4::ZERO;
+        }
+
+        if quad.outline_width > 0.0 || quad.outline_softness > 0.0 {
+            // The glow falls off quadratically past the outline width
+            let outline_alpha = if quad.outline_softness > 0.0 && outline_distance > 0.0 {
+                let falloff = (1.0 - outline_distance / quad.outline_softness).clamp(0.0, 1.0);
+                falloff * falloff
+            } else {
+                outline_coverage
+            };
+            // The outline only covers what the quad doesn't, so that it
+            // doesn't tint translucent quads
+            let ring_alpha = (outline_alpha - coverage).max(0.0) * quad.outline_color.w;
+            let alpha = out_color.w + ring_alpha;
+            if alpha > 0.0 {
+                let rgb = (out_color.xyz() * out_color.w + quad.outline_color.xyz() * ring_alpha)
+                    / alpha;
+                *out_color = rgb.extend(alpha);
             }
         }
     }
 }
 
+// Plain quads covering whole pixels with an opaque color, drawn without
+// blending. There is no antialiasing ramp to extend the quad by, as the edges
+// are on the pixel grid.
 #[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn opaque_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] quads: &[InstancedQuad],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+) {
+    *out_instance_index = instance_index;
+
+    let unit_vertex_pos = UNIT_QUAD_VERTICES[vert_index as usize];
+    let quad = quads[instance_index as usize].snapped(constants);
+    *out_position = constants.to_clip(quad.top_left + unit_vertex_pos * quad.size);
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn opaque_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] quads: &[InstancedQuad],
+    #[spirv(flat)] instance_index: i32,
+    out_color: &mut Vec4,
+) {
+    *out_color = quads[instance_index as usize].color;
+}
+
+// Also used on the cpu to blur the cached shadow masks the same way
 pub fn compute_erf7(x: f32) -> f32 {
     let x = x * core::f32::consts::FRAC_2_SQRT_PI;
     let xx = x * x;
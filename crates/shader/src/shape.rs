@@ -0,0 +1,261 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, num_traits::Float, spirv};
+
+#[cfg(target_arch = "spirv")]
+use crate::{coverage, procedural_value, ShaderConstants};
+
+pub const SHAPE_POLYGON: u32 = 0;
+pub const SHAPE_STAR: u32 = 1;
+pub const SHAPE_ARC: u32 = 2;
+pub const SHAPE_PIE: u32 = 3;
+pub const SHAPE_SQUIRCLE: u32 = 4;
+pub const SHAPE_RING: u32 = 5;
+
+// Paint kind of shapes filled with just their color
+pub const SHAPE_PAINT_COLOR: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Debug, bytemuck::Pod, bytemuck::Zeroable)
+)]
+#[repr(C)]
+// A shape inside a circle, drawn by evaluating its signed distance for every
+// pixel instead of tessellating it
+pub struct InstancedShape {
+    pub color: Vec4,
+    // Meaning depends on the kind:
+    // polygon: x = sides
+    // star: x = points, y = inner radius as a fraction of the radius
+    // arc: x = start angle, y = sweep, z = thickness
+    // pie: x = start angle, y = sweep
+    // squircle: x = exponent
+    // ring: x = start angle, y = sweep, z = inner radius, w = 1 for round caps
+    pub parameters: Vec4,
+    // Procedural function blending from the color to the secondary color,
+    // evaluated in the square around the shape, turned along with it
+    pub secondary_color: Vec4,
+    // Same as the parameters of InstancedProcedural
+    pub paint_parameters: Vec4,
+    pub center: Vec2,
+    pub radius: f32,
+    // Radians the shape is turned by around its center
+    pub rotation: f32,
+    pub kind: u32,
+    pub paint_kind: u32,
+    pub _padding: Vec2,
+}
+
+impl InstancedShape {
+    // Signed distance from the point in scene coordinates to the edge of the
+    // shape, negative inside
+    pub fn distance(&self, point: Vec2) -> f32 {
+        shape_distance(
+            self.kind,
+            self.parameters,
+            self.to_local(point),
+            self.radius,
+        )
+    }
+
+    // The point relative to the center, turned back by the rotation
+    pub fn to_local(&self, point: Vec2) -> Vec2 {
+        Vec2::from_angle(-self.rotation).rotate(point - self.center)
+    }
+}
+
+// Signed distance to the edge of the shape of the kind centered on the
+// origin. Every shape fits within the radius. Polygons and stars have a
+// corner towards negative y.
+pub fn shape_distance(kind: u32, parameters: Vec4, point: Vec2, radius: f32) -> f32 {
+    match kind {
+        SHAPE_POLYGON => {
+            // A star with its inner corners in the middle of the sides
+            let sides = parameters.x.max(3.0).floor();
+            let inner = (core::f32::consts::PI / sides).cos();
+            star_distance(point, radius, sides, inner)
+        }
+        SHAPE_STAR => star_distance(
+            point,
+            radius,
+            parameters.x.max(2.0).floor(),
+            parameters.y.clamp(0.0, 1.0),
+        ),
+        SHAPE_ARC => {
+            let inner_radius = radius - parameters.z.clamp(0.0, radius);
+            ring_distance(
+                point,
+                parameters.x,
+                parameters.y,
+                inner_radius,
+                radius,
+                true,
+            )
+        }
+        SHAPE_RING => ring_distance(
+            point,
+            parameters.x,
+            parameters.y,
+            parameters.z.clamp(0.0, radius),
+            radius,
+            parameters.w > 0.5,
+        ),
+        SHAPE_PIE => {
+            if parameters.y.abs() >= core::f32::consts::TAU {
+                return point.length() - radius;
+            }
+            let (point, aperture) = to_sweep_space(point, parameters.x, parameters.y);
+            let point = vec2(point.x.abs(), point.y);
+            let to_circle = point.length() - radius;
+            let to_side = (point - aperture * point.dot(aperture).clamp(0.0, radius)).length();
+            let side = aperture.y * point.x - aperture.x * point.y;
+            to_circle.max(if side > 0.0 { to_side } else { -to_side })
+        }
+        _ => {
+            // Superellipse, with the distance estimated from the gradient
+            let exponent = parameters.x.max(1.0);
+            let point = point.abs() / radius;
+            let power = point.powf(exponent);
+            let length = (power.x + power.y).powf(1.0 / exponent);
+            let gradient = point.powf(exponent - 1.0) * length.powf(1.0 - exponent);
+            radius * (length - 1.0) / gradient.length().max(0.001)
+        }
+    }
+}
+
+// Signed distance to a star with the points on the radius and the corners
+// between them at the fraction of it. Folds the point into the half of a
+// point where the edge is the segment from the point to the next inner
+// corner.
+fn star_distance(point: Vec2, radius: f32, points: f32, inner: f32) -> f32 {
+    let segment = core::f32::consts::TAU / points;
+    // Measured from negative y, where the first point is
+    let angle = point.x.atan2(-point.y);
+    let angle = angle - (angle / segment).floor() * segment;
+    let angle = angle.min(segment - angle);
+    let point = vec2(angle.cos(), angle.sin()) * point.length();
+
+    let outer_corner = vec2(radius, 0.0);
+    let inner_corner = vec2((segment / 2.0).cos(), (segment / 2.0).sin()) * radius * inner;
+    let edge = inner_corner - outer_corner;
+    let from_corner = point - outer_corner;
+    let t = (from_corner.dot(edge) / edge.length_squared()).clamp(0.0, 1.0);
+    let distance = (from_corner - edge * t).length();
+    // The center is on the left of the edge
+    if edge.x * from_corner.y - edge.y * from_corner.x > 0.0 {
+        -distance
+    } else {
+        distance
+    }
+}
+
+// Signed distance to the band between the radii from the start angle over
+// the sweep, with either round caps reaching past the ends or butt caps
+// ending on the rays from the center. A sweep of a full turn or more is a
+// closed ring.
+fn ring_distance(
+    point: Vec2,
+    start_angle: f32,
+    sweep: f32,
+    inner_radius: f32,
+    outer_radius: f32,
+    round_caps: bool,
+) -> f32 {
+    let half_thickness = (outer_radius - inner_radius) / 2.0;
+    let center_radius = inner_radius + half_thickness;
+    let to_band = (point.length() - center_radius).abs() - half_thickness;
+    if sweep.abs() >= core::f32::consts::TAU {
+        return to_band;
+    }
+
+    let (point, aperture) = to_sweep_space(point, start_angle, sweep);
+    let point = vec2(point.x.abs(), point.y);
+    let past_end = aperture.y * point.x > aperture.x * point.y;
+    if round_caps {
+        if past_end {
+            (point - aperture * center_radius).length() - half_thickness
+        } else {
+            to_band
+        }
+    } else {
+        // Cut by the ray through the end, like a pie
+        let to_ray = (point - aperture * point.dot(aperture).clamp(0.0, outer_radius)).length();
+        to_band.max(if past_end { to_ray } else { -to_ray })
+    }
+}
+
+// Turns the point so that the middle of the sweep from the start angle is
+// along positive y, and returns it with the sine and cosine of half the
+// sweep, which the arc and pie are symmetric around
+fn to_sweep_space(point: Vec2, start_angle: f32, sweep: f32) -> (Vec2, Vec2) {
+    let half_sweep = (sweep.abs() / 2.0).min(core::f32::consts::PI);
+    let middle = start_angle + sweep / 2.0;
+    let point = Vec2::from_angle(core::f32::consts::FRAC_PI_2 - middle).rotate(point);
+    (point, vec2(half_sweep.sin(), half_sweep.cos()))
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn shape_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] shapes: &[InstancedShape],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+) {
+    *out_instance_index = instance_index;
+
+    let unit_vertex_pos = match vert_index {
+        0 => vec2(-1.0, -1.0),
+        1 => vec2(1.0, -1.0),
+        2 => vec2(1.0, 1.0),
+        3 => vec2(-1.0, -1.0),
+        4 => vec2(1.0, 1.0),
+        5 => vec2(-1.0, 1.0),
+        _ => unreachable!(),
+    };
+
+    // The square around the circle the shape fits in, extended by the
+    // antialiasing ramp
+    let shape = shapes[instance_index as usize];
+    let extent = shape.radius + constants.antialiasing_width / constants.camera_zoom;
+    *out_position = constants.to_clip(shape.center + unit_vertex_pos * extent);
+}
+
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn shape_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] shapes: &[InstancedShape],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(flat)] instance_index: i32,
+    #[spirv(frag_coord)] surface_position: Vec4,
+    out_color: &mut Vec4,
+) {
+    let shape = shapes[instance_index as usize];
+    let position = constants.from_surface(surface_position.xy());
+    let local_position = shape.to_local(position);
+    let coverage = coverage(
+        shape_distance(shape.kind, shape.parameters, local_position, shape.radius),
+        constants,
+    );
+    *out_color = if shape.paint_kind == SHAPE_PAINT_COLOR {
+        shape.color
+    } else {
+        let size = Vec2::splat(shape.radius * 2.0);
+        let value = procedural_value(
+            shape.paint_kind,
+            shape.paint_parameters,
+            local_position + size / 2.0,
+            size,
+            constants,
+        );
+        shape
+            .color
+            .lerp(shape.secondary_color, value.clamp(0.0, 1.0))
+    };
+    out_color.w *= coverage;
+}
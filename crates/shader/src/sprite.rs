@@ -6,6 +6,13 @@ use spirv_std::{glam::*, image::Image2d, spirv, Sampler};
 
 #[cfg(target_arch = "spirv")]
 use crate::ShaderConstants;
+#[cfg(not(target_arch = "spirv"))]
+use crate::{pack_f16x2, pack_unorm4x8};
+use crate::{unpack_f16x2, unpack_unorm4x8};
+
+// Pages the image atlas can grow to. Sprites on every page are drawn with one
+// draw call when the adapter supports binding arrays of textures.
+pub const MAX_IMAGE_PAGES: usize = 8;
 
 #[derive(Copy, Clone, Default)]
 #[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
@@ -16,11 +23,83 @@ pub struct InstancedSprite {
     pub atlas_top_left: Vec2,
     pub atlas_size: Vec2,
     pub color: Vec4,
+    // Page of the image atlas the image is on
+    pub page: u32,
+    // One of the PIXEL_SNAP constants
+    pub snap: u32,
+    pub _padding: [u32; 2],
+}
+
+// InstancedSprite with the atlas rect stored as halves and the color as 8 bit
+// components. The scene rect stays full precision.
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct PackedSprite {
+    pub top_left: Vec2,
+    pub size: Vec2,
+    pub atlas_top_left: u32,
+    pub atlas_size: u32,
+    pub color: u32,
+    // The page in the low 16 bits and the snap above them
+    pub page: u32,
+}
+
+impl PackedSprite {
+    pub fn unpack(&self) -> InstancedSprite {
+        InstancedSprite {
+            top_left: self.top_left,
+            size: self.size,
+            atlas_top_left: unpack_f16x2(self.atlas_top_left),
+            atlas_size: unpack_f16x2(self.atlas_size),
+            color: unpack_unorm4x8(self.color),
+            page: self.page & 0xffff,
+            snap: self.page >> 16,
+            _padding: [0; 2],
+        }
+    }
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl From<InstancedSprite> for PackedSprite {
+    fn from(sprite: InstancedSprite) -> Self {
+        Self {
+            top_left: sprite.top_left,
+            size: sprite.size,
+            atlas_top_left: pack_f16x2(sprite.atlas_top_left),
+            atlas_size: pack_f16x2(sprite.atlas_size),
+            color: pack_unorm4x8(sprite.color),
+            page: sprite.page | sprite.snap << 16,
+        }
+    }
 }
 
 #[cfg(target_arch = "spirv")]
 #[spirv(vertex)]
 pub fn sprite_vertex(
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(vertex_index)] vert_index: i32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] sprites: &[PackedSprite],
+    #[spirv(push_constant)] constants: &ShaderConstants,
+    #[spirv(position, invariant)] out_position: &mut Vec4,
+    out_instance_index: &mut i32,
+    out_atlas_position: &mut Vec2,
+) {
+    *out_instance_index = instance_index;
+    let instance = sprites[instance_index as usize].unpack();
+    sprite_vertex_position(
+        instance,
+        vert_index,
+        constants,
+        out_position,
+        out_atlas_position,
+    );
+}
+
+// Variant of sprite_vertex reading unpacked instances
+#[cfg(target_arch = "spirv")]
+#[spirv(vertex)]
+pub fn sprite_vertex_f32(
     #[spirv(instance_index)] instance_index: i32,
     #[spirv(vertex_index)] vert_index: i32,
     #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] sprites: &[InstancedSprite],
@@ -30,7 +109,24 @@ pub fn sprite_vertex(
     out_atlas_position: &mut Vec2,
 ) {
     *out_instance_index = instance_index;
+    let instance = sprites[instance_index as usize];
+    sprite_vertex_position(
+        instance,
+        vert_index,
+        constants,
+        out_position,
+        out_atlas_position,
+    );
+}
 
+#[cfg(target_arch = "spirv")]
+fn sprite_vertex_position(
+    instance: InstancedSprite,
+    vert_index: i32,
+    constants: &ShaderConstants,
+    out_position: &mut Vec4,
+    out_atlas_position: &mut Vec2,
+) {
     let unit_vertex_pos = match vert_index {
         0 => vec2(0.0, 0.0),
         1 => vec2(1.0, 0.0),
@@ -41,20 +137,34 @@ pub fn sprite_vertex(
         _ => unreachable!(),
     };
 
-    let instance = sprites[instance_index as usize];
-    let vertex_pixel_pos = instance.top_left + unit_vertex_pos * instance.size;
+    let (top_left, size) = constants.snap_rect(instance.top_left, instance.size, instance.snap);
+    let vertex_pixel_pos = top_left + unit_vertex_pos * size;
 
-    let final_position =
-        vec2(0.0, 2.0) + vertex_pixel_pos / constants.surface_size * vec2(1., -1.) * 2.0 - 1.0;
-    *out_position = final_position.extend(0.0).extend(1.0);
+    *out_position = constants.to_clip(vertex_pixel_pos);
 
     *out_atlas_position = instance.atlas_top_left / constants.atlas_size
-        + unit_vertex_pos * instance.atlas_size / constants.atlas_size;
+        + constants.orient_in_rect(unit_vertex_pos, Vec2::ONE) * instance.atlas_size
+            / constants.atlas_size;
 }
 
 #[cfg(target_arch = "spirv")]
 #[spirv(fragment)]
 pub fn sprite_fragment(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] sprites: &[PackedSprite],
+    #[spirv(descriptor_set = 0, binding = 1)] atlas: &Image2d,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(flat)] instance_index: i32,
+    atlas_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    let color = unpack_unorm4x8(sprites[instance_index as usize].color);
+    *out_color = sprite_color(color, atlas, sampler, atlas_position);
+}
+
+// Variant of sprite_fragment reading unpacked instances
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn sprite_fragment_f32(
     #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] sprites: &[InstancedSprite],
     #[spirv(descriptor_set = 0, binding = 1)] atlas: &Image2d,
     #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
@@ -62,11 +172,50 @@ pub fn sprite_fragment(
     atlas_position: Vec2,
     out_color: &mut Vec4,
 ) {
-    let instance = sprites[instance_index as usize];
+    let color = sprites[instance_index as usize].color;
+    *out_color = sprite_color(color, atlas, sampler, atlas_position);
+}
+
+// Variant of sprite_fragment sampling the page of the sprite from a binding
+// array of every page of the image atlas
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn sprite_fragment_paged(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] sprites: &[PackedSprite],
+    #[spirv(descriptor_set = 0, binding = 1)] pages: &[Image2d; MAX_IMAGE_PAGES],
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(flat)] instance_index: i32,
+    atlas_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    let sprite = sprites[instance_index as usize];
+    let color = unpack_unorm4x8(sprite.color);
+    let atlas = &pages[sprite.page as usize];
+    *out_color = sprite_color(color, atlas, sampler, atlas_position);
+}
+
+// Variant of sprite_fragment_paged reading unpacked instances
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn sprite_fragment_paged_f32(
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] sprites: &[InstancedSprite],
+    #[spirv(descriptor_set = 0, binding = 1)] pages: &[Image2d; MAX_IMAGE_PAGES],
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(flat)] instance_index: i32,
+    atlas_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    let sprite = sprites[instance_index as usize];
+    let atlas = &pages[sprite.page as usize];
+    *out_color = sprite_color(sprite.color, atlas, sampler, atlas_position);
+}
+
+#[cfg(target_arch = "spirv")]
+fn sprite_color(color: Vec4, atlas: &Image2d, sampler: &Sampler, atlas_position: Vec2) -> Vec4 {
     // Here we have to sample specifically the 0 LOD. I don't
     // fully understand why, but I think it has to do with how
     // the spirv is generated.
     // More details here: https://github.com/gfx-rs/wgpu-rs/issues/912
     let image_color = atlas.sample_by_lod(*sampler, atlas_position, 0.);
-    *out_color = instance.color * image_color;
+    color * image_color
 }
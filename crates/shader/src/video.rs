@@ -0,0 +1,55 @@
+#[cfg(not(target_arch = "spirv"))]
+use glam::*;
+
+#[cfg(target_arch = "spirv")]
+use spirv_std::{glam::*, image::Image2d, spirv, Sampler};
+
+// Converts the planes of a yuv video frame to rgb. Each row is the weights of
+// y, u, v and the offset of one color channel, so the range expansion and
+// the color matrix are a single multiply.
+#[derive(Copy, Clone)]
+#[cfg_attr(not(target_arch = "spirv"), derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[repr(C)]
+pub struct VideoConversion {
+    pub red: Vec4,
+    pub green: Vec4,
+    pub blue: Vec4,
+    // One when u and v are interleaved in the chroma plane as in NV12, zero
+    // when v has a plane of its own
+    pub interleaved_chroma: u32,
+    pub _padding: [u32; 3],
+}
+
+impl VideoConversion {
+    pub fn convert(&self, yuv: Vec3) -> Vec3 {
+        let yuv = yuv.extend(1.0);
+        vec3(self.red.dot(yuv), self.green.dot(yuv), self.blue.dot(yuv))
+            .clamp(Vec3::ZERO, Vec3::ONE)
+    }
+}
+
+// Draws with blit::blit_vertex, which covers the viewport set to the atlas
+// rect of the video
+#[cfg(target_arch = "spirv")]
+#[spirv(fragment)]
+pub fn video_fragment(
+    #[spirv(descriptor_set = 0, binding = 0)] luma: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] chroma: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 2)] chroma_v: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 3)] sampler: &Sampler,
+    #[spirv(uniform, descriptor_set = 0, binding = 4)] conversion: &VideoConversion,
+    texture_position: Vec2,
+    out_color: &mut Vec4,
+) {
+    let y = luma.sample_by_lod(*sampler, texture_position, 0.).x;
+    let chroma_sample = chroma.sample_by_lod(*sampler, texture_position, 0.);
+    let uv = if conversion.interleaved_chroma == 1 {
+        chroma_sample.xy()
+    } else {
+        vec2(
+            chroma_sample.x,
+            chroma_v.sample_by_lod(*sampler, texture_position, 0.).x,
+        )
+    };
+    *out_color = conversion.convert(vec3(y, uv.x, uv.y)).extend(1.0);
+}
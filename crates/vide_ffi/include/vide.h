@@ -0,0 +1,189 @@
+
+/* C api of the vide renderer.
+ *
+ * Objects returned by the *_new functions are owned by the caller and freed
+ * with the matching *_free function. Strings are nul terminated utf-8. All
+ * functions have to be called from the thread which created the renderer.
+ */
+
+#ifndef VIDE_H
+#define VIDE_H
+
+/* Generated with cbindgen from crates/vide_ffi, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum VideDrawResult {
+  VIDE_DRAW_RESULT_PRESENTED,
+  // Nothing was drawn because the window is minimized or suspended
+  VIDE_DRAW_RESULT_SKIPPED,
+  // The frame was dropped, drawing again later is expected to succeed
+  VIDE_DRAW_RESULT_DROPPED,
+  // The window can't be drawn to anymore and the renderer has to be
+  // recreated
+  VIDE_DRAW_RESULT_FATAL,
+  // Invalid arguments or a panic inside the renderer
+  VIDE_DRAW_RESULT_FAILED,
+} VideDrawResult;
+
+// Renders scenes to rgba pixels without a window
+typedef struct VideOffscreenRenderer VideOffscreenRenderer;
+
+// A scene built from layers of quads, text and sprites
+typedef struct VideScene VideScene;
+
+// Renders scenes to a native window
+typedef struct VideWindowRenderer VideWindowRenderer;
+
+typedef struct VideColor {
+  float r;
+  float g;
+  float b;
+  float a;
+} VideColor;
+
+typedef struct VideRect {
+  float x;
+  float y;
+  float width;
+  float height;
+} VideRect;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an empty scene with a single layer
+VideScene *vide_scene_new(void);
+
+// Parses a scene in the json format of the scene viewer. Returns null if the
+// json is invalid.
+VideScene *vide_scene_from_json(const char *json);
+
+void vide_scene_free(VideScene *scene);
+
+// Starts a new layer drawn on top of the previous ones. The following
+// primitives and layer settings apply to the new layer.
+void vide_scene_add_layer(VideScene *scene);
+
+void vide_scene_set_background(VideScene *scene, VideColor color);
+
+// Restricts drawing of the current layer to the rect
+void vide_scene_set_clip(VideScene *scene, VideRect clip);
+
+// Sets the font of the text in the current layer. Returns false if the name
+// isn't valid utf-8.
+bool vide_scene_set_font(VideScene *scene, const char *font_name);
+
+void vide_scene_add_quad(VideScene *scene, VideRect rect, VideColor color, float corner_radius);
+
+// Adds a line of text with its baseline starting at x, y. Returns false if
+// the text isn't valid utf-8.
+bool vide_scene_add_text(VideScene *scene,
+                         const char *text,
+                         float x,
+                         float y,
+                         float size,
+                         VideColor color);
+
+// Adds an image registered with the renderer or loaded from its asset
+// directory, stretched to the rect. Returns false if the name isn't valid
+// utf-8.
+bool vide_scene_add_sprite(VideScene *scene, const char *image_name, VideRect rect);
+
+// Creates a renderer for an Xlib window. Returns null on failure.
+VideWindowRenderer *vide_window_renderer_new_xlib(void *display,
+                                                  int screen,
+                                                  unsigned long window,
+                                                  uint32_t width,
+                                                  uint32_t height);
+
+// Creates a renderer for a Wayland surface. Returns null on failure.
+VideWindowRenderer *vide_window_renderer_new_wayland(void *display,
+                                                     void *surface,
+                                                     uint32_t width,
+                                                     uint32_t height);
+
+// Creates a renderer for a Win32 window. Returns null on failure.
+VideWindowRenderer *vide_window_renderer_new_win32(void *hwnd,
+                                                   void *hinstance,
+                                                   uint32_t width,
+                                                   uint32_t height);
+
+// Creates a renderer for the NSView of an AppKit window. Returns null on
+// failure.
+VideWindowRenderer *vide_window_renderer_new_appkit(void *ns_view, uint32_t width, uint32_t height);
+
+// Creates a renderer for a CAMetalLayer, such as the layer of a UIView on
+// iOS. The size is in pixels and the scale factor is the contents scale of
+// the layer. Returns null on failure or on platforms without Metal.
+VideWindowRenderer *vide_window_renderer_new_metal_layer(void *layer,
+                                                         uint32_t width,
+                                                         uint32_t height,
+                                                         float scale_factor);
+
+void vide_window_renderer_free(VideWindowRenderer *renderer);
+
+// Call whenever the window size changes
+void vide_window_renderer_resize(VideWindowRenderer *renderer, uint32_t width, uint32_t height);
+
+// Call whenever the window moves to a screen with another scale factor.
+// Returns false if the scale factor isn't positive.
+bool vide_window_renderer_set_scale_factor(VideWindowRenderer *renderer, float scale_factor);
+
+// Releases cached glyphs and images when the system is low on memory, such
+// as on a memory warning on iOS. They are uploaded again when next drawn.
+void vide_window_renderer_memory_warning(VideWindowRenderer *renderer);
+
+// Draws the scene to the window and presents it
+VideDrawResult vide_window_renderer_draw(VideWindowRenderer *renderer, const VideScene *scene);
+
+// Registers tightly packed rgba8 pixels which sprites can refer to by name.
+// Returns false if the name isn't valid utf-8.
+bool vide_window_renderer_add_image_rgba(VideWindowRenderer *renderer,
+                                         const char *name,
+                                         uint32_t width,
+                                         uint32_t height,
+                                         const uint8_t *data);
+
+// Loads images and fonts by name from files in the directory
+bool vide_window_renderer_set_asset_directory(VideWindowRenderer *renderer, const char *path);
+
+// Creates a renderer drawing to rgba pixels. Returns null on failure.
+VideOffscreenRenderer *vide_offscreen_renderer_new(uint32_t width, uint32_t height);
+
+void vide_offscreen_renderer_free(VideOffscreenRenderer *renderer);
+
+void vide_offscreen_renderer_resize(VideOffscreenRenderer *renderer,
+                                    uint32_t width,
+                                    uint32_t height);
+
+// Renders the scene into pixels, which has to hold width * height * 4
+// bytes of rgba8 rows starting from the top left. Returns false if the
+// buffer is too small.
+bool vide_offscreen_renderer_draw(VideOffscreenRenderer *renderer,
+                                  const VideScene *scene,
+                                  uint8_t *pixels,
+                                  size_t length);
+
+// Registers tightly packed rgba8 pixels which sprites can refer to by name.
+// Returns false if the name isn't valid utf-8.
+bool vide_offscreen_renderer_add_image_rgba(VideOffscreenRenderer *renderer,
+                                            const char *name,
+                                            uint32_t width,
+                                            uint32_t height,
+                                            const uint8_t *data);
+
+// Loads images and fonts by name from files in the directory
+bool vide_offscreen_renderer_set_asset_directory(VideOffscreenRenderer *renderer,
+                                                 const char *path);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VIDE_H */
@@ -0,0 +1,555 @@
+// C bindings for building scenes and rendering them to a window or to rgba
+// pixels. The header in include/vide.h is generated from this file with
+// cbindgen, so the /// comments of the exported items end up in the header.
+//
+// Pointers passed to the functions have to be valid and returned by the
+// matching constructor, and strings have to be nul terminated. Null object
+// pointers are ignored. Panics are caught at the boundary and reported as
+// failures instead of unwinding into the caller.
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+    ffi::{c_char, c_int, c_ulong, c_void, CStr},
+    num::NonZeroIsize,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr::{self, NonNull},
+    slice,
+};
+
+use glam::{vec2, vec4, Vec2, Vec4};
+use smol::block_on;
+use vide::{
+    DirectoryAssets, DrawOutcome, OffscreenRenderer, Quad, RawWindowRenderer, Scene, Sprite, Text,
+};
+use wgpu::rwh::{
+    AppKitDisplayHandle, AppKitWindowHandle, DisplayHandle, HandleError, HasDisplayHandle,
+    HasWindowHandle, RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
+    Win32WindowHandle, WindowHandle, WindowsDisplayHandle, XlibDisplayHandle, XlibWindowHandle,
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VideColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl From<VideColor> for Vec4 {
+    fn from(color: VideColor) -> Self {
+        vec4(color.r, color.g, color.b, color.a)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VideRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl VideRect {
+    fn top_left(&self) -> Vec2 {
+        vec2(self.x, self.y)
+    }
+
+    fn size(&self) -> Vec2 {
+        vec2(self.width, self.height)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideDrawResult {
+    Presented,
+    /// Nothing was drawn because the window is minimized or suspended
+    Skipped,
+    /// The frame was dropped, drawing again later is expected to succeed
+    Dropped,
+    /// The window can't be drawn to anymore and the renderer has to be
+    /// recreated
+    Fatal,
+    /// Invalid arguments or a panic inside the renderer
+    Failed,
+}
+
+impl From<DrawOutcome> for VideDrawResult {
+    fn from(outcome: DrawOutcome) -> Self {
+        match outcome {
+            DrawOutcome::Presented => VideDrawResult::Presented,
+            DrawOutcome::Skipped => VideDrawResult::Skipped,
+            DrawOutcome::Dropped(_) => VideDrawResult::Dropped,
+            DrawOutcome::Fatal(_) => VideDrawResult::Fatal,
+        }
+    }
+}
+
+/// A scene built from layers of quads, text and sprites
+pub struct VideScene(Scene);
+
+/// Renders scenes to a native window
+pub struct VideWindowRenderer(RawWindowRenderer<'static>);
+
+/// Renders scenes to rgba pixels without a window
+pub struct VideOffscreenRenderer(OffscreenRenderer);
+
+// The raw handles of a window owned by the caller, who guarantees they stay
+// valid until the renderer is freed
+struct NativeWindow {
+    window: RawWindowHandle,
+    display: RawDisplayHandle,
+}
+
+// The handles are only dereferenced by the graphics api on the thread which
+// created the renderer
+unsafe impl Send for NativeWindow {}
+unsafe impl Sync for NativeWindow {}
+
+impl HasWindowHandle for NativeWindow {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        Ok(unsafe { WindowHandle::borrow_raw(self.window) })
+    }
+}
+
+impl HasDisplayHandle for NativeWindow {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        Ok(unsafe { DisplayHandle::borrow_raw(self.display) })
+    }
+}
+
+// Runs the body, turning a panic into the fallback value
+fn guard<T>(fallback: T, body: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or(fallback)
+}
+
+unsafe fn string<'a>(text: *const c_char) -> Option<&'a str> {
+    if text.is_null() {
+        return None;
+    }
+    CStr::from_ptr(text).to_str().ok()
+}
+
+fn boxed<T>(value: Option<T>) -> *mut T {
+    value.map_or(ptr::null_mut(), |value| Box::into_raw(Box::new(value)))
+}
+
+unsafe fn free<T>(value: *mut T) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// Creates an empty scene with a single layer
+#[no_mangle]
+pub extern "C" fn vide_scene_new() -> *mut VideScene {
+    boxed(Some(VideScene(Scene::new())))
+}
+
+/// Parses a scene in the json format of the scene viewer. Returns null if the
+/// json is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_from_json(json: *const c_char) -> *mut VideScene {
+    guard(ptr::null_mut(), || {
+        boxed(
+            string(json)
+                .and_then(|json| serde_json::from_str(json).ok())
+                .map(VideScene),
+        )
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_free(scene: *mut VideScene) {
+    free(scene);
+}
+
+/// Starts a new layer drawn on top of the previous ones. The following
+/// primitives and layer settings apply to the new layer.
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_add_layer(scene: *mut VideScene) {
+    if let Some(scene) = scene.as_mut() {
+        scene.0.add_layer(Default::default());
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_set_background(scene: *mut VideScene, color: VideColor) {
+    if let Some(scene) = scene.as_mut() {
+        scene.0.layer_mut().background_color = Some(color.into());
+    }
+}
+
+/// Restricts drawing of the current layer to the rect
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_set_clip(scene: *mut VideScene, clip: VideRect) {
+    if let Some(scene) = scene.as_mut() {
+        scene
+            .0
+            .layer_mut()
+            .set_clip(vec4(clip.x, clip.y, clip.width, clip.height));
+    }
+}
+
+/// Sets the font of the text in the current layer. Returns false if the name
+/// isn't valid utf-8.
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_set_font(
+    scene: *mut VideScene,
+    font_name: *const c_char,
+) -> bool {
+    let (Some(scene), Some(font_name)) = (scene.as_mut(), string(font_name)) else {
+        return false;
+    };
+    scene.0.layer_mut().font_name = font_name.to_string();
+    true
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_add_quad(
+    scene: *mut VideScene,
+    rect: VideRect,
+    color: VideColor,
+    corner_radius: f32,
+) {
+    if let Some(scene) = scene.as_mut() {
+        scene.0.add_quad(
+            Quad::new(rect.top_left(), rect.size(), color.into()).with_corner_radius(corner_radius),
+        );
+    }
+}
+
+/// Adds a line of text with its baseline starting at x, y. Returns false if
+/// the text isn't valid utf-8.
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_add_text(
+    scene: *mut VideScene,
+    text: *const c_char,
+    x: f32,
+    y: f32,
+    size: f32,
+    color: VideColor,
+) -> bool {
+    let (Some(scene), Some(text)) = (scene.as_mut(), string(text)) else {
+        return false;
+    };
+    scene
+        .0
+        .add_text(Text::new(text.to_string(), vec2(x, y), size, color.into()));
+    true
+}
+
+/// Adds an image registered with the renderer or loaded from its asset
+/// directory, stretched to the rect. Returns false if the name isn't valid
+/// utf-8.
+#[no_mangle]
+pub unsafe extern "C" fn vide_scene_add_sprite(
+    scene: *mut VideScene,
+    image_name: *const c_char,
+    rect: VideRect,
+) -> bool {
+    let (Some(scene), Some(image_name)) = (scene.as_mut(), string(image_name)) else {
+        return false;
+    };
+    scene.0.add_sprite(Sprite::new(
+        image_name.to_string(),
+        rect.top_left(),
+        rect.size(),
+    ));
+    true
+}
+
+fn window_renderer(
+    window: Option<NativeWindow>,
+    width: u32,
+    height: u32,
+) -> *mut VideWindowRenderer {
+    guard(ptr::null_mut(), || {
+        boxed(window.and_then(|window| {
+            let renderer = block_on(RawWindowRenderer::new(window, width, height)).ok()?;
+            Some(VideWindowRenderer(renderer.with_builtin_drawables()))
+        }))
+    })
+}
+
+/// Creates a renderer for an Xlib window. Returns null on failure.
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_new_xlib(
+    display: *mut c_void,
+    screen: c_int,
+    window: c_ulong,
+    width: u32,
+    height: u32,
+) -> *mut VideWindowRenderer {
+    let window = NativeWindow {
+        window: XlibWindowHandle::new(window).into(),
+        display: XlibDisplayHandle::new(NonNull::new(display), screen).into(),
+    };
+    window_renderer(Some(window), width, height)
+}
+
+/// Creates a renderer for a Wayland surface. Returns null on failure.
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_new_wayland(
+    display: *mut c_void,
+    surface: *mut c_void,
+    width: u32,
+    height: u32,
+) -> *mut VideWindowRenderer {
+    let window = NonNull::new(display)
+        .zip(NonNull::new(surface))
+        .map(|(display, surface)| NativeWindow {
+            window: WaylandWindowHandle::new(surface).into(),
+            display: WaylandDisplayHandle::new(display).into(),
+        });
+    window_renderer(window, width, height)
+}
+
+/// Creates a renderer for a Win32 window. Returns null on failure.
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_new_win32(
+    hwnd: *mut c_void,
+    hinstance: *mut c_void,
+    width: u32,
+    height: u32,
+) -> *mut VideWindowRenderer {
+    let window = NonZeroIsize::new(hwnd as isize).map(|hwnd| {
+        let mut window = Win32WindowHandle::new(hwnd);
+        window.hinstance = NonZeroIsize::new(hinstance as isize);
+        NativeWindow {
+            window: window.into(),
+            display: WindowsDisplayHandle::new().into(),
+        }
+    });
+    window_renderer(window, width, height)
+}
+
+/// Creates a renderer for the NSView of an AppKit window. Returns null on
+/// failure.
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_new_appkit(
+    ns_view: *mut c_void,
+    width: u32,
+    height: u32,
+) -> *mut VideWindowRenderer {
+    let window = NonNull::new(ns_view).map(|ns_view| NativeWindow {
+        window: AppKitWindowHandle::new(ns_view).into(),
+        display: AppKitDisplayHandle::new().into(),
+    });
+    window_renderer(window, width, height)
+}
+
+/// Creates a renderer for a CAMetalLayer, such as the layer of a UIView on
+/// iOS. The size is in pixels and the scale factor is the contents scale of
+/// the layer. Returns null on failure or on platforms without Metal.
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_new_metal_layer(
+    layer: *mut c_void,
+    width: u32,
+    height: u32,
+    scale_factor: f32,
+) -> *mut VideWindowRenderer {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    if !layer.is_null() {
+        return guard(ptr::null_mut(), || {
+            let renderer = block_on(RawWindowRenderer::from_metal_layer(layer, width, height));
+            boxed(renderer.ok().map(|renderer| {
+                VideWindowRenderer(
+                    renderer
+                        .with_builtin_drawables()
+                        .with_scale_factor(scale_factor),
+                )
+            }))
+        });
+    }
+    let _ = (layer, width, height, scale_factor);
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_free(renderer: *mut VideWindowRenderer) {
+    free(renderer);
+}
+
+/// Call whenever the window size changes
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_resize(
+    renderer: *mut VideWindowRenderer,
+    width: u32,
+    height: u32,
+) {
+    if let Some(renderer) = renderer.as_mut() {
+        guard((), || renderer.0.resize(width, height));
+    }
+}
+
+/// Call whenever the window moves to a screen with another scale factor.
+/// Returns false if the scale factor isn't positive.
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_set_scale_factor(
+    renderer: *mut VideWindowRenderer,
+    scale_factor: f32,
+) -> bool {
+    let Some(renderer) = renderer.as_mut() else {
+        return false;
+    };
+    guard(false, || {
+        renderer.0.set_scale_factor(scale_factor);
+        true
+    })
+}
+
+/// Releases cached glyphs and images when the system is low on memory, such
+/// as on a memory warning on iOS. They are uploaded again when next drawn.
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_memory_warning(renderer: *mut VideWindowRenderer) {
+    if let Some(renderer) = renderer.as_mut() {
+        guard((), || renderer.0.handle_memory_warning());
+    }
+}
+
+/// Draws the scene to the window and presents it
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_draw(
+    renderer: *mut VideWindowRenderer,
+    scene: *const VideScene,
+) -> VideDrawResult {
+    let (Some(renderer), Some(scene)) = (renderer.as_mut(), scene.as_ref()) else {
+        return VideDrawResult::Failed;
+    };
+    guard(VideDrawResult::Failed, || renderer.0.draw(&scene.0).into())
+}
+
+/// Registers tightly packed rgba8 pixels which sprites can refer to by name.
+/// Returns false if the name isn't valid utf-8.
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_add_image_rgba(
+    renderer: *mut VideWindowRenderer,
+    name: *const c_char,
+    width: u32,
+    height: u32,
+    data: *const u8,
+) -> bool {
+    let (Some(renderer), Some(name)) = (renderer.as_mut(), string(name)) else {
+        return false;
+    };
+    if data.is_null() {
+        return false;
+    }
+    let data = slice::from_raw_parts(data, width as usize * height as usize * 4).to_vec();
+    guard(false, || {
+        renderer
+            .0
+            .renderer_mut()
+            .add_image_rgba(name, width, height, data);
+        true
+    })
+}
+
+/// Loads images and fonts by name from files in the directory
+#[no_mangle]
+pub unsafe extern "C" fn vide_window_renderer_set_asset_directory(
+    renderer: *mut VideWindowRenderer,
+    path: *const c_char,
+) -> bool {
+    let (Some(renderer), Some(path)) = (renderer.as_mut(), string(path)) else {
+        return false;
+    };
+    renderer.0.set_asset_source(DirectoryAssets::new(path));
+    true
+}
+
+/// Creates a renderer drawing to rgba pixels. Returns null on failure.
+#[no_mangle]
+pub extern "C" fn vide_offscreen_renderer_new(
+    width: u32,
+    height: u32,
+) -> *mut VideOffscreenRenderer {
+    guard(ptr::null_mut(), || {
+        let renderer = block_on(OffscreenRenderer::new(width, height)).ok();
+        boxed(renderer.map(|renderer| VideOffscreenRenderer(renderer.with_builtin_drawables())))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vide_offscreen_renderer_free(renderer: *mut VideOffscreenRenderer) {
+    free(renderer);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn vide_offscreen_renderer_resize(
+    renderer: *mut VideOffscreenRenderer,
+    width: u32,
+    height: u32,
+) {
+    if let Some(renderer) = renderer.as_mut() {
+        guard((), || renderer.0.resize(width, height));
+    }
+}
+
+/// Renders the scene into pixels, which has to hold width * height * 4
+/// bytes of rgba8 rows starting from the top left. Returns false if the
+/// buffer is too small.
+#[no_mangle]
+pub unsafe extern "C" fn vide_offscreen_renderer_draw(
+    renderer: *mut VideOffscreenRenderer,
+    scene: *const VideScene,
+    pixels: *mut u8,
+    length: usize,
+) -> bool {
+    let (Some(renderer), Some(scene)) = (renderer.as_mut(), scene.as_ref()) else {
+        return false;
+    };
+    if pixels.is_null() {
+        return false;
+    }
+    guard(false, || {
+        let data = block_on(renderer.0.draw_rgba(&scene.0));
+        if data.len() > length {
+            return false;
+        }
+        slice::from_raw_parts_mut(pixels, data.len()).copy_from_slice(&data);
+        true
+    })
+}
+
+/// Registers tightly packed rgba8 pixels which sprites can refer to by name.
+/// Returns false if the name isn't valid utf-8.
+#[no_mangle]
+pub unsafe extern "C" fn vide_offscreen_renderer_add_image_rgba(
+    renderer: *mut VideOffscreenRenderer,
+    name: *const c_char,
+    width: u32,
+    height: u32,
+    data: *const u8,
+) -> bool {
+    let (Some(renderer), Some(name)) = (renderer.as_mut(), string(name)) else {
+        return false;
+    };
+    if data.is_null() {
+        return false;
+    }
+    let data = slice::from_raw_parts(data, width as usize * height as usize * 4).to_vec();
+    guard(false, || {
+        renderer
+            .0
+            .renderer
+            .add_image_rgba(name, width, height, data);
+        true
+    })
+}
+
+/// Loads images and fonts by name from files in the directory
+#[no_mangle]
+pub unsafe extern "C" fn vide_offscreen_renderer_set_asset_directory(
+    renderer: *mut VideOffscreenRenderer,
+    path: *const c_char,
+) -> bool {
+    let (Some(renderer), Some(path)) = (renderer.as_mut(), string(path)) else {
+        return false;
+    };
+    renderer.0.set_asset_source(DirectoryAssets::new(path));
+    true
+}
@@ -0,0 +1,46 @@
+// Renders the standard bench scenes offscreen and reports the cpu and gpu
+// time per frame.
+//
+// cargo run --release --example vide-bench -- [--frames N] [scene names...]
+//
+// Without scene names every scene is rendered.
+
+#[path = "../benches/scenes/mod.rs"]
+mod scenes;
+
+use scenes::{create_renderer, measure, report, standard_scenes};
+
+fn main() {
+    let mut frames = 100;
+    let mut names = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--frames" {
+            frames = args
+                .next()
+                .and_then(|frames| frames.parse().ok())
+                .expect("--frames expects a number");
+        } else {
+            names.push(arg);
+        }
+    }
+
+    let scenes: Vec<_> = standard_scenes()
+        .into_iter()
+        .filter(|(name, _)| names.is_empty() || names.iter().any(|selected| selected == name))
+        .collect();
+    if scenes.is_empty() {
+        let available: Vec<_> = standard_scenes()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        eprintln!("Unknown scene. Available scenes: {}", available.join(", "));
+        std::process::exit(1);
+    }
+
+    let mut renderer = create_renderer();
+    for (name, scene) in scenes {
+        let timings = measure(&mut renderer, &scene, frames);
+        report(name, &timings, frames);
+    }
+}
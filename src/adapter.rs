@@ -0,0 +1,131 @@
+use wgpu::{
+    Adapter, AdapterInfo, Backends, Instance, InstanceDescriptor, PowerPreference,
+    RequestAdapterOptions, Surface,
+};
+
+use tracing::{info, warn};
+
+use crate::capabilities::BACKENDS;
+
+// Comma separated backends the renderers pick an adapter from instead of the
+// default ones, such as "gl" or "vulkan,gl"
+pub const BACKEND_VAR: &str = "VIDE_BACKEND";
+// Adapter the renderers use instead of the one wgpu prefers, either its
+// index among the adapters of the backends or a case insensitive part of
+// its name
+pub const ADAPTER_VAR: &str = "VIDE_ADAPTER";
+
+// Parses a comma separated list of backend names. None when there's a name
+// which isn't a backend, so that a typo doesn't silently pick another one.
+pub(crate) fn parse_backends(list: &str) -> Option<Backends> {
+    let mut backends = Backends::empty();
+    for name in list
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        backends |= match name.to_lowercase().as_str() {
+            "vulkan" | "vk" => Backends::VULKAN,
+            "gl" | "gles" | "opengl" => Backends::GL,
+            "metal" | "mtl" => Backends::METAL,
+            "dx12" | "d3d12" => Backends::DX12,
+            "primary" => Backends::PRIMARY,
+            "all" => Backends::all(),
+            _ => return None,
+        };
+    }
+    (!backends.is_empty()).then_some(backends)
+}
+
+// Index of the adapter the selector picks, by index or by name
+pub(crate) fn select_adapter(adapters: &[AdapterInfo], selector: &str) -> Option<usize> {
+    let selector = selector.trim();
+    if let Ok(index) = selector.parse::<usize>() {
+        return (index < adapters.len()).then_some(index);
+    }
+    let selector = selector.to_lowercase();
+    adapters
+        .iter()
+        .position(|adapter| adapter.name.to_lowercase().contains(&selector))
+}
+
+pub(crate) fn backend_override() -> Option<String> {
+    std::env::var(BACKEND_VAR).ok()
+}
+
+pub(crate) fn adapter_override() -> Option<String> {
+    std::env::var(ADAPTER_VAR).ok()
+}
+
+// Backends of the environment override when it's valid, the default ones
+// otherwise
+pub(crate) fn backends() -> Backends {
+    let Some(list) = backend_override() else {
+        return BACKENDS;
+    };
+    parse_backends(&list).unwrap_or_else(|| {
+        warn!("Ignoring {BACKEND_VAR}={list:?}, which isn't a list of backends");
+        BACKENDS
+    })
+}
+
+pub(crate) fn create_instance() -> Instance {
+    Instance::new(InstanceDescriptor {
+        backends: backends(),
+        ..Default::default()
+    })
+}
+
+// The adapter picked by the environment override when it matches one, and
+// it can present to the surface, otherwise the one wgpu prefers
+pub(crate) async fn request_adapter(
+    instance: &Instance,
+    surface: Option<&Surface<'_>>,
+) -> Option<Adapter> {
+    if let Some(selector) = adapter_override() {
+        let mut adapters = instance.enumerate_adapters(backends());
+        let infos: Vec<_> = adapters.iter().map(Adapter::get_info).collect();
+        match select_adapter(&infos, &selector) {
+            Some(index) => {
+                let adapter = adapters.swap_remove(index);
+                if surface.map_or(true, |surface| adapter.is_surface_supported(surface)) {
+                    log_adapter(&adapter);
+                    return Some(adapter);
+                }
+                warn!(
+                    "The adapter {} picked by {ADAPTER_VAR} can't present to the surface",
+                    infos[index].name
+                );
+            }
+            None => warn!(
+                "{ADAPTER_VAR}={selector:?} matches none of the adapters: {:?}",
+                infos.iter().map(|info| &info.name).collect::<Vec<_>>()
+            ),
+        }
+    }
+
+    let adapter = instance
+        .request_adapter(&RequestAdapterOptions {
+            power_preference: PowerPreference::default(),
+            force_fallback_adapter: false,
+            compatible_surface: surface,
+        })
+        .await;
+    match &adapter {
+        Some(adapter) => log_adapter(adapter),
+        None => warn!("Found no suitable adapter"),
+    }
+    adapter
+}
+
+fn log_adapter(adapter: &Adapter) {
+    let info = adapter.get_info();
+    info!(
+        name = %info.name,
+        backend = ?info.backend,
+        device_type = ?info.device_type,
+        driver = %info.driver,
+        driver_info = %info.driver_info,
+        "Selected adapter"
+    );
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, RwLock,
+    },
+    thread,
+};
+
+#[cfg(feature = "embed")]
+use std::marker::PhantomData;
+
+#[cfg(feature = "embed")]
+use rust_embed::RustEmbed;
+
+// Provides the bytes of named assets such as sprite images, fonts and the
+// shader module. Closures taking the asset name implement this as well, which
+// allows loading assets from anywhere, for example over the network.
+pub trait AssetSource: Send + Sync {
+    fn load(&self, name: &str) -> Option<Vec<u8>>;
+}
+
+impl<F: Fn(&str) -> Option<Vec<u8>> + Send + Sync> AssetSource for F {
+    fn load(&self, name: &str) -> Option<Vec<u8>> {
+        self(name)
+    }
+}
+
+// Assets embedded into the binary with rust-embed
+#[cfg(feature = "embed")]
+pub struct EmbeddedAssets<A: RustEmbed> {
+    _assets: PhantomData<fn() -> A>,
+}
+
+#[cfg(feature = "embed")]
+impl<A: RustEmbed> EmbeddedAssets<A> {
+    pub fn new() -> Self {
+        Self {
+            _assets: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "embed")]
+impl<A: RustEmbed> Default for EmbeddedAssets<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "embed")]
+impl<A: RustEmbed> AssetSource for EmbeddedAssets<A> {
+    fn load(&self, name: &str) -> Option<Vec<u8>> {
+        A::get(name).map(|file| file.data.into_owned())
+    }
+}
+
+// Assets read from files relative to a root directory. Files are read every
+// time they are loaded, so changes on disk are picked up by the next load.
+#[derive(Debug, Clone)]
+pub struct DirectoryAssets {
+    root: PathBuf,
+}
+
+impl DirectoryAssets {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl AssetSource for DirectoryAssets {
+    fn load(&self, name: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.root.join(name)).ok()
+    }
+}
+
+// Assets kept in memory, keyed by name
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAssets {
+    assets: HashMap<String, Arc<[u8]>>,
+}
+
+impl MemoryAssets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_asset(&mut self, name: &str, bytes: impl Into<Arc<[u8]>>) {
+        self.assets.insert(name.to_string(), bytes.into());
+    }
+
+    pub fn with_asset(mut self, name: &str, bytes: impl Into<Arc<[u8]>>) -> Self {
+        self.add_asset(name, bytes);
+        self
+    }
+}
+
+impl AssetSource for MemoryAssets {
+    fn load(&self, name: &str) -> Option<Vec<u8>> {
+        self.assets.get(name).map(|bytes| bytes.to_vec())
+    }
+}
+
+// The asset source registered with the renderer, shared with the drawables
+// which load assets. The source can be swapped out after the drawables have
+// been created.
+#[derive(Default)]
+pub(crate) struct SharedAssets {
+    source: RwLock<Option<Box<dyn AssetSource>>>,
+}
+
+impl SharedAssets {
+    pub fn set(&self, source: impl AssetSource + 'static) {
+        *self.source.write().unwrap() = Some(Box::new(source));
+    }
+
+    pub fn load(&self, name: &str) -> Option<Vec<u8>> {
+        self.source.read().unwrap().as_ref()?.load(name)
+    }
+
+    // Removes the current source, for example to layer another one over it
+    pub fn take(&self) -> Option<Box<dyn AssetSource>> {
+        self.source.write().unwrap().take()
+    }
+}
+
+// Loads and decodes assets from the shared source on a worker thread, so that
+// large images and fonts don't block the frame. The thread exits once the
+// loader is dropped.
+pub(crate) struct AssetLoader<T> {
+    requests: Sender<String>,
+    // The asset is None if it couldn't be loaded
+    results: Receiver<(String, Option<T>)>,
+    pending: HashSet<String>,
+}
+
+impl<T: Send + 'static> AssetLoader<T> {
+    pub fn new(assets: Arc<SharedAssets>, load: fn(&SharedAssets, &str) -> Option<T>) -> Self {
+        let (requests, worker_requests) = channel::<String>();
+        let (worker_results, results) = channel();
+
+        thread::Builder::new()
+            .name("vide asset loader".to_string())
+            .spawn(move || {
+                for name in worker_requests {
+                    let asset = load(&assets, &name);
+                    if worker_results.send((name, asset)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Could not start the asset loader thread");
+
+        Self {
+            requests,
+            results,
+            pending: HashSet::new(),
+        }
+    }
+
+    // Queues the asset unless it's already queued
+    pub fn request(&mut self, name: &str) {
+        if self.pending.insert(name.to_string()) {
+            self.requests
+                .send(name.to_string())
+                .expect("The asset loader thread stopped");
+        }
+    }
+
+    // Number of queued assets which haven't been collected with finished yet
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Assets loaded since the previous call
+    pub fn finished(&mut self) -> Vec<(String, Option<T>)> {
+        let finished: Vec<_> = self.results.try_iter().collect();
+        for (name, _) in finished.iter() {
+            self.pending.remove(name);
+        }
+        finished
+    }
+}
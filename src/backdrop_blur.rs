@@ -0,0 +1,193 @@
+use shader::{InstancedQuad, ShaderConstants};
+use wgpu::*;
+
+use crate::{
+    lod::{scale_background_blur, LodLevel},
+    memory::MemoryReport,
+    renderer::{rects_overlap, visible_content_rect, Drawable},
+    scene::Layer,
+    uploader::Uploader,
+    PipelineInterface, Renderer,
+};
+
+// Blurs the layer has room for
+const MAX_BACKDROP_BLURS: u64 = 1000;
+
+// Draws the backdrop blurs of each layer with the quad shader. Every drawable
+// gets its own pass with the content drawn so far copied into the surface
+// texture first, so registering this after the drawables of the content it
+// blurs is all that's needed for the blurs to see it.
+pub struct BackdropBlurState {
+    buffer: Buffer,
+    bind_group: BindGroup,
+    render_pipeline: RenderPipeline,
+    // Scale of the blur kernels for the level of detail
+    blur_scale: f32,
+}
+
+impl BackdropBlurState {
+    pub fn set_lod_level(&mut self, level: LodLevel) {
+        self.blur_scale = level.blur_scale();
+    }
+}
+
+impl Drawable for BackdropBlurState {
+    fn new(renderer: &Renderer) -> Self {
+        let Renderer {
+            device,
+            shader,
+            format,
+            universal_bind_group_layout,
+            image_atlas,
+            ..
+        } = renderer;
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Backdrop blur buffer"),
+            size: std::mem::size_of::<InstancedQuad>() as u64 * MAX_BACKDROP_BLURS,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Same layout as the quads, which sample patterns from the image
+        // atlas
+        let layout_entries = [
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Backdrop blur bind group layout"),
+            entries: &layout_entries,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Backdrop blur bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(image_atlas.lock().unwrap().view()),
+                },
+            ],
+        });
+
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &["quad::vertex", "quad::fragment"],
+            bind_group_layouts: &[&layout_entries, &renderer.universal_layout_entries()],
+            vertex_buffers: &[],
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Backdrop Blur Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, universal_bind_group_layout],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::all(),
+                range: 0..std::mem::size_of::<ShaderConstants>() as u32,
+            }],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Backdrop Blur Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "quad::vertex",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "quad::fragment",
+                targets: &[Some(ColorTargetState {
+                    format: *format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: renderer.sample_count(),
+                ..Default::default()
+            },
+            multiview: None,
+        });
+
+        Self {
+            buffer,
+            bind_group,
+            render_pipeline,
+            blur_scale: 1.0,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "backdrop_blur"
+    }
+
+    fn draw<'b, 'a: 'b>(
+        &'a mut self,
+        uploader: &mut Uploader,
+        render_pass: &mut RenderPass<'b>,
+        constants: ShaderConstants,
+        universal_bind_group: &'a BindGroup,
+        layer: &Layer,
+    ) {
+        let visible_rect = visible_content_rect(&constants, layer);
+        let blurs: Vec<_> = layer
+            .backdrop_blurs
+            .iter()
+            .filter(|blur| rects_overlap(blur.bounds(), visible_rect))
+            .take(MAX_BACKDROP_BLURS as usize)
+            .map(|blur| {
+                let mut instance = blur.to_instanced();
+                scale_background_blur(&mut instance, self.blur_scale);
+                instance
+            })
+            .collect();
+
+        if blurs.is_empty() {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_push_constants(ShaderStages::all(), 0, bytemuck::cast_slice(&[constants]));
+
+        uploader.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&blurs[..]));
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, universal_bind_group, &[]);
+        render_pass.draw(0..6, 0..blurs.len() as u32);
+    }
+
+    fn memory_usage(&self, report: &mut MemoryReport) {
+        report.buffers.add_buffer(&self.buffer);
+    }
+}
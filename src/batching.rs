@@ -0,0 +1,171 @@
+use std::borrow::Cow;
+
+use glam::{vec4, Vec4, Vec4Swizzles};
+
+use crate::{renderer::rects_overlap, scene::Layer};
+
+// Number of layers rendered by the last render and the number of batches they
+// were merged into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchingStats {
+    pub layers: usize,
+    pub batches: usize,
+}
+
+// Content bounds of a layer for each kind of primitive, in the order quads,
+// procedurals, texts, paths, sprites, carets, cursor trails, shadows,
+// checkerboards and shapes.
+// Shadows are drawn by the quad drawable, but before all the quads of the
+// layer, so they are a kind of their own.
+#[derive(Default)]
+struct KindBounds([Option<Vec4>; 10]);
+
+impl KindBounds {
+    fn new(layer: &Layer, margin: f32, include_background: bool) -> Self {
+        let mut bounds = Self::default();
+        if include_background
+            && (layer.background_color.is_some() || layer.background_blur_radius != 0.0)
+        {
+            // Without a clip the background covers everything
+            let background = layer
+                .clip
+                .map(|clip| clip + vec4(layer.scroll_offset.x, layer.scroll_offset.y, 0., 0.))
+                .unwrap_or(vec4(f32::MIN / 2.0, f32::MIN / 2.0, f32::MAX, f32::MAX));
+            bounds.add(0, background, margin);
+        }
+        for quad in layer.quads.iter() {
+            bounds.add(0, quad.bounds(), margin);
+        }
+        for procedural in layer.procedurals.iter() {
+            bounds.add(1, procedural.bounds(), margin);
+        }
+        for text in layer.texts.iter() {
+            bounds.add(2, text.bounds(), margin);
+        }
+        for path in layer.paths.iter() {
+            bounds.add(3, path.bounds(), margin);
+        }
+        for sprite in layer.sprites.iter() {
+            bounds.add(4, sprite.bounds(), margin);
+        }
+        for caret in layer.carets.iter() {
+            bounds.add(5, caret.bounds(), margin);
+        }
+        for trail in layer.cursor_trails.iter() {
+            bounds.add(6, trail.bounds(), margin);
+        }
+        for shadow in layer.shadows.iter() {
+            bounds.add(7, shadow.bounds(), margin);
+        }
+        for checkerboard in layer.checkerboards.iter() {
+            bounds.add(8, checkerboard.bounds(), margin);
+        }
+        for shape in layer.shapes.iter() {
+            bounds.add(9, shape.bounds(), margin);
+        }
+        bounds
+    }
+
+    fn add(&mut self, kind: usize, rect: Vec4, margin: f32) {
+        let rect = rect + vec4(-margin, -margin, margin * 2.0, margin * 2.0);
+        self.0[kind] = Some(match self.0[kind] {
+            Some(bounds) => union_rects(bounds, rect),
+            None => rect,
+        });
+    }
+
+    fn extend(&mut self, other: &KindBounds) {
+        for (kind, rect) in other.0.iter().enumerate() {
+            if let Some(rect) = rect {
+                self.add(kind, *rect, 0.0);
+            }
+        }
+    }
+
+    // Whether primitives of one kind in self overlap primitives of a
+    // different kind in other. Primitives of the same kind keep their order
+    // when merged, but different kinds are drawn by different drawables.
+    fn overlaps_other_kinds(&self, other: &KindBounds) -> bool {
+        self.0.iter().enumerate().any(|(kind, rect)| {
+            other.0.iter().enumerate().any(|(other_kind, other_rect)| {
+                kind != other_kind
+                    && matches!((rect, other_rect), (Some(a), Some(b)) if rects_overlap(*a, *b))
+            })
+        })
+    }
+}
+
+// Merges consecutive layers which can be drawn together without changing the
+// result into single layers, so that each drawable issues one draw call for
+// all of them. A layer joins the previous batch when it has the same clip,
+// scroll offset and font, has no background or background blur, neither has
+// backdrop blurs or dimming, and none of
+// its primitives overlap primitives of a different kind in the batch. The
+// margin grows the primitive bounds to account for antialiasing.
+//
+// When backgrounds_first is set the backgrounds are drawn before any other
+// primitive of the layer, so they don't prevent merging.
+pub(crate) fn batch_layers<'a>(
+    layers: impl IntoIterator<Item = &'a Layer>,
+    margin: f32,
+    backgrounds_first: bool,
+) -> (Vec<Cow<'a, Layer>>, BatchingStats) {
+    let mut stats = BatchingStats::default();
+    let mut batches: Vec<(Cow<'a, Layer>, KindBounds)> = Vec::new();
+    for layer in layers {
+        stats.layers += 1;
+        let bounds = KindBounds::new(layer, margin, !backgrounds_first);
+
+        if let Some((batch, batch_bounds)) = batches.last_mut() {
+            if can_merge(batch, layer) && !batch_bounds.overlaps_other_kinds(&bounds) {
+                let batch = batch.to_mut();
+                batch.shadows.extend(layer.shadows.iter().cloned());
+                batch.quads.extend(layer.quads.iter().cloned());
+                batch.texts.extend(layer.texts.iter().cloned());
+                batch.paths.extend(layer.paths.iter().cloned());
+                batch.sprites.extend(layer.sprites.iter().cloned());
+                batch.procedurals.extend(layer.procedurals.iter().cloned());
+                batch.shapes.extend(layer.shapes.iter().cloned());
+                batch.carets.extend(layer.carets.iter().cloned());
+                batch
+                    .cursor_trails
+                    .extend(layer.cursor_trails.iter().cloned());
+                batch
+                    .checkerboards
+                    .extend(layer.checkerboards.iter().cloned());
+                batch_bounds.extend(&bounds);
+                continue;
+            }
+        }
+
+        batches.push((Cow::Borrowed(layer), bounds));
+    }
+
+    stats.batches = batches.len();
+    (batches.into_iter().map(|(batch, _)| batch).collect(), stats)
+}
+
+fn can_merge(batch: &Layer, layer: &Layer) -> bool {
+    // Backgrounds and background blurs are drawn before the layer contents
+    // and blurs sample what was drawn before the drawable, which would miss
+    // the earlier layers of the batch. Backdrop blurs would cover the
+    // content of the later layers instead. Dimming applies to everything
+    // drawn within the clip of its layer, so it would also change the other
+    // layers of the batch.
+    batch.clip == layer.clip
+        && batch.scroll_offset == layer.scroll_offset
+        && batch.font_name == layer.font_name
+        && layer.background_color.is_none()
+        && layer.background_blur_radius == 0.0
+        && layer.quads.iter().all(|quad| !quad.has_background_blur())
+        && batch.backdrop_blurs.is_empty()
+        && layer.backdrop_blurs.is_empty()
+        && batch.dimming.is_none()
+        && layer.dimming.is_none()
+}
+
+pub(crate) fn union_rects(a: Vec4, b: Vec4) -> Vec4 {
+    let min = a.xy().min(b.xy());
+    let max = (a.xy() + a.zw()).max(b.xy() + b.zw());
+    vec4(min.x, min.y, max.x - min.x, max.y - min.y)
+}
@@ -0,0 +1,133 @@
+use glam::Vec4;
+use wgpu::*;
+
+use crate::{renderer::texture_sampler_layout_entries, PipelineInterface, Renderer};
+
+// Copies a texture onto another one of any size, stretching it to cover the
+// whole target
+pub(crate) struct Blitter {
+    // The offscreen texture and sampler part of the universal layout, with
+    // the source bound in place of the offscreen texture
+    bind_group_layout: BindGroupLayout,
+    render_pipeline: RenderPipeline,
+}
+
+impl Blitter {
+    pub fn new(renderer: &Renderer, format: TextureFormat) -> Self {
+        let Renderer { device, shader, .. } = renderer;
+        let layout_entries = texture_sampler_layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Blit bind group layout"),
+            entries: &layout_entries,
+        });
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &["blit::blit_vertex", "blit::blit_fragment"],
+            bind_group_layouts: &[&layout_entries],
+            vertex_buffers: &[],
+        });
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Blit Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "blit::blit_vertex",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "blit::blit_fragment",
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            bind_group_layout,
+            render_pipeline,
+        }
+    }
+
+    pub fn blit(&self, renderer: &Renderer, source: &Texture, target: &Texture) {
+        self.draw(renderer, source, target, LoadOp::Clear(Color::WHITE), None);
+    }
+
+    // Stretches the source over the rect (top left and size) of the target,
+    // keeping the rest of the target
+    pub fn blit_region(&self, renderer: &Renderer, source: &Texture, target: &Texture, rect: Vec4) {
+        self.draw(renderer, source, target, LoadOp::Load, Some(rect));
+    }
+
+    fn draw(
+        &self,
+        renderer: &Renderer,
+        source: &Texture,
+        target: &Texture,
+        load: LoadOp<Color>,
+        rect: Option<Vec4>,
+    ) {
+        let source_view = source.create_view(&Default::default());
+        let bind_group = renderer.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Blit bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&source_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&renderer.sampler),
+                },
+            ],
+        });
+        let target_view = target.create_view(&Default::default());
+
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Blit Encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Blit Render Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &target_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            if let Some(rect) = rect {
+                render_pass.set_viewport(rect.x, rect.y, rect.z, rect.w, 0., 1.);
+                render_pass.set_scissor_rect(
+                    rect.x as u32,
+                    rect.y as u32,
+                    rect.z as u32,
+                    rect.w as u32,
+                );
+            }
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
@@ -0,0 +1,109 @@
+use wgpu::{Adapter, Backends, DownlevelCapabilities, DownlevelFlags, Features, Limits};
+
+use crate::{indirect::INDIRECT_FEATURES, sprite::TEXTURE_ARRAY_FEATURES};
+
+// Backends the renderers pick an adapter from. Vulkan adapters are picked
+// over GL ones of the same kind, which serve GLES3 devices without Vulkan.
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+pub(crate) const BACKENDS: Backends = Backends::VULKAN.union(Backends::GL);
+
+// Metal on Apple platforms, where Vulkan is only available through MoltenVK
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub(crate) const BACKENDS: Backends = Backends::METAL;
+
+// Features the renderer can't work without. Push constants are emulated with
+// a uniform buffer by the GL backend, so GLES devices have them too.
+const REQUIRED_FEATURES: Features = Features::PUSH_CONSTANTS.union(Features::CLEAR_TEXTURE);
+
+// Features which are requested when the adapter supports them
+const OPTIONAL_FEATURES: Features = Features::SPIRV_SHADER_PASSTHROUGH
+    .union(Features::VERTEX_WRITABLE_STORAGE)
+    .union(Features::TIMESTAMP_QUERY)
+    .union(INDIRECT_FEATURES)
+    .union(TEXTURE_ARRAY_FEATURES);
+
+// What the adapter supports, detected when the renderer is created to choose
+// the code paths. Downlevel adapters, such as GLES3 and old Android devices,
+// get the downlevel limits with the texture size of the adapter, draw the
+// sprites of each atlas page separately instead of through texture arrays,
+// and cull the quads on the cpu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    // Features requested from the device
+    pub features: Features,
+    // The adapter doesn't meet the WebGPU baseline
+    pub downlevel: bool,
+    // Compute passes and indirect draws for the gpu culling
+    pub compute_shaders: bool,
+    pub indirect_execution: bool,
+    // Sprites on every atlas page are drawn with one draw call
+    pub texture_arrays: bool,
+    // Gpu time of frames is measured when tracking latency
+    pub timestamp_queries: bool,
+    pub max_texture_size: u32,
+    // What the renderer requires but the adapter lacks
+    pub missing: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn new(adapter: &Adapter) -> Self {
+        Self::detect(
+            adapter.features(),
+            &adapter.get_downlevel_capabilities(),
+            &adapter.limits(),
+        )
+    }
+
+    pub(crate) fn detect(
+        features: Features,
+        downlevel: &DownlevelCapabilities,
+        limits: &Limits,
+    ) -> Self {
+        let mut missing = Vec::new();
+        if !features.contains(Features::PUSH_CONSTANTS) {
+            missing.push("push constants");
+        }
+        if !features.contains(Features::CLEAR_TEXTURE) {
+            missing.push("texture clearing");
+        }
+        // The instances of every primitive are read from storage buffers
+        if !downlevel.flags.contains(DownlevelFlags::VERTEX_STORAGE) {
+            missing.push("vertex shader storage buffers");
+        }
+
+        let features = REQUIRED_FEATURES | (features & OPTIONAL_FEATURES);
+        Self {
+            downlevel: !downlevel.is_webgpu_compliant(),
+            compute_shaders: downlevel.flags.contains(DownlevelFlags::COMPUTE_SHADERS),
+            indirect_execution: downlevel.flags.contains(DownlevelFlags::INDIRECT_EXECUTION),
+            texture_arrays: features.contains(TEXTURE_ARRAY_FEATURES),
+            timestamp_queries: features.contains(Features::TIMESTAMP_QUERY),
+            max_texture_size: limits.max_texture_dimension_2d,
+            features,
+            missing,
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    // The quads can be culled with compute passes feeding an indirect draw
+    pub fn supports_gpu_culling(&self) -> bool {
+        self.compute_shaders && self.indirect_execution
+    }
+
+    // Limits requested from the device, within those of the adapter
+    pub(crate) fn required_limits(&self, adapter_limits: &Limits) -> Limits {
+        let base = if self.downlevel {
+            Limits::downlevel_defaults()
+        } else {
+            Limits::default()
+        };
+        Limits {
+            max_push_constant_size: 256,
+            max_texture_dimension_2d: self.max_texture_size,
+            ..base.using_resolution(adapter_limits.clone())
+        }
+    }
+}
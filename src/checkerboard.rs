@@ -0,0 +1,158 @@
+use shader::{InstancedCheckerboard, ShaderConstants};
+use wgpu::*;
+
+use crate::{
+    memory::MemoryReport,
+    renderer::{rects_overlap, visible_content_rect, Drawable},
+    scene::Layer,
+    uploader::Uploader,
+    PipelineInterface, Renderer,
+};
+
+// Draws the checkerboards of each layer. Not one of the builtin drawables, so
+// it has to be added to the renderer. Drawables draw each layer in the order
+// they were added, so adding it before the builtin drawables puts the
+// checkerboards beneath the rest of the layer, although a layer background
+// still covers them.
+pub struct CheckerboardState {
+    buffer: Buffer,
+    bind_group: BindGroup,
+    render_pipeline: RenderPipeline,
+}
+
+impl Drawable for CheckerboardState {
+    fn new(renderer: &Renderer) -> Self {
+        let Renderer {
+            device,
+            shader,
+            format,
+            universal_bind_group_layout,
+            ..
+        } = renderer;
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Checkerboard buffer"),
+            size: std::mem::size_of::<InstancedCheckerboard>() as u64 * 1000,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout_entries = [BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Checkerboard bind group layout"),
+            entries: &layout_entries,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Checkerboard bind group"),
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &[
+                "checkerboard::checkerboard_vertex",
+                "checkerboard::checkerboard_fragment",
+            ],
+            bind_group_layouts: &[&layout_entries, &renderer.universal_layout_entries()],
+            vertex_buffers: &[],
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Checkerboard Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, universal_bind_group_layout],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::all(),
+                range: 0..std::mem::size_of::<ShaderConstants>() as u32,
+            }],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Checkerboard Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "checkerboard::checkerboard_vertex",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "checkerboard::checkerboard_fragment",
+                targets: &[Some(ColorTargetState {
+                    format: *format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: renderer.sample_count(),
+                ..Default::default()
+            },
+            multiview: None,
+        });
+
+        Self {
+            buffer,
+            bind_group,
+            render_pipeline,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "checkerboard"
+    }
+
+    fn draw<'b, 'a: 'b>(
+        &'a mut self,
+        uploader: &mut Uploader,
+        render_pass: &mut RenderPass<'b>,
+        constants: ShaderConstants,
+        universal_bind_group: &'a BindGroup,
+        layer: &Layer,
+    ) {
+        let visible_rect = visible_content_rect(&constants, layer);
+        let checkerboards: Vec<_> = layer
+            .checkerboards
+            .iter()
+            .filter(|checkerboard| rects_overlap(checkerboard.bounds(), visible_rect))
+            .map(|checkerboard| checkerboard.to_instanced())
+            .collect();
+
+        if checkerboards.is_empty() {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_push_constants(ShaderStages::all(), 0, bytemuck::cast_slice(&[constants]));
+
+        uploader.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&checkerboards[..]));
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, universal_bind_group, &[]);
+        render_pass.draw(0..6, 0..checkerboards.len() as u32);
+    }
+
+    fn memory_usage(&self, report: &mut MemoryReport) {
+        report.buffers.add_buffer(&self.buffer);
+    }
+}
@@ -0,0 +1,415 @@
+use std::fmt;
+
+use glam::{vec3, UVec3, Vec3};
+use shader::{lut_coordinate, ColorGrading};
+use wgpu::{util::DeviceExt, *};
+
+use crate::{PipelineInterface, Renderer};
+
+const ENTRY_POINTS: [&str; 2] = ["blit::blit_vertex", "color_grading::color_grading_fragment"];
+// Largest number of entries along a side of a table
+const MAX_LUT_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColorLutError {
+    Syntax { line: usize, message: String },
+    // The cube file has no LUT_3D_SIZE
+    MissingSize,
+    // Tables need between 2 and 256 entries along each side
+    InvalidSize(u32),
+    EntryCount { expected: usize, found: usize },
+}
+
+impl fmt::Display for ColorLutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorLutError::Syntax { line, message } => write!(f, "{line}: {message}"),
+            ColorLutError::MissingSize => write!(f, "The table has no LUT_3D_SIZE"),
+            ColorLutError::InvalidSize(size) => {
+                write!(
+                    f,
+                    "A table size of {size} is not between 2 and {MAX_LUT_SIZE}"
+                )
+            }
+            ColorLutError::EntryCount { expected, found } => {
+                write!(f, "The table has {found} entries instead of {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ColorLutError {}
+
+// A 3D color lookup table mapping every color of a frame to another, for
+// theming, night mode or colorblind assistance transforms. The colors are
+// looked up sRGB encoded, the way tables made by color grading tools expect
+// them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLut {
+    size: u32,
+    domain_min: Vec3,
+    domain_max: Vec3,
+    // Output colors with red changing fastest, then green and then blue
+    entries: Vec<Vec3>,
+}
+
+impl ColorLut {
+    // The entries are size cubed output colors, in the order of cube files
+    pub fn new(size: u32, entries: Vec<Vec3>) -> Result<Self, ColorLutError> {
+        if !(2..=MAX_LUT_SIZE).contains(&size) {
+            return Err(ColorLutError::InvalidSize(size));
+        }
+        let expected = size.pow(3) as usize;
+        if entries.len() != expected {
+            return Err(ColorLutError::EntryCount {
+                expected,
+                found: entries.len(),
+            });
+        }
+        Ok(Self {
+            size,
+            domain_min: Vec3::ZERO,
+            domain_max: Vec3::ONE,
+            entries,
+        })
+    }
+
+    // A table of the size sampling the transform, such as a matrix
+    // simulating or correcting color blindness
+    pub fn from_fn(size: u32, transform: impl Fn(Vec3) -> Vec3) -> Self {
+        let size = size.clamp(2, MAX_LUT_SIZE);
+        let step = 1.0 / (size - 1) as f32;
+        let entries = (0..size.pow(3))
+            .map(|index| {
+                let (red, green, blue) = (index % size, index / size % size, index / size / size);
+                transform(vec3(red as f32, green as f32, blue as f32) * step)
+            })
+            .collect();
+        Self {
+            size,
+            domain_min: Vec3::ZERO,
+            domain_max: Vec3::ONE,
+            entries,
+        }
+    }
+
+    pub fn identity(size: u32) -> Self {
+        Self::from_fn(size, |color| color)
+    }
+
+    // Parses a table in the .cube format of color grading tools. Only 3D
+    // tables are supported.
+    pub fn parse_cube(source: &str) -> Result<Self, ColorLutError> {
+        let mut size = None;
+        let mut domain_min = Vec3::ZERO;
+        let mut domain_max = Vec3::ONE;
+        let mut entries = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let syntax_error = |message: &str| ColorLutError::Syntax {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let mut words = line.split_whitespace();
+            let Some(keyword) = words.next().filter(|word| !word.starts_with('#')) else {
+                continue;
+            };
+            let rest: Vec<_> = words.collect();
+            let color = |values: &[&str]| -> Result<Vec3, ColorLutError> {
+                let values = values
+                    .iter()
+                    .map(|value| value.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| syntax_error("Expected a number"))?;
+                match values[..] {
+                    [red, green, blue] => Ok(vec3(red, green, blue)),
+                    _ => Err(syntax_error("Expected three numbers")),
+                }
+            };
+            match keyword {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    let value = rest
+                        .first()
+                        .and_then(|value| value.parse().ok())
+                        .ok_or_else(|| syntax_error("Expected the size of the table"))?;
+                    size = Some(value);
+                }
+                "LUT_1D_SIZE" => return Err(syntax_error("1D tables are not supported")),
+                "DOMAIN_MIN" => domain_min = color(&rest)?,
+                "DOMAIN_MAX" => domain_max = color(&rest)?,
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    return Err(syntax_error(&format!("Unknown keyword {keyword}")))
+                }
+                _ => entries.push(color(&[&[keyword], &rest[..]].concat())?),
+            }
+        }
+
+        let size = size.ok_or(ColorLutError::MissingSize)?;
+        Ok(Self {
+            domain_min,
+            domain_max,
+            ..Self::new(size, entries)?
+        })
+    }
+
+    pub fn with_domain(mut self, min: Vec3, max: Vec3) -> Self {
+        self.domain_min = min;
+        self.domain_max = max;
+        self
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    // The graded color, interpolated between the entries like on the gpu
+    pub fn sample(&self, color: Vec3) -> Vec3 {
+        let position = lut_coordinate(color, &self.grading(1.0, false)) * self.size as f32 - 0.5;
+        let last = self.size - 1;
+        let low = position.floor().as_uvec3().min(UVec3::splat(last));
+        let high = (low + 1).min(UVec3::splat(last));
+        let t = position - position.floor();
+        let entry = |red: u32, green: u32, blue: u32| {
+            self.entries[(red + (green + blue * self.size) * self.size) as usize]
+        };
+        let along_red =
+            |green, blue| entry(low.x, green, blue).lerp(entry(high.x, green, blue), t.x);
+        let along_green = |blue| along_red(low.y, blue).lerp(along_red(high.y, blue), t.y);
+        along_green(low.z).lerp(along_green(high.z), t.z)
+    }
+
+    fn grading(&self, strength: f32, srgb: bool) -> ColorGrading {
+        ColorGrading {
+            domain_min: self.domain_min.extend(0.0),
+            domain_max: self.domain_max.extend(1.0),
+            size: self.size as f32,
+            strength,
+            srgb: srgb as u32,
+            _padding: 0,
+        }
+    }
+
+    fn to_rgba8(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .flat_map(|color| {
+                let color = (color.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).round();
+                [color.x as u8, color.y as u8, color.z as u8, 255]
+            })
+            .collect()
+    }
+}
+
+// Draws the frame through the table of the renderer after the scenes, by
+// copying the frame to the offscreen texture and drawing it back graded
+pub(crate) struct ColorGradingPass {
+    lut: ColorLut,
+    texture: Texture,
+    grading: Buffer,
+    // Linear so that the lookup interpolates between the entries
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    render_pipeline: RenderPipeline,
+}
+
+impl ColorGradingPass {
+    pub fn new(renderer: &Renderer, lut: ColorLut) -> Self {
+        let Renderer {
+            device,
+            queue,
+            shader,
+            format,
+            ..
+        } = renderer;
+
+        let size = Extent3d {
+            width: lut.size,
+            height: lut.size,
+            depth_or_array_layers: lut.size,
+        };
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("Color lut texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D3,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            util::TextureDataOrder::LayerMajor,
+            &lut.to_rgba8(),
+        );
+
+        let grading = device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some("Color grading buffer"),
+            contents: bytemuck::cast_slice(&[lut.grading(1.0, format.is_srgb())]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Color grading sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let layout_entries = layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Color grading bind group layout"),
+            entries: &layout_entries,
+        });
+
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &ENTRY_POINTS,
+            bind_group_layouts: &[&layout_entries],
+            vertex_buffers: &[],
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Color Grading Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Color Grading Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: ENTRY_POINTS[0],
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: ENTRY_POINTS[1],
+                targets: &[Some(ColorTargetState {
+                    format: *format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            lut,
+            texture,
+            grading,
+            sampler,
+            bind_group_layout,
+            render_pipeline,
+        }
+    }
+
+    pub fn lut(&self) -> &ColorLut {
+        &self.lut
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn apply(&self, renderer: &Renderer, encoder: &mut CommandEncoder, frame: &Texture) {
+        let size = Extent3d {
+            width: renderer.width,
+            height: renderer.height,
+            depth_or_array_layers: 1,
+        };
+        encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            renderer.offscreen_texture.as_image_copy(),
+            size,
+        );
+        renderer.queue.write_buffer(
+            &self.grading,
+            0,
+            bytemuck::cast_slice(&[self
+                .lut
+                .grading(renderer.color_grading_strength, renderer.format.is_srgb())]),
+        );
+
+        let source_view = renderer.offscreen_texture.create_view(&Default::default());
+        let lut_view = self.texture.create_view(&Default::default());
+        let bind_group = renderer.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Color grading bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&source_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&lut_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: self.grading.as_entire_binding(),
+                },
+            ],
+        });
+
+        let frame_view = frame.create_view(&Default::default());
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Color Grading Render Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &frame_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_viewport(0., 0., size.width as f32, size.height as f32, 0., 1.);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn layout_entries() -> [BindGroupLayoutEntry; 4] {
+    let texture = |binding, view_dimension| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension,
+            multisampled: false,
+        },
+        count: None,
+    };
+    [
+        texture(0, TextureViewDimension::D2),
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        },
+        texture(2, TextureViewDimension::D3),
+        BindGroupLayoutEntry {
+            binding: 3,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ]
+}
@@ -0,0 +1,340 @@
+use glam::{vec2, Vec2, Vec4, Vec4Swizzles};
+use shader::ShaderConstants;
+
+use crate::{
+    renderer::surface_bounds_in_scene,
+    scene::{Layer, Quad, Text},
+};
+
+// Logical pixels between the labeled ticks of the rulers at the least
+const MIN_LABEL_SPACING: f32 = 64.0;
+// Unlabeled ticks between two labeled ones, plus one
+const MINOR_TICKS: i64 = 5;
+// Grid lines closer together than this in surface pixels are left out
+const MIN_GRID_SPACING: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuideAxis {
+    // A line across the scene at a y coordinate
+    Horizontal,
+    // A line down the scene at an x coordinate
+    Vertical,
+}
+
+// Line across the whole scene at a position in scene coordinates, for
+// aligning primitives to while designing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Guide {
+    pub axis: GuideAxis,
+    pub position: f32,
+}
+
+impl Guide {
+    pub fn horizontal(y: f32) -> Self {
+        Self {
+            axis: GuideAxis::Horizontal,
+            position: y,
+        }
+    }
+
+    pub fn vertical(x: f32) -> Self {
+        Self {
+            axis: GuideAxis::Vertical,
+            position: x,
+        }
+    }
+
+    // Scene units from the point to the line
+    pub fn distance(&self, point: Vec2) -> f32 {
+        match self.axis {
+            GuideAxis::Horizontal => (point.y - self.position).abs(),
+            GuideAxis::Vertical => (point.x - self.position).abs(),
+        }
+    }
+}
+
+// Grid, rulers and guides the renderer draws on top of every scene while the
+// overlay is visible, for building design tools. They are drawn as a layer of
+// quads and texts appended to the scene, with one surface pixel wide lines
+// and rulers along the top and left edges of the surface which stay the same
+// size at any camera zoom. The layout assumes a camera which isn't rotated.
+// Guides are dragged by finding the one under the pointer with guide_at and
+// moving it with move_guide.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesignOverlay {
+    pub visible: bool,
+    // Scene units between the grid lines. No grid when unset.
+    pub grid_spacing: Option<f32>,
+    pub grid_color: Vec4,
+    pub rulers: bool,
+    // Logical pixels the rulers reach into the surface
+    pub ruler_size: f32,
+    pub ruler_background: Vec4,
+    // Color of the ticks and their labels
+    pub ruler_color: Vec4,
+    pub guides: Vec<Guide>,
+    pub guide_color: Vec4,
+}
+
+impl Default for DesignOverlay {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            grid_spacing: None,
+            grid_color: Vec4::new(0.5, 0.5, 0.5, 0.3),
+            rulers: false,
+            ruler_size: 20.0,
+            ruler_background: Vec4::new(0.95, 0.95, 0.95, 1.0),
+            ruler_color: Vec4::new(0.2, 0.2, 0.2, 1.0),
+            guides: Vec::new(),
+            guide_color: Vec4::new(0.0, 0.6, 1.0, 1.0),
+        }
+    }
+}
+
+impl DesignOverlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_grid(mut self, spacing: f32, color: Vec4) -> Self {
+        self.grid_spacing = Some(spacing);
+        self.grid_color = color;
+        self
+    }
+
+    pub fn with_rulers(mut self) -> Self {
+        self.rulers = true;
+        self
+    }
+
+    pub fn with_ruler_colors(mut self, background: Vec4, color: Vec4) -> Self {
+        self.ruler_background = background;
+        self.ruler_color = color;
+        self
+    }
+
+    pub fn with_guide(mut self, guide: Guide) -> Self {
+        self.add_guide(guide);
+        self
+    }
+
+    pub fn with_guide_color(mut self, color: Vec4) -> Self {
+        self.guide_color = color;
+        self
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn add_guide(&mut self, guide: Guide) {
+        self.guides.push(guide);
+    }
+
+    // Index of the guide closest to the point in scene coordinates, if any is
+    // within the tolerance in scene units
+    pub fn guide_at(&self, point: Vec2, tolerance: f32) -> Option<usize> {
+        self.guides
+            .iter()
+            .enumerate()
+            .map(|(index, guide)| (index, guide.distance(point)))
+            .filter(|(_, distance)| *distance <= tolerance)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+
+    // Moves the guide through the point in scene coordinates
+    pub fn move_guide(&mut self, index: usize, point: Vec2) {
+        if let Some(guide) = self.guides.get_mut(index) {
+            guide.position = match guide.axis {
+                GuideAxis::Horizontal => point.y,
+                GuideAxis::Vertical => point.x,
+            };
+        }
+    }
+
+    // The overlay as a layer covering the part of the scene the constants
+    // show on the surface
+    pub(crate) fn layer(&self, constants: &ShaderConstants) -> Layer {
+        let visible = surface_bounds_in_scene(constants);
+        let (min, size) = (visible.xy(), visible.zw());
+        let max = min + size;
+        // A surface pixel in scene units
+        let pixel = 1.0 / constants.camera_zoom;
+        let mut layer = Layer::new().without_background();
+
+        if let Some(spacing) = self
+            .grid_spacing
+            .filter(|spacing| *spacing * constants.camera_zoom >= MIN_GRID_SPACING)
+        {
+            for (_, x) in steps(min.x, max.x, spacing) {
+                layer.add_quad(Quad::new(
+                    vec2(x - pixel / 2.0, min.y),
+                    vec2(pixel, size.y),
+                    self.grid_color,
+                ));
+            }
+            for (_, y) in steps(min.y, max.y, spacing) {
+                layer.add_quad(Quad::new(
+                    vec2(min.x, y - pixel / 2.0),
+                    vec2(size.x, pixel),
+                    self.grid_color,
+                ));
+            }
+        }
+
+        for guide in self.guides.iter() {
+            let (top_left, size) = match guide.axis {
+                GuideAxis::Horizontal => (
+                    vec2(min.x, guide.position - pixel / 2.0),
+                    vec2(size.x, pixel),
+                ),
+                GuideAxis::Vertical => (
+                    vec2(guide.position - pixel / 2.0, min.y),
+                    vec2(pixel, size.y),
+                ),
+            };
+            layer.add_quad(Quad::new(top_left, size, self.guide_color));
+        }
+
+        if self.rulers {
+            self.add_rulers(&mut layer, constants, min, max, pixel);
+        }
+        layer
+    }
+
+    fn add_rulers(
+        &self,
+        layer: &mut Layer,
+        constants: &ShaderConstants,
+        min: Vec2,
+        max: Vec2,
+        pixel: f32,
+    ) {
+        let logical_pixel = pixel * constants.scale_factor;
+        let ruler = self.ruler_size * logical_pixel;
+        let font_size = ruler * 0.5;
+        // The top of the surface is at the largest y when the y axis points
+        // up. Returns the scene y of a distance down the surface from it.
+        let down = |distance: f32| {
+            if constants.y_direction > 0.0 {
+                min.y + distance
+            } else {
+                max.y - distance
+            }
+        };
+        // Smallest y and the height of a band down the surface
+        let band = |from: f32, to: f32| (down(from).min(down(to)), to - from);
+
+        let label_step = ruler_step(
+            constants.camera_zoom,
+            MIN_LABEL_SPACING * constants.scale_factor,
+        );
+        let tick_step = label_step / MINOR_TICKS as f32;
+        let tick_length = |index: i64| {
+            if index.rem_euclid(MINOR_TICKS) == 0 {
+                ruler * 0.5
+            } else {
+                ruler * 0.25
+            }
+        };
+
+        // Along the top edge
+        let (y, height) = band(0.0, ruler);
+        layer.add_quad(Quad::new(
+            vec2(min.x, y),
+            vec2(max.x - min.x, height),
+            self.ruler_background,
+        ));
+        for (index, x) in steps(min.x + ruler, max.x, tick_step) {
+            let length = tick_length(index);
+            let (y, height) = band(ruler - length, ruler);
+            layer.add_quad(Quad::new(
+                vec2(x - pixel / 2.0, y),
+                vec2(pixel, height),
+                self.ruler_color,
+            ));
+            if index.rem_euclid(MINOR_TICKS) == 0 {
+                layer.add_text(Text::new(
+                    ruler_label(x, label_step),
+                    vec2(x + logical_pixel * 2.0, down(font_size)),
+                    font_size,
+                    self.ruler_color,
+                ));
+            }
+        }
+
+        // Along the left edge, with the labels below their ticks
+        let (top, bottom) = (down(ruler), down(max.y - min.y));
+        let (from, to) = (top.min(bottom), top.max(bottom));
+        layer.add_quad(Quad::new(
+            vec2(min.x, min.y),
+            vec2(ruler, max.y - min.y),
+            self.ruler_background,
+        ));
+        for (index, y) in steps(from, to, tick_step) {
+            let length = tick_length(index);
+            layer.add_quad(Quad::new(
+                vec2(min.x + ruler - length, y - pixel / 2.0),
+                vec2(length, pixel),
+                self.ruler_color,
+            ));
+            if index.rem_euclid(MINOR_TICKS) == 0 {
+                let baseline = font_size + logical_pixel * 2.0;
+                layer.add_text(Text::new(
+                    ruler_label(y, label_step),
+                    vec2(
+                        min.x + logical_pixel * 2.0,
+                        y + baseline * constants.y_direction,
+                    ),
+                    font_size,
+                    self.ruler_color,
+                ));
+            }
+        }
+
+        // The corner where the rulers meet covers the labels running into it
+        let (y, height) = band(0.0, ruler);
+        layer.add_quad(Quad::new(
+            vec2(min.x, y),
+            vec2(ruler, height),
+            self.ruler_background,
+        ));
+    }
+}
+
+// Scene units between the labeled ticks of the rulers. The smallest step of
+// 1, 2 or 5 times a power of ten which keeps the ticks at least the spacing
+// apart in surface pixels at the zoom.
+pub fn ruler_step(zoom: f32, min_spacing: f32) -> f32 {
+    let min_step = min_spacing / zoom;
+    let power = 10f32.powf(min_step.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * power)
+        .find(|step| *step >= min_step * 0.999)
+        .unwrap_or(power * 10.0)
+}
+
+// Multiples of the step between the start and the end along with their index
+fn steps(start: f32, end: f32, step: f32) -> impl Iterator<Item = (i64, f32)> {
+    let first = (start / step).ceil() as i64;
+    let last = (end / step).floor() as i64;
+    (first..=last).map(move |index| (index, index as f32 * step))
+}
+
+// The value with as many decimals as the step needs
+fn ruler_label(value: f32, step: f32) -> String {
+    let decimals = (-step.log10()).ceil().max(0.0) as usize;
+    let label = format!("{value:.decimals$}");
+    // Avoids labeling the origin as negative zero
+    match label.trim_start_matches('-').trim_matches(['0', '.']) {
+        "" => "0".to_string(),
+        _ => label,
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+
+use wgpu::{
+    Adapter, AdapterInfo, Backends, Features, Instance, Limits, TextureFormat, TextureUsages,
+};
+
+use crate::{
+    adapter::{adapter_override, backend_override, backends},
+    capabilities::Capabilities,
+    Renderer,
+};
+
+// Formats checked for rendering offscreen, which has no surface to report
+// its formats
+const OFFSCREEN_FORMATS: [TextureFormat; 6] = [
+    TextureFormat::Rgba8UnormSrgb,
+    TextureFormat::Bgra8UnormSrgb,
+    TextureFormat::Rgba8Unorm,
+    TextureFormat::Bgra8Unorm,
+    TextureFormat::Rgb10a2Unorm,
+    TextureFormat::Rgba16Float,
+];
+
+// The gpu a renderer runs on and how it was picked, for reporting gpu
+// specific issues. Printing it gives a readable summary.
+#[derive(Debug, Clone)]
+pub struct GpuDiagnostics {
+    pub adapter: AdapterInfo,
+    // Every adapter of the backends, in the order VIDE_ADAPTER indexes them
+    pub adapters: Vec<AdapterInfo>,
+    pub backends: Backends,
+    // Values of VIDE_BACKEND and VIDE_ADAPTER
+    pub backend_override: Option<String>,
+    pub adapter_override: Option<String>,
+    // Features and limits of the device, which are within those of the
+    // adapter
+    pub features: Features,
+    pub limits: Limits,
+    pub capabilities: Capabilities,
+    // Format the renderer draws in
+    pub format: TextureFormat,
+    // Formats of the surface, or the formats the adapter can render to
+    // offscreen
+    pub surface_formats: Vec<TextureFormat>,
+}
+
+impl GpuDiagnostics {
+    pub(crate) fn new(
+        instance: &Instance,
+        renderer: &Renderer,
+        surface_formats: Option<Vec<TextureFormat>>,
+    ) -> Self {
+        let backends = backends();
+        Self {
+            adapter: renderer.adapter.get_info(),
+            adapters: instance
+                .enumerate_adapters(backends)
+                .iter()
+                .map(Adapter::get_info)
+                .collect(),
+            backends,
+            backend_override: backend_override(),
+            adapter_override: adapter_override(),
+            features: renderer.device.features(),
+            limits: renderer.device.limits(),
+            capabilities: renderer.capabilities().clone(),
+            format: renderer.format,
+            surface_formats: surface_formats
+                .unwrap_or_else(|| offscreen_formats(&renderer.adapter)),
+        }
+    }
+}
+
+fn offscreen_formats(adapter: &Adapter) -> Vec<TextureFormat> {
+    OFFSCREEN_FORMATS
+        .into_iter()
+        .filter(|format| {
+            adapter
+                .get_texture_format_features(*format)
+                .allowed_usages
+                .contains(TextureUsages::RENDER_ATTACHMENT)
+        })
+        .collect()
+}
+
+impl fmt::Display for GpuDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let AdapterInfo {
+            name,
+            backend,
+            device_type,
+            driver,
+            driver_info,
+            ..
+        } = &self.adapter;
+        writeln!(f, "Adapter: {name} ({backend:?}, {device_type:?})")?;
+        writeln!(f, "Driver: {driver} {driver_info}")?;
+        writeln!(f, "Backends: {:?}", self.backends)?;
+        if let Some(backend_override) = &self.backend_override {
+            writeln!(f, "VIDE_BACKEND: {backend_override}")?;
+        }
+        if let Some(adapter_override) = &self.adapter_override {
+            writeln!(f, "VIDE_ADAPTER: {adapter_override}")?;
+        }
+        writeln!(f, "Available adapters:")?;
+        for (index, adapter) in self.adapters.iter().enumerate() {
+            writeln!(
+                f,
+                "  {index}: {} ({:?}, {:?})",
+                adapter.name, adapter.backend, adapter.device_type
+            )?;
+        }
+        writeln!(f, "Format: {:?}", self.format)?;
+        writeln!(f, "Surface formats: {:?}", self.surface_formats)?;
+        writeln!(f, "Features: {:?}", self.features)?;
+        if !self.capabilities.missing.is_empty() {
+            writeln!(f, "Missing: {}", self.capabilities.missing.join(", "))?;
+        }
+        writeln!(
+            f,
+            "Downlevel: {}, gpu culling: {}, texture arrays: {}, timestamp queries: {}",
+            self.capabilities.downlevel,
+            self.capabilities.supports_gpu_culling(),
+            self.capabilities.texture_arrays,
+            self.capabilities.timestamp_queries
+        )?;
+        write!(f, "Limits: {:?}", self.limits)
+    }
+}
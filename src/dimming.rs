@@ -0,0 +1,150 @@
+use shader::{InstancedDimming, ShaderConstants};
+use wgpu::*;
+
+use crate::{
+    memory::MemoryReport, renderer::Drawable, scene::Layer, uploader::Uploader, PipelineInterface,
+    Renderer,
+};
+
+const ENTRY_POINTS: [&str; 2] = ["blit::blit_vertex", "dimming::dimming_fragment"];
+
+// Replaces what has been drawn within the clip of a layer with its dimmed
+// colors, after the other drawables of the layer
+pub struct DimmingState {
+    buffer: Buffer,
+    bind_group: BindGroup,
+    render_pipeline: RenderPipeline,
+}
+
+impl Drawable for DimmingState {
+    fn new(renderer: &Renderer) -> Self {
+        let Renderer {
+            device,
+            shader,
+            format,
+            universal_bind_group_layout,
+            ..
+        } = renderer;
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Dimming buffer"),
+            size: std::mem::size_of::<InstancedDimming>() as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout_entries = [BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Dimming bind group layout"),
+            entries: &layout_entries,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Dimming bind group"),
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &ENTRY_POINTS,
+            bind_group_layouts: &[&layout_entries, &renderer.universal_layout_entries()],
+            vertex_buffers: &[],
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Dimming Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, universal_bind_group_layout],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::all(),
+                range: 0..std::mem::size_of::<ShaderConstants>() as u32,
+            }],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Dimming Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: ENTRY_POINTS[0],
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: ENTRY_POINTS[1],
+                // The dimmed colors replace the drawn ones
+                targets: &[Some(ColorTargetState {
+                    format: *format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: renderer.sample_count(),
+                ..Default::default()
+            },
+            multiview: None,
+        });
+
+        Self {
+            buffer,
+            bind_group,
+            render_pipeline,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "dimming"
+    }
+
+    fn draw<'b, 'a: 'b>(
+        &'a mut self,
+        uploader: &mut Uploader,
+        render_pass: &mut RenderPass<'b>,
+        constants: ShaderConstants,
+        universal_bind_group: &'a BindGroup,
+        layer: &Layer,
+    ) {
+        let Some(dimming) = layer.dimming.filter(|dimming| !dimming.is_noop()) else {
+            return;
+        };
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_push_constants(ShaderStages::all(), 0, bytemuck::cast_slice(&[constants]));
+
+        uploader.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[dimming.to_instanced()]),
+        );
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, universal_bind_group, &[]);
+        // A single triangle covering the viewport, cut to the clip of the
+        // layer by the scissor rect
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn memory_usage(&self, report: &mut MemoryReport) {
+        report.buffers.add_buffer(&self.buffer);
+    }
+}
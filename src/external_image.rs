@@ -0,0 +1,154 @@
+#[cfg(all(target_os = "linux", feature = "dmabuf"))]
+mod dmabuf;
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use wgpu::*;
+
+use crate::{blit::Blitter, image_atlas::ImageAtlas, Renderer};
+
+#[cfg(all(target_os = "linux", feature = "dmabuf"))]
+pub use dmabuf::DmaBuf;
+
+// Image memory shared by another api or process, such as a compositor or a
+// video decoder, which is drawn without copying it through the cpu. Only
+// DMA-BUFs on linux are imported by vide itself. IOSurfaces and D3D shared
+// handles aren't, the application imports them into a texture on the device
+// of the renderer and passes that instead.
+pub enum ExternalImage {
+    // A texture created on the device of the renderer, for example an
+    // IOSurface imported with the metal texture_from_raw or a D3D shared
+    // handle with the dx12 texture_from_raw of wgpu-hal, followed by
+    // Device::create_texture_from_hal
+    Texture(Texture),
+    #[cfg(all(target_os = "linux", feature = "dmabuf"))]
+    DmaBuf(DmaBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalImageError {
+    // The image can't be imported by the backend of the device
+    UnsupportedBackend,
+    // The device was created without the extensions needed for the import
+    MissingExtensions(Vec<String>),
+    UnsupportedFormat(TextureFormat),
+    // The import failed in the graphics api
+    Import(String),
+    // The image atlas has no room for the image
+    AtlasFull,
+}
+
+impl fmt::Display for ExternalImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExternalImageError::UnsupportedBackend => {
+                write!(f, "The backend can't import the external image")
+            }
+            ExternalImageError::MissingExtensions(extensions) => write!(
+                f,
+                "The device was created without the extensions {}",
+                extensions.join(", ")
+            ),
+            ExternalImageError::UnsupportedFormat(format) => {
+                write!(f, "External images can't have the format {format:?}")
+            }
+            ExternalImageError::Import(error) => {
+                write!(f, "Could not import the external image: {error}")
+            }
+            ExternalImageError::AtlasFull => {
+                write!(f, "The image atlas has no room for the external image")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExternalImageError {}
+
+impl ExternalImage {
+    // Turns the image into a texture on the device of the renderer
+    #[cfg_attr(
+        not(all(target_os = "linux", feature = "dmabuf")),
+        allow(unused_variables)
+    )]
+    pub fn import(self, renderer: &Renderer) -> Result<Texture, ExternalImageError> {
+        match self {
+            ExternalImage::Texture(texture) => Ok(texture),
+            #[cfg(all(target_os = "linux", feature = "dmabuf"))]
+            ExternalImage::DmaBuf(dmabuf) => dmabuf::import(&renderer.device, dmabuf),
+        }
+    }
+}
+
+// Requests the device of the renderer. With the dmabuf feature the extensions
+// for importing DMA-BUFs are enabled when the adapter supports them.
+pub(crate) async fn request_device(
+    adapter: &Adapter,
+    descriptor: &DeviceDescriptor<'_>,
+) -> Result<(Device, Queue), RequestDeviceError> {
+    #[cfg(all(target_os = "linux", feature = "dmabuf"))]
+    if let Some(device) = dmabuf::request_device(adapter, descriptor) {
+        return Ok(device);
+    }
+
+    adapter.request_device(descriptor, None).await
+}
+
+// An image in the image atlas showing an external image. Sprites and pattern
+// fills draw it by the name it was created with. The image is copied into the
+// atlas on the gpu, stretched to the size of the texture or scaled down to fit
+// the atlas, whenever update is called. Has to be created again after the renderer is recreated.
+pub struct ExternalTexture {
+    name: String,
+    texture: Texture,
+    blitter: Blitter,
+    image_atlas: Arc<Mutex<ImageAtlas>>,
+}
+
+impl ExternalTexture {
+    pub fn new(
+        renderer: &Renderer,
+        name: &str,
+        image: ExternalImage,
+    ) -> Result<Self, ExternalImageError> {
+        let texture = image.import(renderer)?;
+        let format = renderer.image_atlas.lock().unwrap().texture().format();
+        let external_texture = Self {
+            name: name.to_string(),
+            texture,
+            blitter: Blitter::new(renderer, format),
+            image_atlas: renderer.image_atlas.clone(),
+        };
+        external_texture.update(renderer)?;
+        Ok(external_texture)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    // Copies the current contents of the image into the atlas. Call after the
+    // producer has finished writing a new frame into the shared memory.
+    pub fn update(&self, renderer: &Renderer) -> Result<(), ExternalImageError> {
+        let mut image_atlas = self.image_atlas.lock().unwrap();
+        let size = self.texture.size();
+        let rect = image_atlas
+            .reserve(&self.name, size.width, size.height)
+            .ok_or(ExternalImageError::AtlasFull)?;
+        self.blitter
+            .blit_region(renderer, &self.texture, image_atlas.texture(), rect);
+        Ok(())
+    }
+}
+
+impl Drop for ExternalTexture {
+    fn drop(&mut self) {
+        self.image_atlas.lock().unwrap().release(&self.name);
+    }
+}
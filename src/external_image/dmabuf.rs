@@ -0,0 +1,268 @@
+use std::{
+    ffi::CStr,
+    os::fd::{AsRawFd, IntoRawFd, OwnedFd},
+};
+
+use ash::{extensions::khr, vk};
+use wgpu::{
+    hal::{self, api::Vulkan, vulkan},
+    *,
+};
+
+use super::ExternalImageError;
+
+// A single plane image shared through a linux DMA-BUF file descriptor, for
+// example a wayland client buffer or a frame of a hardware video decoder
+#[derive(Debug)]
+pub struct DmaBuf {
+    // Owned by the imported texture once the import succeeds
+    pub fd: OwnedFd,
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    // DRM format modifier describing the tiling of the memory
+    pub modifier: u64,
+    pub offset: u64,
+    pub stride: u64,
+}
+
+fn extensions() -> [&'static CStr; 3] {
+    [
+        vk::KhrExternalMemoryFdFn::name(),
+        vk::ExtExternalMemoryDmaBufFn::name(),
+        vk::ExtImageDrmFormatModifierFn::name(),
+    ]
+}
+
+// Opens the device through wgpu-hal the same way wgpu does, with the
+// extensions for importing DMA-BUFs added. Returns None if the adapter isn't a
+// Vulkan adapter supporting them.
+pub(crate) fn request_device(
+    adapter: &Adapter,
+    descriptor: &DeviceDescriptor<'_>,
+) -> Option<(Device, Queue)> {
+    let features = descriptor.required_features;
+    let open_device = unsafe {
+        adapter.as_hal::<Vulkan, _, _>(|hal_adapter| {
+            let hal_adapter = hal_adapter?;
+            let capabilities = hal_adapter.physical_device_capabilities();
+            if !extensions()
+                .iter()
+                .all(|extension| capabilities.supports_extension(extension))
+            {
+                return None;
+            }
+
+            let mut enabled_extensions = hal_adapter.required_device_extensions(features);
+            for extension in extensions() {
+                if !enabled_extensions.contains(&extension) {
+                    enabled_extensions.push(extension);
+                }
+            }
+            let mut device_features =
+                hal_adapter.physical_device_features(&enabled_extensions, features);
+
+            let family_info = vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(0)
+                .queue_priorities(&[1.0])
+                .build();
+            let family_infos = [family_info];
+            let extension_names: Vec<_> = enabled_extensions
+                .iter()
+                .map(|extension| extension.as_ptr())
+                .collect();
+            let info = device_features
+                .add_to_device_create_builder(
+                    vk::DeviceCreateInfo::builder()
+                        .queue_create_infos(&family_infos)
+                        .enabled_extension_names(&extension_names),
+                )
+                .build();
+
+            let raw_device = hal_adapter
+                .shared_instance()
+                .raw_instance()
+                .create_device(hal_adapter.raw_physical_device(), &info, None)
+                .ok()?;
+            hal_adapter
+                .device_from_raw(raw_device, true, &enabled_extensions, features, 0, 0)
+                .ok()
+        })
+    }?;
+
+    unsafe { adapter.create_device_from_hal(open_device, descriptor, None) }.ok()
+}
+
+fn vk_format(format: TextureFormat) -> Option<vk::Format> {
+    Some(match format {
+        TextureFormat::Rgba8Unorm => vk::Format::R8G8B8A8_UNORM,
+        TextureFormat::Rgba8UnormSrgb => vk::Format::R8G8B8A8_SRGB,
+        TextureFormat::Bgra8Unorm => vk::Format::B8G8R8A8_UNORM,
+        TextureFormat::Bgra8UnormSrgb => vk::Format::B8G8R8A8_SRGB,
+        TextureFormat::Rgb10a2Unorm => vk::Format::A2B10G10R10_UNORM_PACK32,
+        TextureFormat::R8Unorm => vk::Format::R8_UNORM,
+        TextureFormat::Rg8Unorm => vk::Format::R8G8_UNORM,
+        _ => return None,
+    })
+}
+
+// Destroys the image and frees the imported memory with the texture
+struct ImportedImage {
+    device: ash::Device,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+}
+
+impl Drop for ImportedImage {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+pub(crate) fn import(device: &Device, dmabuf: DmaBuf) -> Result<Texture, ExternalImageError> {
+    let vk_format =
+        vk_format(dmabuf.format).ok_or(ExternalImageError::UnsupportedFormat(dmabuf.format))?;
+    let size = Extent3d {
+        width: dmabuf.width,
+        height: dmabuf.height,
+        depth_or_array_layers: 1,
+    };
+    let usage = TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC;
+
+    let hal_texture = unsafe {
+        device.as_hal::<Vulkan, _, _>(|hal_device| {
+            let hal_device = hal_device.ok_or(ExternalImageError::UnsupportedBackend)?;
+            let missing: Vec<_> = extensions()
+                .into_iter()
+                .filter(|extension| !hal_device.enabled_device_extensions().contains(extension))
+                .map(|extension| extension.to_string_lossy().into_owned())
+                .collect();
+            if !missing.is_empty() {
+                return Err(ExternalImageError::MissingExtensions(missing));
+            }
+
+            let raw_device = hal_device.raw_device();
+            let import_error = |error: vk::Result| ExternalImageError::Import(error.to_string());
+
+            let plane_layouts = [vk::SubresourceLayout {
+                offset: dmabuf.offset,
+                size: 0,
+                row_pitch: dmabuf.stride,
+                array_pitch: 0,
+                depth_pitch: 0,
+            }];
+            let mut modifier_info = vk::ImageDrmFormatModifierExplicitCreateInfoEXT::builder()
+                .drm_format_modifier(dmabuf.modifier)
+                .plane_layouts(&plane_layouts);
+            let mut external_info = vk::ExternalMemoryImageCreateInfo::builder()
+                .handle_types(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT);
+            let image_info = vk::ImageCreateInfo::builder()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(vk_format)
+                .extent(vk::Extent3D {
+                    width: dmabuf.width,
+                    height: dmabuf.height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT)
+                .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_SRC)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .push_next(&mut external_info)
+                .push_next(&mut modifier_info);
+            let image = raw_device
+                .create_image(&image_info, None)
+                .map_err(import_error)?;
+
+            let memory = import_memory(hal_device, image, dmabuf.fd).map_err(|error| {
+                raw_device.destroy_image(image, None);
+                import_error(error)
+            })?;
+
+            let guard = ImportedImage {
+                device: raw_device.clone(),
+                image,
+                memory,
+            };
+            Ok(vulkan::Device::texture_from_raw(
+                image,
+                &hal::TextureDescriptor {
+                    label: Some("External image"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: dmabuf.format,
+                    usage: hal::TextureUses::RESOURCE | hal::TextureUses::COPY_SRC,
+                    memory_flags: hal::MemoryFlags::empty(),
+                    view_formats: Vec::new(),
+                },
+                Some(Box::new(guard)),
+            ))
+        })
+    }
+    .ok_or(ExternalImageError::UnsupportedBackend)??;
+
+    Ok(unsafe {
+        device.create_texture_from_hal::<Vulkan>(
+            hal_texture,
+            &TextureDescriptor {
+                label: Some("External image"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: dmabuf.format,
+                usage,
+                view_formats: &[],
+            },
+        )
+    })
+}
+
+// Allocates memory for the image backed by the file descriptor and binds it.
+// The file descriptor is closed if the import fails.
+unsafe fn import_memory(
+    hal_device: &vulkan::Device,
+    image: vk::Image,
+    fd: OwnedFd,
+) -> Result<vk::DeviceMemory, vk::Result> {
+    let raw_device = hal_device.raw_device();
+    let fd_loader =
+        khr::ExternalMemoryFd::new(hal_device.shared_instance().raw_instance(), raw_device);
+    let fd_properties = fd_loader.get_memory_fd_properties(
+        vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
+        fd.as_raw_fd(),
+    )?;
+
+    let requirements = raw_device.get_image_memory_requirements(image);
+    let memory_types = requirements.memory_type_bits & fd_properties.memory_type_bits;
+    if memory_types == 0 {
+        return Err(vk::Result::ERROR_INVALID_EXTERNAL_HANDLE);
+    }
+
+    let mut import_info = vk::ImportMemoryFdInfoKHR::builder()
+        .handle_type(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT)
+        .fd(fd.as_raw_fd());
+    let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::builder().image(image);
+    let allocate_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(requirements.size)
+        .memory_type_index(memory_types.trailing_zeros())
+        .push_next(&mut import_info)
+        .push_next(&mut dedicated_info);
+    let memory = raw_device.allocate_memory(&allocate_info, None)?;
+    // Vulkan owns the file descriptor after a successful import
+    let _ = fd.into_raw_fd();
+
+    if let Err(error) = raw_device.bind_image_memory(image, memory, 0) {
+        raw_device.free_memory(memory, None);
+        return Err(error);
+    }
+    Ok(memory)
+}
@@ -1,6 +1,11 @@
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 
-use font_kit::{handle::Handle, source::SystemSource};
+use font_kit::{
+    family_name::FamilyName,
+    handle::Handle,
+    properties::{Properties, Style, Weight},
+    source::SystemSource,
+};
 use swash::FontRef;
 
 #[derive(Clone)]
@@ -10,13 +15,36 @@ pub struct Font {
 }
 
 impl Font {
+    // Uses the first font in the font data
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        Self {
+            data: Arc::new(data),
+            index: 0,
+        }
+    }
+
+    // The regular face of the family
     pub fn from_name(font_name: &str) -> Option<Self> {
-        let font = &SystemSource::new()
-            .select_family_by_name(font_name)
-            .ok()?
-            .fonts()[0]
-            .clone();
+        Self::from_name_and_style(font_name, false, false)
+    }
 
+    // The face of the family closest to the weight and slant, which may be
+    // the regular face when the family has no bold or italic one
+    pub fn from_name_and_style(font_name: &str, bold: bool, italic: bool) -> Option<Self> {
+        let mut properties = Properties::new();
+        if bold {
+            properties.weight(Weight::BOLD);
+        }
+        if italic {
+            properties.style(Style::Italic);
+        }
+        let font = SystemSource::new()
+            .select_best_match(&[FamilyName::Title(font_name.to_string())], &properties)
+            .ok()?;
+        Self::from_handle(&font)
+    }
+
+    fn from_handle(font: &Handle) -> Option<Self> {
         match font {
             Handle::Path { path, font_index } => {
                 let data = std::fs::read(path).ok()?;
@@ -32,7 +60,42 @@ impl Font {
         }
     }
 
+    // Semibold and heavier faces count as bold
+    pub fn is_bold(&self) -> bool {
+        self.as_ref()
+            .is_some_and(|font| font.attributes().weight().0 >= 600)
+    }
+
+    // Oblique faces count as italic
+    pub fn is_italic(&self) -> bool {
+        self.as_ref()
+            .is_some_and(|font| font.attributes().style() != swash::Style::Normal)
+    }
+
     pub fn as_ref<'a>(&'a self) -> Option<FontRef<'a>> {
         FontRef::from_index(self.data.as_ref(), self.index)
     }
 }
+
+// Name a styled face is loaded and cached under, the family name followed by
+// :bold, :italic or :bold italic. Asset sources can provide the styled faces
+// under these names.
+pub(crate) fn styled_font_name(font_name: &str, bold: bool, italic: bool) -> Cow<'_, str> {
+    match (bold, italic) {
+        (false, false) => Cow::Borrowed(font_name),
+        (true, false) => Cow::Owned(format!("{font_name}:bold")),
+        (false, true) => Cow::Owned(format!("{font_name}:italic")),
+        (true, true) => Cow::Owned(format!("{font_name}:bold italic")),
+    }
+}
+
+// Splits a name made by styled_font_name into the family and whether it's
+// bold and italic
+pub(crate) fn parse_styled_font_name(name: &str) -> (&str, bool, bool) {
+    match name.rsplit_once(':') {
+        Some((family, "bold")) => (family, true, false),
+        Some((family, "italic")) => (family, false, true),
+        Some((family, "bold italic")) => (family, true, true),
+        _ => (name, false, false),
+    }
+}
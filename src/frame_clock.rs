@@ -0,0 +1,78 @@
+use std::time::Instant;
+
+// Timing of a single rendered frame as exposed to the shaders
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FrameTime {
+    // Seconds since the first frame after the clock was created or reset
+    pub elapsed: f32,
+    // Seconds since the previous frame. Zero for the first frame.
+    pub delta: f32,
+    pub frame_index: u32,
+}
+
+// Tracks the time of every rendered frame. Either follows the wall clock or,
+// with a fixed time step, advances by the same amount each frame which keeps
+// offscreen rendering and recordings deterministic.
+#[derive(Debug, Clone)]
+pub struct FrameClock {
+    start: Instant,
+    fixed_time_step: Option<f32>,
+    previous: Option<FrameTime>,
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            fixed_time_step: None,
+            previous: None,
+        }
+    }
+
+    pub fn set_fixed_time_step(&mut self, time_step: Option<f32>) {
+        self.fixed_time_step = time_step;
+    }
+
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    // Time of the previously rendered frame
+    pub fn last_frame(&self) -> Option<FrameTime> {
+        self.previous
+    }
+
+    pub(crate) fn tick(&mut self) -> FrameTime {
+        let frame = match (self.previous, self.fixed_time_step) {
+            (None, _) => FrameTime {
+                elapsed: 0.0,
+                delta: 0.0,
+                frame_index: 0,
+            },
+            (Some(previous), Some(time_step)) => FrameTime {
+                elapsed: previous.elapsed + time_step,
+                delta: time_step,
+                frame_index: previous.frame_index.wrapping_add(1),
+            },
+            (Some(previous), None) => {
+                let elapsed = self.start.elapsed().as_secs_f32();
+                FrameTime {
+                    elapsed,
+                    delta: elapsed - previous.elapsed,
+                    frame_index: previous.frame_index.wrapping_add(1),
+                }
+            }
+        };
+        if self.previous.is_none() {
+            self.start = Instant::now();
+        }
+        self.previous = Some(frame);
+        frame
+    }
+}
@@ -0,0 +1,154 @@
+use glam::{vec4, Vec2, Vec4};
+use shader::ShaderConstants;
+
+use crate::{
+    glyph::GlyphState,
+    renderer::{clip_to_surface, intersect_rects, visible_content_rect},
+    scene::{Layer, PrimitiveKind, Scene},
+    viewport::Viewport,
+};
+
+// A text run drawn by the last render. The bounds are in frame pixels and
+// cover the shaped line of the run, or the visible part of the layer while
+// its font is loading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun {
+    pub text: String,
+    pub bounds: Vec4,
+    pub tag: Option<u64>,
+    // Index of the scene in the rendered viewports and of the layer in it
+    pub scene: usize,
+    pub layer: usize,
+}
+
+// A tagged primitive drawn by the last render, which the embedder can expose
+// as something to interact with. The bounds are in frame pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InteractiveRegion {
+    pub bounds: Vec4,
+    pub kind: PrimitiveKind,
+    pub tag: u64,
+    pub scene: usize,
+    pub layer: usize,
+}
+
+// What the last render drew in a form accessibility apis like AccessKit can
+// consume, in drawing order. Only primitives inside the visible part of their
+// layer are listed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameMetadata {
+    pub text_runs: Vec<TextRun>,
+    pub regions: Vec<InteractiveRegion>,
+}
+
+impl FrameMetadata {
+    pub(crate) fn clear(&mut self) {
+        self.text_runs.clear();
+        self.regions.clear();
+    }
+
+    // Adds the visible text runs and tagged primitives of a scene drawn into
+    // the viewport with the constants
+    pub(crate) fn add_scene(
+        &mut self,
+        scene_index: usize,
+        scene: &Scene,
+        viewport: Viewport,
+        constants: &ShaderConstants,
+        mut glyphs: Option<&mut GlyphState>,
+    ) {
+        let constants = constants.with_scroll_offset(Vec2::ZERO);
+        for (layer_index, layer) in scene.layers.iter().enumerate() {
+            let visible = visible_content_rect(&constants, layer);
+            // Content rect to frame pixels, or None when it isn't visible
+            let to_frame = |rect: Vec4| {
+                let rect = intersect_rects(rect, visible);
+                if rect.z <= 0.0 || rect.w <= 0.0 {
+                    return None;
+                }
+                let scrolled = rect - vec4(layer.scroll_offset.x, layer.scroll_offset.y, 0., 0.);
+                let bounds = clip_to_surface(&constants, scrolled);
+                Some(bounds + vec4(viewport.x as f32, viewport.y as f32, 0., 0.))
+            };
+
+            for text in layer.texts.iter() {
+                let line = glyphs.as_deref_mut().and_then(|glyphs| {
+                    glyphs.text_bounds(&layer.font_name, text, constants.y_direction)
+                });
+                let Some(bounds) = to_frame(line.unwrap_or(text.bounds())) else {
+                    continue;
+                };
+                self.text_runs.push(TextRun {
+                    text: text.text.clone(),
+                    bounds,
+                    tag: text.tag,
+                    scene: scene_index,
+                    layer: layer_index,
+                });
+                if let Some(tag) = text.tag {
+                    self.add_region(bounds, PrimitiveKind::Text, tag, scene_index, layer_index);
+                }
+            }
+
+            for (kind, rect, tag) in tagged_primitives(layer) {
+                if let Some(bounds) = to_frame(rect) {
+                    self.add_region(bounds, kind, tag, scene_index, layer_index);
+                }
+            }
+        }
+    }
+
+    fn add_region(
+        &mut self,
+        bounds: Vec4,
+        kind: PrimitiveKind,
+        tag: u64,
+        scene: usize,
+        layer: usize,
+    ) {
+        self.regions.push(InteractiveRegion {
+            bounds,
+            kind,
+            tag,
+            scene,
+            layer,
+        });
+    }
+}
+
+// Bounds and tags of the tagged primitives of the layer other than texts
+fn tagged_primitives(layer: &Layer) -> impl Iterator<Item = (PrimitiveKind, Vec4, u64)> + '_ {
+    let quads = layer
+        .quads
+        .iter()
+        .filter_map(|quad| Some((PrimitiveKind::Quad, quad.bounds(), quad.tag()?)));
+    let paths = layer
+        .paths
+        .iter()
+        .filter_map(|path| Some((PrimitiveKind::Path, path.bounds(), path.tag?)));
+    let sprites = layer
+        .sprites
+        .iter()
+        .filter_map(|sprite| Some((PrimitiveKind::Sprite, sprite.bounds(), sprite.tag?)));
+    let procedurals = layer.procedurals.iter().filter_map(|procedural| {
+        Some((
+            PrimitiveKind::Procedural,
+            procedural.bounds(),
+            procedural.tag?,
+        ))
+    });
+    let shapes = layer
+        .shapes
+        .iter()
+        .filter_map(|shape| Some((PrimitiveKind::Shape, shape.bounds(), shape.tag?)));
+    let carets = layer
+        .carets
+        .iter()
+        .filter_map(|caret| Some((PrimitiveKind::Caret, caret.bounds(), caret.tag?)));
+    quads
+        .chain(paths)
+        .chain(sprites)
+        .chain(procedurals)
+        .chain(shapes)
+        .chain(carets)
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+// How often frames are drawn while content is animating
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PacingMode {
+    // Every refresh of the display
+    #[default]
+    EveryRefresh,
+    // At the rate the content animates at, such as a smooth scroll animated
+    // at 48 frames per second
+    ContentRate(f32),
+}
+
+// Decides when to draw the next frame. On displays with a fixed refresh rate
+// the content rate is rounded to a whole number of refreshes per frame, since
+// frames which stay on screen for alternating numbers of refreshes judder.
+// Displays with variable refresh, such as with FreeSync or G-Sync, show each
+// frame when it's presented, so the content rate is kept as is, within the
+// refresh rate of the display.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FramePacer {
+    pub mode: PacingMode,
+    // Refresh rate of the display in millihertz, the maximum one for
+    // variable refresh displays
+    pub refresh_rate_millihertz: Option<u32>,
+    pub variable_refresh: bool,
+    last_presented: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_mode(&mut self, mode: PacingMode) {
+        if let PacingMode::ContentRate(frames_per_second) = mode {
+            assert!(
+                frames_per_second > 0.0,
+                "The content rate has to be positive"
+            );
+        }
+        self.mode = mode;
+    }
+
+    pub fn with_mode(mut self, mode: PacingMode) -> Self {
+        self.set_mode(mode);
+        self
+    }
+
+    pub fn set_refresh_rate_millihertz(&mut self, refresh_rate: Option<u32>) {
+        self.refresh_rate_millihertz = refresh_rate.filter(|refresh_rate| *refresh_rate > 0);
+    }
+
+    pub fn with_refresh_rate_millihertz(mut self, refresh_rate: Option<u32>) -> Self {
+        self.set_refresh_rate_millihertz(refresh_rate);
+        self
+    }
+
+    pub fn set_variable_refresh(&mut self, enabled: bool) {
+        self.variable_refresh = enabled;
+    }
+
+    pub fn with_variable_refresh(mut self, enabled: bool) -> Self {
+        self.set_variable_refresh(enabled);
+        self
+    }
+
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_rate_millihertz
+            .map(|refresh_rate| Duration::from_secs_f64(1000.0 / refresh_rate as f64))
+    }
+
+    // Time between the presented frames. Unknown when drawing every refresh
+    // of a display with an unknown refresh rate, in which case the
+    // presentation is paced by the surface alone.
+    pub fn frame_interval(&self) -> Option<Duration> {
+        let refresh_interval = self.refresh_interval();
+        let PacingMode::ContentRate(frames_per_second) = self.mode else {
+            return refresh_interval;
+        };
+        let content_interval = Duration::from_secs_f64(1.0 / frames_per_second as f64);
+        let Some(refresh_interval) = refresh_interval else {
+            return Some(content_interval);
+        };
+        if self.variable_refresh {
+            Some(content_interval.max(refresh_interval))
+        } else {
+            let refreshes = (content_interval.as_secs_f64() / refresh_interval.as_secs_f64())
+                .round()
+                .max(1.0);
+            Some(refresh_interval.mul_f64(refreshes))
+        }
+    }
+
+    // When the next frame should be drawn when pacing to the content rate.
+    // None when it can be drawn right away, which includes every frame when
+    // drawing every refresh, as the surface paces those.
+    pub fn next_frame_time(&self) -> Option<Instant> {
+        if self.mode == PacingMode::EveryRefresh {
+            return None;
+        }
+        Some(self.last_presented? + self.frame_interval()?)
+    }
+
+    pub fn frame_presented(&mut self, time: Instant) {
+        self.last_presented = Some(time);
+    }
+
+    // Forgets the last frame, so that the first frame after the content
+    // starts animating again is drawn right away
+    pub fn reset(&mut self) {
+        self.last_presented = None;
+    }
+}
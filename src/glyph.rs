@@ -1,181 +1,810 @@
-use std::{collections::HashMap, sync::Arc};
+pub(crate) mod box_drawing;
+mod decoration;
+pub(crate) mod fit;
+mod metrics;
+pub(crate) mod missing_glyph;
+mod rasterizer;
+pub(crate) mod shaping;
+pub(crate) mod tabs;
+pub(crate) mod zoom;
 
-use etagere::{size2, AllocId, AtlasAllocator};
-use glam::{vec2, Vec2, Vec4};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use etagere::{size2, AllocId, AtlasAllocator, Rectangle};
+use glam::{vec2, vec4, Vec2, Vec4};
 use ordered_float::OrderedFloat;
-use shader::{InstancedGlyph, ShaderConstants};
+use shader::{InstancedGlyph, InstancedTextPaint, ShaderConstants, TEXT_PAINT_COLOR};
 use swash::{
-    scale::{Render, ScaleContext, Source, StrikeWith},
-    shape::{cluster::Glyph, ShapeContext},
-    zeno::{Format, Placement, Vector},
-    CacheKey, FontRef, GlyphId,
+    scale::{image::Image, Render, ScaleContext, Source, StrikeWith},
+    shape::{cluster::Glyph, ShapeContext, ShaperBuilder},
+    zeno::{Angle, Format, Placement, Transform, Vector},
+    FontRef, GlyphId,
 };
+use tracing::{debug, error};
+use unicode_segmentation::UnicodeSegmentation;
 use wgpu::*;
 
 use crate::{
-    font::Font,
-    renderer::{Drawable, Renderer},
-    scene::{Layer, Text},
+    asset_source::{AssetLoader, SharedAssets},
+    font::{parse_styled_font_name, styled_font_name, Font},
+    image_atlas::ImageAtlas,
+    memory::{entries_over_budget, MemoryBudget, MemoryReport},
+    renderer::{rects_overlap, visible_content_rect, Drawable, Renderer},
+    scene::{grapheme_width, Hinting, Layer, Text, TextOverflow, TextPaint, TextRendering},
+    shader_layout::PipelineInterface,
+    uploader::Uploader,
     ATLAS_SIZE,
 };
 
+use box_drawing::{is_box_drawing, rasterize_box_drawing};
+use decoration::{text_background, text_decoration, DecorationLine, DecorationPipeline};
+use fit::layout_text;
+use missing_glyph::rasterize_missing_glyph;
+pub(crate) use rasterizer::default_rasterizer_threads;
+use rasterizer::GlyphRasterizer;
+use shaping::{ShapingCache, ShapingCacheStats, DEFAULT_SHAPING_CACHE_CAPACITY};
+use tabs::expand_tabs;
+use zoom::ZoomRasterization;
+
+pub use metrics::TextMetrics;
+pub use zoom::GlyphZoomPolicy;
+
+// Layout of the instances in the gpu buffer. Packed to halve the upload size
+// unless the f32-instances feature is enabled, which allows comparing the
+// output against full precision instances.
+#[cfg(not(feature = "f32-instances"))]
+type GpuGlyph = shader::PackedGlyph;
+#[cfg(feature = "f32-instances")]
+type GpuGlyph = InstancedGlyph;
+
+#[cfg(not(feature = "f32-instances"))]
+const ENTRY_POINTS: [&str; 2] = ["glyph::glyph_vertex", "glyph::glyph_fragment"];
+#[cfg(feature = "f32-instances")]
+const ENTRY_POINTS: [&str; 2] = ["glyph::glyph_vertex_f32", "glyph::glyph_fragment_f32"];
+
+// Text paints the paint buffer holds. Runs past it are drawn with their color.
+const MAX_TEXT_PAINTS: usize = 10000;
+
+// Glyphs shaped from a run of grid cells. A ligature spans the cells of all
+// its characters, so that callers can keep drawing the backgrounds and the
+// cursor per cell, or break the ligature up when the cursor is inside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellCluster {
+    // Byte range of the characters in the text
+    pub source: Range<usize>,
+    // Cells covered by the glyphs, with the widths given by grapheme_width
+    pub cells: Range<usize>,
+    pub glyphs: Vec<GlyphId>,
+    pub advance: f32,
+}
+
+impl CellCluster {
+    pub fn is_ligature(&self) -> bool {
+        self.cells.len() > 1
+    }
+}
+
+// Rasterizes a glyph drawn by the renderer into a cell of the width and
+// height, with the top of the cell ascent pixels above the baseline
+type RasterizeSynthetic = fn(char, u32, u32, i32) -> Image;
+
 pub struct GlyphState {
     buffer: Buffer,
+    paint_buffer: Buffer,
     atlas_texture: Texture,
+    bind_group_layout: BindGroupLayout,
     bind_group: BindGroup,
     render_pipeline: RenderPipeline,
+    decorations: DecorationPipeline,
+    // Holds the pattern images of the text paints
+    image_atlas: Arc<Mutex<ImageAtlas>>,
 
     scale_context: ScaleContext,
     shaping_context: ShapeContext,
-    glyph_lookup: HashMap<GlyphKey, (Placement, AllocId)>,
-    shaped_text_lookup: HashMap<ShapeKey, Vec<Glyph>>,
+    // Placement, allocation and the frame the glyph was last drawn in
+    glyph_lookup: HashMap<GlyphKey, (Placement, AllocId, u64)>,
+    // Glyphs without any pixels, which aren't put in the atlas
+    blank_glyphs: HashSet<GlyphKey>,
+    shaping_cache: ShapingCache,
     atlas_allocator: AtlasAllocator,
+    // Only set while rasterizing in the background
+    rasterizer: Option<GlyphRasterizer>,
+    rasterizer_threads: usize,
+
+    assets: Arc<SharedAssets>,
+    fonts: HashMap<String, Font>,
+    // Only set while loading fonts in the background
+    font_loader: Option<AssetLoader<Font>>,
+
+    // Draws box drawing and block characters as geometry filling the cell
+    // unless overridden by the text
+    synthetic_box_drawing: bool,
+    // Draws characters the font has no glyph for as a box with the hex code
+    missing_glyph_boxes: bool,
+    // Hinting and pixel snapping unless overridden by the text
+    text_rendering: TextRendering,
+    // Fakes bold and italic text when the family has no such face
+    font_synthesis: bool,
+    // Rasterizes zoomed glyphs at steps of the zoom and scales them in
+    // between
+    zoom_policy: GlyphZoomPolicy,
+    zoom: ZoomRasterization,
+
+    frame: u64,
+    evictions: usize,
 }
 
 impl GlyphState {
-    fn prepare_glyph<'a, 'b: 'a>(
-        &'b mut self,
+    // Returns None while the font is loading in the background
+    fn font(&mut self, font_name: &str) -> Option<Font> {
+        if let Some(font) = self.fonts.get(font_name) {
+            return Some(font.clone());
+        }
+
+        if let Some(loader) = &mut self.font_loader {
+            loader.request(font_name);
+            return None;
+        }
+
+        let font = load_font(&self.assets, font_name)
+            .unwrap_or_else(|| panic!("Could not load font {font_name}"));
+        self.fonts.insert(font_name.to_string(), font.clone());
+        Some(font)
+    }
+
+    // Face for the weight and slant of the text, and what the rasterizer has
+    // to fake because the family has no such face. Returns None while the
+    // face is loading in the background.
+    fn text_font<'a>(
+        &mut self,
+        font_name: &'a str,
+        text: &Text,
+    ) -> Option<(Cow<'a, str>, Font, Synthesis)> {
+        let name = styled_font_name(font_name, text.bold, text.italic);
+        let font = self.font(&name)?;
+        let synthesis = if self.font_synthesis {
+            Synthesis {
+                embolden: text.bold && !font.is_bold(),
+                oblique: text.italic && !font.is_italic(),
+            }
+        } else {
+            Synthesis::default()
+        };
+        Some((name, font, synthesis))
+    }
+
+    // Loads fonts on a worker thread instead of while drawing. Text in a font
+    // which is still loading is left out.
+    pub fn set_background_font_loading(&mut self, enabled: bool) {
+        if enabled && self.font_loader.is_none() {
+            self.font_loader = Some(AssetLoader::new(self.assets.clone(), load_font));
+        } else if !enabled {
+            self.font_loader = None;
+        }
+    }
+
+    // Fonts which are still being loaded in the background
+    pub fn pending_fonts(&self) -> usize {
+        self.font_loader
+            .as_ref()
+            .map_or(0, |loader| loader.pending())
+    }
+
+    fn add_loaded_fonts(&mut self) {
+        let Some(loader) = &mut self.font_loader else {
+            return;
+        };
+        for (font_name, font) in loader.finished() {
+            let font = font.unwrap_or_else(|| panic!("Could not load font {font_name}"));
+            self.fonts.entry(font_name).or_insert(font);
+        }
+    }
+
+    fn prepare_glyph(
+        &mut self,
         queue: &Queue,
-        font_name: &str,
-        font_ref: FontRef<'a>,
-        glyph: swash::GlyphId,
+        font: &Font,
+        glyph_key: GlyphKey,
         bottom_left: Vec2,
-        size: f32,
         color: Vec4,
+        rotation: Vec2,
     ) -> Option<InstancedGlyph> {
-        // Create a font scaler for the given font and size
-        let mut scaler = self
-            .scale_context
-            .builder(font_ref)
-            .size(size)
-            .hint(true)
-            .build();
-
-        let glyph_key = GlyphKey::new(font_name, glyph, size, bottom_left);
+        if self.blank_glyphs.contains(&glyph_key) {
+            return None;
+        }
 
         // Get or find atlas allocation
         let (placement, allocation_rectangle) =
-            if let Some((placement, alloc_id)) = self.glyph_lookup.get(&glyph_key) {
+            if let Some((placement, alloc_id, last_used)) = self.glyph_lookup.get_mut(&glyph_key) {
+                *last_used = self.frame;
                 (*placement, self.atlas_allocator.get(*alloc_id))
+            } else if let Some(rasterizer) = &mut self.rasterizer {
+                rasterizer.request(&glyph_key, font);
+                self.placeholder(&glyph_key)?
             } else {
-                let image = Render::new(&[
-                    Source::ColorOutline(0),
-                    Source::ColorBitmap(StrikeWith::BestFit),
-                    Source::Outline,
-                ])
-                // Select a subpixel format
-                .format(Format::Subpixel)
-                // Apply the fractional offset
-                .offset(glyph_key.quantized_offset())
-                // Render the image
-                .render(&mut scaler, glyph)
-                .expect("Could not render glyph into an image");
-
-                if image.placement.width == 0 || image.placement.height == 0 {
-                    return None;
-                }
-
-                let allocation = self
-                    .atlas_allocator
-                    .allocate(size2(
-                        image.placement.width as i32,
-                        image.placement.height as i32,
-                    ))
-                    .expect("Could not allocate glyph to atlas");
-
-                self.glyph_lookup
-                    .insert(glyph_key, (image.placement, allocation.id));
-
-                queue.write_texture(
-                    ImageCopyTexture {
-                        texture: &self.atlas_texture,
-                        mip_level: 0,
-                        origin: Origin3d {
-                            x: allocation.rectangle.min.x as u32,
-                            y: allocation.rectangle.min.y as u32,
-                            z: 0,
-                        },
-                        aspect: TextureAspect::All,
-                    },
-                    &image.data,
-                    ImageDataLayout {
-                        offset: 0,
-                        bytes_per_row: Some(4 * image.placement.width as u32),
-                        rows_per_image: Some(image.placement.height as u32),
-                    },
-                    Extent3d {
-                        width: image.placement.width as u32,
-                        height: image.placement.height as u32,
-                        depth_or_array_layers: 1,
-                    },
-                );
-
-                (image.placement, allocation.rectangle)
+                let image = rasterize_glyph(&mut self.scale_context, font.as_ref()?, &glyph_key)
+                    .expect("Could not render glyph into an image");
+                self.upload_glyph(queue, glyph_key, image)?
             };
 
-        // Add the glyph to instances
-        Some(InstancedGlyph {
-            bottom_left: bottom_left.floor()
-                + vec2(
-                    placement.left as f32,
-                    placement.height as f32 - placement.top as f32,
-                ),
-            atlas_top_left: vec2(
-                allocation_rectangle.min.x as f32,
-                allocation_rectangle.min.y as f32,
-            ),
-            atlas_size: vec2(placement.width as f32, placement.height as f32),
-            _padding: Default::default(),
+        Some(glyph_instance(
+            bottom_left,
+            placement,
+            allocation_rectangle,
             color,
+            rotation,
+        ))
+    }
+
+    // Looks up or rasterizes a glyph drawn by the renderer instead of the
+    // font, such as box drawing characters. The name identifies the image,
+    // which doesn't depend on the font.
+    fn prepare_synthetic_glyph(
+        &mut self,
+        queue: &Queue,
+        name: String,
+        rasterize: impl FnOnce() -> Image,
+    ) -> Option<(Placement, Rectangle)> {
+        let glyph_key = GlyphKey {
+            glyph: 0,
+            font_name: Arc::from(name),
+            size: 0.0.into(),
+            x_offset: SubpixelOffset::Zero,
+            y_offset: SubpixelOffset::Zero,
+            hinted: false,
+            synthesis: Synthesis::default(),
+        };
+        if self.blank_glyphs.contains(&glyph_key) {
+            return None;
+        }
+
+        if let Some((placement, alloc_id, last_used)) = self.glyph_lookup.get_mut(&glyph_key) {
+            *last_used = self.frame;
+            Some((*placement, self.atlas_allocator.get(*alloc_id)))
+        } else {
+            self.upload_glyph(queue, glyph_key, rasterize())
+        }
+    }
+
+    // Shapes the text as one run and maps the clusters to the cells they
+    // cover. Returns None while the font is loading in the background.
+    pub fn cell_clusters(
+        &mut self,
+        font_name: &str,
+        text: &str,
+        size: f32,
+    ) -> Option<Vec<CellCluster>> {
+        let font = self.font(font_name)?;
+        Some(cell_clusters(
+            &mut self.shaping_context,
+            font.as_ref()?,
+            text,
+            size,
+        ))
+    }
+
+    pub fn set_synthetic_box_drawing(&mut self, enabled: bool) {
+        self.synthetic_box_drawing = enabled;
+    }
+
+    pub fn set_missing_glyph_boxes(&mut self, enabled: bool) {
+        self.missing_glyph_boxes = enabled;
+    }
+
+    pub fn set_text_rendering(&mut self, text_rendering: TextRendering) {
+        self.text_rendering = text_rendering;
+    }
+
+    pub fn set_font_synthesis(&mut self, enabled: bool) {
+        self.font_synthesis = enabled;
+    }
+
+    pub fn set_zoom_policy(&mut self, policy: GlyphZoomPolicy) {
+        self.zoom_policy = policy;
+    }
+
+    // Whether glyphs of the last frame were scaled from another zoom, and
+    // will be rasterized again once the zoom settles
+    pub fn scaled_glyphs(&self) -> bool {
+        self.zoom.scaled()
+    }
+
+    pub fn shaping_cache_stats(&self) -> ShapingCacheStats {
+        self.shaping_cache.stats()
+    }
+
+    pub fn set_shaping_cache_capacity(&mut self, capacity: usize) {
+        self.shaping_cache.set_capacity(capacity);
+    }
+
+    pub fn clear_shaping_cache(&mut self) {
+        self.shaping_cache.clear();
+    }
+
+    // Stands in for a glyph which is still being rasterized in the
+    // background. The same glyph at another subpixel offset is only off by a
+    // fraction of a pixel, glyphs without such a variant are left out until
+    // they are ready.
+    fn placeholder(&mut self, glyph_key: &GlyphKey) -> Option<(Placement, Rectangle)> {
+        let variant = SubpixelOffset::ALL
+            .iter()
+            .flat_map(|x_offset| {
+                SubpixelOffset::ALL.iter().map(|y_offset| GlyphKey {
+                    x_offset: *x_offset,
+                    y_offset: *y_offset,
+                    ..glyph_key.clone()
+                })
+            })
+            .find(|key| self.glyph_lookup.contains_key(key))?;
+        let (placement, alloc_id, last_used) = self.glyph_lookup.get_mut(&variant)?;
+        *last_used = self.frame;
+        Some((*placement, self.atlas_allocator.get(*alloc_id)))
+    }
+
+    // Allocates space for the image in the atlas and uploads it. Returns None
+    // for glyphs without any pixels, such as spaces.
+    fn upload_glyph(
+        &mut self,
+        queue: &Queue,
+        glyph_key: GlyphKey,
+        image: Image,
+    ) -> Option<(Placement, Rectangle)> {
+        if image.placement.width == 0 || image.placement.height == 0 {
+            self.blank_glyphs.insert(glyph_key);
+            return None;
+        }
+
+        let Some(allocation) = self.atlas_allocator.allocate(size2(
+            image.placement.width as i32,
+            image.placement.height as i32,
+        )) else {
+            error!(
+                glyphs = self.glyph_lookup.len(),
+                width = image.placement.width,
+                height = image.placement.height,
+                "The glyph atlas is full"
+            );
+            panic!("Could not allocate glyph to atlas");
+        };
+
+        self.glyph_lookup
+            .insert(glyph_key, (image.placement, allocation.id, self.frame));
+
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &self.atlas_texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: allocation.rectangle.min.x as u32,
+                    y: allocation.rectangle.min.y as u32,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            &image.data,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.placement.width),
+                rows_per_image: Some(image.placement.height),
+            },
+            Extent3d {
+                width: image.placement.width,
+                height: image.placement.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Some((image.placement, allocation.rectangle))
+    }
+
+    // Rasterizes glyphs on worker threads instead of while drawing. Glyphs
+    // which are still being rasterized are drawn with a placeholder.
+    pub fn set_background_rasterization(&mut self, enabled: bool) {
+        let threads = self.rasterizer_threads;
+        let running = self
+            .rasterizer
+            .as_ref()
+            .is_some_and(|rasterizer| rasterizer.threads() == threads);
+        if enabled && !running {
+            self.rasterizer = Some(GlyphRasterizer::new(threads));
+        } else if !enabled {
+            self.rasterizer = None;
+        }
+    }
+
+    // Takes effect when background rasterization is next set
+    pub fn set_rasterizer_threads(&mut self, threads: usize) {
+        self.rasterizer_threads = threads.max(1);
+    }
+
+    // Glyphs which are still being rasterized in the background
+    pub fn pending_glyphs(&self) -> usize {
+        self.rasterizer
+            .as_ref()
+            .map_or(0, |rasterizer| rasterizer.pending())
+    }
+
+    // Uploads the glyphs the worker thread finished since the last frame
+    fn upload_finished_glyphs(&mut self, queue: &Queue) {
+        let Some(rasterizer) = &mut self.rasterizer else {
+            return;
+        };
+        for (glyph_key, image) in rasterizer.finished() {
+            let image = image.expect("Could not render glyph into an image");
+            if !self.glyph_lookup.contains_key(&glyph_key) {
+                self.upload_glyph(queue, glyph_key, image);
+            }
+        }
+    }
+
+    // Shapes the text unless it's cached already
+    // The tab advances depend on the tab stops of the text, so they are set
+    // after the glyphs are cached.
+    fn shaped_glyphs(&mut self, font_ref: FontRef, text: &Text) -> Cow<'_, [Glyph]> {
+        let glyphs = self
+            .shaping_cache
+            .get_or_shape(&mut self.shaping_context, font_ref, text);
+        if !text.text.contains('\t') {
+            return Cow::Borrowed(glyphs);
+        }
+        let mut glyphs = glyphs.to_vec();
+        expand_tabs(&mut glyphs, &text.tab_stops, font_ref, text.size);
+        Cow::Owned(glyphs)
+    }
+
+    // Sum of the advances of the shaped text
+    fn shaped_width(&mut self, font_ref: FontRef, text: &Text) -> f32 {
+        self.shaped_glyphs(font_ref, text)
+            .iter()
+            .map(|glyph| glyph.advance)
+            .sum()
+    }
+
+    // Width of the text as drawn, which ends at the max width for faded text
+    fn visible_width(&mut self, font_ref: FontRef, text: &Text) -> f32 {
+        let width = self.shaped_width(font_ref, text);
+        text.max_width
+            .map_or(width, |max_width| width.min(max_width))
+    }
+
+    // Resolves the size and position of a text fitted to a rect, and the
+    // truncation of overflowing text. Other texts are returned as they are.
+    fn laid_out_text<'a>(&mut self, font_ref: FontRef, text: &'a Text) -> Cow<'a, Text> {
+        if text.fit.is_none() && text.max_width.is_none() {
+            return Cow::Borrowed(text);
+        }
+        let width = self.shaped_width(font_ref, text);
+        layout_text(&mut self.shaping_context, font_ref, text, width)
+    }
+
+    // Shapes the text in the font to measure it, at the size it's fitted to.
+    // Returns None while the font is loading in the background.
+    pub fn measure_text(&mut self, font_name: &str, text: &Text) -> Option<TextMetrics> {
+        let (_, font, _) = self.text_font(font_name, text)?;
+        let font_ref = font.as_ref()?;
+        let text = self.laid_out_text(font_ref, text);
+        Some(TextMetrics {
+            width: self.visible_width(font_ref, &text),
+            ..TextMetrics::new(font_ref, text.size)
         })
     }
 
-    pub fn shape_and_rasterize_text<'a, 'b: 'a>(
+    // Scene space rect of the line of the laid out text, from the descent to
+    // the ascent of the font
+    pub(crate) fn text_bounds(
+        &mut self,
+        font_name: &str,
+        text: &Text,
+        y_direction: f32,
+    ) -> Option<Vec4> {
+        let metrics = self.measure_text(font_name, text)?;
+        let (_, font, _) = self.text_font(font_name, text)?;
+        let text = self.laid_out_text(font.as_ref()?, text);
+        Some(line_bounds(&text, &metrics, y_direction))
+    }
+
+    // Paint of a laid out text filling the line of the text, along with its
+    // outline. Texts with only an outline are filled with their color.
+    fn text_paint(
         &mut self,
         queue: &Queue,
+        font_ref: FontRef,
+        text: &Text,
+        constants: &ShaderConstants,
+    ) -> InstancedTextPaint {
+        let metrics = TextMetrics {
+            width: self.visible_width(font_ref, text),
+            ..TextMetrics::new(font_ref, text.size)
+        };
+        let bounds = line_bounds(text, &metrics, constants.y_direction);
+        let raster_zoom = self.zoom.raster_zoom(constants.camera_zoom);
+        let paint = match &text.paint {
+            None => InstancedTextPaint {
+                kind: TEXT_PAINT_COLOR,
+                ..Default::default()
+            },
+            Some(TextPaint::Procedural {
+                kind,
+                secondary_color,
+            }) => {
+                let (kind, parameters) = kind.to_shader();
+                InstancedTextPaint {
+                    secondary_color: *secondary_color,
+                    parameters,
+                    bounds,
+                    kind,
+                    ..Default::default()
+                }
+            }
+            Some(TextPaint::Pattern(pattern)) => InstancedTextPaint {
+                bounds,
+                pattern_atlas_rect: self
+                    .image_atlas
+                    .lock()
+                    .unwrap()
+                    .get_or_upload(queue, &pattern.image),
+                pattern_transform: Vec4::from_array(pattern.transform.to_cols_array()),
+                pattern_offset: pattern.offset,
+                ..Default::default()
+            },
+        };
+        match text.outline {
+            // The outline is drawn in atlas pixels, which are scaled to the
+            // surface when the glyphs aren't rasterized at the zoom
+            Some(outline) => InstancedTextPaint {
+                outline_color: outline.color,
+                outline_width: outline.width * raster_zoom,
+                outline_softness: outline.softness * raster_zoom,
+                ..paint
+            },
+            None => paint,
+        }
+    }
+
+    // Places the glyphs of the text on the surface without rasterizing any
+    fn place_glyphs(
+        &mut self,
+        constants: &ShaderConstants,
         font_name: &str,
-        font_ref: FontRef<'a>,
+        font_ref: FontRef,
+        synthesis: Synthesis,
         text: &Text,
-    ) -> Vec<InstancedGlyph> {
-        let key = ShapeKey::new(Arc::from(text.text.as_str()), font_ref, text.size.into());
+    ) -> PlacedText {
+        let glyphs = self.shaped_glyphs(font_ref, text).into_owned();
 
-        let mut shaper = self
-            .shaping_context
-            .builder(font_ref)
-            .size(text.size)
-            .build();
-        let glyphs = self
-            .shaped_text_lookup
-            .entry(key)
-            .or_insert_with(|| {
-                shaper.add_str(&text.text);
+        // Glyphs are rasterized at the size they will appear on the surface so
+        // that zoomed text stays crisp instead of scaling the atlas bitmaps,
+        // unless the zoom policy picked another zoom while zooming. The font
+        // glyphs are then placed in the pixels of that zoom, and scaled to the
+        // surface when drawn.
+        let raster_zoom = self.zoom.raster_zoom(constants.camera_zoom);
+        let raster_size = text.size * raster_zoom;
+        let scale = constants.camera_zoom / raster_zoom;
+
+        let synthetic_box_drawing = text
+            .synthetic_box_drawing
+            .unwrap_or(self.synthetic_box_drawing);
+        let rendering = text.rendering.unwrap_or(self.text_rendering);
+        // Rounded advances keep every glyph at the same offset from the pixel
+        // grid, so that static text stays sharp
+        let zoom = constants.camera_zoom;
+        let advance = |glyph: &Glyph| {
+            if rendering.round_advances {
+                (glyph.advance * zoom).round() / zoom
+            } else {
+                glyph.advance
+            }
+        };
+        let metrics = font_ref.metrics(&[]).scale(text.size * zoom);
+        let ascent = metrics.ascent.round() as i32;
+        let cell_height = (metrics.ascent + metrics.descent).round().max(1.0) as u32;
+
+        // Overflowing faded text fades out over the stretch before the max
+        // width, and the glyphs past it are left out
+        let width: f32 = glyphs.iter().map(|glyph| glyph.advance).sum();
+        let fade = match (text.max_width, text.overflow) {
+            (Some(max_width), TextOverflow::Fade) if width > max_width => Some(vec2(
+                (max_width - text.size * FADE_LENGTH).max(0.0),
+                max_width,
+            )),
+            _ => None,
+        };
+
+        let mut current_x = 0.;
+        let mut placed = Vec::new();
+        for glyph in glyphs.iter() {
+            if fade.is_some_and(|fade| current_x >= fade.y) {
+                break;
+            }
+
+            // Synthetic glyphs fill a cell of the advance and the line
+            // height, aligned to whole pixels so that neighbouring cells
+            // connect
+            if let Some(character) = char::from_u32(glyph.data) {
+                let cell_width = (glyph.advance * constants.camera_zoom).ceil() as u32;
+                let box_drawing = synthetic_box_drawing && is_box_drawing(character);
+                let missing = self.missing_glyph_boxes
+                    && glyph.id == 0
+                    && cell_width > 0
+                    && !character.is_control();
+                if box_drawing || missing {
+                    let (kind, rasterize): (_, RasterizeSynthetic) = if box_drawing {
+                        ("box drawing", rasterize_box_drawing)
+                    } else {
+                        ("missing glyph", rasterize_missing_glyph)
+                    };
+                    let bottom_left = constants.to_surface(text.bottom_left + vec2(current_x, 0.0));
+                    current_x += advance(glyph);
+                    placed.push(PlacedGlyph::Synthetic {
+                        name: format!("{kind} {character} {cell_width}x{cell_height}+{ascent}"),
+                        character,
+                        rasterize,
+                        cell_width: cell_width.max(1),
+                        bottom_left: bottom_left.round(),
+                    });
+                    continue;
+                }
+            }
+
+            let mut bottom_left = constants.to_surface(
+                text.bottom_left + vec2(current_x + glyph.x, -glyph.y * constants.y_direction),
+            ) / scale;
+            match (rendering.snap_origins, rendering.hinting) {
+                (true, _) => bottom_left = bottom_left.round(),
+                // Keeps the horizontal subpixel position but puts the
+                // baseline on the pixel grid
+                (false, Hinting::Slight) => bottom_left.y = bottom_left.y.round(),
+                _ => {}
+            }
+            let key = GlyphKey::new(
+                font_name,
+                glyph.id,
+                raster_size,
+                bottom_left,
+                rendering.hinting == Hinting::Full,
+            )
+            .with_synthesis(synthesis);
+            placed.push(PlacedGlyph::Font { key, bottom_left });
+            current_x += advance(glyph);
+        }
 
-                let mut glyphs = Vec::new();
+        PlacedText {
+            glyphs: placed,
+            cell_height,
+            ascent,
+            fade,
+            scale,
+        }
+    }
 
-                shaper.shape_with(|cluster| {
-                    for glyph in cluster.glyphs {
-                        glyphs.push(*glyph);
+    // Queues the glyphs of the visible texts which aren't in the atlas yet
+    // with the background rasterizer, so that the workers start on them
+    // before the layers are drawn
+    fn request_glyphs(&mut self, constants: &ShaderConstants, layers: &[&Layer]) {
+        if self.rasterizer.is_none() {
+            return;
+        }
+        for layer in layers {
+            let visible_rect = visible_content_rect(constants, layer);
+            for text in layer
+                .texts
+                .iter()
+                .filter(|text| rects_overlap(text.bounds(), visible_rect))
+            {
+                let Some((font_name, font, synthesis)) = self.text_font(&layer.font_name, text)
+                else {
+                    continue;
+                };
+                let font_ref = font.as_ref().unwrap();
+                let text = self.laid_out_text(font_ref, text);
+                let placed = self.place_glyphs(constants, &font_name, font_ref, synthesis, &text);
+                for glyph in placed.glyphs {
+                    let PlacedGlyph::Font { key, .. } = glyph else {
+                        continue;
+                    };
+                    if self.glyph_lookup.contains_key(&key) || self.blank_glyphs.contains(&key) {
+                        continue;
+                    }
+                    if let Some(rasterizer) = &mut self.rasterizer {
+                        rasterizer.request(&key, &font);
                     }
-                });
+                }
+            }
+        }
+    }
 
-                glyphs
-            })
-            .clone();
+    fn shape_and_rasterize_text(
+        &mut self,
+        queue: &Queue,
+        constants: &ShaderConstants,
+        font_name: &str,
+        font: &Font,
+        synthesis: Synthesis,
+        text: &Text,
+    ) -> Vec<InstancedGlyph> {
+        let font_ref = font.as_ref().unwrap();
+        let PlacedText {
+            glyphs,
+            cell_height,
+            ascent,
+            fade,
+            scale,
+        } = self.place_glyphs(constants, font_name, font_ref, synthesis, text);
+        let overrides: HashMap<_, _> = text
+            .glyph_overrides
+            .iter()
+            .map(|glyph_override| (glyph_override.glyph, glyph_override))
+            .collect();
 
-        let mut current_x = 0.;
         glyphs
-            .iter()
-            .filter_map(|glyph| {
-                let instance = self.prepare_glyph(
-                    queue,
-                    font_name,
-                    font_ref,
-                    glyph.id,
-                    text.bottom_left + vec2(current_x + glyph.x, -glyph.y),
-                    text.size,
-                    text.color,
-                );
-                current_x += glyph.advance;
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, glyph)| {
+                // Synthetic glyphs are always drawn at the zoom
+                let (instance, scale) = match glyph {
+                    PlacedGlyph::Synthetic {
+                        name,
+                        character,
+                        rasterize,
+                        cell_width,
+                        bottom_left,
+                    } => {
+                        let (placement, allocation_rectangle) =
+                            self.prepare_synthetic_glyph(queue, name, || {
+                                rasterize(character, cell_width, cell_height, ascent)
+                            })?;
+                        let instance = glyph_instance(
+                            bottom_left,
+                            placement,
+                            allocation_rectangle,
+                            text.color,
+                            constants.surface_rotation(),
+                        );
+                        (instance, 1.0)
+                    }
+                    PlacedGlyph::Font { key, bottom_left } => {
+                        let instance = self.prepare_glyph(
+                            queue,
+                            font,
+                            key,
+                            bottom_left,
+                            text.color,
+                            constants.surface_rotation(),
+                        )?;
+                        (instance, scale)
+                    }
+                };
+                Some((index, instance, scale))
+            })
+            .map(|(index, mut instance, scale)| {
+                // The shader fades along the text relative to the left of
+                // the glyph, in the pixels of the atlas
+                if let Some(fade) = fade {
+                    let origin = constants.to_surface(text.bottom_left) / scale;
+                    let offset = (instance.bottom_left - origin).dot(constants.surface_rotation());
+                    instance.fade = fade * constants.camera_zoom / scale - Vec2::splat(offset);
+                }
+                if scale != 1.0 {
+                    scale_glyph_instance(&mut instance, scale, constants.surface_rotation());
+                }
+                // Overrides apply after the fade, so that moved glyphs keep
+                // their opacity
+                if let Some(glyph_override) = overrides.get(&index) {
+                    instance.bottom_left += constants.to_surface(glyph_override.offset)
+                        - constants.to_surface(Vec2::ZERO);
+                    instance.transform = vec2(
+                        instance.transform.x * glyph_override.scale,
+                        glyph_override.rotation,
+                    );
+                    if let Some(color) = glyph_override.color {
+                        instance.color = color;
+                    }
+                }
                 instance
             })
             .collect()
@@ -183,18 +812,22 @@ impl GlyphState {
 }
 
 impl Drawable for GlyphState {
-    fn new(
-        Renderer {
+    fn new(renderer: &Renderer) -> Self {
+        let Renderer {
             device,
-            shader,
-            format,
-            universal_bind_group_layout,
+            assets,
+            image_atlas,
             ..
-        }: &Renderer,
-    ) -> Self {
+        } = renderer;
         let buffer = device.create_buffer(&BufferDescriptor {
             label: Some("Glyph buffer"),
-            size: std::mem::size_of::<InstancedGlyph>() as u64 * 100000,
+            size: std::mem::size_of::<GpuGlyph>() as u64 * 100000,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let paint_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Text paint buffer"),
+            size: (std::mem::size_of::<InstancedTextPaint>() * MAX_TEXT_PAINTS) as u64,
             usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -214,30 +847,52 @@ impl Drawable for GlyphState {
             view_formats: &[],
         });
 
-        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: Some("Glyph bind group layout"),
-            entries: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
+        let layout_entries = [
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
                 },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Texture {
-                        sample_type: TextureSampleType::Float { filterable: true },
-                        view_dimension: TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
                 },
-            ],
+                count: None,
+            },
+            // The vertex shader extends outlined glyphs by their outline
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 3,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Glyph bind group layout"),
+            entries: &layout_entries,
         });
 
         let atlas_texture_view = atlas_texture.create_view(&TextureViewDescriptor::default());
@@ -254,95 +909,488 @@ impl Drawable for GlyphState {
                     binding: 1,
                     resource: BindingResource::TextureView(&atlas_texture_view),
                 },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: paint_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(image_atlas.lock().unwrap().view()),
+                },
             ],
         });
 
-        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Glyph Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout, &universal_bind_group_layout],
-            push_constant_ranges: &[PushConstantRange {
-                stages: ShaderStages::all(),
-                range: 0..std::mem::size_of::<ShaderConstants>() as u32,
-            }],
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &ENTRY_POINTS,
+            bind_group_layouts: &[&layout_entries, &renderer.universal_layout_entries()],
+            vertex_buffers: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Glyph Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: VertexState {
-                module: &shader,
-                entry_point: "glyph::glyph_vertex",
-                buffers: &[],
-            },
-            fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: "glyph::glyph_fragment",
-                targets: &[Some(ColorTargetState {
-                    format: *format,
-                    blend: Some(BlendState::ALPHA_BLENDING),
-                    write_mask: ColorWrites::ALL,
-                })],
-            }),
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: None,
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: MultisampleState {
-                count: 4,
-                ..Default::default()
-            },
-            multiview: None,
-        });
+        let render_pipeline = create_pipeline(renderer, &bind_group_layout);
 
         Self {
             buffer,
+            paint_buffer,
             atlas_texture,
+            bind_group_layout,
             bind_group,
             render_pipeline,
+            decorations: DecorationPipeline::new(renderer),
+            image_atlas: image_atlas.clone(),
 
             scale_context: ScaleContext::new(),
             shaping_context: ShapeContext::new(),
             atlas_allocator: AtlasAllocator::new(size2(ATLAS_SIZE.x as i32, ATLAS_SIZE.y as i32)),
             glyph_lookup: HashMap::new(),
-            shaped_text_lookup: HashMap::new(),
+            blank_glyphs: HashSet::new(),
+            shaping_cache: ShapingCache::new(DEFAULT_SHAPING_CACHE_CAPACITY),
+            rasterizer: None,
+            rasterizer_threads: default_rasterizer_threads(),
+
+            assets: assets.clone(),
+            fonts: HashMap::new(),
+            font_loader: None,
+
+            synthetic_box_drawing: false,
+            missing_glyph_boxes: true,
+            text_rendering: TextRendering::default(),
+            font_synthesis: true,
+            zoom_policy: GlyphZoomPolicy::default(),
+            zoom: ZoomRasterization::default(),
+
+            frame: 0,
+            evictions: 0,
         }
     }
 
+    fn name(&self) -> &'static str {
+        "glyph"
+    }
+
+    // Shapes every text which isn't cached yet up front, in parallel when the
+    // rayon feature is enabled. With background rasterization the missing
+    // glyphs are queued here too, otherwise rasterizing into the atlas stays
+    // in draw.
+    fn prepare(&mut self, queue: &Queue, constants: &ShaderConstants, layers: &[&Layer]) {
+        self.add_loaded_fonts();
+        self.upload_finished_glyphs(queue);
+        self.zoom.update(self.zoom_policy, constants.camera_zoom);
+
+        // Bold and italic texts can use another face than the rest of the
+        // layer
+        let mut texts = Vec::new();
+        for layer in layers {
+            let visible_rect = visible_content_rect(constants, layer);
+            for text in layer
+                .texts
+                .iter()
+                .filter(|text| rects_overlap(text.bounds(), visible_rect))
+            {
+                if let Some((_, font, _)) = self.text_font(&layer.font_name, text) {
+                    texts.push((font, text));
+                }
+            }
+        }
+
+        self.shaping_cache.prepare(
+            texts
+                .iter()
+                .map(|(font, text)| (font.as_ref().unwrap(), *text)),
+        );
+        self.request_glyphs(constants, layers);
+    }
+
+    // The conversion is the identity with unpacked instances
+    #[cfg_attr(feature = "f32-instances", allow(clippy::useless_conversion))]
     fn draw<'b, 'a: 'b>(
         &'a mut self,
-        queue: &Queue,
+        uploader: &mut Uploader,
         render_pass: &mut RenderPass<'b>,
         constants: ShaderConstants,
         universal_bind_group: &'a BindGroup,
         layer: &Layer,
     ) {
-        let font = Font::from_name(&layer.font_name).unwrap();
-        let font_ref = font.as_ref().unwrap();
+        let queue = uploader.queue();
 
-        let glyphs: Vec<_> = layer
+        let visible_rect = visible_content_rect(&constants, layer);
+        let mut glyphs: Vec<GpuGlyph> = Vec::new();
+        let mut paints = Vec::new();
+        // Strikeouts go after the underlines and overlines, so that they are
+        // drawn above the glyphs and the others beneath. Backgrounds go first,
+        // beneath everything else.
+        let mut backgrounds = Vec::new();
+        let mut decorations = Vec::new();
+        let mut strikeouts = Vec::new();
+        let y_direction = constants.y_direction;
+        for text in layer
             .texts
             .iter()
-            .map(|text| {
-                self.shape_and_rasterize_text(queue, &layer.font_name, font_ref, &text)
-                    .into_iter()
-            })
-            .flatten()
-            .collect();
+            .filter(|text| rects_overlap(text.bounds(), visible_rect))
+        {
+            let Some((font_name, font, synthesis)) = self.text_font(&layer.font_name, text) else {
+                continue;
+            };
+            let font_ref = font.as_ref().unwrap();
+            let text = self.laid_out_text(font_ref, text);
+            let text = text.as_ref();
+            // Glyphs refer to their paint by one more than its index
+            let mut paint = 0;
+            if (text.paint.is_some() || text.outline.is_some()) && paints.len() < MAX_TEXT_PAINTS {
+                paints.push(self.text_paint(queue, font_ref, text, &constants));
+                paint = paints.len() as u32;
+            }
+            glyphs.extend(
+                self.shape_and_rasterize_text(
+                    queue, &constants, &font_name, &font, synthesis, text,
+                )
+                .into_iter()
+                .map(|glyph| GpuGlyph::from(InstancedGlyph { paint, ..glyph })),
+            );
+            if text.underline.is_none()
+                && text.strikeout.is_none()
+                && text.overline.is_none()
+                && text.background.is_none()
+            {
+                continue;
+            }
+
+            let metrics = TextMetrics {
+                width: self.visible_width(font_ref, text),
+                ..TextMetrics::new(font_ref, text.size)
+            };
+            if let Some(background) = &text.background {
+                backgrounds.push(text_background(text, background, &metrics, y_direction));
+            }
+            if let Some(underline) = &text.underline {
+                let line = DecorationLine::Underline;
+                decorations.push(text_decoration(
+                    text,
+                    underline,
+                    line,
+                    &metrics,
+                    y_direction,
+                ));
+            }
+            if let Some(overline) = &text.overline {
+                let line = DecorationLine::Overline;
+                decorations.push(text_decoration(text, overline, line, &metrics, y_direction));
+            }
+            if let Some(strikeout) = &text.strikeout {
+                let line = DecorationLine::Strikeout;
+                strikeouts.push(text_decoration(
+                    text,
+                    strikeout,
+                    line,
+                    &metrics,
+                    y_direction,
+                ));
+            }
+        }
+        decorations.splice(0..0, backgrounds);
+        let beneath = 0..decorations.len() as u32;
+        decorations.extend(strikeouts);
+        let above = beneath.end..decorations.len() as u32;
+        self.decorations.upload(uploader, &decorations);
+
+        // Underlines are drawn beneath the glyphs, so descenders cross them
+        self.decorations
+            .draw(render_pass, constants, universal_bind_group, beneath);
 
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_push_constants(ShaderStages::all(), 0, bytemuck::cast_slice(&[constants]));
 
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&glyphs[..]));
+        uploader.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&glyphs[..]));
+        if !paints.is_empty() {
+            uploader.write_buffer(&self.paint_buffer, 0, bytemuck::cast_slice(&paints[..]));
+        }
         render_pass.set_bind_group(0, &self.bind_group, &[]);
         render_pass.set_bind_group(1, &universal_bind_group, &[]);
         render_pass.draw(0..6, 0..glyphs.len() as u32);
+
+        self.decorations
+            .draw(render_pass, constants, universal_bind_group, above);
+    }
+
+    fn memory_usage(&self, report: &mut MemoryReport) {
+        report.buffers.add_buffer(&self.buffer);
+        report.buffers.add_buffer(&self.paint_buffer);
+        report.buffers.add_buffer(&self.decorations.buffer);
+        report.textures.add_texture(&self.atlas_texture);
+        report.glyph_atlas.allocations += self.glyph_lookup.len();
+        report.glyph_atlas.used_bytes += self.atlas_allocator.allocated_space() as u64 * 4;
+        report.glyph_atlas.capacity_bytes += ATLAS_SIZE.x as u64 * ATLAS_SIZE.y as u64 * 4;
+        report.glyph_atlas.evictions += self.evictions;
+    }
+
+    fn trim(&mut self, budget: &MemoryBudget) {
+        self.frame += 1;
+        self.zoom.begin_frame();
+        self.shaping_cache.trim();
+        let Some(budget) = budget.glyph_atlas_bytes else {
+            return;
+        };
+
+        let used_bytes = self.atlas_allocator.allocated_space() as u64 * 4;
+        let entries = self.glyph_lookup.iter().map(|(key, (_, id, last_used))| {
+            let bytes = self.atlas_allocator.get(*id).area() as u64 * 4;
+            (key.clone(), bytes, *last_used)
+        });
+        let evicted = entries_over_budget(entries, used_bytes, budget);
+        if !evicted.is_empty() {
+            debug!(
+                count = evicted.len(),
+                budget, "Evicting glyphs over the budget"
+            );
+        }
+        for key in evicted {
+            if let Some((_, id, _)) = self.glyph_lookup.remove(&key) {
+                self.atlas_allocator.deallocate(id);
+                self.evictions += 1;
+            }
+        }
+
+        // Start over from a fresh allocator so that the packing doesn't
+        // depend on what was evicted
+        if self.glyph_lookup.is_empty() {
+            self.atlas_allocator.clear();
+        }
+    }
+
+    fn recreate_pipelines(&mut self, renderer: &Renderer) -> bool {
+        self.render_pipeline = create_pipeline(renderer, &self.bind_group_layout);
+        self.decorations = DecorationPipeline::new(renderer);
+        true
+    }
+}
+
+fn create_pipeline(renderer: &Renderer, bind_group_layout: &BindGroupLayout) -> RenderPipeline {
+    let Renderer {
+        device,
+        shader,
+        format,
+        universal_bind_group_layout,
+        ..
+    } = renderer;
+    let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Glyph Pipeline Layout"),
+        bind_group_layouts: &[bind_group_layout, universal_bind_group_layout],
+        push_constant_ranges: &[PushConstantRange {
+            stages: ShaderStages::all(),
+            range: 0..std::mem::size_of::<ShaderConstants>() as u32,
+        }],
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Glyph Pipeline"),
+        layout: Some(&render_pipeline_layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: ENTRY_POINTS[0],
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: ENTRY_POINTS[1],
+            targets: &[Some(ColorTargetState {
+                format: *format,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: MultisampleState {
+            count: renderer.sample_count(),
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+pub(crate) fn shape_text(shaper: ShaperBuilder, font_ref: FontRef, text: &str) -> Vec<Glyph> {
+    let mut shaper = shaper.build();
+    shaper.add_str(text);
+
+    // The glyphs of single character clusters keep the character in their
+    // user data, which selects the synthetic box drawing and missing glyphs
+    let mut glyphs = Vec::new();
+    let space = font_ref.charmap().map(' ');
+    shaper.shape_with(|cluster| {
+        let source = &text[cluster.source.to_range()];
+        let mut characters = source.chars();
+        let character = match (characters.next(), characters.next()) {
+            (Some(character), None) => character as u32,
+            _ => 0,
+        };
+        // The shaper leaves out tabs, so they get a blank glyph which is
+        // given an advance by expand_tabs
+        if cluster.glyphs.is_empty() && source == "\t" {
+            glyphs.push(Glyph {
+                id: space,
+                data: character,
+                ..Default::default()
+            });
+        }
+        for glyph in cluster.glyphs {
+            glyphs.push(Glyph {
+                data: character,
+                ..*glyph
+            });
+        }
+    });
+    glyphs
+}
+
+pub(crate) fn cell_clusters(
+    context: &mut ShapeContext,
+    font_ref: FontRef,
+    text: &str,
+    size: f32,
+) -> Vec<CellCluster> {
+    let mut shaper = context.builder(font_ref).size(size).build();
+    shaper.add_str(text);
+
+    // Byte offset and cell of the start of each grapheme, followed by the end
+    // of the text
+    let mut grapheme_cells = Vec::new();
+    let mut cell = 0;
+    for (offset, grapheme) in text.grapheme_indices(true) {
+        grapheme_cells.push((offset, cell));
+        cell += grapheme_width(grapheme);
     }
+    grapheme_cells.push((text.len(), cell));
+    let cell_at = |offset: usize| {
+        grapheme_cells
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= offset)
+            .map_or(0, |(_, cell)| *cell)
+    };
+
+    let mut clusters: Vec<CellCluster> = Vec::new();
+    shaper.shape_with(|cluster| {
+        let source = cluster.source.to_range();
+        clusters.push(CellCluster {
+            cells: cell_at(source.start)..cell_at(source.end),
+            source,
+            glyphs: cluster.glyphs.iter().map(|glyph| glyph.id).collect(),
+            advance: cluster.glyphs.iter().map(|glyph| glyph.advance).sum(),
+        });
+    });
+    clusters
+}
+
+fn glyph_instance(
+    bottom_left: Vec2,
+    placement: Placement,
+    allocation_rectangle: Rectangle,
+    color: Vec4,
+    rotation: Vec2,
+) -> InstancedGlyph {
+    InstancedGlyph {
+        bottom_left: bottom_left.floor()
+            + vec2(
+                placement.left as f32,
+                placement.height as f32 - placement.top as f32,
+            )
+            .rotate(rotation),
+        atlas_top_left: vec2(
+            allocation_rectangle.min.x as f32,
+            allocation_rectangle.min.y as f32,
+        ),
+        atlas_size: vec2(placement.width as f32, placement.height as f32),
+        fade: Vec2::ZERO,
+        color,
+        paint: 0,
+        _padding: 0,
+        transform: vec2(1.0, 0.0),
+    }
+}
+
+// Moves a glyph placed in the pixels of another zoom to the surface. The
+// shader scales the glyphs around their center, so the bottom left is moved
+// to keep the scaled glyph where the scaled placement puts it.
+pub(crate) fn scale_glyph_instance(instance: &mut InstancedGlyph, scale: f32, rotation: Vec2) {
+    let center = vec2(0.5, -0.5) * instance.atlas_size;
+    instance.bottom_left = instance.bottom_left * scale - (center * (1.0 - scale)).rotate(rotation);
+    instance.transform.x *= scale;
+}
+
+// Scene rect of the line of a laid out text, from the descent to the ascent
+// of the font
+fn line_bounds(text: &Text, metrics: &TextMetrics, y_direction: f32) -> Vec4 {
+    let above = if y_direction < 0.0 {
+        metrics.descent
+    } else {
+        metrics.ascent
+    };
+    vec4(
+        text.bottom_left.x,
+        text.bottom_left.y - above,
+        metrics.width,
+        metrics.ascent + metrics.descent,
+    )
+}
+
+// Fonts are looked up by name in the asset source first and then in the
+// system fonts
+fn load_font(assets: &SharedAssets, font_name: &str) -> Option<Font> {
+    if let Some(data) = assets.load(font_name) {
+        return Some(Font::from_bytes(data));
+    }
+    match parse_styled_font_name(font_name) {
+        (family, false, false) => Font::from_name(family),
+        // Falls back to the regular face, which is then synthesized
+        (family, bold, italic) => {
+            Font::from_name_and_style(family, bold, italic).or_else(|| load_font(assets, family))
+        }
+    }
+}
+
+// Renders the glyph at the size and quantized subpixel offset of the key
+fn rasterize_glyph(context: &mut ScaleContext, font_ref: FontRef, key: &GlyphKey) -> Option<Image> {
+    let size = key.size.into_inner();
+    let mut scaler = context
+        .builder(font_ref)
+        .size(size)
+        .hint(key.hinted)
+        .build();
+
+    // Synthetic bold widens the strokes by a 24th of the size like FreeType,
+    // the strength is applied to both sides of the outline. Synthetic oblique
+    // shears the outlines around the baseline. Color bitmaps are left as they
+    // are.
+    let embolden = if key.synthesis.embolden {
+        size / 48.0
+    } else {
+        0.0
+    };
+    let shear = key
+        .synthesis
+        .oblique
+        .then(|| Transform::skew(Angle::from_degrees(OBLIQUE_ANGLE), Angle::from_degrees(0.0)));
+
+    Render::new(&[
+        Source::ColorOutline(0),
+        Source::ColorBitmap(StrikeWith::BestFit),
+        Source::Outline,
+    ])
+    // Select a subpixel format
+    .format(Format::Subpixel)
+    // Apply the fractional offset
+    .offset(key.quantized_offset())
+    .embolden(embolden)
+    .transform(shear)
+    // Render the image
+    .render(&mut scaler, key.glyph)
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -354,6 +1402,8 @@ enum SubpixelOffset {
 }
 
 impl SubpixelOffset {
+    const ALL: [Self; 4] = [Self::Zero, Self::Quarter, Self::Half, Self::ThreeQuarters];
+
     fn quantize(value: f32) -> Self {
         let value = value.fract();
         if value < 0.125 {
@@ -379,6 +1429,50 @@ impl SubpixelOffset {
     }
 }
 
+// Slant of synthetic oblique glyphs in degrees
+const OBLIQUE_ANGLE: f32 = 12.0;
+
+// Length of the fade of overflowing text in multiples of the font size
+const FADE_LENGTH: f32 = 2.0;
+
+// Weight and slant faked by the rasterizer when the family has no bold or
+// italic face. The advances of the regular face are kept, so that synthetic
+// bold text still lines up with the regular text in a grid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct Synthesis {
+    embolden: bool,
+    oblique: bool,
+}
+
+// Where a glyph of a text run goes on the surface
+enum PlacedGlyph {
+    // Placed in the pixels of the zoom the glyph is rasterized at
+    Font {
+        key: GlyphKey,
+        bottom_left: Vec2,
+    },
+    // Drawn by the renderer into a cell, with the name identifying the image
+    Synthetic {
+        name: String,
+        character: char,
+        rasterize: RasterizeSynthetic,
+        cell_width: u32,
+        bottom_left: Vec2,
+    },
+}
+
+struct PlacedText {
+    glyphs: Vec<PlacedGlyph>,
+    // Height and ascent of the cells of the synthetic glyphs
+    cell_height: u32,
+    ascent: i32,
+    // Range along the text over which it fades out
+    fade: Option<Vec2>,
+    // Scale from the pixels the font glyphs are placed and rasterized in to
+    // the surface
+    scale: f32,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct GlyphKey {
     glyph: GlyphId,
@@ -386,10 +1480,12 @@ struct GlyphKey {
     size: OrderedFloat<f32>,
     x_offset: SubpixelOffset,
     y_offset: SubpixelOffset,
+    hinted: bool,
+    synthesis: Synthesis,
 }
 
 impl GlyphKey {
-    fn new(font_name: &str, glyph: GlyphId, size: f32, offset: Vec2) -> Self {
+    fn new(font_name: &str, glyph: GlyphId, size: f32, offset: Vec2, hinted: bool) -> Self {
         let size = size.into();
         let x_offset = SubpixelOffset::quantize(offset.x);
         let y_offset = SubpixelOffset::quantize(offset.y);
@@ -399,29 +1495,17 @@ impl GlyphKey {
             size,
             x_offset,
             y_offset,
+            hinted,
+            synthesis: Synthesis::default(),
         }
     }
 
-    fn quantized_offset(&self) -> Vector {
-        Vector::new(self.x_offset.to_f32(), self.y_offset.to_f32())
+    fn with_synthesis(mut self, synthesis: Synthesis) -> Self {
+        self.synthesis = synthesis;
+        self
     }
-}
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct ShapeKey {
-    text: Arc<str>,
-    size: OrderedFloat<f32>,
-    font_cache_key: CacheKey,
-}
-
-impl ShapeKey {
-    fn new(text: Arc<str>, font_ref: FontRef, size: f32) -> Self {
-        let font_cache_key = font_ref.key;
-        let size = size.into();
-        Self {
-            text,
-            size,
-            font_cache_key,
-        }
+    fn quantized_offset(&self) -> Vector {
+        Vector::new(self.x_offset.to_f32(), self.y_offset.to_f32())
     }
 }
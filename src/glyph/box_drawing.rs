@@ -0,0 +1,471 @@
+use glam::{vec2, Vec2};
+use swash::{
+    scale::{
+        image::{Content, Image},
+        Source,
+    },
+    zeno::Placement,
+};
+
+// Box drawing, block elements and the Powerline separators, which are drawn
+// as geometry filling the whole cell instead of with the font, so that they
+// connect to the neighbouring cells without gaps
+pub(crate) fn is_box_drawing(character: char) -> bool {
+    matches!(character, '\u{2500}'..='\u{259F}' | '\u{E0B0}'..='\u{E0B3}')
+}
+
+// Weights of the lines from the center to the top, right, bottom and left
+// edges of the cell, one nibble each with 0 for none, 1 for light, 2 for
+// heavy and 3 for double. Zero for the characters drawn some other way.
+#[rustfmt::skip]
+const LINE_ARMS: [u16; 128] = [
+    0x0101, 0x0202, 0x1010, 0x2020, 0, 0, 0, 0, 0, 0, 0, 0, 0x0110, 0x0210, 0x0120, 0x0220,
+    0x0011, 0x0012, 0x0021, 0x0022, 0x1100, 0x1200, 0x2100, 0x2200,
+    0x1001, 0x1002, 0x2001, 0x2002, 0x1110, 0x1210, 0x2110, 0x1120,
+    0x2120, 0x2210, 0x1220, 0x2220, 0x1011, 0x1012, 0x2011, 0x1021,
+    0x2021, 0x2012, 0x1022, 0x2022, 0x0111, 0x0112, 0x0211, 0x0212,
+    0x0121, 0x0122, 0x0221, 0x0222, 0x1101, 0x1102, 0x1201, 0x1202,
+    0x2101, 0x2102, 0x2201, 0x2202, 0x1111, 0x1112, 0x1211, 0x1212,
+    0x2111, 0x1121, 0x2121, 0x2112, 0x2211, 0x1122, 0x1221, 0x2212,
+    0x1222, 0x2122, 0x2221, 0x2222, 0, 0, 0, 0,
+    0x0303, 0x3030, 0x0310, 0x0130, 0x0330, 0x0013, 0x0031, 0x0033,
+    0x1300, 0x3100, 0x3300, 0x1003, 0x3001, 0x3003, 0x1310, 0x3130,
+    0x3330, 0x1013, 0x3031, 0x3033, 0x0313, 0x0131, 0x0333, 0x1303,
+    0x3101, 0x3303, 0x1313, 0x3131, 0x3333, 0, 0, 0,
+    0, 0, 0, 0, 0x0001, 0x1000, 0x0100, 0x0010,
+    0x0002, 0x2000, 0x0200, 0x0020, 0x0102, 0x1020, 0x0201, 0x2010,
+];
+
+const NONE: u16 = 0;
+const LIGHT: u16 = 1;
+const HEAVY: u16 = 2;
+const DOUBLE: u16 = 3;
+
+// Coverage of each pixel of the cell
+pub(super) struct Canvas {
+    width: usize,
+    height: usize,
+    coverage: Vec<f32>,
+}
+
+impl Canvas {
+    pub(super) fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: width as usize,
+            height: height as usize,
+            coverage: vec![0.0; width as usize * height as usize],
+        }
+    }
+
+    fn cover(&mut self, x: usize, y: usize, coverage: f32) {
+        let pixel = &mut self.coverage[y * self.width + x];
+        *pixel = pixel.max(coverage);
+    }
+
+    // Covers each pixel by the area of it inside the rect
+    pub(super) fn rect(&mut self, left: f32, top: f32, right: f32, bottom: f32) {
+        let left = left.max(0.0);
+        let top = top.max(0.0);
+        let right = right.min(self.width as f32);
+        let bottom = bottom.min(self.height as f32);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        for y in top.floor() as usize..bottom.ceil() as usize {
+            let y_coverage = (bottom.min(y as f32 + 1.0) - top.max(y as f32)).max(0.0);
+            for x in left.floor() as usize..right.ceil() as usize {
+                let x_coverage = (right.min(x as f32 + 1.0) - left.max(x as f32)).max(0.0);
+                self.cover(x, y, x_coverage * y_coverage);
+            }
+        }
+    }
+
+    // Covers each pixel by the fraction of 4x4 samples inside the shape
+    fn shape(&mut self, inside: impl Fn(Vec2) -> bool) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let samples = (0..16)
+                    .filter(|sample| {
+                        let offset = vec2((sample % 4) as f32, (sample / 4) as f32);
+                        inside(vec2(x as f32, y as f32) + (offset + 0.5) / 4.0)
+                    })
+                    .count();
+                if samples > 0 {
+                    self.cover(x, y, samples as f32 / 16.0);
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self, coverage: f32) {
+        self.coverage.fill(coverage);
+    }
+
+    // A mask in the subpixel format the glyph shader expects, with the same
+    // coverage in every channel
+    pub(super) fn into_image(self, ascent: i32) -> Image {
+        let mut image = Image::new();
+        image.source = Source::Outline;
+        image.content = Content::SubpixelMask;
+        image.placement = Placement {
+            left: 0,
+            top: ascent,
+            width: self.width as u32,
+            height: self.height as u32,
+        };
+        image.data = self
+            .coverage
+            .iter()
+            .flat_map(|coverage| [(coverage.clamp(0.0, 1.0) * 255.0).round() as u8; 4])
+            .collect();
+        image
+    }
+}
+
+// Start of a line of the thickness centered in the extent, snapped to pixels
+fn centered(extent: f32, thickness: f32) -> f32 {
+    ((extent - thickness) / 2.0).floor()
+}
+
+fn distance_to_segment(position: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let segment = end - start;
+    let t = ((position - start).dot(segment) / segment.length_squared()).clamp(0.0, 1.0);
+    position.distance(start + segment * t)
+}
+
+// Rasterizes the character into a cell of the size. The top of the cell is
+// ascent pixels above the baseline.
+pub(crate) fn rasterize_box_drawing(
+    character: char,
+    width: u32,
+    height: u32,
+    ascent: i32,
+) -> Image {
+    let mut canvas = Canvas::new(width, height);
+    let w = width as f32;
+    let h = height as f32;
+    let light = (w.min(h) / 8.0).round().max(1.0);
+
+    match character {
+        // Dashed lines with 3, 3, 4 and 4 dashes, and then 2
+        '\u{2504}'..='\u{250B}' | '\u{254C}'..='\u{254F}' => {
+            let index = character as u32 - 0x2504;
+            let (dashes, heavy, vertical) = if character >= '\u{254C}' {
+                let index = character as u32 - 0x254C;
+                (2, index % 2 == 1, index >= 2)
+            } else {
+                (
+                    if index < 4 { 3 } else { 4 },
+                    index % 2 == 1,
+                    index % 4 >= 2,
+                )
+            };
+            let thickness = if heavy { light * 2.0 } else { light };
+            let length = if vertical { h } else { w };
+            let period = length / dashes as f32;
+            let gap = (period / 4.0).round().max(1.0);
+            for dash in 0..dashes {
+                let start = (dash as f32 * period + gap / 2.0).round();
+                let end = ((dash + 1) as f32 * period - gap / 2.0).round();
+                if vertical {
+                    let x = centered(w, thickness);
+                    canvas.rect(x, start, x + thickness, end);
+                } else {
+                    let y = centered(h, thickness);
+                    canvas.rect(start, y, end, y + thickness);
+                }
+            }
+        }
+        // Rounded corners, given by the direction of the two arms
+        '\u{256D}'..='\u{2570}' => {
+            let (x_direction, y_direction) = match character {
+                '\u{256D}' => (1.0, 1.0),
+                '\u{256E}' => (-1.0, 1.0),
+                '\u{256F}' => (-1.0, -1.0),
+                _ => (1.0, -1.0),
+            };
+            let left = centered(w, light);
+            let top = centered(h, light);
+            let center_line = vec2(left, top) + light / 2.0;
+            let radius = (w / 2.0).min(h / 2.0);
+            let center = center_line + vec2(x_direction, y_direction) * radius;
+            canvas.shape(|position| {
+                let offset = position - center;
+                offset.x * x_direction <= 0.0
+                    && offset.y * y_direction <= 0.0
+                    && (offset.length() - radius).abs() <= light / 2.0
+            });
+            // Straight parts from the ends of the arc to the edges
+            if x_direction > 0.0 {
+                canvas.rect(center.x, top, w, top + light);
+            } else {
+                canvas.rect(0.0, top, center.x, top + light);
+            }
+            if y_direction > 0.0 {
+                canvas.rect(left, center.y, left + light, h);
+            } else {
+                canvas.rect(left, 0.0, left + light, center.y);
+            }
+        }
+        '\u{2571}'..='\u{2573}' => {
+            let rising = character != '\u{2572}';
+            let falling = character != '\u{2571}';
+            canvas.shape(|position| {
+                (rising && distance_to_segment(position, vec2(0.0, h), vec2(w, 0.0)) <= light / 2.0)
+                    || (falling
+                        && distance_to_segment(position, Vec2::ZERO, vec2(w, h)) <= light / 2.0)
+            });
+        }
+        '\u{2500}'..='\u{257F}' => {
+            let arms = LINE_ARMS[character as usize - 0x2500];
+            draw_lines(
+                &mut canvas,
+                arms >> 12,
+                (arms >> 8) & 0xF,
+                (arms >> 4) & 0xF,
+                arms & 0xF,
+                light,
+            );
+        }
+        // Upper half and lower eighths
+        '\u{2580}'..='\u{2588}' => {
+            if character == '\u{2580}' {
+                canvas.rect(0.0, 0.0, w, (h / 2.0).round());
+            } else {
+                let eighths = (character as u32 - 0x2580) as f32;
+                canvas.rect(0.0, (h * (1.0 - eighths / 8.0)).round(), w, h);
+            }
+        }
+        // Left eighths
+        '\u{2589}'..='\u{258F}' => {
+            let eighths = (0x2590 - character as u32) as f32;
+            canvas.rect(0.0, 0.0, (w * eighths / 8.0).round(), h);
+        }
+        '\u{2590}' => canvas.rect((w / 2.0).round(), 0.0, w, h),
+        '\u{2591}' => canvas.fill(0.25),
+        '\u{2592}' => canvas.fill(0.5),
+        '\u{2593}' => canvas.fill(0.75),
+        '\u{2594}' => canvas.rect(0.0, 0.0, w, (h / 8.0).round()),
+        '\u{2595}' => canvas.rect((w * 7.0 / 8.0).round(), 0.0, w, h),
+        // Quadrants
+        '\u{2596}'..='\u{259F}' => {
+            // Upper left, upper right, lower left and lower right bits
+            let quadrants = [4, 8, 1, 13, 9, 7, 11, 2, 6, 14][character as usize - 0x2596];
+            let x = (w / 2.0).round();
+            let y = (h / 2.0).round();
+            for (bit, (left, top, right, bottom)) in [
+                (0.0, 0.0, x, y),
+                (x, 0.0, w, y),
+                (0.0, y, x, h),
+                (x, y, w, h),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                if quadrants & (1 << bit) != 0 {
+                    canvas.rect(left, top, right, bottom);
+                }
+            }
+        }
+        // Powerline solid and outlined arrows pointing right and left
+        '\u{E0B0}'..='\u{E0B3}' => {
+            let pointing_right = character <= '\u{E0B1}';
+            let solid = character == '\u{E0B0}' || character == '\u{E0B2}';
+            let (base, tip) = if pointing_right { (0.0, w) } else { (w, 0.0) };
+            let top = vec2(base, 0.0);
+            let point = vec2(tip, h / 2.0);
+            let bottom = vec2(base, h);
+            canvas.shape(|position| {
+                if solid {
+                    let depth = (position.x - base).abs() / w;
+                    depth <= 1.0 - (position.y - h / 2.0).abs() / (h / 2.0)
+                } else {
+                    distance_to_segment(position, top, point)
+                        .min(distance_to_segment(position, point, bottom))
+                        <= light / 2.0
+                }
+            });
+        }
+        _ => {}
+    }
+
+    canvas.into_image(ascent)
+}
+
+// Draws the arms of the line character. Arms reach past the center to the
+// far side of the lines crossing them, so that the joints are filled.
+fn draw_lines(canvas: &mut Canvas, up: u16, right: u16, down: u16, left: u16, light: f32) {
+    let w = canvas.width as f32;
+    let h = canvas.height as f32;
+    let thickness = |weight| if weight == HEAVY { light * 2.0 } else { light };
+    let solid = |weight| weight == LIGHT || weight == HEAVY;
+    // Thickest solid line crossing each axis
+    let vertical = [up, down]
+        .into_iter()
+        .filter(|weight| solid(*weight))
+        .map(thickness)
+        .fold(0.0, f32::max);
+    let horizontal = [left, right]
+        .into_iter()
+        .filter(|weight| solid(*weight))
+        .map(thickness)
+        .fold(0.0, f32::max);
+
+    // The two lines of double arms are a line apart on each side of the
+    // center
+    let center_x = centered(w, light);
+    let center_y = centered(h, light);
+    let (left_line, right_line) = (center_x - light, center_x + light);
+    let (top_line, bottom_line) = (center_y - light, center_y + light);
+
+    let double_up = up == DOUBLE;
+    let double_down = down == DOUBLE;
+    let double_left = left == DOUBLE;
+    let double_right = right == DOUBLE;
+
+    // Inner ends of the top and bottom lines of a double horizontal arm.
+    // The outer line of a corner reaches to the far vertical line.
+    let horizontal_start = |towards_right: bool| -> (f32, f32) {
+        let (near, far) = if towards_right {
+            (right_line, left_line)
+        } else {
+            (left_line + light, right_line + light)
+        };
+        if vertical > 0.0 {
+            let start = centered(w, vertical);
+            let start = if towards_right {
+                start
+            } else {
+                start + vertical
+            };
+            (start, start)
+        } else if double_up && double_down {
+            (near, near)
+        } else if double_down {
+            (far, near)
+        } else if double_up {
+            (near, far)
+        } else if towards_right {
+            (center_x, center_x)
+        } else {
+            (center_x + light, center_x + light)
+        }
+    };
+    // Inner ends of the left and right lines of a double vertical arm
+    let vertical_start = |towards_bottom: bool| -> (f32, f32) {
+        let (near, far) = if towards_bottom {
+            (bottom_line, top_line)
+        } else {
+            (top_line + light, bottom_line + light)
+        };
+        if horizontal > 0.0 {
+            let start = centered(h, horizontal);
+            let start = if towards_bottom {
+                start
+            } else {
+                start + horizontal
+            };
+            (start, start)
+        } else if double_left && double_right {
+            (near, near)
+        } else if double_right {
+            (far, near)
+        } else if double_left {
+            (near, far)
+        } else if towards_bottom {
+            (center_y, center_y)
+        } else {
+            (center_y + light, center_y + light)
+        }
+    };
+
+    // Solid arms attach to the nearer line of a continuous double line and to
+    // the far one of a corner
+    let solid_horizontal_start = |towards_right: bool, weight: u16| -> f32 {
+        if vertical > 0.0 {
+            let start = centered(w, vertical);
+            return if towards_right {
+                start
+            } else {
+                start + vertical
+            };
+        }
+        let continuous = double_up && double_down;
+        let corner = double_up || double_down;
+        match (towards_right, continuous, corner) {
+            (true, true, _) => right_line,
+            (true, false, true) => left_line,
+            (false, true, _) => left_line + light,
+            (false, false, true) => right_line + light,
+            (true, false, false) => centered(w, thickness(weight)),
+            (false, false, false) => centered(w, thickness(weight)) + thickness(weight),
+        }
+    };
+    let solid_vertical_start = |towards_bottom: bool, weight: u16| -> f32 {
+        if horizontal > 0.0 {
+            let start = centered(h, horizontal);
+            return if towards_bottom {
+                start
+            } else {
+                start + horizontal
+            };
+        }
+        let continuous = double_left && double_right;
+        let corner = double_left || double_right;
+        match (towards_bottom, continuous, corner) {
+            (true, true, _) => bottom_line,
+            (true, false, true) => top_line,
+            (false, true, _) => top_line + light,
+            (false, false, true) => bottom_line + light,
+            (true, false, false) => centered(h, thickness(weight)),
+            (false, false, false) => centered(h, thickness(weight)) + thickness(weight),
+        }
+    };
+
+    if right == DOUBLE {
+        let (top, bottom) = horizontal_start(true);
+        canvas.rect(top, top_line, w, top_line + light);
+        canvas.rect(bottom, bottom_line, w, bottom_line + light);
+    } else if right != NONE {
+        let y = centered(h, thickness(right));
+        canvas.rect(
+            solid_horizontal_start(true, right),
+            y,
+            w,
+            y + thickness(right),
+        );
+    }
+
+    if left == DOUBLE {
+        let (top, bottom) = horizontal_start(false);
+        canvas.rect(0.0, top_line, top, top_line + light);
+        canvas.rect(0.0, bottom_line, bottom, bottom_line + light);
+    } else if left != NONE {
+        let y = centered(h, thickness(left));
+        canvas.rect(
+            0.0,
+            y,
+            solid_horizontal_start(false, left),
+            y + thickness(left),
+        );
+    }
+
+    if down == DOUBLE {
+        let (left, right) = vertical_start(true);
+        canvas.rect(left_line, left, left_line + light, h);
+        canvas.rect(right_line, right, right_line + light, h);
+    } else if down != NONE {
+        let x = centered(w, thickness(down));
+        canvas.rect(x, solid_vertical_start(true, down), x + thickness(down), h);
+    }
+
+    if up == DOUBLE {
+        let (left, right) = vertical_start(false);
+        canvas.rect(left_line, 0.0, left_line + light, left);
+        canvas.rect(right_line, 0.0, right_line + light, right);
+    } else if up != NONE {
+        let x = centered(w, thickness(up));
+        canvas.rect(x, 0.0, x + thickness(up), solid_vertical_start(false, up));
+    }
+}
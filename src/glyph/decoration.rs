@@ -0,0 +1,232 @@
+use std::ops::Range;
+
+use glam::vec2;
+use shader::{
+    InstancedDecoration, ShaderConstants, TEXT_BACKGROUND, UNDERLINE_DASHED, UNDERLINE_DOTTED,
+    UNDERLINE_DOUBLE, UNDERLINE_SINGLE, UNDERLINE_WAVY,
+};
+use wgpu::*;
+
+use super::TextMetrics;
+use crate::{
+    renderer::Renderer,
+    scene::{Text, TextBackground, Underline, UnderlineStyle},
+    shader_layout::PipelineInterface,
+    uploader::Uploader,
+};
+
+// Draws the backgrounds, underlines, strikeouts and overlines of the text runs
+// with the decoration shader, which evaluates every style as a distance field instead
+// of rasterizing it
+pub(crate) struct DecorationPipeline {
+    pub buffer: Buffer,
+    bind_group: BindGroup,
+    render_pipeline: RenderPipeline,
+}
+
+impl DecorationPipeline {
+    pub fn new(renderer: &Renderer) -> Self {
+        let Renderer {
+            device,
+            shader,
+            format,
+            universal_bind_group_layout,
+            ..
+        } = renderer;
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Decoration buffer"),
+            size: std::mem::size_of::<InstancedDecoration>() as u64 * 10000,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layout_entries = [BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Decoration bind group layout"),
+            entries: &layout_entries,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Decoration bind group"),
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &[
+                "decoration::decoration_vertex",
+                "decoration::decoration_fragment",
+            ],
+            bind_group_layouts: &[&layout_entries, &renderer.universal_layout_entries()],
+            vertex_buffers: &[],
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Decoration Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, universal_bind_group_layout],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::all(),
+                range: 0..std::mem::size_of::<ShaderConstants>() as u32,
+            }],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Decoration Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "decoration::decoration_vertex",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "decoration::decoration_fragment",
+                targets: &[Some(ColorTargetState {
+                    format: *format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: renderer.sample_count(),
+                ..Default::default()
+            },
+            multiview: None,
+        });
+
+        Self {
+            buffer,
+            bind_group,
+            render_pipeline,
+        }
+    }
+
+    // Uploads the decorations of the layer, which are then drawn in ranges
+    // beneath and above the glyphs
+    pub fn upload(&self, uploader: &mut Uploader, decorations: &[InstancedDecoration]) {
+        if !decorations.is_empty() {
+            uploader.write_buffer(&self.buffer, 0, bytemuck::cast_slice(decorations));
+        }
+    }
+
+    pub fn draw<'b, 'a: 'b>(
+        &'a self,
+        render_pass: &mut RenderPass<'b>,
+        constants: ShaderConstants,
+        universal_bind_group: &'a BindGroup,
+        instances: Range<u32>,
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_push_constants(ShaderStages::all(), 0, bytemuck::cast_slice(&[constants]));
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, universal_bind_group, &[]);
+        render_pass.draw(0..6, instances);
+    }
+}
+
+// Which of the lines of the text run a decoration is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DecorationLine {
+    Underline,
+    Strikeout,
+    Overline,
+}
+
+// Places the line along the text run, using the metrics of the font for the
+// unset thickness and offset. The offsets of the font are downwards, so they
+// are flipped when the y axis points up.
+pub(crate) fn text_decoration(
+    text: &Text,
+    decoration: &Underline,
+    line: DecorationLine,
+    metrics: &TextMetrics,
+    y_direction: f32,
+) -> InstancedDecoration {
+    let (default_thickness, default_offset) = match line {
+        DecorationLine::Underline => (metrics.underline_thickness, metrics.underline_offset),
+        DecorationLine::Strikeout => (metrics.strikeout_thickness, metrics.strikeout_offset),
+        DecorationLine::Overline => (metrics.underline_thickness, metrics.overline_offset),
+    };
+    let thickness = decoration.thickness.unwrap_or(default_thickness);
+    let offset = decoration.offset.unwrap_or(match line {
+        // Keep the center of the line where the font puts it, but make sure
+        // a thicker underline doesn't cross into the baseline
+        DecorationLine::Underline => default_offset.max(thickness / 2.0),
+        _ => default_offset,
+    });
+
+    let (style, height, amplitude, period) = match decoration.style {
+        UnderlineStyle::Single => (UNDERLINE_SINGLE, thickness, 0.0, 0.0),
+        UnderlineStyle::Double => (UNDERLINE_DOUBLE, thickness * 3.0, 0.0, 0.0),
+        UnderlineStyle::Dotted => (UNDERLINE_DOTTED, thickness, 0.0, 0.0),
+        UnderlineStyle::Dashed { period } => (UNDERLINE_DASHED, thickness, 0.0, period),
+        UnderlineStyle::Wavy { amplitude, period } => (
+            UNDERLINE_WAVY,
+            amplitude.abs() * 2.0 + thickness,
+            amplitude,
+            period,
+        ),
+    };
+
+    InstancedDecoration {
+        color: decoration.color.unwrap_or(text.color),
+        top_left: text.bottom_left + vec2(0.0, offset * y_direction - height / 2.0),
+        size: vec2(metrics.width, height),
+        thickness,
+        style,
+        amplitude,
+        period,
+    }
+}
+
+// Covers the shaped width of the run and the line height of the font,
+// extended by the padding
+pub(crate) fn text_background(
+    text: &Text,
+    background: &TextBackground,
+    metrics: &TextMetrics,
+    y_direction: f32,
+) -> InstancedDecoration {
+    let padding = background.padding;
+    // The edge of the background with the smaller y
+    let above = if y_direction < 0.0 {
+        metrics.descent
+    } else {
+        metrics.ascent
+    };
+    InstancedDecoration {
+        color: background.color,
+        top_left: text.bottom_left - vec2(padding.x, above + padding.y),
+        size: vec2(metrics.width, metrics.ascent + metrics.descent) + padding * 2.0,
+        thickness: 0.0,
+        style: TEXT_BACKGROUND,
+        amplitude: background.corner_radius,
+        period: 0.0,
+    }
+}
@@ -0,0 +1,125 @@
+use std::borrow::Cow;
+
+use glam::vec2;
+use swash::{shape::ShapeContext, FontRef};
+
+use super::cell_clusters;
+use crate::scene::{Text, TextOverflow};
+
+const ELLIPSIS: &str = "\u{2026}";
+
+// Resolves the fit and the overflow of the text, given its width when shaped
+// at its own size. Texts without either are returned as they are.
+pub(crate) fn layout_text<'a>(
+    context: &mut ShapeContext,
+    font_ref: FontRef,
+    text: &'a Text,
+    width: f32,
+) -> Cow<'a, Text> {
+    let (text, width) = fit_text(font_ref, text, width);
+    overflow_text(context, font_ref, text, width)
+}
+
+// Scales the text uniformly to fit the rect of its TextFit and centers it in
+// the rect. The advances scale linearly with the size, so the width measured
+// at the size of the text is enough to find the fitting size. Text which
+// still overflows is placed at the left edge of the rect and cut off at its
+// right edge. Returns the text and its width.
+fn fit_text<'a>(font_ref: FontRef, text: &'a Text, width: f32) -> (Cow<'a, Text>, f32) {
+    let Some(fit) = text.fit else {
+        return (Cow::Borrowed(text), width);
+    };
+    let metrics = font_ref.metrics(&[]).scale(text.size);
+    let height = metrics.ascent + metrics.descent;
+
+    // Largest size at which the text isn't wider than the rect
+    let width_limit = text.size * fit.size.x / width;
+    let mut size = width_limit.min(text.size * fit.size.y / height);
+    if let Some(max_size) = fit.max_size {
+        size = size.min(max_size);
+    }
+    if let Some(min_size) = fit.min_size {
+        size = size.max(min_size);
+    }
+    if !size.is_finite() || size <= 0.0 {
+        return (Cow::Borrowed(text), width);
+    }
+
+    let scale = size / text.size;
+    let mut fitted = Text {
+        size,
+        ..text.clone()
+    };
+    let fitted_width = width * scale;
+    if size > width_limit {
+        let max_width = text.max_width.map_or(fit.size.x, |max| max.min(fit.size.x));
+        fitted.max_width = Some(max_width);
+        if fit.ellipsis {
+            fitted.overflow = TextOverflow::Ellipsis;
+        }
+    }
+
+    let offset = vec2(
+        ((fit.size.x - fitted_width) / 2.0).max(0.0),
+        (fit.size.y - height * scale) / 2.0 + metrics.ascent * scale,
+    );
+    fitted.bottom_left = fit.top_left + offset;
+    (Cow::Owned(fitted), fitted_width)
+}
+
+// Cuts off text wider than its max width, followed by the marker of the
+// overflow. Faded text is cut off while drawing instead, as the fade is
+// applied by the text shader.
+fn overflow_text<'a>(
+    context: &mut ShapeContext,
+    font_ref: FontRef,
+    text: Cow<'a, Text>,
+    width: f32,
+) -> Cow<'a, Text> {
+    let Some(max_width) = text.max_width else {
+        return text;
+    };
+    if width <= max_width {
+        return text;
+    }
+    let marker = match text.overflow {
+        TextOverflow::Clip => String::new(),
+        TextOverflow::Ellipsis => ELLIPSIS.to_string(),
+        TextOverflow::Custom(marker) => marker.to_string(),
+        TextOverflow::Fade => return text,
+    };
+    let truncated = truncate(context, font_ref, &text.text, text.size, max_width, &marker);
+    Cow::Owned(Text {
+        text: truncated,
+        ..text.into_owned()
+    })
+}
+
+// Cuts the text after the last cluster which still fits in the width together
+// with the marker, dropping the whitespace before the marker
+fn truncate(
+    context: &mut ShapeContext,
+    font_ref: FontRef,
+    text: &str,
+    size: f32,
+    max_width: f32,
+    marker: &str,
+) -> String {
+    let marker_width: f32 = cell_clusters(context, font_ref, marker, size)
+        .iter()
+        .map(|cluster| cluster.advance)
+        .sum();
+
+    let mut end = 0;
+    let mut width = 0.0;
+    for cluster in cell_clusters(context, font_ref, text, size) {
+        width += cluster.advance;
+        if width + marker_width > max_width {
+            break;
+        }
+        if !text[cluster.source.clone()].trim().is_empty() {
+            end = cluster.source.end;
+        }
+    }
+    format!("{}{marker}", &text[..end])
+}
@@ -0,0 +1,79 @@
+use swash::{tag_from_bytes, FontRef, TableProvider};
+
+// Measurements of a text run in the units of the scene, scaled to the size
+// of the text. The line metrics come from the hhea, OS/2 and post tables of
+// the font, with fallbacks for fonts which leave them out.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextMetrics {
+    // Sum of the advances of the shaped glyphs
+    pub width: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    // Recommended gap between lines
+    pub leading: f32,
+    pub x_height: f32,
+    pub cap_height: f32,
+    // Distances from the baseline down to the center of the decoration
+    // lines, negative above the baseline
+    pub underline_offset: f32,
+    pub underline_thickness: f32,
+    pub strikeout_offset: f32,
+    pub strikeout_thickness: f32,
+    pub overline_offset: f32,
+}
+
+impl TextMetrics {
+    // Metrics of the font at the size, without a width
+    pub(crate) fn new(font_ref: FontRef, size: f32) -> Self {
+        let metrics = font_ref.metrics(&[]);
+        let scale = size / metrics.units_per_em.max(1) as f32;
+        let metrics = metrics.scale(size);
+
+        // Swash reports the underline thickness of the post table as the
+        // stroke size, so the strikeout size is read from OS/2 directly
+        let strikeout_size = font_ref
+            .table_by_tag(tag_from_bytes(b"OS/2"))
+            .and_then(|os2| os2.get(26..28))
+            .map_or(0.0, |bytes| {
+                i16::from_be_bytes([bytes[0], bytes[1]]) as f32 * scale
+            });
+
+        let underline_thickness = [metrics.stroke_size, strikeout_size, size / 14.0]
+            .into_iter()
+            .find(|thickness| *thickness > 0.0)
+            .unwrap();
+        let strikeout_thickness = [strikeout_size, underline_thickness]
+            .into_iter()
+            .find(|thickness| *thickness > 0.0)
+            .unwrap();
+        let x_height = if metrics.x_height > 0.0 {
+            metrics.x_height
+        } else {
+            size / 2.0
+        };
+
+        // The font offsets are to the top of the strokes and negative below
+        // the baseline
+        let underline_offset = (-metrics.underline_offset).max(0.0) + underline_thickness / 2.0;
+        let strikeout_offset = if metrics.strikeout_offset > 0.0 {
+            -metrics.strikeout_offset + strikeout_thickness / 2.0
+        } else {
+            -x_height / 2.0
+        };
+        let overline_offset = -metrics.ascent + underline_thickness / 2.0;
+
+        Self {
+            width: 0.0,
+            ascent: metrics.ascent,
+            descent: metrics.descent,
+            leading: metrics.leading,
+            x_height,
+            cap_height: metrics.cap_height,
+            underline_offset,
+            underline_thickness,
+            strikeout_offset,
+            strikeout_thickness,
+            overline_offset,
+        }
+    }
+}
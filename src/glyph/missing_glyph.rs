@@ -0,0 +1,88 @@
+use swash::scale::image::Image;
+
+use super::box_drawing::Canvas;
+
+// Hex digits in a 3x5 pixel font, one row per byte with the leftmost pixel
+// in the highest of the three bits
+const HEX_DIGITS: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b111, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b111, 0b100, 0b100, 0b100, 0b111],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b111, 0b100, 0b111],
+    [0b111, 0b100, 0b111, 0b100, 0b100],
+];
+
+// Rasterizes the box drawn for a character the font has no glyph for, with
+// the code point in hex on two rows inside. The digits are left out when the
+// cell is too small for them to be legible. The top of the cell is ascent
+// pixels above the baseline.
+pub(crate) fn rasterize_missing_glyph(
+    character: char,
+    width: u32,
+    height: u32,
+    ascent: i32,
+) -> Image {
+    let mut canvas = Canvas::new(width, height);
+    let w = width as f32;
+    let h = height as f32;
+    let thickness = (w.min(h) / 16.0).round().max(1.0);
+
+    // Outline of the box, inset from the cell so that neighbouring boxes stay
+    // apart
+    let inset = if w >= 6.0 { 1.0 } else { 0.0 };
+    let (left, right) = (inset, w - inset);
+    let (top, bottom) = ((h * 0.1).round(), h - (h * 0.1).round());
+    canvas.rect(left, top, right, top + thickness);
+    canvas.rect(left, bottom - thickness, right, bottom);
+    canvas.rect(left, top, left + thickness, bottom);
+    canvas.rect(right - thickness, top, right, bottom);
+
+    let code = character as u32;
+    let digits = if code > 0xFFFF {
+        format!("{code:06X}")
+    } else {
+        format!("{code:04X}")
+    };
+    let columns = digits.len() / 2;
+
+    // Digits are 3 units wide and 5 high with a unit between them, sized to
+    // whole pixels when there is room
+    let padding = thickness * 2.0;
+    let inner_width = right - left - padding * 2.0;
+    let inner_height = bottom - top - padding * 2.0;
+    let unit = (inner_width / (columns * 4 - 1) as f32).min(inner_height / 11.0);
+    let unit = if unit >= 1.0 { unit.floor() } else { unit };
+    if unit < 0.5 {
+        return canvas.into_image(ascent);
+    }
+
+    let origin_x = ((w - unit * (columns * 4 - 1) as f32) / 2.0).round();
+    let origin_y = ((top + bottom - unit * 11.0) / 2.0).round();
+    for (index, digit) in digits.chars().enumerate() {
+        let rows = HEX_DIGITS[digit.to_digit(16).unwrap() as usize];
+        let x = origin_x + (index % columns * 4) as f32 * unit;
+        let y = origin_y + (index / columns * 6) as f32 * unit;
+        for (row, bits) in rows.into_iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    let pixel_x = x + column as f32 * unit;
+                    let pixel_y = y + row as f32 * unit;
+                    canvas.rect(pixel_x, pixel_y, pixel_x + unit, pixel_y + unit);
+                }
+            }
+        }
+    }
+
+    canvas.into_image(ascent)
+}
@@ -0,0 +1,98 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use swash::scale::{image::Image, ScaleContext};
+
+use super::{rasterize_glyph, GlyphKey};
+use crate::font::Font;
+
+// Half of the cores, leaving the rest to the render thread and the
+// application, but no more than four, as few frames need more glyphs than
+// that many threads rasterize in time
+pub(crate) fn default_rasterizer_threads() -> usize {
+    thread::available_parallelism().map_or(1, |cores| (cores.get() / 2).clamp(1, 4))
+}
+
+// Rasterizes glyphs on a pool of worker threads so that frames with many new
+// glyphs, such as after changing the font or opening a document in another
+// script, don't stall while the glyphs are rendered. The threads exit once
+// the rasterizer is dropped.
+pub(crate) struct GlyphRasterizer {
+    requests: Sender<(GlyphKey, Font)>,
+    // The image is None if the glyph couldn't be rendered
+    results: Receiver<(GlyphKey, Option<Image>)>,
+    pending: HashSet<GlyphKey>,
+    threads: usize,
+}
+
+impl GlyphRasterizer {
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        let (requests, worker_requests) = channel::<(GlyphKey, Font)>();
+        let (worker_results, results) = channel();
+
+        // The workers take turns waiting for the next request
+        let worker_requests = Arc::new(Mutex::new(worker_requests));
+        for index in 0..threads {
+            let worker_requests = worker_requests.clone();
+            let worker_results = worker_results.clone();
+            thread::Builder::new()
+                .name(format!("vide glyph rasterizer {index}"))
+                .spawn(move || {
+                    let mut context = ScaleContext::new();
+                    loop {
+                        let Ok((key, font)) = worker_requests.lock().unwrap().recv() else {
+                            break;
+                        };
+                        let image = font
+                            .as_ref()
+                            .and_then(|font_ref| rasterize_glyph(&mut context, font_ref, &key));
+                        if worker_results.send((key, image)).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("Could not start a glyph rasterizer thread");
+        }
+
+        Self {
+            requests,
+            results,
+            pending: HashSet::new(),
+            threads,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    // Queues the glyph unless it's already queued
+    pub fn request(&mut self, key: &GlyphKey, font: &Font) {
+        if self.pending.insert(key.clone()) {
+            self.requests
+                .send((key.clone(), font.clone()))
+                .expect("The glyph rasterizer threads stopped");
+        }
+    }
+
+    // Number of queued glyphs which haven't been collected with finished yet
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Glyphs rasterized since the previous call
+    pub fn finished(&mut self) -> Vec<(GlyphKey, Option<Image>)> {
+        let finished: Vec<_> = self.results.try_iter().collect();
+        for (key, _) in finished.iter() {
+            self.pending.remove(key);
+        }
+        finished
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ordered_float::OrderedFloat;
+use swash::{
+    shape::{cluster::Glyph, Direction, ShapeContext},
+    CacheKey, FontRef,
+};
+
+use super::shape_text;
+use crate::{
+    parallel::map_init,
+    scene::{FontFeature, Text, TextDirection},
+};
+
+pub const DEFAULT_SHAPING_CACHE_CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShapingCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+impl ShapingCacheStats {
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f32 / lookups as f32
+        }
+    }
+}
+
+// Everything the glyphs of a shaped run depend on. The tab stops aren't part
+// of it, as the tab advances are set after the glyphs are looked up.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ShapeKey {
+    pub text: Arc<str>,
+    pub font_cache_key: CacheKey,
+    pub size: OrderedFloat<f32>,
+    pub features: Vec<FontFeature>,
+    pub direction: TextDirection,
+}
+
+impl ShapeKey {
+    pub fn new(font_ref: FontRef, text: &Text) -> Self {
+        Self {
+            text: Arc::from(text.text.as_str()),
+            font_cache_key: font_ref.key,
+            size: text.size.into(),
+            features: text.features.clone(),
+            direction: text.direction,
+        }
+    }
+
+    pub fn shape(&self, context: &mut ShapeContext, font_ref: FontRef) -> Vec<Glyph> {
+        let features = self
+            .features
+            .iter()
+            .map(|feature| (feature.tag.as_str(), feature.value));
+        let direction = match self.direction {
+            TextDirection::LeftToRight => Direction::LeftToRight,
+            TextDirection::RightToLeft => Direction::RightToLeft,
+        };
+        let shaper = context
+            .builder(font_ref)
+            .size(*self.size)
+            .features(features)
+            .direction(direction);
+        shape_text(shaper, font_ref, &self.text)
+    }
+}
+
+// Least recently used cache of shaped text runs, kept across frames so that
+// only the runs which weren't drawn recently are shaped again
+pub(crate) struct ShapingCache {
+    capacity: usize,
+    entries: HashMap<ShapeKey, (Vec<Glyph>, u64)>,
+    lookup_count: u64,
+    stats: ShapingCacheStats,
+}
+
+impl ShapingCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            lookup_count: 0,
+            stats: Default::default(),
+        }
+    }
+
+    // Shapes every run which isn't cached yet up front, in parallel when the
+    // rayon feature is enabled. Prepared runs count as misses.
+    pub fn prepare<'a>(&mut self, runs: impl IntoIterator<Item = (FontRef<'a>, &'a Text)>) {
+        let mut missing = HashMap::new();
+        for (font_ref, text) in runs {
+            let key = ShapeKey::new(font_ref, text);
+            if !self.entries.contains_key(&key) {
+                missing.entry(key).or_insert(font_ref);
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+
+        self.stats.misses += missing.len() as u64;
+        let shaped = map_init(
+            missing.into_iter().collect(),
+            ShapeContext::new,
+            |context, (key, font_ref)| {
+                let glyphs = key.shape(context, font_ref);
+                (key, glyphs)
+            },
+        );
+        for (key, glyphs) in shaped {
+            // Zero marks the entry as not looked up yet
+            self.entries.insert(key, (glyphs, 0));
+        }
+    }
+
+    pub fn get_or_shape(
+        &mut self,
+        context: &mut ShapeContext,
+        font_ref: FontRef,
+        text: &Text,
+    ) -> &[Glyph] {
+        self.lookup_count += 1;
+        let key = ShapeKey::new(font_ref, text);
+
+        match self.entries.get(&key) {
+            Some((_, 0)) => {}
+            Some(_) => self.stats.hits += 1,
+            None => {
+                self.stats.misses += 1;
+                let glyphs = key.shape(context, font_ref);
+                self.entries.insert(key.clone(), (glyphs, 0));
+            }
+        }
+
+        let (glyphs, last_used) = self.entries.get_mut(&key).unwrap();
+        *last_used = self.lookup_count;
+        glyphs
+    }
+
+    // Evicts the least recently used runs until the cache fits within its
+    // capacity. Called once per frame so that runs used in the current frame
+    // are never evicted mid frame.
+    pub fn trim(&mut self) {
+        if self.entries.len() > self.capacity {
+            let mut by_age: Vec<_> = self
+                .entries
+                .iter()
+                .map(|(key, (_, last_used))| (*last_used, key.clone()))
+                .collect();
+            by_age.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+            let excess = self.entries.len() - self.capacity;
+            for (_, key) in by_age.into_iter().take(excess) {
+                self.entries.remove(&key);
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.stats.entries = self.entries.len();
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.stats.entries = 0;
+    }
+
+    pub fn stats(&self) -> ShapingCacheStats {
+        self.stats
+    }
+}
@@ -0,0 +1,39 @@
+use swash::{shape::cluster::Glyph, FontRef};
+
+use crate::scene::{TabStops, TabWidth};
+
+// Sets the advances of the tab glyphs, so that the text after each tab starts
+// at the next tab stop
+pub(crate) fn expand_tabs(
+    glyphs: &mut [Glyph],
+    tab_stops: &TabStops,
+    font_ref: FontRef,
+    size: f32,
+) {
+    let interval = match tab_stops.interval {
+        TabWidth::Spaces(spaces) => {
+            let space = font_ref.charmap().map(' ');
+            spaces * font_ref.glyph_metrics(&[]).scale(size).advance_width(space)
+        }
+        TabWidth::Pixels(pixels) => pixels,
+    };
+
+    let mut x = 0.0;
+    for glyph in glyphs {
+        if glyph.data == '\t' as u32 {
+            glyph.advance = next_tab_stop(x, &tab_stops.stops, interval) - x;
+        }
+        x += glyph.advance;
+    }
+}
+
+fn next_tab_stop(x: f32, stops: &[f32], interval: f32) -> f32 {
+    if let Some(stop) = stops.iter().find(|stop| **stop > x) {
+        return *stop;
+    }
+    if interval <= 0.0 {
+        return x;
+    }
+    let last = stops.last().copied().unwrap_or(0.0);
+    last + ((x - last) / interval).floor() * interval + interval
+}
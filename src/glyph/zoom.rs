@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+// How glyphs are rasterized while the camera zoom changes. Glyphs are
+// rasterized at the size they appear on the surface, so every new zoom needs
+// every visible glyph rasterized again.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GlyphZoomPolicy {
+    // Rasterizes the glyphs at every zoom, so text is always sharp, but
+    // zooming smoothly rasterizes all of them on every frame
+    #[default]
+    Exact,
+    // Rasterizes the glyphs at zooms a fixed ratio apart and scales them to
+    // the zoom in between. The glyphs are rasterized at the step at or above
+    // the zoom, so that they are mostly scaled down. A step is kept until the
+    // zoom is more than the hysteresis, as a fraction of a step, past it, so
+    // that zooming back and forth around a step doesn't rasterize twice. Once
+    // the zoom has stayed the same for the settle frames the glyphs are
+    // rasterized at the exact zoom again.
+    Stepped {
+        steps_per_doubling: u32,
+        hysteresis: f32,
+        settle_frames: u32,
+    },
+}
+
+impl GlyphZoomPolicy {
+    // Four steps per doubling of the zoom, which scales the glyphs by at most
+    // about 16 percent
+    pub fn stepped() -> Self {
+        GlyphZoomPolicy::Stepped {
+            steps_per_doubling: 4,
+            hysteresis: 0.25,
+            settle_frames: 3,
+        }
+    }
+}
+
+// Picks the zoom the glyphs of each frame are rasterized at
+#[derive(Debug, Default)]
+pub(crate) struct ZoomRasterization {
+    // Step the glyphs were last rasterized at, as a power of the step ratio
+    step: Option<i32>,
+    // Frames each zoom of the previous frame had stayed the same for
+    previous_frame: HashMap<u32, u32>,
+    // Frames unchanged and the raster zoom of each zoom of this frame
+    frame: HashMap<u32, (u32, f32)>,
+}
+
+impl ZoomRasterization {
+    pub fn begin_frame(&mut self) {
+        self.previous_frame = self
+            .frame
+            .drain()
+            .map(|(zoom, (frames, _))| (zoom, frames))
+            .collect();
+    }
+
+    // Picks the raster zoom for a camera zoom of this frame. Called once for
+    // every viewport before the glyphs are placed.
+    pub fn update(&mut self, policy: GlyphZoomPolicy, zoom: f32) -> f32 {
+        let key = zoom.to_bits();
+        if let Some((_, raster_zoom)) = self.frame.get(&key) {
+            return *raster_zoom;
+        }
+        let unchanged_frames = self.previous_frame.get(&key).map_or(0, |frames| frames + 1);
+
+        let raster_zoom = match policy {
+            GlyphZoomPolicy::Stepped {
+                steps_per_doubling,
+                hysteresis,
+                settle_frames,
+            } if unchanged_frames < settle_frames && zoom > 0.0 => {
+                let steps = steps_per_doubling.max(1) as f32;
+                let position = zoom.log2() * steps;
+                let step = match self.step {
+                    Some(step)
+                        if position > (step - 1) as f32 - hysteresis
+                            && position <= step as f32 + hysteresis =>
+                    {
+                        step
+                    }
+                    _ => position.ceil() as i32,
+                };
+                self.step = Some(step);
+                (step as f32 / steps).exp2()
+            }
+            _ => zoom,
+        };
+        self.frame.insert(key, (unchanged_frames, raster_zoom));
+        raster_zoom
+    }
+
+    // Raster zoom picked for the zoom this frame, the zoom itself if it
+    // wasn't updated
+    pub fn raster_zoom(&self, zoom: f32) -> f32 {
+        self.frame
+            .get(&zoom.to_bits())
+            .map_or(zoom, |(_, raster_zoom)| *raster_zoom)
+    }
+
+    // Whether glyphs of this frame are scaled from another zoom
+    pub fn scaled(&self) -> bool {
+        self.frame
+            .iter()
+            .any(|(zoom, (_, raster_zoom))| f32::from_bits(*zoom) != *raster_zoom)
+    }
+}
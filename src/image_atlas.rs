@@ -0,0 +1,578 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Arc,
+};
+
+use etagere::{size2, AllocId, AtlasAllocator};
+use glam::{vec4, Vec4};
+use shader::MAX_IMAGE_PAGES;
+use tracing::{debug, error};
+use wgpu::*;
+
+use crate::{
+    asset_source::{AssetLoader, SharedAssets},
+    memory::{entries_over_budget, AtlasUsage},
+    ATLAS_SIZE,
+};
+
+// Width, height and rgba8 pixels of a decoded image
+type DecodedImage = (u32, u32, Vec<u8>);
+
+// Decodes png or jpeg bytes into the width, height and rgba8 pixels of the
+// image. None when the bytes aren't a supported image.
+#[cfg(feature = "image")]
+pub(crate) fn decode_image(bytes: &[u8]) -> Option<DecodedImage> {
+    match image::load_from_memory(bytes) {
+        Ok(image) => {
+            let image = image.to_rgba8();
+            Some((image.width(), image.height(), image.into_raw()))
+        }
+        Err(error) => {
+            error!(%error, "Could not decode the image");
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "image"))]
+pub(crate) fn decode_image(_bytes: &[u8]) -> Option<DecodedImage> {
+    error!("Decoding images requires the image feature. Register rgba images instead");
+    None
+}
+
+// Atlas of images shared between every drawable which samples images such as
+// sprites and pattern fills. Images are uploaded the first time they are used.
+// Sprites can overflow into additional pages of the same size once the first
+// page is full, while everything else only uses the first page. Images used
+// by both are moved to the first page.
+pub struct ImageAtlas {
+    pages: Vec<AtlasPage>,
+    // Page, allocation, rect and the frame the image was last used in
+    lookup: HashMap<String, (u32, AllocId, Vec4, u64)>,
+    assets: Arc<SharedAssets>,
+    // Images registered directly as width, height and rgba8 pixels. These are
+    // used before falling back to the asset source.
+    images: HashMap<String, (u32, u32, Vec<u8>)>,
+    // Regions reserved for images drawn on the gpu, such as video frames.
+    // Their contents can't be uploaded again, so they are never evicted.
+    reserved: HashSet<String>,
+    // Images sampled from the first page only, such as pattern fills, which
+    // are kept off the overflow pages when loaded in the background
+    first_page: HashSet<String>,
+    // Regions of images moved to the first page. They are freed at the start
+    // of the next frame, since sprites of this frame may still sample them.
+    moved: Vec<(u32, AllocId)>,
+    // Only set while loading images in the background
+    loader: Option<AssetLoader<DecodedImage>>,
+    // Images which couldn't be loaded or decoded. The placeholder is drawn
+    // for them instead of loading them again every frame.
+    failed: HashSet<String>,
+    // Drawn in place of images which are still loading or failed to load
+    placeholder_color: Vec4,
+    placeholder: Option<(AllocId, Vec4)>,
+    frame: u64,
+    evictions: usize,
+}
+
+struct AtlasPage {
+    texture: Texture,
+    view: TextureView,
+    allocator: AtlasAllocator,
+}
+
+impl AtlasPage {
+    fn new(device: &Device) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Image atlas texture"),
+            size: Extent3d {
+                width: ATLAS_SIZE.x as u32,
+                height: ATLAS_SIZE.y as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            // Video textures render their frames into the atlas
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            allocator: AtlasAllocator::new(size2(ATLAS_SIZE.x as i32, ATLAS_SIZE.y as i32)),
+        }
+    }
+}
+
+impl ImageAtlas {
+    pub fn new(device: &Device, assets: Arc<SharedAssets>) -> Self {
+        Self {
+            pages: vec![AtlasPage::new(device)],
+            lookup: HashMap::new(),
+            assets,
+            images: HashMap::new(),
+            reserved: HashSet::new(),
+            first_page: HashSet::new(),
+            moved: Vec::new(),
+            loader: None,
+            failed: HashSet::new(),
+            placeholder_color: vec4(0.5, 0.5, 0.5, 0.5),
+            placeholder: None,
+            frame: 0,
+            evictions: 0,
+        }
+    }
+
+    // New atlas on the device with the same asset source and registered
+    // images. Everything is uploaded again on first use.
+    pub fn recreate(&self, device: &Device) -> Self {
+        Self {
+            images: self.images.clone(),
+            placeholder_color: self.placeholder_color,
+            ..Self::new(device, self.assets.clone())
+        }
+    }
+
+    // View of the first page
+    pub fn view(&self) -> &TextureView {
+        &self.pages[0].view
+    }
+
+    // Texture of the first page
+    pub fn texture(&self) -> &Texture {
+        &self.pages[0].texture
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn page_view(&self, page: usize) -> &TextureView {
+        &self.pages[page].view
+    }
+
+    pub fn page_textures(&self) -> impl Iterator<Item = &Texture> {
+        self.pages.iter().map(|page| &page.texture)
+    }
+
+    pub fn usage(&self) -> AtlasUsage {
+        let used_bytes = self
+            .pages
+            .iter()
+            .map(|page| page.allocator.allocated_space() as u64 * 4)
+            .sum();
+        AtlasUsage {
+            allocations: self.lookup.len(),
+            used_bytes,
+            capacity_bytes: ATLAS_SIZE.x as u64 * ATLAS_SIZE.y as u64 * 4 * self.pages.len() as u64,
+            peak_used_bytes: 0,
+            evictions: self.evictions,
+        }
+    }
+
+    // Starts a new frame, evicting the least recently used images if the
+    // atlas uses more than the budget
+    pub fn trim(&mut self, budget: Option<u64>) {
+        self.frame += 1;
+        for (page, id) in self.moved.drain(..) {
+            self.pages[page as usize].allocator.deallocate(id);
+        }
+        let Some(budget) = budget else {
+            return;
+        };
+
+        let entries = self
+            .lookup
+            .iter()
+            .filter(|(name, _)| !self.reserved.contains(*name))
+            .map(|(name, (page, id, _, last_used))| {
+                let bytes = self.pages[*page as usize].allocator.get(*id).area() as u64 * 4;
+                (name.clone(), bytes, *last_used)
+            });
+        let evicted = entries_over_budget(entries, self.usage().used_bytes, budget);
+        if !evicted.is_empty() {
+            debug!(
+                count = evicted.len(),
+                budget, "Evicting images over the budget"
+            );
+        }
+        for name in evicted {
+            if self.remove(&name) {
+                self.evictions += 1;
+            }
+        }
+
+        // Start over from fresh allocators so that the packing doesn't
+        // depend on what was evicted. The pages are kept for reuse.
+        if self.lookup.is_empty() {
+            for page in &mut self.pages {
+                page.allocator.clear();
+            }
+            self.placeholder = None;
+        }
+    }
+
+    // Allocates a region of the given size for an image which is drawn into
+    // the atlas on the gpu and returns its rect. Sprites and patterns draw the
+    // region by name like any other image. Sizes larger than the atlas are
+    // scaled down to fit, keeping the aspect ratio, so the image has to be
+    // stretched to the rect. Keeps the current region if it already has the
+    // size. None when the atlas has no room for it.
+    pub fn reserve(&mut self, name: &str, width: u32, height: u32) -> Option<Vec4> {
+        let (width, height) = fit_to_atlas(width, height);
+        if let Some((0, _, rect, _)) = self.lookup.get(name) {
+            if rect.z == width as f32 && rect.w == height as f32 {
+                return Some(*rect);
+            }
+        }
+        self.remove(name);
+        self.reserved.remove(name);
+
+        let Some(allocation) = self.pages[0]
+            .allocator
+            .allocate(size2(width as i32, height as i32))
+        else {
+            error!(
+                name,
+                width, height, "No room to reserve the image in the atlas"
+            );
+            return None;
+        };
+        let rect = vec4(
+            allocation.rectangle.min.x as f32,
+            allocation.rectangle.min.y as f32,
+            width as f32,
+            height as f32,
+        );
+        self.lookup
+            .insert(name.to_string(), (0, allocation.id, rect, self.frame));
+        self.reserved.insert(name.to_string());
+        Some(rect)
+    }
+
+    // Frees a region allocated with reserve
+    pub fn release(&mut self, name: &str) {
+        if self.reserved.remove(name) {
+            self.remove(name);
+        }
+    }
+
+    // Frees the region of the image, returning whether it was in the atlas
+    fn remove(&mut self, name: &str) -> bool {
+        let Some((page, id, _, _)) = self.lookup.remove(name) else {
+            return false;
+        };
+        self.pages[page as usize].allocator.deallocate(id);
+        true
+    }
+
+    // Loads and decodes images from the asset source on a worker thread
+    // instead of while drawing. Images which are still loading are drawn with
+    // the placeholder color.
+    pub fn set_background_loading(&mut self, enabled: bool) {
+        if enabled && self.loader.is_none() {
+            self.loader = Some(AssetLoader::new(self.assets.clone(), |assets, name| {
+                assets.load(name).and_then(|bytes| decode_image(&bytes))
+            }));
+        } else if !enabled {
+            self.loader = None;
+        }
+    }
+
+    pub fn set_placeholder_color(&mut self, color: Vec4) {
+        self.placeholder_color = color;
+        if let Some((id, _)) = self.placeholder.take() {
+            self.pages[0].allocator.deallocate(id);
+        }
+    }
+
+    // Images which are still being loaded in the background
+    pub fn pending(&self) -> usize {
+        self.loader.as_ref().map_or(0, |loader| loader.pending())
+    }
+
+    // Uploads the images the worker thread loaded since the last frame. They
+    // may be placed on any page.
+    pub fn upload_loaded(&mut self, device: &Device, queue: &Queue) {
+        let Some(loader) = &mut self.loader else {
+            return;
+        };
+        for (name, image) in loader.finished() {
+            let Some((image_width, image_height, data)) = image else {
+                error!(name, "Could not load the image");
+                self.failed.insert(name);
+                continue;
+            };
+            if !self.lookup.contains_key(&name) && !self.images.contains_key(&name) {
+                let device = (!self.first_page.contains(&name)).then_some(device);
+                if self
+                    .upload(device, queue, &name, image_width, image_height, &data)
+                    .is_none()
+                {
+                    error!(name, "No room in the image atlas for the loaded image");
+                }
+            }
+        }
+    }
+
+    // Rect of a solid block of the placeholder color. The block has a border
+    // of the same color so that filtering doesn't pick up neighbouring images.
+    fn placeholder(&mut self, queue: &Queue) -> Vec4 {
+        if let Some((_, rect)) = self.placeholder {
+            return rect;
+        }
+
+        let pixel = self
+            .placeholder_color
+            .to_array()
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+        let (_, id, block) = self
+            .allocate_and_write(None, queue, 3, 3, &pixel.repeat(9))
+            .expect("Could not allocate the placeholder to the image atlas");
+        let rect = vec4(block.x + 1.0, block.y + 1.0, 1.0, 1.0);
+        self.placeholder = Some((id, rect));
+        rect
+    }
+
+    // Registers tightly packed rgba8 pixels under the given name, replacing
+    // any image previously registered or loaded with it
+    pub fn add_image_rgba(&mut self, name: &str, width: u32, height: u32, data: Vec<u8>) {
+        assert_eq!(
+            data.len(),
+            (width * height * 4) as usize,
+            "Image data does not match the image size"
+        );
+
+        self.remove(name);
+        self.reserved.remove(name);
+        self.failed.remove(name);
+        self.images.insert(name.to_string(), (width, height, data));
+    }
+
+    // Forgets an image registered with add_image_rgba and frees its region
+    pub fn remove_image(&mut self, name: &str) {
+        self.images.remove(name);
+        self.remove(name);
+    }
+
+    // Returns the rect (top left and size) of the image on the first page of
+    // the atlas, loading and uploading it first if needed. While loading in
+    // the background, returns the placeholder until the image is uploaded.
+    // Images a sprite placed on an overflow page are uploaded again to the
+    // first page, and images which don't fit on it are drawn with the
+    // placeholder.
+    pub fn get_or_upload(&mut self, queue: &Queue, name: &str) -> Vec4 {
+        if !self.first_page.contains(name) {
+            self.first_page.insert(name.to_string());
+        }
+        if let Some((page, id, _, _)) = self.lookup.get(name) {
+            if *page != 0 {
+                self.moved.push((*page, *id));
+                self.lookup.remove(name);
+            }
+        }
+
+        match self.get_or_upload_to(None, queue, name) {
+            Some((_, rect)) => rect,
+            None => {
+                error!(name, "No room on the first page of the image atlas");
+                self.placeholder(queue)
+            }
+        }
+    }
+
+    // Like get_or_upload, but places the image on a new page when the
+    // existing ones are full. Returns the page along with the rect.
+    pub fn get_or_upload_paged(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        name: &str,
+    ) -> (u32, Vec4) {
+        self.get_or_upload_to(Some(device), queue, name)
+            .unwrap_or_else(|| {
+                error!(name, "No room in the image atlas");
+                (0, self.placeholder(queue))
+            })
+    }
+
+    // New pages are only added when given the device. None when the image
+    // doesn't fit.
+    fn get_or_upload_to(
+        &mut self,
+        device: Option<&Device>,
+        queue: &Queue,
+        name: &str,
+    ) -> Option<(u32, Vec4)> {
+        if let Some((page, _, rect, last_used)) = self.lookup.get_mut(name) {
+            *last_used = self.frame;
+            return Some((*page, *rect));
+        }
+
+        if let Some((image_width, image_height, data)) = self.images.remove(name) {
+            let location = self.upload(device, queue, name, image_width, image_height, &data);
+            self.images
+                .insert(name.to_string(), (image_width, image_height, data));
+            return location;
+        }
+
+        if self.failed.contains(name) {
+            return Some((0, self.placeholder(queue)));
+        }
+
+        if let Some(loader) = &mut self.loader {
+            loader.request(name);
+            return Some((0, self.placeholder(queue)));
+        }
+
+        let Some((image_width, image_height, data)) = self
+            .assets
+            .load(name)
+            .and_then(|image_file| decode_image(&image_file))
+        else {
+            error!(name, "Could not load the image");
+            self.failed.insert(name.to_string());
+            return Some((0, self.placeholder(queue)));
+        };
+        self.upload(device, queue, name, image_width, image_height, &data)
+    }
+
+    fn upload(
+        &mut self,
+        device: Option<&Device>,
+        queue: &Queue,
+        name: &str,
+        image_width: u32,
+        image_height: u32,
+        data: &[u8],
+    ) -> Option<(u32, Vec4)> {
+        let (page, id, rect) =
+            self.allocate_and_write(device, queue, image_width, image_height, data)?;
+        self.lookup
+            .insert(name.to_string(), (page, id, rect, self.frame));
+        Some((page, rect))
+    }
+
+    // Allocates on the first page with room, adding a page when given the
+    // device and there is none
+    fn allocate(
+        &mut self,
+        device: Option<&Device>,
+        width: u32,
+        height: u32,
+    ) -> Option<(u32, AllocId, Vec4)> {
+        let size = size2(width as i32, height as i32);
+        let mut allocation = None;
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            if let Some(found) = page.allocator.allocate(size) {
+                allocation = Some((index, found));
+                break;
+            }
+            if device.is_none() {
+                break;
+            }
+        }
+        if allocation.is_none() && self.pages.len() < MAX_IMAGE_PAGES {
+            if let Some(device) = device {
+                debug!(
+                    page = self.pages.len(),
+                    width, height, "Adding an image atlas page"
+                );
+                let mut page = AtlasPage::new(device);
+                allocation = page
+                    .allocator
+                    .allocate(size)
+                    .map(|found| (self.pages.len(), found));
+                self.pages.push(page);
+            }
+        }
+        let Some((page, allocation)) = allocation else {
+            error!(
+                width,
+                height,
+                pages = self.pages.len(),
+                "The image atlas is full"
+            );
+            return None;
+        };
+
+        // Use the image size rather than the allocation size since the
+        // allocator may round allocations up
+        let rect = vec4(
+            allocation.rectangle.min.x as f32,
+            allocation.rectangle.min.y as f32,
+            width as f32,
+            height as f32,
+        );
+        Some((page as u32, allocation.id, rect))
+    }
+
+    fn allocate_and_write(
+        &mut self,
+        device: Option<&Device>,
+        queue: &Queue,
+        image_width: u32,
+        image_height: u32,
+        data: &[u8],
+    ) -> Option<(u32, AllocId, Vec4)> {
+        let (page, id, rect) = self.allocate(device, image_width, image_height)?;
+
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &self.pages[page as usize].texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: rect.x as u32,
+                    y: rect.y as u32,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            data,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image_width),
+                rows_per_image: Some(image_height),
+            },
+            Extent3d {
+                width: image_width,
+                height: image_height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Some((page, id, rect))
+    }
+}
+
+// The image atlas has no room left for an image drawn into it on the gpu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasFullError;
+
+impl fmt::Display for AtlasFullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The image atlas has no room for the image")
+    }
+}
+
+impl std::error::Error for AtlasFullError {}
+
+// Scales a size larger than the atlas down until it fits
+fn fit_to_atlas(width: u32, height: u32) -> (u32, u32) {
+    let scale = (ATLAS_SIZE.x / width as f32)
+        .min(ATLAS_SIZE.y / height as f32)
+        .min(1.0);
+    if scale == 1.0 {
+        return (width, height);
+    }
+    (
+        ((width as f32 * scale) as u32).max(1),
+        ((height as f32 * scale) as u32).max(1),
+    )
+}
@@ -0,0 +1,77 @@
+use wgpu::{
+    util::{DrawIndexedIndirectArgs, DrawIndirectArgs},
+    Buffer, BufferAddress, Features, RenderPass,
+};
+
+// Optional features for indirect drawing which are requested when the
+// adapter supports them
+pub(crate) const INDIRECT_FEATURES: Features =
+    Features::MULTI_DRAW_INDIRECT.union(Features::INDIRECT_FIRST_INSTANCE);
+
+// Issues draws whose arguments the gpu writes into a buffer, such as the
+// output of compute passes culling instances or simulating particles, without
+// reading them back. Consecutive draws are issued with one call when the
+// adapter supports MULTI_DRAW_INDIRECT and one by one otherwise. A first
+// instance other than zero needs INDIRECT_FIRST_INSTANCE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndirectDraws {
+    features: Features,
+}
+
+impl IndirectDraws {
+    pub fn new(features: Features) -> Self {
+        Self { features }
+    }
+
+    pub fn supports_multi_draw(&self) -> bool {
+        self.features.contains(Features::MULTI_DRAW_INDIRECT)
+    }
+
+    pub fn supports_first_instance(&self) -> bool {
+        self.features.contains(Features::INDIRECT_FIRST_INSTANCE)
+    }
+
+    // Draws count consecutive DrawIndirectArgs starting at the offset
+    pub fn draw<'a>(
+        &self,
+        render_pass: &mut RenderPass<'a>,
+        buffer: &'a Buffer,
+        offset: BufferAddress,
+        count: u32,
+    ) {
+        if self.supports_multi_draw() {
+            render_pass.multi_draw_indirect(buffer, offset, count);
+        } else {
+            for offset in draw_offsets::<DrawIndirectArgs>(offset, count) {
+                render_pass.draw_indirect(buffer, offset);
+            }
+        }
+    }
+
+    // Draws count consecutive DrawIndexedIndirectArgs starting at the offset
+    // with the bound index buffer
+    pub fn draw_indexed<'a>(
+        &self,
+        render_pass: &mut RenderPass<'a>,
+        buffer: &'a Buffer,
+        offset: BufferAddress,
+        count: u32,
+    ) {
+        if self.supports_multi_draw() {
+            render_pass.multi_draw_indexed_indirect(buffer, offset, count);
+        } else {
+            for offset in draw_offsets::<DrawIndexedIndirectArgs>(offset, count) {
+                render_pass.draw_indexed_indirect(buffer, offset);
+            }
+        }
+    }
+}
+
+// Offsets of the arguments of the draws, which are tightly packed
+pub(crate) fn draw_offsets<T>(
+    offset: BufferAddress,
+    count: u32,
+) -> impl Iterator<Item = BufferAddress> {
+    let stride = std::mem::size_of::<T>() as BufferAddress;
+    (0..count as BufferAddress).map(move |index| offset + index * stride)
+}
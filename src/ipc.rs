@@ -0,0 +1,353 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    sync::{Arc, RwLock},
+};
+
+use crate::{asset_source::SharedAssets, AssetSource, MemoryAssets, Renderer, Scene};
+
+// Bumped whenever the framing or the encoding of a message changes. Scenes are
+// sent as json, so fields added with serde defaults don't need a new version.
+pub const IPC_PROTOCOL_VERSION: u32 = 1;
+
+const MAGIC: [u8; 4] = *b"VIDE";
+// Guards against allocating huge buffers for corrupted message headers
+const MAX_MESSAGE_SIZE: usize = 1 << 30;
+
+const SCENE_MESSAGE: u8 = 0;
+const IMAGE_MESSAGE: u8 = 1;
+const FONT_MESSAGE: u8 = 2;
+
+#[derive(Debug)]
+pub enum IpcError {
+    Io(io::Error),
+    // The other side doesn't speak the vide protocol
+    InvalidHandshake,
+    VersionMismatch { client: u32, server: u32 },
+    InvalidMessage(String),
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpcError::Io(error) => write!(f, "Ipc stream error: {error}"),
+            IpcError::InvalidHandshake => write!(f, "The peer is not a vide ipc endpoint"),
+            IpcError::VersionMismatch { client, server } => write!(
+                f,
+                "The client speaks protocol version {client} but the server speaks {server}"
+            ),
+            IpcError::InvalidMessage(error) => write!(f, "Invalid ipc message: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for IpcError {}
+
+impl From<io::Error> for IpcError {
+    fn from(error: io::Error) -> Self {
+        IpcError::Io(error)
+    }
+}
+
+// Sent from the client to the server. Images and fonts are registered under
+// their name and referred to by sprites, patterns and layers like assets of
+// the renderer.
+#[derive(Debug, Clone)]
+pub enum IpcMessage {
+    Scene(Scene),
+    // Tightly packed rgba8 pixels
+    Image {
+        name: String,
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    },
+    // The bytes of a ttf or otf file
+    Font {
+        name: String,
+        data: Vec<u8>,
+    },
+}
+
+pub(crate) fn write_handshake(stream: &mut impl Write, version: u32) -> io::Result<()> {
+    stream.write_all(&MAGIC)?;
+    stream.write_all(&version.to_le_bytes())?;
+    stream.flush()
+}
+
+fn read_handshake(stream: &mut impl Read) -> Result<u32, IpcError> {
+    let mut magic = [0; 4];
+    stream.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(IpcError::InvalidHandshake);
+    }
+    read_u32(stream).map_err(IpcError::from)
+}
+
+fn read_u32(stream: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    stream.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_bytes(payload: &mut Vec<u8>, bytes: &[u8]) {
+    payload.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    payload.extend_from_slice(bytes);
+}
+
+fn truncated() -> IpcError {
+    IpcError::InvalidMessage("Truncated message".to_string())
+}
+
+fn take_u32(payload: &mut &[u8]) -> Result<u32, IpcError> {
+    if payload.len() < 4 {
+        return Err(truncated());
+    }
+    let (value, rest) = payload.split_at(4);
+    *payload = rest;
+    Ok(u32::from_le_bytes(value.try_into().unwrap()))
+}
+
+// Splits a length prefixed field off the front of the payload
+fn take_bytes<'a>(payload: &mut &'a [u8]) -> Result<&'a [u8], IpcError> {
+    let length = take_u32(payload)? as usize;
+    if payload.len() < length {
+        return Err(truncated());
+    }
+    let (bytes, rest) = payload.split_at(length);
+    *payload = rest;
+    Ok(bytes)
+}
+
+fn take_string(payload: &mut &[u8]) -> Result<String, IpcError> {
+    String::from_utf8(take_bytes(payload)?.to_vec())
+        .map_err(|error| IpcError::InvalidMessage(error.to_string()))
+}
+
+fn write_scene(stream: &mut impl Write, scene: &Scene) -> io::Result<()> {
+    write_frame(stream, SCENE_MESSAGE, &serde_json::to_vec(scene)?)
+}
+
+fn write_image(
+    stream: &mut impl Write,
+    name: &str,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut payload = Vec::with_capacity(name.len() + data.len() + 16);
+    write_bytes(&mut payload, name.as_bytes());
+    payload.extend_from_slice(&width.to_le_bytes());
+    payload.extend_from_slice(&height.to_le_bytes());
+    write_bytes(&mut payload, data);
+    write_frame(stream, IMAGE_MESSAGE, &payload)
+}
+
+fn write_font(stream: &mut impl Write, name: &str, data: &[u8]) -> io::Result<()> {
+    let mut payload = Vec::with_capacity(name.len() + data.len() + 8);
+    write_bytes(&mut payload, name.as_bytes());
+    write_bytes(&mut payload, data);
+    write_frame(stream, FONT_MESSAGE, &payload)
+}
+
+// Each message is framed as a kind byte and the length of the payload
+fn write_frame(stream: &mut impl Write, kind: u8, payload: &[u8]) -> io::Result<()> {
+    stream.write_all(&[kind])?;
+    stream.write_all(&(payload.len() as u32).to_le_bytes())?;
+    stream.write_all(payload)?;
+    stream.flush()
+}
+
+pub(crate) fn write_message(stream: &mut impl Write, message: &IpcMessage) -> io::Result<()> {
+    match message {
+        IpcMessage::Scene(scene) => write_scene(stream, scene),
+        IpcMessage::Image {
+            name,
+            width,
+            height,
+            data,
+        } => write_image(stream, name, *width, *height, data),
+        IpcMessage::Font { name, data } => write_font(stream, name, data),
+    }
+}
+
+// Returns None once the client closes the stream between messages
+pub(crate) fn read_message(stream: &mut impl Read) -> Result<Option<IpcMessage>, IpcError> {
+    let mut kind = [0];
+    match stream.read_exact(&mut kind) {
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let length = read_u32(stream)? as usize;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(IpcError::InvalidMessage(format!(
+            "Message of {length} bytes is too large"
+        )));
+    }
+    let mut buffer = vec![0; length];
+    stream.read_exact(&mut buffer)?;
+
+    let mut payload = buffer.as_slice();
+    let message = match kind[0] {
+        SCENE_MESSAGE => IpcMessage::Scene(
+            serde_json::from_slice(payload)
+                .map_err(|error| IpcError::InvalidMessage(error.to_string()))?,
+        ),
+        IMAGE_MESSAGE => {
+            let name = take_string(&mut payload)?;
+            let width = take_u32(&mut payload)?;
+            let height = take_u32(&mut payload)?;
+            let data = take_bytes(&mut payload)?.to_vec();
+            if data.len() != width as usize * height as usize * 4 {
+                return Err(IpcError::InvalidMessage(format!(
+                    "Image {name} doesn't have {width}x{height} rgba pixels"
+                )));
+            }
+            IpcMessage::Image {
+                name,
+                width,
+                height,
+                data,
+            }
+        }
+        FONT_MESSAGE => IpcMessage::Font {
+            name: take_string(&mut payload)?,
+            data: take_bytes(&mut payload)?.to_vec(),
+        },
+        kind => {
+            return Err(IpcError::InvalidMessage(format!(
+                "Unknown message kind {kind}"
+            )))
+        }
+    };
+    Ok(Some(message))
+}
+
+// Streams scenes to a renderer in another process, so that a crash or a hang
+// of the gpu driver takes down the render process instead of the application.
+// Any io error, such as a broken pipe after the server died, is returned and
+// the application can start a new server and connect again. The resources
+// have to be sent again after reconnecting.
+pub struct IpcClient<S: Read + Write> {
+    stream: S,
+}
+
+impl<S: Read + Write> IpcClient<S> {
+    // Performs the handshake on a connected stream
+    pub fn new(mut stream: S) -> Result<Self, IpcError> {
+        write_handshake(&mut stream, IPC_PROTOCOL_VERSION)?;
+        let server = read_handshake(&mut stream)?;
+        if server != IPC_PROTOCOL_VERSION {
+            return Err(IpcError::VersionMismatch {
+                client: IPC_PROTOCOL_VERSION,
+                server,
+            });
+        }
+        Ok(Self { stream })
+    }
+
+    pub fn send(&mut self, message: &IpcMessage) -> Result<(), IpcError> {
+        write_message(&mut self.stream, message).map_err(IpcError::from)
+    }
+
+    pub fn send_scene(&mut self, scene: &Scene) -> Result<(), IpcError> {
+        write_scene(&mut self.stream, scene).map_err(IpcError::from)
+    }
+
+    pub fn send_image_rgba(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        data: &[u8],
+    ) -> Result<(), IpcError> {
+        write_image(&mut self.stream, name, width, height, data).map_err(IpcError::from)
+    }
+
+    pub fn send_font(&mut self, name: &str, data: &[u8]) -> Result<(), IpcError> {
+        write_font(&mut self.stream, name, data).map_err(IpcError::from)
+    }
+}
+
+#[cfg(unix)]
+impl IpcClient<std::os::unix::net::UnixStream> {
+    pub fn connect(path: impl AsRef<std::path::Path>) -> Result<Self, IpcError> {
+        Self::new(std::os::unix::net::UnixStream::connect(path)?)
+    }
+}
+
+#[cfg(windows)]
+impl IpcClient<std::fs::File> {
+    // Connects to the named pipe \\.\pipe\<name> created by the server process
+    pub fn connect(name: &str) -> Result<Self, IpcError> {
+        let pipe = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!(r"\\.\pipe\{name}"))?;
+        Self::new(pipe)
+    }
+}
+
+// The render process side of the protocol. Accept a connection, for example
+// from a UnixListener or a named pipe, then draw every received scene:
+//
+// while let Some(scene) = server.receive(&mut renderer)? {
+//     // draw the scene to the window or offscreen texture
+// }
+pub struct IpcServer<S: Read + Write> {
+    stream: S,
+    fonts: Arc<RwLock<MemoryAssets>>,
+}
+
+impl<S: Read + Write> IpcServer<S> {
+    // Performs the handshake and layers the fonts sent by the client over the
+    // asset source of the renderer, which still provides everything else.
+    // Fonts are loaded once, so a font has to be sent before the first scene
+    // using it.
+    pub fn accept(mut stream: S, renderer: &mut Renderer) -> Result<Self, IpcError> {
+        let client = read_handshake(&mut stream)?;
+        write_handshake(&mut stream, IPC_PROTOCOL_VERSION)?;
+        if client != IPC_PROTOCOL_VERSION {
+            return Err(IpcError::VersionMismatch {
+                client,
+                server: IPC_PROTOCOL_VERSION,
+            });
+        }
+
+        let fonts = Arc::new(RwLock::new(MemoryAssets::new()));
+        layer_assets(&renderer.assets, fonts.clone());
+        Ok(Self { stream, fonts })
+    }
+
+    // Registers the resources sent before the next scene with the renderer
+    // and returns the scene. Returns None when the client disconnects.
+    pub fn receive(&mut self, renderer: &mut Renderer) -> Result<Option<Scene>, IpcError> {
+        while let Some(message) = read_message(&mut self.stream)? {
+            match message {
+                IpcMessage::Scene(scene) => return Ok(Some(scene)),
+                IpcMessage::Image {
+                    name,
+                    width,
+                    height,
+                    data,
+                } => renderer.add_image_rgba(&name, width, height, data),
+                IpcMessage::Font { name, data } => {
+                    self.fonts.write().unwrap().add_asset(&name, data)
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Loads from the assets first and falls back to the previous source
+pub(crate) fn layer_assets(shared: &SharedAssets, assets: Arc<RwLock<MemoryAssets>>) {
+    let previous = shared.take();
+    shared.set(move |name: &str| {
+        assets
+            .read()
+            .unwrap()
+            .load(name)
+            .or_else(|| previous.as_ref()?.load(name))
+    });
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use wgpu::{
+    Buffer, BufferAddress, BufferDescriptor, BufferUsages, CommandEncoder, Device, Maintain,
+    MapMode, QuerySet, QuerySetDescriptor, QueryType, Queue,
+};
+
+// Frames whose measurements are still pending before the oldest are dropped
+const MAX_PENDING_FRAMES: usize = 8;
+
+const TIMESTAMPS_SIZE: BufferAddress = 2 * std::mem::size_of::<u64>() as BufferAddress;
+
+// Timestamps of a rendered frame, from the input it responds to until it was
+// presented. Gpu completion is observed when the device is next polled,
+// which happens at the latest when the next frame is rendered, so it can be
+// late by up to a frame. Platforms don't report when a frame is scanned out,
+// so presentation is when the frame was handed to the compositor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameLatency {
+    // The first input marked since the previous frame
+    pub input: Option<Instant>,
+    pub render_start: Instant,
+    pub submitted: Instant,
+    pub gpu_completed: Instant,
+    pub presented: Option<Instant>,
+    // Time the gpu spent on the frame, measured with timestamp queries where
+    // the adapter supports them
+    pub gpu_duration: Option<Duration>,
+}
+
+impl FrameLatency {
+    // Time the cpu spent preparing and submitting the frame
+    pub fn cpu_duration(&self) -> Duration {
+        self.submitted.saturating_duration_since(self.render_start)
+    }
+
+    // Estimated time from the input until the frame shows up, which is when
+    // the gpu finished it or it was presented, whichever came later. Doesn't
+    // include the delay of the compositor and display.
+    pub fn input_to_photon(&self) -> Option<Duration> {
+        let shown = self.presented.map_or(self.gpu_completed, |presented| {
+            presented.max(self.gpu_completed)
+        });
+        Some(shown.saturating_duration_since(self.input?))
+    }
+}
+
+struct PendingFrame {
+    input: Option<Instant>,
+    render_start: Instant,
+    submitted: Option<Instant>,
+    presented: Option<Instant>,
+    gpu_completed: Arc<Mutex<Option<Instant>>>,
+    // Whether the timestamps of the frame are being read back
+    timestamps: bool,
+    // Whether mapping the timestamps succeeded, once it has finished
+    timestamps_mapped: Arc<Mutex<Option<bool>>>,
+}
+
+// Gpu timestamp queries of the frame being read back
+struct Timestamps {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    busy: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct LatencyTracker {
+    input: Option<Instant>,
+    pending: VecDeque<PendingFrame>,
+    timestamps: Option<Timestamps>,
+    last: Option<FrameLatency>,
+}
+
+impl LatencyTracker {
+    // Keeps the first input until a frame is rendered
+    pub fn mark_input(&mut self, time: Instant) {
+        self.input.get_or_insert(time);
+    }
+
+    pub fn last(&self) -> Option<FrameLatency> {
+        self.last
+    }
+
+    // Collects the measurements of the finished frames and starts measuring
+    // a new one. The start timestamp is written to the encoder when the
+    // adapter supports timestamp queries.
+    pub fn begin_frame(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        timestamp_queries: bool,
+    ) {
+        // Frames rendered into the encoder of the caller are submitted by
+        // the caller, at the latest before the next frame
+        self.submitted(queue);
+        device.poll(Maintain::Poll);
+        self.collect(queue);
+
+        if self.pending.len() >= MAX_PENDING_FRAMES {
+            self.drop_oldest();
+        }
+
+        let timestamps = if timestamp_queries {
+            let timestamps = self
+                .timestamps
+                .get_or_insert_with(|| create_timestamps(device));
+            if !timestamps.busy {
+                encoder.write_timestamp(&timestamps.query_set, 0);
+                timestamps.busy = true;
+                true
+            } else {
+                false
+            }
+        } else {
+            false
+        };
+        self.pending.push_back(PendingFrame {
+            input: self.input.take(),
+            render_start: Instant::now(),
+            submitted: None,
+            presented: None,
+            gpu_completed: Arc::new(Mutex::new(None)),
+            timestamps,
+            timestamps_mapped: Arc::new(Mutex::new(None)),
+        });
+    }
+
+    // Writes the end timestamp of the frame and copies the timestamps where
+    // they can be read back
+    pub fn end_frame(&mut self, encoder: &mut CommandEncoder) {
+        let Some(frame) = self.pending.back() else {
+            return;
+        };
+        if let (true, Some(timestamps)) = (frame.timestamps, &self.timestamps) {
+            encoder.write_timestamp(&timestamps.query_set, 1);
+            encoder.resolve_query_set(&timestamps.query_set, 0..2, &timestamps.resolve_buffer, 0);
+            encoder.copy_buffer_to_buffer(
+                &timestamps.resolve_buffer,
+                0,
+                &timestamps.readback_buffer,
+                0,
+                TIMESTAMPS_SIZE,
+            );
+        }
+    }
+
+    // Waits for the gpu to finish the submitted frames
+    pub fn submitted(&mut self, queue: &Queue) {
+        let Some(frame) = self.pending.back_mut() else {
+            return;
+        };
+        if frame.submitted.is_some() {
+            return;
+        }
+        frame.submitted = Some(Instant::now());
+
+        let gpu_completed = frame.gpu_completed.clone();
+        queue.on_submitted_work_done(move || {
+            *gpu_completed.lock().unwrap() = Some(Instant::now());
+        });
+        if let (true, Some(timestamps)) = (frame.timestamps, &self.timestamps) {
+            let mapped = frame.timestamps_mapped.clone();
+            timestamps
+                .readback_buffer
+                .slice(..)
+                .map_async(MapMode::Read, move |result| {
+                    *mapped.lock().unwrap() = Some(result.is_ok());
+                });
+        }
+    }
+
+    pub fn presented(&mut self) {
+        if let Some(frame) = self.pending.back_mut() {
+            frame.presented.get_or_insert_with(Instant::now);
+        }
+    }
+
+    // Moves the frames the gpu finished, in order, to the last measured frame
+    fn collect(&mut self, queue: &Queue) {
+        while let Some(frame) = self.pending.front() {
+            let Some(submitted) = frame.submitted else {
+                break;
+            };
+            let Some(gpu_completed) = *frame.gpu_completed.lock().unwrap() else {
+                break;
+            };
+            let mapped = *frame.timestamps_mapped.lock().unwrap();
+            if frame.timestamps && mapped.is_none() {
+                break;
+            }
+
+            let gpu_duration = match (frame.timestamps, &mut self.timestamps) {
+                (true, Some(timestamps)) => {
+                    timestamps.busy = false;
+                    (mapped == Some(true))
+                        .then(|| read_timestamps(&timestamps.readback_buffer, queue))
+                }
+                _ => None,
+            };
+            self.last = Some(FrameLatency {
+                input: frame.input,
+                render_start: frame.render_start,
+                submitted,
+                gpu_completed,
+                presented: frame.presented,
+                gpu_duration,
+            });
+            self.pending.pop_front();
+        }
+    }
+
+    // Gives up on the oldest frame, such as when the device was lost. The
+    // timestamp buffers are recreated if they may still be mapped.
+    fn drop_oldest(&mut self) {
+        let Some(frame) = self.pending.pop_front() else {
+            return;
+        };
+        if frame.timestamps {
+            match (
+                *frame.timestamps_mapped.lock().unwrap(),
+                &mut self.timestamps,
+            ) {
+                (Some(mapped), Some(timestamps)) => {
+                    if mapped {
+                        timestamps.readback_buffer.unmap();
+                    }
+                    timestamps.busy = false;
+                }
+                _ => self.timestamps = None,
+            }
+        }
+    }
+}
+
+fn create_timestamps(device: &Device) -> Timestamps {
+    let buffer = |label, usage| {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: TIMESTAMPS_SIZE,
+            usage,
+            mapped_at_creation: false,
+        })
+    };
+    Timestamps {
+        query_set: device.create_query_set(&QuerySetDescriptor {
+            label: Some("Frame Timestamps"),
+            ty: QueryType::Timestamp,
+            count: 2,
+        }),
+        resolve_buffer: buffer(
+            "Timestamp Resolve Buffer",
+            BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+        ),
+        readback_buffer: buffer(
+            "Timestamp Readback Buffer",
+            BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        ),
+        busy: false,
+    }
+}
+
+fn read_timestamps(buffer: &Buffer, queue: &Queue) -> Duration {
+    let ticks = {
+        let data = buffer.slice(..).get_mapped_range();
+        let timestamps: &[u64] = bytemuck::cast_slice(&data);
+        timestamps[1].saturating_sub(timestamps[0])
+    };
+    buffer.unmap();
+    Duration::from_nanos((ticks as f64 * queue.get_timestamp_period() as f64) as u64)
+}
@@ -1,27 +1,107 @@
+mod adapter;
+mod asset_source;
+mod backdrop_blur;
+mod batching;
+mod blit;
+mod capabilities;
+mod caret;
+mod checkerboard;
+mod color_grading;
+mod cursor_trail;
+mod design_overlay;
+mod diagnostics;
+mod dimming;
+mod external_image;
 mod font;
+mod frame_clock;
+mod frame_metadata;
+mod frame_pacer;
 mod glyph;
+mod image_atlas;
+mod indirect;
+mod ipc;
+mod latency;
+mod lod;
+mod memory;
+mod occlusion;
 mod offscreen_renderer;
+mod parallel;
 mod path;
+mod procedural;
 mod quad;
+mod raw_window_renderer;
 mod renderer;
 mod scene;
+mod shader_layout;
+mod shape;
 // mod shaper;
 mod sprite;
+mod state_sorting;
+#[cfg(feature = "taffy")]
+mod taffy_scene;
+mod universal_binding;
+mod uploader;
+mod video;
+mod viewport;
+#[cfg(feature = "winit")]
 mod winit_renderer;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "image", feature = "embed"))]
 mod test;
 
 use glam::{vec2, Vec2};
-use rust_embed::*;
 
-pub use offscreen_renderer::OffscreenRenderer;
-pub use renderer::Renderer;
+pub use adapter::{ADAPTER_VAR, BACKEND_VAR};
+#[cfg(feature = "embed")]
+pub use asset_source::EmbeddedAssets;
+pub use asset_source::{AssetSource, DirectoryAssets, MemoryAssets};
+pub use backdrop_blur::BackdropBlurState;
+pub use batching::BatchingStats;
+pub use capabilities::Capabilities;
+pub use caret::CaretState;
+pub use checkerboard::CheckerboardState;
+pub use color_grading::{ColorLut, ColorLutError};
+pub use cursor_trail::CursorTrailState;
+pub use design_overlay::{ruler_step, DesignOverlay, Guide, GuideAxis};
+pub use diagnostics::GpuDiagnostics;
+pub use dimming::DimmingState;
+#[cfg(all(target_os = "linux", feature = "dmabuf"))]
+pub use external_image::DmaBuf;
+pub use external_image::{ExternalImage, ExternalImageError, ExternalTexture};
+pub use frame_clock::{FrameClock, FrameTime};
+pub use frame_metadata::{FrameMetadata, InteractiveRegion, TextRun};
+pub use frame_pacer::{FramePacer, PacingMode};
+pub use glyph::{shaping::ShapingCacheStats, CellCluster, GlyphZoomPolicy, TextMetrics};
+pub use image_atlas::AtlasFullError;
+pub use indirect::IndirectDraws;
+pub use ipc::{IpcClient, IpcError, IpcMessage, IpcServer, IPC_PROTOCOL_VERSION};
+pub use latency::FrameLatency;
+pub use lod::{LodLevel, LodPolicy};
+pub use memory::{AtlasUsage, MemoryBudget, MemoryReport, ResourceUsage};
+pub use occlusion::OcclusionStats;
+#[cfg(feature = "image")]
+pub use offscreen_renderer::OffscreenImage;
+pub use offscreen_renderer::{OffscreenFormat, OffscreenRenderer};
+pub use path::{PathState, TessellationCacheStats};
+pub use procedural::ProceduralState;
+pub use raw_window_renderer::{DrawOutcome, RawWindowRenderer, ResizeStrategy, SurfaceErrorEvent};
+pub use renderer::{Drawable, DrawableError, Renderer, RendererError, SHADER_ASSET};
 pub use scene::*;
-pub use winit_renderer::WinitRenderer;
+pub use shader_layout::{
+    reflect_shader, shader_constants_layout, verify_pipeline_interface, verify_shader_constants,
+    FieldLayout, PipelineInterface, ShaderLayoutError,
+};
+pub use shape::ShapeState;
+pub use state_sorting::StateSortingStats;
+#[cfg(feature = "taffy")]
+pub use taffy;
+#[cfg(feature = "taffy")]
+pub use taffy_scene::{scene_from_taffy, NodePaint, NodeText};
+pub use universal_binding::{UniversalBinding, UniversalResource, FIRST_USER_UNIVERSAL_BINDING};
+pub use uploader::Uploader;
+pub use video::{VideoFormat, VideoFrame, VideoFrameError, VideoTexture, YuvMatrix, YuvRange};
+pub use viewport::Viewport;
+#[cfg(feature = "winit")]
+pub use winit_renderer::{closest_video_mode, DisplayMode, WinitRenderer};
 
 pub const ATLAS_SIZE: Vec2 = vec2(1024., 1024.);
-
-#[derive(RustEmbed)]
-#[folder = "spirv"]
-struct Asset;
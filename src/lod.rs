@@ -0,0 +1,196 @@
+use std::time::{Duration, Instant};
+
+use shader::InstancedQuad;
+
+// How much the renderer simplifies the frames to keep up under load. Every
+// level also applies the simplifications of the levels before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum LodLevel {
+    #[default]
+    Full,
+    // Background and backdrop blurs with half the kernel radius, which takes
+    // a quarter of the samples
+    ReducedBlur,
+    // Leaves out the shadows of the layers and of elevated quads
+    NoShadows,
+    // Draws with a single sample per pixel. The sdf shapes and glyphs stay
+    // antialiased, but the edges of paths get jagged.
+    NoMultisampling,
+}
+
+impl LodLevel {
+    // Scale of the kernel radius of the background blurs
+    pub fn blur_scale(self) -> f32 {
+        if self >= LodLevel::ReducedBlur {
+            0.5
+        } else {
+            1.0
+        }
+    }
+
+    pub fn shadows(self) -> bool {
+        self < LodLevel::NoShadows
+    }
+
+    // Samples per pixel the drawables render with
+    pub fn sample_count(self) -> u32 {
+        if self >= LodLevel::NoMultisampling {
+            1
+        } else {
+            4
+        }
+    }
+
+    fn lower(self) -> Self {
+        match self {
+            LodLevel::Full => LodLevel::ReducedBlur,
+            LodLevel::ReducedBlur => LodLevel::NoShadows,
+            LodLevel::NoShadows | LodLevel::NoMultisampling => LodLevel::NoMultisampling,
+        }
+    }
+
+    fn higher(self) -> Self {
+        match self {
+            LodLevel::Full | LodLevel::ReducedBlur => LodLevel::Full,
+            LodLevel::NoShadows => LodLevel::ReducedBlur,
+            LodLevel::NoMultisampling => LodLevel::NoShadows,
+        }
+    }
+}
+
+// When the renderer steps down to a simpler level of detail and back up.
+// The level changes by one step at a time after a run of slow or fast frames,
+// so that a single hitch doesn't degrade the frames and the quality doesn't
+// flicker between levels. Frames drawn after the renderer was idle start at
+// full quality again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodPolicy {
+    // Time a frame may take. By default the cpu time spent rendering it, or
+    // the gpu time if that is longer and measured by the latency tracking.
+    pub frame_budget: Duration,
+    // Consecutive frames over the budget before stepping down
+    pub frames_to_degrade: u32,
+    // Consecutive frames within the fraction of the budget before stepping
+    // back up
+    pub frames_to_restore: u32,
+    pub restore_fraction: f32,
+    // Time without frames after which the next frame is drawn at full
+    // quality
+    pub idle_time: Duration,
+    // The simplest level the renderer steps down to
+    pub lowest_level: LodLevel,
+}
+
+impl Default for LodPolicy {
+    fn default() -> Self {
+        Self {
+            frame_budget: Duration::from_secs_f64(1.0 / 60.0),
+            frames_to_degrade: 3,
+            frames_to_restore: 60,
+            restore_fraction: 0.5,
+            idle_time: Duration::from_millis(500),
+            lowest_level: LodLevel::NoMultisampling,
+        }
+    }
+}
+
+impl LodPolicy {
+    pub fn new(frame_budget: Duration) -> Self {
+        Self {
+            frame_budget,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_lowest_level(mut self, level: LodLevel) -> Self {
+        self.lowest_level = level;
+        self
+    }
+
+    pub fn with_frames_to_degrade(mut self, frames: u32) -> Self {
+        self.frames_to_degrade = frames;
+        self
+    }
+
+    pub fn with_frames_to_restore(mut self, frames: u32) -> Self {
+        self.frames_to_restore = frames;
+        self
+    }
+
+    pub fn with_idle_time(mut self, idle_time: Duration) -> Self {
+        self.idle_time = idle_time;
+        self
+    }
+}
+
+// Tracks the frame times against the policy and picks the level of detail
+// of the next frames
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LodController {
+    level: LodLevel,
+    slow_frames: u32,
+    fast_frames: u32,
+    last_frame: Option<Instant>,
+}
+
+impl LodController {
+    pub fn level(&self) -> LodLevel {
+        self.level
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // Records the time a frame finished at and took, and returns the level
+    // of the frames after it
+    pub fn record_frame(
+        &mut self,
+        policy: &LodPolicy,
+        finished: Instant,
+        frame_time: Duration,
+    ) -> LodLevel {
+        let idle = self.last_frame.is_some_and(|last_frame| {
+            finished.saturating_duration_since(last_frame) > policy.idle_time + frame_time
+        });
+        self.last_frame = Some(finished);
+        if idle {
+            self.level = LodLevel::Full;
+            self.slow_frames = 0;
+            self.fast_frames = 0;
+            return self.level;
+        }
+
+        if frame_time > policy.frame_budget {
+            self.fast_frames = 0;
+            self.slow_frames += 1;
+            if self.slow_frames >= policy.frames_to_degrade {
+                self.slow_frames = 0;
+                if self.level < policy.lowest_level {
+                    self.level = self.level.lower();
+                }
+            }
+        } else if frame_time.as_secs_f32()
+            <= policy.frame_budget.as_secs_f32() * policy.restore_fraction
+        {
+            self.slow_frames = 0;
+            self.fast_frames += 1;
+            if self.fast_frames >= policy.frames_to_restore {
+                self.fast_frames = 0;
+                self.level = self.level.higher();
+            }
+        } else {
+            self.slow_frames = 0;
+            self.fast_frames = 0;
+        }
+        self.level
+    }
+}
+
+// Shrinks the kernel of a quad blurring the background behind it. Quads with
+// an external blur, such as shadows, keep their look.
+pub(crate) fn scale_background_blur(quad: &mut InstancedQuad, scale: f32) {
+    if quad.blur < 0.0 {
+        quad.blur = (quad.blur * scale).min(-1.0);
+    }
+}
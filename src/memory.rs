@@ -0,0 +1,129 @@
+use wgpu::*;
+
+// Number, size and high-water mark of a kind of gpu resource
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub count: usize,
+    pub bytes: u64,
+    pub peak_bytes: u64,
+}
+
+impl ResourceUsage {
+    pub fn add_buffer(&mut self, buffer: &Buffer) {
+        self.count += 1;
+        self.bytes += buffer.size();
+    }
+
+    pub fn add_texture(&mut self, texture: &Texture) {
+        let size = texture.size();
+        let block_size = texture.format().block_copy_size(None).unwrap_or(4);
+        self.count += 1;
+        self.bytes += size.width as u64
+            * size.height as u64
+            * size.depth_or_array_layers as u64
+            * block_size as u64
+            * texture.sample_count() as u64;
+    }
+
+    fn record_peak(&mut self, previous: &ResourceUsage) {
+        self.peak_bytes = self.bytes.max(self.peak_bytes).max(previous.peak_bytes);
+    }
+}
+
+// Space used in an atlas texture by the glyphs or images currently uploaded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AtlasUsage {
+    pub allocations: usize,
+    pub used_bytes: u64,
+    pub capacity_bytes: u64,
+    pub peak_used_bytes: u64,
+    // Entries removed so far to stay within the budget
+    pub evictions: usize,
+}
+
+impl AtlasUsage {
+    fn record_peak(&mut self, previous: &AtlasUsage) {
+        self.peak_used_bytes = self
+            .used_bytes
+            .max(self.peak_used_bytes)
+            .max(previous.peak_used_bytes);
+    }
+}
+
+// Gpu memory allocated by the renderer and its drawables. The atlas textures
+// are counted in textures as well.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub buffers: ResourceUsage,
+    pub textures: ResourceUsage,
+    pub glyph_atlas: AtlasUsage,
+    pub image_atlas: AtlasUsage,
+}
+
+impl MemoryReport {
+    pub fn total_bytes(&self) -> u64 {
+        self.buffers.bytes + self.textures.bytes
+    }
+
+    // Raises the high-water marks to the current usage and the marks of an
+    // earlier report
+    pub(crate) fn record_peaks(&mut self, previous: &MemoryReport) {
+        self.buffers.record_peak(&previous.buffers);
+        self.textures.record_peak(&previous.textures);
+        self.glyph_atlas.record_peak(&previous.glyph_atlas);
+        self.image_atlas.record_peak(&previous.image_atlas);
+    }
+}
+
+// Limits on the atlas space in bytes. When an atlas uses more at the start of
+// a frame, the least recently drawn entries are evicted until it fits. Evicted
+// glyphs and images are uploaded again the next time they are drawn. The
+// instance buffers have a fixed size and are not part of the budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryBudget {
+    pub glyph_atlas_bytes: Option<u64>,
+    pub image_atlas_bytes: Option<u64>,
+}
+
+impl MemoryBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_glyph_atlas_bytes(mut self, bytes: u64) -> Self {
+        self.glyph_atlas_bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_image_atlas_bytes(mut self, bytes: u64) -> Self {
+        self.image_atlas_bytes = Some(bytes);
+        self
+    }
+}
+
+// Picks the least recently used entries to evict so that the used bytes fit
+// the budget. The entries are the key, size in bytes and the frame the entry
+// was last used in.
+pub(crate) fn entries_over_budget<K>(
+    entries: impl IntoIterator<Item = (K, u64, u64)>,
+    used_bytes: u64,
+    budget: u64,
+) -> Vec<K> {
+    if used_bytes <= budget {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<_> = entries.into_iter().collect();
+    candidates.sort_by_key(|(_, _, last_used)| *last_used);
+
+    let mut remaining = used_bytes;
+    candidates
+        .into_iter()
+        .take_while(|(_, bytes, _)| {
+            let over = remaining > budget;
+            remaining = remaining.saturating_sub(*bytes);
+            over
+        })
+        .map(|(key, _, _)| key)
+        .collect()
+}
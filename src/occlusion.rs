@@ -0,0 +1,116 @@
+use glam::{vec4, Vec4, Vec4Swizzles};
+use shader::ShaderConstants;
+
+use crate::{
+    renderer::{clip_to_surface, intersect_rects, scissor_rect},
+    scene::{Layer, Scene},
+};
+
+// Work skipped by the last render because layers were completely hidden
+// beneath opaque layers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OcclusionStats {
+    pub layers_skipped: usize,
+    pub primitives_skipped: usize,
+}
+
+// Returns whether each layer of the scene is completely covered by the opaque
+// background of a layer above it. The analysis is conservative: only layer
+// backgrounds without blur occlude, and nothing is hidden while the camera is
+// rotated since the backgrounds are then not axis aligned on the surface.
+pub(crate) fn hidden_layers(
+    scene: &Scene,
+    constants: &ShaderConstants,
+) -> (Vec<bool>, OcclusionStats) {
+    let mut hidden = vec![false; scene.layers.len()];
+    let mut stats = OcclusionStats::default();
+    if scene.camera.rotation != 0.0 {
+        return (hidden, stats);
+    }
+
+    let mut occluders: Vec<Vec4> = Vec::new();
+    for (index, layer) in scene.layers.iter().enumerate().rev() {
+        let bounds = layer_surface_bounds(constants, layer);
+        if occluders
+            .iter()
+            .any(|occluder| rect_contains_rect(*occluder, bounds))
+        {
+            hidden[index] = true;
+            stats.layers_skipped += 1;
+            stats.primitives_skipped += primitive_count(layer);
+            continue;
+        }
+
+        if let Some(covered) = opaque_surface_rect(constants, layer) {
+            occluders.push(covered);
+        }
+    }
+
+    (hidden, stats)
+}
+
+// Surface space rect which the layer can draw to. Rounded outwards so that
+// it contains the scissor rect of the clip.
+fn layer_surface_bounds(constants: &ShaderConstants, layer: &Layer) -> Vec4 {
+    let surface = vec4(0., 0., constants.surface_size.x, constants.surface_size.y);
+    match layer.clip {
+        Some(clip) => {
+            let clip = clip_to_surface(constants, clip);
+            let min = clip.xy().floor();
+            let max = (clip.xy() + clip.zw()).ceil();
+            intersect_rects(surface, vec4(min.x, min.y, max.x - min.x, max.y - min.y))
+        }
+        None => surface,
+    }
+}
+
+// Surface space rect of whole pixels which the layer background paints fully
+// opaque, if any. Pixels are only fully covered when their center is at least
+// half the antialiasing ramp inside the background edge.
+fn opaque_surface_rect(constants: &ShaderConstants, layer: &Layer) -> Option<Vec4> {
+    let color = layer.background_color?;
+    if color.w < 1.0 || layer.background_blur_radius != 0.0 {
+        return None;
+    }
+
+    let surface = vec4(0., 0., constants.surface_size.x, constants.surface_size.y);
+    let (background, scissor) = match layer.clip {
+        Some(clip) => {
+            let [x, y, w, h] = scissor_rect(constants, clip);
+            (
+                clip_to_surface(constants, clip),
+                vec4(x as f32, y as f32, w as f32, h as f32),
+            )
+        }
+        None => (surface, surface),
+    };
+
+    let inset = constants.antialiasing_width / 2.0 - 0.5;
+    let min = (background.xy() + inset).ceil();
+    let max = (background.xy() + background.zw() - inset).floor();
+    let covered = intersect_rects(scissor, vec4(min.x, min.y, max.x - min.x, max.y - min.y));
+    (covered.z > 0.0 && covered.w > 0.0).then_some(covered)
+}
+
+fn primitive_count(layer: &Layer) -> usize {
+    layer.quads.len()
+        + layer.shadows.len()
+        + layer.texts.len()
+        + layer.paths.len()
+        + layer.sprites.len()
+        + layer.procedurals.len()
+        + layer.shapes.len()
+        + layer.backdrop_blurs.len()
+        + layer.carets.len()
+        + layer.cursor_trails.len()
+        + layer.checkerboards.len()
+}
+
+fn rect_contains_rect(outer: Vec4, inner: Vec4) -> bool {
+    inner.z <= 0.0
+        || inner.w <= 0.0
+        || (inner.xy().cmpge(outer.xy()).all()
+            && (inner.xy() + inner.zw())
+                .cmple(outer.xy() + outer.zw())
+                .all())
+}
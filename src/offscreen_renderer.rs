@@ -1,61 +1,436 @@
 use futures_intrusive::channel::shared::oneshot_channel;
-use image::{imageops::crop_imm, ImageBuffer, Rgba};
-use rust_embed::RustEmbed;
-use wgpu::{Instance, PowerPreference, RequestAdapterOptions};
+#[cfg(feature = "image")]
+use image::{DynamicImage, Rgba32FImage, RgbaImage};
+use shader::unpack_f16x2;
+use wgpu::{Instance, TextureFormat};
 
-use crate::{renderer::Drawable, Renderer, Scene};
+use crate::{
+    adapter::{create_instance, request_adapter},
+    renderer::{Drawable, DrawableError, RendererError},
+    AssetSource, ColorLut, CoordinateOrigin, GlyphZoomPolicy, GpuDiagnostics, Renderer, Scene,
+    TextRendering, Theme, Viewport,
+};
+
+// Format the offscreen renderer draws and reads back the frames in. The
+// srgb formats encode the colors for display, while the others hold the
+// linear colors the shaders output. The bgra formats match the native
+// layout of most os image apis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OffscreenFormat {
+    #[default]
+    Rgba8Srgb,
+    Rgba8Unorm,
+    Bgra8Srgb,
+    Bgra8Unorm,
+    // Half floats, which keep the colors outside of the zero to one range
+    Rgba16Float,
+}
+
+impl OffscreenFormat {
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            OffscreenFormat::Rgba8Srgb => TextureFormat::Rgba8UnormSrgb,
+            OffscreenFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+            OffscreenFormat::Bgra8Srgb => TextureFormat::Bgra8UnormSrgb,
+            OffscreenFormat::Bgra8Unorm => TextureFormat::Bgra8Unorm,
+            OffscreenFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> u32 {
+        match self {
+            OffscreenFormat::Rgba16Float => 8,
+            _ => 4,
+        }
+    }
+
+    pub fn is_bgra(self) -> bool {
+        matches!(
+            self,
+            OffscreenFormat::Bgra8Srgb | OffscreenFormat::Bgra8Unorm
+        )
+    }
+}
+
+// A frame read back in the format of the offscreen renderer
+#[cfg(feature = "image")]
+#[derive(Debug, Clone)]
+pub enum OffscreenImage {
+    // Srgb or linear, depending on the format
+    Rgba8(RgbaImage),
+    // The image crate has no bgra pixels, so the tightly packed bytes are
+    // kept as they were read back
+    Bgra8 {
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    },
+    Rgba32F(Rgba32FImage),
+}
+
+#[cfg(feature = "image")]
+impl OffscreenImage {
+    // Converts to rgba8 pixels in the same encoding. Float colors are clamped
+    // to the zero to one range.
+    pub fn into_rgba8(self) -> RgbaImage {
+        match self {
+            OffscreenImage::Rgba8(image) => image,
+            OffscreenImage::Bgra8 {
+                width,
+                height,
+                mut data,
+            } => {
+                swap_red_blue(&mut data);
+                RgbaImage::from_raw(width, height, data).unwrap()
+            }
+            OffscreenImage::Rgba32F(image) => DynamicImage::ImageRgba32F(image).into_rgba8(),
+        }
+    }
+}
 
 pub struct OffscreenRenderer {
     pub instance: Instance,
     pub renderer: Renderer,
+    format: OffscreenFormat,
 }
 
 impl OffscreenRenderer {
-    // Creating some of the wgpu types requires async code
-    pub async fn new(width: u32, height: u32) -> Self {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::VULKAN,
-            ..Default::default()
-        });
+    // Creating some of the wgpu types requires async code. Sizes beyond the
+    // maximum texture size are rendered scaled down, so the drawn images can
+    // be smaller than requested. Fails when no adapter can run the renderer.
+    pub async fn new(width: u32, height: u32) -> Result<Self, RendererError> {
+        Self::with_format(width, height, OffscreenFormat::default()).await
+    }
 
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: None,
-            })
+    pub async fn with_format(
+        width: u32,
+        height: u32,
+        format: OffscreenFormat,
+    ) -> Result<Self, RendererError> {
+        // VIDE_BACKEND and VIDE_ADAPTER pick another gpu than the default
+        let instance = create_instance();
+        let adapter = request_adapter(&instance, None)
             .await
-            .unwrap();
+            .ok_or(RendererError::NoAdapter)?;
 
-        let renderer =
-            Renderer::new(width, height, adapter, wgpu::TextureFormat::Rgba8UnormSrgb).await;
+        let renderer = Renderer::new(width, height, adapter, format.texture_format()).await?;
 
-        Self { instance, renderer }
+        Ok(Self {
+            instance,
+            renderer,
+            format,
+        })
+    }
+
+    pub fn format(&self) -> OffscreenFormat {
+        self.format
+    }
+
+    // The adapter, backend, limits, features and formats the renderer runs
+    // with, along with the adapters it could have picked. The surface
+    // formats are those the adapter can render to.
+    pub fn diagnostics(&self) -> GpuDiagnostics {
+        GpuDiagnostics::new(&self.instance, &self.renderer, None)
     }
 
     pub fn resize(&mut self, new_width: u32, new_height: u32) {
         self.renderer.resize(new_width, new_height);
     }
 
-    pub fn add_drawable<T: Drawable + 'static>(&mut self) {
-        self.renderer.add_drawable::<T>();
+    pub fn add_drawable<T: Drawable + 'static>(&mut self) -> Result<(), DrawableError> {
+        self.renderer.add_drawable::<T>()
     }
 
     pub fn with_drawable<T: Drawable + 'static>(mut self) -> Self {
-        self.add_drawable::<T>();
+        if let Err(error) = self.add_drawable::<T>() {
+            panic!("{error}");
+        }
+        self
+    }
+
+    pub fn set_antialiasing_width(&mut self, width: f32) {
+        self.renderer.set_antialiasing_width(width);
+    }
+
+    pub fn with_antialiasing_width(mut self, width: f32) -> Self {
+        self.set_antialiasing_width(width);
+        self
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.renderer.set_scale_factor(scale_factor);
+    }
+
+    pub fn with_scale_factor(mut self, scale_factor: f32) -> Self {
+        self.set_scale_factor(scale_factor);
+        self
+    }
+
+    pub fn set_coordinate_origin(&mut self, origin: CoordinateOrigin) {
+        self.renderer.set_coordinate_origin(origin);
+    }
+
+    pub fn with_coordinate_origin(mut self, origin: CoordinateOrigin) -> Self {
+        self.set_coordinate_origin(origin);
+        self
+    }
+
+    pub fn set_pixels_per_unit(&mut self, pixels_per_unit: f32) {
+        self.renderer.set_pixels_per_unit(pixels_per_unit);
+    }
+
+    pub fn with_pixels_per_unit(mut self, pixels_per_unit: f32) -> Self {
+        self.set_pixels_per_unit(pixels_per_unit);
+        self
+    }
+
+    pub fn set_fixed_time_step(&mut self, time_step: Option<f32>) {
+        self.renderer.set_fixed_time_step(time_step);
+    }
+
+    pub fn with_fixed_time_step(mut self, time_step: Option<f32>) -> Self {
+        self.set_fixed_time_step(time_step);
+        self
+    }
+
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.renderer.set_deterministic(deterministic);
+    }
+
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.set_deterministic(deterministic);
+        self
+    }
+
+    pub fn set_validate_scenes(&mut self, enabled: bool) {
+        self.renderer.set_validate_scenes(enabled);
+    }
+
+    pub fn with_validate_scenes(mut self, enabled: bool) -> Self {
+        self.set_validate_scenes(enabled);
+        self
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.renderer.set_theme(theme);
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.set_theme(theme);
+        self
+    }
+
+    pub fn set_color_lut(&mut self, lut: Option<ColorLut>) {
+        self.renderer.set_color_lut(lut);
+    }
+
+    pub fn with_color_lut(mut self, lut: ColorLut) -> Self {
+        self.set_color_lut(Some(lut));
+        self
+    }
+
+    pub fn set_color_grading_strength(&mut self, strength: f32) {
+        self.renderer.set_color_grading_strength(strength);
+    }
+
+    pub fn with_color_grading_strength(mut self, strength: f32) -> Self {
+        self.set_color_grading_strength(strength);
+        self
+    }
+
+    pub fn set_background_glyph_rasterization(&mut self, enabled: bool) {
+        self.renderer.set_background_glyph_rasterization(enabled);
+    }
+
+    pub fn with_background_glyph_rasterization(mut self, enabled: bool) -> Self {
+        self.set_background_glyph_rasterization(enabled);
+        self
+    }
+
+    pub fn set_glyph_rasterizer_threads(&mut self, threads: usize) {
+        self.renderer.set_glyph_rasterizer_threads(threads);
+    }
+
+    pub fn with_glyph_rasterizer_threads(mut self, threads: usize) -> Self {
+        self.set_glyph_rasterizer_threads(threads);
+        self
+    }
+
+    pub fn set_synthetic_box_drawing(&mut self, enabled: bool) {
+        self.renderer.set_synthetic_box_drawing(enabled);
+    }
+
+    pub fn with_synthetic_box_drawing(mut self, enabled: bool) -> Self {
+        self.set_synthetic_box_drawing(enabled);
+        self
+    }
+
+    pub fn set_missing_glyph_boxes(&mut self, enabled: bool) {
+        self.renderer.set_missing_glyph_boxes(enabled);
+    }
+
+    pub fn with_missing_glyph_boxes(mut self, enabled: bool) -> Self {
+        self.set_missing_glyph_boxes(enabled);
+        self
+    }
+
+    pub fn set_text_rendering(&mut self, text_rendering: TextRendering) {
+        self.renderer.set_text_rendering(text_rendering);
+    }
+
+    pub fn with_text_rendering(mut self, text_rendering: TextRendering) -> Self {
+        self.set_text_rendering(text_rendering);
+        self
+    }
+
+    pub fn set_font_synthesis(&mut self, enabled: bool) {
+        self.renderer.set_font_synthesis(enabled);
+    }
+
+    pub fn with_font_synthesis(mut self, enabled: bool) -> Self {
+        self.set_font_synthesis(enabled);
+        self
+    }
+
+    pub fn set_glyph_zoom_policy(&mut self, policy: GlyphZoomPolicy) {
+        self.renderer.set_glyph_zoom_policy(policy);
+    }
+
+    pub fn with_glyph_zoom_policy(mut self, policy: GlyphZoomPolicy) -> Self {
+        self.set_glyph_zoom_policy(policy);
+        self
+    }
+
+    pub fn set_shaping_cache_capacity(&mut self, capacity: usize) {
+        self.renderer.set_shaping_cache_capacity(capacity);
+    }
+
+    pub fn with_shaping_cache_capacity(mut self, capacity: usize) -> Self {
+        self.set_shaping_cache_capacity(capacity);
+        self
+    }
+
+    pub fn set_background_asset_loading(&mut self, enabled: bool) {
+        self.renderer.set_background_asset_loading(enabled);
+    }
+
+    pub fn with_background_asset_loading(mut self, enabled: bool) -> Self {
+        self.set_background_asset_loading(enabled);
         self
     }
 
-    pub fn add_default_drawables<A: RustEmbed + 'static>(&mut self) {
-        self.renderer.add_default_drawables::<A>();
+    pub fn add_builtin_drawables(&mut self) -> Result<(), DrawableError> {
+        self.renderer.add_builtin_drawables()
     }
 
-    pub fn with_default_drawables<A: RustEmbed + 'static>(mut self) -> Self {
-        self.add_default_drawables::<A>();
+    pub fn with_builtin_drawables(mut self) -> Self {
+        if let Err(error) = self.add_builtin_drawables() {
+            panic!("{error}");
+        }
         self
     }
 
-    pub async fn draw(&mut self, scene: &Scene) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    pub fn add_default_drawables(
+        &mut self,
+        assets: impl AssetSource + 'static,
+    ) -> Result<(), DrawableError> {
+        self.renderer.add_default_drawables(assets)
+    }
+
+    pub fn with_default_drawables(mut self, assets: impl AssetSource + 'static) -> Self {
+        if let Err(error) = self.add_default_drawables(assets) {
+            panic!("{error}");
+        }
+        self
+    }
+
+    pub fn set_asset_source(&mut self, assets: impl AssetSource + 'static) {
+        self.renderer.set_asset_source(assets);
+    }
+
+    pub fn with_asset_source(mut self, assets: impl AssetSource + 'static) -> Self {
+        self.set_asset_source(assets);
+        self
+    }
+
+    // Renders the scene as rgba8 pixels, converted from the format
+    #[cfg(feature = "image")]
+    pub async fn draw(&mut self, scene: &Scene) -> RgbaImage {
+        self.draw_image(scene).await.into_rgba8()
+    }
+
+    // Renders the scene into an image of the format
+    #[cfg(feature = "image")]
+    pub async fn draw_image(&mut self, scene: &Scene) -> OffscreenImage {
+        let data = self.draw_pixels(scene).await;
+        self.image(data)
+    }
+
+    // Renders each scene into its viewport and reads back the whole frame
+    #[cfg(feature = "image")]
+    pub async fn draw_viewports(&mut self, viewports: &[(Scene, Viewport)]) -> RgbaImage {
+        let data = self
+            .read_back(|renderer, texture, encoder| {
+                renderer.submit_and_render_viewports(viewports, texture, encoder)
+            })
+            .await;
+        self.image(data).into_rgba8()
+    }
+
+    #[cfg(feature = "image")]
+    fn image(&self, data: Vec<u8>) -> OffscreenImage {
+        let (width, height) = (self.renderer.width, self.renderer.height);
+        match self.format {
+            OffscreenFormat::Rgba8Srgb | OffscreenFormat::Rgba8Unorm => {
+                OffscreenImage::Rgba8(RgbaImage::from_raw(width, height, data).unwrap())
+            }
+            OffscreenFormat::Bgra8Srgb | OffscreenFormat::Bgra8Unorm => OffscreenImage::Bgra8 {
+                width,
+                height,
+                data,
+            },
+            OffscreenFormat::Rgba16Float => OffscreenImage::Rgba32F(
+                Rgba32FImage::from_raw(width, height, half_floats(&data)).unwrap(),
+            ),
+        }
+    }
+
+    // Renders the scene and reads it back as tightly packed rgba8 pixels, row
+    // by row from the top left. An empty renderer renders nothing and returns
+    // no pixels.
+    pub async fn draw_rgba(&mut self, scene: &Scene) -> Vec<u8> {
+        let mut data = self.draw_pixels(scene).await;
+        match self.format {
+            OffscreenFormat::Rgba8Srgb | OffscreenFormat::Rgba8Unorm => {}
+            OffscreenFormat::Bgra8Srgb | OffscreenFormat::Bgra8Unorm => swap_red_blue(&mut data),
+            OffscreenFormat::Rgba16Float => {
+                data = half_floats(&data)
+                    .into_iter()
+                    .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+                    .collect();
+            }
+        }
+        data
+    }
+
+    // Renders the scene and reads back the tightly packed pixels in the
+    // format, row by row from the top left
+    pub async fn draw_pixels(&mut self, scene: &Scene) -> Vec<u8> {
+        self.read_back(|renderer, texture, encoder| {
+            renderer.submit_and_render(scene, texture, encoder)
+        })
+        .await
+    }
+
+    // Reads back the frame the render function records into the encoder
+    async fn read_back(
+        &mut self,
+        render: impl FnOnce(&mut Renderer, &wgpu::Texture, wgpu::CommandEncoder) -> wgpu::CommandEncoder,
+    ) -> Vec<u8> {
+        if self.renderer.is_empty() {
+            return Vec::new();
+        }
+
         let texture_desc = wgpu::TextureDescriptor {
             size: wgpu::Extent3d {
                 width: self.renderer.width,
@@ -65,27 +440,24 @@ impl OffscreenRenderer {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: self.format.texture_format(),
             usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::RENDER_ATTACHMENT,
             label: None,
             view_formats: &[],
         };
         let texture = self.renderer.device.create_texture(&texture_desc);
 
-        self.renderer.render(scene, &texture);
-
-        let mut encoder = self
+        // The read back is recorded after the commands of the frame
+        let encoder = self
             .renderer
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut encoder = render(&mut self.renderer, &texture, encoder);
 
-        let u32_size = std::mem::size_of::<u32>() as u32;
-        let bytes_per_row = u32_size * self.renderer.width;
+        let bytes_per_row = self.format.bytes_per_pixel() * self.renderer.width;
         // The bytes_per_row must be padded to be aligned to COPY_BYTES_PER_ROW_ALIGNMENT (256)
-        let padding =
-            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT - bytes_per_row % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
-        let padded_bytes_per_row = bytes_per_row + padding;
-        let padded_width = padded_bytes_per_row / u32_size;
+        let padded_bytes_per_row =
+            wgpu::util::align_to(bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
         let output_buffer_size =
             (padded_bytes_per_row * self.renderer.height) as wgpu::BufferAddress;
         let output_buffer_desc = wgpu::BufferDescriptor {
@@ -117,6 +489,7 @@ impl OffscreenRenderer {
         );
 
         self.renderer.queue.submit(Some(encoder.finish()));
+        self.renderer.frame_submitted();
 
         let buffer_slice = output_buffer.slice(..);
 
@@ -129,17 +502,28 @@ impl OffscreenRenderer {
         self.renderer.device.poll(wgpu::Maintain::Wait);
         rx.receive().await.unwrap().unwrap();
 
-        let data = buffer_slice.get_mapped_range().to_vec();
-        let padded_image =
-            ImageBuffer::<Rgba<u8>, _>::from_raw(padded_width, self.renderer.height, data).unwrap();
+        // Strip the row padding
+        let padded_data = buffer_slice.get_mapped_range();
+        padded_data
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| &row[..bytes_per_row as usize])
+            .copied()
+            .collect()
+    }
+}
 
-        crop_imm(
-            &padded_image,
-            0,
-            0,
-            self.renderer.width,
-            self.renderer.height,
-        )
-        .to_image()
+fn swap_red_blue(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
     }
 }
+
+// Channels of half float pixels as f32
+pub(crate) fn half_floats(data: &[u8]) -> Vec<f32> {
+    data.chunks_exact(4)
+        .flat_map(|pair| {
+            let halves = unpack_f16x2(u32::from_le_bytes(pair.try_into().unwrap()));
+            [halves.x, halves.y]
+        })
+        .collect()
+}
@@ -0,0 +1,27 @@
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+// Maps the items using per thread state created by init. The items are split
+// across the rayon thread pool when the rayon feature is enabled and mapped in
+// order on the current thread otherwise.
+#[cfg(feature = "rayon")]
+pub(crate) fn map_init<T: Send, S, R: Send>(
+    items: Vec<T>,
+    init: impl Fn() -> S + Sync + Send,
+    map: impl Fn(&mut S, T) -> R + Sync + Send,
+) -> Vec<R> {
+    items.into_par_iter().map_init(init, map).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+pub(crate) fn map_init<T: Send, S, R: Send>(
+    items: Vec<T>,
+    init: impl Fn() -> S + Sync + Send,
+    map: impl Fn(&mut S, T) -> R + Sync + Send,
+) -> Vec<R> {
+    let mut state = init();
+    items
+        .into_iter()
+        .map(|item| map(&mut state, item))
+        .collect()
+}
@@ -1,35 +1,55 @@
-use glam::vec2;
-use lyon::{
-    geom::point,
-    lyon_tessellation::{
-        BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator,
-        StrokeVertex, VertexBuffers,
-    },
-    path::Path,
-};
+mod cache;
+
+use std::sync::{Arc, Mutex};
+
+use glam::{Vec2, Vec4, Vec4Swizzles};
+use lyon::lyon_tessellation::VertexBuffers;
 use shader::{PathVertex, ShaderConstants};
 use wgpu::*;
 
 use crate::{
-    renderer::{Drawable, Renderer},
-    scene::{Layer, PathCommand},
+    image_atlas::ImageAtlas,
+    memory::{MemoryBudget, MemoryReport},
+    renderer::{rects_overlap, visible_content_rect, Drawable, Renderer},
+    scene::{Layer, Pattern},
+    shader_layout::PipelineInterface,
+    uploader::Uploader,
 };
 
+pub use cache::*;
+
 pub struct PathState {
     vertex_buffer: Buffer,
     index_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
     render_pipeline: RenderPipeline,
+    tessellation_cache: TessellationCache,
+
+    image_atlas: Arc<Mutex<ImageAtlas>>,
+}
+
+impl PathState {
+    pub fn tessellation_cache_stats(&self) -> TessellationCacheStats {
+        self.tessellation_cache.stats()
+    }
+
+    pub fn set_tessellation_cache_capacity(&mut self, capacity: usize) {
+        self.tessellation_cache.set_capacity(capacity);
+    }
+
+    pub fn clear_tessellation_cache(&mut self) {
+        self.tessellation_cache.clear();
+    }
 }
 
 impl Drawable for PathState {
-    fn new(
-        Renderer {
+    fn new(renderer: &Renderer) -> Self {
+        let Renderer {
             device,
-            shader,
-            format,
+            image_atlas,
             ..
-        }: &Renderer,
-    ) -> Self {
+        } = renderer;
         let vertex_buffer = device.create_buffer(&BufferDescriptor {
             label: Some("Path Vertex Buffer"),
             size: std::mem::size_of::<PathVertex>() as u64 * 100000,
@@ -44,148 +64,232 @@ impl Drawable for PathState {
             mapped_at_creation: false,
         });
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Path render pipeline"),
-            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
-                label: Some("Path Pipeline layout"),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[PushConstantRange {
-                    stages: ShaderStages::all(),
-                    range: 0..std::mem::size_of::<ShaderConstants>() as u32,
-                }],
-            })),
-            vertex: VertexState {
-                module: &shader,
-                entry_point: "path::path_vertex",
-                buffers: &[VertexBufferLayout {
-                    array_stride: std::mem::size_of::<PathVertex>() as BufferAddress,
-                    step_mode: VertexStepMode::Vertex,
-                    attributes: &vertex_attr_array![0 => Float32x4, 1 => Float32x2, 2 => Float32x2],
-                }],
+        let layout_entries = [BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
             },
-            fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: "path::path_fragment",
-                targets: &[Some(ColorTargetState {
-                    format: *format,
-                    blend: Some(BlendState::ALPHA_BLENDING),
-                    write_mask: ColorWrites::ALL,
-                })],
-            }),
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: None,
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: MultisampleState {
-                count: 4,
-                ..Default::default()
-            },
-            multiview: None,
+            count: None,
+        }];
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Path bind group layout"),
+            entries: &layout_entries,
         });
 
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Path bind group"),
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(image_atlas.lock().unwrap().view()),
+            }],
+        });
+
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &["path::path_vertex", "path::path_fragment"],
+            bind_group_layouts: &[&layout_entries, &renderer.universal_layout_entries()],
+            vertex_buffers: &vertex_buffers(),
+        });
+
+        let render_pipeline = create_pipeline(renderer, &bind_group_layout);
+
         Self {
             vertex_buffer,
             index_buffer,
+            bind_group_layout,
+            bind_group,
             render_pipeline,
+            tessellation_cache: TessellationCache::new(DEFAULT_TESSELLATION_CACHE_CAPACITY),
+
+            image_atlas: image_atlas.clone(),
         }
     }
 
+    fn name(&self) -> &'static str {
+        "path"
+    }
+
+    fn prepare(&mut self, _queue: &Queue, constants: &ShaderConstants, layers: &[&Layer]) {
+        self.tessellation_cache
+            .prepare(layers.iter().flat_map(|layer| {
+                let visible_rect = visible_content_rect(constants, layer);
+                layer
+                    .paths
+                    .iter()
+                    .filter(move |path| rects_overlap(path.bounds(), visible_rect))
+            }));
+    }
+
     fn draw<'b, 'a: 'b>(
         &'a mut self,
-        queue: &Queue,
+        uploader: &mut Uploader,
         render_pass: &mut RenderPass<'b>,
         constants: ShaderConstants,
-        _universal_bind_group: &'a BindGroup,
+        universal_bind_group: &'a BindGroup,
         layer: &Layer,
     ) {
         let mut geometry: VertexBuffers<PathVertex, u32> = VertexBuffers::new();
-        let mut fill_tesselator = FillTessellator::new();
-        let mut stroke_tesselator = StrokeTessellator::new();
-
-        for scene_path in layer.paths.iter() {
-            let mut builder = Path::builder();
-            builder.begin(point(scene_path.start.x, scene_path.start.y));
-            for path_command in scene_path.commands.iter() {
-                match path_command {
-                    PathCommand::LineTo { to } => {
-                        builder.line_to(point(to.x, to.y));
-                    }
-                    PathCommand::QuadraticBezierTo { control, to } => {
-                        builder.quadratic_bezier_to(point(control.x, control.y), point(to.x, to.y));
-                    }
-                    PathCommand::CubicBezierTo {
-                        control1,
-                        control2,
-                        to,
-                    } => {
-                        builder.cubic_bezier_to(
-                            point(control1.x, control1.y),
-                            point(control2.x, control2.y),
-                            point(to.x, to.y),
-                        );
-                    }
+
+        let visible_rect = visible_content_rect(&constants, layer);
+        let mut image_atlas = self.image_atlas.lock().unwrap();
+        for scene_path in layer
+            .paths
+            .iter()
+            .filter(|path| rects_overlap(path.bounds(), visible_rect))
+        {
+            let path_geometry = self.tessellation_cache.get_or_tessellate(scene_path);
+
+            // Beneath the fill so that only the part outside the path shows
+            if let Some(outline) = scene_path.outline {
+                for (alpha, stroke) in path_geometry.outline.iter() {
+                    let mut color = outline.color;
+                    color.w *= alpha;
+                    append_geometry(&mut geometry, stroke, color, None);
                 }
             }
-            builder.close();
-            let path = builder.build();
 
             if let Some(fill) = scene_path.fill {
-                fill_tesselator
-                    .tessellate_path(
-                        &path,
-                        &FillOptions::default(),
-                        &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| PathVertex {
-                            color: fill,
-                            position: vec2(vertex.position().x, vertex.position().y),
-                            ..Default::default()
-                        }),
-                    )
-                    .expect("Could not tesselate path");
+                let pattern = scene_path.fill_pattern.as_ref().map(|pattern| {
+                    let atlas_rect = image_atlas.get_or_upload(uploader.queue(), &pattern.image);
+                    (pattern, atlas_rect, scene_path.bounds().xy())
+                });
+                append_geometry(&mut geometry, &path_geometry.fill, fill, pattern);
             }
 
-            if let Some((width, stroke)) = scene_path.stroke {
-                stroke_tesselator
-                    .tessellate_path(
-                        &path,
-                        &StrokeOptions::default().with_line_width(width),
-                        &mut BuffersBuilder::new(&mut geometry, |vertex: StrokeVertex| {
-                            PathVertex {
-                                color: stroke,
-                                position: vec2(vertex.position().x, vertex.position().y),
-                                ..Default::default()
-                            }
-                        }),
-                    )
-                    .expect("Could not tesselate path");
+            if let Some((_, stroke)) = scene_path.stroke {
+                append_geometry(&mut geometry, &path_geometry.stroke, stroke, None);
             }
+        }
+        drop(image_atlas);
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_push_constants(
-                ShaderStages::all(),
-                0,
-                bytemuck::cast_slice(&[constants]),
-            );
-
-            queue.write_buffer(
-                &self.vertex_buffer,
-                0,
-                bytemuck::cast_slice(&geometry.vertices[..]),
-            );
-            queue.write_buffer(
-                &self.index_buffer,
-                0,
-                bytemuck::cast_slice(&geometry.indices[..]),
-            );
-
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint32);
-            render_pass.draw_indexed(0..geometry.indices.len() as u32, 0, 0..1);
+        if geometry.indices.is_empty() {
+            return;
         }
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_push_constants(ShaderStages::all(), 0, bytemuck::cast_slice(&[constants]));
+
+        uploader.write_buffer(
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&geometry.vertices[..]),
+        );
+        uploader.write_buffer(
+            &self.index_buffer,
+            0,
+            bytemuck::cast_slice(&geometry.indices[..]),
+        );
+
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, universal_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint32);
+        render_pass.draw_indexed(0..geometry.indices.len() as u32, 0, 0..1);
+    }
+
+    fn memory_usage(&self, report: &mut MemoryReport) {
+        report.buffers.add_buffer(&self.vertex_buffer);
+        report.buffers.add_buffer(&self.index_buffer);
+    }
+
+    fn trim(&mut self, _budget: &MemoryBudget) {
+        self.tessellation_cache.trim();
     }
+
+    fn recreate_pipelines(&mut self, renderer: &Renderer) -> bool {
+        self.render_pipeline = create_pipeline(renderer, &self.bind_group_layout);
+        true
+    }
+}
+
+const VERTEX_ATTRIBUTES: [VertexAttribute; 4] =
+    vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x2, 3 => Float32x2];
+
+fn vertex_buffers() -> [VertexBufferLayout<'static>; 1] {
+    [VertexBufferLayout {
+        array_stride: std::mem::size_of::<PathVertex>() as BufferAddress,
+        step_mode: VertexStepMode::Vertex,
+        attributes: &VERTEX_ATTRIBUTES,
+    }]
+}
+
+fn create_pipeline(renderer: &Renderer, bind_group_layout: &BindGroupLayout) -> RenderPipeline {
+    let Renderer {
+        device,
+        shader,
+        format,
+        universal_bind_group_layout,
+        ..
+    } = renderer;
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Path render pipeline"),
+        layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Path Pipeline layout"),
+            bind_group_layouts: &[bind_group_layout, universal_bind_group_layout],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::all(),
+                range: 0..std::mem::size_of::<ShaderConstants>() as u32,
+            }],
+        })),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "path::path_vertex",
+            buffers: &vertex_buffers(),
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "path::path_fragment",
+            targets: &[Some(ColorTargetState {
+                format: *format,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: MultisampleState {
+            count: renderer.sample_count(),
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+fn append_geometry(
+    geometry: &mut VertexBuffers<PathVertex, u32>,
+    path_geometry: &VertexBuffers<Vec2, u32>,
+    color: Vec4,
+    // Fill pattern along with its rect in the image atlas and the top left of
+    // the path which the pattern is positioned relative to
+    pattern: Option<(&Pattern, Vec4, Vec2)>,
+) {
+    let base_index = geometry.vertices.len() as u32;
+    geometry
+        .vertices
+        .extend(path_geometry.vertices.iter().map(|position| {
+            let mut vertex = PathVertex {
+                color,
+                position: *position,
+                ..Default::default()
+            };
+            if let Some((pattern, atlas_rect, top_left)) = pattern {
+                vertex.pattern_atlas_rect = atlas_rect;
+                vertex.pattern_position = pattern.to_pattern_space(*position - top_left);
+            }
+            vertex
+        }));
+    geometry
+        .indices
+        .extend(path_geometry.indices.iter().map(|index| base_index + index));
 }
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use glam::{vec2, Vec2};
+use lyon::lyon_tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator,
+    StrokeVertex, VertexBuffers,
+};
+
+use crate::{
+    parallel::map_init,
+    scene::{FillRule, Path, PathCommand},
+};
+
+pub const DEFAULT_TESSELLATION_CACHE_CAPACITY: usize = 1024;
+// Strokes the softness of an outline is approximated with
+const OUTLINE_SOFTNESS_STEPS: usize = 4;
+
+// Tessellated geometry for a single path without any color information
+#[derive(Default)]
+pub struct PathGeometry {
+    pub fill: VertexBuffers<Vec2, u32>,
+    pub stroke: VertexBuffers<Vec2, u32>,
+    // Strokes making up the outline along with the fraction of the outline
+    // alpha each is drawn with
+    pub outline: Vec<(f32, VertexBuffers<Vec2, u32>)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TessellationCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+impl TessellationCacheStats {
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f32 / lookups as f32
+        }
+    }
+}
+
+// Everything the geometry hash covers. Compared on every hit so that paths
+// with colliding hashes don't share a tessellation.
+struct GeometryKey {
+    start: Vec2,
+    commands: Vec<PathCommand>,
+    fill: bool,
+    fill_rule: FillRule,
+    stroke_width: Option<f32>,
+    outline: Option<(f32, f32)>,
+    tolerance: f32,
+}
+
+impl GeometryKey {
+    fn new(path: &Path) -> Self {
+        Self {
+            start: path.start,
+            commands: path.commands.clone(),
+            fill: path.fill.is_some(),
+            fill_rule: path.fill_rule,
+            stroke_width: path.stroke.map(|(width, _)| width),
+            outline: path
+                .outline
+                .map(|outline| (outline.width, outline.softness)),
+            tolerance: path.tolerance,
+        }
+    }
+
+    // Compares the bits of the floats like the geometry hash does
+    fn matches(&self, path: &Path) -> bool {
+        let same_point =
+            |a: Vec2, b: Vec2| a.x.to_bits() == b.x.to_bits() && a.y.to_bits() == b.y.to_bits();
+        let same_command = |a: &PathCommand, b: &PathCommand| {
+            std::mem::discriminant(a) == std::mem::discriminant(b)
+                && a.points()
+                    .into_iter()
+                    .zip(b.points())
+                    .all(|(a, b)| same_point(a, b))
+        };
+
+        same_point(self.start, path.start)
+            && self.commands.len() == path.commands.len()
+            && self
+                .commands
+                .iter()
+                .zip(path.commands.iter())
+                .all(|(a, b)| same_command(a, b))
+            && self.fill == path.fill.is_some()
+            && self.fill_rule == path.fill_rule
+            && self.stroke_width.map(f32::to_bits) == path.stroke.map(|(width, _)| width.to_bits())
+            && self
+                .outline
+                .map(|(width, softness)| (width.to_bits(), softness.to_bits()))
+                == path
+                    .outline
+                    .map(|outline| (outline.width.to_bits(), outline.softness.to_bits()))
+            && self.tolerance.to_bits() == path.tolerance.to_bits()
+    }
+}
+
+struct CacheEntry {
+    key: GeometryKey,
+    geometry: PathGeometry,
+    // Lookup count when the entry was last used or prepared
+    last_used: u64,
+    // Prepared and not looked up yet. Preparing counted as the miss already.
+    prepared: bool,
+}
+
+// Least recently used cache of path tessellations keyed by the geometry hash
+// of the path
+pub struct TessellationCache {
+    capacity: usize,
+    entries: HashMap<u64, CacheEntry>,
+    lookup_count: u64,
+    stats: TessellationCacheStats,
+
+    tessellators: Tessellators,
+}
+
+impl TessellationCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            lookup_count: 0,
+            stats: Default::default(),
+
+            tessellators: Tessellators::default(),
+        }
+    }
+
+    // Tessellates every path which isn't cached yet up front, in parallel
+    // when the rayon feature is enabled. Prepared paths count as misses.
+    pub fn prepare<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>) {
+        let mut missing = HashMap::new();
+        for path in paths {
+            let key = path.geometry_hash();
+            if !self.contains(key, path) {
+                missing.entry(key).or_insert(path);
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+
+        self.stats.misses += missing.len() as u64;
+        let geometries = map_init(
+            missing.into_iter().collect(),
+            Tessellators::default,
+            |tessellators, (key, path)| {
+                (key, GeometryKey::new(path), tessellators.tessellate(path))
+            },
+        );
+        for (hash, key, geometry) in geometries {
+            self.entries.insert(
+                hash,
+                CacheEntry {
+                    key,
+                    geometry,
+                    last_used: self.lookup_count,
+                    prepared: true,
+                },
+            );
+        }
+    }
+
+    fn contains(&self, hash: u64, path: &Path) -> bool {
+        self.entries
+            .get(&hash)
+            .is_some_and(|entry| entry.key.matches(path))
+    }
+
+    pub fn get_or_tessellate(&mut self, path: &Path) -> &PathGeometry {
+        self.lookup_count += 1;
+        let hash = path.geometry_hash();
+
+        match self.entries.get(&hash) {
+            Some(entry) if entry.key.matches(path) => {
+                if !entry.prepared {
+                    self.stats.hits += 1;
+                }
+            }
+            // Missing, or a path with a colliding hash, which is replaced
+            _ => {
+                self.stats.misses += 1;
+                let geometry = self.tessellators.tessellate(path);
+                self.entries.insert(
+                    hash,
+                    CacheEntry {
+                        key: GeometryKey::new(path),
+                        geometry,
+                        last_used: 0,
+                        prepared: false,
+                    },
+                );
+            }
+        }
+
+        let entry = self.entries.get_mut(&hash).unwrap();
+        entry.last_used = self.lookup_count;
+        entry.prepared = false;
+        &entry.geometry
+    }
+
+    // Evicts the least recently used entries until the cache fits within its
+    // capacity. Called once per frame before any path is prepared or drawn,
+    // so that entries used in the current frame are never evicted mid frame.
+    pub fn trim(&mut self) {
+        if self.entries.len() > self.capacity {
+            let mut by_age: Vec<_> = self
+                .entries
+                .iter()
+                .map(|(key, entry)| (entry.last_used, *key))
+                .collect();
+            by_age.sort_unstable();
+
+            let excess = self.entries.len() - self.capacity;
+            for (_, key) in by_age.into_iter().take(excess) {
+                self.entries.remove(&key);
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.stats.entries = self.entries.len();
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.stats.entries = 0;
+    }
+
+    pub fn stats(&self) -> TessellationCacheStats {
+        self.stats
+    }
+}
+
+// Reusable lyon tessellators. Each thread tessellating paths needs its own.
+#[derive(Default)]
+struct Tessellators {
+    fill_tessellator: FillTessellator,
+    stroke_tessellator: StrokeTessellator,
+}
+
+impl Tessellators {
+    fn tessellate(&mut self, path: &Path) -> PathGeometry {
+        let lyon_path = path.to_lyon_path();
+        let mut geometry = PathGeometry::default();
+
+        if path.fill.is_some() {
+            self.fill_tessellator
+                .tessellate_path(
+                    &lyon_path,
+                    &FillOptions::default()
+                        .with_tolerance(path.tolerance)
+                        .with_fill_rule(path.fill_rule.into()),
+                    &mut BuffersBuilder::new(&mut geometry.fill, |vertex: FillVertex| {
+                        vec2(vertex.position().x, vertex.position().y)
+                    }),
+                )
+                .expect("Could not tesselate path");
+        }
+
+        if let Some((width, _)) = path.stroke {
+            self.stroke_tessellator
+                .tessellate_path(
+                    &lyon_path,
+                    &StrokeOptions::default()
+                        .with_tolerance(path.tolerance)
+                        .with_line_width(width),
+                    &mut BuffersBuilder::new(&mut geometry.stroke, |vertex: StrokeVertex| {
+                        vec2(vertex.position().x, vertex.position().y)
+                    }),
+                )
+                .expect("Could not tesselate path");
+        }
+
+        if let Some(outline) = path.outline {
+            // Strokes centered on the path reaching past the edge of the
+            // stroke by the width of the outline, and then by increasing
+            // fractions of the softness at decreasing alphas which stack into
+            // the falloff of the glow
+            let stroke_extension = path.stroke.map(|(width, _)| width / 2.0).unwrap_or(0.0);
+            let mut rings = Vec::new();
+            if outline.width > 0.0 {
+                rings.push((1.0, stroke_extension + outline.width));
+            }
+            if outline.softness > 0.0 {
+                for step in 1..=OUTLINE_SOFTNESS_STEPS {
+                    let fraction = step as f32 / OUTLINE_SOFTNESS_STEPS as f32;
+                    rings.push((
+                        1.0 / OUTLINE_SOFTNESS_STEPS as f32,
+                        stroke_extension + outline.width + outline.softness * fraction,
+                    ));
+                }
+            }
+
+            for (alpha, extension) in rings {
+                let mut buffers = VertexBuffers::new();
+                self.stroke_tessellator
+                    .tessellate_path(
+                        &lyon_path,
+                        &StrokeOptions::default()
+                            .with_tolerance(path.tolerance)
+                            .with_line_width(extension * 2.0),
+                        &mut BuffersBuilder::new(&mut buffers, |vertex: StrokeVertex| {
+                            vec2(vertex.position().x, vertex.position().y)
+                        }),
+                    )
+                    .expect("Could not tesselate path");
+                geometry.outline.push((alpha, buffers));
+            }
+        }
+
+        geometry
+    }
+}
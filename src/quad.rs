@@ -1,35 +1,103 @@
-use glam::{Vec2, Vec4, Vec4Swizzles};
+pub(crate) mod culling;
+pub(crate) mod shadow_cache;
+
+use std::sync::{Arc, Mutex};
+
+use glam::{vec2, vec4, Vec2, Vec4, Vec4Swizzles};
 use shader::{InstancedQuad, ShaderConstants};
 use wgpu::*;
 
-use crate::{renderer::Drawable, scene::Layer, Quad, Renderer};
+use culling::QuadCuller;
+use shadow_cache::ShadowCache;
+
+use crate::{
+    image_atlas::ImageAtlas,
+    lod::{scale_background_blur, LodLevel},
+    memory::{MemoryBudget, MemoryReport},
+    renderer::{rects_overlap, surface_bounds_in_scene, visible_content_rect, Drawable},
+    scene::Layer,
+    state_sorting::{grow_rect, sort_by_state, state_runs, StateSortingStats},
+    uploader::Uploader,
+    PipelineInterface, Quad, Renderer,
+};
+
+// Quads the instance buffer holds
+pub(crate) const MAX_QUADS: u64 = 100000;
 
 pub struct QuadState {
     buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
     bind_group: BindGroup,
     render_pipeline: RenderPipeline,
+    // Draws the quads which cover whole pixels with an opaque color without
+    // blending
+    opaque_pipeline: RenderPipeline,
+
+    image_atlas: Arc<Mutex<ImageAtlas>>,
+    // Coalesces adjacent quads of the same paint into one instance
+    merge_adjacent: bool,
+    // Created by the renderer the first time gpu culling is used, so that
+    // adapters without compute shaders never create its pipelines
+    culler: Option<QuadCuller>,
+    // Culls the quads with compute passes instead of on the cpu
+    gpu_culling: bool,
+    // Groups the opaque and translucent quads which don't overlap
+    state_sorting: bool,
+    state_sorting_stats: StateSortingStats,
+    shadow_cache: ShadowCache,
+    // Simplifications of the level of detail
+    shadows: bool,
+    blur_scale: f32,
+}
+
+impl QuadState {
+    pub fn set_merge_adjacent_quads(&mut self, enabled: bool) {
+        self.merge_adjacent = enabled;
+    }
+
+    pub fn set_gpu_culling(&mut self, enabled: bool) {
+        self.gpu_culling = enabled;
+    }
+
+    pub(crate) fn needs_culler(&self) -> bool {
+        self.culler.is_none()
+    }
+
+    pub(crate) fn set_culler(&mut self, culler: QuadCuller) {
+        self.culler = Some(culler);
+    }
+
+    pub fn set_state_sorting(&mut self, enabled: bool) {
+        self.state_sorting = enabled;
+    }
+
+    pub fn set_lod_level(&mut self, level: LodLevel) {
+        self.shadows = level.shadows();
+        self.blur_scale = level.blur_scale();
+    }
+
+    // Pipeline changes since the last call
+    pub fn take_state_sorting_stats(&mut self) -> StateSortingStats {
+        std::mem::take(&mut self.state_sorting_stats)
+    }
 }
 
 impl Drawable for QuadState {
-    fn new(
-        Renderer {
+    fn new(renderer: &Renderer) -> Self {
+        let Renderer {
             device,
-            universal_bind_group_layout,
-            shader,
-            format,
+            image_atlas,
             ..
-        }: &Renderer,
-    ) -> Self {
+        } = renderer;
         let buffer = device.create_buffer(&BufferDescriptor {
             label: Some("Quad buffer"),
-            size: std::mem::size_of::<InstancedQuad>() as u64 * 100000,
+            size: std::mem::size_of::<InstancedQuad>() as u64 * MAX_QUADS,
             usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
 
-        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: Some("Quad bind group layout"),
-            entries: &[BindGroupLayoutEntry {
+        let layout_entries = [
+            BindGroupLayoutEntry {
                 binding: 0,
                 visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
                 ty: BindingType::Buffer {
@@ -38,41 +106,274 @@ impl Drawable for QuadState {
                     min_binding_size: None,
                 },
                 count: None,
-            }],
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Quad bind group layout"),
+            entries: &layout_entries,
         });
 
         let bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: Some("Quad bind group"),
             layout: &bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(image_atlas.lock().unwrap().view()),
+                },
+            ],
         });
 
-        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Quad Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout, &universal_bind_group_layout],
-            push_constant_ranges: &[PushConstantRange {
-                stages: ShaderStages::all(),
-                range: 0..std::mem::size_of::<ShaderConstants>() as u32,
-            }],
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &[
+                "quad::vertex",
+                "quad::fragment",
+                "quad::opaque_vertex",
+                "quad::opaque_fragment",
+            ],
+            bind_group_layouts: &[&layout_entries, &renderer.universal_layout_entries()],
+            vertex_buffers: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Quad Pipeline"),
+        let (render_pipeline, opaque_pipeline) = create_pipelines(renderer, &bind_group_layout);
+
+        Self {
+            buffer,
+            bind_group_layout,
+            bind_group,
+            render_pipeline,
+            opaque_pipeline,
+
+            image_atlas: image_atlas.clone(),
+            merge_adjacent: true,
+            culler: None,
+            gpu_culling: false,
+            state_sorting: true,
+            state_sorting_stats: StateSortingStats::default(),
+            shadow_cache: ShadowCache::default(),
+            shadows: true,
+            blur_scale: 1.0,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "quad"
+    }
+
+    fn draw<'b, 'a: 'b>(
+        &'a mut self,
+        uploader: &mut Uploader,
+        render_pass: &mut RenderPass<'b>,
+        constants: ShaderConstants,
+        universal_bind_group: &'a BindGroup,
+        layer: &Layer,
+    ) {
+        let mut quads = Vec::new();
+        if layer.background_color.is_some() || layer.background_blur_radius != 0.0 {
+            // The background isn't scrolled with the layer contents
+            let background_rect = layer
+                .clip
+                .unwrap_or_else(|| surface_bounds_in_scene(&constants));
+            let mut background = Quad::new(
+                background_rect.xy() + layer.scroll_offset,
+                background_rect.zw(),
+                layer.background_color.unwrap_or(Vec4::ONE),
+            )
+            .with_background_blur(layer.background_blur_radius)
+            .to_instanced();
+            scale_background_blur(&mut background, self.blur_scale);
+            quads.push(background);
+        }
+
+        // With gpu culling every quad is uploaded and tested by the compute
+        // passes instead
+        let visible_rect = visible_content_rect(&constants, layer);
+        let gpu_culling = self.gpu_culling && self.culler.is_some();
+        let mut image_atlas = self.image_atlas.lock().unwrap();
+        let shadow_cache = &mut self.shadow_cache;
+        let shadows = self.shadows;
+        quads.extend(
+            layer
+                .shadows
+                .iter()
+                .filter(|_| shadows)
+                .filter(|shadow| gpu_culling || rects_overlap(shadow.bounds(), visible_rect))
+                .map(|shadow| {
+                    shadow_cache.instance(
+                        shadow,
+                        constants.camera_zoom,
+                        &mut image_atlas,
+                        uploader.queue(),
+                    )
+                }),
+        );
+        // The shadows of elevated quads go right beneath them
+        for quad in layer
+            .quads
+            .iter()
+            .filter(|quad| gpu_culling || rects_overlap(quad.bounds(), visible_rect))
+        {
+            for shadow in quad
+                .elevation_shadows(constants.y_direction)
+                .into_iter()
+                .filter(|_| shadows)
+            {
+                quads.push(shadow_cache.instance(
+                    &shadow,
+                    constants.camera_zoom,
+                    &mut image_atlas,
+                    uploader.queue(),
+                ));
+            }
+            let mut instance = quad.to_instanced();
+            scale_background_blur(&mut instance, self.blur_scale);
+            if let Some(pattern) = quad.pattern() {
+                instance.pattern_atlas_rect =
+                    image_atlas.get_or_upload(uploader.queue(), &pattern.image);
+            }
+            quads.push(instance);
+        }
+        if self.merge_adjacent && !gpu_culling {
+            quads = merge_adjacent_quads(quads);
+        }
+
+        // Runs of opaque and translucent quads are drawn in the order of the
+        // layer, switching the pipeline between them. Sorting moves quads
+        // into earlier runs of the same kind when they overlap nothing in
+        // between.
+        let opaque_key = |quad: &InstancedQuad| is_opaque(quad, &constants) as u32;
+        let mut runs = Vec::new();
+        if !gpu_culling {
+            let unsorted_runs = state_runs(&quads, opaque_key).len();
+            if self.state_sorting {
+                let margin = (constants.antialiasing_width + 1.0) / constants.camera_zoom;
+                quads = sort_by_state(quads, opaque_key, |quad| {
+                    grow_rect(instance_bounds(quad), margin)
+                });
+            }
+            runs = state_runs(&quads, opaque_key);
+            self.state_sorting_stats.add(StateSortingStats {
+                rebinds_unsorted: unsorted_runs,
+                rebinds_sorted: runs.len(),
+            });
+        }
+
+        let quad_data: &[u8] = bytemuck::cast_slice(&quads[..]);
+        uploader.write_buffer(&self.buffer, 0, quad_data);
+        render_pass.set_bind_group(1, &universal_bind_group, &[]);
+
+        // The culled quads are only known on the gpu, so they are all drawn
+        // with blending
+        if let Some(culler) = self.culler.as_mut().filter(|_| gpu_culling) {
+            if quads.is_empty() {
+                return;
+            }
+            culler.cull(
+                uploader,
+                &self.buffer,
+                &self.bind_group_layout,
+                image_atlas.view(),
+                quads.len() as u32,
+                visible_rect,
+            );
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_push_constants(
+                ShaderStages::all(),
+                0,
+                bytemuck::cast_slice(&[constants]),
+            );
+            culler.draw(render_pass);
+            return;
+        }
+        drop(image_atlas);
+
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+
+        for (opaque, instances) in runs {
+            let pipeline = if opaque == 1 {
+                &self.opaque_pipeline
+            } else {
+                &self.render_pipeline
+            };
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_push_constants(
+                ShaderStages::all(),
+                0,
+                bytemuck::cast_slice(&[constants]),
+            );
+            render_pass.draw(0..6, instances);
+        }
+    }
+
+    fn memory_usage(&self, report: &mut MemoryReport) {
+        report.buffers.add_buffer(&self.buffer);
+        if let Some(culler) = &self.culler {
+            culler.memory_usage(report);
+        }
+    }
+
+    fn trim(&mut self, _budget: &MemoryBudget) {
+        self.shadow_cache
+            .trim(&mut self.image_atlas.lock().unwrap());
+    }
+
+    fn recreate_pipelines(&mut self, renderer: &Renderer) -> bool {
+        (self.render_pipeline, self.opaque_pipeline) =
+            create_pipelines(renderer, &self.bind_group_layout);
+        true
+    }
+}
+
+// The blended and the opaque pipeline
+fn create_pipelines(
+    renderer: &Renderer,
+    bind_group_layout: &BindGroupLayout,
+) -> (RenderPipeline, RenderPipeline) {
+    let Renderer {
+        device,
+        universal_bind_group_layout,
+        shader,
+        format,
+        ..
+    } = renderer;
+    let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("Quad Pipeline Layout"),
+        bind_group_layouts: &[bind_group_layout, universal_bind_group_layout],
+        push_constant_ranges: &[PushConstantRange {
+            stages: ShaderStages::all(),
+            range: 0..std::mem::size_of::<ShaderConstants>() as u32,
+        }],
+    });
+
+    let create_pipeline = |label, vertex_entry_point, fragment_entry_point, blend| {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
             layout: Some(&render_pipeline_layout),
             vertex: VertexState {
                 module: &shader,
-                entry_point: "quad::vertex",
+                entry_point: vertex_entry_point,
                 buffers: &[],
             },
             fragment: Some(FragmentState {
                 module: &shader,
-                entry_point: "quad::fragment",
+                entry_point: fragment_entry_point,
                 targets: &[Some(ColorTargetState {
                     format: *format,
-                    blend: Some(BlendState::ALPHA_BLENDING),
+                    blend,
                     write_mask: ColorWrites::ALL,
                 })],
             }),
@@ -87,52 +388,111 @@ impl Drawable for QuadState {
             },
             depth_stencil: None,
             multisample: MultisampleState {
-                count: 4,
+                count: renderer.sample_count(),
                 ..Default::default()
             },
             multiview: None,
-        });
+        })
+    };
+    (
+        create_pipeline(
+            "Quad Pipeline",
+            "quad::vertex",
+            "quad::fragment",
+            Some(BlendState::ALPHA_BLENDING),
+        ),
+        create_pipeline(
+            "Opaque Quad Pipeline",
+            "quad::opaque_vertex",
+            "quad::opaque_fragment",
+            None,
+        ),
+    )
+}
 
-        Self {
-            buffer,
-            bind_group,
-            render_pipeline,
+// Edges closer than this are considered touching or on the pixel grid, so
+// that positions computed from cell sizes still line up
+const EDGE_TOLERANCE: f32 = 0.001;
+
+// Coalesces each quad into the previous one when both are plain rects of the
+// same color sharing a full edge, such as the cell backgrounds of a row of a
+// terminal grid. A merged row is merged in turn into the row above it. Only
+// consecutive quads are merged, so that the drawing order is kept.
+pub(crate) fn merge_adjacent_quads(quads: Vec<InstancedQuad>) -> Vec<InstancedQuad> {
+    let mut merged: Vec<InstancedQuad> = Vec::with_capacity(quads.len());
+    for quad in quads {
+        merged.push(quad);
+        while let [.., previous, last] = merged.as_slice() {
+            let Some(union) = merge_quads(previous, last) else {
+                break;
+            };
+            merged.pop();
+            *merged.last_mut().unwrap() = union;
         }
     }
+    merged
+}
 
-    fn draw<'b, 'a: 'b>(
-        &'a mut self,
-        queue: &Queue,
-        render_pass: &mut RenderPass<'b>,
-        constants: ShaderConstants,
-        universal_bind_group: &'a BindGroup,
-        layer: &Layer,
-    ) {
-        let mut quads = Vec::new();
-        if layer.background_color.is_some() || layer.background_blur_radius != 0.0 {
-            quads.push(
-                Quad::new(
-                    layer.clip.map(|clip| clip.xy()).unwrap_or(Vec2::ZERO),
-                    layer
-                        .clip
-                        .map(|clip| clip.zw())
-                        .unwrap_or(constants.surface_size),
-                    layer.background_color.unwrap_or(Vec4::ONE),
-                )
-                .with_background_blur(layer.background_blur_radius)
-                .to_instanced(),
-            );
-        }
+fn merge_quads(a: &InstancedQuad, b: &InstancedQuad) -> Option<InstancedQuad> {
+    let plain = |quad: &InstancedQuad| {
+        quad.corner_radius == 0.0
+            && quad.blur == 0.0
+            && quad.pattern_atlas_rect.zw() == Vec2::ZERO
+            && quad.extension() == 0.0
+    };
+    if !plain(a) || !plain(b) || a.color != b.color || a.snap != b.snap {
+        return None;
+    }
 
-        quads.extend(layer.quads.iter().map(|quad| quad.to_instanced()));
+    let touches = |x: f32, y: f32| (x - y).abs() <= EDGE_TOLERANCE;
+    let a_bottom_right = a.top_left + a.size;
+    let b_bottom_right = b.top_left + b.size;
+    let same_rows =
+        touches(a.top_left.y, b.top_left.y) && touches(a_bottom_right.y, b_bottom_right.y);
+    let same_columns =
+        touches(a.top_left.x, b.top_left.x) && touches(a_bottom_right.x, b_bottom_right.x);
+    let horizontal = same_rows
+        && (touches(a_bottom_right.x, b.top_left.x) || touches(b_bottom_right.x, a.top_left.x));
+    let vertical = same_columns
+        && (touches(a_bottom_right.y, b.top_left.y) || touches(b_bottom_right.y, a.top_left.y));
+    if !horizontal && !vertical {
+        return None;
+    }
 
-        render_pass.set_pipeline(&self.render_pipeline); // 2.
-        render_pass.set_push_constants(ShaderStages::all(), 0, bytemuck::cast_slice(&[constants]));
+    let top_left = a.top_left.min(b.top_left);
+    Some(InstancedQuad {
+        top_left,
+        size: a_bottom_right.max(b_bottom_right) - top_left,
+        ..*a
+    })
+}
 
-        let quad_data: &[u8] = bytemuck::cast_slice(&quads[..]);
-        queue.write_buffer(&self.buffer, 0, quad_data);
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
-        render_pass.set_bind_group(1, &universal_bind_group, &[]);
-        render_pass.draw(0..6, 0..quads.len() as u32);
+// Scene rect covered by the quad including its external blur or outline
+fn instance_bounds(quad: &InstancedQuad) -> Vec4 {
+    let rect = vec4(quad.top_left.x, quad.top_left.y, quad.size.x, quad.size.y);
+    grow_rect(rect, quad.extension())
+}
+
+// Plain quads of an opaque color whose edges are on the pixel grid have no
+// antialiased pixels to blend, so they can overwrite what is beneath them
+pub(crate) fn is_opaque(quad: &InstancedQuad, constants: &ShaderConstants) -> bool {
+    if quad.color.w < 1.0
+        || quad.corner_radius != 0.0
+        || quad.blur != 0.0
+        || quad.extension() != 0.0
+        || quad.pattern_atlas_rect.zw() != Vec2::ZERO
+        || constants.camera_rotation != vec2(1.0, 0.0)
+    {
+        return false;
     }
+    let on_grid = |position: Vec2| {
+        let surface_position = constants.to_surface(position);
+        (surface_position - surface_position.round())
+            .abs()
+            .max_element()
+            <= EDGE_TOLERANCE
+    };
+    // Snapped quads are drawn with their snapped rect
+    let (top_left, size) = constants.snap_rect(quad.top_left, quad.size, quad.snap);
+    on_grid(top_left) && on_grid(top_left + size)
 }
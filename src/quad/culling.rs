@@ -0,0 +1,250 @@
+use glam::Vec4;
+use shader::{InstancedQuad, QuadCulling, CULLING_WORKGROUP_SIZE};
+use wgpu::*;
+
+use super::MAX_QUADS;
+use crate::{
+    indirect::IndirectDraws, memory::MemoryReport, renderer::Renderer,
+    shader_layout::PipelineInterface, uploader::Uploader,
+};
+
+// Culls the quads of a layer on the gpu with compute passes, which write the
+// visible quads to a compacted buffer drawn with an indirect draw. Saves the
+// cpu from testing every quad against the visible rect in scenes with
+// hundreds of thousands of them.
+pub(crate) struct QuadCuller {
+    bind_group_layout: BindGroupLayout,
+    cull_pipeline: ComputePipeline,
+    offsets_pipeline: ComputePipeline,
+    compact_pipeline: ComputePipeline,
+    indirect_draws: IndirectDraws,
+    // Created the first time culling is used
+    buffers: Option<CullingBuffers>,
+}
+
+struct CullingBuffers {
+    visible: Buffer,
+    group_offsets: Buffer,
+    compacted: Buffer,
+    draw_arguments: Buffer,
+    bind_group: BindGroup,
+    // Binds the compacted quads in place of the quad buffer
+    render_bind_group: BindGroup,
+}
+
+impl QuadCuller {
+    pub fn new(renderer: &Renderer) -> Self {
+        let Renderer { device, shader, .. } = renderer;
+
+        let storage_entry = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout_entries = [
+            storage_entry(0, true),
+            storage_entry(1, false),
+            storage_entry(2, false),
+            storage_entry(3, false),
+            storage_entry(4, false),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Quad culling bind group layout"),
+            entries: &layout_entries,
+        });
+
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &[
+                "culling::cull_quads",
+                "culling::cull_quads_offsets",
+                "culling::compact_quads",
+            ],
+            bind_group_layouts: &[&layout_entries],
+            vertex_buffers: &[],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Quad Culling Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[PushConstantRange {
+                stages: ShaderStages::COMPUTE,
+                range: 0..std::mem::size_of::<QuadCulling>() as u32,
+            }],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: shader,
+                entry_point,
+            })
+        };
+
+        Self {
+            cull_pipeline: create_pipeline("Quad Culling Pipeline", "culling::cull_quads"),
+            offsets_pipeline: create_pipeline(
+                "Quad Culling Offsets Pipeline",
+                "culling::cull_quads_offsets",
+            ),
+            compact_pipeline: create_pipeline("Quad Compaction Pipeline", "culling::compact_quads"),
+            bind_group_layout,
+            indirect_draws: renderer.indirect_draws(),
+            buffers: None,
+        }
+    }
+
+    // Records the passes culling the first count quads of the quad buffer
+    // against the visible rect. They run after the uploads recorded before.
+    pub fn cull(
+        &mut self,
+        uploader: &mut Uploader,
+        quads: &Buffer,
+        render_layout: &BindGroupLayout,
+        atlas_view: &TextureView,
+        count: u32,
+        visible_rect: Vec4,
+    ) {
+        let buffers = self.buffers.get_or_insert_with(|| {
+            CullingBuffers::new(
+                uploader.device,
+                &self.bind_group_layout,
+                quads,
+                render_layout,
+                atlas_view,
+            )
+        });
+        let encoder = &mut *uploader.encoder;
+        encoder.clear_buffer(&buffers.group_offsets, 0, None);
+
+        let culling = QuadCulling {
+            visible_rect,
+            count,
+            ..Default::default()
+        };
+        let groups = count.div_ceil(CULLING_WORKGROUP_SIZE);
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Quad culling pass"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, &buffers.bind_group, &[]);
+        pass.set_push_constants(0, bytemuck::cast_slice(&[culling]));
+        pass.set_pipeline(&self.cull_pipeline);
+        pass.dispatch_workgroups(groups, 1, 1);
+        pass.set_pipeline(&self.offsets_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+        pass.set_pipeline(&self.compact_pipeline);
+        pass.dispatch_workgroups(groups, 1, 1);
+    }
+
+    // Draws the quads which passed the culling. The pipeline, push constants
+    // and the universal bind group have to be set.
+    pub fn draw<'b, 'a: 'b>(&'a self, render_pass: &mut RenderPass<'b>) {
+        let Some(buffers) = &self.buffers else {
+            return;
+        };
+        render_pass.set_bind_group(0, &buffers.render_bind_group, &[]);
+        self.indirect_draws
+            .draw(render_pass, &buffers.draw_arguments, 0, 1);
+    }
+
+    pub fn memory_usage(&self, report: &mut MemoryReport) {
+        if let Some(buffers) = &self.buffers {
+            report.buffers.add_buffer(&buffers.visible);
+            report.buffers.add_buffer(&buffers.group_offsets);
+            report.buffers.add_buffer(&buffers.compacted);
+            report.buffers.add_buffer(&buffers.draw_arguments);
+        }
+    }
+}
+
+impl CullingBuffers {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        quads: &Buffer,
+        render_layout: &BindGroupLayout,
+        atlas_view: &TextureView,
+    ) -> Self {
+        let create_buffer = |label, size, usage| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+        let groups = (MAX_QUADS as u32).div_ceil(CULLING_WORKGROUP_SIZE) as u64;
+        let visible = create_buffer(
+            "Quad visibility buffer",
+            MAX_QUADS * 4,
+            BufferUsages::empty(),
+        );
+        let group_offsets = create_buffer(
+            "Quad culling group buffer",
+            groups * 4,
+            BufferUsages::COPY_DST,
+        );
+        let compacted = create_buffer(
+            "Compacted quad buffer",
+            std::mem::size_of::<InstancedQuad>() as u64 * MAX_QUADS,
+            BufferUsages::empty(),
+        );
+        let draw_arguments =
+            create_buffer("Quad draw arguments buffer", 16, BufferUsages::INDIRECT);
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Quad culling bind group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: quads.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: visible.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: group_offsets.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: compacted.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: draw_arguments.as_entire_binding(),
+                },
+            ],
+        });
+        let render_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Compacted quad bind group"),
+            layout: render_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: compacted.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(atlas_view),
+                },
+            ],
+        });
+
+        Self {
+            visible,
+            group_offsets,
+            compacted,
+            draw_arguments,
+            bind_group,
+            render_bind_group,
+        }
+    }
+}
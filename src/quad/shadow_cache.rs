@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use glam::{vec2, Mat2, UVec2, Vec2, Vec4Swizzles};
+use shader::{compute_erf7, InstancedQuad};
+use wgpu::Queue;
+
+use crate::{image_atlas::ImageAtlas, scene::Shadow, Pattern, Quad};
+
+// Largest side of a cached mask in pixels. Larger shadows are rasterized at a
+// lower resolution and stretched, as long as a mask pixel stays small next to
+// the blur.
+const MAX_MASK_SIZE: u32 = 256;
+// Smallest blur radius in mask pixels of a stretched mask
+const MIN_MASK_BLUR: f32 = 4.0;
+// Masks kept in the image atlas before the least recently drawn are dropped
+const MAX_CACHED_SHADOWS: usize = 64;
+
+// A shadow in pixels at the zoom it's drawn at. Shadows with the same key
+// share a mask wherever they are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct ShadowKey {
+    pub size: UVec2,
+    pub corner_radius: u32,
+    pub blur: u32,
+    pub spread: i32,
+}
+
+impl ShadowKey {
+    pub fn new(shadow: &Shadow, zoom: f32) -> Self {
+        let pixels = |value: f32| (value * zoom).round();
+        Self {
+            size: vec2(pixels(shadow.size.x), pixels(shadow.size.y))
+                .max(Vec2::ZERO)
+                .as_uvec2(),
+            corner_radius: pixels(shadow.corner_radius).max(0.0) as u32,
+            blur: pixels(shadow.blur).max(0.0) as u32,
+            spread: pixels(shadow.spread) as i32,
+        }
+    }
+
+    // Size and corner radius of the rect grown by the spread
+    fn shape(&self) -> (Vec2, f32) {
+        let spread = self.spread as f32;
+        let size = (self.size.as_vec2() + Vec2::splat(spread * 2.0)).max(Vec2::ZERO);
+        (size, (self.corner_radius as f32 + spread).max(0.0))
+    }
+
+    // Empty space around the shape which the blur spreads into, plus a pixel
+    // so that filtering at the edges of the mask stays transparent
+    fn margin(&self) -> f32 {
+        (self.blur as f32 * 3.0).ceil() + 1.0
+    }
+
+    // Mask pixels per shadow pixel, or None when the mask would have to be
+    // stretched so much that the blur no longer hides it
+    pub fn mask_scale(&self) -> Option<f32> {
+        let (size, _) = self.shape();
+        let largest = (size + Vec2::splat(self.margin() * 2.0)).max_element();
+        let scale = (MAX_MASK_SIZE as f32 / largest).min(1.0);
+        (scale == 1.0 || self.blur as f32 * scale >= MIN_MASK_BLUR).then_some(scale)
+    }
+
+    fn mask_size(&self, scale: f32) -> Vec2 {
+        let (size, _) = self.shape();
+        ((size + Vec2::splat(self.margin() * 2.0)) * scale)
+            .ceil()
+            .max(Vec2::ONE)
+    }
+
+    fn image_name(&self) -> String {
+        format!(
+            "vide-shadow-{}x{}-{}-{}-{}",
+            self.size.x, self.size.y, self.corner_radius, self.blur, self.spread
+        )
+    }
+}
+
+// Rasterizes the blurred alpha of the shape as white rgba8 pixels, blurred
+// the same way the quad shader blurs the edges of quads
+pub(crate) fn rasterize_shadow_mask(key: &ShadowKey, scale: f32) -> (u32, u32, Vec<u8>) {
+    let (size, corner_radius) = key.shape();
+    let margin = key.margin();
+    let mask_size = key.mask_size(scale).as_uvec2();
+
+    let blur = key.blur as f32;
+    let inverse_blur = 1.0 / blur;
+    let edge_scale = 0.5 * compute_erf7(blur * 0.5 * (size.max_element() - 0.5 * corner_radius));
+    let center = Vec2::splat(margin) + size / 2.0;
+    let half_size = size / 2.0 - Vec2::splat(corner_radius);
+
+    let mut data = Vec::with_capacity((mask_size.x * mask_size.y * 4) as usize);
+    for y in 0..mask_size.y {
+        for x in 0..mask_size.x {
+            let point = (vec2(x as f32, y as f32) + 0.5) / scale;
+            let d = (point - center).abs() - half_size;
+            let distance = d.max(Vec2::ZERO).length() + d.max_element().min(0.0) - corner_radius;
+            let alpha = edge_scale
+                * (compute_erf7(inverse_blur * (size.min_element() + distance))
+                    - compute_erf7(inverse_blur * distance));
+            let alpha = (alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+            data.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+    (mask_size.x, mask_size.y, data)
+}
+
+// Blurred masks of the shadows drawn recently, stored in the image atlas, so
+// that each shadow is drawn as a quad with the mask as its pattern instead of
+// evaluating the blur for every pixel. Shadows without a blur and ones too
+// large for a mask are drawn as blurred quads instead.
+#[derive(Default)]
+pub(crate) struct ShadowCache {
+    // Frame each mask was last drawn in
+    masks: HashMap<ShadowKey, u64>,
+    frame: u64,
+}
+
+impl ShadowCache {
+    pub fn instance(
+        &mut self,
+        shadow: &Shadow,
+        zoom: f32,
+        image_atlas: &mut ImageAtlas,
+        queue: &Queue,
+    ) -> InstancedQuad {
+        let shape = shadow.shape();
+        let key = ShadowKey::new(shadow, zoom);
+        let scale = key.mask_scale();
+        let (Some(scale), true) = (scale, key.blur > 0) else {
+            return Quad::new(shape.xy(), shape.zw(), shadow.color)
+                .with_corner_radius(shadow.shape_corner_radius())
+                .with_blur(shadow.blur)
+                .to_instanced();
+        };
+
+        let name = key.image_name();
+        if self.masks.insert(key, self.frame).is_none() {
+            let (width, height, data) = rasterize_shadow_mask(&key, scale);
+            image_atlas.add_image_rgba(&name, width, height, data);
+        }
+
+        // The mask is stretched over the shape and its margin in the scene
+        let margin = Vec2::splat(key.margin() / zoom);
+        let top_left = shape.xy() - margin;
+        let size = shape.zw() + margin * 2.0;
+        let atlas_rect = image_atlas.get_or_upload(queue, &name);
+        let pattern = Pattern {
+            image: name,
+            transform: Mat2::from_diagonal(key.mask_size(scale) / size),
+            offset: Vec2::ZERO,
+        };
+        let mut instance = Quad::new(top_left, size, shadow.color)
+            .with_pattern(pattern)
+            .to_instanced();
+        instance.pattern_atlas_rect = atlas_rect;
+        instance
+    }
+
+    // Starts a new frame, dropping the least recently drawn masks over the
+    // limit
+    pub fn trim(&mut self, image_atlas: &mut ImageAtlas) {
+        self.frame += 1;
+        let Some(excess) = self.masks.len().checked_sub(MAX_CACHED_SHADOWS) else {
+            return;
+        };
+        let mut masks: Vec<_> = self
+            .masks
+            .iter()
+            .map(|(key, frame)| (*key, *frame))
+            .collect();
+        masks.sort_by_key(|(_, last_used)| *last_used);
+        for (key, _) in masks.into_iter().take(excess) {
+            self.masks.remove(&key);
+            image_atlas.remove_image(&key.image_name());
+        }
+    }
+}
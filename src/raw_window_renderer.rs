@@ -0,0 +1,610 @@
+use smol::block_on;
+use tracing::{debug, error, info, warn};
+use wgpu::*;
+
+use crate::{
+    adapter::{create_instance, request_adapter},
+    blit::Blitter,
+    renderer::{Drawable, DrawableError, RendererError},
+    AssetSource, CoordinateOrigin, GpuDiagnostics, Renderer, Scene,
+};
+
+// Number of times acquiring a frame is retried after reconfiguring an outdated
+// or lost surface
+const MAX_SURFACE_RETRIES: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DrawOutcome {
+    Presented,
+    // There's no surface to draw to, because the app is suspended or the
+    // window is minimized
+    Skipped,
+    // The frame was dropped because of a transient surface error. Drawing
+    // again later is expected to succeed.
+    Dropped(SurfaceError),
+    // The surface can't be drawn to anymore
+    Fatal(SurfaceError),
+}
+
+impl DrawOutcome {
+    pub fn is_presented(&self) -> bool {
+        matches!(self, DrawOutcome::Presented)
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, DrawOutcome::Fatal(_))
+    }
+}
+
+type SurfaceErrorCallback = Box<dyn FnMut(&SurfaceErrorEvent)>;
+
+#[derive(Debug, Clone)]
+pub struct SurfaceErrorEvent {
+    pub error: SurfaceError,
+    pub fatal: bool,
+    // Whether the surface was reconfigured and the frame will be retried
+    pub retrying: bool,
+}
+
+// How the window contents are updated while the window is interactively
+// resized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeStrategy {
+    // Reconfigure the surface and wait for the next redraw. Cheap, but the
+    // window may flash black or show garbage until the app draws again.
+    #[default]
+    Deferred,
+    // Render the last drawn scene at the new size while handling the resize
+    // event, blocking until the frame is presented. Keeps a copy of the last
+    // scene.
+    Immediate,
+    // Present the previous frame stretched to the new size while handling the
+    // resize event and let the next redraw render the real frame. Keeps a copy
+    // of the last frame.
+    StretchPrevious,
+}
+
+// Renders to any window which provides raw window and display handles. Use
+// this to integrate with windowing libraries other than winit. The owner of
+// the window is responsible for forwarding resizes and the suspend and resume
+// lifecycle.
+pub struct RawWindowRenderer<'a> {
+    pub instance: Instance,
+    pub surface: Option<Surface<'a>>,
+    pub surface_config: SurfaceConfiguration,
+    renderer: Renderer,
+
+    resize_strategy: ResizeStrategy,
+    last_scene: Option<Scene>,
+    previous_frame: Option<Texture>,
+    blitter: Option<Blitter>,
+    surface_error_callback: Option<SurfaceErrorCallback>,
+    present_mode: PresentMode,
+}
+
+impl<'a> RawWindowRenderer<'a> {
+    // Creating some of the wgpu types requires async code. On platforms like
+    // Android the window can't be drawn to until the app is resumed, in which
+    // case the surface is created by `resume`. Fails when no adapter can run
+    // the renderer.
+    pub async fn new(
+        window: impl WindowHandle + 'a,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, RendererError> {
+        let instance = create_instance();
+        let surface = instance.create_surface(window).ok();
+        Self::with_surface(instance, surface, width, height).await
+    }
+
+    // Renders to a CAMetalLayer, such as the layer of a UIView on iOS or
+    // iPadOS created without winit. The size is in pixels, so the contents
+    // scale of the layer should match the scale factor of the screen to
+    // render at Retina resolution.
+    //
+    // # Safety
+    //
+    // The layer has to be a valid CAMetalLayer which outlives the renderer.
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub async unsafe fn from_metal_layer(
+        layer: *mut std::ffi::c_void,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, RendererError> {
+        let instance = create_instance();
+        let surface = instance
+            .create_surface_unsafe(SurfaceTargetUnsafe::CoreAnimationLayer(layer))
+            .ok();
+        Self::with_surface(instance, surface, width, height).await
+    }
+
+    async fn with_surface(
+        instance: Instance,
+        surface: Option<Surface<'a>>,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, RendererError> {
+        let adapter = request_adapter(&instance, surface.as_ref())
+            .await
+            .ok_or(RendererError::NoAdapter)?;
+
+        let (format, alpha_mode) = match &surface {
+            Some(surface) => {
+                let swapchain_capabilities = surface.get_capabilities(&adapter);
+                (
+                    swapchain_capabilities.formats[0],
+                    swapchain_capabilities.alpha_modes[0],
+                )
+            }
+            None => (TextureFormat::Rgba8UnormSrgb, CompositeAlphaMode::Auto),
+        };
+
+        // The surface has the size of the renderer, which is clamped to the
+        // maximum texture size
+        let renderer = Renderer::new(width, height, adapter, format).await?;
+        let surface_config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            format,
+            width: renderer.width,
+            height: renderer.height,
+            present_mode: PresentMode::Fifo,
+            alpha_mode,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+
+        if let Some(surface) = &surface {
+            info!(
+                ?format,
+                ?alpha_mode,
+                width = surface_config.width,
+                height = surface_config.height,
+                "Configuring surface"
+            );
+            if !renderer.is_empty() {
+                surface.configure(&renderer.device, &surface_config);
+            }
+        }
+
+        Ok(Self {
+            instance,
+            surface,
+            surface_config,
+            renderer,
+
+            resize_strategy: ResizeStrategy::default(),
+            last_scene: None,
+            previous_frame: None,
+            blitter: None,
+            surface_error_callback: None,
+            present_mode: PresentMode::Fifo,
+        })
+    }
+
+    pub fn renderer(&self) -> &Renderer {
+        &self.renderer
+    }
+
+    pub fn renderer_mut(&mut self) -> &mut Renderer {
+        &mut self.renderer
+    }
+
+    // The adapter, backend, limits, features and formats the renderer runs
+    // with, along with the adapters it could have picked
+    pub fn diagnostics(&self) -> GpuDiagnostics {
+        let surface_formats = self
+            .surface
+            .as_ref()
+            .map(|surface| surface.get_capabilities(&self.renderer.adapter).formats);
+        GpuDiagnostics::new(&self.instance, &self.renderer, surface_formats)
+    }
+
+    pub fn add_drawable<T: Drawable + 'static>(&mut self) -> Result<(), DrawableError> {
+        self.renderer.add_drawable::<T>()
+    }
+
+    pub fn with_drawable<T: Drawable + 'static>(mut self) -> Self {
+        if let Err(error) = self.add_drawable::<T>() {
+            panic!("{error}");
+        }
+        self
+    }
+
+    pub fn set_antialiasing_width(&mut self, width: f32) {
+        self.renderer.set_antialiasing_width(width);
+    }
+
+    pub fn with_antialiasing_width(mut self, width: f32) -> Self {
+        self.set_antialiasing_width(width);
+        self
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.renderer.set_scale_factor(scale_factor);
+    }
+
+    pub fn with_scale_factor(mut self, scale_factor: f32) -> Self {
+        self.set_scale_factor(scale_factor);
+        self
+    }
+
+    pub fn set_coordinate_origin(&mut self, origin: CoordinateOrigin) {
+        self.renderer.set_coordinate_origin(origin);
+    }
+
+    pub fn with_coordinate_origin(mut self, origin: CoordinateOrigin) -> Self {
+        self.set_coordinate_origin(origin);
+        self
+    }
+
+    pub fn set_pixels_per_unit(&mut self, pixels_per_unit: f32) {
+        self.renderer.set_pixels_per_unit(pixels_per_unit);
+    }
+
+    pub fn with_pixels_per_unit(mut self, pixels_per_unit: f32) -> Self {
+        self.set_pixels_per_unit(pixels_per_unit);
+        self
+    }
+
+    pub fn set_fixed_time_step(&mut self, time_step: Option<f32>) {
+        self.renderer.set_fixed_time_step(time_step);
+    }
+
+    pub fn with_fixed_time_step(mut self, time_step: Option<f32>) -> Self {
+        self.set_fixed_time_step(time_step);
+        self
+    }
+
+    pub fn set_resize_strategy(&mut self, resize_strategy: ResizeStrategy) {
+        self.resize_strategy = resize_strategy;
+        self.last_scene = None;
+        self.previous_frame = None;
+    }
+
+    pub fn with_resize_strategy(mut self, resize_strategy: ResizeStrategy) -> Self {
+        self.set_resize_strategy(resize_strategy);
+        self
+    }
+
+    pub fn add_builtin_drawables(&mut self) -> Result<(), DrawableError> {
+        self.renderer.add_builtin_drawables()
+    }
+
+    pub fn with_builtin_drawables(mut self) -> Self {
+        if let Err(error) = self.add_builtin_drawables() {
+            panic!("{error}");
+        }
+        self
+    }
+
+    pub fn add_default_drawables(
+        &mut self,
+        assets: impl AssetSource + 'static,
+    ) -> Result<(), DrawableError> {
+        self.renderer.add_default_drawables(assets)
+    }
+
+    pub fn with_default_drawables(mut self, assets: impl AssetSource + 'static) -> Self {
+        if let Err(error) = self.add_default_drawables(assets) {
+            panic!("{error}");
+        }
+        self
+    }
+
+    pub fn set_asset_source(&mut self, assets: impl AssetSource + 'static) {
+        self.renderer.set_asset_source(assets);
+    }
+
+    pub fn with_asset_source(mut self, assets: impl AssetSource + 'static) -> Self {
+        self.set_asset_source(assets);
+        self
+    }
+
+    // Creates the surface for the window when the app is resumed. The device
+    // may have been lost while the app was suspended, or the new surface may
+    // not support the format the renderer was created with. In both cases the
+    // renderer is recreated.
+    pub fn resume(&mut self, window: impl WindowHandle + 'a, width: u32, height: u32) {
+        if let Ok(surface) = self.instance.create_surface(window) {
+            self.resume_surface(surface, width, height);
+        }
+    }
+
+    // Creates the surface for a CAMetalLayer when the app is resumed, like
+    // `resume`
+    //
+    // # Safety
+    //
+    // The layer has to be a valid CAMetalLayer which outlives the renderer.
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    pub unsafe fn resume_metal_layer(
+        &mut self,
+        layer: *mut std::ffi::c_void,
+        width: u32,
+        height: u32,
+    ) {
+        let surface = self
+            .instance
+            .create_surface_unsafe(SurfaceTargetUnsafe::CoreAnimationLayer(layer));
+        if let Ok(surface) = surface {
+            self.resume_surface(surface, width, height);
+        }
+    }
+
+    fn resume_surface(&mut self, surface: Surface<'a>, width: u32, height: u32) {
+        self.surface = Some(surface);
+        self.renegotiate_surface(width, height);
+    }
+
+    // Queries the capabilities of the surface again and reconfigures it. The
+    // renderer is recreated when the device was lost or the surface doesn't
+    // support its format anymore. Call after the window changes display mode,
+    // such as entering exclusive fullscreen, since the surface may then
+    // support other formats and present modes.
+    pub fn renegotiate_surface(&mut self, width: u32, height: u32) {
+        let Some(surface) = self.surface.take() else {
+            return;
+        };
+        let swapchain_capabilities = surface.get_capabilities(&self.renderer.adapter);
+        let format_supported = swapchain_capabilities
+            .formats
+            .contains(&self.renderer.format);
+
+        if self.renderer.is_device_lost() || !format_supported {
+            warn!(
+                device_lost = self.renderer.is_device_lost(),
+                format_supported, "Recreating the renderer for the surface"
+            );
+            let Some(adapter) = block_on(request_adapter(&self.instance, Some(&surface))) else {
+                error!("{}", RendererError::NoAdapter);
+                self.surface = Some(surface);
+                return;
+            };
+            let swapchain_capabilities = surface.get_capabilities(&adapter);
+            let format = if format_supported {
+                self.renderer.format
+            } else {
+                swapchain_capabilities.formats[0]
+            };
+            match block_on(self.renderer.recreate(adapter, format)) {
+                Ok(renderer) => self.renderer = renderer,
+                Err(error) => {
+                    error!("Could not recreate the renderer: {error}");
+                    self.surface = Some(surface);
+                    return;
+                }
+            }
+        }
+
+        let swapchain_capabilities = surface.get_capabilities(&self.renderer.adapter);
+        self.surface_config.format = self.renderer.format;
+        self.surface_config.alpha_mode = swapchain_capabilities.alpha_modes[0];
+        self.surface_config.present_mode =
+            supported_present_mode(&swapchain_capabilities, self.present_mode);
+        info!(
+            format = ?self.surface_config.format,
+            alpha_mode = ?self.surface_config.alpha_mode,
+            present_mode = ?self.surface_config.present_mode,
+            "Renegotiated surface"
+        );
+        self.surface = Some(surface);
+        // The kept frame and the blitter may be of the old format
+        self.blitter = None;
+        self.previous_frame = None;
+        self.resize_surface(width, height);
+    }
+
+    // Presentation mode requested from the surface. Falls back to Fifo, which
+    // every surface supports, when the surface doesn't support the mode.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.present_mode = present_mode;
+        let Some(surface) = &self.surface else {
+            return;
+        };
+        let swapchain_capabilities = surface.get_capabilities(&self.renderer.adapter);
+        self.surface_config.present_mode =
+            supported_present_mode(&swapchain_capabilities, present_mode);
+        if !self.renderer.is_empty() {
+            surface.configure(&self.renderer.device, &self.surface_config);
+        }
+    }
+
+    pub fn with_present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.set_present_mode(present_mode);
+        self
+    }
+
+    // The mode the surface is presented with, which may differ from the
+    // requested one
+    pub fn present_mode(&self) -> PresentMode {
+        self.surface_config.present_mode
+    }
+
+    // Surfaces and anything tied to them have to be released when the app is
+    // suspended. On Android the native window is destroyed.
+    pub fn suspend(&mut self) {
+        self.surface = None;
+        self.blitter = None;
+        self.previous_frame = None;
+    }
+
+    // Releases what can be recreated on demand when the system is low on
+    // memory: the cached atlas entries and the copies kept for resizing
+    pub fn handle_memory_warning(&mut self) {
+        self.renderer.release_memory();
+        self.last_scene = None;
+        self.previous_frame = None;
+    }
+
+    fn resize_surface(&mut self, new_width: u32, new_height: u32) {
+        self.renderer.resize(new_width, new_height);
+        self.surface_config.width = self.renderer.width;
+        self.surface_config.height = self.renderer.height;
+
+        // Surfaces can't be configured with a zero size. The surface is
+        // configured again once the window is restored.
+        if !self.renderer.is_empty() {
+            if let Some(surface) = &self.surface {
+                debug!(
+                    width = self.surface_config.width,
+                    height = self.surface_config.height,
+                    "Resizing surface"
+                );
+                surface.configure(&self.renderer.device, &self.surface_config);
+            }
+        }
+    }
+
+    // Resizes the surface and updates the window contents according to the
+    // resize strategy. The window should be redrawn afterwards.
+    pub fn resize(&mut self, new_width: u32, new_height: u32) {
+        self.resize_surface(new_width, new_height);
+        // Nothing can be shown while minimized. The last scene and frame are
+        // kept for when the window is restored.
+        if self.renderer.is_empty() {
+            return;
+        }
+
+        match self.resize_strategy {
+            ResizeStrategy::Deferred => {}
+            ResizeStrategy::Immediate => {
+                if let Some(scene) = self.last_scene.take() {
+                    self.draw(&scene);
+                    self.renderer.device.poll(Maintain::Wait);
+                }
+            }
+            ResizeStrategy::StretchPrevious => self.present_stretched_previous_frame(),
+        }
+    }
+
+    // Called whenever acquiring a surface texture fails, including failures
+    // which are recovered from automatically
+    pub fn set_surface_error_callback(
+        &mut self,
+        callback: impl FnMut(&SurfaceErrorEvent) + 'static,
+    ) {
+        self.surface_error_callback = Some(Box::new(callback));
+    }
+
+    pub fn with_surface_error_callback(
+        mut self,
+        callback: impl FnMut(&SurfaceErrorEvent) + 'static,
+    ) -> Self {
+        self.set_surface_error_callback(callback);
+        self
+    }
+
+    pub fn draw(&mut self, scene: &Scene) -> DrawOutcome {
+        if self.surface.is_none() || self.renderer.is_empty() {
+            return DrawOutcome::Skipped;
+        }
+
+        let mut attempt = 0;
+        let frame = loop {
+            let surface = self.surface.as_ref().unwrap();
+            let error = match surface.get_current_texture() {
+                Ok(frame) => break frame,
+                Err(error) => error,
+            };
+
+            // Outdated and lost surfaces are recovered from by reconfiguring
+            // the surface. Timeouts only drop the frame.
+            let recoverable = matches!(error, SurfaceError::Outdated | SurfaceError::Lost);
+            let retry = recoverable && attempt < MAX_SURFACE_RETRIES;
+            if recoverable {
+                surface.configure(&self.renderer.device, &self.surface_config);
+            }
+
+            let fatal = error == SurfaceError::OutOfMemory;
+            warn!(%error, fatal, retrying = retry, "Could not acquire a surface texture");
+            if let Some(callback) = &mut self.surface_error_callback {
+                callback(&SurfaceErrorEvent {
+                    error: error.clone(),
+                    fatal,
+                    retrying: retry,
+                });
+            }
+
+            if fatal {
+                return DrawOutcome::Fatal(error);
+            } else if !retry {
+                return DrawOutcome::Dropped(error);
+            }
+            attempt += 1;
+        };
+
+        self.renderer.render(scene, &frame.texture);
+        match self.resize_strategy {
+            ResizeStrategy::Deferred => {}
+            ResizeStrategy::Immediate => self.last_scene = Some(scene.clone()),
+            ResizeStrategy::StretchPrevious => self.store_previous_frame(&frame.texture),
+        }
+        frame.present();
+        self.renderer.frame_presented();
+        DrawOutcome::Presented
+    }
+
+    fn store_previous_frame(&mut self, frame: &Texture) {
+        let size = frame.size();
+        if !matches!(&self.previous_frame, Some(previous) if previous.size() == size) {
+            self.previous_frame = Some(self.renderer.device.create_texture(&TextureDescriptor {
+                label: Some("Previous frame texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: frame.format(),
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            }));
+        }
+
+        let mut encoder = self
+            .renderer
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Previous frame encoder"),
+            });
+        encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            self.previous_frame.as_ref().unwrap().as_image_copy(),
+            size,
+        );
+        self.renderer
+            .queue
+            .submit(std::iter::once(encoder.finish()));
+    }
+
+    fn present_stretched_previous_frame(&mut self) {
+        let (Some(surface), Some(previous_frame)) = (&self.surface, &self.previous_frame) else {
+            return;
+        };
+        if self.renderer.is_empty() {
+            return;
+        }
+
+        let Ok(frame) = surface.get_current_texture() else {
+            return;
+        };
+        let blitter = self
+            .blitter
+            .get_or_insert_with(|| Blitter::new(&self.renderer, self.surface_config.format));
+        blitter.blit(&self.renderer, previous_frame, &frame.texture);
+        frame.present();
+    }
+}
+
+fn supported_present_mode(
+    capabilities: &SurfaceCapabilities,
+    present_mode: PresentMode,
+) -> PresentMode {
+    if capabilities.present_modes.contains(&present_mode) {
+        present_mode
+    } else {
+        warn!(
+            ?present_mode,
+            "The surface doesn't support the present mode, using Fifo"
+        );
+        PresentMode::Fifo
+    }
+}
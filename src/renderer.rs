@@ -1,80 +1,437 @@
-use rust_embed::RustEmbed;
-use wgpu::*;
+use std::{
+    any::Any,
+    borrow::Cow,
+    cell::RefCell,
+    fmt,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use smol::block_on;
+use tracing::{debug, error, info, warn};
+use wgpu::{util::StagingBelt, *};
 
+#[cfg(feature = "image")]
+use crate::image_atlas::decode_image;
 use crate::{
-    glyph::GlyphState, path::PathState, quad::QuadState, scene::Layer, sprite::SpriteState, Asset,
-    Scene, ATLAS_SIZE,
+    asset_source::{AssetSource, SharedAssets},
+    backdrop_blur::BackdropBlurState,
+    batching::{batch_layers, BatchingStats},
+    capabilities::Capabilities,
+    caret::CaretState,
+    color_grading::{ColorGradingPass, ColorLut},
+    design_overlay::DesignOverlay,
+    dimming::DimmingState,
+    external_image,
+    frame_clock::{FrameClock, FrameTime},
+    frame_metadata::FrameMetadata,
+    glyph::{
+        default_rasterizer_threads,
+        shaping::{ShapingCacheStats, DEFAULT_SHAPING_CACHE_CAPACITY},
+        CellCluster, GlyphState, GlyphZoomPolicy, TextMetrics,
+    },
+    image_atlas::ImageAtlas,
+    indirect::IndirectDraws,
+    latency::{FrameLatency, LatencyTracker},
+    lod::{LodController, LodLevel, LodPolicy},
+    memory::{MemoryBudget, MemoryReport},
+    occlusion::{hidden_layers, OcclusionStats},
+    path::PathState,
+    procedural::ProceduralState,
+    quad::{culling::QuadCuller, QuadState},
+    scene::Layer,
+    shader_layout::{
+        reflect_shader, shader_constants_layout, verify_pipeline_interface, verify_push_constants,
+        verify_shader_constants, PipelineInterface, ShaderLayoutError,
+    },
+    shape::ShapeState,
+    sprite::SpriteState,
+    state_sorting::StateSortingStats,
+    universal_binding::{
+        UniversalBinding, UniversalEntry, UniversalResource, UniversalResourceFactory,
+        FIRST_USER_UNIVERSAL_BINDING,
+    },
+    uploader::{Uploader, STAGING_BELT_CHUNK_SIZE},
+    viewport::Viewport,
+    Camera, CoordinateOrigin, Hit, Scene, Text, TextRendering, Theme, ATLAS_SIZE,
 };
 use glam::*;
 use shader::ShaderConstants;
 
-pub trait Drawable {
+// Allows looking up the concrete type of registered drawables
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn type_name(&self) -> &'static str;
+}
+
+// Boxed drawables implement this too, so call it on the unboxed drawable
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+}
+
+pub trait Drawable: AsAny {
     fn new(renderer: &Renderer) -> Self
     where
         Self: Sized;
 
+    // Identifies the drawable in the renderer. Names have to be unique, the
+    // builtin drawables use the name of their shader module.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    // Called once per frame for each viewport with every layer about to be
+    // drawn in it, before any of them are drawn. Lets drawables do expensive
+    // CPU work for the whole scene at once, in parallel when the rayon
+    // feature is enabled. The constants don't include the scroll offset of
+    // any layer.
+    fn prepare(&mut self, _queue: &Queue, _constants: &ShaderConstants, _layers: &[&Layer]) {}
+
     fn draw<'b, 'a: 'b>(
         &'a mut self,
-        queue: &Queue,
+        uploader: &mut Uploader,
         render_pass: &mut RenderPass<'b>,
         constants: ShaderConstants,
         universal_bind_group: &'a BindGroup,
         layer: &Layer,
     );
+
+    // Adds the gpu memory allocated by the drawable to the report
+    fn memory_usage(&self, _report: &mut MemoryReport) {}
+
+    // Called at the start of every frame. Drawables with caches on the gpu
+    // evict entries to stay within the budget.
+    fn trim(&mut self, _budget: &MemoryBudget) {}
+
+    // Called when the sample count of the renderer changes. Drawables which
+    // create their pipelines again with the new sample count return true and
+    // keep their caches, the others are created again from scratch.
+    fn recreate_pipelines(&mut self, _renderer: &Renderer) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DrawableError {
+    // A drawable of the same type is already registered
+    AlreadyRegistered(&'static str),
+    // A drawable of another type is registered with the same name
+    DuplicateName {
+        name: &'static str,
+        existing: &'static str,
+    },
+    // The pipelines of the drawable don't match the interface of their entry
+    // points in the shader
+    PipelineMismatch {
+        name: &'static str,
+        errors: Vec<String>,
+    },
+}
+
+impl fmt::Display for DrawableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrawableError::AlreadyRegistered(type_name) => {
+                write!(f, "The drawable {type_name} is already registered")
+            }
+            DrawableError::DuplicateName { name, existing } => {
+                write!(f, "The drawable name {name} is already used by {existing}")
+            }
+            DrawableError::PipelineMismatch { name, errors } => write!(
+                f,
+                "The pipelines of the drawable {name} don't match the shader:\n{}",
+                errors.join("\n")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DrawableError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RendererError {
+    // No adapter of the backends can run the renderer
+    NoAdapter,
+    // The adapter lacks features or limits the renderer needs
+    UnsupportedAdapter {
+        adapter: String,
+        missing: Vec<&'static str>,
+    },
+    RequestDevice(String),
+    // The push constants of the shader don't match ShaderConstants
+    ShaderMismatch(ShaderLayoutError),
 }
 
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::NoAdapter => write!(f, "Could not find a suitable adapter"),
+            RendererError::UnsupportedAdapter { adapter, missing } => write!(
+                f,
+                "The adapter {adapter} doesn't support {}",
+                missing.join(", ")
+            ),
+            RendererError::RequestDevice(error) => {
+                write!(f, "Could not create the device: {error}")
+            }
+            RendererError::ShaderMismatch(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+// Recreates a registered drawable when the renderer is rebuilt after the
+// device was lost
+type DrawableFactory = fn(&Renderer) -> Box<dyn Drawable>;
+
+// Name of the shader module in the asset source used by reload_shader
+pub const SHADER_ASSET: &str = "shader.spv";
+
 pub struct Renderer {
     pub adapter: Adapter,
     pub device: Device,
     pub queue: Queue,
     pub shader: ShaderModule,
+    // Reflection of the shader used to check the pipelines of the drawables.
+    // None when naga can't parse the shader.
+    shader_reflection: Option<naga::Module>,
+    // Pipeline mismatches of the drawable add_drawable is creating
+    pipeline_errors: RefCell<Option<Vec<String>>>,
 
     pub format: TextureFormat,
+    // Size of the rendered frames. Smaller than the requested size when that
+    // exceeds the maximum texture size of the device.
     pub width: u32,
     pub height: u32,
+    requested_size: (u32, u32),
+    render_scale: f32,
 
     pub offscreen_texture: Texture,
     pub multisampled_texture: Texture,
+    // Copy of the last rendered frame, bound at binding 2 of the universal
+    // bind group for temporal effects such as motion trails. Cleared to
+    // transparent black on the first frame and after resizing.
+    pub previous_frame_texture: Texture,
     pub sampler: Sampler,
     pub universal_bind_group_layout: BindGroupLayout,
     pub universal_bind_group: BindGroup,
+    universal_entries: Vec<UniversalEntry>,
     pub(crate) drawables: Vec<Box<dyn Drawable>>,
+    drawable_factories: Vec<DrawableFactory>,
+    device_lost: Arc<AtomicBool>,
+    capabilities: Capabilities,
+    pub(crate) assets: Arc<SharedAssets>,
+    pub(crate) image_atlas: Arc<Mutex<ImageAtlas>>,
+
+    // Width in logical pixels of the antialiasing ramp for sdf shapes. Zero,
+    // the default, keeps the hard edges. One pixel gives smooth edges.
+    pub antialiasing_width: f32,
+    // Physical pixels per logical pixel, such as the scale factor of the
+    // window. The frame size stays in physical pixels.
+    pub scale_factor: f32,
+    // Origin and y axis direction of the scene coordinates
+    pub coordinate_origin: CoordinateOrigin,
+    // Surface pixels per scene unit, for scenes described in units such as
+    // points or millimeters. Glyphs are rasterized at the resulting size.
+    pub pixels_per_unit: f32,
+    pub clock: FrameClock,
+    // Lists the text runs and tagged primitives of each render for
+    // accessibility apis
+    pub collect_frame_metadata: bool,
+    frame_metadata: FrameMetadata,
+    // Timestamps the submission, gpu completion and presentation of each
+    // frame, measuring the gpu time with timestamp queries where supported,
+    // to estimate the latency from input to the frame showing up
+    pub latency_tracking: bool,
+    latency: LatencyTracker,
+    // Skips layers hidden beneath opaque layer backgrounds
+    pub occlusion_culling: bool,
+    occlusion_stats: OcclusionStats,
+    // Merges compatible consecutive layers to reduce draw calls
+    pub layer_batching: bool,
+    batching_stats: BatchingStats,
+    // Coalesces adjacent quads of the same color, such as the cell
+    // backgrounds of a terminal grid, into single instances. Disable to see
+    // the quads as they are in the scene when debugging.
+    pub merge_adjacent_quads: bool,
+    // Culls the quads of the layers on the gpu with compute passes which
+    // compact the visible quads for an indirect draw, instead of testing
+    // every quad on the cpu. Pays off for scenes with hundreds of thousands
+    // of quads. The quads are then neither merged nor drawn without blending.
+    pub gpu_culling: bool,
+    // Reorders the quads and sprites of a layer which don't overlap so that
+    // those drawn with the same pipeline or texture page are drawn together
+    pub state_sorting: bool,
+    state_sorting_stats: StateSortingStats,
+    // Staging buffers for the buffer uploads of the drawables, reused across
+    // frames
+    staging_belt: StagingBelt,
+    pub memory_budget: MemoryBudget,
+    // Renders a scene to the same pixels on every run on the same adapter, for
+    // golden image tests. The shaders see no time, and the atlases are cleared
+    // every frame so that their packing only depends on the scene. Primitives
+    // are always drawn in scene order and multisampling is resolved by the
+    // hardware with a fixed sample count, so those need no special handling.
+    pub deterministic: bool,
+    // Checks every scene with Scene::validate before rendering it and panics
+    // with the errors found. On by default in debug builds.
+    pub validate_scenes: bool,
+    // Values of the theme variables which fields of the rendered scenes
+    // refer to
+    pub theme: Theme,
+    // Grid, rulers and guides drawn on top of every scene while visible
+    pub design_overlay: Option<DesignOverlay>,
+    // Steps down to simpler levels of detail while the frames take longer
+    // than the budget of the policy, and back up once they are fast again.
+    // Ignored when rendering deterministically.
+    pub lod_policy: Option<LodPolicy>,
+    lod: LodController,
+    // Samples per pixel of the multisampled texture and the pipelines of the
+    // drawables
+    sample_count: u32,
+    // Color lookup table the whole frame is drawn through after the scenes
+    color_grading: Option<ColorGradingPass>,
+    // Blend from the original colors of the frame at zero to the graded ones
+    // at one, for fading the grading in and out
+    pub color_grading_strength: f32,
+    // Rasterizes new glyphs on worker threads instead of while drawing the
+    // frame, which avoids stalls when many glyphs appear at once. The glyphs
+    // are queued while preparing the scene and uploaded to the atlas in the
+    // frame after they are ready. Until then a glyph is drawn at another
+    // subpixel offset if that is in the atlas and left out otherwise.
+    // Ignored when rendering deterministically.
+    pub background_glyph_rasterization: bool,
+    // Worker threads of the background glyph rasterization. Defaults to half
+    // of the cores, at most four.
+    pub glyph_rasterizer_threads: usize,
+    // Loads and decodes images and fonts from the asset source on a worker
+    // thread instead of while drawing the frame. Images which are still
+    // loading are drawn with the image placeholder color, text in a font
+    // which is still loading is left out. Ignored when rendering
+    // deterministically.
+    pub background_asset_loading: bool,
+    // Draws box drawing, block and Powerline characters as geometry aligned
+    // to the cell instead of with the font, so that lines in terminal grids
+    // connect without gaps. Text can override it per run.
+    pub synthetic_box_drawing: bool,
+    // Draws characters the font has no glyph for as a box with the hex code
+    // of the character instead of the missing glyph of the font. On by
+    // default.
+    pub missing_glyph_boxes: bool,
+    // Hinting and pixel snapping of the glyphs of text runs which don't set
+    // their own. Defaults to hinted glyphs at subpixel positions.
+    pub text_rendering: TextRendering,
+    // Fakes bold and italic text by dilating and shearing the glyphs of the
+    // regular face when the family has no such face. Disabled, such text is
+    // drawn with the closest face there is.
+    pub font_synthesis: bool,
+    // Rasterizes the glyphs at every zoom by default. Stepped, zooming only
+    // rasterizes them again at steps of the zoom and scales them in between,
+    // until the zoom settles.
+    pub glyph_zoom_policy: GlyphZoomPolicy,
+    // Shaped text runs kept across frames, so that scrolling only shapes the
+    // newly visible runs. The least recently drawn runs are evicted first.
+    pub shaping_cache_capacity: usize,
+    // High-water marks of the memory usage at the end of the previous frames
+    memory_peaks: MemoryReport,
 }
 
 impl Renderer {
-    // Creating some of the wgpu types requires async code
-    pub async fn new(width: u32, height: u32, adapter: Adapter, format: TextureFormat) -> Self {
-        let (device, queue) = adapter
-            .request_device(
-                &DeviceDescriptor {
-                    required_features: Features::PUSH_CONSTANTS
-                        | Features::SPIRV_SHADER_PASSTHROUGH
-                        | Features::VERTEX_WRITABLE_STORAGE
-                        | Features::CLEAR_TEXTURE,
-                    required_limits: Limits {
-                        max_push_constant_size: 256,
-                        ..Default::default()
-                    },
-                    label: None,
-                },
-                None,
-            )
-            .await
-            .unwrap();
+    // Creating some of the wgpu types requires async code. Fails when the
+    // adapter can't run the renderer.
+    pub async fn new(
+        width: u32,
+        height: u32,
+        adapter: Adapter,
+        format: TextureFormat,
+    ) -> Result<Self, RendererError> {
+        let capabilities = Capabilities::new(&adapter);
+        if !capabilities.is_supported() {
+            return Err(RendererError::UnsupportedAdapter {
+                adapter: adapter.get_info().name,
+                missing: capabilities.missing.clone(),
+            });
+        }
+        let (device, queue) = external_image::request_device(
+            &adapter,
+            &DeviceDescriptor {
+                required_features: capabilities.features,
+                required_limits: capabilities.required_limits(&adapter.limits()),
+                label: None,
+            },
+        )
+        .await
+        .map_err(|error| RendererError::RequestDevice(error.to_string()))?;
+
+        debug!(
+            features = ?device.features(),
+            max_texture_size = device.limits().max_texture_dimension_2d,
+            downlevel = capabilities.downlevel,
+            "Created device"
+        );
 
+        // Dropping the device also reports it as lost, which isn't an error
+        let device_lost = Arc::new(AtomicBool::new(false));
+        device.set_device_lost_callback({
+            let device_lost = device_lost.clone();
+            move |reason, message| {
+                if matches!(
+                    reason,
+                    DeviceLostReason::Unknown | DeviceLostReason::Destroyed
+                ) {
+                    error!(?reason, "Device lost: {message}");
+                    device_lost.store(true, Ordering::Relaxed);
+                } else {
+                    debug!(?reason, "Device dropped");
+                }
+            }
+        });
+        // Validation and out of memory errors which no error scope caught.
+        // Debug builds still panic on them like wgpu does by default.
+        device.on_uncaptured_error(Box::new(|error| {
+            error!("Uncaptured device error: {error}");
+            if cfg!(debug_assertions) {
+                panic!("{error}");
+            }
+        }));
+
+        let spirv = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/spirv/shader.spv"));
+        let shader_reflection = reflect(spirv);
+        if let Some(module) = &shader_reflection {
+            verify_push_constants(module, &shader_constants_layout())
+                .map_err(RendererError::ShaderMismatch)?;
+        }
         let shader = device.create_shader_module(ShaderModuleDescriptor {
             label: Some("Shader"),
-            source: util::make_spirv(
-                &Asset::get("shader.spv")
-                    .expect("Could not load shader")
-                    .data,
-            ),
+            source: util::make_spirv(spirv),
         });
 
+        let requested_size = (width, height);
+        let (width, height, render_scale) =
+            fit_texture_size(width, height, device.limits().max_texture_dimension_2d);
+
         let offscreen_texture =
             create_texture(&device, width, height, format, 1, "Offscreen Texture");
         let multisampled_texture =
             create_texture(&device, width, height, format, 4, "Output Texture");
+        let previous_frame_texture =
+            create_texture(&device, width, height, format, 1, "Previous Frame Texture");
 
         let sampler = device.create_sampler(&SamplerDescriptor {
             address_mode_u: AddressMode::ClampToEdge,
@@ -86,131 +443,1354 @@ impl Renderer {
             ..Default::default()
         });
 
-        let universal_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("Universal bind group layout"),
-                entries: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Texture {
-                            sample_type: TextureSampleType::Float { filterable: true },
-                            view_dimension: TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
-
+        let universal_bind_group_layout = create_bind_group_layout(&device, &[]);
         let universal_bind_group = create_bind_group(
             &device,
             &universal_bind_group_layout,
             &offscreen_texture,
+            &previous_frame_texture,
             &sampler,
+            &[],
         );
 
-        Self {
+        let assets = Arc::new(SharedAssets::default());
+        let image_atlas = Arc::new(Mutex::new(ImageAtlas::new(&device, assets.clone())));
+
+        Ok(Self {
             adapter,
             device,
             queue,
             shader,
+            shader_reflection,
+            pipeline_errors: RefCell::new(None),
 
             format,
             width,
             height,
+            requested_size,
+            render_scale,
 
             offscreen_texture,
             multisampled_texture,
+            previous_frame_texture,
             sampler,
             universal_bind_group_layout,
             universal_bind_group,
+            universal_entries: Vec::new(),
 
             drawables: Vec::new(),
+            drawable_factories: Vec::new(),
+            device_lost,
+            capabilities,
+            assets,
+            image_atlas,
+
+            antialiasing_width: 0.0,
+            scale_factor: 1.0,
+            coordinate_origin: CoordinateOrigin::default(),
+            pixels_per_unit: 1.0,
+            clock: FrameClock::new(),
+            collect_frame_metadata: false,
+            frame_metadata: FrameMetadata::default(),
+            latency_tracking: false,
+            latency: LatencyTracker::default(),
+            occlusion_culling: true,
+            occlusion_stats: OcclusionStats::default(),
+            layer_batching: true,
+            merge_adjacent_quads: true,
+            gpu_culling: false,
+            state_sorting: true,
+            state_sorting_stats: StateSortingStats::default(),
+            batching_stats: BatchingStats::default(),
+            staging_belt: StagingBelt::new(STAGING_BELT_CHUNK_SIZE),
+            memory_budget: MemoryBudget::default(),
+            deterministic: false,
+            validate_scenes: cfg!(debug_assertions),
+            theme: Theme::new(),
+            design_overlay: None,
+            lod_policy: None,
+            lod: LodController::default(),
+            sample_count: 4,
+            color_grading: None,
+            color_grading_strength: 1.0,
+            background_glyph_rasterization: false,
+            glyph_rasterizer_threads: default_rasterizer_threads(),
+            synthetic_box_drawing: false,
+            missing_glyph_boxes: true,
+            text_rendering: TextRendering::default(),
+            font_synthesis: true,
+            glyph_zoom_policy: GlyphZoomPolicy::default(),
+            shaping_cache_capacity: DEFAULT_SHAPING_CACHE_CAPACITY,
+            background_asset_loading: false,
+            memory_peaks: MemoryReport::default(),
+        })
+    }
+
+    pub fn set_collect_frame_metadata(&mut self, enabled: bool) {
+        self.collect_frame_metadata = enabled;
+    }
+
+    pub fn with_collect_frame_metadata(mut self, enabled: bool) -> Self {
+        self.set_collect_frame_metadata(enabled);
+        self
+    }
+
+    // Text runs and interactive regions of the last render. Empty unless
+    // collect_frame_metadata is enabled.
+    pub fn frame_metadata(&self) -> &FrameMetadata {
+        &self.frame_metadata
+    }
+
+    pub fn set_latency_tracking(&mut self, enabled: bool) {
+        self.latency_tracking = enabled;
+    }
+
+    pub fn with_latency_tracking(mut self, enabled: bool) -> Self {
+        self.set_latency_tracking(enabled);
+        self
+    }
+
+    // Marks that input arrived which the next rendered frame responds to.
+    // Only the first input before a frame counts.
+    pub fn mark_input(&mut self) {
+        self.mark_input_at(Instant::now());
+    }
+
+    pub fn mark_input_at(&mut self, time: Instant) {
+        if self.latency_tracking {
+            self.latency.mark_input(time);
+        }
+    }
+
+    // Call after submitting the encoder returned by submit_and_render when
+    // tracking latency. The render methods which submit call it themselves.
+    pub fn frame_submitted(&mut self) {
+        if self.latency_tracking {
+            self.latency.submitted(&self.queue);
+        }
+    }
+
+    // Call right after presenting the rendered frame when tracking latency
+    pub fn frame_presented(&mut self) {
+        if self.latency_tracking {
+            self.latency.presented();
+        }
+    }
+
+    // The latest frame the gpu finished, when latency tracking is enabled
+    pub fn frame_latency(&self) -> Option<FrameLatency> {
+        self.latency.last()
+    }
+
+    pub fn set_occlusion_culling(&mut self, enabled: bool) {
+        self.occlusion_culling = enabled;
+    }
+
+    pub fn with_occlusion_culling(mut self, enabled: bool) -> Self {
+        self.set_occlusion_culling(enabled);
+        self
+    }
+
+    // Layers and primitives skipped by the last render because they were
+    // hidden beneath opaque layers
+    pub fn occlusion_stats(&self) -> OcclusionStats {
+        self.occlusion_stats
+    }
+
+    pub fn set_layer_batching(&mut self, enabled: bool) {
+        self.layer_batching = enabled;
+    }
+
+    pub fn with_layer_batching(mut self, enabled: bool) -> Self {
+        self.set_layer_batching(enabled);
+        self
+    }
+
+    pub fn set_merge_adjacent_quads(&mut self, enabled: bool) {
+        self.merge_adjacent_quads = enabled;
+    }
+
+    pub fn with_merge_adjacent_quads(mut self, enabled: bool) -> Self {
+        self.set_merge_adjacent_quads(enabled);
+        self
+    }
+
+    pub fn set_gpu_culling(&mut self, enabled: bool) {
+        self.gpu_culling = enabled;
+    }
+
+    pub fn with_gpu_culling(mut self, enabled: bool) -> Self {
+        self.set_gpu_culling(enabled);
+        self
+    }
+
+    // Visible layers of the last render and the batches they were drawn in
+    pub fn batching_stats(&self) -> BatchingStats {
+        self.batching_stats
+    }
+
+    pub fn set_state_sorting(&mut self, enabled: bool) {
+        self.state_sorting = enabled;
+    }
+
+    pub fn with_state_sorting(mut self, enabled: bool) -> Self {
+        self.set_state_sorting(enabled);
+        self
+    }
+
+    // Pipeline and bind group changes of the quads and sprites in the last
+    // render, with and without state sorting
+    pub fn state_sorting_stats(&self) -> StateSortingStats {
+        self.state_sorting_stats
+    }
+
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.set_deterministic(deterministic);
+        self
+    }
+
+    pub fn set_validate_scenes(&mut self, enabled: bool) {
+        self.validate_scenes = enabled;
+    }
+
+    pub fn with_validate_scenes(mut self, enabled: bool) -> Self {
+        self.set_validate_scenes(enabled);
+        self
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.set_theme(theme);
+        self
+    }
+
+    pub fn set_design_overlay(&mut self, overlay: Option<DesignOverlay>) {
+        self.design_overlay = overlay;
+    }
+
+    pub fn with_design_overlay(mut self, overlay: DesignOverlay) -> Self {
+        self.set_design_overlay(Some(overlay));
+        self
+    }
+
+    // Shows or hides the design overlay, if there is one
+    pub fn toggle_design_overlay(&mut self) {
+        if let Some(overlay) = &mut self.design_overlay {
+            overlay.toggle();
+        }
+    }
+
+    // Steps the level of detail down while frames are over the budget of the
+    // policy, or always draws at full detail with None
+    pub fn set_lod_policy(&mut self, policy: Option<LodPolicy>) {
+        self.lod_policy = policy;
+        self.lod.reset();
+    }
+
+    pub fn with_lod_policy(mut self, policy: LodPolicy) -> Self {
+        self.set_lod_policy(Some(policy));
+        self
+    }
+
+    // Level of detail the next frame is drawn at
+    pub fn lod_level(&self) -> LodLevel {
+        if self.lod_policy.is_some() && !self.deterministic {
+            self.lod.level()
+        } else {
+            LodLevel::Full
+        }
+    }
+
+    // Samples per pixel the drawables have to create their pipelines with.
+    // Drops to one while the level of detail is LodLevel::NoMultisampling,
+    // in which case the pipelines of the drawables are recreated.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    fn set_sample_count(&mut self, sample_count: u32) {
+        if self.sample_count == sample_count {
+            return;
+        }
+        info!(
+            sample_count,
+            "Recreating the pipelines for a new sample count"
+        );
+        self.sample_count = sample_count;
+        self.multisampled_texture = create_texture(
+            &self.device,
+            self.width,
+            self.height,
+            self.format,
+            sample_count,
+            "Multisampled Texture",
+        );
+        self.recreate_pipelines();
+    }
+
+    // Applies the table to every rendered frame, or stops grading with None
+    pub fn set_color_lut(&mut self, lut: Option<ColorLut>) {
+        self.color_grading = lut.map(|lut| ColorGradingPass::new(self, lut));
+    }
+
+    pub fn with_color_lut(mut self, lut: ColorLut) -> Self {
+        self.set_color_lut(Some(lut));
+        self
+    }
+
+    pub fn color_lut(&self) -> Option<&ColorLut> {
+        self.color_grading
+            .as_ref()
+            .map(|color_grading| color_grading.lut())
+    }
+
+    pub fn set_color_grading_strength(&mut self, strength: f32) {
+        self.color_grading_strength = strength.clamp(0.0, 1.0);
+    }
+
+    pub fn with_color_grading_strength(mut self, strength: f32) -> Self {
+        self.set_color_grading_strength(strength);
+        self
+    }
+
+    pub fn set_background_glyph_rasterization(&mut self, enabled: bool) {
+        self.background_glyph_rasterization = enabled;
+    }
+
+    pub fn with_background_glyph_rasterization(mut self, enabled: bool) -> Self {
+        self.set_background_glyph_rasterization(enabled);
+        self
+    }
+
+    pub fn set_glyph_rasterizer_threads(&mut self, threads: usize) {
+        self.glyph_rasterizer_threads = threads;
+    }
+
+    pub fn with_glyph_rasterizer_threads(mut self, threads: usize) -> Self {
+        self.set_glyph_rasterizer_threads(threads);
+        self
+    }
+
+    pub fn set_synthetic_box_drawing(&mut self, enabled: bool) {
+        self.synthetic_box_drawing = enabled;
+    }
+
+    pub fn with_synthetic_box_drawing(mut self, enabled: bool) -> Self {
+        self.set_synthetic_box_drawing(enabled);
+        self
+    }
+
+    pub fn set_missing_glyph_boxes(&mut self, enabled: bool) {
+        self.missing_glyph_boxes = enabled;
+    }
+
+    pub fn with_missing_glyph_boxes(mut self, enabled: bool) -> Self {
+        self.set_missing_glyph_boxes(enabled);
+        self
+    }
+
+    pub fn set_text_rendering(&mut self, text_rendering: TextRendering) {
+        self.text_rendering = text_rendering;
+    }
+
+    pub fn with_text_rendering(mut self, text_rendering: TextRendering) -> Self {
+        self.set_text_rendering(text_rendering);
+        self
+    }
+
+    pub fn set_font_synthesis(&mut self, enabled: bool) {
+        self.font_synthesis = enabled;
+    }
+
+    pub fn with_font_synthesis(mut self, enabled: bool) -> Self {
+        self.set_font_synthesis(enabled);
+        self
+    }
+
+    pub fn set_glyph_zoom_policy(&mut self, policy: GlyphZoomPolicy) {
+        self.glyph_zoom_policy = policy;
+    }
+
+    pub fn with_glyph_zoom_policy(mut self, policy: GlyphZoomPolicy) -> Self {
+        self.set_glyph_zoom_policy(policy);
+        self
+    }
+
+    pub fn set_shaping_cache_capacity(&mut self, capacity: usize) {
+        self.shaping_cache_capacity = capacity;
+    }
+
+    pub fn with_shaping_cache_capacity(mut self, capacity: usize) -> Self {
+        self.set_shaping_cache_capacity(capacity);
+        self
+    }
+
+    // Hits and misses of the shaping cache since the glyph drawable was
+    // added
+    pub fn shaping_cache_stats(&self) -> ShapingCacheStats {
+        self.drawable::<GlyphState>()
+            .map_or(ShapingCacheStats::default(), |glyphs| {
+                glyphs.shaping_cache_stats()
+            })
+    }
+
+    pub fn clear_shaping_cache(&mut self) {
+        if let Some(glyphs) = self.drawable_mut::<GlyphState>() {
+            glyphs.clear_shaping_cache();
+        }
+    }
+
+    // Glyphs still being rasterized in the background. Render again while
+    // there are any to draw them once they are ready.
+    pub fn pending_glyphs(&self) -> usize {
+        self.drawable::<GlyphState>()
+            .map_or(0, |glyphs| glyphs.pending_glyphs())
+    }
+
+    // Whether the last frame drew glyphs scaled from another zoom by the
+    // glyph zoom policy. Render again while it did, so that the text gets
+    // sharp once the zoom settles.
+    pub fn scaled_glyphs(&self) -> bool {
+        self.drawable::<GlyphState>()
+            .is_some_and(|glyphs| glyphs.scaled_glyphs())
+    }
+
+    // Shapes the text as one run in the font and returns the grid cells each
+    // cluster covers, which is more than one for ligatures. None without the
+    // glyph drawable or while the font is loading in the background.
+    pub fn cell_clusters(
+        &mut self,
+        font_name: &str,
+        text: &str,
+        size: f32,
+    ) -> Option<Vec<CellCluster>> {
+        self.drawable_mut::<GlyphState>()?
+            .cell_clusters(font_name, text, size)
+    }
+
+    // Width and line metrics of the text in the font, from which the
+    // decorations are placed. None without the glyph drawable or while the
+    // font is loading in the background.
+    pub fn measure_text(&mut self, font_name: &str, text: &Text) -> Option<TextMetrics> {
+        self.drawable_mut::<GlyphState>()?
+            .measure_text(font_name, text)
+    }
+
+    pub fn set_background_asset_loading(&mut self, enabled: bool) {
+        self.background_asset_loading = enabled;
+    }
+
+    pub fn with_background_asset_loading(mut self, enabled: bool) -> Self {
+        self.set_background_asset_loading(enabled);
+        self
+    }
+
+    // Color drawn in place of images which are still loading in the
+    // background
+    pub fn set_image_placeholder_color(&mut self, color: Vec4) {
+        self.image_atlas
+            .lock()
+            .unwrap()
+            .set_placeholder_color(color);
+    }
+
+    pub fn with_image_placeholder_color(mut self, color: Vec4) -> Self {
+        self.set_image_placeholder_color(color);
+        self
+    }
+
+    // Images and fonts still being loaded in the background. Render again
+    // while there are any to draw them once they are ready.
+    pub fn pending_assets(&self) -> usize {
+        let fonts = self
+            .drawable::<GlyphState>()
+            .map_or(0, |glyphs| glyphs.pending_fonts());
+        self.image_atlas.lock().unwrap().pending() + fonts
+    }
+
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.memory_budget = budget;
+    }
+
+    pub fn with_memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.set_memory_budget(budget);
+        self
+    }
+
+    // Evicts everything the atlases hold, for when the system runs low on
+    // memory, such as on a memory warning on iOS or Android. The glyphs and
+    // images are uploaded again when they are next drawn.
+    pub fn release_memory(&mut self) {
+        let budget = MemoryBudget::new()
+            .with_glyph_atlas_bytes(0)
+            .with_image_atlas_bytes(0);
+        self.image_atlas
+            .lock()
+            .unwrap()
+            .trim(budget.image_atlas_bytes);
+        for drawable in self.drawables.iter_mut() {
+            drawable.trim(&budget);
+        }
+    }
+
+    // Current gpu memory allocations of the renderer and its drawables, with
+    // the high-water marks since the renderer was created
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport::default();
+        report.textures.add_texture(&self.offscreen_texture);
+        report.textures.add_texture(&self.multisampled_texture);
+        report.textures.add_texture(&self.previous_frame_texture);
+        if let Some(color_grading) = &self.color_grading {
+            report.textures.add_texture(color_grading.texture());
+        }
+
+        let image_atlas = self.image_atlas.lock().unwrap();
+        for texture in image_atlas.page_textures() {
+            report.textures.add_texture(texture);
+        }
+        report.image_atlas = image_atlas.usage();
+
+        for drawable in self.drawables.iter() {
+            drawable.memory_usage(&mut report);
         }
+
+        report.record_peaks(&self.memory_peaks);
+        report
+    }
+
+    pub fn set_antialiasing_width(&mut self, width: f32) {
+        self.antialiasing_width = width.max(0.0);
+    }
+
+    pub fn with_antialiasing_width(mut self, width: f32) -> Self {
+        self.set_antialiasing_width(width);
+        self
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        assert!(scale_factor > 0.0, "The scale factor has to be positive");
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn with_scale_factor(mut self, scale_factor: f32) -> Self {
+        self.set_scale_factor(scale_factor);
+        self
+    }
+
+    pub fn set_coordinate_origin(&mut self, origin: CoordinateOrigin) {
+        self.coordinate_origin = origin;
+    }
+
+    pub fn with_coordinate_origin(mut self, origin: CoordinateOrigin) -> Self {
+        self.set_coordinate_origin(origin);
+        self
     }
 
-    pub fn add_drawable<T: Drawable + 'static>(&mut self) {
-        let drawable = T::new(&self);
-        self.drawables.push(Box::new(drawable));
+    pub fn set_pixels_per_unit(&mut self, pixels_per_unit: f32) {
+        assert!(
+            pixels_per_unit > 0.0,
+            "The pixels per unit have to be positive"
+        );
+        self.pixels_per_unit = pixels_per_unit;
+    }
+
+    pub fn with_pixels_per_unit(mut self, pixels_per_unit: f32) -> Self {
+        self.set_pixels_per_unit(pixels_per_unit);
+        self
+    }
+
+    // Converts a position on the frame, such as the mouse position, into the
+    // coordinates of a scene drawn over the whole frame, following the
+    // camera and the coordinate origin. Use it with Renderer::hit_test.
+    pub fn surface_to_scene(&self, camera: &Camera, position: Vec2) -> Vec2 {
+        self.camera_constants(camera, self.width, self.height)
+            .from_surface(position)
+    }
+
+    // Scene::hit_test which also hits the text without a fit within its
+    // shaped line, for a scene drawn over the whole frame. The position is in
+    // scene coordinates.
+    pub fn hit_test(&mut self, scene: &Scene, position: Vec2) -> Vec<Hit> {
+        let y_direction = self
+            .camera_constants(&scene.camera, self.width, self.height)
+            .y_direction;
+        let mut glyphs = self.drawable_mut::<GlyphState>();
+        scene.hit_test_with(position, |layer, text| {
+            glyphs
+                .as_deref_mut()?
+                .text_bounds(&layer.font_name, text, y_direction)
+        })
     }
 
+    // Tags of the primitives Renderer::hit_test hits, top most first
+    pub fn hit_test_tags(&mut self, scene: &Scene, position: Vec2) -> Vec<u64> {
+        self.hit_test(scene, position)
+            .into_iter()
+            .filter_map(|hit| hit.tag)
+            .collect()
+    }
+
+    // The inverse of surface_to_scene
+    pub fn scene_to_surface(&self, camera: &Camera, position: Vec2) -> Vec2 {
+        self.camera_constants(camera, self.width, self.height)
+            .to_surface(position)
+    }
+
+    // Positions the primitives of the scene which have a placement for a
+    // scene drawn over the whole frame. The renderer does this while
+    // rendering, so it's only needed for hit testing the placed positions.
+    pub fn resolve_placements(&self, scene: &mut Scene) {
+        self.place_primitives(scene, self.width, self.height);
+    }
+
+    fn place_primitives(&self, scene: &mut Scene, width: u32, height: u32) {
+        let constants = self.camera_constants(&scene.camera, width, height);
+        for layer in scene.layers.iter_mut() {
+            let rect = visible_content_rect(&constants, layer);
+            layer.resolve_placements(rect, constants.y_direction);
+        }
+    }
+
+    // Constants of a scene drawn into a surface of the given size, without
+    // the frame time. The zoom includes the units, and the render scale which
+    // fits the requested size into the clamped one by zooming out.
+    fn camera_constants(&self, camera: &Camera, width: u32, height: u32) -> ShaderConstants {
+        let zoom = camera.zoom * self.pixels_per_unit * self.render_scale;
+        ShaderConstants::new(vec2(width as f32, height as f32))
+            .with_target_size(vec2(self.width as f32, self.height as f32))
+            .with_atlas_size(ATLAS_SIZE)
+            .with_camera(camera.offset, camera.rotation_vector(), zoom)
+            .with_antialiasing_width(self.antialiasing_width * self.scale_factor)
+            .with_scale_factor(self.scale_factor)
+            .with_y_direction(self.coordinate_origin.y_direction())
+    }
+
+    // Advances the frame time by a fixed amount every frame instead of
+    // following the wall clock
+    pub fn set_fixed_time_step(&mut self, time_step: Option<f32>) {
+        self.clock.set_fixed_time_step(time_step);
+    }
+
+    pub fn with_fixed_time_step(mut self, time_step: Option<f32>) -> Self {
+        self.set_fixed_time_step(time_step);
+        self
+    }
+
+    // Registers a drawable of the type. Each type can only be registered
+    // once, and its name can't be used by another drawable.
+    pub fn add_drawable<T: Drawable + 'static>(&mut self) -> Result<(), DrawableError> {
+        if self.drawable::<T>().is_some() {
+            return Err(DrawableError::AlreadyRegistered(std::any::type_name::<T>()));
+        }
+
+        let factory: DrawableFactory = |renderer| Box::new(T::new(renderer));
+        // Pipelines which don't match the shader fail validation, which is
+        // caught so that it's reported with the mismatches
+        *self.pipeline_errors.borrow_mut() = Some(Vec::new());
+        self.device.push_error_scope(ErrorFilter::Validation);
+        let drawable = factory(self);
+        let validation_error = block_on(self.device.pop_error_scope());
+        let mut errors = self.pipeline_errors.take().unwrap_or_default();
+        if let Some(validation_error) = validation_error {
+            errors.push(validation_error.to_string());
+        }
+        if !errors.is_empty() {
+            return Err(DrawableError::PipelineMismatch {
+                name: drawable.name(),
+                errors,
+            });
+        }
+
+        if let Some(existing) = self
+            .drawables
+            .iter()
+            .find(|existing| existing.name() == drawable.name())
+        {
+            return Err(DrawableError::DuplicateName {
+                name: drawable.name(),
+                existing: existing.as_ref().type_name(),
+            });
+        }
+
+        debug!(
+            name = drawable.name(),
+            "Created the pipelines of the drawable"
+        );
+        self.drawables.push(drawable);
+        self.drawable_factories.push(factory);
+        Ok(())
+    }
+
+    // Panics if the drawable can't be registered, see add_drawable
     pub fn with_drawable<T: Drawable + 'static>(mut self) -> Self {
-        self.add_drawable::<T>();
+        if let Err(error) = self.add_drawable::<T>() {
+            panic!("{error}");
+        }
         self
     }
 
-    pub fn add_default_drawables<A: RustEmbed + 'static>(&mut self) {
-        self.add_drawable::<QuadState>();
-        self.add_drawable::<GlyphState>();
-        self.add_drawable::<PathState>();
-        self.add_drawable::<SpriteState<A>>();
+    // Names of the registered drawables in the order they draw each layer
+    pub fn drawable_names(&self) -> Vec<&'static str> {
+        self.drawables
+            .iter()
+            .map(|drawable| drawable.name())
+            .collect()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
+    // Creates a new renderer on the adapter with the same size, settings and
+    // drawables. Used to recover from a lost device or a change of surface
+    // format. The drawables start out empty, so atlas contents such as glyphs
+    // and images are uploaded again the next time they are drawn.
+    pub async fn recreate(
+        &self,
+        adapter: Adapter,
+        format: TextureFormat,
+    ) -> Result<Self, RendererError> {
+        let (width, height) = self.requested_size;
+        let mut renderer = Renderer::new(width, height, adapter, format).await?;
+        renderer.antialiasing_width = self.antialiasing_width;
+        renderer.scale_factor = self.scale_factor;
+        renderer.coordinate_origin = self.coordinate_origin;
+        renderer.pixels_per_unit = self.pixels_per_unit;
+        renderer.clock = self.clock.clone();
+        renderer.occlusion_culling = self.occlusion_culling;
+        renderer.collect_frame_metadata = self.collect_frame_metadata;
+        renderer.latency_tracking = self.latency_tracking;
+        renderer.layer_batching = self.layer_batching;
+        renderer.merge_adjacent_quads = self.merge_adjacent_quads;
+        renderer.gpu_culling = self.gpu_culling;
+        renderer.state_sorting = self.state_sorting;
+        renderer.memory_budget = self.memory_budget;
+        renderer.deterministic = self.deterministic;
+        renderer.validate_scenes = self.validate_scenes;
+        renderer.theme = self.theme.clone();
+        renderer.design_overlay = self.design_overlay.clone();
+        renderer.lod_policy = self.lod_policy;
+        renderer.color_grading_strength = self.color_grading_strength;
+        if let Some(color_grading) = &self.color_grading {
+            renderer.set_color_lut(Some(color_grading.lut().clone()));
+        }
+        renderer.background_glyph_rasterization = self.background_glyph_rasterization;
+        renderer.glyph_rasterizer_threads = self.glyph_rasterizer_threads;
+        renderer.background_asset_loading = self.background_asset_loading;
+        renderer.synthetic_box_drawing = self.synthetic_box_drawing;
+        renderer.missing_glyph_boxes = self.missing_glyph_boxes;
+        renderer.text_rendering = self.text_rendering;
+        renderer.font_synthesis = self.font_synthesis;
+        renderer.glyph_zoom_policy = self.glyph_zoom_policy;
+        renderer.shaping_cache_capacity = self.shaping_cache_capacity;
+        renderer.assets = self.assets.clone();
+        renderer.image_atlas = Arc::new(Mutex::new(
+            self.image_atlas.lock().unwrap().recreate(&renderer.device),
+        ));
+        renderer.universal_entries = self
+            .universal_entries
+            .iter()
+            .map(|entry| entry.recreate(&renderer.device, &renderer.queue))
+            .collect();
+        renderer.universal_bind_group_layout =
+            create_bind_group_layout(&renderer.device, &renderer.universal_entries);
+        renderer.update_universal_bind_group();
+        for factory in self.drawable_factories.iter() {
+            renderer.drawables.push(factory(&renderer));
+            renderer.drawable_factories.push(*factory);
+        }
+        Ok(renderer)
+    }
+
+    pub fn drawable<T: Drawable + 'static>(&self) -> Option<&T> {
+        self.drawables
+            .iter()
+            .find_map(|drawable| drawable.as_ref().as_any().downcast_ref::<T>())
+    }
+
+    pub fn drawable_mut<T: Drawable + 'static>(&mut self) -> Option<&mut T> {
+        self.drawables
+            .iter_mut()
+            .find_map(|drawable| drawable.as_mut().as_any_mut().downcast_mut::<T>())
     }
 
-    pub fn with_default_drawables<A: RustEmbed + 'static>(mut self) -> Self {
-        self.add_default_drawables::<A>();
+    // Adds the drawables for every scene primitive without setting an asset
+    // source. Images then have to be registered with add_image or
+    // add_image_rgba, or loaded from a source set with set_asset_source.
+    pub fn add_builtin_drawables(&mut self) -> Result<(), DrawableError> {
+        self.add_drawable::<QuadState>()?;
+        self.add_drawable::<ProceduralState>()?;
+        self.add_drawable::<ShapeState>()?;
+        self.add_drawable::<GlyphState>()?;
+        self.add_drawable::<PathState>()?;
+        self.add_drawable::<SpriteState>()?;
+        self.add_drawable::<BackdropBlurState>()?;
+        self.add_drawable::<CaretState>()?;
+        self.add_drawable::<DimmingState>()
+    }
+
+    // Panics if any of the drawables can't be registered
+    pub fn with_builtin_drawables(mut self) -> Self {
+        if let Err(error) = self.add_builtin_drawables() {
+            panic!("{error}");
+        }
+        self
+    }
+
+    // Adds the builtin drawables and loads images and fonts from the assets
+    pub fn add_default_drawables(
+        &mut self,
+        assets: impl AssetSource + 'static,
+    ) -> Result<(), DrawableError> {
+        self.set_asset_source(assets);
+        self.add_builtin_drawables()
+    }
+
+    // Panics if any of the drawables can't be registered
+    pub fn with_default_drawables(mut self, assets: impl AssetSource + 'static) -> Self {
+        if let Err(error) = self.add_default_drawables(assets) {
+            panic!("{error}");
+        }
+        self
+    }
+
+    // Source of the images which haven't been registered directly, of fonts
+    // and of the shader module loaded by reload_shader. Replaces any previous
+    // source. Images which have already been uploaded are not reloaded.
+    pub fn set_asset_source(&mut self, assets: impl AssetSource + 'static) {
+        self.assets.set(assets);
+    }
+
+    pub fn with_asset_source(mut self, assets: impl AssetSource + 'static) -> Self {
+        self.set_asset_source(assets);
         self
     }
 
+    // Replaces the builtin shader module with SHADER_ASSET from the asset
+    // source and recreates the drawables to use it. Returns false and keeps
+    // the current shader if the source doesn't have the asset or its push
+    // constants don't match ShaderConstants. Combined with DirectoryAssets
+    // this allows hot reloading the shaders.
+    pub fn reload_shader(&mut self) -> bool {
+        let Some(spirv) = self.assets.load(SHADER_ASSET) else {
+            return false;
+        };
+        if let Err(ShaderLayoutError::Mismatch(_)) = verify_shader_constants(&spirv) {
+            return false;
+        }
+        self.shader_reflection = reflect(&spirv);
+
+        self.shader = self.device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: util::make_spirv(&spirv),
+        });
+        self.recreate_drawables();
+        true
+    }
+
+    // Checks that the pipeline provides every binding and vertex input its
+    // entry points use. Drawables call this before creating their pipelines.
+    // add_drawable returns the bindings which are missing or have the wrong
+    // type as an error, otherwise they are logged.
+    pub fn verify_pipeline(&self, interface: &PipelineInterface) {
+        let Some(module) = &self.shader_reflection else {
+            return;
+        };
+        if let Err(error) = verify_pipeline_interface(module, interface) {
+            match self.pipeline_errors.borrow_mut().as_mut() {
+                Some(errors) => errors.push(error.to_string()),
+                None => error!("{error}"),
+            }
+        }
+    }
+
+    // Entries of the universal bind group layout, for verifying pipelines
+    pub fn universal_layout_entries(&self) -> Vec<BindGroupLayoutEntry> {
+        universal_layout_entries(&self.universal_entries)
+    }
+
+    // Adds a resource shared by every drawable to the universal bind group
+    // and returns the handle used to look up its binding index. The resource
+    // is created with the given function, which is called again when the
+    // renderer is recreated on a new device. Registered drawables are
+    // recreated to pick up the new layout, so bindings are best added right
+    // after constructing the renderer.
+    pub fn add_universal_binding<R: UniversalResource + 'static>(
+        &mut self,
+        visibility: ShaderStages,
+        ty: BindingType,
+        create: impl Fn(&Device, &Queue) -> R + 'static,
+    ) -> UniversalBinding<R> {
+        let binding = FIRST_USER_UNIVERSAL_BINDING + self.universal_entries.len() as u32;
+        let factory: UniversalResourceFactory =
+            Rc::new(move |device, queue| Box::new(create(device, queue)));
+        self.universal_entries.push(UniversalEntry {
+            layout: BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty,
+                count: None,
+            },
+            resource: factory(&self.device, &self.queue),
+            factory,
+        });
+
+        self.universal_bind_group_layout =
+            create_bind_group_layout(&self.device, &self.universal_entries);
+        self.update_universal_bind_group();
+        self.recreate_drawables();
+        UniversalBinding::new(binding)
+    }
+
+    // Replaces the resource of a binding, for example with a texture of a new
+    // size. The replacement is not kept when the renderer is recreated.
+    pub fn set_universal_binding<R: UniversalResource + 'static>(
+        &mut self,
+        binding: UniversalBinding<R>,
+        resource: R,
+    ) {
+        self.universal_entries[(binding.binding() - FIRST_USER_UNIVERSAL_BINDING) as usize]
+            .resource = Box::new(resource);
+        self.update_universal_bind_group();
+    }
+
+    pub fn universal_binding<R: UniversalResource + 'static>(
+        &self,
+        binding: UniversalBinding<R>,
+    ) -> &R {
+        self.universal_entries[(binding.binding() - FIRST_USER_UNIVERSAL_BINDING) as usize]
+            .resource
+            .as_any()
+            .downcast_ref::<R>()
+            .unwrap()
+    }
+
+    fn update_universal_bind_group(&mut self) {
+        self.universal_bind_group = create_bind_group(
+            &self.device,
+            &self.universal_bind_group_layout,
+            &self.offscreen_texture,
+            &self.previous_frame_texture,
+            &self.sampler,
+            &self.universal_entries,
+        );
+    }
+
+    fn recreate_pipelines(&mut self) {
+        let mut drawables = std::mem::take(&mut self.drawables);
+        for (drawable, factory) in drawables.iter_mut().zip(self.drawable_factories.iter()) {
+            if !drawable.recreate_pipelines(self) {
+                *drawable = factory(self);
+            }
+        }
+        self.drawables = drawables;
+    }
+
+    fn recreate_drawables(&mut self) {
+        self.drawables = self
+            .drawable_factories
+            .iter()
+            .map(|factory| factory(self))
+            .collect();
+    }
+
+    // Registers an encoded png or jpeg image which sprites and patterns can
+    // refer to by name. Bytes which can't be decoded are logged and ignored.
+    #[cfg(feature = "image")]
+    pub fn add_image(&mut self, name: &str, bytes: &[u8]) {
+        match decode_image(bytes) {
+            Some((width, height, data)) => self.add_image_rgba(name, width, height, data),
+            None => error!(name, "Could not add the image"),
+        }
+    }
+
+    // Registers tightly packed rgba8 pixels which sprites and patterns can
+    // refer to by name. Doesn't require the image feature.
+    pub fn add_image_rgba(&mut self, name: &str, width: u32, height: u32, data: Vec<u8>) {
+        self.image_atlas
+            .lock()
+            .unwrap()
+            .add_image_rgba(name, width, height, data);
+    }
+
+    // A size of zero, for example of a minimized window, skips rendering
+    // until the renderer is resized again. The textures are kept meanwhile,
+    // so restoring the previous size doesn't recreate them.
     pub fn resize(&mut self, new_width: u32, new_height: u32) {
-        if new_width != 0 && new_height != 0 {
-            self.width = new_width;
-            self.height = new_height;
-            self.offscreen_texture = create_texture(
-                &self.device,
-                new_width,
-                new_height,
-                self.format,
-                1,
-                "Offscreen Texture",
-            );
-            self.multisampled_texture = create_texture(
-                &self.device,
-                new_width,
-                new_height,
-                self.format,
-                4,
-                "Multisampled Texture",
-            );
+        self.requested_size = (new_width, new_height);
+        let (new_width, new_height, render_scale) = fit_texture_size(
+            new_width,
+            new_height,
+            self.limits().max_texture_dimension_2d,
+        );
+        self.width = new_width;
+        self.height = new_height;
+        self.render_scale = render_scale;
+        if self.is_empty() {
+            return;
+        }
 
-            self.universal_bind_group = create_bind_group(
-                &self.device,
-                &self.universal_bind_group_layout,
-                &self.offscreen_texture,
-                &self.sampler,
-            );
+        let size = self.offscreen_texture.size();
+        if size.width == new_width && size.height == new_height {
+            return;
         }
+
+        self.offscreen_texture = create_texture(
+            &self.device,
+            new_width,
+            new_height,
+            self.format,
+            1,
+            "Offscreen Texture",
+        );
+        self.multisampled_texture = create_texture(
+            &self.device,
+            new_width,
+            new_height,
+            self.format,
+            self.sample_count,
+            "Multisampled Texture",
+        );
+        self.previous_frame_texture = create_texture(
+            &self.device,
+            new_width,
+            new_height,
+            self.format,
+            1,
+            "Previous Frame Texture",
+        );
+
+        self.update_universal_bind_group();
+    }
+
+    // Whether the width or height is zero, in which case nothing is rendered
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    // Limits of the device, such as the maximum texture size. Callers can use
+    // them to keep their layout within what can be rendered at full size.
+    pub fn limits(&self) -> Limits {
+        self.device.limits()
+    }
+
+    // Issues indirect draws for drawables, with multi draw when the device
+    // supports it
+    pub fn indirect_draws(&self) -> IndirectDraws {
+        IndirectDraws::new(self.device.features())
+    }
+
+    // Whether sprites on every page of the image atlas are drawn with one
+    // draw call. Otherwise consecutive sprites on the same page are batched.
+    pub fn supports_texture_arrays(&self) -> bool {
+        self.capabilities.texture_arrays
+    }
+
+    // What the adapter supports, which chose the code paths of the renderer
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    // Scale the scenes are rendered at. Below one when the requested size
+    // exceeds the maximum texture size, in which case the whole scene is
+    // scaled down to fit the smaller frames.
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
     }
 
     pub fn render(&mut self, scene: &Scene, frame: &Texture) {
-        if self.width == 0 || self.height == 0 {
-            return;
+        let encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Frame Encoder"),
+            });
+        let encoder = self.submit_and_render(scene, frame, encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.frame_submitted();
+    }
+
+    // Renders the scene as part of a larger frame. Submits the commands
+    // recorded in the encoder first, as the layers are submitted one by one
+    // after their uploads, so the scene can't be recorded into the encoder.
+    // Returns a new encoder with the remaining commands of the frame, which
+    // the caller can record more commands into and has to submit.
+    pub fn submit_and_render(
+        &mut self,
+        scene: &Scene,
+        frame: &Texture,
+        encoder: CommandEncoder,
+    ) -> CommandEncoder {
+        let viewport = Viewport::new(0, 0, self.width, self.height);
+        self.render_scenes(&[(scene, viewport)], frame, encoder)
+    }
+
+    // Draws each scene into its viewport of the frame in one render, sharing
+    // the uploads, atlases and caches between them. Later scenes are drawn
+    // over earlier ones where the viewports overlap.
+    pub fn render_viewports(&mut self, viewports: &[(Scene, Viewport)], frame: &Texture) {
+        let encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Frame Encoder"),
+            });
+        let encoder = self.submit_and_render_viewports(viewports, frame, encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.frame_submitted();
+    }
+
+    // Like submit_and_render with a scene per viewport
+    pub fn submit_and_render_viewports(
+        &mut self,
+        viewports: &[(Scene, Viewport)],
+        frame: &Texture,
+        encoder: CommandEncoder,
+    ) -> CommandEncoder {
+        let scenes: Vec<_> = viewports
+            .iter()
+            .map(|(scene, viewport)| (scene, *viewport))
+            .collect();
+        self.render_scenes(&scenes, frame, encoder)
+    }
+
+    fn render_scenes(
+        &mut self,
+        scenes: &[(&Scene, Viewport)],
+        frame: &Texture,
+        mut encoder: CommandEncoder,
+    ) -> CommandEncoder {
+        if self.is_empty() {
+            return encoder;
+        }
+        let render_start = Instant::now();
+        // The level of detail picked after the previous frames
+        let lod_level = self.lod_level();
+        self.set_sample_count(lod_level.sample_count());
+        if self.latency_tracking {
+            let timestamp_queries = self.capabilities.timestamp_queries;
+            self.latency
+                .begin_frame(&self.device, &self.queue, &mut encoder, timestamp_queries);
+        }
+
+        // The theme replaces the values of fields bound to theme variables,
+        // the placed primitives are positioned in their layers and the
+        // design overlay is added on top. The render scale is applied by the
+        // zoom of the constants.
+        let mut prepared_scenes = Vec::new();
+        for (scene_index, (scene, viewport)) in scenes.iter().enumerate() {
+            let viewport = viewport.clamp(self.width, self.height);
+            if viewport.is_empty() {
+                continue;
+            }
+
+            if self.validate_scenes {
+                if let Err(errors) = scene.validate() {
+                    let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+                    panic!("Invalid scene:\n{}", errors.join("\n"));
+                }
+            }
+
+            let themed = scene.has_theme_variables();
+            let placed = scene.has_placements();
+            let overlay = self
+                .design_overlay
+                .as_ref()
+                .filter(|overlay| overlay.visible);
+            let scene = if themed || placed || overlay.is_some() {
+                let mut scene = (*scene).clone();
+                if themed {
+                    scene.apply_theme(&self.theme);
+                }
+                if placed {
+                    self.place_primitives(&mut scene, viewport.width, viewport.height);
+                }
+                if let Some(overlay) = overlay {
+                    let constants =
+                        self.camera_constants(&scene.camera, viewport.width, viewport.height);
+                    scene.add_layer(overlay.layer(&constants));
+                }
+                Cow::Owned(scene)
+            } else {
+                Cow::Borrowed(*scene)
+            };
+            prepared_scenes.push((scene_index, scene, viewport));
         }
 
         let frame_view = frame.create_view(&Default::default());
         let multisampled_view = self.multisampled_texture.create_view(&Default::default());
 
-        let constants = ShaderConstants {
-            surface_size: vec2(self.width as f32, self.height as f32),
-            atlas_size: ATLAS_SIZE,
-            clip: Vec4::ZERO,
+        let frame_time = if self.deterministic {
+            FrameTime::default()
+        } else {
+            self.clock.tick()
         };
 
-        let mut first = true;
-        for layer in scene.layers.iter() {
+        // A zero budget evicts everything
+        let budget = if self.deterministic {
+            MemoryBudget::new()
+                .with_glyph_atlas_bytes(0)
+                .with_image_atlas_bytes(0)
+        } else {
+            self.memory_budget
+        };
+        let background_loading = self.background_asset_loading && !self.deterministic;
+        {
+            let mut image_atlas = self.image_atlas.lock().unwrap();
+            image_atlas.trim(budget.image_atlas_bytes);
+            image_atlas.set_background_loading(background_loading);
+            image_atlas.upload_loaded(&self.device, &self.queue);
+        }
+        for drawable in self.drawables.iter_mut() {
+            drawable.trim(&budget);
+        }
+
+        let merge_adjacent_quads = self.merge_adjacent_quads;
+        // Downlevel adapters without compute shaders cull on the cpu
+        let gpu_culling = self.gpu_culling && self.capabilities.supports_gpu_culling();
+        if gpu_culling
+            && self
+                .drawable::<QuadState>()
+                .is_some_and(QuadState::needs_culler)
+        {
+            let culler = QuadCuller::new(self);
+            if let Some(quads) = self.drawable_mut::<QuadState>() {
+                quads.set_culler(culler);
+            }
+        }
+        let state_sorting = self.state_sorting;
+        if let Some(quads) = self.drawable_mut::<QuadState>() {
+            quads.set_merge_adjacent_quads(merge_adjacent_quads);
+            quads.set_gpu_culling(gpu_culling);
+            quads.set_state_sorting(state_sorting);
+            quads.set_lod_level(lod_level);
+        }
+        if let Some(backdrop_blurs) = self.drawable_mut::<BackdropBlurState>() {
+            backdrop_blurs.set_lod_level(lod_level);
+        }
+        if let Some(sprites) = self.drawable_mut::<SpriteState>() {
+            sprites.set_state_sorting(state_sorting);
+        }
+
+        let background_rasterization = self.background_glyph_rasterization && !self.deterministic;
+        let rasterizer_threads = self.glyph_rasterizer_threads;
+        let synthetic_box_drawing = self.synthetic_box_drawing;
+        let missing_glyph_boxes = self.missing_glyph_boxes;
+        let text_rendering = self.text_rendering;
+        let font_synthesis = self.font_synthesis;
+        let glyph_zoom_policy = self.glyph_zoom_policy;
+        let shaping_cache_capacity = self.shaping_cache_capacity;
+        if let Some(glyphs) = self.drawable_mut::<GlyphState>() {
+            glyphs.set_synthetic_box_drawing(synthetic_box_drawing);
+            glyphs.set_missing_glyph_boxes(missing_glyph_boxes);
+            glyphs.set_text_rendering(text_rendering);
+            glyphs.set_font_synthesis(font_synthesis);
+            glyphs.set_zoom_policy(glyph_zoom_policy);
+            glyphs.set_shaping_cache_capacity(shaping_cache_capacity);
+            glyphs.set_rasterizer_threads(rasterizer_threads);
+            glyphs.set_background_rasterization(background_rasterization);
+            glyphs.set_background_font_loading(background_loading);
+        }
+
+        // The quad drawable renders the layer backgrounds which occlude
+        let occlusion_culling = self.occlusion_culling && self.drawable::<QuadState>().is_some();
+        let backgrounds_first = self
+            .drawables
+            .first()
+            .is_some_and(|drawable| drawable.as_any().is::<QuadState>());
+        self.occlusion_stats = OcclusionStats::default();
+        self.batching_stats = BatchingStats::default();
+        self.frame_metadata.clear();
+
+        // Layers of every viewport in drawing order, with the constants of
+        // their scene
+        let mut draws = Vec::new();
+        for (scene_index, scene, viewport) in prepared_scenes.iter() {
+            let constants = self
+                .camera_constants(&scene.camera, viewport.width, viewport.height)
+                .with_time(frame_time.elapsed, frame_time.delta, frame_time.frame_index);
+
+            if self.collect_frame_metadata {
+                let mut frame_metadata = std::mem::take(&mut self.frame_metadata);
+                let glyphs = self.drawable_mut::<GlyphState>();
+                frame_metadata.add_scene(*scene_index, scene, *viewport, &constants, glyphs);
+                self.frame_metadata = frame_metadata;
+            }
+
+            let (hidden, occlusion_stats) = if occlusion_culling {
+                hidden_layers(scene, &constants)
+            } else {
+                (vec![false; scene.layers.len()], OcclusionStats::default())
+            };
+            self.occlusion_stats.layers_skipped += occlusion_stats.layers_skipped;
+            self.occlusion_stats.primitives_skipped += occlusion_stats.primitives_skipped;
+
+            let visible_layers = scene
+                .layers
+                .iter()
+                .zip(hidden)
+                .filter_map(|(layer, hidden)| (!hidden).then_some(layer));
+            let (layers, batching_stats) = if self.layer_batching {
+                // Grow the bounds by the antialiasing ramp and rounding to pixels
+                let margin = (constants.antialiasing_width + 1.0) / constants.camera_zoom;
+                batch_layers(visible_layers, margin, backgrounds_first)
+            } else {
+                let layers: Vec<_> = visible_layers.map(Cow::Borrowed).collect();
+                let stats = BatchingStats {
+                    layers: layers.len(),
+                    batches: layers.len(),
+                };
+                (layers, stats)
+            };
+            self.batching_stats.layers += batching_stats.layers;
+            self.batching_stats.batches += batching_stats.batches;
+
+            let layer_refs: Vec<&Layer> = layers.iter().map(|layer| layer.as_ref()).collect();
+            for drawable in self.drawables.iter_mut() {
+                drawable.prepare(&self.queue, &constants, &layer_refs);
+            }
+
+            for layer in layers {
+                draws.push((*viewport, constants, layer));
+            }
+        }
+
+        // Command buffers of the caller, submitted along with the first layer
+        let mut preceding = Some(encoder.finish());
+        let drawable_count = self.drawables.len();
+        let sample_count = self.sample_count;
+        for (layer_index, (viewport, constants, layer)) in draws.iter().enumerate() {
+            let constants = constants.with_scroll_offset(layer.scroll_offset);
             let mut encoder = self
                 .device
                 .create_command_encoder(&CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
+                    label: Some(&format!("Layer {layer_index} Encoder")),
                 });
-            for drawable in self.drawables.iter_mut() {
-                // Either clear the offscreen texture or copy the previous layer to it
+            // Uploads are recorded into a separate encoder which is submitted
+            // before the render encoder, so that they are complete before any
+            // of the draws of the layer
+            let mut upload_encoder =
+                self.device
+                    .create_command_encoder(&CommandEncoderDescriptor {
+                        label: Some(&format!("Layer {layer_index} Upload Encoder")),
+                    });
+            let mut uploader = Uploader {
+                device: &self.device,
+                queue: &self.queue,
+                encoder: &mut upload_encoder,
+                belt: &mut self.staging_belt,
+            };
+            for (drawable_index, drawable) in self.drawables.iter_mut().enumerate() {
+                let first = layer_index == 0 && drawable_index == 0;
+                let last = layer_index == draws.len() - 1 && drawable_index == drawable_count - 1;
+
+                // Either clear the offscreen texture or copy the previous
+                // passes to it
                 if first {
                     encoder.clear_texture(
                         &self.offscreen_texture,
@@ -244,24 +1824,35 @@ impl Renderer {
                     );
                 }
 
-                // The first drawable should clear the output texture
-                let attachment_op = if first {
-                    Operations::<Color> {
-                        load: LoadOp::<_>::Clear(Color::WHITE),
-                        store: StoreOp::Store,
-                    }
+                // The first pass clears the multisampled texture and the
+                // others continue from it. The samples of the last pass are
+                // only needed for the resolve into the frame. Without
+                // multisampling the passes draw to the frame directly.
+                let multisampled = sample_count > 1;
+                let attachment_op = Operations::<Color> {
+                    load: if first {
+                        LoadOp::Clear(Color::WHITE)
+                    } else {
+                        LoadOp::Load
+                    },
+                    store: if last && multisampled {
+                        StoreOp::Discard
+                    } else {
+                        StoreOp::Store
+                    },
+                };
+                let (view, resolve_target) = if multisampled {
+                    (&multisampled_view, Some(&frame_view))
                 } else {
-                    Operations::<Color> {
-                        load: LoadOp::<_>::Load,
-                        store: StoreOp::Store,
-                    }
+                    (&frame_view, None)
                 };
 
+                let label = format!("{} Pass, Layer {layer_index}", drawable.name());
                 let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                    label: Some("Render Pass"),
+                    label: Some(&label),
                     color_attachments: &[Some(RenderPassColorAttachment {
-                        view: &multisampled_view,
-                        resolve_target: Some(&frame_view),
+                        view,
+                        resolve_target,
                         ops: attachment_op,
                     })],
                     depth_stencil_attachment: None,
@@ -269,27 +1860,170 @@ impl Renderer {
                     occlusion_query_set: None,
                 });
 
-                if let Some(clip) = layer.clip {
-                    let x = (clip.x.ceil().max(0.0) as u32).min(self.width);
-                    let y = (clip.y.ceil().max(0.0) as u32).min(self.height);
-                    let w = (clip.z as u32).min(self.width - x);
-                    let h = (clip.w as u32).min(self.height - y);
-                    render_pass.set_scissor_rect(x, y, w, h);
-                }
+                render_pass.set_viewport(
+                    viewport.x as f32,
+                    viewport.y as f32,
+                    viewport.width as f32,
+                    viewport.height as f32,
+                    0.0,
+                    1.0,
+                );
+                let scissor = match layer.clip {
+                    Some(clip) => scissor_rect(&constants, clip),
+                    None => [0, 0, viewport.width, viewport.height],
+                };
+                let [x, y, w, h] = viewport.offset_scissor(scissor);
+                render_pass.set_scissor_rect(x, y, w, h);
 
                 drawable.draw(
-                    &self.queue,
+                    &mut uploader,
                     &mut render_pass,
                     constants,
                     &self.universal_bind_group,
                     &layer,
                 );
-
-                first = false;
             }
-            self.queue.submit(std::iter::once(encoder.finish()));
+            self.staging_belt.finish();
+            self.queue.submit(
+                preceding
+                    .take()
+                    .into_iter()
+                    .chain([upload_encoder.finish(), encoder.finish()]),
+            );
+            self.staging_belt.recall();
         }
+        if let Some(preceding) = preceding {
+            self.queue.submit(std::iter::once(preceding));
+        }
+
+        let mut state_sorting_stats = StateSortingStats::default();
+        if let Some(quads) = self.drawable_mut::<QuadState>() {
+            state_sorting_stats.add(quads.take_state_sorting_stats());
+        }
+        if let Some(sprites) = self.drawable_mut::<SpriteState>() {
+            state_sorting_stats.add(sprites.take_state_sorting_stats());
+        }
+        self.state_sorting_stats = state_sorting_stats;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Frame End Encoder"),
+            });
+        if let Some(color_grading) = &self.color_grading {
+            color_grading.apply(self, &mut encoder, frame);
+        }
+        encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            self.previous_frame_texture.as_image_copy(),
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        if self.latency_tracking {
+            self.latency.end_frame(&mut encoder);
+        }
+        self.memory_peaks = self.memory_report();
+
+        if let Some(policy) = self.lod_policy.filter(|_| !self.deterministic) {
+            // The gpu time is of an earlier frame, as it's measured once the
+            // gpu has finished it
+            let finished = Instant::now();
+            let gpu_duration = self
+                .latency
+                .last()
+                .and_then(|latency| latency.gpu_duration)
+                .unwrap_or_default();
+            let frame_time = finished.duration_since(render_start).max(gpu_duration);
+            self.lod.record_frame(&policy, finished, frame_time);
+        }
+        encoder
+    }
+}
+
+// Fits a size within the maximum texture dimension, keeping the aspect ratio.
+// Returns the fitted size and the scale applied to it.
+pub(crate) fn fit_texture_size(width: u32, height: u32, max_dimension: u32) -> (u32, u32, f32) {
+    let largest = width.max(height);
+    if largest <= max_dimension {
+        return (width, height, 1.0);
     }
+
+    let scale = max_dimension as f32 / largest as f32;
+    let fit = |dimension: u32| {
+        ((dimension as f32 * scale).round() as u32).clamp(dimension.min(1), max_dimension)
+    };
+    (fit(width), fit(height), scale)
+}
+
+// Computes a scene space rect which covers the entire surface after the
+// camera transform is applied. Ignores the layer scroll offset.
+pub(crate) fn surface_bounds_in_scene(constants: &ShaderConstants) -> Vec4 {
+    let constants = constants.with_scroll_offset(Vec2::ZERO);
+    let corners = [
+        Vec2::ZERO,
+        vec2(constants.surface_size.x, 0.),
+        vec2(0., constants.surface_size.y),
+        constants.surface_size,
+    ]
+    .map(|corner| constants.from_surface(corner));
+
+    let min = corners.into_iter().reduce(Vec2::min).unwrap();
+    let max = corners.into_iter().reduce(Vec2::max).unwrap();
+    vec4(min.x, min.y, max.x - min.x, max.y - min.y)
+}
+
+// The rect in layer content coordinates which is visible through the layer
+// clip after scrolling. Primitives outside of it can be culled.
+pub(crate) fn visible_content_rect(constants: &ShaderConstants, layer: &Layer) -> Vec4 {
+    let visible = surface_bounds_in_scene(constants);
+    let visible = match layer.clip {
+        Some(clip) => intersect_rects(visible, clip),
+        None => visible,
+    };
+    visible + vec4(layer.scroll_offset.x, layer.scroll_offset.y, 0., 0.)
+}
+
+pub(crate) fn intersect_rects(a: Vec4, b: Vec4) -> Vec4 {
+    let min = a.xy().max(b.xy());
+    let max = (a.xy() + a.zw()).min(b.xy() + b.zw());
+    let size = (max - min).max(Vec2::ZERO);
+    vec4(min.x, min.y, size.x, size.y)
+}
+
+pub(crate) fn rects_overlap(a: Vec4, b: Vec4) -> bool {
+    a.x < b.x + b.z && b.x < a.x + a.z && a.y < b.y + b.w && b.y < a.y + a.w
+}
+
+// Computes the surface space bounding box of a scene space clip rect
+pub(crate) fn clip_to_surface(constants: &ShaderConstants, clip: Vec4) -> Vec4 {
+    let constants = constants.with_scroll_offset(Vec2::ZERO);
+    let corners = [
+        clip.xy(),
+        clip.xy() + vec2(clip.z, 0.),
+        clip.xy() + vec2(0., clip.w),
+        clip.xy() + clip.zw(),
+    ]
+    .map(|corner| constants.to_surface(corner));
+
+    let min = corners.into_iter().reduce(Vec2::min).unwrap();
+    let max = corners.into_iter().reduce(Vec2::max).unwrap();
+    vec4(min.x, min.y, max.x - min.x, max.y - min.y)
+}
+
+// The scissor rect in whole pixels of a scene space clip rect, limited to the
+// surface
+pub(crate) fn scissor_rect(constants: &ShaderConstants, clip: Vec4) -> [u32; 4] {
+    let [width, height] = constants.surface_size.to_array().map(|size| size as u32);
+    let clip = clip_to_surface(constants, clip);
+    let x = (clip.x.ceil().max(0.0) as u32).min(width);
+    let y = (clip.y.ceil().max(0.0) as u32).min(height);
+    let w = (clip.z as u32).min(width - x);
+    let h = (clip.w as u32).min(height - y);
+    [x, y, w, h]
 }
 
 fn create_texture(
@@ -300,10 +2034,11 @@ fn create_texture(
     samples: u32,
     label: &'static str,
 ) -> Texture {
+    // Textures can't be empty. Renderers with a zero size never draw to them.
     device.create_texture(&TextureDescriptor {
         size: Extent3d {
-            width,
-            height,
+            width: width.max(1),
+            height: height.max(1),
             depth_or_array_layers: 1,
         },
         mip_level_count: 1,
@@ -318,26 +2053,99 @@ fn create_texture(
     })
 }
 
+// The offscreen texture and sampler entries at the start of the universal
+// bind group layout
+pub(crate) fn texture_sampler_layout_entries() -> [BindGroupLayoutEntry; 2] {
+    [
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        },
+    ]
+}
+
+// Naga can't reflect every instruction rust-gpu emits, so modules it fails to
+// parse are not checked
+fn reflect(spirv: &[u8]) -> Option<naga::Module> {
+    match reflect_shader(spirv) {
+        Ok(module) => Some(module),
+        Err(error) => {
+            warn!("Not verifying the pipelines against the shader: {error}");
+            None
+        }
+    }
+}
+
+// Layout of the universal bind group with the offscreen texture, sampler and
+// previous frame followed by the given entries
+fn universal_layout_entries(entries: &[UniversalEntry]) -> Vec<BindGroupLayoutEntry> {
+    let mut layout_entries = texture_sampler_layout_entries().to_vec();
+    layout_entries.push(BindGroupLayoutEntry {
+        binding: 2,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    });
+    layout_entries.extend(entries.iter().map(|entry| entry.layout));
+    layout_entries
+}
+
+fn create_bind_group_layout(device: &Device, entries: &[UniversalEntry]) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("Universal bind group layout"),
+        entries: &universal_layout_entries(entries),
+    })
+}
+
 fn create_bind_group(
     device: &Device,
     bind_group_layout: &BindGroupLayout,
     offscreen_texture: &Texture,
+    previous_frame_texture: &Texture,
     sampler: &Sampler,
+    entries: &[UniversalEntry],
 ) -> BindGroup {
     let offscreen_texture_view = offscreen_texture.create_view(&TextureViewDescriptor::default());
+    let previous_frame_view = previous_frame_texture.create_view(&TextureViewDescriptor::default());
+
+    let mut bind_group_entries = vec![
+        BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::TextureView(&offscreen_texture_view),
+        },
+        BindGroupEntry {
+            binding: 1,
+            resource: BindingResource::Sampler(sampler),
+        },
+        BindGroupEntry {
+            binding: 2,
+            resource: BindingResource::TextureView(&previous_frame_view),
+        },
+    ];
+    bind_group_entries.extend(entries.iter().map(|entry| BindGroupEntry {
+        binding: entry.layout.binding,
+        resource: entry.resource.binding_resource(),
+    }));
 
     device.create_bind_group(&BindGroupDescriptor {
         label: Some("Universal bind group"),
         layout: bind_group_layout,
-        entries: &[
-            BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::TextureView(&offscreen_texture_view),
-            },
-            BindGroupEntry {
-                binding: 1,
-                resource: BindingResource::Sampler(sampler),
-            },
-        ],
+        entries: &bind_group_entries,
     })
 }
@@ -1,30 +1,87 @@
+mod animation;
+mod backdrop_blur;
+mod camera;
+mod caret;
+mod checkerboard;
+mod cursor_trail;
+mod dimming;
+mod format;
+mod grid;
+mod hit_test;
 mod layer;
+mod outline;
 mod path;
+mod path_boolean;
+mod path_builder;
+mod pattern;
+mod placement;
+mod procedural;
 mod quad;
+mod shadow;
+mod shape;
+mod snap;
 mod sprite;
 mod text;
-
-use glam::Vec4;
-use serde::Deserialize;
-
+mod theme;
+mod validation;
+mod width;
+
+use glam::{Vec2, Vec4};
+use serde::{Deserialize, Serialize};
+
+pub use animation::*;
+pub use backdrop_blur::*;
+pub use camera::*;
+pub use caret::*;
+pub use checkerboard::*;
+pub use cursor_trail::*;
+pub use dimming::*;
+pub use format::*;
+pub use grid::*;
+pub use hit_test::*;
 pub use layer::*;
+pub use outline::*;
 pub use path::*;
+pub use path_boolean::*;
+pub use path_builder::*;
+pub use pattern::*;
+pub use placement::*;
+pub use procedural::*;
 pub use quad::*;
+pub use shadow::*;
+pub use shape::*;
+pub use snap::*;
 pub use sprite::*;
 pub use text::*;
+pub use theme::*;
+pub use validation::*;
+pub use width::*;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "serde_json::Value")]
 pub struct Scene {
+    #[serde(default)]
+    pub camera: Camera,
     pub layers: Vec<Layer>,
 }
 
 impl Scene {
     pub fn new() -> Self {
         Self {
+            camera: Camera::default(),
             layers: vec![Default::default()],
         }
     }
 
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+    }
+
+    pub fn with_camera(mut self, camera: Camera) -> Self {
+        self.set_camera(camera);
+        self
+    }
+
     pub fn add_layer(&mut self, layer: Layer) {
         self.layers.push(layer);
     }
@@ -47,6 +104,11 @@ impl Scene {
         self
     }
 
+    pub fn with_scroll_offset(mut self, scroll_offset: Vec2) -> Self {
+        self.layer_mut().scroll_offset = scroll_offset;
+        self
+    }
+
     pub fn with_blur(mut self, radius: f32) -> Self {
         self.layer_mut().background_blur_radius = radius;
         self
@@ -101,4 +163,67 @@ impl Scene {
         self.add_sprite(sprite);
         self
     }
+
+    pub fn add_procedural(&mut self, procedural: Procedural) {
+        self.layer_mut().add_procedural(procedural);
+    }
+
+    pub fn with_procedural(mut self, procedural: Procedural) -> Self {
+        self.add_procedural(procedural);
+        self
+    }
+
+    pub fn add_shape(&mut self, shape: Shape) {
+        self.layer_mut().add_shape(shape);
+    }
+
+    pub fn with_shape(mut self, shape: Shape) -> Self {
+        self.add_shape(shape);
+        self
+    }
+
+    pub fn add_shadow(&mut self, shadow: Shadow) {
+        self.layer_mut().add_shadow(shadow);
+    }
+
+    pub fn with_shadow(mut self, shadow: Shadow) -> Self {
+        self.add_shadow(shadow);
+        self
+    }
+
+    pub fn add_backdrop_blur(&mut self, blur: BackdropBlurQuad) {
+        self.layer_mut().add_backdrop_blur(blur);
+    }
+
+    pub fn with_backdrop_blur(mut self, blur: BackdropBlurQuad) -> Self {
+        self.add_backdrop_blur(blur);
+        self
+    }
+
+    pub fn add_caret(&mut self, caret: Caret) {
+        self.layer_mut().add_caret(caret);
+    }
+
+    pub fn with_caret(mut self, caret: Caret) -> Self {
+        self.add_caret(caret);
+        self
+    }
+
+    pub fn add_cursor_trail(&mut self, trail: CursorTrail) {
+        self.layer_mut().add_cursor_trail(trail);
+    }
+
+    pub fn with_cursor_trail(mut self, trail: CursorTrail) -> Self {
+        self.add_cursor_trail(trail);
+        self
+    }
+
+    pub fn add_checkerboard(&mut self, checkerboard: Checkerboard) {
+        self.layer_mut().add_checkerboard(checkerboard);
+    }
+
+    pub fn with_checkerboard(mut self, checkerboard: Checkerboard) -> Self {
+        self.add_checkerboard(checkerboard);
+        self
+    }
 }
@@ -0,0 +1,276 @@
+use std::f32::consts::PI;
+
+use glam::Vec4;
+
+use super::{dimming::interpolate_dimming, Camera, Layer, Scene};
+
+// Maps the linear progress of an animation to the eased progress
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    SineInOut,
+    // Steps through the progress in the given number of discrete jumps
+    Steps(u32),
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Easing::Steps(steps) => {
+                let steps = (*steps).max(1) as f32;
+                (t * steps).floor() / steps
+            }
+        }
+    }
+}
+
+// Scene types which can be blended between two states. Properties which can't
+// be blended, such as text contents or images, switch half way through.
+pub trait Interpolate: Sized {
+    fn interpolate(&self, to: &Self, t: f32) -> Self;
+
+    // Copy of self with the opacity multiplied by the given factor. Used to
+    // fade in and out primitives which only exist in one of the states.
+    fn fade(&self, opacity: f32) -> Self;
+}
+
+pub(crate) fn interpolate_color(from: Vec4, to: Vec4, t: f32) -> Vec4 {
+    from.lerp(to, t)
+}
+
+pub(crate) fn fade_color(color: Vec4, opacity: f32) -> Vec4 {
+    color * Vec4::new(1.0, 1.0, 1.0, opacity)
+}
+
+pub(crate) fn interpolate_option<T: Clone>(
+    from: &Option<T>,
+    to: &Option<T>,
+    t: f32,
+    interpolate: impl Fn(&T, &T) -> T,
+) -> Option<T> {
+    match (from, to) {
+        (Some(from), Some(to)) => Some(interpolate(from, to)),
+        _ => snap(from, to, t).clone(),
+    }
+}
+
+// Picks the from state for the first half of the interpolation and the to
+// state for the second
+pub(crate) fn snap<'a, T>(from: &'a T, to: &'a T, t: f32) -> &'a T {
+    if t < 0.5 {
+        from
+    } else {
+        to
+    }
+}
+
+// Primitives are matched up by their index in the layer. Primitives without a
+// counterpart fade out or in.
+fn interpolate_primitives<T: Interpolate + Clone>(from: &[T], to: &[T], t: f32) -> Vec<T> {
+    let len = from.len().max(to.len());
+    (0..len)
+        .map(|index| match (from.get(index), to.get(index)) {
+            (Some(from), Some(to)) => from.interpolate(to, t),
+            (Some(from), None) => from.fade(1.0 - t),
+            (None, Some(to)) => to.fade(t),
+            (None, None) => unreachable!(),
+        })
+        .collect()
+}
+
+impl Interpolate for Camera {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Camera {
+            offset: self.offset.lerp(to.offset, t),
+            zoom: self.zoom + (to.zoom - self.zoom) * t,
+            rotation: self.rotation + (to.rotation - self.rotation) * t,
+        }
+    }
+
+    fn fade(&self, _opacity: f32) -> Self {
+        *self
+    }
+}
+
+impl Interpolate for Layer {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Layer {
+            clip: interpolate_option(&self.clip, &to.clip, t, |from, to| from.lerp(*to, t)),
+            scroll_offset: self.scroll_offset.lerp(to.scroll_offset, t),
+            background_blur_radius: self.background_blur_radius
+                + (to.background_blur_radius - self.background_blur_radius) * t,
+            background_color: interpolate_option(
+                &self.background_color,
+                &to.background_color,
+                t,
+                |from, to| interpolate_color(*from, *to, t),
+            ),
+            dimming: interpolate_dimming(&self.dimming, &to.dimming, t),
+            font_name: snap(&self.font_name, &to.font_name, t).clone(),
+            shadows: interpolate_primitives(&self.shadows, &to.shadows, t),
+            quads: interpolate_primitives(&self.quads, &to.quads, t),
+            texts: interpolate_primitives(&self.texts, &to.texts, t),
+            paths: interpolate_primitives(&self.paths, &to.paths, t),
+            sprites: interpolate_primitives(&self.sprites, &to.sprites, t),
+            procedurals: interpolate_primitives(&self.procedurals, &to.procedurals, t),
+            shapes: interpolate_primitives(&self.shapes, &to.shapes, t),
+            backdrop_blurs: interpolate_primitives(&self.backdrop_blurs, &to.backdrop_blurs, t),
+            carets: interpolate_primitives(&self.carets, &to.carets, t),
+            cursor_trails: interpolate_primitives(&self.cursor_trails, &to.cursor_trails, t),
+            checkerboards: interpolate_primitives(&self.checkerboards, &to.checkerboards, t),
+            theme: snap(&self.theme, &to.theme, t).clone(),
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Layer {
+            background_color: self
+                .background_color
+                .map(|color| fade_color(color, opacity)),
+            shadows: self
+                .shadows
+                .iter()
+                .map(|shadow| shadow.fade(opacity))
+                .collect(),
+            quads: self.quads.iter().map(|quad| quad.fade(opacity)).collect(),
+            texts: self.texts.iter().map(|text| text.fade(opacity)).collect(),
+            paths: self.paths.iter().map(|path| path.fade(opacity)).collect(),
+            sprites: self
+                .sprites
+                .iter()
+                .map(|sprite| sprite.fade(opacity))
+                .collect(),
+            procedurals: self
+                .procedurals
+                .iter()
+                .map(|procedural| procedural.fade(opacity))
+                .collect(),
+            shapes: self
+                .shapes
+                .iter()
+                .map(|shape| shape.fade(opacity))
+                .collect(),
+            backdrop_blurs: self
+                .backdrop_blurs
+                .iter()
+                .map(|blur| blur.fade(opacity))
+                .collect(),
+            carets: self
+                .carets
+                .iter()
+                .map(|caret| caret.fade(opacity))
+                .collect(),
+            cursor_trails: self
+                .cursor_trails
+                .iter()
+                .map(|trail| trail.fade(opacity))
+                .collect(),
+            checkerboards: self
+                .checkerboards
+                .iter()
+                .map(|checkerboard| checkerboard.fade(opacity))
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Interpolate for Scene {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Scene {
+            camera: self.camera.interpolate(&to.camera, t),
+            layers: interpolate_primitives(&self.layers, &to.layers, t),
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Scene {
+            camera: self.camera,
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| layer.fade(opacity))
+                .collect(),
+        }
+    }
+}
+
+impl Scene {
+    // Intermediate scene between self and `to` at the linear progress t
+    // between 0 and 1, shaped by the easing function
+    pub fn tween(&self, to: &Scene, t: f32, easing: Easing) -> Scene {
+        self.interpolate(to, easing.apply(t))
+    }
+}
+
+// A sequence of scenes at given times which can be sampled at any time in
+// between
+#[derive(Debug, Clone, Default)]
+pub struct Keyframes {
+    keyframes: Vec<(f32, Scene)>,
+    easing: Easing,
+}
+
+impl Keyframes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn add_keyframe(&mut self, time: f32, scene: Scene) {
+        let index = self
+            .keyframes
+            .partition_point(|(keyframe_time, _)| *keyframe_time <= time);
+        self.keyframes.insert(index, (time, scene));
+    }
+
+    pub fn with_keyframe(mut self, time: f32, scene: Scene) -> Self {
+        self.add_keyframe(time, scene);
+        self
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|(time, _)| *time).unwrap_or(0.0)
+    }
+
+    // Scene at the given time. Times before the first or after the last
+    // keyframe are clamped. The easing is applied between each pair of
+    // keyframes.
+    pub fn sample(&self, time: f32) -> Option<Scene> {
+        let next = self
+            .keyframes
+            .partition_point(|(keyframe_time, _)| *keyframe_time <= time);
+
+        if next == 0 {
+            return self.keyframes.first().map(|(_, scene)| scene.clone());
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes.last().map(|(_, scene)| scene.clone());
+        }
+
+        let (from_time, from) = &self.keyframes[next - 1];
+        let (to_time, to) = &self.keyframes[next];
+        let t = (time - from_time) / (to_time - from_time);
+        Some(from.tween(to, t, self.easing))
+    }
+}
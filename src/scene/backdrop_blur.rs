@@ -0,0 +1,80 @@
+use glam::{vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
+use shader::InstancedQuad;
+
+use super::{
+    animation::{fade_color, interpolate_color},
+    Interpolate, Quad,
+};
+
+// Rounded rect blurring everything drawn beneath it so far, including the
+// other primitives of its own layer, such as behind tooltips and popups. The
+// blurs of a layer are drawn after its other primitives except the carets,
+// so the content on top of the blur goes in a later layer. The tint is
+// blended over the blurred content by its alpha.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackdropBlurQuad {
+    pub top_left: Vec2,
+    pub size: Vec2,
+    pub radius: f32,
+    #[serde(default)]
+    pub corner_radius: f32,
+    #[serde(default)]
+    pub tint: Vec4,
+}
+
+impl BackdropBlurQuad {
+    pub fn new(top_left: Vec2, size: Vec2, radius: f32) -> Self {
+        Self {
+            top_left,
+            size,
+            radius,
+            corner_radius: 0.0,
+            tint: Vec4::ZERO,
+        }
+    }
+
+    pub fn with_corner_radius(mut self, corner_radius: f32) -> Self {
+        self.corner_radius = corner_radius;
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Vec4) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn bounds(&self) -> Vec4 {
+        vec4(self.top_left.x, self.top_left.y, self.size.x, self.size.y)
+    }
+
+    // Drawn by the quad shader as a quad with a background blur. Radii below
+    // a pixel only tint.
+    pub fn to_instanced(&self) -> InstancedQuad {
+        Quad::new(self.top_left, self.size, self.tint)
+            .with_corner_radius(self.corner_radius)
+            .with_background_blur(self.radius.max(1.0))
+            .to_instanced()
+    }
+}
+
+impl Interpolate for BackdropBlurQuad {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        BackdropBlurQuad {
+            top_left: self.top_left.lerp(to.top_left, t),
+            size: self.size.lerp(to.size, t),
+            radius: self.radius + (to.radius - self.radius) * t,
+            corner_radius: self.corner_radius + (to.corner_radius - self.corner_radius) * t,
+            tint: interpolate_color(self.tint, to.tint, t),
+        }
+    }
+
+    // The blur fades in by growing the radius
+    fn fade(&self, opacity: f32) -> Self {
+        BackdropBlurQuad {
+            radius: self.radius * opacity,
+            tint: fade_color(self.tint, opacity),
+            ..self.clone()
+        }
+    }
+}
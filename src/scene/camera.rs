@@ -0,0 +1,99 @@
+use glam::{vec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+// Transforms every layer of a scene. The offset is the scene position drawn at
+// the top left of the surface, zoom scales around that point, and rotation is
+// in radians around the top left of the surface.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    #[serde(default)]
+    pub offset: Vec2,
+    #[serde(default = "default_zoom")]
+    pub zoom: f32,
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+// Where the origin of the scene is on the surface and which way the y axis
+// points. TopLeft is the usual ui convention with y growing downwards and
+// BottomLeft the math convention with y growing upwards. The camera offset is
+// the scene position drawn at the origin corner.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CoordinateOrigin {
+    #[default]
+    TopLeft,
+    BottomLeft,
+}
+
+impl CoordinateOrigin {
+    // Sign of the scene y axis on the surface
+    pub fn y_direction(&self) -> f32 {
+        match self {
+            CoordinateOrigin::TopLeft => 1.0,
+            CoordinateOrigin::BottomLeft => -1.0,
+        }
+    }
+}
+
+fn default_zoom() -> f32 {
+    1.0
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_zoom(mut self, zoom: f32) -> Self {
+        self.zoom = zoom;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    // Cosine and sine of the rotation in the form expected by the shaders
+    pub fn rotation_vector(&self) -> Vec2 {
+        vec2(self.rotation.cos(), self.rotation.sin())
+    }
+
+    pub fn to_surface(&self, position: Vec2) -> Vec2 {
+        ((position - self.offset) * self.zoom).rotate(self.rotation_vector())
+    }
+
+    pub fn from_surface(&self, position: Vec2) -> Vec2 {
+        let inverse_rotation = self.rotation_vector() * vec2(1.0, -1.0);
+        position.rotate(inverse_rotation) / self.zoom + self.offset
+    }
+
+    // Moves the camera by a distance in surface pixels
+    pub fn pan(&mut self, surface_delta: Vec2) {
+        let inverse_rotation = self.rotation_vector() * vec2(1.0, -1.0);
+        self.offset -= surface_delta.rotate(inverse_rotation) / self.zoom;
+    }
+
+    // Multiplies the zoom by factor while keeping the scene position under
+    // surface_position fixed. Useful for zooming around the mouse cursor.
+    pub fn zoom_at(&mut self, surface_position: Vec2, factor: f32) {
+        let anchor = self.from_surface(surface_position);
+        self.zoom *= factor;
+        self.offset += anchor - self.from_surface(surface_position);
+    }
+}
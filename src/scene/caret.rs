@@ -0,0 +1,231 @@
+use glam::{vec2, vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
+use shader::{
+    caret_move_progress, InstancedCaret, CARET_COLOR_CONTRAST, CARET_COLOR_FIXED,
+    CARET_COLOR_INVERT,
+};
+
+use super::{
+    animation::{fade_color, interpolate_color, snap},
+    theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue},
+    Interpolate,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaretShape {
+    // Vertical bar at the left edge of the cell
+    #[default]
+    Bar,
+    // Horizontal line at the bottom of the cell
+    Underline,
+    // Covers the whole cell
+    Block,
+}
+
+// How the color a caret is drawn with is picked. The derived colors are taken
+// from the pixels already drawn behind the caret, including the text of its
+// own layer, so that block carets stay visible over any background.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaretColorMode {
+    // The color of the caret
+    #[default]
+    Fixed,
+    // The inverse of the pixels behind the caret, with the alpha of the caret
+    // color
+    Invert,
+    // Black over light pixels and white over dark ones, with the alpha of the
+    // caret color
+    Contrast,
+}
+
+// Text cursor drawn on top of the other primitives of the layer. The caret
+// blinks and glides to new positions in the shader, so the scene only has to
+// change when the caret moves, but it has to be rendered every frame while
+// it's animating. Times are in seconds of the renderer clock, as reported by
+// FrameClock::last_frame.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Caret {
+    // Top left and size of the cell the caret is in
+    pub top_left: Vec2,
+    pub size: Vec2,
+    #[serde(default)]
+    pub shape: CaretShape,
+    pub color: Vec4,
+    #[serde(default)]
+    pub color_mode: CaretColorMode,
+    // Width of the bar or height of the underline
+    #[serde(default = "default_thickness")]
+    pub thickness: f32,
+    // Seconds the caret stays visible and then hidden. Zero disables
+    // blinking.
+    #[serde(default)]
+    pub blink_interval: f32,
+    // Time the caret last moved. Blinking restarts from this time with the
+    // caret visible, so that it doesn't disappear while typing.
+    #[serde(default)]
+    pub moved_at: f32,
+    // Cell position the caret glides from to the top left over the move
+    // duration, starting at the moved at time
+    #[serde(default)]
+    pub moved_from: Option<Vec2>,
+    #[serde(default)]
+    pub move_duration: f32,
+    // Opaque user data reported by hit testing. Ignored by rendering.
+    #[serde(default)]
+    pub tag: Option<u64>,
+    // Theme variables replacing the values of fields when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    pub theme: ThemeBindings,
+}
+
+fn default_thickness() -> f32 {
+    2.0
+}
+
+impl Caret {
+    pub fn new(top_left: Vec2, size: Vec2, color: Vec4) -> Self {
+        Self {
+            top_left,
+            size,
+            shape: CaretShape::default(),
+            color,
+            color_mode: CaretColorMode::default(),
+            thickness: default_thickness(),
+            blink_interval: 0.0,
+            moved_at: 0.0,
+            moved_from: None,
+            move_duration: 0.0,
+            tag: None,
+            theme: ThemeBindings::new(),
+        }
+    }
+
+    pub fn with_shape(mut self, shape: CaretShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    pub fn with_color_mode(mut self, color_mode: CaretColorMode) -> Self {
+        self.color_mode = color_mode;
+        self
+    }
+
+    pub fn with_thickness(mut self, thickness: f32) -> Self {
+        self.thickness = thickness;
+        self
+    }
+
+    pub fn with_blink_interval(mut self, blink_interval: f32) -> Self {
+        self.blink_interval = blink_interval;
+        self
+    }
+
+    pub fn with_moved_at(mut self, time: f32) -> Self {
+        self.moved_at = time;
+        self
+    }
+
+    pub fn with_tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| {
+            if let (ThemeField::Color, ThemeValue::Color(color)) = (field, value) {
+                self.color = color;
+            }
+        });
+        self.theme = bindings;
+    }
+
+    // Cell position the caret is drawn at at the time
+    pub fn position_at(&self, time: f32) -> Vec2 {
+        match self.moved_from {
+            Some(moved_from) => {
+                let progress = caret_move_progress(time, self.moved_at, self.move_duration);
+                moved_from.lerp(self.top_left, progress)
+            }
+            None => self.top_left,
+        }
+    }
+
+    // Moves the caret to the cell at the time. With a non zero duration the
+    // caret glides there from where it's drawn at the time, so a move in the
+    // middle of another one continues smoothly.
+    pub fn move_to(&mut self, top_left: Vec2, time: f32, duration: f32) {
+        self.moved_from = (duration > 0.0).then(|| self.position_at(time));
+        self.top_left = top_left;
+        self.moved_at = time;
+        self.move_duration = duration;
+    }
+
+    // Offset and size of the drawn rect within the cell
+    fn shape_rect(&self) -> Vec4 {
+        let thickness = self.thickness.max(0.0);
+        match self.shape {
+            CaretShape::Bar => vec4(0., 0., thickness.min(self.size.x), self.size.y),
+            CaretShape::Underline => {
+                let height = thickness.min(self.size.y);
+                vec4(0., self.size.y - height, self.size.x, height)
+            }
+            CaretShape::Block => vec4(0., 0., self.size.x, self.size.y),
+        }
+    }
+
+    // Bounds of the caret at the end of the move and where it glides from
+    pub fn bounds(&self) -> Vec4 {
+        let start = self.moved_from.unwrap_or(self.top_left);
+        let top_left = start.min(self.top_left);
+        let bottom_right = start.max(self.top_left) + self.size;
+        let size = bottom_right - top_left;
+        vec4(top_left.x, top_left.y, size.x, size.y)
+    }
+
+    pub fn to_instanced(&self) -> InstancedCaret {
+        let rect = self.shape_rect();
+        InstancedCaret {
+            color: self.color,
+            top_left: self.top_left + vec2(rect.x, rect.y),
+            size: vec2(rect.z, rect.w),
+            move_offset: self
+                .moved_from
+                .map_or(Vec2::ZERO, |moved_from| moved_from - self.top_left),
+            moved_at: self.moved_at,
+            move_duration: self.move_duration,
+            blink_interval: self.blink_interval,
+            color_mode: match self.color_mode {
+                CaretColorMode::Fixed => CARET_COLOR_FIXED,
+                CaretColorMode::Invert => CARET_COLOR_INVERT,
+                CaretColorMode::Contrast => CARET_COLOR_CONTRAST,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl Interpolate for Caret {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        let snapped = snap(self, to, t);
+        Caret {
+            top_left: self.top_left.lerp(to.top_left, t),
+            size: self.size.lerp(to.size, t),
+            color: interpolate_color(self.color, to.color, t),
+            thickness: self.thickness + (to.thickness - self.thickness) * t,
+            ..snapped.clone()
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Caret {
+            color: fade_color(self.color, opacity),
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use glam::{vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
+use shader::InstancedCheckerboard;
+
+use super::{
+    animation::{fade_color, interpolate_color},
+    Interpolate,
+};
+
+// Rect of alternating light and dark cells, which shows through the
+// transparent parts of images drawn on top of it. The cell size is in logical
+// pixels of the surface, so the cells stay the same size and sharp at any
+// camera zoom. Drawn by CheckerboardState, which isn't one of the builtin
+// drawables and has to be added to the renderer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkerboard {
+    pub top_left: Vec2,
+    pub size: Vec2,
+    #[serde(default = "default_cell_size")]
+    pub cell_size: f32,
+    #[serde(default = "default_light_color")]
+    pub light_color: Vec4,
+    #[serde(default = "default_dark_color")]
+    pub dark_color: Vec4,
+}
+
+fn default_cell_size() -> f32 {
+    8.0
+}
+
+fn default_light_color() -> Vec4 {
+    vec4(1.0, 1.0, 1.0, 1.0)
+}
+
+fn default_dark_color() -> Vec4 {
+    vec4(0.8, 0.8, 0.8, 1.0)
+}
+
+impl Checkerboard {
+    pub fn new(top_left: Vec2, size: Vec2) -> Self {
+        Self {
+            top_left,
+            size,
+            cell_size: default_cell_size(),
+            light_color: default_light_color(),
+            dark_color: default_dark_color(),
+        }
+    }
+
+    pub fn with_cell_size(mut self, cell_size: f32) -> Self {
+        self.cell_size = cell_size;
+        self
+    }
+
+    pub fn with_colors(mut self, light_color: Vec4, dark_color: Vec4) -> Self {
+        self.light_color = light_color;
+        self.dark_color = dark_color;
+        self
+    }
+
+    pub fn bounds(&self) -> Vec4 {
+        vec4(self.top_left.x, self.top_left.y, self.size.x, self.size.y)
+    }
+
+    pub fn to_instanced(&self) -> InstancedCheckerboard {
+        InstancedCheckerboard {
+            first_color: self.light_color,
+            second_color: self.dark_color,
+            top_left: self.top_left,
+            size: self.size,
+            cell_size: self.cell_size,
+            ..Default::default()
+        }
+    }
+}
+
+impl Interpolate for Checkerboard {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Checkerboard {
+            top_left: self.top_left.lerp(to.top_left, t),
+            size: self.size.lerp(to.size, t),
+            cell_size: self.cell_size + (to.cell_size - self.cell_size) * t,
+            light_color: interpolate_color(self.light_color, to.light_color, t),
+            dark_color: interpolate_color(self.dark_color, to.dark_color, t),
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Checkerboard {
+            light_color: fade_color(self.light_color, opacity),
+            dark_color: fade_color(self.dark_color, opacity),
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use glam::{vec4, Vec2, Vec4, Vec4Swizzles};
+use serde::{Deserialize, Serialize};
+use shader::{cursor_trail_corner, InstancedCursorTrail, CURSOR_TRAIL_CORNERS};
+
+use super::{
+    animation::{fade_color, interpolate_color, snap},
+    theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue},
+    Interpolate,
+};
+
+// Animated smear of a cursor moving from its previous rect to the current
+// one. The corners leading the move arrive first and the trailing ones catch
+// up, stretching the cursor along the way. Drawn by CursorTrailState, which
+// isn't one of the builtin drawables and has to be added to the renderer.
+// Times are in seconds of the renderer clock, as reported by
+// FrameClock::last_frame. The trail isn't drawn once the animation finishes,
+// so it's usually drawn together with a caret at the current rect.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CursorTrail {
+    // Top left and size of the previous and current cursor rects
+    pub from: Vec4,
+    pub to: Vec4,
+    pub color: Vec4,
+    // Time the cursor moved
+    pub started_at: f32,
+    // Seconds until the trailing corners arrive
+    #[serde(default = "default_duration")]
+    pub duration: f32,
+    // Fraction of the duration the leading corners arrive earlier. Zero
+    // moves the cursor without stretching it.
+    #[serde(default = "default_trail_size")]
+    pub trail_size: f32,
+    // Theme variables replacing the values of fields when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    pub theme: ThemeBindings,
+}
+
+fn default_duration() -> f32 {
+    0.15
+}
+
+fn default_trail_size() -> f32 {
+    0.8
+}
+
+impl CursorTrail {
+    pub fn new(from: Vec4, to: Vec4, color: Vec4, started_at: f32) -> Self {
+        Self {
+            from,
+            to,
+            color,
+            started_at,
+            duration: default_duration(),
+            trail_size: default_trail_size(),
+            theme: ThemeBindings::new(),
+        }
+    }
+
+    pub fn with_duration(mut self, duration: f32) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn with_trail_size(mut self, trail_size: f32) -> Self {
+        self.trail_size = trail_size;
+        self
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| {
+            if let (ThemeField::Color, ThemeValue::Color(color)) = (field, value) {
+                self.color = color;
+            }
+        });
+        self.theme = bindings;
+    }
+
+    pub fn is_finished_at(&self, time: f32) -> bool {
+        time >= self.started_at + self.duration
+    }
+
+    // The top left, top right, bottom right and bottom left corners of the
+    // trail at the time
+    pub fn corners_at(&self, time: f32) -> [Vec2; 4] {
+        let instanced = self.to_instanced();
+        CURSOR_TRAIL_CORNERS.map(|corner| cursor_trail_corner(&instanced, corner, time))
+    }
+
+    // The trail stays within the two rects during the whole animation
+    pub fn bounds(&self) -> Vec4 {
+        let top_left = self.from.xy().min(self.to.xy());
+        let bottom_right = (self.from.xy() + self.from.zw()).max(self.to.xy() + self.to.zw());
+        let size = bottom_right - top_left;
+        vec4(top_left.x, top_left.y, size.x, size.y)
+    }
+
+    pub fn to_instanced(&self) -> InstancedCursorTrail {
+        InstancedCursorTrail {
+            color: self.color,
+            from: self.from,
+            to: self.to,
+            started_at: self.started_at,
+            duration: self.duration,
+            trail_size: self.trail_size,
+            ..Default::default()
+        }
+    }
+}
+
+impl Interpolate for CursorTrail {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        let snapped = snap(self, to, t);
+        CursorTrail {
+            color: interpolate_color(self.color, to.color, t),
+            ..snapped.clone()
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        CursorTrail {
+            color: fade_color(self.color, opacity),
+            ..self.clone()
+        }
+    }
+}
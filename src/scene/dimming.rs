@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use shader::{dimming_progress, InstancedDimming};
+
+use super::{animation::snap, Interpolate};
+
+// Desaturates and darkens a layer, such as an inactive pane, once the layer
+// has been drawn. The adjustment is applied to everything within the clip of
+// the layer, including what shows through from the layers beneath it, and
+// the layers above stay untouched. The progress goes from unchanged at zero
+// to the saturation and brightness at one. It's either set by the
+// application or transitions from another value over time in the shader, in
+// which case the scene only has to change when the transition starts, but it
+// has to be rendered every frame until it ends. Times are in seconds of the
+// renderer clock, as reported by FrameClock::last_frame.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LayerDimming {
+    // Multiplier of the saturation when fully dimmed, gray at zero
+    #[serde(default = "default_saturation")]
+    pub saturation: f32,
+    // Multiplier of the colors when fully dimmed, black at zero
+    #[serde(default = "default_brightness")]
+    pub brightness: f32,
+    #[serde(default = "default_progress")]
+    pub progress: f32,
+    #[serde(default)]
+    pub transition: Option<DimmingTransition>,
+}
+
+// Eases the progress of a dimming in from a value, starting at a time
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DimmingTransition {
+    pub from: f32,
+    pub started_at: f32,
+    pub duration: f32,
+}
+
+fn default_saturation() -> f32 {
+    0.5
+}
+
+fn default_brightness() -> f32 {
+    0.7
+}
+
+fn default_progress() -> f32 {
+    1.0
+}
+
+impl Default for LayerDimming {
+    fn default() -> Self {
+        Self::new(default_saturation(), default_brightness())
+    }
+}
+
+impl LayerDimming {
+    // Fully dimmed to the saturation and brightness
+    pub fn new(saturation: f32, brightness: f32) -> Self {
+        Self {
+            saturation,
+            brightness,
+            progress: default_progress(),
+            transition: None,
+        }
+    }
+
+    pub fn with_progress(mut self, progress: f32) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    // Transitions to the progress from the given progress over the duration,
+    // starting at the time
+    pub fn with_transition(mut self, from: f32, started_at: f32, duration: f32) -> Self {
+        self.transition = Some(DimmingTransition {
+            from,
+            started_at,
+            duration,
+        });
+        self
+    }
+
+    // Progress at the time, matching the shader
+    pub fn progress_at(&self, time: f32) -> f32 {
+        dimming_progress(&self.to_instanced(), time)
+    }
+
+    // Whether the dimming changes nothing at any time
+    pub fn is_noop(&self) -> bool {
+        let unchanged =
+            |progress: f32| progress == 0.0 || (self.saturation == 1.0 && self.brightness == 1.0);
+        unchanged(self.progress)
+            && self
+                .transition
+                .map_or(true, |transition| unchanged(transition.from))
+    }
+
+    pub fn to_instanced(&self) -> InstancedDimming {
+        let transition = self.transition.unwrap_or(DimmingTransition {
+            from: self.progress,
+            started_at: 0.0,
+            duration: 0.0,
+        });
+        InstancedDimming {
+            saturation: self.saturation,
+            brightness: self.brightness,
+            progress: self.progress,
+            from_progress: transition.from,
+            started_at: transition.started_at,
+            duration: transition.duration,
+            ..Default::default()
+        }
+    }
+}
+
+impl Interpolate for LayerDimming {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        let lerp = |from: f32, to: f32| from + (to - from) * t;
+        LayerDimming {
+            saturation: lerp(self.saturation, to.saturation),
+            brightness: lerp(self.brightness, to.brightness),
+            progress: lerp(self.progress, to.progress),
+            transition: *snap(&self.transition, &to.transition, t),
+        }
+    }
+
+    // Dimming isn't a color of its own, so it's unaffected by the opacity
+    fn fade(&self, _opacity: f32) -> Self {
+        *self
+    }
+}
+
+// Layers without a dimming animate to and from one as if it was undimmed, so
+// that panes fade smoothly when they lose or gain focus
+pub(crate) fn interpolate_dimming(
+    from: &Option<LayerDimming>,
+    to: &Option<LayerDimming>,
+    t: f32,
+) -> Option<LayerDimming> {
+    let undimmed = |dimming: &LayerDimming| LayerDimming {
+        progress: 0.0,
+        transition: None,
+        ..*dimming
+    };
+    match (from, to) {
+        (Some(from), Some(to)) => Some(from.interpolate(to, t)),
+        (Some(from), None) => Some(from.interpolate(&undimmed(from), t)),
+        (None, Some(to)) => Some(undimmed(to).interpolate(to, t)),
+        (None, None) => None,
+    }
+}
@@ -0,0 +1,477 @@
+use std::fmt;
+
+use glam::Vec2;
+use serde_json::{Map, Number, Value};
+
+use super::Scene;
+use crate::AssetSource;
+
+// A hand written scene format for editing scenes by hand, which maps onto the
+// json representation of the scene:
+//
+// // Comments run to the end of the line
+// camera: { zoom: 2 }
+// layers: [
+//     {
+//         background_color: white
+//         include "header.scene"
+//         quads: [
+//             { top_left: [10, 10], size: [50%, 5vh], color: #ff8800 }
+//         ]
+//         procedurals: [
+//             { kind: Checker { cell_size: 8 }, primary_color: black, ... }
+//         ]
+//     }
+// ]
+//
+// The braces around the top level and the commas between items are optional
+// and keys don't need quotes. Colors can be written as #rgb, #rrggbb and
+// #rrggbbaa or with their css names. Lengths with vw and vh units are relative
+// to the viewport width and height, and percentages in vectors and rects
+// relative to the viewport size along their axis. Enum variants with data are
+// written as `Variant { ... }` or `Variant(value)`.
+//
+// An include directive inside an object merges the keys of the included file
+// and inside a list adds its items. Included names are relative to the name
+// of the including file.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneFormatError {
+    Syntax {
+        file: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    MissingInclude(String),
+    RecursiveInclude(String),
+    // The document doesn't describe a valid scene
+    Scene(String),
+}
+
+impl fmt::Display for SceneFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFormatError::Syntax {
+                file,
+                line,
+                column,
+                message,
+            } => write!(f, "{file}:{line}:{column}: {message}"),
+            SceneFormatError::MissingInclude(name) => {
+                write!(f, "The included file {name} could not be loaded")
+            }
+            SceneFormatError::RecursiveInclude(name) => {
+                write!(f, "The file {name} includes itself")
+            }
+            SceneFormatError::Scene(error) => write!(f, "Invalid scene: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneFormatError {}
+
+// Parses a scene in the scene format. Documents with includes have to be
+// loaded with load_scene instead.
+pub fn parse_scene(source: &str, viewport: Vec2) -> Result<Scene, SceneFormatError> {
+    let mut loader = Loader {
+        assets: None,
+        viewport,
+        include_stack: Vec::new(),
+    };
+    let value = loader.parse("<scene>", source)?;
+    scene_from_value(value)
+}
+
+// Loads a scene in the scene format and the files it includes from the assets
+pub fn load_scene(
+    assets: &impl AssetSource,
+    name: &str,
+    viewport: Vec2,
+) -> Result<Scene, SceneFormatError> {
+    let mut loader = Loader {
+        assets: Some(assets),
+        viewport,
+        include_stack: Vec::new(),
+    };
+    let value = loader.load(name)?;
+    scene_from_value(value)
+}
+
+fn scene_from_value(value: Value) -> Result<Scene, SceneFormatError> {
+    serde_json::from_value(value).map_err(|error| SceneFormatError::Scene(error.to_string()))
+}
+
+struct Loader<'a> {
+    assets: Option<&'a dyn AssetSource>,
+    viewport: Vec2,
+    // Names of the files being parsed, for detecting recursive includes
+    include_stack: Vec<String>,
+}
+
+impl<'a> Loader<'a> {
+    fn load(&mut self, name: &str) -> Result<Value, SceneFormatError> {
+        if self.include_stack.iter().any(|parent| parent == name) {
+            return Err(SceneFormatError::RecursiveInclude(name.to_string()));
+        }
+        let bytes = self
+            .assets
+            .and_then(|assets| assets.load(name))
+            .ok_or_else(|| SceneFormatError::MissingInclude(name.to_string()))?;
+        let source = String::from_utf8_lossy(&bytes);
+        self.parse(name, &source)
+    }
+
+    fn parse(&mut self, name: &str, source: &str) -> Result<Value, SceneFormatError> {
+        self.include_stack.push(name.to_string());
+        let mut parser = Parser {
+            file: name,
+            source,
+            position: 0,
+            line: 1,
+            column: 1,
+        };
+        let value = parser.document(self);
+        self.include_stack.pop();
+        value
+    }
+
+    fn include(&mut self, including: &str, name: &str) -> Result<Value, SceneFormatError> {
+        let name = match including.rfind('/') {
+            Some(directory_end) => format!("{}/{name}", &including[..directory_end]),
+            None => name.to_string(),
+        };
+        self.load(&name)
+    }
+}
+
+struct Parser<'s> {
+    file: &'s str,
+    source: &'s str,
+    // Byte offset of the next character
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error(&self, message: impl Into<String>) -> SceneFormatError {
+        SceneFormatError::Syntax {
+            file: self.file.to_string(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let character = self.peek()?;
+        self.position += character.len_utf8();
+        if character == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(character)
+    }
+
+    // Skips whitespace, comments and the optional commas between items
+    fn skip_separators(&mut self) {
+        while let Some(character) = self.peek() {
+            if character.is_whitespace() || character == ',' {
+                self.next();
+            } else if self.source[self.position..].starts_with("//") {
+                while !matches!(self.next(), Some('\n') | None) {}
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SceneFormatError> {
+        self.skip_separators();
+        if self.peek() == Some(expected) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected '{expected}'")))
+        }
+    }
+
+    fn word(&mut self) -> &'s str {
+        let start = self.position;
+        while matches!(self.peek(), Some(character) if character.is_alphanumeric() || character == '_')
+        {
+            self.next();
+        }
+        &self.source[start..self.position]
+    }
+
+    fn document(&mut self, loader: &mut Loader) -> Result<Value, SceneFormatError> {
+        self.skip_separators();
+        let value = match self.peek() {
+            Some('[') => self.list(loader)?,
+            Some('{') => self.object(loader)?,
+            _ => Value::Object(self.object_body(loader, None)?),
+        };
+        self.skip_separators();
+        if self.peek().is_some() {
+            return Err(self.error("Unexpected content after the document"));
+        }
+        Ok(value)
+    }
+
+    fn object(&mut self, loader: &mut Loader) -> Result<Value, SceneFormatError> {
+        self.expect('{')?;
+        let object = self.object_body(loader, Some('}'))?;
+        self.expect('}')?;
+        Ok(Value::Object(object))
+    }
+
+    fn object_body(
+        &mut self,
+        loader: &mut Loader,
+        end: Option<char>,
+    ) -> Result<Map<String, Value>, SceneFormatError> {
+        let mut object = Map::new();
+        loop {
+            self.skip_separators();
+            if self.peek() == end {
+                return Ok(object);
+            }
+
+            let key = match self.peek() {
+                Some('"') => self.string()?,
+                _ => self.word().to_string(),
+            };
+            if key.is_empty() {
+                return Err(self.error("Expected a key"));
+            }
+
+            if key == "include" && self.peek_string() {
+                match self.included(loader)? {
+                    Value::Object(included) => object.extend(included),
+                    _ => return Err(self.error("Only objects can be included in an object")),
+                }
+                continue;
+            }
+
+            self.expect(':')?;
+            let value = self.value(loader, None)?;
+            object.insert(key, value);
+        }
+    }
+
+    fn list(&mut self, loader: &mut Loader) -> Result<Value, SceneFormatError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_separators();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(items));
+            }
+
+            if self.source[self.position..].starts_with("include") {
+                let start = (self.position, self.line, self.column);
+                if self.word() == "include" && self.peek_string() {
+                    match self.included(loader)? {
+                        Value::Array(included) => items.extend(included),
+                        included => items.push(included),
+                    }
+                    continue;
+                }
+                (self.position, self.line, self.column) = start;
+            }
+
+            let value = self.value(loader, Some(items.len()))?;
+            items.push(value);
+        }
+    }
+
+    // Whether a string follows the current word, which distinguishes an
+    // include directive from a key or value named include
+    fn peek_string(&mut self) -> bool {
+        self.skip_whitespace();
+        self.peek() == Some('"')
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(character) if character.is_whitespace() && character != '\n')
+        {
+            self.next();
+        }
+    }
+
+    fn included(&mut self, loader: &mut Loader) -> Result<Value, SceneFormatError> {
+        let name = self.string()?;
+        loader.include(self.file, &name)
+    }
+
+    // The index is the position of the value in the surrounding list, which
+    // decides the axis of percentages
+    fn value(
+        &mut self,
+        loader: &mut Loader,
+        index: Option<usize>,
+    ) -> Result<Value, SceneFormatError> {
+        self.skip_separators();
+        match self.peek() {
+            Some('{') => self.object(loader),
+            Some('[') => self.list(loader),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('#') => self.hex_color(),
+            Some(character)
+                if character == '-' || character == '.' || character.is_ascii_digit() =>
+            {
+                self.number(loader.viewport, index)
+            }
+            Some(character) if character.is_alphabetic() => self.identifier(loader),
+            Some(character) => Err(self.error(format!("Unexpected character '{character}'"))),
+            None => Err(self.error("Expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SceneFormatError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some(character @ ('"' | '\\')) => string.push(character),
+                    _ => return Err(self.error("Invalid escape sequence")),
+                },
+                Some(character) => string.push(character),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    fn number(&mut self, viewport: Vec2, index: Option<usize>) -> Result<Value, SceneFormatError> {
+        let start = self.position;
+        while matches!(self.peek(), Some(character) if character.is_ascii_digit() || matches!(character, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.next();
+        }
+        let text = &self.source[start..self.position];
+        let number: f64 = text
+            .parse()
+            .map_err(|_| self.error(format!("Invalid number {text}")))?;
+
+        let number = match self.peek() {
+            Some('%') => {
+                self.next();
+                let axis = index.ok_or_else(|| {
+                    self.error("Percentages are only allowed inside vectors and rects")
+                })?;
+                number / 100. * viewport[axis % 2] as f64
+            }
+            Some(character) if character.is_alphabetic() => match self.word() {
+                "vw" => number / 100. * viewport.x as f64,
+                "vh" => number / 100. * viewport.y as f64,
+                unit => return Err(self.error(format!("Unknown unit {unit}"))),
+            },
+            _ => number,
+        };
+
+        // Integers stay integers so that they deserialize into integer fields
+        if number.fract() == 0. && number.abs() < u32::MAX as f64 && !text.contains('.') {
+            Ok(Value::from(number as i64))
+        } else {
+            Number::from_f64(number)
+                .map(Value::Number)
+                .ok_or_else(|| self.error(format!("Invalid number {text}")))
+        }
+    }
+
+    fn hex_color(&mut self) -> Result<Value, SceneFormatError> {
+        self.next();
+        let digits = self.word();
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+        let channels: Option<Vec<u8>> = match digits.len() {
+            3 => digits
+                .chars()
+                .map(|digit| channel(&digit.to_string().repeat(2)))
+                .chain([Some(255)])
+                .collect(),
+            6 | 8 => (0..digits.len())
+                .step_by(2)
+                .map(|start| channel(&digits[start..start + 2]))
+                .chain((digits.len() == 6).then_some(Some(255)))
+                .collect(),
+            _ => None,
+        };
+        let channels = channels.ok_or_else(|| self.error(format!("Invalid color #{digits}")))?;
+        Ok(color(channels[0], channels[1], channels[2], channels[3]))
+    }
+
+    fn identifier(&mut self, loader: &mut Loader) -> Result<Value, SceneFormatError> {
+        let name = self.word();
+        self.skip_whitespace();
+        match self.peek() {
+            // Enum variants with fields
+            Some('{') => {
+                let fields = self.object(loader)?;
+                return Ok(Value::Object(Map::from_iter([(name.to_string(), fields)])));
+            }
+            Some('(') => {
+                self.next();
+                let value = self.value(loader, None)?;
+                self.expect(')')?;
+                return Ok(Value::Object(Map::from_iter([(name.to_string(), value)])));
+            }
+            _ => {}
+        }
+
+        Ok(match name {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "null" | "none" => Value::Null,
+            _ => named_color(name).unwrap_or_else(|| Value::String(name.to_string())),
+        })
+    }
+}
+
+fn color(red: u8, green: u8, blue: u8, alpha: u8) -> Value {
+    Value::Array(
+        [red, green, blue, alpha]
+            .into_iter()
+            .map(|channel| Value::from(channel as f64 / 255.))
+            .collect(),
+    )
+}
+
+fn named_color(name: &str) -> Option<Value> {
+    let (red, green, blue, alpha) = match name {
+        "transparent" => (0, 0, 0, 0),
+        "black" => (0, 0, 0, 255),
+        "white" => (255, 255, 255, 255),
+        "gray" | "grey" => (128, 128, 128, 255),
+        "silver" => (192, 192, 192, 255),
+        "red" => (255, 0, 0, 255),
+        "maroon" => (128, 0, 0, 255),
+        "orange" => (255, 165, 0, 255),
+        "yellow" => (255, 255, 0, 255),
+        "olive" => (128, 128, 0, 255),
+        "lime" => (0, 255, 0, 255),
+        "green" => (0, 128, 0, 255),
+        "teal" => (0, 128, 128, 255),
+        "cyan" | "aqua" => (0, 255, 255, 255),
+        "blue" => (0, 0, 255, 255),
+        "navy" => (0, 0, 128, 255),
+        "purple" => (128, 0, 128, 255),
+        "magenta" | "fuchsia" => (255, 0, 255, 255),
+        "pink" => (255, 192, 203, 255),
+        "brown" => (165, 42, 42, 255),
+        _ => return None,
+    };
+    Some(color(red, green, blue, alpha))
+}
@@ -0,0 +1,352 @@
+use glam::{vec2, vec4, Vec2, Vec4};
+
+use super::{grapheme_width, Layer, Quad, Text};
+
+// A cell of a TextGrid. The cell after a double width character is covered by
+// it and holds no text of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridCell {
+    pub text: String,
+    pub foreground: Vec4,
+    pub background: Option<Vec4>,
+    pub double_width: bool,
+    // Set for the second half of a double width character
+    pub continuation: bool,
+}
+
+impl GridCell {
+    // Wide east asian characters and emoji are double width, as measured by
+    // grapheme_width
+    pub fn new(text: &str, foreground: Vec4) -> Self {
+        Self {
+            text: text.to_string(),
+            foreground,
+            background: None,
+            double_width: grapheme_width(text) == 2,
+            continuation: false,
+        }
+    }
+
+    pub fn with_background(mut self, background: Vec4) -> Self {
+        self.background = Some(background);
+        self
+    }
+
+    pub fn with_double_width(mut self) -> Self {
+        self.double_width = true;
+        self
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.text.is_empty() && self.background.is_none() && !self.continuation
+    }
+}
+
+impl Default for GridCell {
+    fn default() -> Self {
+        Self::new("", Vec4::ZERO)
+    }
+}
+
+// Lays out text in fixed size cells, like a terminal. Each cell is drawn as
+// its own text run at the cell position unless ligatures are enabled, so
+// glyphs never drift off the grid, and runs of cells with the same
+// background are merged into one quad. The primitives are kept per row in
+// the layer given to update_layer, and only the rows with changed cells are
+// rebuilt.
+#[derive(Debug, Clone)]
+pub struct TextGrid {
+    top_left: Vec2,
+    cell_size: Vec2,
+    font_size: f32,
+    // Distance from the top of the cell to the baseline
+    baseline: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<GridCell>,
+    dirty_rows: Vec<bool>,
+    // Number of texts and quads each row has in the layer
+    row_texts: Vec<usize>,
+    row_quads: Vec<usize>,
+    // Shapes neighbouring cells together so that ligatures can form
+    ligatures: bool,
+    needs_full_update: bool,
+}
+
+impl TextGrid {
+    pub fn new(columns: usize, rows: usize, cell_size: Vec2, font_size: f32) -> Self {
+        Self {
+            top_left: Vec2::ZERO,
+            cell_size,
+            font_size,
+            baseline: cell_size.y * 0.8,
+            columns,
+            rows,
+            cells: vec![GridCell::default(); columns * rows],
+            dirty_rows: vec![true; rows],
+            row_texts: vec![0; rows],
+            row_quads: vec![0; rows],
+            ligatures: false,
+            needs_full_update: true,
+        }
+    }
+
+    pub fn with_top_left(mut self, top_left: Vec2) -> Self {
+        self.top_left = top_left;
+        self.needs_full_update = true;
+        self
+    }
+
+    pub fn with_baseline(mut self, baseline: f32) -> Self {
+        self.baseline = baseline;
+        self.needs_full_update = true;
+        self
+    }
+
+    // Shapes runs of neighbouring cells with the same foreground as one text
+    // run, so that programming ligatures can form across the cells. The
+    // backgrounds are still drawn per cell. Only keeps the glyphs on the grid
+    // when the advance of the font matches the cell width, and
+    // Renderer::cell_clusters tells which cells each ligature covers.
+    pub fn with_ligatures(mut self) -> Self {
+        self.ligatures = true;
+        self.needs_full_update = true;
+        self
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cell_size(&self) -> Vec2 {
+        self.cell_size
+    }
+
+    pub fn cell(&self, column: usize, row: usize) -> Option<&GridCell> {
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        self.cells.get(row * self.columns + column)
+    }
+
+    // Top left and size of the cell
+    pub fn cell_rect(&self, column: usize, row: usize) -> Vec4 {
+        let top_left = self.top_left + vec2(column as f32, row as f32) * self.cell_size;
+        vec4(top_left.x, top_left.y, self.cell_size.x, self.cell_size.y)
+    }
+
+    // Column and row of the cell containing the position
+    pub fn cell_at(&self, position: Vec2) -> Option<(usize, usize)> {
+        let cell = ((position - self.top_left) / self.cell_size).floor();
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return None;
+        }
+        let (column, row) = (cell.x as usize, cell.y as usize);
+        (column < self.columns && row < self.rows).then_some((column, row))
+    }
+
+    // Replaces the cell and returns if it changed. A double width cell also
+    // covers the next one, and overwriting either half of a double width
+    // character blanks the other half. Cells outside of the grid, and double
+    // width cells in the last column, are ignored.
+    pub fn set_cell(&mut self, column: usize, row: usize, mut cell: GridCell) -> bool {
+        if column >= self.columns || row >= self.rows {
+            return false;
+        }
+        cell.continuation = false;
+        if cell.double_width && column + 1 >= self.columns {
+            return false;
+        }
+        if self.cells[self.index(column, row)] == cell {
+            return false;
+        }
+
+        self.break_double_width(column, row);
+        if cell.double_width {
+            self.break_double_width(column + 1, row);
+            let continuation = GridCell {
+                text: String::new(),
+                double_width: false,
+                continuation: true,
+                ..cell.clone()
+            };
+            let index = self.index(column + 1, row);
+            self.cells[index] = continuation;
+        }
+        let index = self.index(column, row);
+        self.cells[index] = cell;
+        self.dirty_rows[row] = true;
+        true
+    }
+
+    pub fn clear_cell(&mut self, column: usize, row: usize) -> bool {
+        self.set_cell(column, row, GridCell::default())
+    }
+
+    pub fn clear(&mut self) {
+        for row in 0..self.rows {
+            if self.row(row).iter().any(|cell| !cell.is_blank()) {
+                self.dirty_rows[row] = true;
+            }
+        }
+        self.cells.fill(GridCell::default());
+    }
+
+    // Keeps the cells which are still inside the grid
+    pub fn resize(&mut self, columns: usize, rows: usize) {
+        let mut cells = vec![GridCell::default(); columns * rows];
+        for row in 0..rows.min(self.rows) {
+            for column in 0..columns.min(self.columns) {
+                cells[row * columns + column] = self.cells[self.index(column, row)].clone();
+            }
+            // Double width characters cut in half by the new edge
+            if columns < self.columns && columns > 0 {
+                let last = &mut cells[row * columns + columns - 1];
+                if last.double_width {
+                    *last = GridCell::default();
+                }
+            }
+        }
+        self.columns = columns;
+        self.rows = rows;
+        self.cells = cells;
+        self.dirty_rows = vec![true; rows];
+        self.needs_full_update = true;
+    }
+
+    // Rows with cells changed since the last update
+    pub fn dirty_rows(&self) -> usize {
+        self.dirty_rows.iter().filter(|dirty| **dirty).count()
+    }
+
+    // Brings the texts and quads of the layer up to date with the cells,
+    // rebuilding only the rows which changed. The layer must not hold any
+    // other texts or quads, otherwise all of them are replaced. Returns the
+    // number of rows rebuilt.
+    pub fn update_layer(&mut self, layer: &mut Layer) -> usize {
+        let expected_texts: usize = self.row_texts.iter().sum();
+        let expected_quads: usize = self.row_quads.iter().sum();
+        let full_update = self.needs_full_update
+            || layer.texts.len() != expected_texts
+            || layer.quads.len() != expected_quads;
+        if full_update {
+            layer.texts.clear();
+            layer.quads.clear();
+            self.row_texts = vec![0; self.rows];
+            self.row_quads = vec![0; self.rows];
+        }
+
+        let mut rebuilt = 0;
+        let mut text_offset = 0;
+        let mut quad_offset = 0;
+        for row in 0..self.rows {
+            if full_update || self.dirty_rows[row] {
+                let (texts, quads) = self.row_primitives(row);
+                let text_range = text_offset..text_offset + self.row_texts[row];
+                let quad_range = quad_offset..quad_offset + self.row_quads[row];
+                self.row_texts[row] = texts.len();
+                self.row_quads[row] = quads.len();
+                layer.texts.splice(text_range, texts);
+                layer.quads.splice(quad_range, quads);
+                self.dirty_rows[row] = false;
+                rebuilt += 1;
+            }
+            text_offset += self.row_texts[row];
+            quad_offset += self.row_quads[row];
+        }
+        self.needs_full_update = false;
+        rebuilt
+    }
+
+    fn row_primitives(&self, row: usize) -> (Vec<Text>, Vec<Quad>) {
+        let mut texts = Vec::new();
+        let mut quads = Vec::new();
+        // Start column and color of the background run being merged
+        let mut background: Option<(usize, Vec4)> = None;
+        // Start column and the text of the cells shaped together
+        let mut run: Option<(usize, String)> = None;
+        for (column, cell) in self.row(row).iter().enumerate() {
+            if background.map(|(_, color)| color) != cell.background {
+                if let Some((start, color)) = background {
+                    quads.push(self.background_quad(start, column, row, color));
+                }
+                background = cell.background.map(|color| (column, color));
+            }
+
+            if cell.continuation || cell.text.trim().is_empty() {
+                continue;
+            }
+            let joins_run =
+                self.ligatures && !cell.double_width && run.is_some() && column > 0 && {
+                    // The previous cell is the end of the run unless it's
+                    // blank
+                    let previous = &self.row(row)[column - 1];
+                    !previous.text.trim().is_empty()
+                        && !previous.double_width
+                        && !previous.continuation
+                        && previous.foreground == cell.foreground
+                };
+            if joins_run {
+                run.as_mut().unwrap().1.push_str(&cell.text);
+                continue;
+            }
+            if let Some((start, text)) = run.take() {
+                texts.push(self.text_run(start, row, text));
+            }
+            run = Some((column, cell.text.clone()));
+        }
+        if let Some((start, text)) = run {
+            texts.push(self.text_run(start, row, text));
+        }
+        if let Some((start, color)) = background {
+            quads.push(self.background_quad(start, self.columns, row, color));
+        }
+        (texts, quads)
+    }
+
+    fn text_run(&self, column: usize, row: usize, text: String) -> Text {
+        let rect = self.cell_rect(column, row);
+        let foreground = self.row(row)[column].foreground;
+        Text::new(
+            text,
+            vec2(rect.x, rect.y + self.baseline),
+            self.font_size,
+            foreground,
+        )
+    }
+
+    fn background_quad(&self, start: usize, end: usize, row: usize, color: Vec4) -> Quad {
+        let rect = self.cell_rect(start, row);
+        Quad::new(
+            vec2(rect.x, rect.y),
+            vec2(self.cell_size.x * (end - start) as f32, self.cell_size.y),
+            color,
+        )
+    }
+
+    // Blanks the other half of a double width character the cell is part of
+    fn break_double_width(&mut self, column: usize, row: usize) {
+        let cell = &self.cells[self.index(column, row)];
+        let other = if cell.double_width {
+            column + 1
+        } else if cell.continuation {
+            column - 1
+        } else {
+            return;
+        };
+        let index = self.index(other, row);
+        self.cells[index] = GridCell::default();
+    }
+
+    fn row(&self, row: usize) -> &[GridCell] {
+        &self.cells[row * self.columns..(row + 1) * self.columns]
+    }
+
+    fn index(&self, column: usize, row: usize) -> usize {
+        row * self.columns + column
+    }
+}
@@ -0,0 +1,211 @@
+use glam::{vec2, vec4, Vec2, Vec4, Vec4Swizzles};
+use lyon::{
+    algorithms::hit_test::hit_test_path,
+    geom::point,
+    path::{iterator::PathIterator, PathEvent},
+};
+
+use super::{Layer, Path, Scene, Text};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveKind {
+    Quad,
+    Text,
+    Path,
+    Sprite,
+    Procedural,
+    Shape,
+    Caret,
+}
+
+// A primitive under the hit test position. The index refers to the position
+// of the primitive in the corresponding list of the layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hit {
+    pub layer: usize,
+    pub kind: PrimitiveKind,
+    pub index: usize,
+    pub tag: Option<u64>,
+}
+
+impl Scene {
+    // Returns every primitive under the position in scene coordinates, with
+    // the top most primitive first. Use Renderer::surface_to_scene to convert
+    // a surface position into scene coordinates. The shaped width of text
+    // isn't known here, so text is only hit within the rect it's fitted to.
+    // Renderer::hit_test also hits the other text within its shaped line.
+    pub fn hit_test(&self, position: Vec2) -> Vec<Hit> {
+        self.hit_test_with(position, |_, _| None)
+    }
+
+    // Hit test with the line bounds of the shaped texts, when the closure
+    // has them
+    pub(crate) fn hit_test_with(
+        &self,
+        position: Vec2,
+        mut line_bounds: impl FnMut(&Layer, &Text) -> Option<Vec4>,
+    ) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        for (layer_index, layer) in self.layers.iter().enumerate().rev() {
+            let hit_test = layer.hit_test_with(position, |text| line_bounds(layer, text));
+            hits.extend(hit_test.into_iter().map(|mut hit| {
+                hit.layer = layer_index;
+                hit
+            }));
+        }
+        hits
+    }
+
+    // Tags of the primitives under the position, top most first. Untagged
+    // primitives are skipped.
+    pub fn hit_test_tags(&self, position: Vec2) -> Vec<u64> {
+        self.hit_test(position)
+            .into_iter()
+            .filter_map(|hit| hit.tag)
+            .collect()
+    }
+}
+
+impl Layer {
+    // Returns every primitive of the layer under the position, with the top
+    // most primitive first. Primitives are tested in the order the default
+    // drawables render them. Text is only hit within the rect it's fitted
+    // to, since its shaped width isn't known here.
+    pub fn hit_test(&self, position: Vec2) -> Vec<Hit> {
+        self.hit_test_with(position, |_| None)
+    }
+
+    fn hit_test_with(
+        &self,
+        position: Vec2,
+        mut line_bounds: impl FnMut(&Text) -> Option<Vec4>,
+    ) -> Vec<Hit> {
+        if let Some(clip) = self.clip {
+            if !rect_contains(clip, position) {
+                return Vec::new();
+            }
+        }
+
+        let position = position + self.scroll_offset;
+        let hit = |kind, index, tag| Hit {
+            layer: 0,
+            kind,
+            index,
+            tag,
+        };
+
+        let mut hits = Vec::new();
+        for (index, caret) in self.carets.iter().enumerate().rev() {
+            if rect_contains(caret.bounds(), position) {
+                hits.push(hit(PrimitiveKind::Caret, index, caret.tag));
+            }
+        }
+
+        for (index, sprite) in self.sprites.iter().enumerate().rev() {
+            if rect_contains(sprite.bounds(), position) {
+                hits.push(hit(PrimitiveKind::Sprite, index, sprite.tag));
+            }
+        }
+
+        for (index, path) in self.paths.iter().enumerate().rev() {
+            if path_contains(path, position) {
+                hits.push(hit(PrimitiveKind::Path, index, path.tag));
+            }
+        }
+
+        for (index, text) in self.texts.iter().enumerate().rev() {
+            let bounds = line_bounds(text).or_else(|| fit_bounds(text));
+            if bounds.is_some_and(|bounds| rect_contains(bounds, position)) {
+                hits.push(hit(PrimitiveKind::Text, index, text.tag));
+            }
+        }
+
+        for (index, shape) in self.shapes.iter().enumerate().rev() {
+            if shape.contains(position) {
+                hits.push(hit(PrimitiveKind::Shape, index, shape.tag));
+            }
+        }
+
+        for (index, procedural) in self.procedurals.iter().enumerate().rev() {
+            if rect_contains(procedural.bounds(), position) {
+                hits.push(hit(PrimitiveKind::Procedural, index, procedural.tag));
+            }
+        }
+
+        for (index, quad) in self.quads.iter().enumerate().rev() {
+            if quad.contains(position) {
+                hits.push(hit(PrimitiveKind::Quad, index, quad.tag()));
+            }
+        }
+
+        hits
+    }
+}
+
+fn rect_contains(rect: Vec4, position: Vec2) -> bool {
+    let bottom_right = rect.xy() + rect.zw();
+    position.cmpge(rect.xy()).all() && position.cmple(bottom_right).all()
+}
+
+// Rect of the fit of the text, grown vertically like its bounds
+fn fit_bounds(text: &Text) -> Option<Vec4> {
+    let fit = text.fit?;
+    let bounds = text.bounds();
+    Some(vec4(fit.top_left.x, bounds.y, fit.size.x, bounds.w))
+}
+
+// Fills are tested using the fill rule of the path and strokes by the distance
+// to the flattened outline
+fn path_contains(path: &Path, position: Vec2) -> bool {
+    let stroke_width = path.stroke.map(|(width, _)| width).unwrap_or(0.0);
+    let bounds = path.bounds();
+    let grown_bounds = vec4(
+        bounds.x - stroke_width / 2.0,
+        bounds.y - stroke_width / 2.0,
+        bounds.z + stroke_width,
+        bounds.w + stroke_width,
+    );
+    if !rect_contains(grown_bounds, position) {
+        return false;
+    }
+
+    let lyon_path = path.to_lyon_path();
+    let filled = path.fill.is_some()
+        && hit_test_path(
+            &point(position.x, position.y),
+            lyon_path.iter(),
+            path.fill_rule.into(),
+            path.tolerance,
+        );
+
+    filled
+        || path.stroke.is_some_and(|(width, _)| {
+            lyon_path
+                .iter()
+                .flattened(path.tolerance)
+                .any(|event| match event {
+                    PathEvent::Line { from, to }
+                    | PathEvent::End {
+                        last: from,
+                        first: to,
+                        ..
+                    } => {
+                        let from = vec2(from.x, from.y);
+                        let to = vec2(to.x, to.y);
+                        segment_distance(from, to, position) <= width / 2.0
+                    }
+                    _ => false,
+                })
+        })
+}
+
+fn segment_distance(from: Vec2, to: Vec2, position: Vec2) -> f32 {
+    let segment = to - from;
+    let length_squared = segment.length_squared();
+    if length_squared == 0.0 {
+        return position.distance(from);
+    }
+
+    let t = ((position - from).dot(segment) / length_squared).clamp(0.0, 1.0);
+    position.distance(from + segment * t)
+}
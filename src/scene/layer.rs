@@ -1,22 +1,37 @@
-use glam::Vec4;
-use serde::Deserialize;
+use glam::{Vec2, Vec4};
+use serde::{Deserialize, Serialize};
 
+use super::theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue};
+use super::BackdropBlurQuad;
+use super::Caret;
+use super::Checkerboard;
+use super::CursorTrail;
+use super::LayerDimming;
 use super::Path;
+use super::Procedural;
 use super::Quad;
+use super::Shadow;
+use super::Shape;
 use super::Sprite;
 use super::Text;
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Layer {
     #[serde(default)]
     pub clip: Option<Vec4>,
     #[serde(default)]
+    pub scroll_offset: Vec2,
+    #[serde(default)]
     pub background_blur_radius: f32,
     #[serde(default)]
     pub background_color: Option<Vec4>,
+    #[serde(default)]
+    pub dimming: Option<LayerDimming>,
     #[serde(default = "default_font")]
     pub font_name: String,
     #[serde(default)]
+    pub shadows: Vec<Shadow>,
+    #[serde(default)]
     pub quads: Vec<Quad>,
     #[serde(default)]
     pub texts: Vec<Text>,
@@ -24,19 +39,44 @@ pub struct Layer {
     pub paths: Vec<Path>,
     #[serde(default)]
     pub sprites: Vec<Sprite>,
+    #[serde(default)]
+    pub procedurals: Vec<Procedural>,
+    #[serde(default)]
+    pub shapes: Vec<Shape>,
+    #[serde(default)]
+    pub backdrop_blurs: Vec<BackdropBlurQuad>,
+    #[serde(default)]
+    pub carets: Vec<Caret>,
+    #[serde(default)]
+    pub cursor_trails: Vec<CursorTrail>,
+    #[serde(default)]
+    pub checkerboards: Vec<Checkerboard>,
+    // Theme variables replacing the background of the layer when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    pub theme: ThemeBindings,
 }
 
 impl Default for Layer {
     fn default() -> Self {
         Self {
             clip: None,
+            scroll_offset: Vec2::ZERO,
             background_blur_radius: 0.0,
             background_color: Some(Vec4::new(1.0, 1.0, 1.0, 1.0)),
+            dimming: None,
             font_name: "monospace".to_string(),
+            shadows: Vec::new(),
             quads: Vec::new(),
             texts: Vec::new(),
             paths: Vec::new(),
             sprites: Vec::new(),
+            procedurals: Vec::new(),
+            shapes: Vec::new(),
+            backdrop_blurs: Vec::new(),
+            carets: Vec::new(),
+            cursor_trails: Vec::new(),
+            checkerboards: Vec::new(),
+            theme: ThemeBindings::new(),
         }
     }
 }
@@ -59,6 +99,34 @@ impl Layer {
         self.clip = Some(clip);
     }
 
+    pub fn with_scroll_offset(mut self, scroll_offset: Vec2) -> Self {
+        self.scroll_offset = scroll_offset;
+        self
+    }
+
+    pub fn set_scroll_offset(&mut self, scroll_offset: Vec2) {
+        self.scroll_offset = scroll_offset;
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| match (field, value) {
+            (ThemeField::BackgroundColor, ThemeValue::Color(color)) => {
+                self.background_color = Some(color)
+            }
+            (ThemeField::BackgroundBlurRadius, ThemeValue::Number(radius)) => {
+                self.background_blur_radius = radius
+            }
+            _ => {}
+        });
+        self.theme = bindings;
+    }
+
     pub fn with_blur(mut self, radius: f32) -> Self {
         self.background_blur_radius = radius;
         self
@@ -77,6 +145,29 @@ impl Layer {
         self.background_color = Some(color);
     }
 
+    // Layers without a background can be batched with the layer below
+    pub fn without_background(mut self) -> Self {
+        self.remove_background();
+        self
+    }
+
+    pub fn remove_background(&mut self) {
+        self.background_color = None;
+    }
+
+    pub fn with_dimming(mut self, dimming: LayerDimming) -> Self {
+        self.dimming = Some(dimming);
+        self
+    }
+
+    pub fn set_dimming(&mut self, dimming: LayerDimming) {
+        self.dimming = Some(dimming);
+    }
+
+    pub fn remove_dimming(&mut self) {
+        self.dimming = None;
+    }
+
     pub fn with_font(mut self, font_name: String) -> Self {
         self.font_name = font_name;
         self
@@ -121,4 +212,67 @@ impl Layer {
         self.add_sprite(sprite);
         self
     }
+
+    pub fn add_procedural(&mut self, procedural: Procedural) {
+        self.procedurals.push(procedural);
+    }
+
+    pub fn with_procedural(mut self, procedural: Procedural) -> Self {
+        self.add_procedural(procedural);
+        self
+    }
+
+    pub fn add_shape(&mut self, shape: Shape) {
+        self.shapes.push(shape);
+    }
+
+    pub fn with_shape(mut self, shape: Shape) -> Self {
+        self.add_shape(shape);
+        self
+    }
+
+    pub fn add_shadow(&mut self, shadow: Shadow) {
+        self.shadows.push(shadow);
+    }
+
+    pub fn with_shadow(mut self, shadow: Shadow) -> Self {
+        self.add_shadow(shadow);
+        self
+    }
+
+    pub fn add_backdrop_blur(&mut self, blur: BackdropBlurQuad) {
+        self.backdrop_blurs.push(blur);
+    }
+
+    pub fn with_backdrop_blur(mut self, blur: BackdropBlurQuad) -> Self {
+        self.add_backdrop_blur(blur);
+        self
+    }
+
+    pub fn add_caret(&mut self, caret: Caret) {
+        self.carets.push(caret);
+    }
+
+    pub fn with_caret(mut self, caret: Caret) -> Self {
+        self.add_caret(caret);
+        self
+    }
+
+    pub fn add_cursor_trail(&mut self, trail: CursorTrail) {
+        self.cursor_trails.push(trail);
+    }
+
+    pub fn with_cursor_trail(mut self, trail: CursorTrail) -> Self {
+        self.add_cursor_trail(trail);
+        self
+    }
+
+    pub fn add_checkerboard(&mut self, checkerboard: Checkerboard) {
+        self.checkerboards.push(checkerboard);
+    }
+
+    pub fn with_checkerboard(mut self, checkerboard: Checkerboard) -> Self {
+        self.add_checkerboard(checkerboard);
+        self
+    }
 }
@@ -0,0 +1,62 @@
+use glam::Vec4;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    animation::{fade_color, interpolate_color},
+    validation::Validator,
+    Interpolate,
+};
+
+// Band of color around the edge of a shape, such as a focus ring. With a
+// softness the band fades out over that distance past its width into a glow,
+// and with a zero width it's only a glow.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Outline {
+    pub color: Vec4,
+    pub width: f32,
+    #[serde(default)]
+    pub softness: f32,
+}
+
+impl Outline {
+    pub fn new(color: Vec4, width: f32) -> Self {
+        Self {
+            color,
+            width,
+            softness: 0.0,
+        }
+    }
+
+    pub fn with_softness(mut self, softness: f32) -> Self {
+        self.softness = softness;
+        self
+    }
+
+    // Distance past the edge of the shape the outline reaches
+    pub fn extent(&self) -> f32 {
+        self.width.max(0.0) + self.softness.max(0.0)
+    }
+
+    pub(crate) fn validate_into(&self, validator: &mut Validator, path: &str) {
+        validator.color(&format!("{path}.color"), self.color);
+        validator.non_negative(&format!("{path}.width"), self.width);
+        validator.non_negative(&format!("{path}.softness"), self.softness);
+    }
+}
+
+impl Interpolate for Outline {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Outline {
+            color: interpolate_color(self.color, to.color, t),
+            width: self.width + (to.width - self.width) * t,
+            softness: self.softness + (to.softness - self.softness) * t,
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Outline {
+            color: fade_color(self.color, opacity),
+            ..*self
+        }
+    }
+}
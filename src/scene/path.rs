@@ -1,7 +1,19 @@
-use glam::{Vec2, Vec4};
-use serde::Deserialize;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
 
-#[derive(Deserialize, Debug, Clone)]
+use glam::{vec4, Vec2, Vec4};
+use lyon::{geom::point, tessellation::FillOptions};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    animation::{fade_color, interpolate_color, interpolate_option, snap},
+    theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue},
+    Interpolate, Outline, Pattern,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum PathCommand {
     CubicBezierTo {
@@ -16,43 +28,121 @@ pub enum PathCommand {
     LineTo {
         to: Vec2,
     },
+    // Ends the current sub path and starts a new one. Every sub path is
+    // closed when tessellated.
+    MoveTo {
+        start: Vec2,
+    },
+}
+
+impl PathCommand {
+    pub fn points(&self) -> Vec<Vec2> {
+        match self {
+            PathCommand::CubicBezierTo {
+                control1,
+                control2,
+                to,
+            } => vec![*control1, *control2, *to],
+            PathCommand::QuadraticBezierTo { control, to } => vec![*control, *to],
+            PathCommand::LineTo { to } => vec![*to],
+            PathCommand::MoveTo { start } => vec![*start],
+        }
+    }
+}
+
+// Determines which regions of self intersecting paths or paths with multiple
+// sub paths are considered inside the path when filling
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FillRule {
+    #[default]
+    EvenOdd,
+    NonZero,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl From<FillRule> for lyon::path::FillRule {
+    fn from(fill_rule: FillRule) -> Self {
+        match fill_rule {
+            FillRule::EvenOdd => lyon::path::FillRule::EvenOdd,
+            FillRule::NonZero => lyon::path::FillRule::NonZero,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Path {
     #[serde(default)]
     pub fill: Option<Vec4>,
     #[serde(default)]
+    pub fill_rule: FillRule,
+    // Image tiled across the fill. The fill color tints the pattern.
+    #[serde(default)]
+    pub fill_pattern: Option<Pattern>,
+    #[serde(default)]
     pub stroke: Option<(f32, Vec4)>,
+    // Drawn as strokes beneath the fill and stroke reaching past the stroke,
+    // so the part inside the path only shows through a translucent fill
+    #[serde(default)]
+    pub outline: Option<Outline>,
+    // Maximum distance between the curves and the tessellated geometry
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f32,
     pub start: Vec2,
     pub commands: Vec<PathCommand>,
+    // Opaque user data reported by hit testing. Ignored by rendering.
+    #[serde(default)]
+    pub tag: Option<u64>,
+    // Theme variables replacing the values of fields when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    pub theme: ThemeBindings,
+}
+
+fn default_tolerance() -> f32 {
+    FillOptions::DEFAULT_TOLERANCE
 }
 
 impl Path {
     pub fn new_fill(fill: Vec4, start: Vec2) -> Self {
         Self {
             fill: Some(fill),
+            fill_rule: FillRule::default(),
+            fill_pattern: None,
             stroke: None,
+            outline: None,
+            tolerance: default_tolerance(),
             start,
             commands: Vec::new(),
+            tag: None,
+            theme: ThemeBindings::new(),
         }
     }
 
     pub fn new_stroke(stroke: (f32, Vec4), start: Vec2) -> Self {
         Self {
             fill: None,
+            fill_rule: FillRule::default(),
+            fill_pattern: None,
             stroke: Some(stroke),
+            outline: None,
+            tolerance: default_tolerance(),
             start,
             commands: Vec::new(),
+            tag: None,
+            theme: ThemeBindings::new(),
         }
     }
 
     pub fn new(start: Vec2) -> Self {
         Self {
             fill: None,
+            fill_rule: FillRule::default(),
+            fill_pattern: None,
             stroke: None,
+            outline: None,
+            tolerance: default_tolerance(),
             start,
             commands: Vec::new(),
+            tag: None,
+            theme: ThemeBindings::new(),
         }
     }
 
@@ -61,11 +151,61 @@ impl Path {
         self
     }
 
+    pub fn with_fill_pattern(mut self, pattern: Pattern) -> Self {
+        self.fill.get_or_insert(Vec4::ONE);
+        self.fill_pattern = Some(pattern);
+        self
+    }
+
+    pub fn with_tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| match (field, value) {
+            (ThemeField::Fill, ThemeValue::Color(color)) => self.fill = Some(color),
+            (ThemeField::StrokeWidth, ThemeValue::Number(width)) => {
+                if let Some(stroke) = &mut self.stroke {
+                    stroke.0 = width;
+                }
+            }
+            (ThemeField::StrokeColor, ThemeValue::Color(color)) => {
+                if let Some(stroke) = &mut self.stroke {
+                    stroke.1 = color;
+                }
+            }
+            _ => {}
+        });
+        self.theme = bindings;
+    }
+
+    pub fn with_fill_rule(mut self, fill_rule: FillRule) -> Self {
+        self.fill_rule = fill_rule;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
     pub fn with_stroke(mut self, width: f32, color: Vec4) -> Self {
         self.stroke = Some((width, color));
         self
     }
 
+    pub fn with_outline(mut self, outline: Outline) -> Self {
+        self.outline = Some(outline);
+        self
+    }
+
     pub fn cubic_bezier_to(mut self, control1: Vec2, control2: Vec2, to: Vec2) -> Self {
         self.commands.push(PathCommand::CubicBezierTo {
             control1,
@@ -85,4 +225,191 @@ impl Path {
         self.commands.push(PathCommand::LineTo { to });
         self
     }
+
+    pub fn move_to(mut self, start: Vec2) -> Self {
+        self.commands.push(PathCommand::MoveTo { start });
+        self
+    }
+
+    // Bounds of every point and control point in the path expanded by the
+    // stroke width and the outline. Control points make this conservative for curves.
+    pub fn bounds(&self) -> Vec4 {
+        let mut min = self.start;
+        let mut max = self.start;
+        for command in self.commands.iter() {
+            for point in command.points() {
+                min = min.min(point);
+                max = max.max(point);
+            }
+        }
+
+        let stroke_extension = self.stroke.map(|(width, _)| width / 2.0).unwrap_or(0.0)
+            + self.outline.map(|outline| outline.extent()).unwrap_or(0.0);
+        vec4(
+            min.x - stroke_extension,
+            min.y - stroke_extension,
+            max.x - min.x + stroke_extension * 2.0,
+            max.y - min.y + stroke_extension * 2.0,
+        )
+    }
+
+    // Hash of everything which affects the tessellated geometry of the path.
+    // Colors are excluded so that recolored paths can share tessellations.
+    pub fn geometry_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let mut hash_point = |point: Vec2| {
+            point.x.to_bits().hash(&mut hasher);
+            point.y.to_bits().hash(&mut hasher);
+        };
+
+        hash_point(self.start);
+        for command in self.commands.iter() {
+            for point in command.points() {
+                hash_point(point);
+            }
+        }
+
+        for command in self.commands.iter() {
+            std::mem::discriminant(command).hash(&mut hasher);
+        }
+        self.fill.is_some().hash(&mut hasher);
+        self.fill_rule.hash(&mut hasher);
+        self.stroke
+            .map(|(width, _)| width.to_bits())
+            .hash(&mut hasher);
+        self.outline
+            .map(|outline| (outline.width.to_bits(), outline.softness.to_bits()))
+            .hash(&mut hasher);
+        self.tolerance.to_bits().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn to_lyon_path(&self) -> lyon::path::Path {
+        let mut builder = lyon::path::Path::builder();
+        builder.begin(point(self.start.x, self.start.y));
+        for path_command in self.commands.iter() {
+            match path_command {
+                PathCommand::LineTo { to } => {
+                    builder.line_to(point(to.x, to.y));
+                }
+                PathCommand::QuadraticBezierTo { control, to } => {
+                    builder.quadratic_bezier_to(point(control.x, control.y), point(to.x, to.y));
+                }
+                PathCommand::CubicBezierTo {
+                    control1,
+                    control2,
+                    to,
+                } => {
+                    builder.cubic_bezier_to(
+                        point(control1.x, control1.y),
+                        point(control2.x, control2.y),
+                        point(to.x, to.y),
+                    );
+                }
+                PathCommand::MoveTo { start } => {
+                    builder.close();
+                    builder.begin(point(start.x, start.y));
+                }
+            }
+        }
+        builder.close();
+        builder.build()
+    }
+}
+
+impl PathCommand {
+    // Blends the points of two commands of the same kind
+    fn interpolate(&self, to: &Self, t: f32) -> Option<Self> {
+        Some(match (self, to) {
+            (
+                PathCommand::CubicBezierTo {
+                    control1,
+                    control2,
+                    to: end,
+                },
+                PathCommand::CubicBezierTo {
+                    control1: to_control1,
+                    control2: to_control2,
+                    to: to_end,
+                },
+            ) => PathCommand::CubicBezierTo {
+                control1: control1.lerp(*to_control1, t),
+                control2: control2.lerp(*to_control2, t),
+                to: end.lerp(*to_end, t),
+            },
+            (
+                PathCommand::QuadraticBezierTo { control, to: end },
+                PathCommand::QuadraticBezierTo {
+                    control: to_control,
+                    to: to_end,
+                },
+            ) => PathCommand::QuadraticBezierTo {
+                control: control.lerp(*to_control, t),
+                to: end.lerp(*to_end, t),
+            },
+            (PathCommand::LineTo { to: end }, PathCommand::LineTo { to: to_end }) => {
+                PathCommand::LineTo {
+                    to: end.lerp(*to_end, t),
+                }
+            }
+            (PathCommand::MoveTo { start }, PathCommand::MoveTo { start: to_start }) => {
+                PathCommand::MoveTo {
+                    start: start.lerp(*to_start, t),
+                }
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl Interpolate for Path {
+    // The geometry is only blended when both paths are made out of the same
+    // sequence of commands. Otherwise it switches half way through.
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        let commands = if self.commands.len() == to.commands.len() {
+            self.commands
+                .iter()
+                .zip(to.commands.iter())
+                .map(|(from, to)| from.interpolate(to, t))
+                .collect::<Option<Vec<_>>>()
+        } else {
+            None
+        };
+
+        let snapped = snap(self, to, t);
+        let (start, commands) = match commands {
+            Some(commands) => (self.start.lerp(to.start, t), commands),
+            None => (snapped.start, snapped.commands.clone()),
+        };
+
+        Path {
+            fill: interpolate_option(&self.fill, &to.fill, t, |from, to| {
+                interpolate_color(*from, *to, t)
+            }),
+            stroke: interpolate_option(&self.stroke, &to.stroke, t, |from, to| {
+                (
+                    from.0 + (to.0 - from.0) * t,
+                    interpolate_color(from.1, to.1, t),
+                )
+            }),
+            outline: interpolate_option(&self.outline, &to.outline, t, |from, to| {
+                from.interpolate(to, t)
+            }),
+            tolerance: self.tolerance.min(to.tolerance),
+            start,
+            commands,
+            ..snapped.clone()
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Path {
+            fill: self.fill.map(|fill| fade_color(fill, opacity)),
+            stroke: self
+                .stroke
+                .map(|(width, color)| (width, fade_color(color, opacity))),
+            outline: self.outline.map(|outline| outline.fade(opacity)),
+            ..self.clone()
+        }
+    }
 }
@@ -0,0 +1,127 @@
+use geo::{BooleanOps, Coord, LineString, MultiPolygon, Polygon};
+use glam::vec2;
+use lyon::path::{iterator::PathIterator, PathEvent};
+
+use super::{FillRule, Path, PathCommand};
+
+// Tolerance used when flattening curves into line segments before running
+// boolean operations
+pub const DEFAULT_BOOLEAN_TOLERANCE: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BooleanOp {
+    Union,
+    Intersection,
+    Difference,
+    Xor,
+}
+
+impl Path {
+    pub fn union(&self, other: &Path) -> Path {
+        self.boolean(other, BooleanOp::Union)
+    }
+
+    pub fn intersection(&self, other: &Path) -> Path {
+        self.boolean(other, BooleanOp::Intersection)
+    }
+
+    pub fn difference(&self, other: &Path) -> Path {
+        self.boolean(other, BooleanOp::Difference)
+    }
+
+    pub fn xor(&self, other: &Path) -> Path {
+        self.boolean(other, BooleanOp::Xor)
+    }
+
+    pub fn boolean(&self, other: &Path, op: BooleanOp) -> Path {
+        self.boolean_with_tolerance(other, op, DEFAULT_BOOLEAN_TOLERANCE)
+    }
+
+    // Combines the filled areas of two paths. Curves are flattened into line
+    // segments with the given tolerance, so the resulting path only contains
+    // straight lines. The fill, stroke and tag of self are kept on the result.
+    pub fn boolean_with_tolerance(&self, other: &Path, op: BooleanOp, tolerance: f32) -> Path {
+        let a = self.to_multi_polygon(tolerance);
+        let b = other.to_multi_polygon(tolerance);
+
+        let result = match op {
+            BooleanOp::Union => a.union(&b),
+            BooleanOp::Intersection => a.intersection(&b),
+            BooleanOp::Difference => a.difference(&b),
+            BooleanOp::Xor => a.xor(&b),
+        };
+
+        let mut path = Path::from_multi_polygon(&result);
+        path.fill = self.fill;
+        path.fill_pattern = self.fill_pattern.clone();
+        path.stroke = self.stroke;
+        path.tag = self.tag;
+        path
+    }
+
+    // Converts the area filled by the path into polygons. Each sub path is
+    // treated as a separate polygon which are then combined according to the
+    // fill rule. For non zero paths this assumes sub paths don't cancel each
+    // other out with opposing windings.
+    fn to_multi_polygon(&self, tolerance: f32) -> MultiPolygon<f32> {
+        let mut rings = Vec::new();
+        let mut current = Vec::new();
+        for event in self.to_lyon_path().iter().flattened(tolerance) {
+            match event {
+                PathEvent::Begin { at } => {
+                    current = vec![Coord { x: at.x, y: at.y }];
+                }
+                PathEvent::Line { to, .. } => {
+                    current.push(Coord { x: to.x, y: to.y });
+                }
+                PathEvent::End { .. } if current.len() >= 3 => {
+                    rings.push(std::mem::take(&mut current));
+                }
+                _ => {}
+            }
+        }
+
+        rings
+            .into_iter()
+            .map(|ring| MultiPolygon::new(vec![Polygon::new(LineString::new(ring), Vec::new())]))
+            .reduce(|combined, polygon| match self.fill_rule {
+                FillRule::EvenOdd => combined.xor(&polygon),
+                FillRule::NonZero => combined.union(&polygon),
+            })
+            .unwrap_or_else(|| MultiPolygon::new(Vec::new()))
+    }
+
+    fn from_multi_polygon(multi_polygon: &MultiPolygon<f32>) -> Path {
+        let mut rings = multi_polygon
+            .iter()
+            .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+            .filter(|ring| ring.0.len() >= 3);
+
+        let Some(first) = rings.next() else {
+            return Path::new(vec2(0., 0.));
+        };
+
+        let mut path =
+            Path::new(vec2(first.0[0].x, first.0[0].y)).with_fill_rule(FillRule::EvenOdd);
+        push_ring_lines(&mut path, first);
+        for ring in rings {
+            path.commands.push(PathCommand::MoveTo {
+                start: vec2(ring.0[0].x, ring.0[0].y),
+            });
+            push_ring_lines(&mut path, ring);
+        }
+        path
+    }
+}
+
+fn push_ring_lines(path: &mut Path, ring: &LineString<f32>) {
+    // Geo rings repeat the first coordinate at the end. Paths are closed
+    // automatically so the duplicate is skipped
+    let coordinates = ring.0.iter().skip(1);
+    let coordinates = coordinates.take(ring.0.len().saturating_sub(2));
+    for coordinate in coordinates {
+        path.commands.push(PathCommand::LineTo {
+            to: vec2(coordinate.x, coordinate.y),
+        });
+    }
+}
@@ -0,0 +1,202 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use glam::{vec2, Vec2};
+use lyon::geom::{point, vector, Angle, Arc, ArcFlags, CubicBezierSegment, SvgArc};
+
+use super::{Path, PathCommand};
+
+// Ergonomic way to build up a Path out of sub paths, curves, arcs and common
+// shapes. Arcs are converted into cubic bezier curves so the resulting path
+// only uses the primitive path commands.
+#[derive(Debug, Clone, Default)]
+pub struct PathBuilder {
+    start: Option<Vec2>,
+    commands: Vec<PathCommand>,
+    current: Vec2,
+    subpath_start: Vec2,
+    needs_move: bool,
+}
+
+impl PathBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn move_to(mut self, to: Vec2) -> Self {
+        if self.start.is_none() {
+            self.start = Some(to);
+        } else {
+            self.commands.push(PathCommand::MoveTo { start: to });
+        }
+        self.current = to;
+        self.subpath_start = to;
+        self.needs_move = false;
+        self
+    }
+
+    pub fn line_to(mut self, to: Vec2) -> Self {
+        self.ensure_started();
+        self.commands.push(PathCommand::LineTo { to });
+        self.current = to;
+        self
+    }
+
+    pub fn quad_to(mut self, control: Vec2, to: Vec2) -> Self {
+        self.ensure_started();
+        self.commands
+            .push(PathCommand::QuadraticBezierTo { control, to });
+        self.current = to;
+        self
+    }
+
+    pub fn cubic_to(mut self, control1: Vec2, control2: Vec2, to: Vec2) -> Self {
+        self.ensure_started();
+        self.commands.push(PathCommand::CubicBezierTo {
+            control1,
+            control2,
+            to,
+        });
+        self.current = to;
+        self
+    }
+
+    // Svg style elliptical arc from the current point to `to`
+    pub fn arc_to(
+        mut self,
+        radii: Vec2,
+        x_rotation: f32,
+        large_arc: bool,
+        sweep: bool,
+        to: Vec2,
+    ) -> Self {
+        self.ensure_started();
+        let arc = SvgArc {
+            from: point(self.current.x, self.current.y),
+            to: point(to.x, to.y),
+            radii: vector(radii.x, radii.y),
+            x_rotation: Angle::radians(x_rotation),
+            flags: ArcFlags { large_arc, sweep },
+        };
+
+        if arc.is_straight_line() {
+            return self.line_to(to);
+        }
+
+        arc.for_each_cubic_bezier(&mut |segment| self.push_cubic(segment));
+        self.current = to;
+        self
+    }
+
+    // Elliptical arc around a center point. Draws a line from the current
+    // point to the start of the arc if a sub path is already in progress,
+    // otherwise starts a new sub path at the start of the arc.
+    pub fn arc(mut self, center: Vec2, radii: Vec2, start_angle: f32, sweep_angle: f32) -> Self {
+        let arc = Arc {
+            center: point(center.x, center.y),
+            radii: vector(radii.x, radii.y),
+            start_angle: Angle::radians(start_angle),
+            sweep_angle: Angle::radians(sweep_angle),
+            x_rotation: Angle::radians(0.0),
+        };
+
+        let from = arc.from();
+        let from = vec2(from.x, from.y);
+        self = if self.start.is_none() || self.needs_move {
+            self.move_to(from)
+        } else if self.current != from {
+            self.line_to(from)
+        } else {
+            self
+        };
+
+        arc.for_each_cubic_bezier(&mut |segment| self.push_cubic(segment));
+        let to = arc.to();
+        self.current = vec2(to.x, to.y);
+        self
+    }
+
+    // Ends the current sub path. The next drawing command continues from the
+    // start of the closed sub path.
+    pub fn close(mut self) -> Self {
+        self.current = self.subpath_start;
+        self.needs_move = true;
+        self
+    }
+
+    pub fn rect(self, top_left: Vec2, size: Vec2) -> Self {
+        self.move_to(top_left)
+            .line_to(top_left + vec2(size.x, 0.))
+            .line_to(top_left + size)
+            .line_to(top_left + vec2(0., size.y))
+            .close()
+    }
+
+    pub fn rounded_rect(self, top_left: Vec2, size: Vec2, corner_radius: f32) -> Self {
+        let radius = corner_radius.min(size.x / 2.).min(size.y / 2.).max(0.);
+        if radius == 0. {
+            return self.rect(top_left, size);
+        }
+
+        let radii = Vec2::splat(radius);
+        let bottom_right = top_left + size;
+        self.move_to(top_left + vec2(radius, 0.))
+            .arc(
+                vec2(bottom_right.x - radius, top_left.y + radius),
+                radii,
+                -FRAC_PI_2,
+                FRAC_PI_2,
+            )
+            .arc(bottom_right - radii, radii, 0., FRAC_PI_2)
+            .arc(
+                vec2(top_left.x + radius, bottom_right.y - radius),
+                radii,
+                FRAC_PI_2,
+                FRAC_PI_2,
+            )
+            .arc(top_left + radii, radii, 2. * FRAC_PI_2, FRAC_PI_2)
+            .close()
+    }
+
+    pub fn circle(self, center: Vec2, radius: f32) -> Self {
+        self.ellipse(center, Vec2::splat(radius))
+    }
+
+    pub fn ellipse(self, center: Vec2, radii: Vec2) -> Self {
+        self.move_to(center + vec2(radii.x, 0.))
+            .arc(center, radii, 0., TAU)
+            .close()
+    }
+
+    pub fn build(self) -> Path {
+        let mut path = Path::new(self.start.unwrap_or(Vec2::ZERO));
+        path.commands = self.commands;
+        path
+    }
+
+    fn ensure_started(&mut self) {
+        if self.start.is_none() {
+            self.start = Some(self.current);
+            self.subpath_start = self.current;
+        } else if self.needs_move {
+            self.commands.push(PathCommand::MoveTo {
+                start: self.current,
+            });
+            self.subpath_start = self.current;
+        }
+        self.needs_move = false;
+    }
+
+    fn push_cubic(&mut self, segment: &CubicBezierSegment<f32>) {
+        self.commands.push(PathCommand::CubicBezierTo {
+            control1: vec2(segment.ctrl1.x, segment.ctrl1.y),
+            control2: vec2(segment.ctrl2.x, segment.ctrl2.y),
+            to: vec2(segment.to.x, segment.to.y),
+        });
+    }
+}
+
+impl Path {
+    pub fn builder() -> PathBuilder {
+        PathBuilder::new()
+    }
+}
@@ -0,0 +1,49 @@
+use glam::{Mat2, Vec2};
+use serde::{Deserialize, Serialize};
+
+// Repeating image fill. The transform and offset map positions relative to
+// the top left of the filled primitive into pixels of the image, which is
+// then tiled infinitely in both directions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pattern {
+    pub image: String,
+    #[serde(default)]
+    pub transform: Mat2,
+    #[serde(default)]
+    pub offset: Vec2,
+}
+
+impl Pattern {
+    pub fn new(image: String) -> Self {
+        Self {
+            image,
+            transform: Mat2::IDENTITY,
+            offset: Vec2::ZERO,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Mat2) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    // Draws the pattern tiles `scale` times larger
+    pub fn with_scale(mut self, scale: Vec2) -> Self {
+        self.transform *= Mat2::from_diagonal(1.0 / scale);
+        self
+    }
+
+    pub fn with_rotation(mut self, angle: f32) -> Self {
+        self.transform *= Mat2::from_angle(-angle);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn to_pattern_space(&self, local_position: Vec2) -> Vec2 {
+        self.transform * local_position + self.offset
+    }
+}
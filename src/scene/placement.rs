@@ -0,0 +1,143 @@
+use glam::{vec2, Vec2, Vec4, Vec4Swizzles};
+use serde::{Deserialize, Serialize};
+
+use super::{Layer, Scene};
+
+// Corner, edge center or center of a rect, as seen on the surface
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // Position of the anchor as a fraction of the rect size, measured from
+    // the top left
+    pub fn fraction(&self) -> Vec2 {
+        match self {
+            Anchor::TopLeft => vec2(0.0, 0.0),
+            Anchor::Top => vec2(0.5, 0.0),
+            Anchor::TopRight => vec2(1.0, 0.0),
+            Anchor::Left => vec2(0.0, 0.5),
+            Anchor::Center => vec2(0.5, 0.5),
+            Anchor::Right => vec2(1.0, 0.5),
+            Anchor::BottomLeft => vec2(0.0, 1.0),
+            Anchor::Bottom => vec2(0.5, 1.0),
+            Anchor::BottomRight => vec2(1.0, 1.0),
+        }
+    }
+}
+
+// Positions a primitive relative to the rect of its layer, which is the part
+// of the layer clip visible on the surface. Resolved when the scene is
+// prepared for rendering, so that the primitive follows the layer when the
+// surface is resized. The fractions are measured from the top left of the
+// rect as seen on the surface, whichever way the y axis points. Texts have no
+// size before shaping, so their baseline start is placed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    // Point of the layer rect as a fraction of its size
+    pub anchor: Vec2,
+    // Point of the primitive put at the anchor as a fraction of its size
+    #[serde(default)]
+    pub pivot: Vec2,
+    // Moves the primitive from the anchor in scene units, with y pointing
+    // down the surface like the fractions
+    #[serde(default)]
+    pub offset: Vec2,
+    // Size of the primitive as a fraction of the layer rect. The primitive
+    // keeps its own size when unset.
+    #[serde(default)]
+    pub relative_size: Option<Vec2>,
+}
+
+impl Placement {
+    // Puts the anchor point of the primitive at the same point of the layer,
+    // so that for example a primitive anchored to the bottom right stays in
+    // the bottom right corner
+    pub fn anchored(anchor: Anchor, offset: Vec2) -> Self {
+        Self {
+            anchor: anchor.fraction(),
+            pivot: anchor.fraction(),
+            offset,
+            relative_size: None,
+        }
+    }
+
+    // Puts the top left of the primitive at a percentage of the layer size
+    pub fn percent(x: f32, y: f32) -> Self {
+        Self {
+            anchor: vec2(x, y) / 100.0,
+            pivot: Vec2::ZERO,
+            offset: Vec2::ZERO,
+            relative_size: None,
+        }
+    }
+
+    pub fn with_pivot(mut self, pivot: Anchor) -> Self {
+        self.pivot = pivot.fraction();
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    // Sizes the primitive to a percentage of the layer size
+    pub fn with_percent_size(mut self, width: f32, height: f32) -> Self {
+        self.relative_size = Some(vec2(width, height) / 100.0);
+        self
+    }
+
+    // Top left and size of a primitive of the given size placed in the rect
+    pub fn resolve(&self, rect: Vec4, size: Vec2, y_direction: f32) -> (Vec2, Vec2) {
+        let size = self
+            .relative_size
+            .map_or(size, |relative| relative * rect.zw());
+        // Flips a fraction measured from the top of the surface when the y
+        // axis points up
+        let orient = |fraction: Vec2| vec2(fraction.x, 0.5 - (0.5 - fraction.y) * y_direction);
+        let anchor = rect.xy() + orient(self.anchor) * rect.zw();
+        let top_left = anchor + self.offset * vec2(1.0, y_direction) - orient(self.pivot) * size;
+        (top_left, size)
+    }
+}
+
+impl Layer {
+    // Positions the placed primitives of the layer in the rect
+    pub fn resolve_placements(&mut self, rect: Vec4, y_direction: f32) {
+        for quad in self.quads.iter_mut() {
+            quad.resolve_placement(rect, y_direction);
+        }
+        for sprite in self.sprites.iter_mut() {
+            if let Some(placement) = sprite.placement {
+                (sprite.top_left, sprite.size) = placement.resolve(rect, sprite.size, y_direction);
+            }
+        }
+        for text in self.texts.iter_mut() {
+            if let Some(placement) = text.placement {
+                (text.bottom_left, _) = placement.resolve(rect, Vec2::ZERO, y_direction);
+            }
+        }
+    }
+
+    pub fn has_placements(&self) -> bool {
+        self.quads.iter().any(|quad| quad.placement().is_some())
+            || self.sprites.iter().any(|sprite| sprite.placement.is_some())
+            || self.texts.iter().any(|text| text.placement.is_some())
+    }
+}
+
+impl Scene {
+    pub fn has_placements(&self) -> bool {
+        self.layers.iter().any(Layer::has_placements)
+    }
+}
@@ -0,0 +1,234 @@
+use glam::{vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
+use shader::{
+    InstancedGradientStop, InstancedProcedural, PROCEDURAL_CHECKER, PROCEDURAL_CONIC_GRADIENT,
+    PROCEDURAL_GRID, PROCEDURAL_LINEAR_GRADIENT, PROCEDURAL_NOISE, PROCEDURAL_RADIAL_GRADIENT,
+};
+
+use super::{
+    animation::{fade_color, interpolate_color, snap},
+    theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue},
+    Interpolate,
+};
+
+// Built in functions which can fill a procedural rect. Each produces a value
+// between 0 and 1 used to blend from the primary to the secondary color.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ProceduralKind {
+    // Angle in radians of the gradient direction. Zero goes from left to right.
+    LinearGradient {
+        angle: f32,
+    },
+    // Elliptical gradient from the center out to the edges of the rect
+    RadialGradient,
+    Checker {
+        cell_size: f32,
+    },
+    Grid {
+        cell_size: f32,
+        line_width: f32,
+    },
+    // Fractal gradient noise. The speed animates the noise over time.
+    Noise {
+        feature_size: f32,
+        octaves: u32,
+        #[serde(default)]
+        speed: f32,
+    },
+    // Gradient around a center, such as for color wheels and circular
+    // progress indicators. It starts at the start angle, which is zero to the
+    // right, and goes in the direction of increasing angles over the sweep,
+    // or the other way when it's negative. Outside of a partial sweep the
+    // secondary color is used. The offset moves the center away from the
+    // center of the rect.
+    ConicGradient {
+        start_angle: f32,
+        sweep: f32,
+        #[serde(default)]
+        center_offset: Vec2,
+    },
+}
+
+impl ProceduralKind {
+    // Kind and parameters of the function in the shaders
+    pub(crate) fn to_shader(self) -> (u32, Vec4) {
+        match self {
+            ProceduralKind::LinearGradient { angle } => {
+                (PROCEDURAL_LINEAR_GRADIENT, vec4(angle, 0., 0., 0.))
+            }
+            ProceduralKind::RadialGradient => (PROCEDURAL_RADIAL_GRADIENT, Vec4::ZERO),
+            ProceduralKind::Checker { cell_size } => {
+                (PROCEDURAL_CHECKER, vec4(cell_size, 0., 0., 0.))
+            }
+            ProceduralKind::Grid {
+                cell_size,
+                line_width,
+            } => (PROCEDURAL_GRID, vec4(cell_size, line_width, 0., 0.)),
+            ProceduralKind::Noise {
+                feature_size,
+                octaves,
+                speed,
+            } => (
+                PROCEDURAL_NOISE,
+                vec4(feature_size, octaves as f32, speed, 0.),
+            ),
+            ProceduralKind::ConicGradient {
+                start_angle,
+                sweep,
+                center_offset,
+            } => (
+                PROCEDURAL_CONIC_GRADIENT,
+                vec4(start_angle, sweep, center_offset.x, center_offset.y),
+            ),
+        }
+    }
+}
+
+// Most stops a single procedural can have
+pub const MAX_GRADIENT_STOPS: usize = 1024;
+
+// Color at an offset between 0 and 1 along the value of a procedural function
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: Vec4,
+}
+
+impl GradientStop {
+    pub fn new(offset: f32, color: Vec4) -> Self {
+        Self { offset, color }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Procedural {
+    pub top_left: Vec2,
+    pub size: Vec2,
+    pub kind: ProceduralKind,
+    pub primary_color: Vec4,
+    pub secondary_color: Vec4,
+    // Colors picked by the value of the function instead of blending from the
+    // primary to the secondary color, sorted by their offsets. For the stops
+    // of imported SVG gradients and the color ramps of heatmaps.
+    #[serde(default)]
+    pub stops: Vec<GradientStop>,
+    // Opaque user data reported by hit testing. Ignored by rendering.
+    #[serde(default)]
+    pub tag: Option<u64>,
+    // Theme variables replacing the values of fields when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    pub theme: ThemeBindings,
+}
+
+impl Procedural {
+    pub fn new(
+        kind: ProceduralKind,
+        top_left: Vec2,
+        size: Vec2,
+        primary_color: Vec4,
+        secondary_color: Vec4,
+    ) -> Self {
+        Self {
+            top_left,
+            size,
+            kind,
+            primary_color,
+            secondary_color,
+            stops: Vec::new(),
+            tag: None,
+            theme: ThemeBindings::new(),
+        }
+    }
+
+    pub fn with_stops(mut self, stops: Vec<GradientStop>) -> Self {
+        self.stops = stops;
+        self
+    }
+
+    pub fn with_tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| match (field, value) {
+            (ThemeField::PrimaryColor, ThemeValue::Color(color)) => self.primary_color = color,
+            (ThemeField::SecondaryColor, ThemeValue::Color(color)) => self.secondary_color = color,
+            _ => {}
+        });
+        self.theme = bindings;
+    }
+
+    pub fn bounds(&self) -> Vec4 {
+        vec4(self.top_left.x, self.top_left.y, self.size.x, self.size.y)
+    }
+
+    pub fn to_instanced(&self) -> InstancedProcedural {
+        let (kind, parameters) = self.kind.to_shader();
+        InstancedProcedural {
+            primary_color: self.primary_color,
+            secondary_color: self.secondary_color,
+            parameters,
+            top_left: self.top_left,
+            size: self.size,
+            kind,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn instanced_stops(&self) -> impl Iterator<Item = InstancedGradientStop> + '_ {
+        self.stops.iter().map(|stop| InstancedGradientStop {
+            color: stop.color,
+            offset: stop.offset,
+            ..Default::default()
+        })
+    }
+}
+
+impl Interpolate for Procedural {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Procedural {
+            top_left: self.top_left.lerp(to.top_left, t),
+            size: self.size.lerp(to.size, t),
+            kind: *snap(&self.kind, &to.kind, t),
+            primary_color: interpolate_color(self.primary_color, to.primary_color, t),
+            secondary_color: interpolate_color(self.secondary_color, to.secondary_color, t),
+            stops: if self.stops.len() == to.stops.len() {
+                self.stops
+                    .iter()
+                    .zip(to.stops.iter())
+                    .map(|(from, to)| GradientStop {
+                        offset: from.offset + (to.offset - from.offset) * t,
+                        color: interpolate_color(from.color, to.color, t),
+                    })
+                    .collect()
+            } else {
+                snap(&self.stops, &to.stops, t).clone()
+            },
+            tag: *snap(&self.tag, &to.tag, t),
+            theme: snap(&self.theme, &to.theme, t).clone(),
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Procedural {
+            primary_color: fade_color(self.primary_color, opacity),
+            secondary_color: fade_color(self.secondary_color, opacity),
+            stops: self
+                .stops
+                .iter()
+                .map(|stop| GradientStop {
+                    color: fade_color(stop.color, opacity),
+                    ..*stop
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
@@ -1,8 +1,16 @@
-use glam::{Vec2, Vec4};
-use serde::Deserialize;
+use glam::{vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
 use shader::InstancedQuad;
 
-#[derive(Deserialize, Debug, Clone)]
+use super::{
+    animation::{fade_color, interpolate_color, interpolate_option, snap},
+    elevation_shadows,
+    theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue},
+    validation::Validator,
+    Interpolate, Outline, Pattern, PixelSnap, Placement, Shadow,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Quad {
     top_left: Vec2,
     size: Vec2,
@@ -11,6 +19,23 @@ pub struct Quad {
     corner_radius: f32,
     #[serde(default)]
     blur: f32,
+    #[serde(default)]
+    pattern: Option<Pattern>,
+    // Logical pixels the quad is raised by, drawn as shadows beneath it
+    #[serde(default)]
+    elevation: f32,
+    #[serde(default)]
+    outline: Option<Outline>,
+    #[serde(default)]
+    tag: Option<u64>,
+    // Positions the quad relative to its layer when set
+    #[serde(default)]
+    placement: Option<Placement>,
+    #[serde(default)]
+    snap: PixelSnap,
+    // Theme variables replacing the values of fields when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    theme: ThemeBindings,
 }
 
 impl Quad {
@@ -21,6 +46,13 @@ impl Quad {
             color,
             corner_radius: 0.0,
             blur: 0.0,
+            pattern: None,
+            elevation: 0.0,
+            outline: None,
+            tag: None,
+            placement: None,
+            snap: PixelSnap::None,
+            theme: ThemeBindings::new(),
         }
     }
 
@@ -39,14 +71,202 @@ impl Quad {
         self
     }
 
+    pub fn has_background_blur(&self) -> bool {
+        self.blur < 0.0
+    }
+
+    pub fn with_elevation(mut self, elevation: f32) -> Self {
+        self.elevation = elevation;
+        self
+    }
+
+    pub fn elevation(&self) -> f32 {
+        self.elevation
+    }
+
+    // The shadows of the elevation, which fade along with the color of the
+    // quad
+    pub fn elevation_shadows(&self, y_direction: f32) -> Vec<Shadow> {
+        if self.elevation <= 0.0 {
+            return Vec::new();
+        }
+        elevation_shadows(
+            self.top_left,
+            self.size,
+            self.corner_radius,
+            self.elevation,
+            self.color.w,
+            y_direction,
+        )
+        .to_vec()
+    }
+
+    pub fn with_outline(mut self, outline: Outline) -> Self {
+        self.outline = Some(outline);
+        self
+    }
+
+    pub fn outline(&self) -> Option<&Outline> {
+        self.outline.as_ref()
+    }
+
+    // Rect covered by the quad including any external blur, outline and the
+    // shadows of its elevation
+    pub fn bounds(&self) -> Vec4 {
+        let outline_extent = self.outline.map_or(0.0, |outline| outline.extent());
+        let blur_extension = (self.blur.max(0.0) * 3.0)
+            .max(self.elevation.max(0.0) * 3.0)
+            .max(outline_extent);
+        vec4(
+            self.top_left.x - blur_extension,
+            self.top_left.y - blur_extension,
+            self.size.x + blur_extension * 2.0,
+            self.size.y + blur_extension * 2.0,
+        )
+    }
+
+    pub fn with_pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    pub fn pattern(&self) -> Option<&Pattern> {
+        self.pattern.as_ref()
+    }
+
+    // Opaque user data reported by hit testing. Ignored by rendering.
+    pub fn with_tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        self.placement = Some(placement);
+        self
+    }
+
+    pub fn placement(&self) -> Option<&Placement> {
+        self.placement.as_ref()
+    }
+
+    pub fn with_snap(mut self, snap: PixelSnap) -> Self {
+        self.snap = snap;
+        self
+    }
+
+    pub fn snap(&self) -> PixelSnap {
+        self.snap
+    }
+
+    pub(crate) fn resolve_placement(&mut self, rect: Vec4, y_direction: f32) {
+        if let Some(placement) = self.placement {
+            (self.top_left, self.size) = placement.resolve(rect, self.size, y_direction);
+        }
+    }
+
+    pub fn has_theme_variables(&self) -> bool {
+        !self.theme.is_empty()
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| match (field, value) {
+            (ThemeField::Color, ThemeValue::Color(color)) => self.color = color,
+            (ThemeField::CornerRadius, ThemeValue::Number(radius)) => self.corner_radius = radius,
+            (ThemeField::Blur, ThemeValue::Number(blur)) => self.blur = blur,
+            (ThemeField::Elevation, ThemeValue::Number(elevation)) => self.elevation = elevation,
+            _ => {}
+        });
+        self.theme = bindings;
+    }
+
+    pub fn tag(&self) -> Option<u64> {
+        self.tag
+    }
+
+    // Whether the point is inside the quad, taking the rounded corners into
+    // account
+    pub fn contains(&self, point: Vec2) -> bool {
+        let corner_radius = self.corner_radius.min(self.size.min_element() / 2.0);
+        let half_size = self.size / 2.0 - Vec2::splat(corner_radius);
+        let relative_point = point - (self.top_left + self.size / 2.0);
+        let d = relative_point.abs() - half_size;
+        d.max(Vec2::ZERO).length() + d.max_element().min(0.0) - corner_radius <= 0.0
+    }
+
+    // A negative blur is a background blur, so only its finiteness is checked
+    pub(crate) fn validate_into(&self, validator: &mut Validator, path: &str) {
+        validator.point(&format!("{path}.top_left"), self.top_left);
+        validator.size(&format!("{path}.size"), self.size);
+        validator.color(&format!("{path}.color"), self.color);
+        validator.non_negative(&format!("{path}.corner_radius"), self.corner_radius);
+        validator.finite(&format!("{path}.blur"), self.blur);
+        validator.non_negative(&format!("{path}.elevation"), self.elevation);
+        if let Some(outline) = &self.outline {
+            outline.validate_into(validator, &format!("{path}.outline"));
+        }
+        if let Some(pattern) = &self.pattern {
+            validator.pattern(&format!("{path}.pattern"), pattern);
+        }
+    }
+
     pub fn to_instanced(&self) -> InstancedQuad {
+        let outline = self.outline.unwrap_or(Outline::new(Vec4::ZERO, 0.0));
         InstancedQuad {
             top_left: self.top_left,
             size: self.size,
             color: self.color,
             corner_radius: self.corner_radius,
             blur: self.blur,
+            snap: self.snap.to_shader(),
+            pattern_transform: self
+                .pattern
+                .as_ref()
+                .map(|pattern| Vec4::from_array(pattern.transform.to_cols_array()))
+                .unwrap_or_default(),
+            pattern_offset: self
+                .pattern
+                .as_ref()
+                .map(|pattern| pattern.offset)
+                .unwrap_or_default(),
+            outline_color: outline.color,
+            outline_width: outline.width,
+            outline_softness: outline.softness,
             ..Default::default()
         }
     }
 }
+
+impl Interpolate for Quad {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Quad {
+            top_left: self.top_left.lerp(to.top_left, t),
+            size: self.size.lerp(to.size, t),
+            color: interpolate_color(self.color, to.color, t),
+            corner_radius: self.corner_radius + (to.corner_radius - self.corner_radius) * t,
+            blur: self.blur + (to.blur - self.blur) * t,
+            pattern: snap(&self.pattern, &to.pattern, t).clone(),
+            elevation: self.elevation + (to.elevation - self.elevation) * t,
+            outline: interpolate_option(&self.outline, &to.outline, t, |from, to| {
+                from.interpolate(to, t)
+            }),
+            tag: *snap(&self.tag, &to.tag, t),
+            placement: *snap(&self.placement, &to.placement, t),
+            snap: *snap(&self.snap, &to.snap, t),
+            theme: snap(&self.theme, &to.theme, t).clone(),
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Quad {
+            color: fade_color(self.color, opacity),
+            outline: self.outline.map(|outline| outline.fade(opacity)),
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use glam::{vec2, vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    animation::{fade_color, interpolate_color},
+    Interpolate,
+};
+
+// Opacity of the shadows of elevated rects, before the alpha of the rect
+const AMBIENT_SHADOW_ALPHA: f32 = 0.12;
+const KEY_SHADOW_ALPHA: f32 = 0.2;
+
+// Blurred shadow of a rounded rect without the rect itself, such as beneath
+// panels drawn by a later layer or by the quads of the same layer. The spread
+// grows the rect before blurring and the offset moves the shadow away from
+// the rect. The shadows of a layer are drawn after its background and before
+// its quads. Identical shadows share a cached blurred mask.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Shadow {
+    pub top_left: Vec2,
+    pub size: Vec2,
+    pub blur: f32,
+    pub color: Vec4,
+    #[serde(default)]
+    pub corner_radius: f32,
+    #[serde(default)]
+    pub spread: f32,
+    #[serde(default)]
+    pub offset: Vec2,
+}
+
+impl Shadow {
+    pub fn new(top_left: Vec2, size: Vec2, blur: f32, color: Vec4) -> Self {
+        Self {
+            top_left,
+            size,
+            blur,
+            color,
+            corner_radius: 0.0,
+            spread: 0.0,
+            offset: Vec2::ZERO,
+        }
+    }
+
+    pub fn with_corner_radius(mut self, corner_radius: f32) -> Self {
+        self.corner_radius = corner_radius;
+        self
+    }
+
+    pub fn with_spread(mut self, spread: f32) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    // The rect casting the shadow, moved by the offset and grown by the
+    // spread
+    pub fn shape(&self) -> Vec4 {
+        let top_left = self.top_left + self.offset - Vec2::splat(self.spread);
+        let size = (self.size + Vec2::splat(self.spread * 2.0)).max(Vec2::ZERO);
+        vec4(top_left.x, top_left.y, size.x, size.y)
+    }
+
+    // Corner radius of the shape, which grows along with it
+    pub fn shape_corner_radius(&self) -> f32 {
+        (self.corner_radius + self.spread).max(0.0)
+    }
+
+    // Rect covered by the blurred shape
+    pub fn bounds(&self) -> Vec4 {
+        let shape = self.shape();
+        let margin = self.blur.max(0.0) * 3.0;
+        vec4(
+            shape.x - margin,
+            shape.y - margin,
+            shape.z + margin * 2.0,
+            shape.w + margin * 2.0,
+        )
+    }
+}
+
+// The standard shadows of a rect raised to the elevation in logical pixels
+// above what is beneath it, in the style of material design: a soft ambient
+// shadow all around it and a sharper key shadow cast downwards by a light
+// above. Both grow softer and the key shadow moves further down as the
+// elevation grows. Down is towards the bottom of the surface, which is
+// negative y when the y axis points up.
+pub fn elevation_shadows(
+    top_left: Vec2,
+    size: Vec2,
+    corner_radius: f32,
+    elevation: f32,
+    alpha: f32,
+    y_direction: f32,
+) -> [Shadow; 2] {
+    let ambient = Shadow::new(
+        top_left,
+        size,
+        elevation,
+        vec4(0., 0., 0., AMBIENT_SHADOW_ALPHA * alpha),
+    )
+    .with_corner_radius(corner_radius);
+    let key = Shadow::new(
+        top_left,
+        size,
+        elevation * 0.5,
+        vec4(0., 0., 0., KEY_SHADOW_ALPHA * alpha),
+    )
+    .with_corner_radius(corner_radius)
+    .with_offset(vec2(0., elevation * 0.5 * y_direction));
+    [ambient, key]
+}
+
+impl Interpolate for Shadow {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Shadow {
+            top_left: self.top_left.lerp(to.top_left, t),
+            size: self.size.lerp(to.size, t),
+            blur: self.blur + (to.blur - self.blur) * t,
+            color: interpolate_color(self.color, to.color, t),
+            corner_radius: self.corner_radius + (to.corner_radius - self.corner_radius) * t,
+            spread: self.spread + (to.spread - self.spread) * t,
+            offset: self.offset.lerp(to.offset, t),
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Shadow {
+            color: fade_color(self.color, opacity),
+            ..self.clone()
+        }
+    }
+}
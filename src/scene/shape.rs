@@ -0,0 +1,331 @@
+use glam::{vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
+use shader::{
+    InstancedShape, SHAPE_ARC, SHAPE_PAINT_COLOR, SHAPE_PIE, SHAPE_POLYGON, SHAPE_RING,
+    SHAPE_SQUIRCLE, SHAPE_STAR,
+};
+
+use super::{
+    animation::{fade_color, interpolate_color, snap},
+    theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue},
+    Interpolate, ProceduralKind,
+};
+
+// Shapes evaluated analytically in the shaders, which stay antialiased at
+// any size and while animating without being tessellated. Every shape fits
+// in the circle of the radius of the primitive. Angles are in radians,
+// starting at positive x and growing towards positive y.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ShapeKind {
+    // Regular polygon with its corners on the circle and one of them towards
+    // negative y
+    Polygon {
+        sides: u32,
+    },
+    // Points on the circle with the corners between them at the inner radius,
+    // as a fraction of the radius
+    Star {
+        points: u32,
+        inner_radius: f32,
+    },
+    // Band of the thickness along the inside of the circle from the start
+    // angle over the sweep, with round ends
+    Arc {
+        start_angle: f32,
+        sweep: f32,
+        thickness: f32,
+    },
+    // Slice of the disc from the start angle over the sweep
+    Pie {
+        start_angle: f32,
+        sweep: f32,
+    },
+    // Superellipse filling the square around the circle, from a circle at an
+    // exponent of 2 to a square as the exponent grows
+    Squircle {
+        exponent: f32,
+    },
+    // Band between the inner radius and the radius of the shape from the
+    // start angle to the end angle, such as the track and the indicator of a
+    // progress spinner. A full turn or more between the angles is a closed
+    // ring.
+    Ring {
+        inner_radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        #[serde(default)]
+        cap: RingCap,
+    },
+}
+
+// How the ends of a ring which isn't closed look
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RingCap {
+    // Cut off along the lines from the center at the angles
+    #[default]
+    Butt,
+    // Half circles reaching past the angles
+    Round,
+}
+
+// Fills a shape instead of its color by blending from the color to the
+// secondary color. The function is evaluated in the square around the shape
+// and turns along with it, so the angles of a conic gradient match the
+// angles of a ring.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ShapePaint {
+    pub kind: ProceduralKind,
+    pub secondary_color: Vec4,
+}
+
+impl ShapeKind {
+    // Kind and parameters of the shape in the shaders
+    pub(crate) fn to_shader(self) -> (u32, Vec4) {
+        match self {
+            ShapeKind::Polygon { sides } => (SHAPE_POLYGON, vec4(sides as f32, 0., 0., 0.)),
+            ShapeKind::Star {
+                points,
+                inner_radius,
+            } => (SHAPE_STAR, vec4(points as f32, inner_radius, 0., 0.)),
+            ShapeKind::Arc {
+                start_angle,
+                sweep,
+                thickness,
+            } => (SHAPE_ARC, vec4(start_angle, sweep, thickness, 0.)),
+            ShapeKind::Pie { start_angle, sweep } => (SHAPE_PIE, vec4(start_angle, sweep, 0., 0.)),
+            ShapeKind::Squircle { exponent } => (SHAPE_SQUIRCLE, vec4(exponent, 0., 0., 0.)),
+            ShapeKind::Ring {
+                inner_radius,
+                start_angle,
+                end_angle,
+                cap,
+            } => (
+                SHAPE_RING,
+                vec4(
+                    start_angle,
+                    end_angle - start_angle,
+                    inner_radius,
+                    if cap == RingCap::Round { 1. } else { 0. },
+                ),
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Shape {
+    pub center: Vec2,
+    pub radius: f32,
+    pub kind: ShapeKind,
+    pub color: Vec4,
+    // Radians the shape is turned by around its center
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default)]
+    pub paint: Option<ShapePaint>,
+    // Opaque user data reported by hit testing. Ignored by rendering.
+    #[serde(default)]
+    pub tag: Option<u64>,
+    // Theme variables replacing the values of fields when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    pub theme: ThemeBindings,
+}
+
+impl Shape {
+    pub fn new(kind: ShapeKind, center: Vec2, radius: f32, color: Vec4) -> Self {
+        Self {
+            center,
+            radius,
+            kind,
+            color,
+            rotation: 0.0,
+            paint: None,
+            tag: None,
+            theme: ThemeBindings::new(),
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_paint(mut self, kind: ProceduralKind, secondary_color: Vec4) -> Self {
+        self.paint = Some(ShapePaint {
+            kind,
+            secondary_color,
+        });
+        self
+    }
+
+    pub fn with_tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| match (field, value) {
+            (ThemeField::Color, ThemeValue::Color(color)) => self.color = color,
+            (ThemeField::SecondaryColor, ThemeValue::Color(color)) => {
+                if let Some(paint) = self.paint.as_mut() {
+                    paint.secondary_color = color;
+                }
+            }
+            _ => {}
+        });
+        self.theme = bindings;
+    }
+
+    // The square around the circle the shape fits in
+    pub fn bounds(&self) -> Vec4 {
+        vec4(
+            self.center.x - self.radius,
+            self.center.y - self.radius,
+            self.radius * 2.0,
+            self.radius * 2.0,
+        )
+    }
+
+    // Whether the point is inside the shape itself rather than just its
+    // bounds
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.to_instanced().distance(point) <= 0.0
+    }
+
+    pub fn to_instanced(&self) -> InstancedShape {
+        let (kind, parameters) = self.kind.to_shader();
+        let (paint_kind, paint_parameters, secondary_color) = match self.paint {
+            Some(paint) => {
+                let (kind, parameters) = paint.kind.to_shader();
+                (kind, parameters, paint.secondary_color)
+            }
+            None => (SHAPE_PAINT_COLOR, Vec4::ZERO, Vec4::ZERO),
+        };
+        InstancedShape {
+            color: self.color,
+            parameters,
+            secondary_color,
+            paint_parameters,
+            center: self.center,
+            radius: self.radius,
+            rotation: self.rotation,
+            kind,
+            paint_kind,
+            ..Default::default()
+        }
+    }
+}
+
+impl Interpolate for Shape {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        let snapped = snap(self, to, t);
+        Shape {
+            center: self.center.lerp(to.center, t),
+            radius: self.radius + (to.radius - self.radius) * t,
+            kind: interpolate_kind(self.kind, to.kind, t).unwrap_or(snapped.kind),
+            color: interpolate_color(self.color, to.color, t),
+            rotation: self.rotation + (to.rotation - self.rotation) * t,
+            paint: match (self.paint, to.paint) {
+                (Some(from), Some(to)) => Some(ShapePaint {
+                    secondary_color: interpolate_color(from.secondary_color, to.secondary_color, t),
+                    ..*snap(&from, &to, t)
+                }),
+                _ => snapped.paint,
+            },
+            ..snapped.clone()
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Shape {
+            color: fade_color(self.color, opacity),
+            paint: self.paint.map(|paint| ShapePaint {
+                secondary_color: fade_color(paint.secondary_color, opacity),
+                ..paint
+            }),
+            ..self.clone()
+        }
+    }
+}
+
+// Shapes of the same kind morph by their parameters, so that for example an
+// arc can sweep around as a progress indicator. Counts of sides and points
+// snap.
+fn interpolate_kind(from: ShapeKind, to: ShapeKind, t: f32) -> Option<ShapeKind> {
+    let lerp = |from: f32, to: f32| from + (to - from) * t;
+    Some(match (from, to) {
+        (
+            ShapeKind::Star {
+                points,
+                inner_radius,
+            },
+            ShapeKind::Star {
+                points: to_points,
+                inner_radius: to_inner_radius,
+            },
+        ) => ShapeKind::Star {
+            points: *snap(&points, &to_points, t),
+            inner_radius: lerp(inner_radius, to_inner_radius),
+        },
+        (
+            ShapeKind::Arc {
+                start_angle,
+                sweep,
+                thickness,
+            },
+            ShapeKind::Arc {
+                start_angle: to_start_angle,
+                sweep: to_sweep,
+                thickness: to_thickness,
+            },
+        ) => ShapeKind::Arc {
+            start_angle: lerp(start_angle, to_start_angle),
+            sweep: lerp(sweep, to_sweep),
+            thickness: lerp(thickness, to_thickness),
+        },
+        (
+            ShapeKind::Pie { start_angle, sweep },
+            ShapeKind::Pie {
+                start_angle: to_start_angle,
+                sweep: to_sweep,
+            },
+        ) => ShapeKind::Pie {
+            start_angle: lerp(start_angle, to_start_angle),
+            sweep: lerp(sweep, to_sweep),
+        },
+        (
+            ShapeKind::Squircle { exponent },
+            ShapeKind::Squircle {
+                exponent: to_exponent,
+            },
+        ) => ShapeKind::Squircle {
+            exponent: lerp(exponent, to_exponent),
+        },
+        (
+            ShapeKind::Ring {
+                inner_radius,
+                start_angle,
+                end_angle,
+                cap,
+            },
+            ShapeKind::Ring {
+                inner_radius: to_inner_radius,
+                start_angle: to_start_angle,
+                end_angle: to_end_angle,
+                cap: to_cap,
+            },
+        ) => ShapeKind::Ring {
+            inner_radius: lerp(inner_radius, to_inner_radius),
+            start_angle: lerp(start_angle, to_start_angle),
+            end_angle: lerp(end_angle, to_end_angle),
+            cap: *snap(&cap, &to_cap, t),
+        },
+        _ => return None,
+    })
+}
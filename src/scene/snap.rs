@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use shader::{PIXEL_SNAP_NONE, PIXEL_SNAP_ROUND, PIXEL_SNAP_ROUND_HALF};
+
+// How a primitive is aligned to the pixel grid of the surface when drawn.
+// Snapping keeps hairlines and one pixel borders from blurring across two
+// pixels at fractional positions, while leaving the positions in the scene
+// as they are, so animations stay smooth.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelSnap {
+    #[default]
+    None,
+    // Rounds each edge to the nearest pixel boundary
+    Round,
+    // Rounds the size to whole pixels and the position so that the edges are
+    // on pixel boundaries, keeping the size the same while moving
+    RoundHalf,
+}
+
+impl PixelSnap {
+    pub(crate) fn to_shader(self) -> u32 {
+        match self {
+            PixelSnap::None => PIXEL_SNAP_NONE,
+            PixelSnap::Round => PIXEL_SNAP_ROUND,
+            PixelSnap::RoundHalf => PIXEL_SNAP_ROUND_HALF,
+        }
+    }
+}
@@ -1,12 +1,29 @@
-use glam::{Vec2, Vec4};
-use serde::Deserialize;
+use glam::{vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, Clone)]
+use super::{
+    animation::{fade_color, interpolate_color, snap},
+    theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue},
+    Interpolate, PixelSnap, Placement,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Sprite {
     pub top_left: Vec2,
     pub size: Vec2,
     pub color: Vec4,
     pub texture: String,
+    // Opaque user data reported by hit testing. Ignored by rendering.
+    #[serde(default)]
+    pub tag: Option<u64>,
+    // Positions the sprite relative to its layer when set
+    #[serde(default)]
+    pub placement: Option<Placement>,
+    #[serde(default)]
+    pub snap: PixelSnap,
+    // Theme variables replacing the values of fields when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    pub theme: ThemeBindings,
 }
 
 impl Sprite {
@@ -16,6 +33,10 @@ impl Sprite {
             size,
             color: Vec4::ONE,
             texture,
+            tag: None,
+            placement: None,
+            snap: PixelSnap::None,
+            theme: ThemeBindings::new(),
         }
     }
 
@@ -23,4 +44,60 @@ impl Sprite {
         self.color = color;
         self
     }
+
+    pub fn with_tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        self.placement = Some(placement);
+        self
+    }
+
+    pub fn with_snap(mut self, snap: PixelSnap) -> Self {
+        self.snap = snap;
+        self
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| {
+            if let (ThemeField::Color, ThemeValue::Color(color)) = (field, value) {
+                self.color = color;
+            }
+        });
+        self.theme = bindings;
+    }
+
+    pub fn bounds(&self) -> Vec4 {
+        vec4(self.top_left.x, self.top_left.y, self.size.x, self.size.y)
+    }
+}
+
+impl Interpolate for Sprite {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Sprite {
+            top_left: self.top_left.lerp(to.top_left, t),
+            size: self.size.lerp(to.size, t),
+            color: interpolate_color(self.color, to.color, t),
+            texture: snap(&self.texture, &to.texture, t).clone(),
+            tag: *snap(&self.tag, &to.tag, t),
+            placement: *snap(&self.placement, &to.placement, t),
+            snap: *snap(&self.snap, &to.snap, t),
+            theme: snap(&self.theme, &to.theme, t).clone(),
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Sprite {
+            color: fade_color(self.color, opacity),
+            ..self.clone()
+        }
+    }
 }
@@ -1,7 +1,349 @@
-use glam::{Vec2, Vec4};
-use serde::Deserialize;
+use glam::{vec4, Vec2, Vec4};
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, Clone)]
+use super::{
+    animation::{fade_color, interpolate_color, interpolate_option, snap},
+    theme::{apply_bindings, Theme, ThemeBindings, ThemeField, ThemeValue},
+    Interpolate, Outline, Pattern, Placement, ProceduralKind,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnderlineStyle {
+    #[default]
+    Single,
+    // Two lines separated by a gap of the thickness
+    Double,
+    // Round dots spaced by their diameter
+    Dotted,
+    // Dashes covering 60 percent of each period
+    Dashed {
+        period: f32,
+    },
+    // Sine wave, also known as undercurl. The amplitude is the height of the
+    // peaks above the center line and the period the length of one wave.
+    Wavy {
+        amplitude: f32,
+        period: f32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Hinting {
+    // Unhinted outlines at their exact subpixel positions, which keeps
+    // animated text moving smoothly
+    None,
+    // Unhinted outlines with the baseline snapped to whole pixels
+    Slight,
+    // Outlines fitted to the pixel grid by the hinting instructions of the
+    // font
+    #[default]
+    Full,
+}
+
+// How glyphs are fitted to the pixel grid. Snapping makes small static text
+// sharper at the cost of glyphs jumping between pixels when the text moves.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextRendering {
+    #[serde(default)]
+    pub hinting: Hinting,
+    // Places each glyph on a whole pixel instead of a subpixel offset
+    #[serde(default)]
+    pub snap_origins: bool,
+    // Rounds the advances to whole pixels, so that all the glyphs keep the
+    // same offset from the pixel grid
+    #[serde(default)]
+    pub round_advances: bool,
+}
+
+impl TextRendering {
+    pub fn new(hinting: Hinting) -> Self {
+        Self {
+            hinting,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_snapped_origins(mut self) -> Self {
+        self.snap_origins = true;
+        self
+    }
+
+    pub fn with_rounded_advances(mut self) -> Self {
+        self.round_advances = true;
+        self
+    }
+}
+
+// Line drawn under, through or over a text run. The thickness and offset
+// default to the matching metrics of the font.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Underline {
+    #[serde(default)]
+    pub style: UnderlineStyle,
+    // Defaults to the color of the text
+    #[serde(default)]
+    pub color: Option<Vec4>,
+    #[serde(default)]
+    pub thickness: Option<f32>,
+    // Distance from the baseline down to the center of the line
+    #[serde(default)]
+    pub offset: Option<f32>,
+}
+
+impl Underline {
+    pub fn new(style: UnderlineStyle) -> Self {
+        Self {
+            style,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_color(mut self, color: Vec4) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn with_thickness(mut self, thickness: f32) -> Self {
+        self.thickness = Some(thickness);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+}
+
+// Rounded rect painted behind a text run, sized from the shaped text, such as
+// a selection highlight or a label. The padding is added on the left and
+// right, and above the ascent and below the descent of the font.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TextBackground {
+    pub color: Vec4,
+    #[serde(default)]
+    pub padding: Vec2,
+    #[serde(default)]
+    pub corner_radius: f32,
+}
+
+impl TextBackground {
+    pub fn new(color: Vec4) -> Self {
+        Self {
+            color,
+            padding: Vec2::ZERO,
+            corner_radius: 0.0,
+        }
+    }
+
+    pub fn with_padding(mut self, padding: Vec2) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_corner_radius(mut self, corner_radius: f32) -> Self {
+        self.corner_radius = corner_radius;
+        self
+    }
+}
+
+// Fills the glyphs of a text run instead of the color, evaluated relative to
+// the line of the run from the ascent to the descent
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TextPaint {
+    // Blends from the color of the text to the secondary color
+    Procedural {
+        kind: ProceduralKind,
+        secondary_color: Vec4,
+    },
+    // Multiplied with the color of the text
+    Pattern(Pattern),
+}
+
+// Changes the look of one glyph of a text run, so that typewriter, wave and
+// emphasis animations don't have to split the run into single glyphs. The
+// glyph is picked by its index in the shaped run, which is the index of its
+// character unless the text has ligatures or combining marks. Scaled glyphs
+// are stretched from their rasterized size.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct GlyphOverride {
+    pub glyph: usize,
+    #[serde(default)]
+    pub offset: Vec2,
+    // Scale and rotation in radians around the center of the glyph
+    #[serde(default = "default_glyph_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub rotation: f32,
+    // Replaces the color of the text
+    #[serde(default)]
+    pub color: Option<Vec4>,
+}
+
+fn default_glyph_scale() -> f32 {
+    1.0
+}
+
+impl GlyphOverride {
+    pub fn new(glyph: usize) -> Self {
+        Self {
+            glyph,
+            offset: Vec2::ZERO,
+            scale: 1.0,
+            rotation: 0.0,
+            color: None,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_color(mut self, color: Vec4) -> Self {
+        self.color = Some(color);
+        self
+    }
+}
+
+// Distance between regular tab stops
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TabWidth {
+    // Multiple of the advance of a space in the font
+    Spaces(f32),
+    Pixels(f32),
+}
+
+// Positions tabs advance the text to. The text after a tab starts at the
+// first of the stops past the pen, given in pixels from the start of the text
+// in ascending order, and after the last of them at the regular interval.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TabStops {
+    pub interval: TabWidth,
+    #[serde(default)]
+    pub stops: Vec<f32>,
+}
+
+impl TabStops {
+    pub fn spaces(spaces: f32) -> Self {
+        Self {
+            interval: TabWidth::Spaces(spaces),
+            stops: Vec::new(),
+        }
+    }
+
+    pub fn pixels(pixels: f32) -> Self {
+        Self {
+            interval: TabWidth::Pixels(pixels),
+            stops: Vec::new(),
+        }
+    }
+
+    pub fn with_stops(mut self, stops: Vec<f32>) -> Self {
+        self.stops = stops;
+        self
+    }
+}
+
+// Every 8 spaces, like terminals
+impl Default for TabStops {
+    fn default() -> Self {
+        Self::spaces(8.0)
+    }
+}
+
+// OpenType feature applied when shaping the text, such as ("liga", 0) to turn
+// off the standard ligatures or ("ss01", 1) to turn on a stylistic set
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FontFeature {
+    pub tag: String,
+    pub value: u16,
+}
+
+impl FontFeature {
+    pub fn new(tag: &str, value: u16) -> Self {
+        Self {
+            tag: tag.to_string(),
+            value,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TextDirection {
+    #[default]
+    LeftToRight,
+    RightToLeft,
+}
+
+// How a text run wider than its max width is cut off. The text is cut at a
+// cluster boundary, so that ligatures and combining marks stay whole.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextOverflow {
+    // Leaves out the clusters which don't fit
+    #[default]
+    Clip,
+    // Ends the text with an ellipsis
+    Ellipsis,
+    // Fades the text out towards the max width
+    Fade,
+    // Ends the text with the character
+    Custom(char),
+}
+
+// Rect a text run is scaled to fit in, replacing its size and position. The
+// text is centered in the rect. Text which doesn't fit even at the minimum
+// size overflows the width of the rect, with an ellipsis when enabled and
+// otherwise as set by the overflow of the text.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TextFit {
+    pub top_left: Vec2,
+    pub size: Vec2,
+    #[serde(default)]
+    pub min_size: Option<f32>,
+    #[serde(default)]
+    pub max_size: Option<f32>,
+    #[serde(default)]
+    pub ellipsis: bool,
+}
+
+impl TextFit {
+    pub fn new(top_left: Vec2, size: Vec2) -> Self {
+        Self {
+            top_left,
+            size,
+            min_size: None,
+            max_size: None,
+            ellipsis: false,
+        }
+    }
+
+    pub fn with_min_size(mut self, min_size: f32) -> Self {
+        self.min_size = Some(min_size);
+        self
+    }
+
+    pub fn with_max_size(mut self, max_size: f32) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn with_ellipsis(mut self) -> Self {
+        self.ellipsis = true;
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Text {
     pub text: String,
     pub bottom_left: Vec2,
@@ -13,6 +355,51 @@ pub struct Text {
     pub italic: bool,
     #[serde(default = "default_subpixel")]
     pub subpixel: bool,
+    #[serde(default)]
+    pub underline: Option<Underline>,
+    #[serde(default)]
+    pub strikeout: Option<Underline>,
+    #[serde(default)]
+    pub overline: Option<Underline>,
+    #[serde(default)]
+    pub background: Option<TextBackground>,
+    #[serde(default)]
+    pub paint: Option<TextPaint>,
+    // Drawn around the glyphs. Outlined text is blended without subpixel
+    // antialiasing.
+    #[serde(default)]
+    pub outline: Option<Outline>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub glyph_overrides: Vec<GlyphOverride>,
+    #[serde(default)]
+    pub fit: Option<TextFit>,
+    // Width the text is cut off at as set by the overflow
+    #[serde(default)]
+    pub max_width: Option<f32>,
+    #[serde(default)]
+    pub overflow: TextOverflow,
+    #[serde(default)]
+    pub tab_stops: TabStops,
+    #[serde(default)]
+    pub features: Vec<FontFeature>,
+    #[serde(default)]
+    pub direction: TextDirection,
+    // Draws box drawing and block characters as geometry filling the cell
+    // instead of with the font. Uses the renderer setting when unset.
+    #[serde(default)]
+    pub synthetic_box_drawing: Option<bool>,
+    // Hinting and pixel snapping. Uses the renderer setting when unset.
+    #[serde(default)]
+    pub rendering: Option<TextRendering>,
+    // Opaque user data reported by hit testing. Ignored by rendering.
+    #[serde(default)]
+    pub tag: Option<u64>,
+    // Positions the start of the baseline relative to the layer when set
+    #[serde(default)]
+    pub placement: Option<Placement>,
+    // Theme variables replacing the values of fields when rendering
+    #[serde(default, skip_serializing_if = "ThemeBindings::is_empty")]
+    pub theme: ThemeBindings,
 }
 
 fn default_subpixel() -> bool {
@@ -29,6 +416,24 @@ impl Text {
             bold: false,
             italic: false,
             subpixel: true,
+            underline: None,
+            strikeout: None,
+            overline: None,
+            background: None,
+            paint: None,
+            outline: None,
+            glyph_overrides: Vec::new(),
+            fit: None,
+            max_width: None,
+            overflow: TextOverflow::Clip,
+            tab_stops: TabStops::default(),
+            features: Vec::new(),
+            direction: TextDirection::LeftToRight,
+            synthetic_box_drawing: None,
+            rendering: None,
+            tag: None,
+            placement: None,
+            theme: ThemeBindings::new(),
         }
     }
 
@@ -46,4 +451,180 @@ impl Text {
         self.subpixel = false;
         self
     }
+
+    pub fn with_underline(mut self, underline: Underline) -> Self {
+        self.underline = Some(underline);
+        self
+    }
+
+    pub fn with_strikeout(mut self, strikeout: Underline) -> Self {
+        self.strikeout = Some(strikeout);
+        self
+    }
+
+    pub fn with_overline(mut self, overline: Underline) -> Self {
+        self.overline = Some(overline);
+        self
+    }
+
+    pub fn with_synthetic_box_drawing(mut self, enabled: bool) -> Self {
+        self.synthetic_box_drawing = Some(enabled);
+        self
+    }
+
+    pub fn with_background(mut self, background: TextBackground) -> Self {
+        self.background = Some(background);
+        self
+    }
+
+    pub fn with_paint(mut self, paint: TextPaint) -> Self {
+        self.paint = Some(paint);
+        self
+    }
+
+    pub fn with_outline(mut self, outline: Outline) -> Self {
+        self.outline = Some(outline);
+        self
+    }
+
+    pub fn with_glyph_override(mut self, glyph_override: GlyphOverride) -> Self {
+        self.glyph_overrides.push(glyph_override);
+        self
+    }
+
+    pub fn with_fit(mut self, fit: TextFit) -> Self {
+        self.fit = Some(fit);
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: TextOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn with_tab_stops(mut self, tab_stops: TabStops) -> Self {
+        self.tab_stops = tab_stops;
+        self
+    }
+
+    pub fn with_feature(mut self, feature: FontFeature) -> Self {
+        self.features.push(feature);
+        self
+    }
+
+    pub fn with_direction(mut self, direction: TextDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_rendering(mut self, rendering: TextRendering) -> Self {
+        self.rendering = Some(rendering);
+        self
+    }
+
+    pub fn with_tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        self.placement = Some(placement);
+        self
+    }
+
+    pub fn with_theme_variable(mut self, field: ThemeField, name: &str) -> Self {
+        self.theme.insert(field, name.to_string());
+        self
+    }
+
+    pub(crate) fn apply_theme(&mut self, theme: &Theme) {
+        let bindings = std::mem::take(&mut self.theme);
+        apply_bindings(&bindings, theme, |field, value| {
+            if let (ThemeField::Color, ThemeValue::Color(color)) = (field, value) {
+                self.color = color;
+            }
+        });
+        self.theme = bindings;
+    }
+
+    // Conservative bounds of the text run. The horizontal extent isn't known
+    // until the text is shaped, so only the vertical extent is limited, to
+    // the rect of the fit when there is one, grown by the glyph offsets.
+    pub fn bounds(&self) -> Vec4 {
+        let padding = self
+            .background
+            .map_or(0.0, |background| background.padding.y)
+            + self
+                .glyph_overrides
+                .iter()
+                .map(|glyph_override| glyph_override.offset.y.abs())
+                .fold(0.0, f32::max)
+            + self.outline.map_or(0.0, |outline| outline.extent());
+        if let Some(fit) = self.fit {
+            return vec4(
+                f32::MIN / 2.0,
+                fit.top_left.y - padding,
+                f32::MAX,
+                fit.size.y + padding * 2.0,
+            );
+        }
+        // Covers both sides of the baseline, since the glyphs have larger y
+        // coordinates than it when the y axis points up
+        vec4(
+            f32::MIN / 2.0,
+            self.bottom_left.y - self.size * 1.5 - padding,
+            f32::MAX,
+            self.size * 3.0 + padding * 2.0,
+        )
+    }
+}
+
+impl Interpolate for Text {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        let snapped = snap(self, to, t);
+        Text {
+            bottom_left: self.bottom_left.lerp(to.bottom_left, t),
+            size: self.size + (to.size - self.size) * t,
+            color: interpolate_color(self.color, to.color, t),
+            outline: interpolate_option(&self.outline, &to.outline, t, |from, to| {
+                from.interpolate(to, t)
+            }),
+            ..snapped.clone()
+        }
+    }
+
+    fn fade(&self, opacity: f32) -> Self {
+        Text {
+            color: fade_color(self.color, opacity),
+            background: self.background.map(|background| TextBackground {
+                color: fade_color(background.color, opacity),
+                ..background
+            }),
+            paint: self.paint.clone().map(|paint| match paint {
+                TextPaint::Procedural {
+                    kind,
+                    secondary_color,
+                } => TextPaint::Procedural {
+                    kind,
+                    secondary_color: fade_color(secondary_color, opacity),
+                },
+                pattern => pattern,
+            }),
+            outline: self.outline.map(|outline| outline.fade(opacity)),
+            glyph_overrides: self
+                .glyph_overrides
+                .iter()
+                .map(|glyph_override| GlyphOverride {
+                    color: glyph_override.color.map(|color| fade_color(color, opacity)),
+                    ..*glyph_override
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
 }
@@ -0,0 +1,304 @@
+use std::collections::{BTreeMap, HashMap};
+
+use glam::Vec4;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{Camera, Layer, Scene};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum ThemeValue {
+    Number(f32),
+    Color(Vec4),
+}
+
+// Named colors and sizes which themed fields of a scene refer to. The theme
+// of the renderer is applied when the scene is rendered, so switching between
+// for example a light and a dark theme doesn't require rebuilding the scenes.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Theme {
+    values: HashMap<String, ThemeValue>,
+}
+
+impl Theme {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_color(&mut self, name: &str, color: Vec4) {
+        self.values
+            .insert(name.to_string(), ThemeValue::Color(color));
+    }
+
+    pub fn with_color(mut self, name: &str, color: Vec4) -> Self {
+        self.set_color(name, color);
+        self
+    }
+
+    pub fn set_number(&mut self, name: &str, number: f32) {
+        self.values
+            .insert(name.to_string(), ThemeValue::Number(number));
+    }
+
+    pub fn with_number(mut self, name: &str, number: f32) -> Self {
+        self.set_number(name, number);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<ThemeValue> {
+        self.values.get(name).copied()
+    }
+
+    pub fn color(&self, name: &str) -> Option<Vec4> {
+        match self.get(name)? {
+            ThemeValue::Color(color) => Some(color),
+            ThemeValue::Number(_) => None,
+        }
+    }
+
+    pub fn number(&self, name: &str) -> Option<f32> {
+        match self.get(name)? {
+            ThemeValue::Number(number) => Some(number),
+            ThemeValue::Color(_) => None,
+        }
+    }
+}
+
+// The fields of layers and primitives which can refer to theme variables
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeField {
+    Color,
+    PrimaryColor,
+    SecondaryColor,
+    BackgroundColor,
+    BackgroundBlurRadius,
+    Fill,
+    StrokeWidth,
+    StrokeColor,
+    CornerRadius,
+    Blur,
+    Elevation,
+}
+
+impl ThemeField {
+    // Name of the field in serialized theme bindings
+    fn key(self) -> &'static str {
+        match self {
+            ThemeField::Color => "color",
+            ThemeField::PrimaryColor => "primary_color",
+            ThemeField::SecondaryColor => "secondary_color",
+            ThemeField::BackgroundColor => "background_color",
+            ThemeField::BackgroundBlurRadius => "background_blur_radius",
+            ThemeField::Fill => "fill",
+            ThemeField::StrokeWidth => "stroke_width",
+            ThemeField::StrokeColor => "stroke_color",
+            ThemeField::CornerRadius => "corner_radius",
+            ThemeField::Blur => "blur",
+            ThemeField::Elevation => "elevation",
+        }
+    }
+
+    fn is_color(self) -> bool {
+        !matches!(
+            self,
+            ThemeField::BackgroundBlurRadius
+                | ThemeField::StrokeWidth
+                | ThemeField::CornerRadius
+                | ThemeField::Blur
+                | ThemeField::Elevation
+        )
+    }
+}
+
+// Theme variable names bound to the fields of a layer or primitive. Fields
+// keep their own value when the theme doesn't have the variable, or has a
+// value of the wrong kind.
+pub type ThemeBindings = BTreeMap<ThemeField, String>;
+
+// Fields of a serialized primitive which can be written as "$variable"
+const THEMED_FIELDS: [ThemeField; 9] = [
+    ThemeField::Color,
+    ThemeField::PrimaryColor,
+    ThemeField::SecondaryColor,
+    ThemeField::BackgroundColor,
+    ThemeField::BackgroundBlurRadius,
+    ThemeField::Fill,
+    ThemeField::CornerRadius,
+    ThemeField::Blur,
+    ThemeField::Elevation,
+];
+
+const PRIMITIVE_KEYS: [&str; 8] = [
+    "quads",
+    "texts",
+    "paths",
+    "sprites",
+    "procedurals",
+    "carets",
+    "cursor_trails",
+    "shapes",
+];
+
+impl Scene {
+    pub fn has_theme_variables(&self) -> bool {
+        self.layers.iter().any(|layer| {
+            !layer.theme.is_empty()
+                || layer.quads.iter().any(|quad| quad.has_theme_variables())
+                || layer.texts.iter().any(|text| !text.theme.is_empty())
+                || layer.paths.iter().any(|path| !path.theme.is_empty())
+                || layer.sprites.iter().any(|sprite| !sprite.theme.is_empty())
+                || layer
+                    .procedurals
+                    .iter()
+                    .any(|procedural| !procedural.theme.is_empty())
+                || layer.carets.iter().any(|caret| !caret.theme.is_empty())
+                || layer
+                    .cursor_trails
+                    .iter()
+                    .any(|trail| !trail.theme.is_empty())
+                || layer.shapes.iter().any(|shape| !shape.theme.is_empty())
+        })
+    }
+
+    // Replaces the values of the themed fields with the values of the theme.
+    // Animations should be built from scenes with the theme applied, as the
+    // themed fields would otherwise override the faded colors.
+    pub fn apply_theme(&mut self, theme: &Theme) {
+        for layer in self.layers.iter_mut() {
+            layer.apply_theme(theme);
+            layer
+                .quads
+                .iter_mut()
+                .for_each(|quad| quad.apply_theme(theme));
+            layer
+                .texts
+                .iter_mut()
+                .for_each(|text| text.apply_theme(theme));
+            layer
+                .paths
+                .iter_mut()
+                .for_each(|path| path.apply_theme(theme));
+            layer
+                .sprites
+                .iter_mut()
+                .for_each(|sprite| sprite.apply_theme(theme));
+            layer
+                .procedurals
+                .iter_mut()
+                .for_each(|procedural| procedural.apply_theme(theme));
+            layer
+                .carets
+                .iter_mut()
+                .for_each(|caret| caret.apply_theme(theme));
+            layer
+                .cursor_trails
+                .iter_mut()
+                .for_each(|trail| trail.apply_theme(theme));
+            layer
+                .shapes
+                .iter_mut()
+                .for_each(|shape| shape.apply_theme(theme));
+        }
+    }
+}
+
+// Calls the setter for every bound field whose variable the theme has a
+// value of the right kind for
+pub(crate) fn apply_bindings(
+    bindings: &ThemeBindings,
+    theme: &Theme,
+    mut set: impl FnMut(ThemeField, ThemeValue),
+) {
+    for (field, name) in bindings {
+        match theme.get(name) {
+            Some(value @ ThemeValue::Color(_)) if field.is_color() => set(*field, value),
+            Some(value @ ThemeValue::Number(_)) if !field.is_color() => set(*field, value),
+            _ => {}
+        }
+    }
+}
+
+// The same fields as Scene, which deserializes through this after moving the
+// theme variables out of the fields
+#[derive(Deserialize)]
+struct SceneFields {
+    #[serde(default)]
+    camera: Camera,
+    layers: Vec<Layer>,
+}
+
+impl TryFrom<Value> for Scene {
+    type Error = serde_json::Error;
+
+    fn try_from(mut value: Value) -> Result<Self, Self::Error> {
+        if let Some(Value::Array(layers)) = value.get_mut("layers") {
+            for layer in layers.iter_mut().filter_map(Value::as_object_mut) {
+                bind_variables(layer);
+                for key in PRIMITIVE_KEYS {
+                    if let Some(Value::Array(primitives)) = layer.get_mut(key) {
+                        primitives
+                            .iter_mut()
+                            .filter_map(Value::as_object_mut)
+                            .for_each(bind_variables);
+                    }
+                }
+            }
+        }
+
+        let fields: SceneFields = serde_json::from_value(value)?;
+        Ok(Scene {
+            camera: fields.camera,
+            layers: fields.layers,
+        })
+    }
+}
+
+fn variable(value: &Value) -> Option<String> {
+    value.as_str()?.strip_prefix('$').map(str::to_string)
+}
+
+fn placeholder(field: ThemeField) -> Value {
+    if field.is_color() {
+        serde_json::json!([0., 0., 0., 0.])
+    } else {
+        serde_json::json!(0.)
+    }
+}
+
+// Moves fields written as "$variable" into the theme bindings of the object,
+// leaving a placeholder value which the theme replaces when rendering
+fn bind_variables(object: &mut Map<String, Value>) {
+    let mut bindings = Vec::new();
+    for field in THEMED_FIELDS {
+        if let Some(name) = object.get(field.key()).and_then(variable) {
+            object.insert(field.key().to_string(), placeholder(field));
+            bindings.push((field, name));
+        }
+    }
+    if let Some(Value::Array(stroke)) = object.get_mut("stroke") {
+        for (element, field) in stroke
+            .iter_mut()
+            .zip([ThemeField::StrokeWidth, ThemeField::StrokeColor])
+        {
+            if let Some(name) = variable(element) {
+                *element = placeholder(field);
+                bindings.push((field, name));
+            }
+        }
+    }
+    if bindings.is_empty() {
+        return;
+    }
+
+    let theme = object
+        .entry("theme")
+        .or_insert_with(|| Value::Object(Map::new()));
+    if let Value::Object(theme) = theme {
+        for (field, name) in bindings {
+            theme.insert(field.key().to_string(), Value::String(name));
+        }
+    }
+}
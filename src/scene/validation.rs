@@ -0,0 +1,560 @@
+use std::fmt;
+
+use glam::{Vec2, Vec4, Vec4Swizzles};
+
+use super::{
+    BackdropBlurQuad, Camera, Caret, Checkerboard, CursorTrail, Layer, LayerDimming, Path, Pattern,
+    Procedural, ProceduralKind, Scene, Shadow, Shape, ShapeKind, Sprite, TabWidth, Text, TextPaint,
+    Underline, UnderlineStyle, MAX_GRADIENT_STOPS,
+};
+
+// A value in a scene which can't be rendered correctly. The path points at the
+// value, for example `layers[2].quads[5].size`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+// Collects the errors of the values checked so far
+#[derive(Default)]
+pub(crate) struct Validator {
+    errors: Vec<ValidationError>,
+}
+
+impl Validator {
+    fn error(&mut self, path: &str, message: impl Into<String>) {
+        self.errors.push(ValidationError {
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    pub fn finite(&mut self, path: &str, value: f32) -> bool {
+        if !value.is_finite() {
+            self.error(path, format!("{value} is not a finite number"));
+            return false;
+        }
+        true
+    }
+
+    pub fn non_negative(&mut self, path: &str, value: f32) {
+        if self.finite(path, value) && value < 0.0 {
+            self.error(path, format!("{value} is negative"));
+        }
+    }
+
+    pub fn positive(&mut self, path: &str, value: f32) {
+        if self.finite(path, value) && value <= 0.0 {
+            self.error(path, format!("{value} has to be greater than zero"));
+        }
+    }
+
+    pub fn point(&mut self, path: &str, point: Vec2) {
+        if !point.is_finite() {
+            self.error(path, format!("{point} has a component which is not finite"));
+        }
+    }
+
+    pub fn size(&mut self, path: &str, size: Vec2) {
+        if !size.is_finite() {
+            self.error(path, format!("{size} has a component which is not finite"));
+        } else if size.x < 0.0 || size.y < 0.0 {
+            self.error(path, format!("{size} is negative"));
+        }
+    }
+
+    pub fn color(&mut self, path: &str, color: Vec4) {
+        if !color.is_finite() {
+            self.error(path, format!("{color} has a component which is not finite"));
+        } else if color.cmplt(Vec4::ZERO).any() || color.cmpgt(Vec4::ONE).any() {
+            self.error(path, format!("{color} has a component outside of 0 to 1"));
+        }
+    }
+
+    // Clips are rects with the top left in xy and the size in zw
+    pub fn clip(&mut self, path: &str, clip: Vec4) {
+        if !clip.is_finite() {
+            self.error(path, format!("{clip} has a component which is not finite"));
+        } else if clip.z <= 0.0 || clip.w <= 0.0 {
+            self.error(path, format!("{clip} has no area, so nothing is drawn"));
+        }
+    }
+
+    pub fn pattern(&mut self, path: &str, pattern: &Pattern) {
+        self.point(&format!("{path}.offset"), pattern.offset);
+        let transform = pattern.transform;
+        if !transform.is_finite() {
+            self.error(
+                &format!("{path}.transform"),
+                "has a component which is not finite",
+            );
+        } else if transform.determinant() == 0.0 {
+            self.error(
+                &format!("{path}.transform"),
+                "is not invertible, which collapses the pattern",
+            );
+        }
+    }
+
+    pub fn finish(self) -> Result<(), Vec<ValidationError>> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+}
+
+impl Scene {
+    // Checks the scene for values which would render garbage or make the gpu
+    // validation fail: non finite coordinates, negative sizes, colors outside
+    // of 0 to 1 and clips without area. Layers are owned by the scene, so
+    // they can't form cycles. The renderer runs this before every frame in
+    // debug builds.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut validator = Validator::default();
+        validate_camera(&mut validator, &self.camera);
+        for (index, layer) in self.layers.iter().enumerate() {
+            layer.validate_into(&mut validator, &format!("layers[{index}]"));
+        }
+        validator.finish()
+    }
+}
+
+fn validate_camera(validator: &mut Validator, camera: &Camera) {
+    validator.point("camera.offset", camera.offset);
+    validator.positive("camera.zoom", camera.zoom);
+    validator.finite("camera.rotation", camera.rotation);
+}
+
+impl Layer {
+    pub(crate) fn validate_into(&self, validator: &mut Validator, path: &str) {
+        if let Some(clip) = self.clip {
+            validator.clip(&format!("{path}.clip"), clip);
+        }
+        validator.point(&format!("{path}.scroll_offset"), self.scroll_offset);
+        validator.non_negative(
+            &format!("{path}.background_blur_radius"),
+            self.background_blur_radius,
+        );
+        if let Some(color) = self.background_color {
+            validator.color(&format!("{path}.background_color"), color);
+        }
+        if let Some(dimming) = &self.dimming {
+            validate_dimming(validator, &format!("{path}.dimming"), dimming);
+        }
+
+        for (index, shadow) in self.shadows.iter().enumerate() {
+            validate_shadow(validator, &format!("{path}.shadows[{index}]"), shadow);
+        }
+        for (index, quad) in self.quads.iter().enumerate() {
+            quad.validate_into(validator, &format!("{path}.quads[{index}]"));
+        }
+        for (index, text) in self.texts.iter().enumerate() {
+            validate_text(validator, &format!("{path}.texts[{index}]"), text);
+        }
+        for (index, path_primitive) in self.paths.iter().enumerate() {
+            validate_path(validator, &format!("{path}.paths[{index}]"), path_primitive);
+        }
+        for (index, sprite) in self.sprites.iter().enumerate() {
+            validate_sprite(validator, &format!("{path}.sprites[{index}]"), sprite);
+        }
+        for (index, procedural) in self.procedurals.iter().enumerate() {
+            validate_procedural(
+                validator,
+                &format!("{path}.procedurals[{index}]"),
+                procedural,
+            );
+        }
+        for (index, shape) in self.shapes.iter().enumerate() {
+            validate_shape(validator, &format!("{path}.shapes[{index}]"), shape);
+        }
+        for (index, blur) in self.backdrop_blurs.iter().enumerate() {
+            validate_backdrop_blur(validator, &format!("{path}.backdrop_blurs[{index}]"), blur);
+        }
+        for (index, caret) in self.carets.iter().enumerate() {
+            validate_caret(validator, &format!("{path}.carets[{index}]"), caret);
+        }
+        for (index, trail) in self.cursor_trails.iter().enumerate() {
+            validate_cursor_trail(validator, &format!("{path}.cursor_trails[{index}]"), trail);
+        }
+        for (index, checkerboard) in self.checkerboards.iter().enumerate() {
+            validate_checkerboard(
+                validator,
+                &format!("{path}.checkerboards[{index}]"),
+                checkerboard,
+            );
+        }
+    }
+}
+
+fn validate_dimming(validator: &mut Validator, path: &str, dimming: &LayerDimming) {
+    validator.non_negative(&format!("{path}.saturation"), dimming.saturation);
+    validator.non_negative(&format!("{path}.brightness"), dimming.brightness);
+    validate_dimming_progress(validator, &format!("{path}.progress"), dimming.progress);
+    if let Some(transition) = dimming.transition {
+        validate_dimming_progress(
+            validator,
+            &format!("{path}.transition.from"),
+            transition.from,
+        );
+        validator.finite(
+            &format!("{path}.transition.started_at"),
+            transition.started_at,
+        );
+        validator.non_negative(&format!("{path}.transition.duration"), transition.duration);
+    }
+}
+
+fn validate_dimming_progress(validator: &mut Validator, path: &str, progress: f32) {
+    if validator.finite(path, progress) && !(0.0..=1.0).contains(&progress) {
+        validator.error(path, format!("{progress} is outside of 0 to 1"));
+    }
+}
+
+fn validate_text(validator: &mut Validator, path: &str, text: &Text) {
+    validator.point(&format!("{path}.bottom_left"), text.bottom_left);
+    validator.positive(&format!("{path}.size"), text.size);
+    validator.color(&format!("{path}.color"), text.color);
+    if let Some(underline) = &text.underline {
+        validate_underline(validator, &format!("{path}.underline"), underline);
+    }
+    if let Some(strikeout) = &text.strikeout {
+        validate_underline(validator, &format!("{path}.strikeout"), strikeout);
+    }
+    if let Some(overline) = &text.overline {
+        validate_underline(validator, &format!("{path}.overline"), overline);
+    }
+    if let Some(outline) = &text.outline {
+        outline.validate_into(validator, &format!("{path}.outline"));
+    }
+    match &text.paint {
+        Some(TextPaint::Procedural {
+            kind,
+            secondary_color,
+        }) => {
+            validate_procedural_kind(validator, &format!("{path}.paint.kind"), *kind);
+            validator.color(&format!("{path}.paint.secondary_color"), *secondary_color);
+        }
+        Some(TextPaint::Pattern(pattern)) => {
+            validator.pattern(&format!("{path}.paint"), pattern);
+        }
+        None => {}
+    }
+    for (index, glyph_override) in text.glyph_overrides.iter().enumerate() {
+        let override_path = format!("{path}.glyph_overrides[{index}]");
+        validator.point(&format!("{override_path}.offset"), glyph_override.offset);
+        validator.non_negative(&format!("{override_path}.scale"), glyph_override.scale);
+        validator.finite(
+            &format!("{override_path}.rotation"),
+            glyph_override.rotation,
+        );
+        if let Some(color) = glyph_override.color {
+            validator.color(&format!("{override_path}.color"), color);
+        }
+    }
+    let interval = match text.tab_stops.interval {
+        TabWidth::Spaces(spaces) => spaces,
+        TabWidth::Pixels(pixels) => pixels,
+    };
+    validator.positive(&format!("{path}.tab_stops.interval"), interval);
+    for (index, stop) in text.tab_stops.stops.iter().enumerate() {
+        let stop_path = format!("{path}.tab_stops.stops[{index}]");
+        validator.non_negative(&stop_path, *stop);
+        if index > 0 && *stop < text.tab_stops.stops[index - 1] {
+            validator.error(&stop_path, format!("{stop} is before the previous stop"));
+        }
+    }
+    for (index, feature) in text.features.iter().enumerate() {
+        if feature.tag.len() != 4 || !feature.tag.bytes().all(|byte| byte.is_ascii_graphic()) {
+            validator.error(
+                &format!("{path}.features[{index}].tag"),
+                format!("{:?} is not a four character tag", feature.tag),
+            );
+        }
+    }
+    if let Some(max_width) = text.max_width {
+        validator.non_negative(&format!("{path}.max_width"), max_width);
+    }
+    if let Some(fit) = &text.fit {
+        validator.point(&format!("{path}.fit.top_left"), fit.top_left);
+        validator.size(&format!("{path}.fit.size"), fit.size);
+        if let Some(min_size) = fit.min_size {
+            validator.positive(&format!("{path}.fit.min_size"), min_size);
+        }
+        if let Some(max_size) = fit.max_size {
+            validator.positive(&format!("{path}.fit.max_size"), max_size);
+        }
+    }
+    if let Some(background) = &text.background {
+        validator.color(&format!("{path}.background.color"), background.color);
+        validator.size(&format!("{path}.background.padding"), background.padding);
+        validator.non_negative(
+            &format!("{path}.background.corner_radius"),
+            background.corner_radius,
+        );
+    }
+}
+
+fn validate_underline(validator: &mut Validator, path: &str, underline: &Underline) {
+    if let Some(color) = underline.color {
+        validator.color(&format!("{path}.color"), color);
+    }
+    if let Some(thickness) = underline.thickness {
+        validator.non_negative(&format!("{path}.thickness"), thickness);
+    }
+    if let Some(offset) = underline.offset {
+        validator.finite(&format!("{path}.offset"), offset);
+    }
+    match underline.style {
+        UnderlineStyle::Dashed { period } => {
+            validator.positive(&format!("{path}.style.period"), period);
+        }
+        UnderlineStyle::Wavy { amplitude, period } => {
+            validator.finite(&format!("{path}.style.amplitude"), amplitude);
+            validator.positive(&format!("{path}.style.period"), period);
+        }
+        UnderlineStyle::Single | UnderlineStyle::Double | UnderlineStyle::Dotted => {}
+    }
+}
+
+fn validate_path(validator: &mut Validator, path: &str, path_primitive: &Path) {
+    validator.point(&format!("{path}.start"), path_primitive.start);
+    for (index, command) in path_primitive.commands.iter().enumerate() {
+        for point in command.points() {
+            validator.point(&format!("{path}.commands[{index}]"), point);
+        }
+    }
+    validator.positive(&format!("{path}.tolerance"), path_primitive.tolerance);
+    if let Some(fill) = path_primitive.fill {
+        validator.color(&format!("{path}.fill"), fill);
+    }
+    if let Some(pattern) = &path_primitive.fill_pattern {
+        validator.pattern(&format!("{path}.fill_pattern"), pattern);
+    }
+    if let Some((width, color)) = path_primitive.stroke {
+        validator.non_negative(&format!("{path}.stroke.width"), width);
+        validator.color(&format!("{path}.stroke.color"), color);
+    }
+    if let Some(outline) = &path_primitive.outline {
+        outline.validate_into(validator, &format!("{path}.outline"));
+    }
+}
+
+fn validate_sprite(validator: &mut Validator, path: &str, sprite: &Sprite) {
+    validator.point(&format!("{path}.top_left"), sprite.top_left);
+    validator.size(&format!("{path}.size"), sprite.size);
+    validator.color(&format!("{path}.color"), sprite.color);
+}
+
+fn validate_shadow(validator: &mut Validator, path: &str, shadow: &Shadow) {
+    validator.point(&format!("{path}.top_left"), shadow.top_left);
+    validator.size(&format!("{path}.size"), shadow.size);
+    validator.non_negative(&format!("{path}.blur"), shadow.blur);
+    validator.color(&format!("{path}.color"), shadow.color);
+    validator.non_negative(&format!("{path}.corner_radius"), shadow.corner_radius);
+    validator.finite(&format!("{path}.spread"), shadow.spread);
+    validator.point(&format!("{path}.offset"), shadow.offset);
+}
+
+fn validate_backdrop_blur(validator: &mut Validator, path: &str, blur: &BackdropBlurQuad) {
+    validator.point(&format!("{path}.top_left"), blur.top_left);
+    validator.size(&format!("{path}.size"), blur.size);
+    validator.non_negative(&format!("{path}.radius"), blur.radius);
+    validator.non_negative(&format!("{path}.corner_radius"), blur.corner_radius);
+    validator.color(&format!("{path}.tint"), blur.tint);
+}
+
+fn validate_caret(validator: &mut Validator, path: &str, caret: &Caret) {
+    validator.point(&format!("{path}.top_left"), caret.top_left);
+    validator.size(&format!("{path}.size"), caret.size);
+    validator.color(&format!("{path}.color"), caret.color);
+    validator.non_negative(&format!("{path}.thickness"), caret.thickness);
+    validator.non_negative(&format!("{path}.blink_interval"), caret.blink_interval);
+    validator.finite(&format!("{path}.moved_at"), caret.moved_at);
+    if let Some(moved_from) = caret.moved_from {
+        validator.point(&format!("{path}.moved_from"), moved_from);
+    }
+    validator.non_negative(&format!("{path}.move_duration"), caret.move_duration);
+}
+
+fn validate_cursor_trail(validator: &mut Validator, path: &str, trail: &CursorTrail) {
+    for (name, rect) in [("from", trail.from), ("to", trail.to)] {
+        validator.point(&format!("{path}.{name}"), rect.xy());
+        validator.size(&format!("{path}.{name}"), rect.zw());
+    }
+    validator.color(&format!("{path}.color"), trail.color);
+    validator.finite(&format!("{path}.started_at"), trail.started_at);
+    validator.non_negative(&format!("{path}.duration"), trail.duration);
+    validator.non_negative(&format!("{path}.trail_size"), trail.trail_size);
+}
+
+fn validate_checkerboard(validator: &mut Validator, path: &str, checkerboard: &Checkerboard) {
+    validator.point(&format!("{path}.top_left"), checkerboard.top_left);
+    validator.size(&format!("{path}.size"), checkerboard.size);
+    validator.positive(&format!("{path}.cell_size"), checkerboard.cell_size);
+    validator.color(&format!("{path}.light_color"), checkerboard.light_color);
+    validator.color(&format!("{path}.dark_color"), checkerboard.dark_color);
+}
+
+fn validate_procedural(validator: &mut Validator, path: &str, procedural: &Procedural) {
+    validator.point(&format!("{path}.top_left"), procedural.top_left);
+    validator.size(&format!("{path}.size"), procedural.size);
+    validator.color(&format!("{path}.primary_color"), procedural.primary_color);
+    validator.color(
+        &format!("{path}.secondary_color"),
+        procedural.secondary_color,
+    );
+
+    validate_procedural_kind(validator, &format!("{path}.kind"), procedural.kind);
+
+    if procedural.stops.len() > MAX_GRADIENT_STOPS {
+        validator.error(
+            &format!("{path}.stops"),
+            format!("can have at most {MAX_GRADIENT_STOPS} stops"),
+        );
+    }
+    let mut previous_offset = 0.0;
+    for (index, stop) in procedural.stops.iter().enumerate() {
+        let stop_path = format!("{path}.stops[{index}]");
+        validator.color(&format!("{stop_path}.color"), stop.color);
+        if !validator.finite(&format!("{stop_path}.offset"), stop.offset) {
+            continue;
+        }
+        if !(0.0..=1.0).contains(&stop.offset) {
+            validator.error(&format!("{stop_path}.offset"), "has to be between 0 and 1");
+        } else if stop.offset < previous_offset {
+            validator.error(
+                &format!("{stop_path}.offset"),
+                "is before the offset of the previous stop",
+            );
+        }
+        previous_offset = stop.offset;
+    }
+}
+
+fn validate_procedural_kind(validator: &mut Validator, kind_path: &str, kind: ProceduralKind) {
+    match kind {
+        ProceduralKind::LinearGradient { angle } => {
+            validator.finite(&format!("{kind_path}.angle"), angle);
+        }
+        ProceduralKind::RadialGradient => {}
+        ProceduralKind::Checker { cell_size } => {
+            validator.positive(&format!("{kind_path}.cell_size"), cell_size);
+        }
+        ProceduralKind::Grid {
+            cell_size,
+            line_width,
+        } => {
+            validator.positive(&format!("{kind_path}.cell_size"), cell_size);
+            validator.non_negative(&format!("{kind_path}.line_width"), line_width);
+        }
+        ProceduralKind::Noise {
+            feature_size,
+            octaves,
+            speed,
+        } => {
+            validator.positive(&format!("{kind_path}.feature_size"), feature_size);
+            if octaves == 0 {
+                validator.error(&format!("{kind_path}.octaves"), "has to be at least 1");
+            }
+            validator.finite(&format!("{kind_path}.speed"), speed);
+        }
+        ProceduralKind::ConicGradient {
+            start_angle,
+            sweep,
+            center_offset,
+        } => {
+            validator.finite(&format!("{kind_path}.start_angle"), start_angle);
+            validator.finite(&format!("{kind_path}.sweep"), sweep);
+            if sweep == 0.0 {
+                validator.error(&format!("{kind_path}.sweep"), "can't be zero");
+            }
+            validator.point(&format!("{kind_path}.center_offset"), center_offset);
+        }
+    }
+}
+
+fn validate_shape(validator: &mut Validator, path: &str, shape: &Shape) {
+    validator.point(&format!("{path}.center"), shape.center);
+    validator.non_negative(&format!("{path}.radius"), shape.radius);
+    validator.color(&format!("{path}.color"), shape.color);
+    validator.finite(&format!("{path}.rotation"), shape.rotation);
+
+    let kind_path = format!("{path}.kind");
+    match shape.kind {
+        ShapeKind::Polygon { sides } => {
+            if sides < 3 {
+                validator.error(&format!("{kind_path}.sides"), "has to be at least 3");
+            }
+        }
+        ShapeKind::Star {
+            points,
+            inner_radius,
+        } => {
+            if points < 2 {
+                validator.error(&format!("{kind_path}.points"), "has to be at least 2");
+            }
+            if validator.finite(&format!("{kind_path}.inner_radius"), inner_radius)
+                && !(0.0..=1.0).contains(&inner_radius)
+            {
+                validator.error(
+                    &format!("{kind_path}.inner_radius"),
+                    "has to be between 0 and 1",
+                );
+            }
+        }
+        ShapeKind::Arc {
+            start_angle,
+            sweep,
+            thickness,
+        } => {
+            validator.finite(&format!("{kind_path}.start_angle"), start_angle);
+            validator.finite(&format!("{kind_path}.sweep"), sweep);
+            validator.non_negative(&format!("{kind_path}.thickness"), thickness);
+        }
+        ShapeKind::Pie { start_angle, sweep } => {
+            validator.finite(&format!("{kind_path}.start_angle"), start_angle);
+            validator.finite(&format!("{kind_path}.sweep"), sweep);
+        }
+        ShapeKind::Squircle { exponent } => {
+            if validator.finite(&format!("{kind_path}.exponent"), exponent) && exponent < 1.0 {
+                validator.error(&format!("{kind_path}.exponent"), "has to be at least 1");
+            }
+        }
+        ShapeKind::Ring {
+            inner_radius,
+            start_angle,
+            end_angle,
+            ..
+        } => {
+            if validator.finite(&format!("{kind_path}.inner_radius"), inner_radius)
+                && !(0.0..=shape.radius).contains(&inner_radius)
+            {
+                validator.error(
+                    &format!("{kind_path}.inner_radius"),
+                    "has to be between 0 and the radius",
+                );
+            }
+            validator.finite(&format!("{kind_path}.start_angle"), start_angle);
+            validator.finite(&format!("{kind_path}.end_angle"), end_angle);
+        }
+    }
+
+    if let Some(paint) = &shape.paint {
+        validate_procedural_kind(validator, &format!("{path}.paint.kind"), paint.kind);
+        validator.color(
+            &format!("{path}.paint.secondary_color"),
+            paint.secondary_color,
+        );
+    }
+}
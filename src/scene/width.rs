@@ -0,0 +1,47 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+const TEXT_PRESENTATION: char = '\u{FE0E}';
+const EMOJI_PRESENTATION: char = '\u{FE0F}';
+
+// Number of grid cells the character takes, 2 for wide east asian characters
+// and emoji and 1 otherwise. Characters without a width of their own, such
+// as combining marks, still take a cell when they stand alone.
+pub fn char_width(character: char) -> usize {
+    character.width().unwrap_or(1).clamp(1, 2)
+}
+
+// Number of grid cells the grapheme cluster takes. The presentation
+// selectors switch emoji between the wide emoji and the narrow text style,
+// and a pair of regional indicators is a wide flag. Zero for an empty
+// string.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    let Some(first) = grapheme.chars().next() else {
+        return 0;
+    };
+    if grapheme.contains(EMOJI_PRESENTATION) {
+        return 2;
+    }
+    if grapheme.contains(TEXT_PRESENTATION) {
+        return 1;
+    }
+    if is_regional_indicator(first)
+        && grapheme
+            .chars()
+            .filter(|c| is_regional_indicator(*c))
+            .count()
+            == 2
+    {
+        return 2;
+    }
+    char_width(first)
+}
+
+// Number of grid cells the text takes
+pub fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+fn is_regional_indicator(character: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&character)
+}
@@ -0,0 +1,443 @@
+use std::{fmt, mem::size_of_val, ptr::addr_of};
+
+use bytemuck::Zeroable;
+use naga::{
+    front::spv, AddressSpace, Binding, Block, Expression, Function, GlobalVariable, Handle,
+    ImageClass, Module, ShaderStage, Statement, StorageAccess, TypeInner,
+};
+use shader::ShaderConstants;
+use wgpu::{
+    BindGroupLayoutEntry, BindingType, BufferBindingType, SamplerBindingType, ShaderStages,
+    TextureSampleType, VertexBufferLayout,
+};
+
+// Name, offset and size in bytes of a field of a push constant struct
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldLayout {
+    pub name: &'static str,
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderLayoutError {
+    // The module isn't valid spirv or uses something naga can't reflect
+    Parse(String),
+    // None of the entry points declare push constants
+    MissingPushConstants,
+    // Descriptions of every field which differs between the host and the
+    // shader
+    Mismatch(Vec<String>),
+    // The module has no entry point with this name
+    MissingEntryPoint(String),
+    // Descriptions of every resource or vertex input an entry point uses but
+    // the pipeline doesn't provide
+    Interface(Vec<String>),
+}
+
+impl fmt::Display for ShaderLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderLayoutError::Parse(error) => write!(f, "Could not reflect the shader: {error}"),
+            ShaderLayoutError::MissingPushConstants => {
+                write!(f, "The shader doesn't declare any push constants")
+            }
+            ShaderLayoutError::Mismatch(differences) => write!(
+                f,
+                "ShaderConstants differs between the host and the shader, rebuild the shader \
+                 crate:\n{}",
+                differences.join("\n")
+            ),
+            ShaderLayoutError::MissingEntryPoint(name) => {
+                write!(f, "The shader has no entry point named {name}")
+            }
+            ShaderLayoutError::Interface(differences) => write!(
+                f,
+                "The pipeline doesn't provide what the shader uses:\n{}",
+                differences.join("\n")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ShaderLayoutError {}
+
+// Layout of ShaderConstants as compiled for the host, in field order
+pub fn shader_constants_layout() -> Vec<FieldLayout> {
+    let constants = ShaderConstants::zeroed();
+    let base = addr_of!(constants) as usize;
+    macro_rules! field {
+        ($name:ident) => {
+            FieldLayout {
+                name: stringify!($name),
+                offset: (addr_of!(constants.$name) as usize - base) as u32,
+                size: size_of_val(&constants.$name) as u32,
+            }
+        };
+    }
+
+    vec![
+        field!(surface_size),
+        field!(atlas_size),
+        field!(clip),
+        field!(target_size),
+        field!(camera_offset),
+        field!(camera_rotation),
+        field!(camera_zoom),
+        field!(antialiasing_width),
+        field!(scroll_offset),
+        field!(time),
+        field!(delta_time),
+        field!(frame_index),
+        field!(scale_factor),
+        field!(y_direction),
+        field!(_padding),
+    ]
+}
+
+// Parses the spirv module for the reflection based checks
+pub fn reflect_shader(spirv: &[u8]) -> Result<Module, ShaderLayoutError> {
+    spv::parse_u8_slice(spirv, &spv::Options::default())
+        .map_err(|error| ShaderLayoutError::Parse(error.to_string()))
+}
+
+// Reflects the spirv module and checks that the push constants of every entry
+// point have the layout of ShaderConstants on the host. A mismatch means the
+// bytes written by the renderer are read as different fields by the shader.
+pub fn verify_shader_constants(spirv: &[u8]) -> Result<(), ShaderLayoutError> {
+    verify_push_constants(&reflect_shader(spirv)?, &shader_constants_layout())
+}
+
+pub(crate) fn verify_push_constants(
+    module: &Module,
+    expected: &[FieldLayout],
+) -> Result<(), ShaderLayoutError> {
+    let mut found = false;
+    let mut differences: Vec<String> = Vec::new();
+    // Every entry point has its own push constant variable, usually with the
+    // same type, so each difference is only reported once
+    let mut report = |difference: String| {
+        if !differences.contains(&difference) {
+            differences.push(difference);
+        }
+    };
+    for (_, variable) in module.global_variables.iter() {
+        if variable.space != AddressSpace::PushConstant {
+            continue;
+        }
+        found = true;
+
+        // Rust-gpu wraps the struct in a block with the struct as its only
+        // member
+        let mut ty = &module.types[variable.ty].inner;
+        if let TypeInner::Struct { members, .. } = ty {
+            if let [member] = members.as_slice() {
+                if let inner @ TypeInner::Struct { .. } = &module.types[member.ty].inner {
+                    ty = inner;
+                }
+            }
+        }
+        let TypeInner::Struct { members, .. } = ty else {
+            report("the push constants are not a struct".to_string());
+            continue;
+        };
+
+        if members.len() != expected.len() {
+            report(format!(
+                "the host has {} fields but the shader has {}",
+                expected.len(),
+                members.len()
+            ));
+        }
+        for (field, member) in expected.iter().zip(members) {
+            let name = member.name.as_deref().unwrap_or(field.name);
+            let size = module.types[member.ty].inner.size(module.to_ctx());
+            if name != field.name {
+                report(format!(
+                    "field {} on the host is {name} in the shader",
+                    field.name
+                ));
+            }
+            if member.offset != field.offset || size != field.size {
+                report(format!(
+                    "{} is {} bytes at offset {} on the host but {size} bytes at offset {} in \
+                     the shader",
+                    field.name, field.size, field.offset, member.offset
+                ));
+            }
+        }
+    }
+
+    if !found {
+        Err(ShaderLayoutError::MissingPushConstants)
+    } else if !differences.is_empty() {
+        Err(ShaderLayoutError::Mismatch(differences))
+    } else {
+        Ok(())
+    }
+}
+
+// Resources and vertex inputs a pipeline provides to its entry points. The
+// bind group layouts are indexed by group.
+pub struct PipelineInterface<'a> {
+    pub entry_points: &'a [&'a str],
+    pub bind_group_layouts: &'a [&'a [BindGroupLayoutEntry]],
+    pub vertex_buffers: &'a [VertexBufferLayout<'a>],
+}
+
+// Checks that every resource binding and vertex input the entry points use is
+// declared by the pipeline with a matching type and visibility. Wgpu rejects
+// such pipelines too, but without naming the binding at fault.
+pub fn verify_pipeline_interface(
+    module: &Module,
+    interface: &PipelineInterface,
+) -> Result<(), ShaderLayoutError> {
+    let mut differences = Vec::new();
+    for name in interface.entry_points {
+        let entry_point = module
+            .entry_points
+            .iter()
+            .find(|entry_point| entry_point.name == *name)
+            .ok_or_else(|| ShaderLayoutError::MissingEntryPoint(name.to_string()))?;
+        let stage = match entry_point.stage {
+            ShaderStage::Vertex => ShaderStages::VERTEX,
+            ShaderStage::Fragment => ShaderStages::FRAGMENT,
+            ShaderStage::Compute => ShaderStages::COMPUTE,
+        };
+
+        let mut globals = Vec::new();
+        let mut visited = Vec::new();
+        used_globals(module, &entry_point.function, &mut globals, &mut visited);
+        for handle in globals {
+            let variable = &module.global_variables[handle];
+            let Some(binding) = &variable.binding else {
+                continue;
+            };
+            let location = format!(
+                "{name} uses group {} binding {}",
+                binding.group, binding.binding
+            );
+
+            let Some(layout) = interface.bind_group_layouts.get(binding.group as usize) else {
+                differences.push(format!(
+                    "{location}, but the pipeline only has {} bind groups",
+                    interface.bind_group_layouts.len()
+                ));
+                continue;
+            };
+            let Some(entry) = layout.iter().find(|entry| entry.binding == binding.binding) else {
+                differences.push(format!(
+                    "{location}, which the bind group layout doesn't declare"
+                ));
+                continue;
+            };
+
+            if !entry.visibility.contains(stage) {
+                differences.push(format!(
+                    "{location}, which isn't visible to the {stage:?} stage"
+                ));
+            }
+            if !binding_matches(module, variable, &entry.ty, entry.count.is_some()) {
+                differences.push(format!(
+                    "{location} as {}, but the layout declares {:?}",
+                    describe_global(module, variable),
+                    entry.ty
+                ));
+            }
+        }
+
+        if entry_point.stage == ShaderStage::Vertex {
+            for location in vertex_locations(module, &entry_point.function) {
+                let provided = interface.vertex_buffers.iter().any(|buffer| {
+                    buffer
+                        .attributes
+                        .iter()
+                        .any(|attribute| attribute.shader_location == location)
+                });
+                if !provided {
+                    differences.push(format!(
+                        "{name} reads vertex location {location}, which no vertex buffer provides"
+                    ));
+                }
+            }
+        }
+    }
+
+    if differences.is_empty() {
+        Ok(())
+    } else {
+        Err(ShaderLayoutError::Interface(differences))
+    }
+}
+
+// Collects the global variables referenced by the function and every function
+// it calls
+fn used_globals(
+    module: &Module,
+    function: &Function,
+    globals: &mut Vec<Handle<GlobalVariable>>,
+    visited: &mut Vec<Handle<Function>>,
+) {
+    let mut calls = Vec::new();
+    for (_, expression) in function.expressions.iter() {
+        match expression {
+            Expression::GlobalVariable(handle) if !globals.contains(handle) => {
+                globals.push(*handle)
+            }
+            Expression::CallResult(callee) => calls.push(*callee),
+            _ => {}
+        }
+    }
+    block_calls(&function.body, &mut calls);
+
+    for callee in calls {
+        if !visited.contains(&callee) {
+            visited.push(callee);
+            used_globals(module, &module.functions[callee], globals, visited);
+        }
+    }
+}
+
+fn block_calls(block: &Block, calls: &mut Vec<Handle<Function>>) {
+    for statement in block.iter() {
+        match statement {
+            Statement::Call { function, .. } => calls.push(*function),
+            Statement::Block(block) => block_calls(block, calls),
+            Statement::If { accept, reject, .. } => {
+                block_calls(accept, calls);
+                block_calls(reject, calls);
+            }
+            Statement::Switch { cases, .. } => {
+                for case in cases {
+                    block_calls(&case.body, calls);
+                }
+            }
+            Statement::Loop {
+                body, continuing, ..
+            } => {
+                block_calls(body, calls);
+                block_calls(continuing, calls);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Locations of the vertex inputs, including those of struct members
+fn vertex_locations(module: &Module, function: &Function) -> Vec<u32> {
+    let mut locations = Vec::new();
+    for argument in function.arguments.iter() {
+        match &argument.binding {
+            Some(Binding::Location { location, .. }) => locations.push(*location),
+            Some(Binding::BuiltIn(_)) => {}
+            None => {
+                if let TypeInner::Struct { members, .. } = &module.types[argument.ty].inner {
+                    locations.extend(members.iter().filter_map(|member| match member.binding {
+                        Some(Binding::Location { location, .. }) => Some(location),
+                        _ => None,
+                    }));
+                }
+            }
+        }
+    }
+    locations
+}
+
+fn binding_matches(
+    module: &Module,
+    variable: &GlobalVariable,
+    ty: &BindingType,
+    has_count: bool,
+) -> bool {
+    let mut inner = &module.types[variable.ty].inner;
+    if let TypeInner::BindingArray { base, .. } = inner {
+        if !has_count {
+            return false;
+        }
+        inner = &module.types[*base].inner;
+    }
+
+    match (variable.space, inner, ty) {
+        (
+            AddressSpace::Storage { access },
+            _,
+            BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                ..
+            },
+        ) => !read_only || !access.contains(StorageAccess::STORE),
+        (
+            AddressSpace::Uniform,
+            _,
+            BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                ..
+            },
+        ) => true,
+        (
+            AddressSpace::Handle,
+            TypeInner::Image {
+                class: ImageClass::Sampled { multi, .. },
+                ..
+            },
+            BindingType::Texture {
+                sample_type,
+                multisampled,
+                ..
+            },
+        ) => multi == multisampled && *sample_type != TextureSampleType::Depth,
+        (
+            AddressSpace::Handle,
+            TypeInner::Image {
+                class: ImageClass::Depth { multi },
+                ..
+            },
+            BindingType::Texture {
+                sample_type,
+                multisampled,
+                ..
+            },
+        ) => multi == multisampled && *sample_type == TextureSampleType::Depth,
+        (
+            AddressSpace::Handle,
+            TypeInner::Image {
+                class: ImageClass::Storage { .. },
+                ..
+            },
+            BindingType::StorageTexture { .. },
+        ) => true,
+        (AddressSpace::Handle, TypeInner::Sampler { comparison }, BindingType::Sampler(ty)) => {
+            *comparison == (*ty == SamplerBindingType::Comparison)
+        }
+        _ => false,
+    }
+}
+
+fn describe_global(module: &Module, variable: &GlobalVariable) -> &'static str {
+    match (variable.space, &module.types[variable.ty].inner) {
+        (AddressSpace::Storage { access }, _) if access.contains(StorageAccess::STORE) => {
+            "a writable storage buffer"
+        }
+        (AddressSpace::Storage { .. }, _) => "a read only storage buffer",
+        (AddressSpace::Uniform, _) => "a uniform buffer",
+        (_, TypeInner::BindingArray { .. }) => "a binding array",
+        (
+            _,
+            TypeInner::Image {
+                class: ImageClass::Storage { .. },
+                ..
+            },
+        ) => "a storage texture",
+        (
+            _,
+            TypeInner::Image {
+                class: ImageClass::Depth { .. },
+                ..
+            },
+        ) => "a depth texture",
+        (_, TypeInner::Image { .. }) => "a sampled texture",
+        (_, TypeInner::Sampler { comparison: true }) => "a comparison sampler",
+        (_, TypeInner::Sampler { .. }) => "a sampler",
+        _ => "an unknown resource",
+    }
+}
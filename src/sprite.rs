@@ -1,163 +1,195 @@
-use std::{collections::HashMap, marker::PhantomData};
+use std::{
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+};
 
-use etagere::{size2, AllocId, AtlasAllocator};
-use glam::vec2;
-use image::GenericImageView;
-use rust_embed::RustEmbed;
-use shader::{InstancedSprite, ShaderConstants};
+use glam::{vec4, Vec4Swizzles};
+use shader::{InstancedSprite, ShaderConstants, MAX_IMAGE_PAGES};
 use wgpu::*;
 
 use crate::{
-    renderer::Drawable,
+    image_atlas::ImageAtlas,
+    memory::MemoryReport,
+    renderer::{rects_overlap, visible_content_rect, Drawable},
     scene::{Layer, Sprite},
-    Renderer, ATLAS_SIZE,
+    state_sorting::{grow_rect, sort_by_state, state_runs, StateSortingStats},
+    uploader::Uploader,
+    PipelineInterface, Renderer,
 };
 
-pub struct SpriteState<A: RustEmbed> {
+// Layout of the instances in the gpu buffer. Packed to halve the upload size
+// unless the f32-instances feature is enabled, which allows comparing the
+// output against full precision instances.
+#[cfg(not(feature = "f32-instances"))]
+type GpuSprite = shader::PackedSprite;
+#[cfg(feature = "f32-instances")]
+type GpuSprite = InstancedSprite;
+
+#[cfg(not(feature = "f32-instances"))]
+const ENTRY_POINTS: [&str; 2] = ["sprite::sprite_vertex", "sprite::sprite_fragment"];
+#[cfg(feature = "f32-instances")]
+const ENTRY_POINTS: [&str; 2] = ["sprite::sprite_vertex_f32", "sprite::sprite_fragment_f32"];
+
+#[cfg(not(feature = "f32-instances"))]
+const PAGED_ENTRY_POINTS: [&str; 2] = ["sprite::sprite_vertex", "sprite::sprite_fragment_paged"];
+#[cfg(feature = "f32-instances")]
+const PAGED_ENTRY_POINTS: [&str; 2] = [
+    "sprite::sprite_vertex_f32",
+    "sprite::sprite_fragment_paged_f32",
+];
+
+// Optional features for sampling the page of each sprite from a binding array,
+// which are requested when the adapter supports them
+pub(crate) const TEXTURE_ARRAY_FEATURES: Features = Features::TEXTURE_BINDING_ARRAY
+    .union(Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+
+pub struct SpriteState {
     buffer: Buffer,
-    atlas_texture: Texture,
-    bind_group: BindGroup,
+    bind_group_layout: BindGroupLayout,
+    // A single bind group with every page when binding arrays are supported,
+    // and one per page of the image atlas otherwise
+    bind_groups: Vec<BindGroup>,
+    // Pages of the image atlas when the bind groups were created
+    bound_pages: usize,
+    texture_arrays: bool,
     render_pipeline: RenderPipeline,
+    // Groups the sprites on the same page which don't overlap
+    state_sorting: bool,
+    state_sorting_stats: StateSortingStats,
 
-    image_lookup: HashMap<String, AllocId>,
-    atlas_allocator: AtlasAllocator,
-    _assets: PhantomData<*const A>,
+    image_atlas: Arc<Mutex<ImageAtlas>>,
 }
 
-impl<A: RustEmbed> SpriteState<A> {
-    pub fn upload_sprite(&mut self, queue: &Queue, sprite: &Sprite) -> InstancedSprite {
-        let allocation_rectangle = if let Some(alloc_id) = self.image_lookup.get(&sprite.texture) {
-            self.atlas_allocator.get(*alloc_id)
-        } else {
-            let image_file = A::get(&sprite.texture).unwrap();
-            let image = image::load_from_memory(image_file.data.as_ref()).unwrap();
-            let data = image.to_rgba8();
-            let (image_width, image_height) = image.dimensions();
-
-            let allocation = self
-                .atlas_allocator
-                .allocate(size2(image_width as i32, image_height as i32))
-                .expect("Could not allocate glyph to atlas");
-
-            self.image_lookup
-                .insert(sprite.texture.clone(), allocation.id);
-
-            queue.write_texture(
-                ImageCopyTexture {
-                    texture: &self.atlas_texture,
-                    mip_level: 0,
-                    origin: Origin3d {
-                        x: allocation.rectangle.min.x as u32,
-                        y: allocation.rectangle.min.y as u32,
-                        z: 0,
-                    },
-                    aspect: TextureAspect::All,
-                },
-                &data,
-                ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * image_width as u32),
-                    rows_per_image: Some(image_height as u32),
-                },
-                Extent3d {
-                    width: image_width as u32,
-                    height: image_height as u32,
-                    depth_or_array_layers: 1,
-                },
-            );
-
-            allocation.rectangle
-        };
+impl SpriteState {
+    pub fn upload_sprite(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        sprite: &Sprite,
+    ) -> InstancedSprite {
+        let (page, atlas_rect) =
+            self.image_atlas
+                .lock()
+                .unwrap()
+                .get_or_upload_paged(device, queue, &sprite.texture);
 
         InstancedSprite {
             top_left: sprite.top_left,
             size: sprite.size,
-            atlas_top_left: vec2(
-                allocation_rectangle.min.x as f32,
-                allocation_rectangle.min.y as f32,
-            ),
-            atlas_size: vec2(
-                allocation_rectangle.width() as f32,
-                allocation_rectangle.height() as f32,
-            ),
+            atlas_top_left: atlas_rect.xy(),
+            atlas_size: atlas_rect.zw(),
             color: sprite.color,
+            page,
+            snap: sprite.snap.to_shader(),
+            ..Default::default()
         }
     }
+
+    pub fn set_state_sorting(&mut self, enabled: bool) {
+        self.state_sorting = enabled;
+    }
+
+    // Bind group changes since the last call
+    pub fn take_state_sorting_stats(&mut self) -> StateSortingStats {
+        std::mem::take(&mut self.state_sorting_stats)
+    }
+
+    // Creates the bind groups again when the image atlas gained pages
+    fn update_bind_groups(&mut self, device: &Device) {
+        let image_atlas = self.image_atlas.lock().unwrap();
+        let page_count = image_atlas.page_count();
+        if page_count == self.bound_pages {
+            return;
+        }
+
+        let create_bind_group = |resource| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Sprite bind group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource,
+                    },
+                ],
+            })
+        };
+        self.bind_groups = if self.texture_arrays {
+            // Every element of the array has to be bound, so the slots of
+            // the missing pages repeat the first one
+            let views: Vec<_> = (0..MAX_IMAGE_PAGES)
+                .map(|page| image_atlas.page_view(if page < page_count { page } else { 0 }))
+                .collect();
+            vec![create_bind_group(BindingResource::TextureViewArray(&views))]
+        } else {
+            (0..page_count)
+                .map(|page| {
+                    create_bind_group(BindingResource::TextureView(image_atlas.page_view(page)))
+                })
+                .collect()
+        };
+        self.bound_pages = page_count;
+    }
 }
 
-impl<A: RustEmbed> Drawable for SpriteState<A> {
-    fn new(
-        Renderer {
+impl Drawable for SpriteState {
+    fn new(renderer: &Renderer) -> Self {
+        let Renderer {
             device,
             shader,
             format,
             universal_bind_group_layout,
+            image_atlas,
             ..
-        }: &Renderer,
-    ) -> Self {
+        } = renderer;
+        let texture_arrays = device.features().contains(TEXTURE_ARRAY_FEATURES);
         let buffer = device.create_buffer(&BufferDescriptor {
             label: Some("Sprite buffer"),
-            size: std::mem::size_of::<InstancedSprite>() as u64 * 100000,
+            size: std::mem::size_of::<GpuSprite>() as u64 * 100000,
             usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
 
-        let atlas_texture = device.create_texture(&TextureDescriptor {
-            label: Some("Sprite atlas texture descriptor"),
-            size: Extent3d {
-                width: ATLAS_SIZE.x as u32,
-                height: ATLAS_SIZE.y as u32,
-                depth_or_array_layers: 1,
+        let layout_entries = [
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
             },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8Unorm,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: texture_arrays.then(|| NonZeroU32::new(MAX_IMAGE_PAGES as u32).unwrap()),
+            },
+        ];
         let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("Sprite bind group layout"),
-            entries: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Texture {
-                        sample_type: TextureSampleType::Float { filterable: true },
-                        view_dimension: TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-            ],
+            entries: &layout_entries,
         });
 
-        let atlas_texture_view = atlas_texture.create_view(&TextureViewDescriptor::default());
-
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Sprite bind group"),
-            layout: &bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(&atlas_texture_view),
-                },
-            ],
+        let entry_points = if texture_arrays {
+            PAGED_ENTRY_POINTS
+        } else {
+            ENTRY_POINTS
+        };
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &entry_points,
+            bind_group_layouts: &[&layout_entries, &renderer.universal_layout_entries()],
+            vertex_buffers: &[],
         });
 
         let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
@@ -174,12 +206,12 @@ impl<A: RustEmbed> Drawable for SpriteState<A> {
             layout: Some(&render_pipeline_layout),
             vertex: VertexState {
                 module: &shader,
-                entry_point: "sprite::sprite_vertex",
+                entry_point: entry_points[0],
                 buffers: &[],
             },
             fragment: Some(FragmentState {
                 module: &shader,
-                entry_point: "sprite::sprite_fragment",
+                entry_point: entry_points[1],
                 targets: &[Some(ColorTargetState {
                     format: *format,
                     blend: Some(BlendState::ALPHA_BLENDING),
@@ -197,44 +229,102 @@ impl<A: RustEmbed> Drawable for SpriteState<A> {
             },
             depth_stencil: None,
             multisample: MultisampleState {
-                count: 4,
+                count: renderer.sample_count(),
                 ..Default::default()
             },
             multiview: None,
         });
 
-        Self {
+        let mut state = Self {
             buffer,
-            atlas_texture,
-            bind_group,
+            bind_group_layout,
+            bind_groups: Vec::new(),
+            bound_pages: 0,
+            texture_arrays,
             render_pipeline,
+            state_sorting: true,
+            state_sorting_stats: StateSortingStats::default(),
 
-            image_lookup: HashMap::new(),
-            atlas_allocator: AtlasAllocator::new(size2(ATLAS_SIZE.x as i32, ATLAS_SIZE.y as i32)),
-            _assets: PhantomData,
-        }
+            image_atlas: image_atlas.clone(),
+        };
+        state.update_bind_groups(device);
+        state
     }
 
+    fn name(&self) -> &'static str {
+        "sprite"
+    }
+
+    // The conversion is the identity with unpacked instances
+    #[cfg_attr(feature = "f32-instances", allow(clippy::useless_conversion))]
     fn draw<'b, 'a: 'b>(
         &'a mut self,
-        queue: &Queue,
+        uploader: &mut Uploader,
         render_pass: &mut RenderPass<'b>,
         constants: ShaderConstants,
         universal_bind_group: &'a BindGroup,
         layer: &Layer,
     ) {
-        let sprites: Vec<_> = layer
+        let visible_rect = visible_content_rect(&constants, layer);
+        let mut sprites: Vec<InstancedSprite> = layer
             .sprites
             .iter()
-            .map(|sprite| self.upload_sprite(queue, sprite))
+            .filter(|sprite| rects_overlap(sprite.bounds(), visible_rect))
+            .map(|sprite| self.upload_sprite(uploader.device, uploader.queue(), sprite))
             .collect();
+        self.update_bind_groups(uploader.device);
+
+        // Without binding arrays the page is bound for each run of sprites on
+        // the same page. Sorting moves sprites into earlier runs of their page
+        // when they overlap nothing in between.
+        let page_key = |sprite: &InstancedSprite| sprite.page;
+        let runs = if self.texture_arrays {
+            let runs = usize::from(!sprites.is_empty());
+            self.state_sorting_stats.add(StateSortingStats {
+                rebinds_unsorted: runs,
+                rebinds_sorted: runs,
+            });
+            Vec::new()
+        } else {
+            let unsorted_runs = state_runs(&sprites, page_key).len();
+            if self.state_sorting {
+                let margin = (constants.antialiasing_width + 1.0) / constants.camera_zoom;
+                sprites = sort_by_state(sprites, page_key, |sprite| {
+                    let rect = vec4(
+                        sprite.top_left.x,
+                        sprite.top_left.y,
+                        sprite.size.x,
+                        sprite.size.y,
+                    );
+                    grow_rect(rect, margin)
+                });
+            }
+            let runs = state_runs(&sprites, page_key);
+            self.state_sorting_stats.add(StateSortingStats {
+                rebinds_unsorted: unsorted_runs,
+                rebinds_sorted: runs.len(),
+            });
+            runs
+        };
+        let sprites: Vec<GpuSprite> = sprites.into_iter().map(GpuSprite::from).collect();
 
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_push_constants(ShaderStages::all(), 0, bytemuck::cast_slice(&[constants]));
 
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&sprites[..]));
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        uploader.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&sprites[..]));
         render_pass.set_bind_group(1, &universal_bind_group, &[]);
-        render_pass.draw(0..6, 0..sprites.len() as u32);
+        if self.texture_arrays {
+            render_pass.set_bind_group(0, &self.bind_groups[0], &[]);
+            render_pass.draw(0..6, 0..sprites.len() as u32);
+        } else {
+            for (page, instances) in runs {
+                render_pass.set_bind_group(0, &self.bind_groups[page as usize], &[]);
+                render_pass.draw(0..6, instances);
+            }
+        }
+    }
+
+    fn memory_usage(&self, report: &mut MemoryReport) {
+        report.buffers.add_buffer(&self.buffer);
     }
 }
@@ -0,0 +1,79 @@
+use std::ops::Range;
+
+use glam::{vec4, Vec4};
+
+use crate::{batching::union_rects, renderer::rects_overlap};
+
+// Pipeline and bind group changes the drawables of the last render would
+// have made when drawing in the order of the layers, and the changes they
+// made after sorting
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateSortingStats {
+    pub rebinds_unsorted: usize,
+    pub rebinds_sorted: usize,
+}
+
+impl StateSortingStats {
+    pub(crate) fn add(&mut self, other: StateSortingStats) {
+        self.rebinds_unsorted += other.rebinds_unsorted;
+        self.rebinds_sorted += other.rebinds_sorted;
+    }
+}
+
+// Reorders the items so that items with the same state key are drawn in as
+// few runs as possible. An item only moves before earlier items when it
+// doesn't overlap any of them, so the result looks the same as drawing in the
+// original order. The bounds should include the antialiasing of the items.
+pub(crate) fn sort_by_state<T>(
+    items: Vec<T>,
+    key: impl Fn(&T) -> u32,
+    bounds: impl Fn(&T) -> Vec4,
+) -> Vec<T> {
+    // State key, union of the bounds and items of each run
+    let mut runs: Vec<(u32, Vec4, Vec<T>)> = Vec::new();
+    for item in items {
+        let item_key = key(&item);
+        let item_bounds = bounds(&item);
+
+        let mut target = None;
+        for (index, (run_key, run_bounds, _)) in runs.iter().enumerate().rev() {
+            if *run_key == item_key {
+                target = Some(index);
+                break;
+            }
+            if rects_overlap(*run_bounds, item_bounds) {
+                break;
+            }
+        }
+
+        match target {
+            Some(index) => {
+                let (_, run_bounds, run_items) = &mut runs[index];
+                *run_bounds = union_rects(*run_bounds, item_bounds);
+                run_items.push(item);
+            }
+            None => runs.push((item_key, item_bounds, vec![item])),
+        }
+    }
+    runs.into_iter().flat_map(|(_, _, items)| items).collect()
+}
+
+// Instance ranges of consecutive items with the same state key, each of which
+// is drawn after one rebind
+pub(crate) fn state_runs<T>(items: &[T], key: impl Fn(&T) -> u32) -> Vec<(u32, Range<u32>)> {
+    let mut runs: Vec<(u32, Range<u32>)> = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let item_key = key(item);
+        let index = index as u32;
+        match runs.last_mut() {
+            Some((run_key, instances)) if *run_key == item_key => instances.end = index + 1,
+            _ => runs.push((item_key, index..index + 1)),
+        }
+    }
+    runs
+}
+
+// Grows a rect by the margin on every side
+pub(crate) fn grow_rect(rect: Vec4, margin: f32) -> Vec4 {
+    rect + vec4(-margin, -margin, margin * 2.0, margin * 2.0)
+}
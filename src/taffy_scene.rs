@@ -0,0 +1,161 @@
+use glam::{vec2, vec4, Vec2, Vec4, Vec4Swizzles};
+use taffy::{NodeId, Overflow, TaffyResult, TaffyTree};
+
+use crate::{
+    renderer::intersect_rects,
+    scene::{Layer, Quad, Scene, Text, TextFit},
+};
+
+// Text drawn centered in the content box of a node. It's scaled down when it
+// doesn't fit the box.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeText {
+    pub text: String,
+    pub size: f32,
+    pub color: Vec4,
+}
+
+impl NodeText {
+    pub fn new(text: String, size: f32, color: Vec4) -> Self {
+        Self { text, size, color }
+    }
+}
+
+// How a node of a taffy tree is painted. The border is drawn in the border
+// widths of the node style, with the background inside it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodePaint {
+    pub background: Option<Vec4>,
+    pub border: Option<Vec4>,
+    pub corner_radius: f32,
+    pub text: Option<NodeText>,
+    // Set on the primitives of the node, so that hit testing reports it
+    pub tag: Option<u64>,
+}
+
+impl NodePaint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_background(mut self, color: Vec4) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    pub fn with_border(mut self, color: Vec4) -> Self {
+        self.border = Some(color);
+        self
+    }
+
+    pub fn with_corner_radius(mut self, corner_radius: f32) -> Self {
+        self.corner_radius = corner_radius;
+        self
+    }
+
+    pub fn with_text(mut self, text: NodeText) -> Self {
+        self.text = Some(text);
+        self
+    }
+
+    pub fn with_tag(mut self, tag: u64) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+}
+
+// Builds a scene from the computed layout of a taffy tree. The paint function
+// maps each node to how it's drawn, typically from its node context. Nodes
+// are drawn over their parents in tree order, and nodes whose overflow isn't
+// visible clip their descendants to their padding box with a layer of that
+// clip. Within a layer the quads are drawn beneath the texts, like in any
+// other scene.
+pub fn scene_from_taffy<T>(
+    tree: &TaffyTree<T>,
+    root: NodeId,
+    mut paint: impl FnMut(NodeId) -> NodePaint,
+) -> TaffyResult<Scene> {
+    let mut scene = Scene::new();
+    add_node(tree, root, Vec2::ZERO, None, &mut paint, &mut scene)?;
+    Ok(scene)
+}
+
+fn add_node<T>(
+    tree: &TaffyTree<T>,
+    node: NodeId,
+    parent_top_left: Vec2,
+    clip: Option<Vec4>,
+    paint: &mut impl FnMut(NodeId) -> NodePaint,
+    scene: &mut Scene,
+) -> TaffyResult<()> {
+    let layout = tree.layout(node)?;
+    let top_left = parent_top_left + vec2(layout.location.x, layout.location.y);
+    let size = vec2(layout.size.width, layout.size.height);
+    let border = inset(layout.border);
+    let padding = inset(layout.padding);
+    let node_paint = paint(node);
+    let layer = layer_with_clip(scene, clip);
+
+    let tagged = |quad: Quad| match node_paint.tag {
+        Some(tag) => quad.with_tag(tag),
+        None => quad,
+    };
+    let inner_top_left = top_left + border.xy();
+    let inner_size = (size - border.xy() - border.zw()).max(Vec2::ZERO);
+    if let Some(color) = node_paint.border {
+        if border != Vec4::ZERO {
+            let quad =
+                Quad::new(top_left, size, color).with_corner_radius(node_paint.corner_radius);
+            layer.add_quad(tagged(quad));
+        }
+    }
+    if let Some(color) = node_paint.background {
+        let corner_radius = (node_paint.corner_radius - border.max_element()).max(0.0);
+        let quad = Quad::new(inner_top_left, inner_size, color).with_corner_radius(corner_radius);
+        layer.add_quad(tagged(quad));
+    }
+    if let Some(text) = node_paint.text {
+        let content_top_left = inner_top_left + padding.xy();
+        let content_size = (inner_size - padding.xy() - padding.zw()).max(Vec2::ZERO);
+        let fit = TextFit::new(content_top_left, content_size).with_max_size(text.size);
+        let mut text = Text::new(text.text, content_top_left, text.size, text.color).with_fit(fit);
+        text.tag = node_paint.tag;
+        layer.add_text(text);
+    }
+
+    let style = tree.style(node)?;
+    let clips = style.overflow.x != Overflow::Visible || style.overflow.y != Overflow::Visible;
+    let child_clip = if clips {
+        let padding_box = vec4(
+            inner_top_left.x,
+            inner_top_left.y,
+            inner_size.x,
+            inner_size.y,
+        );
+        Some(clip.map_or(padding_box, |clip| intersect_rects(clip, padding_box)))
+    } else {
+        clip
+    };
+    for child in tree.children(node)? {
+        add_node(tree, child, top_left, child_clip, paint, scene)?;
+    }
+    Ok(())
+}
+
+// Left, top, right and bottom widths of a taffy rect
+fn inset(rect: taffy::Rect<f32>) -> Vec4 {
+    vec4(rect.left, rect.top, rect.right, rect.bottom)
+}
+
+// The last layer of the scene when it has the clip, otherwise a new
+// transparent layer with it
+fn layer_with_clip(scene: &mut Scene, clip: Option<Vec4>) -> &mut Layer {
+    if scene.layer().clip != clip {
+        scene.add_layer(Layer {
+            clip,
+            background_color: None,
+            ..Default::default()
+        });
+    }
+    scene.layers.last_mut().unwrap()
+}
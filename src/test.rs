@@ -1,11 +1,85 @@
-use std::{path::PathBuf, thread};
+mod compare;
 
-use glam::{vec2, vec4};
-use image::io::Reader as ImageReader;
+use std::{
+    f32::consts::{FRAC_PI_2, PI, TAU},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use glam::{vec2, vec3, vec4, Vec2, Vec3, Vec4};
+use image::{io::Reader as ImageReader, Rgba, Rgba32FImage, RgbaImage};
 use lazy_static::lazy_static;
 use rust_embed::RustEmbed;
+use shader::{
+    caret_color, checkerboard_cell, checkerboard_cell_pixels, dim_color, fade_opacity,
+    gradient_color, pack_f16x2, quad_visible, shape_distance, InstancedGlyph,
+    InstancedGradientStop, PackedGlyph, PackedSprite, ShaderConstants, VideoConversion,
+    CARET_COLOR_CONTRAST, CARET_COLOR_FIXED, CARET_COLOR_INVERT, PIXEL_SNAP_NONE, PIXEL_SNAP_ROUND,
+    PIXEL_SNAP_ROUND_HALF, SHAPE_ARC, SHAPE_PAINT_COLOR, SHAPE_PIE, SHAPE_POLYGON, SHAPE_RING,
+    SHAPE_SQUIRCLE, SHAPE_STAR,
+};
+use swash::shape::ShapeContext;
+use wgpu::{
+    util::{DrawIndexedIndirectArgs, DrawIndirectArgs},
+    AdapterInfo, Backend, Backends, DeviceType, DownlevelCapabilities, DownlevelFlags, Features,
+    Limits, ShaderModel, TextureFormat,
+};
 
-use crate::{offscreen_renderer::OffscreenRenderer, scene::Scene, Layer, Path, Quad, Sprite, Text};
+use crate::{
+    adapter::{parse_backends, select_adapter},
+    asset_source::SharedAssets,
+    batching::batch_layers,
+    capabilities::Capabilities,
+    char_width,
+    font::{parse_styled_font_name, styled_font_name, Font},
+    glyph::{
+        box_drawing::rasterize_box_drawing,
+        cell_clusters,
+        fit::layout_text,
+        missing_glyph::rasterize_missing_glyph,
+        scale_glyph_instance,
+        shaping::{ShapeKey, ShapingCache},
+        tabs::expand_tabs,
+        zoom::ZoomRasterization,
+    },
+    grapheme_width,
+    indirect::draw_offsets,
+    ipc::{layer_assets, read_message, write_message},
+    lod::{scale_background_blur, LodController},
+    memory::entries_over_budget,
+    occlusion::hidden_layers,
+    offscreen_renderer::{half_floats, OffscreenRenderer},
+    path::{PathState, TessellationCache},
+    quad::{
+        is_opaque, merge_adjacent_quads,
+        shadow_cache::{rasterize_shadow_mask, ShadowKey},
+        QuadState,
+    },
+    renderer::{fit_texture_size, scissor_rect},
+    ruler_step,
+    scene::{load_scene, parse_scene, Scene},
+    shader_layout::{
+        shader_constants_layout, verify_pipeline_interface, verify_push_constants, FieldLayout,
+        PipelineInterface, ShaderLayoutError,
+    },
+    state_sorting::{sort_by_state, state_runs},
+    text_width,
+    video::{check_frame, yuv_to_rgb},
+    Anchor, AssetSource, BackdropBlurQuad, Camera, Caret, CaretColorMode, CaretShape, Checkerboard,
+    ColorLut, ColorLutError, CoordinateOrigin, CursorTrail, DesignOverlay, DirectoryAssets,
+    Drawable, DrawableError, Easing, EmbeddedAssets, ExternalImage, ExternalTexture, FillRule,
+    FontFeature, FramePacer, GlyphOverride, GlyphZoomPolicy, GradientStop, GridCell, Guide,
+    Hinting, IndirectDraws, IpcMessage, Keyframes, Layer, LayerDimming, LodLevel, LodPolicy,
+    MemoryAssets, OffscreenFormat, OffscreenImage, Outline, PacingMode, Path, Pattern, PixelSnap,
+    Placement, PrimitiveKind, Procedural, ProceduralKind, Quad, Renderer, RingCap,
+    SceneFormatError, Shadow, Shape, ShapeKind, Sprite, TabStops, Text, TextBackground, TextFit,
+    TextGrid, TextMetrics, TextOverflow, TextPaint, TextRendering, Theme, ThemeField, Underline,
+    UnderlineStyle, Uploader, VideoFormat, VideoFrame, VideoFrameError, VideoTexture, Viewport,
+    YuvMatrix, YuvRange, ATLAS_SIZE, IPC_PROTOCOL_VERSION,
+};
+use compare::{compare, Tolerance};
 
 #[derive(RustEmbed)]
 #[folder = "test_data/assets"]
@@ -15,7 +89,16 @@ lazy_static! {
     static ref TEMP_DIR: PathBuf = std::env::temp_dir();
 }
 
+// Set to write the rendered images of regression tests without a baseline as
+// their baselines. The golden tests which are ignored until they have one
+// render it with VIDE_UPDATE_BASELINES=1 cargo test -- --ignored.
+const UPDATE_BASELINES_VAR: &str = "VIDE_UPDATE_BASELINES";
+
 fn assert_no_regressions(width: u32, height: u32, scene: Scene) {
+    assert_no_regressions_with(width, height, scene, Tolerance::default());
+}
+
+fn assert_no_regressions_with(width: u32, height: u32, scene: Scene, tolerance: Tolerance) {
     let thread = thread::current();
     let test_name = thread
         .name()
@@ -35,27 +118,40 @@ fn assert_no_regressions(width: u32, height: u32, scene: Scene) {
     let actual = smol::block_on(async {
         let mut renderer = OffscreenRenderer::new(width, height)
             .await
-            .with_default_drawables::<Assets>();
+            .unwrap()
+            .with_deterministic(true)
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
         renderer.draw(&scene).await
     });
 
     if let Some(expected) = expected {
-        // Compare the actual image to the expected baseline. If they are not the same,
-        // write the diff image to a temp directory and print the file path
-        let result = image_compare::rgba_hybrid_compare(&expected, &actual)
-            .expect("Images had different dimensions");
-        if result.score != 1.0 {
+        // Compare the actual image to the expected baseline. If it is not within the
+        // tolerance, write the diff image to a temp directory and print the file path
+        let failures = compare(&expected, &actual, &tolerance).failures(&tolerance);
+        if !failures.is_empty() {
+            let result = image_compare::rgba_hybrid_compare(&expected, &actual)
+                .expect("Images had different dimensions");
             let diff_path = TEMP_DIR.join(format!("{}.png", test_name));
             let diff_image = result.image.to_color_map();
             diff_image.save(&diff_path).unwrap();
             panic!(
-                "Regression detected. Diff image saved to {}",
+                "Regression detected: {}. Diff image saved to {}",
+                failures.join(", "),
                 diff_path.display()
             );
         }
-    } else {
-        // No baseline file exists. Write the actual to disk as the new baseline
+    } else if std::env::var_os(UPDATE_BASELINES_VAR).is_some() {
+        // No baseline file exists. Write the actual to disk as the new baseline,
+        // which has to be reviewed before committing it
         actual.save(&expected_path).unwrap();
+    } else {
+        let actual_path = TEMP_DIR.join(format!("{}.png", test_name));
+        actual.save(&actual_path).unwrap();
+        panic!(
+            "No baseline at {expected_path}. The rendered image was saved to {}, run with \
+             {UPDATE_BASELINES_VAR}=1 to write it as the baseline",
+            actual_path.display()
+        );
     }
 }
 
@@ -183,3 +279,3488 @@ fn simple_blurred_quad() {
 
     assert_no_regressions(325, 325, scene);
 }
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn simple_camera() {
+    let scene = Scene::new()
+        .with_camera(
+            Camera::new()
+                .with_offset(vec2(-20., -10.))
+                .with_zoom(1.5)
+                .with_rotation(0.2),
+        )
+        .with_quad(
+            Quad::new(vec2(10., 10.), vec2(50., 50.), vec4(0., 0., 1., 1.)).with_corner_radius(5.),
+        )
+        .with_text(Text::new(
+            "Zoomed".to_owned(),
+            vec2(10., 90.),
+            16.,
+            vec4(0., 0., 0., 1.),
+        ));
+
+    assert_no_regressions(150, 150, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn simple_scroll() {
+    let mut layer = Layer::new()
+        .with_clip(vec4(10., 10., 80., 80.))
+        .with_scroll_offset(vec2(0., 35.));
+    for i in 0..10 {
+        layer.add_quad(Quad::new(
+            vec2(15., 15. + i as f32 * 20.),
+            vec2(70., 15.),
+            vec4(i as f32 / 10., 0., 1., 1.),
+        ));
+    }
+
+    assert_no_regressions(100, 100, Scene::new().with_layer(layer));
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn path_builder_shapes() {
+    let scene = Scene::new()
+        .with_path(
+            Path::builder()
+                .rounded_rect(vec2(10., 10.), vec2(80., 50.), 10.)
+                .build()
+                .with_fill(vec4(0., 0., 1., 1.)),
+        )
+        .with_path(
+            Path::builder()
+                .circle(vec2(50., 120.), 30.)
+                .build()
+                .with_stroke(3., vec4(1., 0., 0., 1.)),
+        )
+        .with_path(
+            Path::builder()
+                .move_to(vec2(110., 10.))
+                .arc_to(vec2(40., 40.), 0., false, true, vec2(190., 90.))
+                .line_to(vec2(110., 90.))
+                .close()
+                .ellipse(vec2(150., 130.), vec2(40., 20.))
+                .build()
+                .with_fill(vec4(0., 1., 0., 1.)),
+        );
+
+    assert_no_regressions(200, 160, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn path_fill_rules() {
+    let donut = |center| {
+        Path::builder()
+            .circle(center, 40.)
+            .circle(center, 20.)
+            .build()
+            .with_fill(vec4(0., 0., 1., 1.))
+    };
+
+    let scene = Scene::new()
+        .with_path(donut(vec2(50., 50.)).with_fill_rule(FillRule::EvenOdd))
+        .with_path(donut(vec2(150., 50.)).with_fill_rule(FillRule::NonZero));
+
+    assert_no_regressions(200, 100, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn path_boolean_ops() {
+    let square = Path::builder()
+        .rect(vec2(10., 10.), vec2(50., 50.))
+        .build()
+        .with_fill(vec4(0., 0., 1., 1.));
+    let circle = Path::builder().circle(vec2(60., 60.), 30.).build();
+
+    let scene = Scene::new()
+        .with_path(square.union(&circle))
+        .with_path(square.intersection(&circle).with_fill(vec4(1., 0., 0., 1.)));
+
+    assert_no_regressions(100, 100, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn pattern_fills() {
+    let scene = Scene::new()
+        .with_quad(
+            Quad::new(vec2(10., 10.), vec2(100., 100.), vec4(1., 1., 1., 1.))
+                .with_corner_radius(10.)
+                .with_pattern(Pattern::new("Leaf.png".to_owned()).with_scale(vec2(0.25, 0.25))),
+        )
+        .with_path(
+            Path::builder()
+                .circle(vec2(180., 60.), 50.)
+                .build()
+                .with_fill_pattern(
+                    Pattern::new("Leaf.png".to_owned())
+                        .with_scale(vec2(0.5, 0.5))
+                        .with_rotation(0.5),
+                ),
+        );
+
+    assert_no_regressions(240, 120, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn backdrop_blurs() {
+    // The blur covers the stripes drawn before it in its own layer, and the
+    // tooltip text goes in the next layer
+    let mut scene = Scene::new();
+    for index in 0..6 {
+        let color = if index % 2 == 0 {
+            vec4(0.2, 0.4, 1., 1.)
+        } else {
+            vec4(1., 0.8, 0.2, 1.)
+        };
+        scene.add_quad(Quad::new(
+            vec2(index as f32 * 20., 0.),
+            vec2(20., 100.),
+            color,
+        ));
+    }
+    scene.add_backdrop_blur(
+        BackdropBlurQuad::new(vec2(20., 30.), vec2(80., 40.), 6.)
+            .with_corner_radius(8.)
+            .with_tint(vec4(1., 1., 1., 0.3)),
+    );
+    scene.add_layer(Layer::new().without_background().with_text(Text::new(
+        "Tooltip".to_string(),
+        vec2(30., 55.),
+        14.,
+        vec4(0., 0., 0., 1.),
+    )));
+
+    // Merging the text into the layer of the blur would blur the text
+    let (_, stats) = batch_layers(scene.layers.iter(), 2.0, true);
+    assert_eq!(stats.batches, 2);
+
+    assert_no_regressions(120, 100, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn shadows() {
+    // Identical shadows share a mask wherever they are
+    let shadow = Shadow::new(vec2(10., 10.), vec2(40., 30.), 4., vec4(0., 0., 0., 0.5))
+        .with_corner_radius(6.)
+        .with_offset(vec2(0., 3.));
+    let moved = Shadow {
+        top_left: vec2(70., 10.),
+        ..shadow.clone()
+    };
+    assert_eq!(ShadowKey::new(&shadow, 1.), ShadowKey::new(&moved, 1.));
+    assert_ne!(ShadowKey::new(&shadow, 1.), ShadowKey::new(&shadow, 2.));
+
+    // The mask is opaque inside the shape and clear at its edges
+    let key = ShadowKey::new(&shadow, 1.);
+    let (width, height, data) = rasterize_shadow_mask(&key, 1.);
+    assert_eq!((width, height), (40 + 26, 30 + 26));
+    let alpha = |x: u32, y: u32| data[((y * width + x) * 4 + 3) as usize];
+    assert_eq!(alpha(width / 2, height / 2), 255);
+    assert_eq!(alpha(0, 0), 0);
+    assert_eq!(alpha(width - 1, height / 2), 0);
+
+    // Large shadows are stretched from smaller masks only when their blur
+    // hides it
+    let panel = Shadow::new(Vec2::ZERO, vec2(1000., 600.), 8., Vec4::ONE);
+    assert_eq!(ShadowKey::new(&panel, 1.).mask_scale(), None);
+    let soft_panel = Shadow { blur: 40., ..panel };
+    assert!(ShadowKey::new(&soft_panel, 1.).mask_scale().unwrap() < 1.);
+
+    let mut scene = Scene::new();
+    for index in 0..2 {
+        let top_left = vec2(10. + index as f32 * 60., 10.);
+        scene.add_shadow(Shadow {
+            top_left,
+            ..shadow.clone()
+        });
+        scene.add_quad(
+            Quad::new(top_left, vec2(40., 30.), vec4(1., 1., 1., 1.)).with_corner_radius(6.),
+        );
+    }
+    assert_no_regressions(130, 60, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn elevation() {
+    let card = Quad::new(vec2(20., 20.), vec2(60., 40.), vec4(1., 1., 1., 1.))
+        .with_corner_radius(4.)
+        .with_elevation(8.);
+    let shadows = card.elevation_shadows(1.);
+    assert_eq!(shadows.len(), 2);
+    // The key shadow is cast downwards, which is negative y when the y axis
+    // points up
+    assert_eq!(shadows[1].offset, vec2(0., 4.));
+    assert_eq!(card.elevation_shadows(-1.)[1].offset, vec2(0., -4.));
+    assert!(card
+        .clone()
+        .with_elevation(0.)
+        .elevation_shadows(1.)
+        .is_empty());
+    assert_eq!(card.bounds(), vec4(-4., -4., 108., 88.));
+
+    let mut scene = Scene::new().with_background(vec4(0.9, 0.9, 0.9, 1.));
+    for (index, elevation) in [1., 4., 12.].into_iter().enumerate() {
+        scene.add_quad(
+            Quad::new(
+                vec2(20. + index as f32 * 80., 20.),
+                vec2(60., 40.),
+                vec4(1., 1., 1., 1.),
+            )
+            .with_corner_radius(4.)
+            .with_elevation(elevation),
+        );
+    }
+    assert_no_regressions(260, 90, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn outlines() {
+    let ring = Outline::new(vec4(0.2, 0.4, 1., 1.), 2.).with_softness(3.);
+    assert_eq!(ring.extent(), 5.);
+    let button = Quad::new(vec2(20., 20.), vec2(80., 30.), vec4(1., 1., 1., 1.))
+        .with_corner_radius(6.)
+        .with_outline(ring);
+    assert_eq!(button.bounds(), vec4(15., 15., 90., 40.));
+    assert_eq!(button.to_instanced().extension(), 5.);
+    let badge = Path::builder()
+        .circle(vec2(40., 120.), 20.)
+        .build()
+        .with_fill(vec4(1., 0.8, 0., 1.))
+        .with_outline(Outline::new(vec4(0., 0., 0., 1.), 3.));
+    assert!(badge.bounds().abs_diff_eq(vec4(17., 97., 46., 46.), 0.001));
+    assert_ne!(
+        badge.geometry_hash(),
+        badge.clone().with_outline(ring).geometry_hash()
+    );
+
+    let scene = Scene::new()
+        .with_background(vec4(0.9, 0.9, 0.9, 1.))
+        .with_quad(button)
+        .with_text(
+            Text::new(
+                "Glow".to_owned(),
+                vec2(120., 50.),
+                24.,
+                vec4(1., 1., 1., 1.),
+            )
+            .with_outline(Outline::new(vec4(1., 0.5, 0., 0.8), 0.).with_softness(4.)),
+        )
+        .with_path(badge);
+    assert_no_regressions(200, 150, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn design_overlay() {
+    assert_eq!(ruler_step(1., 64.), 100.);
+    assert_eq!(ruler_step(2., 64.), 50.);
+    assert_eq!(ruler_step(0.5, 64.), 200.);
+
+    let mut overlay = DesignOverlay::new()
+        .with_grid(20., vec4(0., 0., 0., 0.2))
+        .with_guide(Guide::vertical(50.))
+        .with_guide(Guide::horizontal(30.));
+    assert_eq!(overlay.guide_at(vec2(52., 80.), 4.), Some(0));
+    assert_eq!(overlay.guide_at(vec2(10., 31.), 4.), Some(1));
+    assert_eq!(overlay.guide_at(vec2(10., 80.), 4.), None);
+    overlay.move_guide(0, vec2(70., 0.));
+    assert_eq!(overlay.guides[0], Guide::vertical(70.));
+
+    let constants = ShaderConstants::new(vec2(200., 100.));
+    // Eleven vertical and six horizontal grid lines and the guides
+    assert_eq!(overlay.layer(&constants).quads.len(), 19);
+    // Grid lines a couple of pixels apart are left out
+    let zoomed_out = constants.with_camera(Vec2::ZERO, vec2(1., 0.), 0.1);
+    assert_eq!(overlay.layer(&zoomed_out).quads.len(), 2);
+
+    let overlay = overlay.with_rulers();
+    let labels: Vec<_> = overlay
+        .layer(&constants)
+        .texts
+        .into_iter()
+        .map(|text| text.text)
+        .collect();
+    assert_eq!(labels, vec!["100", "200", "100"]);
+
+    let scene = Scene::new()
+        .with_quad(Quad::new(
+            vec2(60., 40.),
+            vec2(80., 40.),
+            vec4(0.2, 0.6, 0.3, 1.),
+        ))
+        .with_layer(overlay.layer(&constants));
+    assert_no_regressions(200, 100, scene);
+}
+
+#[test]
+fn hit_test_tags() {
+    let scene = Scene::new()
+        .with_quad(Quad::new(vec2(0., 0.), vec2(100., 100.), vec4(1., 0., 0., 1.)).with_tag(1))
+        .with_path(
+            Path::builder()
+                .circle(vec2(50., 50.), 20.)
+                .build()
+                .with_fill(vec4(0., 0., 1., 1.))
+                .with_tag(2),
+        )
+        .with_layer(Layer::new().with_clip(vec4(0., 0., 10., 10.)).with_quad(
+            Quad::new(vec2(0., 0.), vec2(100., 100.), vec4(0., 1., 0., 1.)).with_tag(3),
+        ));
+
+    assert_eq!(scene.hit_test_tags(vec2(50., 50.)), vec![2, 1]);
+    assert_eq!(scene.hit_test_tags(vec2(5., 5.)), vec![3, 1]);
+    assert_eq!(scene.hit_test_tags(vec2(150., 150.)), Vec::<u64>::new());
+}
+
+#[test]
+fn text_hit_test() {
+    let black = vec4(0., 0., 0., 1.);
+    let scene = Scene::new()
+        .with_text(Text::new("Hit".to_string(), vec2(10., 30.), 20., black).with_tag(1))
+        .with_text(
+            Text::new("Fitted".to_string(), vec2(0., 0.), 20., black)
+                .with_tag(2)
+                .with_fit(TextFit::new(vec2(10., 60.), vec2(60., 30.))),
+        );
+
+    // Without the shaped width only the fitted text is hit, within its rect
+    assert_eq!(scene.hit_test_tags(vec2(40., 75.)), vec![2]);
+    assert!(scene.hit_test_tags(vec2(100., 75.)).is_empty());
+    assert!(scene.hit_test_tags(vec2(0., 75.)).is_empty());
+    assert!(scene.hit_test_tags(vec2(15., 25.)).is_empty());
+
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(200, 100)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let renderer = &mut renderer.renderer;
+        assert_eq!(renderer.hit_test_tags(&scene, vec2(15., 25.)), vec![1]);
+        // Beside the glyphs on the same line
+        assert!(renderer.hit_test_tags(&scene, vec2(5., 25.)).is_empty());
+        assert!(renderer.hit_test_tags(&scene, vec2(150., 25.)).is_empty());
+    });
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn keyframe_tween() {
+    let from = Scene::new()
+        .with_quad(Quad::new(
+            vec2(10., 10.),
+            vec2(20., 20.),
+            vec4(1., 0., 0., 1.),
+        ))
+        .with_quad(Quad::new(
+            vec2(60., 60.),
+            vec2(30., 30.),
+            vec4(0., 1., 0., 1.),
+        ));
+    let to = Scene::new().with_quad(
+        Quad::new(vec2(50., 10.), vec2(40., 40.), vec4(0., 0., 1., 1.)).with_corner_radius(20.),
+    );
+
+    let keyframes = Keyframes::new()
+        .with_easing(Easing::EaseInOut)
+        .with_keyframe(0.0, from)
+        .with_keyframe(1.0, to);
+
+    assert_no_regressions(100, 100, keyframes.sample(0.5).unwrap());
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn procedural_fills() {
+    let black = vec4(0., 0., 0., 1.);
+    let white = vec4(1., 1., 1., 1.);
+    let scene = Scene::new()
+        .with_procedural(Procedural::new(
+            ProceduralKind::LinearGradient { angle: 0.5 },
+            vec2(0., 0.),
+            vec2(100., 100.),
+            vec4(1., 0., 0., 1.),
+            vec4(0., 0., 1., 1.),
+        ))
+        .with_procedural(Procedural::new(
+            ProceduralKind::Checker { cell_size: 10. },
+            vec2(100., 0.),
+            vec2(100., 100.),
+            black,
+            white,
+        ))
+        .with_procedural(Procedural::new(
+            ProceduralKind::Grid {
+                cell_size: 20.,
+                line_width: 2.,
+            },
+            vec2(0., 100.),
+            vec2(100., 100.),
+            white,
+            black,
+        ))
+        .with_procedural(Procedural::new(
+            ProceduralKind::Noise {
+                feature_size: 20.,
+                octaves: 4,
+                speed: 0.,
+            },
+            vec2(100., 100.),
+            vec2(100., 100.),
+            black,
+            white,
+        ));
+
+    assert_no_regressions(200, 200, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn gradient_stops() {
+    let red = vec4(1., 0., 0., 1.);
+    let green = vec4(0., 1., 0., 1.);
+    let blue = vec4(0., 0., 1., 1.);
+    let stop = |offset, color| InstancedGradientStop {
+        color,
+        offset,
+        ..Default::default()
+    };
+    // An unrelated stop before the range of the instance
+    let stops = [
+        stop(0., vec4(1., 1., 1., 1.)),
+        stop(0.2, red),
+        stop(0.6, green),
+        stop(0.6, blue),
+    ];
+    assert_eq!(gradient_color(&stops, 1, 3, 0.), red);
+    assert!(gradient_color(&stops, 1, 3, 0.4).abs_diff_eq(vec4(0.5, 0.5, 0., 1.), 0.001));
+    // A hard edge between the stops at the same offset
+    assert_eq!(gradient_color(&stops, 1, 3, 0.6), green);
+    assert_eq!(gradient_color(&stops, 1, 3, 0.61), blue);
+    assert_eq!(gradient_color(&stops, 1, 3, 1.), blue);
+    assert_eq!(gradient_color(&stops, 1, 1, 0.5), red);
+
+    // Color ramp of a heatmap and a gradient with hundreds of stops
+    let heatmap = Procedural::new(
+        ProceduralKind::Noise {
+            feature_size: 20.,
+            octaves: 3,
+            speed: 0.,
+        },
+        vec2(0., 0.),
+        vec2(100., 100.),
+        vec4(0., 0., 0., 1.),
+        vec4(1., 1., 1., 1.),
+    )
+    .with_stops(vec![
+        GradientStop::new(0., vec4(0., 0., 0.5, 1.)),
+        GradientStop::new(0.3, blue),
+        GradientStop::new(0.5, green),
+        GradientStop::new(0.7, vec4(1., 1., 0., 1.)),
+        GradientStop::new(1., red),
+    ]);
+    let bands = Procedural::new(
+        ProceduralKind::LinearGradient { angle: 0. },
+        vec2(100., 0.),
+        vec2(100., 100.),
+        vec4(0., 0., 0., 1.),
+        vec4(1., 1., 1., 1.),
+    )
+    .with_stops(
+        (0..300)
+            .map(|index| {
+                let value = (index / 2 % 2) as f32;
+                GradientStop::new(index as f32 / 299., vec4(value, value, value, 1.))
+            })
+            .collect(),
+    );
+    let scene = Scene::new().with_procedural(heatmap).with_procedural(bands);
+
+    let mut invalid = scene.clone();
+    invalid.layers[0].procedurals[0].stops[2].offset = 0.1;
+    let paths: Vec<_> = invalid
+        .validate()
+        .unwrap_err()
+        .into_iter()
+        .map(|error| error.path)
+        .collect();
+    assert_eq!(paths, vec!["layers[0].procedurals[0].stops[2].offset"]);
+
+    assert_no_regressions(200, 100, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn shapes() {
+    let distance = |kind, parameters, point| shape_distance(kind, parameters, point, 10.);
+    let hexagon = vec4(6., 0., 0., 0.);
+    assert!(distance(SHAPE_POLYGON, hexagon, Vec2::ZERO) < 0.);
+    assert!(distance(SHAPE_POLYGON, hexagon, vec2(0., -10.)).abs() < 0.001);
+    let star = vec4(5., 0.5, 0., 0.);
+    assert!(distance(SHAPE_STAR, star, vec2(0., -10.)).abs() < 0.001);
+    assert!((distance(SHAPE_STAR, star, vec2(0., -11.)) - 1.).abs() < 0.001);
+    assert!(distance(SHAPE_STAR, star, vec2(0., 6.)) > 0.);
+    // A quarter from positive x towards positive y
+    let quarter = vec4(0., FRAC_PI_2, 0., 0.);
+    assert!(distance(SHAPE_PIE, quarter, vec2(5., 5.)) < 0.);
+    assert!(distance(SHAPE_PIE, quarter, vec2(-5., 5.)) > 0.);
+    let half_ring = vec4(0., PI, 2., 0.);
+    assert!(distance(SHAPE_ARC, half_ring, vec2(0., 9.)) < 0.);
+    assert!(distance(SHAPE_ARC, half_ring, vec2(0., -9.)) > 0.);
+    assert!(distance(SHAPE_ARC, half_ring, vec2(0., 5.)) > 0.);
+    // Reaches further into the corners than a circle
+    let squircle = vec4(4., 0., 0., 0.);
+    assert!(distance(SHAPE_SQUIRCLE, squircle, vec2(8., 8.)) < 0.);
+    assert!(distance(SHAPE_SQUIRCLE, squircle, vec2(9., 9.)) > 0.);
+
+    let black = vec4(0., 0., 0., 1.);
+    let pie = Shape::new(
+        ShapeKind::Pie {
+            start_angle: 0.,
+            sweep: FRAC_PI_2,
+        },
+        vec2(150., 50.),
+        40.,
+        vec4(0.9, 0.3, 0.2, 1.),
+    )
+    .with_tag(3);
+    assert_eq!(pie.bounds(), vec4(110., 10., 80., 80.));
+    assert!(pie.contains(vec2(170., 70.)));
+    assert!(!pie.contains(vec2(130., 30.)));
+    assert!(pie.clone().with_rotation(PI).contains(vec2(130., 30.)));
+
+    let scene = Scene::new()
+        .with_background(vec4(1., 1., 1., 1.))
+        .with_shape(Shape::new(
+            ShapeKind::Polygon { sides: 6 },
+            vec2(50., 50.),
+            40.,
+            black,
+        ))
+        .with_shape(pie)
+        .with_shape(
+            Shape::new(
+                ShapeKind::Star {
+                    points: 5,
+                    inner_radius: 0.4,
+                },
+                vec2(250., 50.),
+                40.,
+                vec4(1., 0.8, 0., 1.),
+            )
+            .with_rotation(0.2),
+        )
+        .with_shape(Shape::new(
+            ShapeKind::Arc {
+                start_angle: -FRAC_PI_2,
+                sweep: PI * 1.5,
+                thickness: 8.,
+            },
+            vec2(50., 150.),
+            40.,
+            vec4(0.2, 0.5, 1., 1.),
+        ))
+        .with_shape(Shape::new(
+            ShapeKind::Squircle { exponent: 4. },
+            vec2(150., 150.),
+            40.,
+            vec4(0.3, 0.7, 0.3, 1.),
+        ));
+    assert_eq!(scene.hit_test_tags(vec2(170., 70.)), vec![3]);
+    assert!(scene.hit_test_tags(vec2(130., 30.)).is_empty());
+
+    let mut invalid = scene.clone();
+    invalid.layers[0].shapes[0].kind = ShapeKind::Polygon { sides: 2 };
+    let paths: Vec<_> = invalid
+        .validate()
+        .unwrap_err()
+        .into_iter()
+        .map(|error| error.path)
+        .collect();
+    assert_eq!(paths, vec!["layers[0].shapes[0].kind.sides"]);
+
+    assert_no_regressions(300, 200, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn rings() {
+    let ring = |cap: f32| vec4(0., FRAC_PI_2, 6., cap);
+    let distance = |parameters, point| shape_distance(SHAPE_RING, parameters, point, 10.);
+    assert!(distance(ring(0.), vec2(5.6, 5.6)) < 0.);
+    assert!(distance(ring(0.), Vec2::ZERO) > 0.);
+    // Just before the start angle, where only the round cap reaches
+    assert!((distance(ring(0.), vec2(8., -1.)) - 1.).abs() < 0.001);
+    assert!((distance(ring(1.), vec2(8., -1.)) + 1.).abs() < 0.001);
+    let closed = vec4(0., TAU, 6., 0.);
+    assert!(distance(closed, vec2(-8., 0.)) < 0.);
+    assert!(distance(closed, vec2(-5., 0.)) > 0.);
+
+    let track = Shape::new(
+        ShapeKind::Ring {
+            inner_radius: 32.,
+            start_angle: 0.,
+            end_angle: TAU,
+            cap: RingCap::Butt,
+        },
+        vec2(50., 50.),
+        40.,
+        vec4(0.9, 0.9, 0.9, 1.),
+    );
+    let spinner = Shape::new(
+        ShapeKind::Ring {
+            inner_radius: 32.,
+            start_angle: -FRAC_PI_2,
+            end_angle: PI,
+            cap: RingCap::Round,
+        },
+        vec2(50., 50.),
+        40.,
+        vec4(0.2, 0.5, 1., 0.),
+    )
+    .with_paint(
+        ProceduralKind::ConicGradient {
+            start_angle: -FRAC_PI_2,
+            sweep: PI * 1.5,
+            center_offset: Vec2::ZERO,
+        },
+        vec4(0.2, 0.5, 1., 1.),
+    );
+    let instance = spinner.to_instanced();
+    assert_eq!(instance.parameters, vec4(-FRAC_PI_2, PI * 1.5, 32., 1.));
+    assert_ne!(instance.paint_kind, SHAPE_PAINT_COLOR);
+    assert_eq!(track.to_instanced().paint_kind, SHAPE_PAINT_COLOR);
+    assert!(spinner.contains(vec2(50., 14.)));
+    assert!(!spinner.contains(vec2(50., 50.)));
+
+    let scene = Scene::new()
+        .with_background(vec4(1., 1., 1., 1.))
+        .with_shape(track)
+        .with_shape(spinner)
+        .with_shape(Shape::new(
+            ShapeKind::Ring {
+                inner_radius: 20.,
+                start_angle: PI,
+                end_angle: TAU,
+                cap: RingCap::Butt,
+            },
+            vec2(150., 50.),
+            40.,
+            vec4(0.3, 0.7, 0.3, 1.),
+        ));
+
+    let mut invalid = scene.clone();
+    invalid.layers[0].shapes[0].kind = ShapeKind::Ring {
+        inner_radius: 50.,
+        start_angle: 0.,
+        end_angle: TAU,
+        cap: RingCap::Butt,
+    };
+    let paths: Vec<_> = invalid
+        .validate()
+        .unwrap_err()
+        .into_iter()
+        .map(|error| error.path)
+        .collect();
+    assert_eq!(paths, vec!["layers[0].shapes[0].kind.inner_radius"]);
+
+    assert_no_regressions(200, 100, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn conic_gradients() {
+    let track = vec4(0.9, 0.9, 0.9, 1.);
+    let scene = Scene::new()
+        // Color wheel with the seam at the top
+        .with_procedural(Procedural::new(
+            ProceduralKind::ConicGradient {
+                start_angle: -FRAC_PI_2,
+                sweep: TAU,
+                center_offset: vec2(0., 0.),
+            },
+            vec2(0., 0.),
+            vec2(100., 100.),
+            vec4(1., 0., 0., 1.),
+            vec4(0., 0., 1., 1.),
+        ))
+        // Progress indicator at 60% going counter clockwise
+        .with_procedural(Procedural::new(
+            ProceduralKind::ConicGradient {
+                start_angle: -FRAC_PI_2,
+                sweep: -TAU * 0.6,
+                center_offset: vec2(0., 0.),
+            },
+            vec2(100., 0.),
+            vec2(100., 100.),
+            vec4(0., 0.6, 0.2, 1.),
+            track,
+        ))
+        // Dial with the center moved to the bottom
+        .with_procedural(Procedural::new(
+            ProceduralKind::ConicGradient {
+                start_angle: PI,
+                sweep: PI,
+                center_offset: vec2(0., 40.),
+            },
+            vec2(0., 100.),
+            vec2(200., 100.),
+            vec4(1., 1., 0., 1.),
+            vec4(0.2, 0.2, 0.2, 1.),
+        ));
+
+    assert_no_regressions(200, 200, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn caret_shapes() {
+    let cell = vec2(10., 20.);
+    let mut caret = Caret::new(vec2(0., 0.), cell, vec4(0., 0., 0., 1.));
+    caret.move_to(vec2(100., 0.), 1., 0.5);
+    assert_eq!(caret.position_at(1.), vec2(0., 0.));
+    assert_eq!(caret.position_at(2.), vec2(100., 0.));
+    // Moving again half way glides on from the current position
+    let halfway = caret.position_at(1.25);
+    caret.move_to(vec2(0., 40.), 1.25, 0.5);
+    assert_eq!(caret.position_at(1.25), halfway);
+    assert_eq!(caret.bounds(), vec4(0., 0., halfway.x + cell.x, 60.));
+
+    let scene = Scene::new()
+        .with_text(Text::new(
+            "Caret".to_string(),
+            vec2(10., 40.),
+            20.,
+            vec4(0., 0., 0., 1.),
+        ))
+        .with_caret(Caret::new(vec2(10., 20.), cell, vec4(1., 0., 0., 1.)).with_blink_interval(0.5))
+        .with_caret(
+            Caret::new(vec2(30., 20.), cell, vec4(0., 0.5, 0., 1.))
+                .with_shape(CaretShape::Underline),
+        )
+        .with_caret(
+            Caret::new(vec2(50., 20.), cell, vec4(0., 0., 1., 0.5)).with_shape(CaretShape::Block),
+        );
+
+    assert_no_regressions(100, 60, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn caret_color_modes() {
+    let color = vec4(1., 0., 0., 0.8);
+    assert_eq!(
+        caret_color(color, CARET_COLOR_INVERT, vec3(1., 0.25, 0.)),
+        vec4(0., 0.75, 1., 0.8)
+    );
+    assert_eq!(
+        caret_color(color, CARET_COLOR_CONTRAST, vec3(0.9, 0.9, 0.9)),
+        vec4(0., 0., 0., 0.8)
+    );
+    assert_eq!(
+        caret_color(color, CARET_COLOR_CONTRAST, vec3(0.1, 0.1, 0.3)),
+        vec4(1., 1., 1., 0.8)
+    );
+    assert_eq!(caret_color(color, CARET_COLOR_FIXED, Vec3::ONE), color);
+
+    let cell = vec2(12., 24.);
+    let mut scene = Scene::new().with_background(vec4(0.1, 0.1, 0.2, 1.));
+    scene.add_quad(Quad::new(
+        vec2(50., 0.),
+        vec2(50., 60.),
+        vec4(0.95, 0.95, 0.9, 1.),
+    ));
+    for (x, color_mode) in [
+        (10., CaretColorMode::Invert),
+        (30., CaretColorMode::Contrast),
+        (60., CaretColorMode::Invert),
+        (80., CaretColorMode::Contrast),
+    ] {
+        scene.add_text(Text::new(
+            "W".to_string(),
+            vec2(x, 40.),
+            20.,
+            vec4(0.5, 0.5, 0.5, 1.),
+        ));
+        scene.add_caret(
+            Caret::new(vec2(x, 20.), cell, vec4(1., 1., 1., 1.))
+                .with_shape(CaretShape::Block)
+                .with_color_mode(color_mode),
+        );
+    }
+    assert_no_regressions(100, 60, scene);
+}
+
+#[test]
+fn checkerboard_cells() {
+    // Cells are whole surface pixels at any scale factor
+    assert_eq!(checkerboard_cell_pixels(8., 1.), 8.);
+    assert_eq!(checkerboard_cell_pixels(8., 1.5), 12.);
+    assert_eq!(checkerboard_cell_pixels(0.1, 1.), 1.);
+
+    assert_eq!(checkerboard_cell(vec2(0.5, 0.5), 8.), 0.);
+    assert_eq!(checkerboard_cell(vec2(8.5, 0.5), 8.), 1.);
+    assert_eq!(checkerboard_cell(vec2(8.5, 8.5), 8.), 0.);
+    assert_eq!(checkerboard_cell(vec2(-0.5, 0.5), 8.), 1.);
+
+    let checkerboard = Checkerboard::new(vec2(10., 10.), vec2(64., 32.))
+        .with_cell_size(4.)
+        .with_colors(vec4(1., 1., 1., 1.), vec4(0.5, 0.5, 0.5, 1.));
+    let instance = checkerboard.to_instanced();
+    assert_eq!(instance.cell_size, 4.);
+    assert_eq!(instance.second_color, vec4(0.5, 0.5, 0.5, 1.));
+    assert_eq!(checkerboard.bounds(), vec4(10., 10., 64., 32.));
+
+    let scene = Scene::new().with_checkerboard(checkerboard.with_cell_size(0.));
+    let paths: Vec<_> = scene
+        .validate()
+        .unwrap_err()
+        .into_iter()
+        .map(|error| error.path)
+        .collect();
+    assert_eq!(paths, vec!["layers[0].checkerboards[0].cell_size"]);
+}
+
+#[test]
+fn cursor_trail_corners() {
+    let from = vec4(0., 0., 10., 20.);
+    let to = vec4(100., 0., 10., 20.);
+    let trail = CursorTrail::new(from, to, vec4(1., 1., 1., 1.), 1.).with_duration(0.2);
+    assert_eq!(
+        trail.corners_at(1.),
+        [vec2(0., 0.), vec2(10., 0.), vec2(10., 20.), vec2(0., 20.)]
+    );
+    assert_eq!(
+        trail.corners_at(1.2),
+        [
+            vec2(100., 0.),
+            vec2(110., 0.),
+            vec2(110., 20.),
+            vec2(100., 20.)
+        ]
+    );
+    assert!(trail.is_finished_at(1.2));
+
+    // Moving right, the right corners lead and the left ones trail behind
+    let [top_left, top_right, ..] = trail.corners_at(1.05);
+    assert!(top_right.x - top_left.x > 10.);
+    assert_eq!(trail.bounds(), vec4(0., 0., 110., 20.));
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn text_backgrounds() {
+    let mut scene = Scene::new();
+    // Selection highlight and a pill shaped label
+    scene.add_text(
+        Text::new(
+            "Selected".to_string(),
+            vec2(10., 30.),
+            20.,
+            vec4(0., 0., 0., 1.),
+        )
+        .with_background(TextBackground::new(vec4(0.6, 0.8, 1., 1.))),
+    );
+    scene.add_text(
+        Text::new(
+            "Label".to_string(),
+            vec2(20., 75.),
+            16.,
+            vec4(1., 1., 1., 1.),
+        )
+        .with_background(
+            TextBackground::new(vec4(0.8, 0.2, 0.2, 1.))
+                .with_padding(vec2(8., 3.))
+                .with_corner_radius(11.),
+        )
+        .with_underline(Underline::new(UnderlineStyle::Single)),
+    );
+    assert_no_regressions(120, 100, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn text_paints() {
+    let mut scene = Scene::new();
+    scene.add_text(
+        Text::new(
+            "Gradient".to_string(),
+            vec2(10., 40.),
+            32.,
+            vec4(1., 0.2, 0.2, 1.),
+        )
+        .with_paint(TextPaint::Procedural {
+            kind: ProceduralKind::LinearGradient { angle: 0. },
+            secondary_color: vec4(0.2, 0.2, 1., 1.),
+        }),
+    );
+    scene.add_text(
+        Text::new(
+            "Pattern".to_string(),
+            vec2(10., 90.),
+            40.,
+            vec4(1., 1., 1., 1.),
+        )
+        .with_bold()
+        .with_paint(TextPaint::Pattern(
+            Pattern::new("Leaf.png".to_owned()).with_scale(vec2(0.25, 0.25)),
+        )),
+    );
+    assert_no_regressions(200, 100, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn glyph_overrides() {
+    // A wave moving the glyphs up and down, an emphasized glyph and one
+    // which is still hidden by a typewriter effect
+    let mut text = Text::new(
+        "Waving".to_string(),
+        vec2(10., 50.),
+        24.,
+        vec4(0., 0., 0., 1.),
+    );
+    for glyph in 0..4 {
+        let offset = vec2(0., (glyph as f32 * 1.5).sin() * 8.);
+        text = text.with_glyph_override(GlyphOverride::new(glyph).with_offset(offset));
+    }
+    let text = text
+        .with_glyph_override(
+            GlyphOverride::new(4)
+                .with_scale(1.5)
+                .with_rotation(0.3)
+                .with_color(vec4(1., 0., 0., 1.)),
+        )
+        .with_glyph_override(GlyphOverride::new(5).with_scale(0.));
+
+    // The culling bounds grow by the largest offset
+    let plain = Text::new(text.text.clone(), text.bottom_left, text.size, text.color);
+    let grown = (0..4)
+        .map(|glyph| (glyph as f32 * 1.5).sin().abs() * 8.)
+        .fold(0., f32::max);
+    assert_eq!(text.bounds().w, plain.bounds().w + grown * 2.);
+
+    assert_no_regressions(120, 80, Scene::new().with_text(text));
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn faded_text() {
+    let mut scene = Scene::new();
+    scene.add_text(
+        Text::new(
+            "Text fading out at the max width".to_string(),
+            vec2(10., 30.),
+            20.,
+            vec4(0., 0., 0., 1.),
+        )
+        .with_max_width(150.)
+        .with_overflow(TextOverflow::Fade),
+    );
+    assert_no_regressions(170, 50, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn text_hinting() {
+    let black = vec4(0., 0., 0., 1.);
+    let renderings = [
+        TextRendering::new(Hinting::None),
+        TextRendering::new(Hinting::Slight),
+        TextRendering::new(Hinting::Full),
+        TextRendering::new(Hinting::Full)
+            .with_snapped_origins()
+            .with_rounded_advances(),
+    ];
+    let mut scene = Scene::new();
+    for (index, rendering) in renderings.into_iter().enumerate() {
+        // Fractional positions to show the snapping
+        scene.add_text(
+            Text::new(
+                "Small hinted text".to_string(),
+                vec2(10.3, 20.6 + index as f32 * 16.),
+                11.,
+                black,
+            )
+            .with_rendering(rendering),
+        );
+    }
+    assert_no_regressions(120, 80, scene);
+}
+
+#[test]
+#[ignore = "no reviewed baseline in test_data yet"]
+fn underline_styles() {
+    let black = vec4(0., 0., 0., 1.);
+    let styles = [
+        UnderlineStyle::Single,
+        UnderlineStyle::Double,
+        UnderlineStyle::Dotted,
+        UnderlineStyle::Dashed { period: 6. },
+        UnderlineStyle::Wavy {
+            amplitude: 2.,
+            period: 8.,
+        },
+    ];
+    let mut scene = Scene::new();
+    for (index, style) in styles.into_iter().enumerate() {
+        scene.add_text(
+            Text::new(
+                "Underline".to_string(),
+                vec2(10., 30. + index as f32 * 30.),
+                20.,
+                black,
+            )
+            .with_underline(
+                Underline::new(style)
+                    .with_color(vec4(1., 0., 0., 1.))
+                    .with_thickness(1.5),
+            ),
+        );
+    }
+    scene.add_text(
+        Text::new("Lines".to_string(), vec2(10., 180.), 20., black)
+            .with_strikeout(Underline::new(UnderlineStyle::Single))
+            .with_overline(Underline::new(UnderlineStyle::Double)),
+    );
+
+    assert_no_regressions(120, 200, scene);
+}
+
+#[test]
+fn asset_sources() {
+    let embedded = EmbeddedAssets::<Assets>::new().load("Leaf.png").unwrap();
+    let directory = DirectoryAssets::new("./test_data/assets")
+        .load("Leaf.png")
+        .unwrap();
+    let memory = MemoryAssets::new()
+        .with_asset("Leaf.png", directory.clone())
+        .load("Leaf.png")
+        .unwrap();
+
+    assert_eq!(embedded, directory);
+    assert_eq!(memory, directory);
+    assert!(DirectoryAssets::new("./test_data/assets")
+        .load("Missing.png")
+        .is_none());
+}
+
+#[test]
+fn layer_occlusion() {
+    let scene = Scene::new()
+        .with_quad(Quad::new(
+            vec2(0., 0.),
+            vec2(10., 10.),
+            vec4(1., 0., 0., 1.),
+        ))
+        .with_layer(Layer::new().with_clip(vec4(10., 10., 50., 50.)))
+        .with_layer(Layer::new().with_clip(vec4(0., 0., 100., 100.)))
+        .with_layer(
+            Layer::new()
+                .with_clip(vec4(20., 20., 20., 20.))
+                .with_background(vec4(0., 0., 1., 0.5)),
+        );
+
+    let constants = ShaderConstants::new(vec2(100., 100.));
+    let (hidden, stats) = hidden_layers(&scene, &constants);
+    assert_eq!(hidden, vec![true, true, false, false]);
+    assert_eq!(stats.layers_skipped, 2);
+    assert_eq!(stats.primitives_skipped, 1);
+
+    // A wider antialiasing ramp leaves the edge pixels translucent
+    let (hidden, _) = hidden_layers(&scene, &constants.with_antialiasing_width(2.0));
+    assert_eq!(hidden, vec![false, true, false, false]);
+
+    let rotated = Scene {
+        camera: Camera::new().with_rotation(0.1),
+        ..scene
+    };
+    let (hidden, _) = hidden_layers(&rotated, &constants);
+    assert_eq!(hidden, vec![false; 4]);
+}
+
+#[test]
+fn layer_batching() {
+    let quad_layer = |y| {
+        Layer::new().without_background().with_quad(Quad::new(
+            vec2(0., y),
+            vec2(10., 10.),
+            vec4(1., 0., 0., 1.),
+        ))
+    };
+    let scene = Scene::new()
+        .with_layer(quad_layer(0.))
+        .with_layer(quad_layer(5.))
+        .with_layer(Layer::new().without_background().with_sprite(Sprite::new(
+            "Leaf.png".to_string(),
+            vec2(0., 50.),
+            vec2(10., 10.),
+        )))
+        // Overlaps the sprite of the batch with a quad
+        .with_layer(quad_layer(50.))
+        .with_layer(quad_layer(100.).with_clip(vec4(0., 0., 50., 50.)));
+
+    let (batches, stats) = batch_layers(scene.layers.iter(), 2.0, true);
+    assert_eq!(stats.layers, 6);
+    assert_eq!(stats.batches, 3);
+    assert_eq!(batches[0].quads.len(), 2);
+    assert_eq!(batches[0].sprites.len(), 1);
+    assert_eq!(batches[1].quads.len(), 1);
+
+    // Unless backgrounds are drawn first, the background of the first layer
+    // counts as a quad which the sprite overlaps
+    let (_, stats) = batch_layers(scene.layers.iter(), 2.0, false);
+    assert_eq!(stats.batches, 4);
+}
+
+#[test]
+fn quad_merging() {
+    let red = vec4(1., 0., 0., 1.);
+    let cell = |x: f32, y: f32, color| Quad::new(vec2(x, y), vec2(7., 15.), color).to_instanced();
+    let quads = vec![
+        // A row of cells, then the same row below
+        cell(0., 0., red),
+        cell(7., 0., red),
+        cell(14., 0., red),
+        cell(0., 15., red),
+        cell(7., 15., red),
+        cell(14., 15., red),
+        cell(21., 15., vec4(0., 0., 1., 1.)),
+        // Same color, but rounded
+        Quad::new(vec2(28., 15.), vec2(7., 15.), vec4(0., 0., 1., 1.))
+            .with_corner_radius(2.)
+            .to_instanced(),
+    ];
+
+    let merged = merge_adjacent_quads(quads);
+    assert_eq!(merged.len(), 3);
+    assert_eq!(
+        (merged[0].top_left, merged[0].size),
+        (vec2(0., 0.), vec2(21., 30.))
+    );
+    assert_eq!(merged[1].top_left, vec2(21., 15.));
+    assert_eq!(merged[2].corner_radius, 2.);
+}
+
+#[test]
+fn opaque_quads() {
+    let constants = ShaderConstants::new(vec2(100., 100.));
+    let quad = |top_left, color| Quad::new(top_left, vec2(10., 10.), color);
+    let opaque = vec4(1., 0., 0., 1.);
+
+    assert!(is_opaque(
+        &quad(vec2(5., 5.), opaque).to_instanced(),
+        &constants
+    ));
+    // Antialiased edges, translucent and rounded quads need blending
+    assert!(!is_opaque(
+        &quad(vec2(5.5, 5.), opaque).to_instanced(),
+        &constants
+    ));
+    let translucent = vec4(1., 0., 0., 0.5);
+    assert!(!is_opaque(
+        &quad(vec2(5., 5.), translucent).to_instanced(),
+        &constants
+    ));
+    let rounded = quad(vec2(5., 5.), opaque).with_corner_radius(2.);
+    assert!(!is_opaque(&rounded.to_instanced(), &constants));
+
+    // A zoom of two puts the half pixels of the scene on the grid
+    let zoomed = constants.with_camera(Vec2::ZERO, vec2(1., 0.), 2.);
+    assert!(is_opaque(
+        &quad(vec2(5.5, 5.), opaque).to_instanced(),
+        &zoomed
+    ));
+}
+
+#[test]
+fn packed_instances() {
+    let glyph = InstancedGlyph {
+        bottom_left: vec2(10.25, 20.75),
+        atlas_top_left: vec2(1000., 2.),
+        atlas_size: vec2(12., 17.),
+        fade: vec2(-4., 12.5),
+        color: vec4(1., 0.5, 0., 1.),
+        paint: 3,
+        _padding: 0,
+        transform: vec2(1.5, -0.25),
+    };
+    let unpacked = PackedGlyph::from(glyph).unpack();
+
+    // Positions keep full precision and atlas pixels are exact
+    assert_eq!(unpacked.bottom_left, glyph.bottom_left);
+    assert_eq!(unpacked.atlas_top_left, glyph.atlas_top_left);
+    assert_eq!(unpacked.atlas_size, glyph.atlas_size);
+    assert_eq!(unpacked.fade, glyph.fade);
+    assert_eq!(unpacked.paint, glyph.paint);
+    assert_eq!(unpacked.transform, glyph.transform);
+    assert!(unpacked.color.abs_diff_eq(glyph.color, 1. / 255.));
+    assert_eq!(
+        std::mem::size_of::<PackedGlyph>() * 2,
+        std::mem::size_of::<InstancedGlyph>()
+    );
+}
+
+#[test]
+fn memory_budget_eviction() {
+    // Key, bytes and the frame the entry was last used in
+    let entries = [("a", 100, 3), ("b", 200, 1), ("c", 100, 2)];
+
+    assert!(entries_over_budget(entries, 400, 400).is_empty());
+    assert_eq!(entries_over_budget(entries, 400, 300), vec!["b"]);
+    assert_eq!(entries_over_budget(entries, 400, 100), vec!["b", "c"]);
+    assert_eq!(entries_over_budget(entries, 400, 0), vec!["b", "c", "a"]);
+}
+
+#[test]
+fn image_comparison() {
+    let expected = RgbaImage::from_pixel(16, 16, Rgba([200, 100, 50, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(3, 3, Rgba([202, 100, 50, 255]));
+
+    assert_eq!(
+        compare(&expected, &expected, &Tolerance::default()).ssim,
+        1.0
+    );
+    let exact = Tolerance::default();
+    let comparison = compare(&expected, &actual, &exact);
+    assert_eq!(comparison.differing_pixels, 1);
+    assert_eq!(comparison.failures(&exact).len(), 3);
+
+    let perceptual = Tolerance::new()
+        .with_channel_threshold(1)
+        .with_max_differing_pixels(1)
+        .with_min_ssim(0.99)
+        .with_max_delta_e(2.3);
+    assert!(compare(&expected, &actual, &perceptual)
+        .failures(&perceptual)
+        .is_empty());
+
+    // Large differences only pass when masked
+    actual.put_pixel(10, 12, Rgba([0, 0, 0, 255]));
+    assert!(!compare(&expected, &actual, &perceptual)
+        .failures(&perceptual)
+        .is_empty());
+    let masked = perceptual.with_mask([8, 8, 8, 8]);
+    assert!(compare(&expected, &actual, &masked)
+        .failures(&masked)
+        .is_empty());
+}
+
+#[test]
+fn scene_validation() {
+    let valid = Scene::new()
+        .with_quad(
+            Quad::new(vec2(0., 0.), vec2(10., 10.), vec4(1., 0., 0., 1.)).with_background_blur(4.),
+        )
+        .with_layer(Layer::new().with_clip(vec4(0., 0., 10., 10.)));
+    assert_eq!(valid.validate(), Ok(()));
+
+    let invalid = Scene::new()
+        .with_quad(Quad::new(
+            vec2(f32::NAN, 0.),
+            vec2(-1., 10.),
+            vec4(1.5, 0., 0., 1.),
+        ))
+        .with_layer(
+            Layer::new()
+                .with_clip(vec4(0., 0., 0., 10.))
+                .with_text(Text::new(
+                    "a".to_string(),
+                    vec2(0., 0.),
+                    -3.,
+                    vec4(0., 0., 0., 1.),
+                )),
+        );
+    let paths: Vec<_> = invalid
+        .validate()
+        .unwrap_err()
+        .into_iter()
+        .map(|error| error.path)
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "layers[0].quads[0].top_left",
+            "layers[0].quads[0].size",
+            "layers[0].quads[0].color",
+            "layers[1].clip",
+            "layers[1].texts[0].size",
+        ]
+    );
+}
+
+#[test]
+fn zero_size_resize() {
+    let scene = Scene::new().with_quad(Quad::new(vec2(4., 4.), vec2(8., 8.), vec4(1., 0., 0., 1.)));
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(32, 16)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+
+        renderer.resize(0, 16);
+        assert!(renderer.draw_rgba(&scene).await.is_empty());
+
+        renderer.resize(32, 16);
+        let image = renderer.draw(&scene).await;
+        assert_eq!(image.dimensions(), (32, 16));
+        assert_eq!(image.get_pixel(8, 8), &Rgba([255, 0, 0, 255]));
+    });
+}
+
+#[test]
+fn texture_size_fitting() {
+    assert_eq!(fit_texture_size(800, 600, 8192), (800, 600, 1.0));
+    assert_eq!(fit_texture_size(16384, 4096, 8192), (8192, 2048, 0.5));
+    assert_eq!(fit_texture_size(0, 16384, 8192), (0, 8192, 0.5));
+    assert_eq!(fit_texture_size(100_000, 1, 8192).1, 1);
+}
+
+#[test]
+fn shader_constants_layout_verification() {
+    use naga::{GlobalVariable, Module, Scalar, Span, StructMember, Type, TypeInner, VectorSize};
+
+    let layout = shader_constants_layout();
+    let last = layout.last().unwrap();
+    assert_eq!(
+        (last.offset + last.size) as usize,
+        std::mem::size_of::<ShaderConstants>()
+    );
+
+    // Push constants with the given fields, wrapped in a block like rust-gpu
+    // does
+    let module = |fields: &[FieldLayout]| {
+        let mut module = Module::default();
+        let mut types = |inner| {
+            module
+                .types
+                .insert(Type { name: None, inner }, Span::UNDEFINED)
+        };
+        let scalar = types(TypeInner::Scalar(Scalar::F32));
+        let vec2 = types(TypeInner::Vector {
+            size: VectorSize::Bi,
+            scalar: Scalar::F32,
+        });
+        let vec3 = types(TypeInner::Vector {
+            size: VectorSize::Tri,
+            scalar: Scalar::F32,
+        });
+        let vec4 = types(TypeInner::Vector {
+            size: VectorSize::Quad,
+            scalar: Scalar::F32,
+        });
+        let members = fields
+            .iter()
+            .map(|field| StructMember {
+                name: Some(field.name.to_string()),
+                ty: match field.size {
+                    4 => scalar,
+                    8 => vec2,
+                    12 => vec3,
+                    _ => vec4,
+                },
+                binding: None,
+                offset: field.offset,
+            })
+            .collect();
+        let constants = types(TypeInner::Struct { members, span: 96 });
+        let block = types(TypeInner::Struct {
+            members: vec![StructMember {
+                name: None,
+                ty: constants,
+                binding: None,
+                offset: 0,
+            }],
+            span: 96,
+        });
+        module.global_variables.append(
+            GlobalVariable {
+                name: Some("constants".to_string()),
+                space: naga::AddressSpace::PushConstant,
+                binding: None,
+                ty: block,
+                init: None,
+            },
+            Span::UNDEFINED,
+        );
+        module
+    };
+
+    assert_eq!(verify_push_constants(&module(&layout), &layout), Ok(()));
+
+    let mut shifted = layout.clone();
+    shifted[6].offset += 4;
+    let Err(ShaderLayoutError::Mismatch(differences)) =
+        verify_push_constants(&module(&shifted), &layout)
+    else {
+        panic!("Expected a layout mismatch");
+    };
+    assert_eq!(differences.len(), 1);
+    assert!(differences[0].starts_with("camera_zoom"));
+
+    assert_eq!(
+        verify_push_constants(&Module::default(), &layout),
+        Err(ShaderLayoutError::MissingPushConstants)
+    );
+}
+
+#[test]
+fn pipeline_interface_verification() {
+    use wgpu::{
+        vertex_attr_array, BindGroupLayoutEntry, BindingType, BufferBindingType,
+        SamplerBindingType, ShaderStages, TextureSampleType, TextureViewDimension,
+        VertexBufferLayout, VertexStepMode,
+    };
+
+    let module = naga::front::wgsl::parse_str(
+        "
+        @group(0) @binding(0) var<storage, read> positions: array<vec4<f32>>;
+        @group(1) @binding(0) var atlas: texture_2d<f32>;
+        @group(1) @binding(1) var atlas_sampler: sampler;
+
+        fn position(index: u32) -> vec4<f32> {
+            return positions[index];
+        }
+
+        @vertex
+        fn vertex(@builtin(vertex_index) index: u32, @location(0) offset: vec2<f32>)
+            -> @builtin(position) vec4<f32> {
+            return position(index) + vec4(offset, 0.0, 0.0);
+        }
+
+        @fragment
+        fn fragment() -> @location(0) vec4<f32> {
+            return textureSample(atlas, atlas_sampler, vec2(0.5));
+        }
+        ",
+    )
+    .unwrap();
+
+    let storage = |read_only, visibility| BindGroupLayoutEntry {
+        binding: 0,
+        visibility,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let texture = BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    let sampler = BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+        count: None,
+    };
+    let vertex_buffers = [VertexBufferLayout {
+        array_stride: 8,
+        step_mode: VertexStepMode::Vertex,
+        attributes: &vertex_attr_array![0 => Float32x2],
+    }];
+
+    assert_eq!(
+        verify_pipeline_interface(
+            &module,
+            &PipelineInterface {
+                entry_points: &["vertex", "fragment"],
+                bind_group_layouts: &[&[storage(true, ShaderStages::VERTEX)], &[texture, sampler]],
+                vertex_buffers: &vertex_buffers,
+            },
+        ),
+        Ok(())
+    );
+
+    // The storage buffer has the wrong visibility, the sampler and the vertex
+    // buffer are missing
+    let Err(ShaderLayoutError::Interface(differences)) = verify_pipeline_interface(
+        &module,
+        &PipelineInterface {
+            entry_points: &["vertex", "fragment"],
+            bind_group_layouts: &[&[storage(true, ShaderStages::FRAGMENT)], &[texture]],
+            vertex_buffers: &[],
+        },
+    ) else {
+        panic!("Expected an interface mismatch");
+    };
+    assert_eq!(differences.len(), 3, "{differences:?}");
+
+    // A texture where the shader expects a storage buffer
+    let Err(ShaderLayoutError::Interface(differences)) = verify_pipeline_interface(
+        &module,
+        &PipelineInterface {
+            entry_points: &["vertex"],
+            bind_group_layouts: &[&[BindGroupLayoutEntry {
+                visibility: ShaderStages::VERTEX,
+                ..texture
+            }]],
+            vertex_buffers: &vertex_buffers,
+        },
+    ) else {
+        panic!("Expected an interface mismatch");
+    };
+    assert!(differences[0].contains("read only storage buffer"));
+
+    assert_eq!(
+        verify_pipeline_interface(
+            &module,
+            &PipelineInterface {
+                entry_points: &["missing"],
+                bind_group_layouts: &[],
+                vertex_buffers: &[],
+            },
+        ),
+        Err(ShaderLayoutError::MissingEntryPoint("missing".to_string()))
+    );
+}
+
+#[test]
+fn drawable_registration() {
+    let mut renderer = smol::block_on(OffscreenRenderer::new(16, 16))
+        .unwrap()
+        .with_builtin_drawables();
+    assert_eq!(
+        renderer.renderer.drawable_names(),
+        vec![
+            "quad",
+            "procedural",
+            "shape",
+            "glyph",
+            "path",
+            "sprite",
+            "backdrop_blur",
+            "caret",
+            "dimming"
+        ]
+    );
+    assert!(renderer.renderer.drawable::<QuadState>().is_some());
+    assert!(matches!(
+        renderer.add_drawable::<QuadState>(),
+        Err(DrawableError::AlreadyRegistered(_))
+    ));
+    assert_eq!(renderer.renderer.drawable_names().len(), 5);
+}
+
+// Declares none of the bindings the caret entry points use
+struct MismatchedDrawable;
+
+impl Drawable for MismatchedDrawable {
+    fn new(renderer: &Renderer) -> Self {
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &["caret::caret_vertex", "caret::caret_fragment"],
+            bind_group_layouts: &[],
+            vertex_buffers: &[],
+        });
+        Self
+    }
+
+    fn name(&self) -> &'static str {
+        "mismatched"
+    }
+
+    fn draw<'b, 'a: 'b>(
+        &'a mut self,
+        _uploader: &mut Uploader,
+        _render_pass: &mut wgpu::RenderPass<'b>,
+        _constants: ShaderConstants,
+        _universal_bind_group: &'a wgpu::BindGroup,
+        _layer: &Layer,
+    ) {
+    }
+}
+
+#[test]
+fn drawable_pipeline_mismatch() {
+    let mut renderer = smol::block_on(OffscreenRenderer::new(16, 16)).unwrap();
+    let Err(DrawableError::PipelineMismatch { name, errors }) =
+        renderer.add_drawable::<MismatchedDrawable>()
+    else {
+        panic!("Expected a pipeline mismatch");
+    };
+    assert_eq!(name, "mismatched");
+    assert!(!errors.is_empty());
+    assert!(renderer.renderer.drawable_names().is_empty());
+}
+
+#[test]
+fn gpu_culling() {
+    let visible_rect = vec4(0., 0., 100., 100.);
+    let quad = |top_left, size| Quad::new(top_left, size, vec4(1., 0., 0., 1.));
+    assert!(quad_visible(
+        &quad(vec2(90., 90.), vec2(20., 20.)).to_instanced(),
+        visible_rect
+    ));
+    assert!(!quad_visible(
+        &quad(vec2(110., 0.), vec2(20., 20.)).to_instanced(),
+        visible_rect
+    ));
+    assert!(!quad_visible(
+        &quad(vec2(10., 10.), vec2(0., 20.)).to_instanced(),
+        visible_rect
+    ));
+    // The external blur reaches into the rect
+    let blurred = quad(vec2(105., 10.), vec2(20., 20.)).with_blur(5.);
+    assert!(quad_visible(&blurred.to_instanced(), visible_rect));
+
+    // Overlapping quads, many of them off screen, keep their order
+    let mut layer = Layer::new();
+    for index in 0..500 {
+        let x = (index % 50) as f32 * 7.;
+        let y = (index / 50) as f32 * 7.;
+        let color = vec4(index as f32 / 500., 0.5, 1. - index as f32 / 500., 0.6);
+        layer.add_quad(Quad::new(vec2(x, y), vec2(10., 10.), color));
+    }
+    let scene = Scene::new().with_layer(layer);
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(120, 60)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let expected = renderer.draw(&scene).await;
+        let needs_culler = |renderer: &OffscreenRenderer| {
+            renderer
+                .renderer
+                .drawable::<QuadState>()
+                .unwrap()
+                .needs_culler()
+        };
+        // The culler is only created once gpu culling is used
+        assert!(needs_culler(&renderer));
+        renderer.renderer.set_gpu_culling(true);
+        assert_eq!(renderer.draw(&scene).await, expected);
+        assert_eq!(
+            needs_culler(&renderer),
+            !renderer.renderer.capabilities().supports_gpu_culling()
+        );
+    });
+}
+
+#[test]
+fn color_luts() {
+    let identity = ColorLut::identity(17);
+    let color = vec3(0.2, 0.5, 0.9);
+    assert!(identity.sample(color).abs_diff_eq(color, 0.001));
+
+    // Red changes fastest in the entries
+    let inverting = ColorLut::parse_cube(
+        "# Inverts every color
+        TITLE \"Invert\"
+        LUT_3D_SIZE 2
+        1 1 1
+        0 1 1
+        1 0 1
+        0 0 1
+        1 1 0
+        0 1 0
+        1 0 0
+        0 0 0",
+    )
+    .unwrap();
+    assert!(inverting
+        .sample(vec3(0.25, 0.5, 1.))
+        .abs_diff_eq(vec3(0.75, 0.5, 0.), 0.001));
+    let grayscale = ColorLut::from_fn(9, |color| Vec3::splat(color.dot(vec3(0.3, 0.6, 0.1))));
+    assert!(grayscale
+        .sample(vec3(1., 0., 0.))
+        .abs_diff_eq(Vec3::splat(0.3), 0.001));
+
+    assert_eq!(
+        ColorLut::parse_cube("1 1 1"),
+        Err(ColorLutError::MissingSize)
+    );
+    assert_eq!(
+        ColorLut::parse_cube("LUT_3D_SIZE 2\n0 0 0"),
+        Err(ColorLutError::EntryCount {
+            expected: 8,
+            found: 1
+        })
+    );
+    assert!(matches!(
+        ColorLut::parse_cube("LUT_3D_SIZE 2\nLUT_1D_SIZE 4"),
+        Err(ColorLutError::Syntax { line: 2, .. })
+    ));
+
+    let scene = Scene::new()
+        .with_background(vec4(1., 1., 1., 1.))
+        .with_quad(Quad::new(
+            vec2(10., 10.),
+            vec2(30., 30.),
+            vec4(1., 0., 0., 1.),
+        ))
+        .with_quad(Quad::new(
+            vec2(50., 10.),
+            vec2(30., 30.),
+            vec4(0.2, 0.4, 1., 1.),
+        ));
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(90, 50)
+            .await
+            .unwrap()
+            .with_deterministic(true)
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let original = renderer.draw(&scene).await;
+        renderer.set_color_lut(Some(ColorLut::identity(33)));
+        let graded = renderer.draw(&scene).await;
+        // Only off by the rounding of the table entries
+        let tolerance = Tolerance::new()
+            .with_channel_threshold(2)
+            .with_min_ssim(0.99)
+            .with_max_delta_e(2.);
+        assert!(compare(&original, &graded, &tolerance)
+            .failures(&tolerance)
+            .is_empty());
+
+        renderer.set_color_lut(Some(inverting));
+        let inverted = renderer.draw(&scene).await;
+        assert_eq!(inverted.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        renderer.set_color_grading_strength(0.);
+        assert_eq!(renderer.draw(&scene).await, original);
+    });
+}
+
+#[test]
+fn layer_dimming() {
+    let red = vec3(1., 0., 0.);
+    assert_eq!(dim_color(red, 0.5, 0.7, 0.), red);
+    assert!(dim_color(red, 1., 0.5, 1.).abs_diff_eq(vec3(0.5, 0., 0.), 0.0001));
+    assert!(dim_color(red, 0., 1., 1.).abs_diff_eq(Vec3::splat(0.2126), 0.0001));
+
+    let dimming = LayerDimming::new(0., 0.5).with_transition(0., 2., 1.);
+    assert_eq!(dimming.progress_at(1.), 0.);
+    assert_eq!(dimming.progress_at(2.5), 0.5);
+    assert_eq!(dimming.progress_at(4.), 1.);
+    assert_eq!(LayerDimming::new(0., 0.5).progress_at(0.), 1.);
+    assert!(LayerDimming::new(0., 0.5).with_progress(0.).is_noop());
+    assert!(LayerDimming::new(1., 1.).is_noop());
+    assert!(!LayerDimming::new(0., 0.5)
+        .with_progress(0.)
+        .with_transition(1., 0., 1.)
+        .is_noop());
+
+    // Focus changes animate from and to undimmed layers
+    let unfocused = Scene::new().with_layer(Layer::new().with_dimming(LayerDimming::new(0., 0.5)));
+    let halfway = Scene::new()
+        .with_layer(Layer::new())
+        .tween(&unfocused, 0.5, Easing::Linear);
+    assert_eq!(halfway.layers[1].dimming.unwrap().progress, 0.5);
+
+    // Dimmed layers aren't batched with the layers they would change
+    let quad_layer = |y| {
+        Layer::new().without_background().with_quad(Quad::new(
+            vec2(0., y),
+            vec2(10., 10.),
+            vec4(1., 0., 0., 1.),
+        ))
+    };
+    let layers = [
+        quad_layer(0.),
+        quad_layer(20.).with_dimming(LayerDimming::default()),
+        quad_layer(40.),
+    ];
+    let (_, stats) = batch_layers(layers.iter(), 2.0, true);
+    assert_eq!(stats.batches, 3);
+
+    let invalid = Scene::new().with_layer(
+        Layer::new().with_dimming(LayerDimming::new(-1., 0.5).with_transition(2., 0., 1.)),
+    );
+    let paths: Vec<_> = invalid
+        .validate()
+        .unwrap_err()
+        .into_iter()
+        .map(|error| error.path)
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            "layers[1].dimming.saturation",
+            "layers[1].dimming.transition.from"
+        ]
+    );
+
+    // Only the clip of the dimmed layer changes
+    let scene = Scene::new()
+        .with_background(vec4(1., 0., 0., 1.))
+        .with_layer(
+            Layer::new()
+                .without_background()
+                .with_clip(vec4(0., 0., 20., 20.))
+                .with_dimming(LayerDimming::new(1., 0.5)),
+        );
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(40, 20)
+            .await
+            .unwrap()
+            .with_deterministic(true)
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let image = renderer.draw(&scene).await;
+        let dimmed = image.get_pixel(10, 10);
+        assert!(dimmed[0] > 100 && dimmed[0] < 255);
+        assert_eq!(image.get_pixel(30, 10), &Rgba([255, 0, 0, 255]));
+    });
+}
+
+#[test]
+fn lod_policy() {
+    let policy = LodPolicy::new(Duration::from_millis(10))
+        .with_frames_to_degrade(2)
+        .with_frames_to_restore(3)
+        .with_lowest_level(LodLevel::NoShadows);
+    let mut controller = LodController::default();
+    let mut time = Instant::now();
+    // Milliseconds since the previous frame and milliseconds the frame took
+    let mut frame = |interval, milliseconds| {
+        time += Duration::from_millis(interval);
+        controller.record_frame(&policy, time, Duration::from_millis(milliseconds))
+    };
+
+    // A single slow frame is a hitch
+    assert_eq!(frame(16, 20), LodLevel::Full);
+    assert_eq!(frame(16, 4), LodLevel::Full);
+    assert_eq!(frame(16, 20), LodLevel::Full);
+    assert_eq!(frame(16, 20), LodLevel::ReducedBlur);
+    assert_eq!(frame(16, 20), LodLevel::ReducedBlur);
+    assert_eq!(frame(16, 20), LodLevel::NoShadows);
+    // Not below the lowest level of the policy
+    assert_eq!(frame(16, 20), LodLevel::NoShadows);
+    assert_eq!(frame(16, 20), LodLevel::NoShadows);
+
+    // Frames within the budget but not well within it keep the level
+    assert_eq!(frame(16, 8), LodLevel::NoShadows);
+    for _ in 0..2 {
+        assert_eq!(frame(16, 2), LodLevel::NoShadows);
+    }
+    assert_eq!(frame(16, 2), LodLevel::ReducedBlur);
+
+    // The first frame after being idle is back at full quality
+    assert_eq!(frame(1000, 20), LodLevel::Full);
+
+    assert_eq!(LodLevel::Full.sample_count(), 4);
+    assert!(LodLevel::ReducedBlur.shadows());
+    assert!(!LodLevel::NoMultisampling.shadows());
+    assert_eq!(LodLevel::NoMultisampling.sample_count(), 1);
+    let mut blurred = Quad::new(Vec2::ZERO, vec2(10., 10.), vec4(1., 1., 1., 0.5))
+        .with_background_blur(9.)
+        .to_instanced();
+    scale_background_blur(&mut blurred, LodLevel::ReducedBlur.blur_scale());
+    assert_eq!(blurred.blur, -4.5);
+    let mut shadow = Quad::new(Vec2::ZERO, vec2(10., 10.), vec4(0., 0., 0., 0.5))
+        .with_blur(4.)
+        .to_instanced();
+    scale_background_blur(&mut shadow, 0.5);
+    assert_eq!(shadow.blur, 4.);
+
+    // Every frame is over a budget of zero, so the renderer ends up drawing
+    // without multisampling
+    let scene = Scene::new()
+        .with_quad(Quad::new(
+            vec2(10., 10.),
+            vec2(20., 20.),
+            vec4(1., 0., 0., 1.),
+        ))
+        .with_path(
+            Path::builder()
+                .rect(vec2(32., 32.), vec2(4., 4.))
+                .build()
+                .with_fill(vec4(0., 0., 1., 1.)),
+        );
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(40, 40)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        renderer.renderer.set_lod_policy(Some(
+            LodPolicy::new(Duration::ZERO).with_frames_to_degrade(1),
+        ));
+        assert_eq!(renderer.renderer.lod_level(), LodLevel::Full);
+        for _ in 0..3 {
+            renderer.draw(&scene).await;
+        }
+        assert_eq!(renderer.renderer.lod_level(), LodLevel::NoMultisampling);
+        let image = renderer.draw(&scene).await;
+        assert_eq!(renderer.renderer.sample_count(), 1);
+        assert_eq!(image.get_pixel(20, 20), &Rgba([255, 0, 0, 255]));
+
+        // Only the pipelines were recreated, so the path is still cached
+        let stats = renderer
+            .renderer
+            .drawable::<PathState>()
+            .unwrap()
+            .tessellation_cache_stats();
+        assert_eq!(stats.misses, 1);
+    });
+}
+
+#[test]
+fn glyph_zoom_steps() {
+    let mut zoom = ZoomRasterization::default();
+    let policy = GlyphZoomPolicy::stepped();
+    let mut frame = |camera_zoom: f32| {
+        zoom.begin_frame();
+        let raster_zoom = zoom.update(policy, camera_zoom);
+        assert_eq!(zoom.raster_zoom(camera_zoom), raster_zoom);
+        (raster_zoom, zoom.scaled())
+    };
+    let step = 2f32.powf(0.25);
+
+    assert_eq!(frame(1.0), (1.0, false));
+    // Zooming in rasterizes at the step above and scales the glyphs down
+    assert_eq!(frame(1.1), (step, true));
+    // Zooming back stays at the step within the hysteresis
+    assert_eq!(frame(1.05), (step, true));
+    assert_eq!(frame(1.0), (step, true));
+    assert_eq!(frame(0.95), (1.0, true));
+    // The zoom settles after a few frames and the glyphs are rasterized at
+    // it again
+    assert_eq!(frame(0.95), (1.0, true));
+    assert_eq!(frame(0.95), (1.0, true));
+    assert_eq!(frame(0.95), (0.95, false));
+    assert_eq!(frame(0.95), (0.95, false));
+
+    let mut exact = ZoomRasterization::default();
+    exact.begin_frame();
+    assert_eq!(exact.update(GlyphZoomPolicy::Exact, 1.1), 1.1);
+    assert!(!exact.scaled());
+
+    // Glyphs scaled from the raster zoom have their corners where the
+    // scaled placement puts them
+    let rotation = vec2(0.0, 1.0);
+    let corner = |instance: &InstancedGlyph| {
+        let center = vec2(0.5, -0.5) * instance.atlas_size;
+        let local =
+            center + (vec2(0.0, -1.0) * instance.atlas_size - center) * instance.transform.x;
+        instance.bottom_left + local.rotate(rotation)
+    };
+    let mut instance = InstancedGlyph {
+        bottom_left: vec2(20.0, 10.0),
+        atlas_size: vec2(8.0, 12.0),
+        transform: vec2(1.0, 0.0),
+        ..Default::default()
+    };
+    let raster_corner = corner(&instance);
+    scale_glyph_instance(&mut instance, 0.8, rotation);
+    assert!(corner(&instance).abs_diff_eq(raster_corner * 0.8, 1e-4));
+}
+
+#[test]
+fn indirect_draws() {
+    let offsets: Vec<_> = draw_offsets::<DrawIndirectArgs>(32, 3).collect();
+    assert_eq!(offsets, vec![32, 48, 64]);
+    let offsets: Vec<_> = draw_offsets::<DrawIndexedIndirectArgs>(0, 2).collect();
+    assert_eq!(offsets, vec![0, 20]);
+
+    let draws = IndirectDraws::new(Features::MULTI_DRAW_INDIRECT);
+    assert!(draws.supports_multi_draw() && !draws.supports_first_instance());
+    assert!(!IndirectDraws::new(Features::empty()).supports_multi_draw());
+}
+
+#[test]
+fn image_atlas_pages() {
+    // Two images which don't fit on one page are drawn from two pages
+    let size = ATLAS_SIZE.x as u32 * 3 / 4;
+    let image = |color: [u8; 4]| color.repeat((size * size) as usize);
+    let scene = Scene::new()
+        .with_sprite(Sprite::new("Red".to_string(), vec2(0., 0.), vec2(20., 20.)))
+        .with_sprite(Sprite::new(
+            "Blue".to_string(),
+            vec2(20., 0.),
+            vec2(20., 20.),
+        ));
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(40, 20)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        renderer
+            .renderer
+            .add_image_rgba("Red", size, size, image([255, 0, 0, 255]));
+        renderer
+            .renderer
+            .add_image_rgba("Blue", size, size, image([0, 0, 255, 255]));
+        let frame = renderer.draw(&scene).await;
+        assert_eq!(frame.get_pixel(10, 10), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(30, 10), &Rgba([0, 0, 255, 255]));
+        let report = renderer.renderer.memory_report();
+        let page_bytes = (ATLAS_SIZE.x * ATLAS_SIZE.y * 4.) as u64;
+        assert_eq!(report.image_atlas.capacity_bytes, 2 * page_bytes);
+
+        // A pattern of the image on the overflow page has no room on the
+        // first page, so it's drawn with the placeholder instead of panicking
+        let patterned = Scene::new().with_quad(
+            Quad::new(vec2(0., 0.), vec2(20., 20.), vec4(1., 1., 1., 1.))
+                .with_pattern(Pattern::new("Blue".to_owned())),
+        );
+        let frame = renderer.draw(&patterned).await;
+        assert_ne!(frame.get_pixel(10, 10), &Rgba([0, 0, 255, 255]));
+        // The sprites place it on the overflow page again
+        let frame = renderer.draw(&scene).await;
+        assert_eq!(frame.get_pixel(30, 10), &Rgba([0, 0, 255, 255]));
+    });
+}
+
+#[test]
+fn state_sorting() {
+    let key = |item: &(u32, Vec4)| item.0;
+    let bounds = |item: &(u32, Vec4)| item.1;
+    let items = vec![
+        (0, vec4(0., 0., 10., 10.)),
+        (1, vec4(20., 0., 10., 10.)),
+        (0, vec4(40., 0., 10., 10.)),
+    ];
+    assert_eq!(state_runs(&items, key).len(), 3);
+    let sorted = sort_by_state(items.clone(), key, bounds);
+    assert_eq!(state_runs(&sorted, key), vec![(0, 0..2), (1, 2..3)]);
+
+    // Moving the last item before an item it overlaps would change the result
+    let mut overlapping = items;
+    overlapping[2].1 = vec4(25., 0., 10., 10.);
+    assert_eq!(sort_by_state(overlapping.clone(), key, bounds), overlapping);
+
+    // Alternating opaque and translucent quads which don't overlap
+    let mut layer = Layer::new();
+    for index in 0..10 {
+        let alpha = if index % 2 == 0 { 1. } else { 0.5 };
+        let top_left = vec2(index as f32 * 16., 4.);
+        layer.add_quad(Quad::new(top_left, vec2(8., 8.), vec4(0., 0., 1., alpha)));
+    }
+    let scene = Scene::new().with_layer(layer);
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(160, 16)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        renderer.renderer.set_state_sorting(false);
+        let expected = renderer.draw(&scene).await;
+        renderer.renderer.set_state_sorting(true);
+        assert_eq!(renderer.draw(&scene).await, expected);
+        let stats = renderer.renderer.state_sorting_stats();
+        assert_eq!(stats.rebinds_unsorted, 10);
+        assert_eq!(stats.rebinds_sorted, 2);
+    });
+}
+
+#[test]
+fn viewports() {
+    let viewport = Viewport::new(30, 10, 40, 40);
+    assert_eq!(viewport.clamp(50, 30), Viewport::new(30, 10, 20, 20));
+    assert_eq!(viewport.offset_scissor([2, 3, 4, 5]), [32, 13, 4, 5]);
+    assert!(Viewport::new(0, 0, 0, 10).is_empty());
+
+    // Each scene fills its own half of the frame and nothing else
+    let filled = |color| Scene::new().with_layer(Layer::new().with_background(color));
+    let viewports = [
+        (filled(vec4(1., 0., 0., 1.)), Viewport::new(0, 0, 20, 20)),
+        (filled(vec4(0., 0., 1., 1.)), Viewport::new(20, 0, 20, 20)),
+    ];
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(40, 30)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let frame = renderer.draw_viewports(&viewports).await;
+        assert_eq!(frame.get_pixel(10, 10), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(30, 10), &Rgba([0, 0, 255, 255]));
+        assert_eq!(frame.get_pixel(10, 25), &Rgba([255, 255, 255, 255]));
+    });
+}
+
+#[test]
+fn scale_factor() {
+    let constants = ShaderConstants::new(vec2(400., 300.)).with_scale_factor(2.0);
+    assert_eq!(constants.logical_size(), vec2(200., 150.));
+
+    // The antialiasing width is given in logical pixels
+    smol::block_on(async {
+        let scene = Scene::new().with_layer(
+            Layer::new()
+                .with_background(vec4(1., 1., 1., 1.))
+                .with_quad(Quad::new(
+                    vec2(10., 10.),
+                    vec2(20., 20.),
+                    vec4(0., 0., 0., 1.),
+                )),
+        );
+        let mut renderer = OffscreenRenderer::new(40, 40)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new())
+            .with_antialiasing_width(1.0);
+        let sharp = renderer.draw(&scene).await;
+        renderer.set_scale_factor(4.0);
+        let scaled = renderer.draw(&scene).await;
+        assert_ne!(scaled.get_pixel(9, 15), sharp.get_pixel(9, 15));
+    });
+}
+
+#[test]
+fn coordinate_origin() {
+    let constants = ShaderConstants::new(vec2(100., 50.))
+        .with_y_direction(CoordinateOrigin::BottomLeft.y_direction());
+    assert_eq!(constants.to_surface(vec2(10., 0.)), vec2(10., 50.));
+    assert_eq!(constants.to_surface(vec2(10., 20.)), vec2(10., 30.));
+    assert_eq!(constants.from_surface(vec2(10., 30.)), vec2(10., 20.));
+    assert_eq!(
+        scissor_rect(&constants, vec4(0., 0., 20., 10.)),
+        [0, 40, 20, 10]
+    );
+
+    // The scene origin is at the bottom left of the frame
+    let scene = Scene::new().with_layer(
+        Layer::new()
+            .with_background(vec4(1., 1., 1., 1.))
+            .with_quad(Quad::new(Vec2::ZERO, vec2(10., 10.), vec4(0., 0., 0., 1.))),
+    );
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(40, 40)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new())
+            .with_coordinate_origin(CoordinateOrigin::BottomLeft);
+        let frame = renderer.draw(&scene).await;
+        assert_eq!(frame.get_pixel(5, 35), &Rgba([0, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(5, 5), &Rgba([255, 255, 255, 255]));
+        let position = renderer
+            .renderer
+            .surface_to_scene(&scene.camera, vec2(5., 35.));
+        assert_eq!(scene.hit_test(position).len(), 1);
+    });
+}
+
+#[test]
+fn pixels_per_unit() {
+    let scene = Scene::new().with_layer(
+        Layer::new()
+            .with_background(vec4(1., 1., 1., 1.))
+            .with_quad(Quad::new(vec2(1., 1.), vec2(2., 2.), vec4(0., 0., 0., 1.))),
+    );
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(40, 40)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new())
+            .with_pixels_per_unit(10.0);
+        let frame = renderer.draw(&scene).await;
+        assert_eq!(frame.get_pixel(20, 20), &Rgba([0, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(35, 35), &Rgba([255, 255, 255, 255]));
+        assert_eq!(
+            renderer
+                .renderer
+                .surface_to_scene(&scene.camera, vec2(15., 25.)),
+            vec2(1.5, 2.5)
+        );
+    });
+}
+
+#[test]
+fn placements() {
+    let rect = vec4(0., 0., 200., 100.);
+    let corner = Placement::anchored(Anchor::BottomRight, vec2(-10., -10.));
+    assert_eq!(
+        corner.resolve(rect, vec2(20., 10.), 1.0),
+        (vec2(170., 80.), vec2(20., 10.))
+    );
+    // The anchors are where they are seen on the surface with y pointing up
+    assert_eq!(
+        corner.resolve(rect, vec2(20., 10.), -1.0),
+        (vec2(170., 10.), vec2(20., 10.))
+    );
+
+    let mut layer = Layer::new()
+        .with_quad(
+            Quad::new(Vec2::ZERO, Vec2::ZERO, vec4(0., 0., 0., 1.))
+                .with_placement(Placement::percent(25., 50.).with_percent_size(50., 10.)),
+        )
+        .with_sprite(
+            Sprite::new("Leaf.png".to_string(), Vec2::ZERO, vec2(10., 10.))
+                .with_placement(Placement::anchored(Anchor::Center, Vec2::ZERO)),
+        )
+        .with_text(
+            Text::new("Hud".to_string(), Vec2::ZERO, 16., vec4(0., 0., 0., 1.))
+                .with_placement(Placement::anchored(Anchor::BottomLeft, vec2(4., -4.))),
+        );
+    layer.resolve_placements(rect, 1.0);
+    assert_eq!(layer.quads[0].bounds(), vec4(50., 50., 100., 10.));
+    assert_eq!(layer.sprites[0].bounds(), vec4(95., 45., 10., 10.));
+    assert_eq!(layer.texts[0].bottom_left, vec2(4., 96.));
+}
+
+#[cfg(feature = "taffy")]
+#[test]
+fn taffy_scene() {
+    use crate::{
+        scene_from_taffy,
+        taffy::{prelude::*, Overflow, Point},
+        NodePaint,
+    };
+
+    let mut tree: TaffyTree<()> = TaffyTree::new();
+    let fixed = |width, height| Style {
+        size: Size {
+            width: length(width),
+            height: length(height),
+        },
+        flex_shrink: 0.0,
+        ..Default::default()
+    };
+    let inner = tree.new_leaf(fixed(80., 20.)).unwrap();
+    let left = tree.new_leaf(fixed(50., 50.)).unwrap();
+    let right = tree
+        .new_with_children(
+            Style {
+                overflow: Point {
+                    x: Overflow::Hidden,
+                    y: Overflow::Hidden,
+                },
+                ..fixed(50., 50.)
+            },
+            &[inner],
+        )
+        .unwrap();
+    let root = tree
+        .new_with_children(
+            Style {
+                padding: Rect {
+                    left: length(10.),
+                    right: length(10.),
+                    top: length(10.),
+                    bottom: length(10.),
+                },
+                ..fixed(200., 100.)
+            },
+            &[left, right],
+        )
+        .unwrap();
+    tree.compute_layout(root, Size::MAX_CONTENT).unwrap();
+
+    let scene = scene_from_taffy(&tree, root, |node| {
+        NodePaint::new()
+            .with_background(vec4(0., 0., 0., 1.))
+            .with_tag(u64::from(node))
+    })
+    .unwrap();
+    assert_eq!(scene.layers.len(), 2);
+    let bounds: Vec<_> = scene.layers[0].quads.iter().map(Quad::bounds).collect();
+    assert_eq!(
+        bounds,
+        vec![
+            vec4(0., 0., 200., 100.),
+            vec4(10., 10., 50., 50.),
+            vec4(60., 10., 50., 50.),
+        ]
+    );
+    assert_eq!(scene.layers[1].clip, Some(vec4(60., 10., 50., 50.)));
+    assert_eq!(scene.layers[1].quads[0].bounds(), vec4(60., 10., 80., 20.));
+    assert_eq!(
+        scene.hit_test_tags(vec2(100., 20.)),
+        vec![u64::from(inner), u64::from(right), u64::from(root)]
+    );
+}
+
+#[test]
+fn frame_metadata() {
+    let scene =
+        Scene::new()
+            .with_quad(Quad::new(vec2(10., 10.), vec2(20., 20.), vec4(0., 0., 0., 1.)).with_tag(1))
+            .with_quad(Quad::new(
+                vec2(50., 10.),
+                vec2(20., 20.),
+                vec4(0., 0., 0., 1.),
+            ))
+            .with_text(
+                Text::new(
+                    "Label".to_string(),
+                    vec2(10., 60.),
+                    16.,
+                    vec4(0., 0., 0., 1.),
+                )
+                .with_tag(2),
+            )
+            .with_layer(Layer::new().with_clip(vec4(0., 80., 100., 20.)).with_quad(
+                Quad::new(vec2(0., 0.), vec2(10., 10.), vec4(0., 0., 0., 1.)).with_tag(3),
+            ));
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(100, 100)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        renderer.renderer.set_collect_frame_metadata(true);
+        renderer.draw(&scene).await;
+
+        let metadata = renderer.renderer.frame_metadata();
+        assert_eq!(metadata.text_runs.len(), 1);
+        let run = &metadata.text_runs[0];
+        assert_eq!((run.text.as_str(), run.tag), ("Label", Some(2)));
+        assert!(run.bounds.x == 10. && run.bounds.y < 60. && run.bounds.z > 0.);
+
+        // The quad outside of its layer clip isn't listed
+        let regions: Vec<_> = metadata
+            .regions
+            .iter()
+            .map(|region| (region.kind, region.tag))
+            .collect();
+        assert_eq!(
+            regions,
+            vec![(PrimitiveKind::Text, 2), (PrimitiveKind::Quad, 1)]
+        );
+        assert_eq!(metadata.regions[1].bounds, vec4(10., 10., 20., 20.));
+    });
+}
+
+#[test]
+fn latency_tracking() {
+    let scene = Scene::new().with_quad(Quad::new(
+        vec2(10., 10.),
+        vec2(20., 20.),
+        vec4(0., 0., 0., 1.),
+    ));
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(100, 100)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        renderer.renderer.mark_input();
+        renderer.draw(&scene).await;
+        assert_eq!(renderer.renderer.frame_latency(), None);
+
+        renderer.renderer.set_latency_tracking(true);
+        renderer.renderer.mark_input();
+        renderer.draw(&scene).await;
+        // Measurements are collected when the next frame starts
+        renderer.draw(&scene).await;
+        let latency = renderer.renderer.frame_latency().unwrap();
+        let input = latency.input.unwrap();
+        assert!(input <= latency.render_start);
+        assert!(latency.submitted <= latency.gpu_completed);
+        assert!(latency.input_to_photon().unwrap() >= latency.cpu_duration());
+        assert_eq!(
+            latency.gpu_duration.is_some(),
+            renderer.renderer.capabilities().timestamp_queries
+        );
+
+        renderer.draw(&scene).await;
+        let latency = renderer.renderer.frame_latency().unwrap();
+        assert_eq!(latency.input, None);
+        assert_eq!(latency.input_to_photon(), None);
+    });
+}
+
+#[test]
+fn background_glyph_rasterization() {
+    let scene = Scene::new().with_text(Text::new(
+        "Background glyphs".to_string(),
+        vec2(4., 24.),
+        16.,
+        vec4(0., 0., 0., 1.),
+    ));
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(160, 32)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let expected = renderer.draw(&scene).await;
+
+        let mut renderer = OffscreenRenderer::new(160, 32)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new())
+            .with_background_glyph_rasterization(true)
+            .with_glyph_rasterizer_threads(3);
+        let mut actual = renderer.draw(&scene).await;
+        while renderer.renderer.pending_glyphs() > 0 {
+            thread::sleep(std::time::Duration::from_millis(1));
+            actual = renderer.draw(&scene).await;
+        }
+        assert_eq!(actual, expected);
+    });
+}
+
+#[test]
+fn background_asset_loading() {
+    let scene = Scene::new().with_sprite(Sprite::new(
+        "Leaf.png".to_string(),
+        vec2(10., 10.),
+        vec2(100., 100.),
+    ));
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(120, 120)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let expected = renderer.draw(&scene).await;
+
+        let mut renderer = OffscreenRenderer::new(120, 120)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new())
+            .with_background_asset_loading(true);
+        renderer
+            .renderer
+            .set_image_placeholder_color(vec4(1., 0., 0., 1.));
+        let mut actual = renderer.draw(&scene).await;
+        assert_eq!(actual.get_pixel(60, 60), &Rgba([255, 0, 0, 255]));
+        while renderer.renderer.pending_assets() > 0 {
+            thread::sleep(std::time::Duration::from_millis(1));
+            actual = renderer.draw(&scene).await;
+        }
+        assert_eq!(actual, expected);
+
+        // Images which can't be loaded keep drawing the placeholder, whether
+        // loaded in the background or not
+        let missing = Scene::new().with_sprite(Sprite::new(
+            "Missing.png".to_string(),
+            vec2(10., 10.),
+            vec2(100., 100.),
+        ));
+        renderer.draw(&missing).await;
+        while renderer.renderer.pending_assets() > 0 {
+            thread::sleep(std::time::Duration::from_millis(1));
+            renderer.draw(&missing).await;
+        }
+        let actual = renderer.draw(&missing).await;
+        assert_eq!(actual.get_pixel(60, 60), &Rgba([255, 0, 0, 255]));
+        renderer.renderer.set_background_asset_loading(false);
+        let actual = renderer.draw(&missing).await;
+        assert_eq!(actual.get_pixel(60, 60), &Rgba([255, 0, 0, 255]));
+    });
+}
+
+#[test]
+fn external_texture() {
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(120, 120)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let size = wgpu::Extent3d {
+            width: 4,
+            height: 4,
+            depth_or_array_layers: 1,
+        };
+        let texture = renderer
+            .renderer
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Shared texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+        renderer.renderer.queue.write_texture(
+            texture.as_image_copy(),
+            &[255, 0, 0, 255].repeat(16),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(16),
+                rows_per_image: None,
+            },
+            size,
+        );
+        let external = ExternalTexture::new(
+            &renderer.renderer,
+            "shared",
+            ExternalImage::Texture(texture),
+        )
+        .unwrap();
+
+        let scene = Scene::new().with_sprite(Sprite::new(
+            external.name().to_string(),
+            vec2(10., 10.),
+            vec2(100., 100.),
+        ));
+        let image = renderer.draw(&scene).await;
+        assert_eq!(image.get_pixel(60, 60), &Rgba([255, 0, 0, 255]));
+
+        // Larger than the atlas, so it's scaled down to fit instead of failing
+        let size = wgpu::Extent3d {
+            width: 2048,
+            height: 4,
+            depth_or_array_layers: 1,
+        };
+        let texture = renderer
+            .renderer
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("Wide shared texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+        renderer.renderer.queue.write_texture(
+            texture.as_image_copy(),
+            &[0, 0, 255, 255].repeat(2048 * 4),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(2048 * 4),
+                rows_per_image: None,
+            },
+            size,
+        );
+        let wide =
+            ExternalTexture::new(&renderer.renderer, "wide", ExternalImage::Texture(texture))
+                .unwrap();
+
+        let scene = Scene::new().with_sprite(Sprite::new(
+            wide.name().to_string(),
+            vec2(10., 10.),
+            vec2(100., 100.),
+        ));
+        let image = renderer.draw(&scene).await;
+        assert_eq!(image.get_pixel(60, 60), &Rgba([0, 0, 255, 255]));
+    });
+}
+
+#[test]
+fn scene_format() {
+    let viewport = vec2(200., 100.);
+    let scene = parse_scene(
+        r#"
+        // Comments and commas are optional
+        camera: { zoom: 2 }
+        layers: [
+            {
+                background_color: white
+                quads: [
+                    { top_left: [10, 10%], size: [50%, 5vh], color: #ff000080, corner_radius: 4 }
+                ]
+                procedurals: [
+                    {
+                        kind: Checker { cell_size: 8 }
+                        top_left: [0, 0], size: [10, 10]
+                        primary_color: black, secondary_color: #fff
+                    }
+                ]
+            }
+        ]
+        "#,
+        viewport,
+    )
+    .unwrap();
+    assert_eq!(scene.camera.zoom, 2.);
+    let layer = scene.layer();
+    assert_eq!(layer.background_color, Some(Vec4::ONE));
+    let quad = serde_json::to_value(&layer.quads[0]).unwrap();
+    assert_eq!(quad["top_left"], serde_json::json!([10., 10.]));
+    assert_eq!(quad["size"], serde_json::json!([100., 5.]));
+    assert_eq!(
+        quad["color"],
+        serde_json::json!([1., 0., 0., 128. / 255_f32])
+    );
+    assert_eq!(
+        layer.procedurals[0].kind,
+        ProceduralKind::Checker { cell_size: 8. }
+    );
+    assert_eq!(layer.procedurals[0].primary_color, vec4(0., 0., 0., 1.));
+
+    let assets = MemoryAssets::new()
+        .with_asset("scenes/main", b"layers: [{ include \"quads\" }]".to_vec())
+        .with_asset(
+            "scenes/quads",
+            b"quads: [{ top_left: [0, 0], size: [1, 1], color: red }]".to_vec(),
+        )
+        .with_asset("scenes/loop", b"include \"loop\"".to_vec());
+    let scene = load_scene(&assets, "scenes/main", viewport).unwrap();
+    assert_eq!(scene.layer().quads.len(), 1);
+    assert_eq!(
+        load_scene(&assets, "scenes/loop", viewport).unwrap_err(),
+        SceneFormatError::RecursiveInclude("scenes/loop".to_string())
+    );
+    assert!(matches!(
+        parse_scene("layers: [{ quads: [{ size: 50% }] }]", viewport),
+        Err(SceneFormatError::Syntax { line: 1, .. })
+    ));
+}
+
+#[test]
+fn theme_variables() {
+    let mut scene: Scene = serde_json::from_str(
+        r#"{
+            "layers": [{
+                "background_color": "$background",
+                "quads": [{
+                    "top_left": [0, 0], "size": [10, 10],
+                    "color": "$accent", "corner_radius": "$radius"
+                }],
+                "paths": [{
+                    "start": [0, 0], "commands": [{ "to": [10, 10] }],
+                    "stroke": ["$radius", "$missing"]
+                }]
+            }]
+        }"#,
+    )
+    .unwrap();
+    assert!(scene.has_theme_variables());
+    assert_eq!(
+        scene.layer().paths[0].theme.get(&ThemeField::StrokeWidth),
+        Some(&"radius".to_string())
+    );
+
+    // Bindings survive serialization, so scenes sent over ipc stay themed
+    let round_trip: Scene = serde_json::from_str(&serde_json::to_string(&scene).unwrap()).unwrap();
+    assert_eq!(round_trip.layer().theme, scene.layer().theme);
+
+    let theme = Theme::new()
+        .with_color("background", vec4(0.1, 0.1, 0.1, 1.))
+        .with_color("accent", vec4(1., 0., 0., 1.))
+        .with_number("radius", 4.);
+    scene.apply_theme(&theme);
+    let layer = scene.layer();
+    assert_eq!(layer.background_color, Some(vec4(0.1, 0.1, 0.1, 1.)));
+    let quad = serde_json::to_value(&layer.quads[0]).unwrap();
+    assert_eq!(quad["color"], serde_json::json!([1., 0., 0., 1.]));
+    assert_eq!(quad["corner_radius"], serde_json::json!(4.));
+    // Missing variables keep the placeholder value
+    assert_eq!(layer.paths[0].stroke, Some((4., Vec4::ZERO)));
+}
+
+#[test]
+fn box_drawing_glyphs() {
+    let coverage = |character, x: usize, y: usize| {
+        let image = rasterize_box_drawing(character, 10, 20, 16);
+        assert_eq!(image.placement.top, 16);
+        image.data[(y * 10 + x) * 4]
+    };
+
+    // Lines reach the edges of the cell so that neighbouring cells connect
+    assert!((0..10).all(|x| coverage('─', x, 9) == 255));
+    assert!((0..20).all(|y| coverage('│', 4, y) == 255));
+    assert_eq!(coverage('─', 0, 0), 0);
+    assert!((0..10).all(|x| coverage('┼', x, 9) == 255));
+    // Double lines leave a gap between them
+    assert_eq!(coverage('═', 0, 8), 255);
+    assert_eq!(coverage('═', 0, 9), 0);
+    assert_eq!(coverage('═', 0, 10), 255);
+
+    assert!((0..20).all(|y| (0..10).all(|x| coverage('█', x, y) == 255)));
+    assert_eq!(coverage('▀', 5, 9), 255);
+    assert_eq!(coverage('▀', 5, 10), 0);
+    assert_eq!(coverage('▒', 3, 3), 128);
+    assert_eq!(coverage('▗', 2, 15), 0);
+    assert_eq!(coverage('▗', 7, 15), 255);
+    assert_eq!(coverage('\u{E0B0}', 0, 10), 255);
+    assert_eq!(coverage('\u{E0B0}', 9, 0), 0);
+}
+
+#[test]
+fn text_grid() {
+    let white = vec4(1., 1., 1., 1.);
+    let blue = vec4(0., 0., 1., 1.);
+    let mut grid = TextGrid::new(4, 3, vec2(10., 20.), 16.).with_top_left(vec2(5., 5.));
+    grid.set_cell(0, 0, GridCell::new("a", white).with_background(blue));
+    grid.set_cell(1, 0, GridCell::new("b", white).with_background(blue));
+    grid.set_cell(2, 1, GridCell::new("中", white).with_double_width());
+
+    let mut layer = Layer::new();
+    assert_eq!(grid.update_layer(&mut layer), 3);
+    assert_eq!(layer.texts.len(), 3);
+    assert_eq!(layer.texts[2].bottom_left, vec2(25., 41.));
+    // Neighbouring cells with the same background share a quad
+    assert_eq!(layer.quads.len(), 1);
+    assert_eq!(layer.quads[0].bounds(), vec4(5., 5., 20., 20.));
+    assert!(grid.cell(3, 1).unwrap().continuation);
+
+    // Only the changed row is rebuilt and the same cell isn't a change
+    assert!(!grid.set_cell(0, 0, GridCell::new("a", white).with_background(blue)));
+    assert!(grid.set_cell(1, 2, GridCell::new("c", white)));
+    assert_eq!(grid.update_layer(&mut layer), 1);
+    assert_eq!(layer.texts.len(), 4);
+    assert_eq!(layer.texts[3].text, "c");
+
+    // Overwriting half of a double width character blanks the other half
+    grid.set_cell(3, 1, GridCell::new("d", white));
+    assert!(grid.cell(2, 1).unwrap().is_blank());
+    assert_eq!(grid.update_layer(&mut layer), 1);
+    assert_eq!(layer.texts[2].text, "d");
+    assert_eq!(grid.cell_at(vec2(36., 26.)), Some((3, 1)));
+}
+
+#[test]
+fn ligature_cell_clusters() {
+    let font = Font::from_name("DejaVu Sans").unwrap();
+    let mut context = ShapeContext::new();
+    let clusters = cell_clusters(&mut context, font.as_ref().unwrap(), "a fi", 16.);
+    assert_eq!(clusters.len(), 3);
+    assert_eq!(clusters[1].cells, 1..2);
+    assert!(!clusters[1].is_ligature());
+    // The fi ligature is one glyph covering both cells
+    assert_eq!(clusters[2].source, 2..4);
+    assert_eq!(clusters[2].cells, 2..4);
+    assert_eq!(clusters[2].glyphs.len(), 1);
+
+    // Neighbouring cells of the grid are shaped together
+    let white = vec4(1., 1., 1., 1.);
+    let mut grid = TextGrid::new(5, 1, vec2(10., 20.), 16.).with_ligatures();
+    for (column, text) in ["-", ">", "", "f", "i"].into_iter().enumerate() {
+        grid.set_cell(column, 0, GridCell::new(text, white));
+    }
+    let mut layer = Layer::new();
+    grid.update_layer(&mut layer);
+    let runs: Vec<_> = layer.texts.iter().map(|text| text.text.as_str()).collect();
+    assert_eq!(runs, ["->", "fi"]);
+    assert_eq!(layer.texts[1].bottom_left.x, 30.);
+}
+
+#[test]
+fn char_widths() {
+    assert_eq!(char_width('a'), 1);
+    assert_eq!(char_width('中'), 2);
+    assert_eq!(char_width('😀'), 2);
+    // Combining marks take the width of the character they combine with
+    assert_eq!(grapheme_width("e\u{301}"), 1);
+    // Presentation selectors
+    assert_eq!(grapheme_width("❤"), 1);
+    assert_eq!(grapheme_width("❤\u{FE0F}"), 2);
+    assert_eq!(grapheme_width("🇫🇮"), 2);
+    assert_eq!(grapheme_width("👩\u{200D}💻"), 2);
+    assert_eq!(text_width("a中😀"), 5);
+
+    // Grid cells and shaped clusters agree on the widths
+    assert!(GridCell::new("中", Vec4::ONE).double_width);
+    let font = Font::from_name("DejaVu Sans").unwrap();
+    let clusters = cell_clusters(&mut ShapeContext::new(), font.as_ref().unwrap(), "中a", 16.);
+    assert_eq!(clusters[1].cells, 2..3);
+}
+
+#[test]
+fn missing_glyph_boxes() {
+    let coverage = |image: &swash::scale::image::Image, x: usize, y: usize| {
+        image.data[(y * image.placement.width as usize + x) * 4]
+    };
+
+    // The outline of the box is inset by a pixel from the sides of the cell
+    let image = rasterize_missing_glyph('\u{E000}', 20, 40, 32);
+    assert_eq!(image.placement.top, 32);
+    assert_eq!(coverage(&image, 0, 20), 0);
+    assert_eq!(coverage(&image, 1, 20), 255);
+    assert_eq!(coverage(&image, 18, 20), 255);
+    assert_eq!(coverage(&image, 10, 4), 255);
+    // Covered by the digits
+    assert!(
+        image
+            .data
+            .iter()
+            .step_by(4)
+            .filter(|pixel| **pixel > 0)
+            .count()
+            > 150
+    );
+
+    // Too small for the digits to fit, so only the outline is drawn
+    let image = rasterize_missing_glyph('\u{10FFFF}', 4, 8, 6);
+    assert_eq!(coverage(&image, 2, 4), 0);
+}
+
+#[test]
+fn font_synthesis() {
+    assert_eq!(styled_font_name("Fira Code", false, false), "Fira Code");
+    for (bold, italic) in [(true, false), (false, true), (true, true)] {
+        let name = styled_font_name("Fira Code", bold, italic);
+        assert_eq!(parse_styled_font_name(&name), ("Fira Code", bold, italic));
+    }
+
+    // DejaVu Sans has bold and oblique faces, so nothing is synthesized
+    let regular = Font::from_name("DejaVu Sans").unwrap();
+    assert!(!regular.is_bold() && !regular.is_italic());
+    let bold_italic = Font::from_name_and_style("DejaVu Sans", true, true).unwrap();
+    assert!(bold_italic.is_bold() && bold_italic.is_italic());
+
+    // The math font only has a regular face, which is then emboldened
+    let math = Font::from_name_and_style("DejaVu Math TeX Gyre", true, false).unwrap();
+    assert!(!math.is_bold());
+}
+
+#[test]
+fn text_fitting() {
+    let font = Font::from_name("DejaVu Sans").unwrap();
+    let font_ref = font.as_ref().unwrap();
+    let mut context = ShapeContext::new();
+    let black = vec4(0., 0., 0., 1.);
+    let width = |context: &mut ShapeContext, text: &Text| -> f32 {
+        cell_clusters(context, font_ref, &text.text, text.size)
+            .iter()
+            .map(|cluster| cluster.advance)
+            .sum()
+    };
+
+    // Shrunk to the width of the rect and centered vertically
+    let text = Text::new("Fitted text".to_string(), vec2(0., 0.), 20., black)
+        .with_fit(TextFit::new(vec2(10., 10.), vec2(60., 40.)));
+    let measured = width(&mut context, &text);
+    let fitted = layout_text(&mut context, font_ref, &text, measured);
+    assert!(fitted.size < 20.);
+    assert!((width(&mut context, &fitted) - 60.).abs() < 0.5);
+    assert!(fitted.bottom_left.y > 30. && fitted.bottom_left.y < 40.);
+
+    // Grown up to the maximum size and centered horizontally
+    let text = text.with_fit(TextFit::new(vec2(10., 10.), vec2(400., 100.)).with_max_size(30.));
+    let measured = width(&mut context, &text);
+    let fitted = layout_text(&mut context, font_ref, &text, measured);
+    assert_eq!(fitted.size, 30.);
+    assert!(fitted.bottom_left.x > 10.);
+
+    // Cut off at the right edge when it doesn't fit at the minimum size
+    let text = text.with_fit(
+        TextFit::new(vec2(10., 10.), vec2(60., 40.))
+            .with_min_size(16.)
+            .with_ellipsis(),
+    );
+    let measured = width(&mut context, &text);
+    let fitted = layout_text(&mut context, font_ref, &text, measured);
+    assert_eq!(fitted.size, 16.);
+    assert!(fitted.text.starts_with("Fitt") && fitted.text.ends_with('\u{2026}'));
+    assert!(width(&mut context, &fitted) <= 60.);
+    assert_eq!(fitted.bottom_left.x, 10.);
+}
+
+#[test]
+fn text_overflow() {
+    let font = Font::from_name("DejaVu Sans").unwrap();
+    let font_ref = font.as_ref().unwrap();
+    let mut context = ShapeContext::new();
+    // The accents are combining marks, which stay with their letters
+    let text = Text::new(
+        "Cafe\u{301} cre\u{300}me bru\u{302}le\u{301}e".to_string(),
+        vec2(10., 30.),
+        20.,
+        vec4(0., 0., 0., 1.),
+    );
+    let width = cell_clusters(&mut context, font_ref, &text.text, text.size)
+        .iter()
+        .map(|cluster| cluster.advance)
+        .sum();
+    let overflow = |context: &mut ShapeContext, overflow: TextOverflow| {
+        let text = text.clone().with_max_width(100.).with_overflow(overflow);
+        layout_text(context, font_ref, &text, width).text.clone()
+    };
+
+    assert_eq!(
+        overflow(&mut context, TextOverflow::Clip),
+        "Cafe\u{301} cre\u{300}"
+    );
+    assert_eq!(
+        overflow(&mut context, TextOverflow::Ellipsis),
+        "Cafe\u{301} cr\u{2026}"
+    );
+    assert_eq!(
+        overflow(&mut context, TextOverflow::Custom('>')),
+        "Cafe\u{301} cre\u{300}>"
+    );
+    // Faded text is cut off by the shader
+    assert_eq!(overflow(&mut context, TextOverflow::Fade), text.text);
+
+    // Fits, so nothing is cut off
+    let fitting = text.clone().with_max_width(width);
+    assert_eq!(
+        layout_text(&mut context, font_ref, &fitting, width).text,
+        text.text
+    );
+
+    assert_eq!(fade_opacity(vec2(0., 10.), -5.), 1.);
+    assert_eq!(fade_opacity(vec2(0., 10.), 2.5), 0.75);
+    assert_eq!(fade_opacity(vec2(0., 10.), 15.), 0.);
+    assert_eq!(fade_opacity(Vec2::ZERO, 15.), 1.);
+}
+
+#[test]
+fn tab_stops() {
+    let font = Font::from_name("DejaVu Sans").unwrap();
+    let font_ref = font.as_ref().unwrap();
+    let mut context = ShapeContext::new();
+    // Pen position of each glyph
+    let positions = |context: &mut ShapeContext, text: &str, tab_stops: &TabStops| {
+        let text = Text::new(text.to_string(), Vec2::ZERO, 20., Vec4::ONE);
+        let mut glyphs = ShapeKey::new(font_ref, &text).shape(context, font_ref);
+        expand_tabs(&mut glyphs, tab_stops, font_ref, 20.);
+        let mut x = 0.;
+        glyphs
+            .iter()
+            .map(|glyph| {
+                let position = x;
+                x += glyph.advance;
+                position
+            })
+            .collect::<Vec<f32>>()
+    };
+    let space = positions(&mut context, " a", &TabStops::default())[1];
+
+    // The tab keeps a blank glyph reaching to the next stop
+    let default = positions(&mut context, "a\tb", &TabStops::default());
+    assert_eq!(default.len(), 3);
+    assert!((default[2] - space * 8.).abs() < 0.01);
+
+    // Listed stops first, then every interval after the last one
+    let stops = TabStops::pixels(40.).with_stops(vec![50., 70.]);
+    let listed = positions(&mut context, "a\tb\tc\td\te", &stops);
+    assert_eq!(listed[2], 50.);
+    assert_eq!(listed[4], 70.);
+    assert_eq!(listed[6], 110.);
+    assert_eq!(listed[8], 150.);
+}
+
+#[test]
+fn shaping_cache() {
+    let font = Font::from_name("DejaVu Sans").unwrap();
+    let font_ref = font.as_ref().unwrap();
+    let mut context = ShapeContext::new();
+    let mut cache = ShapingCache::new(2);
+    let text = |text: &str| Text::new(text.to_string(), Vec2::ZERO, 20., Vec4::ONE);
+
+    let ligature = cache
+        .get_or_shape(&mut context, font_ref, &text("fi"))
+        .len();
+    cache.get_or_shape(&mut context, font_ref, &text("fi"));
+    // The features are part of the key
+    let without_ligatures = text("fi").with_feature(FontFeature::new("liga", 0));
+    let separate = cache
+        .get_or_shape(&mut context, font_ref, &without_ligatures)
+        .len();
+    assert_eq!((ligature, separate), (1, 2));
+    cache.get_or_shape(&mut context, font_ref, &text("fl"));
+
+    // The least recently used run goes first
+    cache.trim();
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 3));
+    assert_eq!((stats.evictions, stats.entries), (1, 2));
+    cache.get_or_shape(&mut context, font_ref, &without_ligatures);
+    cache.get_or_shape(&mut context, font_ref, &text("fi"));
+    assert_eq!(cache.stats().hits, 2);
+    assert_eq!(cache.stats().misses, 4);
+}
+
+#[test]
+fn text_metrics() {
+    let font = Font::from_name("DejaVu Sans").unwrap();
+    let metrics = TextMetrics::new(font.as_ref().unwrap(), 20.);
+    assert!(metrics.ascent > metrics.x_height && metrics.x_height > 0.);
+    // Offsets are down from the baseline to the center of the lines
+    assert!(metrics.underline_offset > 0.);
+    assert!(metrics.strikeout_offset < 0. && metrics.strikeout_offset > -metrics.ascent);
+    assert!(metrics.overline_offset < metrics.strikeout_offset);
+    // The strikeout size of DejaVu Sans in OS/2 differs from the underline
+    // size in post
+    assert!(metrics.underline_thickness > 0.);
+    assert!(metrics.strikeout_thickness > 0.);
+    assert_ne!(metrics.underline_thickness, metrics.strikeout_thickness);
+}
+
+#[test]
+fn ipc_messages() {
+    let scene = Scene::new()
+        .with_quad(Quad::new(
+            vec2(10., 10.),
+            vec2(50., 20.),
+            vec4(1., 0., 0., 1.),
+        ))
+        .with_text(Text::new(
+            "Hello".to_string(),
+            vec2(10., 60.),
+            20.,
+            vec4(0., 0., 0., 1.),
+        ));
+    let mut stream = Vec::new();
+    write_message(&mut stream, &IpcMessage::Scene(scene.clone())).unwrap();
+    write_message(
+        &mut stream,
+        &IpcMessage::Image {
+            name: "pixel".to_string(),
+            width: 1,
+            height: 1,
+            data: vec![1, 2, 3, 4],
+        },
+    )
+    .unwrap();
+
+    let mut reader = stream.as_slice();
+    let Some(IpcMessage::Scene(received)) = read_message(&mut reader).unwrap() else {
+        panic!("Expected a scene");
+    };
+    assert_eq!(
+        serde_json::to_value(&received).unwrap(),
+        serde_json::to_value(&scene).unwrap()
+    );
+    let Some(IpcMessage::Image { name, data, .. }) = read_message(&mut reader).unwrap() else {
+        panic!("Expected an image");
+    };
+    assert_eq!((name.as_str(), data), ("pixel", vec![1, 2, 3, 4]));
+    assert!(read_message(&mut reader).unwrap().is_none());
+}
+
+// Named pipes on windows can't be created without the platform apis
+#[cfg(unix)]
+#[test]
+fn ipc_font_layering() {
+    let shared = SharedAssets::default();
+    shared.set(
+        MemoryAssets::new()
+            .with_asset("Leaf.png", vec![1])
+            .with_asset("font", vec![2]),
+    );
+    let fonts = Arc::new(RwLock::new(MemoryAssets::new()));
+    layer_assets(&shared, fonts.clone());
+
+    // The fonts of the client take precedence over the previous source, which
+    // still provides the rest
+    fonts.write().unwrap().add_asset("font", vec![3]);
+    assert_eq!(shared.load("font"), Some(vec![3]));
+    assert_eq!(shared.load("Leaf.png"), Some(vec![1]));
+    assert_eq!(shared.load("missing"), None);
+}
+
+#[test]
+fn ipc_version_mismatch() {
+    use crate::{ipc::write_handshake, IpcClient, IpcError};
+
+    let (client, mut server) = std::os::unix::net::UnixStream::pair().unwrap();
+    write_handshake(&mut server, IPC_PROTOCOL_VERSION + 1).unwrap();
+    assert!(matches!(
+        IpcClient::new(client),
+        Err(IpcError::VersionMismatch { server, .. }) if server == IPC_PROTOCOL_VERSION + 1
+    ));
+}
+
+#[test]
+fn yuv_conversion() {
+    let convert = |matrix, range, [y, u, v]: [u8; 3]| {
+        let [red, green, blue] = yuv_to_rgb(matrix, range);
+        let conversion = VideoConversion {
+            red,
+            green,
+            blue,
+            interleaved_chroma: 1,
+            _padding: [0; 3],
+        };
+        conversion.convert(vec3(y as f32, u as f32, v as f32) / 255.)
+    };
+    let close = |a: Vec3, b: Vec3| (a - b).abs().max_element() < 0.02;
+
+    for matrix in [YuvMatrix::Bt601, YuvMatrix::Bt709, YuvMatrix::Bt2020] {
+        let black = convert(matrix, YuvRange::Limited, [16, 128, 128]);
+        let white = convert(matrix, YuvRange::Limited, [235, 128, 128]);
+        assert!(close(black, Vec3::ZERO), "{matrix:?} black is {black}");
+        assert!(close(white, Vec3::ONE), "{matrix:?} white is {white}");
+        let full_white = convert(matrix, YuvRange::Full, [255, 128, 128]);
+        assert!(
+            close(full_white, Vec3::ONE),
+            "{matrix:?} white is {full_white}"
+        );
+    }
+
+    let red = convert(YuvMatrix::Bt709, YuvRange::Limited, [63, 102, 240]);
+    assert!(close(red, vec3(1., 0., 0.)), "red is {red}");
+}
+
+#[test]
+fn tessellation_cache_prepare() {
+    let square = |x: f32| {
+        Path::builder()
+            .rect(vec2(x, 0.), vec2(10., 10.))
+            .build()
+            .with_fill(vec4(1., 1., 1., 1.))
+    };
+    let (a, b, c) = (square(0.), square(20.), square(40.));
+
+    let mut cache = TessellationCache::new(2);
+    cache.get_or_tessellate(&a);
+    cache.get_or_tessellate(&b);
+    cache.trim();
+
+    // Prepared entries are as recent as the last lookup, so the next trim
+    // evicts the older entry instead
+    cache.prepare([&c]);
+    cache.trim();
+    cache.get_or_tessellate(&c);
+    cache.get_or_tessellate(&b);
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 3, 1));
+
+    cache.get_or_tessellate(&a);
+    assert_eq!(cache.stats().misses, 4);
+}
+
+#[test]
+fn video_texture_larger_than_atlas() {
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::new(120, 120)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let (width, height) = (1920, 1080);
+        let mut video = VideoTexture::new(
+            &renderer.renderer,
+            "video",
+            width,
+            height,
+            VideoFormat::Nv12,
+        )
+        .unwrap();
+        video
+            .write_frame(
+                &renderer.renderer,
+                VideoFrame::Nv12 {
+                    y: &vec![235; (width * height) as usize],
+                    uv: &vec![128; (width * height / 2) as usize],
+                },
+            )
+            .unwrap();
+
+        let scene = Scene::new().with_sprite(Sprite::new(
+            video.name().to_string(),
+            vec2(10., 10.),
+            vec2(100., 100.),
+        ));
+        let image = renderer.draw(&scene).await;
+        assert!(image.get_pixel(60, 60).0[..3]
+            .iter()
+            .all(|value| *value > 250));
+    });
+}
+
+#[test]
+fn video_frame_errors() {
+    let (y, uv) = (vec![16; 16], vec![128; 8]);
+    let nv12 = VideoFrame::Nv12 { y: &y, uv: &uv };
+    assert_eq!(check_frame(VideoFormat::Nv12, 4, 4, &nv12), Ok(()));
+    assert_eq!(
+        check_frame(VideoFormat::I420, 4, 4, &nv12),
+        Err(VideoFrameError::FormatMismatch {
+            texture: VideoFormat::I420,
+            frame: VideoFormat::Nv12,
+        })
+    );
+    // Odd sizes round the chroma up
+    assert_eq!(
+        check_frame(VideoFormat::Nv12, 3, 5, &nv12),
+        Err(VideoFrameError::PlaneSize {
+            plane: "y",
+            expected: 15,
+            actual: 16,
+        })
+    );
+    let i420 = VideoFrame::I420 {
+        y: &y,
+        u: &uv[..4],
+        v: &uv[..3],
+    };
+    assert_eq!(
+        check_frame(VideoFormat::I420, 4, 4, &i420),
+        Err(VideoFrameError::PlaneSize {
+            plane: "v",
+            expected: 4,
+            actual: 3,
+        })
+    );
+
+    smol::block_on(async {
+        let renderer = OffscreenRenderer::new(16, 16)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let renderer = &renderer.renderer;
+        let mut video = VideoTexture::new(renderer, "video", 4, 4, VideoFormat::I420).unwrap();
+        assert_eq!(
+            video.write_frame(renderer, nv12),
+            Err(VideoFrameError::FormatMismatch {
+                texture: VideoFormat::I420,
+                frame: VideoFormat::Nv12,
+            })
+        );
+        let plane = || {
+            renderer.device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: 4,
+                    height: 4,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+        };
+        let (luma, chroma) = (plane(), plane());
+        assert_eq!(
+            video.write_frame_from_textures(renderer, &luma, &chroma, None),
+            Err(VideoFrameError::MissingPlane("v"))
+        );
+    });
+}
+
+#[test]
+fn downlevel_capabilities() {
+    let features = Features::PUSH_CONSTANTS | Features::CLEAR_TEXTURE;
+    let gles = DownlevelCapabilities {
+        flags: DownlevelFlags::VERTEX_STORAGE | DownlevelFlags::FRAGMENT_STORAGE,
+        shader_model: ShaderModel::Sm5,
+        ..Default::default()
+    };
+    let limits = Limits {
+        max_texture_dimension_2d: 4096,
+        ..Limits::downlevel_defaults()
+    };
+    let capabilities = Capabilities::detect(features, &gles, &limits);
+    assert!(capabilities.is_supported());
+    assert!(capabilities.downlevel);
+    assert!(!capabilities.supports_gpu_culling());
+    assert!(!capabilities.texture_arrays);
+    assert_eq!(capabilities.features, features);
+    let required = capabilities.required_limits(&limits);
+    assert_eq!(required.max_texture_dimension_2d, 4096);
+    assert_eq!(required.max_push_constant_size, 256);
+    assert_eq!(
+        required.max_compute_workgroup_size_x,
+        Limits::downlevel_defaults().max_compute_workgroup_size_x
+    );
+
+    let webgl = DownlevelCapabilities {
+        flags: DownlevelFlags::empty(),
+        ..gles
+    };
+    let capabilities = Capabilities::detect(Features::empty(), &webgl, &limits);
+    assert_eq!(
+        capabilities.missing,
+        [
+            "push constants",
+            "texture clearing",
+            "vertex shader storage buffers"
+        ]
+    );
+
+    let desktop = Features::all_webgpu_mask() | Features::PUSH_CONSTANTS | Features::CLEAR_TEXTURE;
+    let capabilities = Capabilities::detect(
+        desktop | Features::SHADER_F64,
+        &DownlevelCapabilities::default(),
+        &Limits::default(),
+    );
+    assert!(!capabilities.downlevel);
+    assert!(capabilities.supports_gpu_culling());
+    assert!(!capabilities.features.contains(Features::SHADER_F64));
+}
+
+#[test]
+fn offscreen_formats() {
+    assert_eq!(
+        OffscreenFormat::default().texture_format(),
+        TextureFormat::Rgba8UnormSrgb
+    );
+    assert_eq!(OffscreenFormat::Rgba16Float.bytes_per_pixel(), 8);
+    assert!(OffscreenFormat::Bgra8Unorm.is_bgra());
+
+    let bgra = OffscreenImage::Bgra8 {
+        width: 1,
+        height: 1,
+        data: vec![10, 20, 30, 255],
+    };
+    assert_eq!(bgra.into_rgba8().get_pixel(0, 0), &Rgba([30, 20, 10, 255]));
+
+    let halves = [pack_f16x2(vec2(0.5, 2.0)), pack_f16x2(vec2(-1.0, 1.0))];
+    let data: Vec<u8> = halves.iter().flat_map(|half| half.to_le_bytes()).collect();
+    let floats = half_floats(&data);
+    assert_eq!(floats, [0.5, 2.0, -1.0, 1.0]);
+    // Float colors outside of the range are kept until converted to rgba8
+    let image = OffscreenImage::Rgba32F(Rgba32FImage::from_raw(1, 1, floats).unwrap());
+    assert_eq!(
+        image.into_rgba8().get_pixel(0, 0),
+        &Rgba([128, 255, 0, 255])
+    );
+
+    let scene = Scene::new().with_quad(Quad::new(
+        vec2(0., 0.),
+        vec2(10., 10.),
+        vec4(1., 0., 0., 1.),
+    ));
+    smol::block_on(async {
+        let mut renderer = OffscreenRenderer::with_format(10, 10, OffscreenFormat::Bgra8Unorm)
+            .await
+            .unwrap()
+            .with_default_drawables(EmbeddedAssets::<Assets>::new());
+        let pixels = renderer.draw_pixels(&scene).await;
+        assert_eq!(&pixels[..4], &[0, 0, 255, 255]);
+        let image = renderer.draw(&scene).await;
+        assert_eq!(image.get_pixel(5, 5), &Rgba([255, 0, 0, 255]));
+    });
+}
+
+#[test]
+fn adapter_overrides() {
+    assert_eq!(parse_backends("gl"), Some(Backends::GL));
+    assert_eq!(
+        parse_backends(" Vulkan, gles "),
+        Some(Backends::VULKAN | Backends::GL)
+    );
+    // A misspelled backend is ignored instead of picking the others
+    assert_eq!(parse_backends("vulkan,glx"), None);
+    assert_eq!(parse_backends(""), None);
+
+    let adapter = |name: &str, backend| AdapterInfo {
+        name: name.to_string(),
+        vendor: 0,
+        device: 0,
+        device_type: DeviceType::DiscreteGpu,
+        driver: String::new(),
+        driver_info: String::new(),
+        backend,
+    };
+    let adapters = [
+        adapter("NVIDIA GeForce RTX 3080", Backend::Vulkan),
+        adapter("llvmpipe (LLVM 15.0.7, 256 bits)", Backend::Vulkan),
+        adapter("llvmpipe (LLVM 15.0.7, 256 bits)", Backend::Gl),
+    ];
+    assert_eq!(select_adapter(&adapters, "LLVMpipe"), Some(1));
+    assert_eq!(select_adapter(&adapters, "2"), Some(2));
+    assert_eq!(select_adapter(&adapters, "3"), None);
+    assert_eq!(select_adapter(&adapters, "radeon"), None);
+}
+
+#[cfg(feature = "winit")]
+#[test]
+fn closest_display_mode() {
+    use crate::winit_renderer::{closest_mode, ModeInfo};
+
+    let mode = |width, height, refresh_rate_millihertz, bit_depth| ModeInfo {
+        size: (width, height),
+        refresh_rate_millihertz,
+        bit_depth,
+    };
+    let modes = [
+        mode(1920, 1080, 60_000, 24),
+        mode(1920, 1080, 144_000, 24),
+        mode(1920, 1080, 144_000, 32),
+        mode(2560, 1440, 60_000, 32),
+        mode(1280, 720, 60_000, 32),
+    ];
+    assert_eq!(closest_mode(&modes, (1920, 1080), 60_000), Some(0));
+    assert_eq!(closest_mode(&modes, (1920, 1080), 120_000), Some(2));
+    assert_eq!(closest_mode(&modes, (2560, 1600), 60_000), Some(3));
+    assert_eq!(closest_mode(&modes, (1366, 768), 75_000), Some(4));
+    assert_eq!(closest_mode(&[], (1920, 1080), 60_000), None);
+}
+
+#[test]
+fn frame_pacing() {
+    let millis =
+        |duration: Option<Duration>| duration.map(|duration| duration.as_secs_f64() * 1000.);
+    let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 0.01;
+
+    let pacer = FramePacer::new().with_refresh_rate_millihertz(Some(60_000));
+    assert!(close(millis(pacer.frame_interval()), 16.67));
+    assert_eq!(pacer.next_frame_time(), None);
+
+    // 24 frames per second on a fixed 60 Hz display alternates two and three
+    // refreshes per frame, so the frames are shown every other refresh
+    let mut pacer = pacer.with_mode(PacingMode::ContentRate(24.));
+    assert!(close(millis(pacer.frame_interval()), 33.33));
+    let pacer_144 = pacer.clone().with_refresh_rate_millihertz(Some(144_000));
+    assert!(close(millis(pacer_144.frame_interval()), 41.67));
+
+    pacer.set_variable_refresh(true);
+    assert!(close(millis(pacer.frame_interval()), 41.67));
+    // Variable refresh can't go beyond the refresh rate of the display
+    let fast = pacer.clone().with_mode(PacingMode::ContentRate(240.));
+    assert!(close(millis(fast.frame_interval()), 16.67));
+
+    assert_eq!(pacer.next_frame_time(), None);
+    let presented = Instant::now();
+    pacer.frame_presented(presented);
+    assert_eq!(
+        pacer.next_frame_time(),
+        Some(presented + pacer.frame_interval().unwrap())
+    );
+    pacer.reset();
+    assert_eq!(pacer.next_frame_time(), None);
+
+    let unknown = FramePacer::new().with_mode(PacingMode::ContentRate(50.));
+    assert!(close(millis(unknown.frame_interval()), 20.));
+}
+
+#[test]
+fn pixel_snap() {
+    let constants = ShaderConstants::new(vec2(100., 100.));
+    let snap = |top_left, size, snap| constants.snap_rect(top_left, size, snap);
+
+    let hairline = (vec2(10.3, 20.6), vec2(30., 0.4));
+    assert_eq!(snap(hairline.0, hairline.1, PIXEL_SNAP_NONE), hairline);
+    // Thin rects stay a pixel wide
+    assert_eq!(
+        snap(hairline.0, hairline.1, PIXEL_SNAP_ROUND),
+        (vec2(10., 21.), vec2(30., 1.))
+    );
+    // Rounding each edge changes the size with the position, while
+    // RoundHalf keeps it
+    assert_eq!(
+        snap(vec2(10.3, 0.), vec2(2.2, 1.), PIXEL_SNAP_ROUND),
+        (vec2(10., 0.), vec2(3., 1.))
+    );
+    assert_eq!(
+        snap(vec2(10.3, 0.), vec2(2.2, 1.), PIXEL_SNAP_ROUND_HALF),
+        (vec2(10., 0.), vec2(2., 1.))
+    );
+    assert_eq!(
+        snap(vec2(10.6, 0.), vec2(2.2, 1.), PIXEL_SNAP_ROUND_HALF),
+        (vec2(11., 0.), vec2(2., 1.))
+    );
+    assert_eq!(
+        snap(vec2(5., 5.), Vec2::ZERO, PIXEL_SNAP_ROUND),
+        (vec2(5., 5.), Vec2::ZERO)
+    );
+
+    // Snapped to device pixels when zoomed, and with the y axis pointing up
+    let zoomed = constants
+        .with_camera(Vec2::ZERO, vec2(1., 0.), 2.)
+        .with_y_direction(-1.);
+    assert_eq!(
+        zoomed.snap_rect(vec2(10.2, 10.2), vec2(0.2, 5.), PIXEL_SNAP_ROUND),
+        (vec2(10., 10.), vec2(0.5, 5.))
+    );
+    let rotated = constants.with_camera(Vec2::ZERO, vec2(0.6, 0.8), 1.);
+    assert_eq!(
+        rotated.snap_rect(hairline.0, hairline.1, PIXEL_SNAP_ROUND),
+        hairline
+    );
+
+    // Snapped quads can be drawn without blending
+    let quad = Quad::new(vec2(10.3, 20.6), vec2(30., 1.), vec4(1., 0., 0., 1.));
+    assert!(!is_opaque(&quad.to_instanced(), &constants));
+    let snapped = quad.with_snap(PixelSnap::Round).to_instanced();
+    assert!(is_opaque(&snapped, &constants));
+
+    let sprite = Sprite::new("image".to_string(), vec2(1., 2.), vec2(3., 4.))
+        .with_snap(PixelSnap::RoundHalf);
+    let instance = shader::InstancedSprite {
+        top_left: sprite.top_left,
+        size: sprite.size,
+        page: 5,
+        snap: sprite.snap.to_shader(),
+        ..Default::default()
+    };
+    let unpacked = PackedSprite::from(instance).unpack();
+    assert_eq!((unpacked.page, unpacked.snap), (5, PIXEL_SNAP_ROUND_HALF));
+}
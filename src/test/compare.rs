@@ -0,0 +1,226 @@
+use image::{Rgba, RgbaImage};
+
+// Side of the square windows the structural similarity is averaged over
+const SSIM_WINDOW: u32 = 8;
+
+// Tolerances for comparing a render with its golden image. The default only
+// accepts identical images. Looser tolerances make golden images usable
+// across gpus whose rasterization differs slightly.
+#[derive(Debug, Clone)]
+pub struct Tolerance {
+    // Largest difference of a channel for which the pixel still counts as
+    // the same
+    pub channel_threshold: u8,
+    // Number of pixels which may differ by more than the channel threshold
+    pub max_differing_pixels: usize,
+    // Lowest accepted mean structural similarity of the luminance. One means
+    // identical.
+    pub min_ssim: f64,
+    // Largest accepted CIE76 color difference of a single pixel. Differences
+    // below about 2.3 are not noticeable.
+    pub max_delta_e: f64,
+    // Rects (x, y, width, height) which are not compared, for example where
+    // the antialiasing differs between drivers
+    pub masks: Vec<[u32; 4]>,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            channel_threshold: 0,
+            max_differing_pixels: 0,
+            min_ssim: 1.0,
+            max_delta_e: 0.0,
+            masks: Vec::new(),
+        }
+    }
+}
+
+impl Tolerance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_channel_threshold(mut self, threshold: u8) -> Self {
+        self.channel_threshold = threshold;
+        self
+    }
+
+    pub fn with_max_differing_pixels(mut self, pixels: usize) -> Self {
+        self.max_differing_pixels = pixels;
+        self
+    }
+
+    pub fn with_min_ssim(mut self, ssim: f64) -> Self {
+        self.min_ssim = ssim;
+        self
+    }
+
+    pub fn with_max_delta_e(mut self, delta_e: f64) -> Self {
+        self.max_delta_e = delta_e;
+        self
+    }
+
+    pub fn with_mask(mut self, rect: [u32; 4]) -> Self {
+        self.masks.push(rect);
+        self
+    }
+
+    fn is_masked(&self, x: u32, y: u32) -> bool {
+        self.masks.iter().any(|[mask_x, mask_y, width, height]| {
+            x >= *mask_x && x < mask_x + width && y >= *mask_y && y < mask_y + height
+        })
+    }
+}
+
+// Metrics of the unmasked pixels of two images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub differing_pixels: usize,
+    pub ssim: f64,
+    pub max_delta_e: f64,
+}
+
+impl Comparison {
+    // Descriptions of every tolerance the comparison exceeds
+    pub fn failures(&self, tolerance: &Tolerance) -> Vec<String> {
+        let mut failures = Vec::new();
+        if self.differing_pixels > tolerance.max_differing_pixels {
+            failures.push(format!(
+                "{} pixels differ by more than {}, at most {} allowed",
+                self.differing_pixels, tolerance.channel_threshold, tolerance.max_differing_pixels
+            ));
+        }
+        if self.ssim < tolerance.min_ssim {
+            failures.push(format!(
+                "SSIM {:.5} is below {:.5}",
+                self.ssim, tolerance.min_ssim
+            ));
+        }
+        if self.max_delta_e > tolerance.max_delta_e {
+            failures.push(format!(
+                "ΔE {:.3} is above {:.3}",
+                self.max_delta_e, tolerance.max_delta_e
+            ));
+        }
+        failures
+    }
+}
+
+pub fn compare(expected: &RgbaImage, actual: &RgbaImage, tolerance: &Tolerance) -> Comparison {
+    assert_eq!(
+        expected.dimensions(),
+        actual.dimensions(),
+        "Images had different dimensions"
+    );
+
+    // Masked pixels are copied from the expected image so that they match
+    let mut actual = actual.clone();
+    for (x, y, pixel) in actual.enumerate_pixels_mut() {
+        if tolerance.is_masked(x, y) {
+            *pixel = *expected.get_pixel(x, y);
+        }
+    }
+
+    let mut differing_pixels = 0;
+    let mut max_delta_e: f64 = 0.0;
+    for (expected, actual) in expected.pixels().zip(actual.pixels()) {
+        let difference = expected
+            .0
+            .iter()
+            .zip(actual.0.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap();
+        if difference > tolerance.channel_threshold {
+            differing_pixels += 1;
+        }
+        max_delta_e = max_delta_e.max(delta_e(expected, actual));
+    }
+
+    Comparison {
+        differing_pixels,
+        ssim: ssim(expected, &actual),
+        max_delta_e,
+    }
+}
+
+// Mean structural similarity of the luminance over square windows
+fn ssim(expected: &RgbaImage, actual: &RgbaImage) -> f64 {
+    const C1: f64 = (0.01 * 255.) * (0.01 * 255.);
+    const C2: f64 = (0.03 * 255.) * (0.03 * 255.);
+
+    let (width, height) = expected.dimensions();
+    let mut total = 0.0;
+    let mut windows = 0;
+    for window_y in (0..height).step_by(SSIM_WINDOW as usize) {
+        for window_x in (0..width).step_by(SSIM_WINDOW as usize) {
+            let mut samples = Vec::new();
+            for y in window_y..(window_y + SSIM_WINDOW).min(height) {
+                for x in window_x..(window_x + SSIM_WINDOW).min(width) {
+                    samples.push((
+                        luminance(expected.get_pixel(x, y)),
+                        luminance(actual.get_pixel(x, y)),
+                    ));
+                }
+            }
+
+            let count = samples.len() as f64;
+            let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / count;
+            let mean_y = samples.iter().map(|(_, y)| y).sum::<f64>() / count;
+            let (mut variance_x, mut variance_y, mut covariance) = (0.0, 0.0, 0.0);
+            for (x, y) in samples.iter() {
+                variance_x += (x - mean_x) * (x - mean_x) / count;
+                variance_y += (y - mean_y) * (y - mean_y) / count;
+                covariance += (x - mean_x) * (y - mean_y) / count;
+            }
+
+            total += (2. * mean_x * mean_y + C1) * (2. * covariance + C2)
+                / ((mean_x * mean_x + mean_y * mean_y + C1) * (variance_x + variance_y + C2));
+            windows += 1;
+        }
+    }
+
+    if windows == 0 {
+        1.0
+    } else {
+        total / windows as f64
+    }
+}
+
+fn luminance(pixel: &Rgba<u8>) -> f64 {
+    let [r, g, b, _] = pixel.0.map(|channel| channel as f64);
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+// CIE76 difference of the colors in the Lab color space. Alpha is ignored.
+fn delta_e(a: &Rgba<u8>, b: &Rgba<u8>) -> f64 {
+    let [l1, a1, b1] = lab(a);
+    let [l2, a2, b2] = lab(b);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+fn lab(pixel: &Rgba<u8>) -> [f64; 3] {
+    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|channel| {
+        let channel = channel as f64 / 255.;
+        if channel <= 0.04045 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    });
+
+    // Linear srgb to xyz relative to the D65 white point
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let [fx, fy, fz] = [x, y, z].map(|t| {
+        if t > 216. / 24389. {
+            t.cbrt()
+        } else {
+            (24389. / 27. * t + 16.) / 116.
+        }
+    });
+    [116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)]
+}
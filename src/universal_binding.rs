@@ -0,0 +1,84 @@
+use std::{marker::PhantomData, rc::Rc};
+
+use wgpu::*;
+
+use crate::renderer::AsAny;
+
+// Bindings 0 to 2 of the universal bind group hold the offscreen texture, its
+// sampler and the previous frame. Bindings added with Renderer::add_universal_binding start at
+// this index so that they stay stable when more builtin bindings are added.
+pub const FIRST_USER_UNIVERSAL_BINDING: u32 = 8;
+
+// Resources which can be bound in the universal bind group
+pub trait UniversalResource: AsAny {
+    fn binding_resource(&self) -> BindingResource<'_>;
+}
+
+impl UniversalResource for TextureView {
+    fn binding_resource(&self) -> BindingResource<'_> {
+        BindingResource::TextureView(self)
+    }
+}
+
+impl UniversalResource for Sampler {
+    fn binding_resource(&self) -> BindingResource<'_> {
+        BindingResource::Sampler(self)
+    }
+}
+
+impl UniversalResource for Buffer {
+    fn binding_resource(&self) -> BindingResource<'_> {
+        self.as_entire_binding()
+    }
+}
+
+// Handle to a binding added to the universal bind group. The type parameter
+// is the type of the bound resource.
+#[derive(Debug)]
+pub struct UniversalBinding<R> {
+    binding: u32,
+    _resource: PhantomData<fn() -> R>,
+}
+
+impl<R> UniversalBinding<R> {
+    pub(crate) fn new(binding: u32) -> Self {
+        Self {
+            binding,
+            _resource: PhantomData,
+        }
+    }
+
+    // Binding index to use in the shaders
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+}
+
+impl<R> Clone for UniversalBinding<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for UniversalBinding<R> {}
+
+// Creates the resource of a binding on the given device. Called again when the
+// renderer is recreated after the device was lost.
+pub(crate) type UniversalResourceFactory =
+    Rc<dyn Fn(&Device, &Queue) -> Box<dyn UniversalResource>>;
+
+pub(crate) struct UniversalEntry {
+    pub layout: BindGroupLayoutEntry,
+    pub factory: UniversalResourceFactory,
+    pub resource: Box<dyn UniversalResource>,
+}
+
+impl UniversalEntry {
+    pub fn recreate(&self, device: &Device, queue: &Queue) -> Self {
+        Self {
+            layout: self.layout,
+            factory: self.factory.clone(),
+            resource: (self.factory)(device, queue),
+        }
+    }
+}
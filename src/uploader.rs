@@ -0,0 +1,35 @@
+use wgpu::{util::StagingBelt, *};
+
+// Size of the staging buffers. Larger uploads get a buffer of their own size.
+pub(crate) const STAGING_BELT_CHUNK_SIZE: BufferAddress = 1 << 20;
+
+// Uploads buffer data for the drawables. Writes are copied into reused
+// staging buffers and recorded as buffer copies which are submitted before
+// the draws of the layer, instead of each write being scheduled separately
+// on the queue.
+pub struct Uploader<'a> {
+    pub(crate) device: &'a Device,
+    pub(crate) queue: &'a Queue,
+    pub(crate) encoder: &'a mut CommandEncoder,
+    pub(crate) belt: &'a mut StagingBelt,
+}
+
+impl<'a> Uploader<'a> {
+    // The queue for uploads the staging belt doesn't handle, such as
+    // texture writes
+    pub fn queue(&self) -> &'a Queue {
+        self.queue
+    }
+
+    // The data size and the offset have to be multiples of
+    // COPY_BUFFER_ALIGNMENT. Empty writes are ignored.
+    pub fn write_buffer(&mut self, buffer: &Buffer, offset: BufferAddress, data: &[u8]) {
+        let Some(size) = BufferSize::new(data.len() as BufferAddress) else {
+            return;
+        };
+
+        self.belt
+            .write_buffer(self.encoder, buffer, offset, size, self.device)
+            .copy_from_slice(data);
+    }
+}
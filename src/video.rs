@@ -0,0 +1,595 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use glam::{vec4, Vec4};
+use shader::VideoConversion;
+use wgpu::{util::DeviceExt, *};
+
+use crate::{
+    image_atlas::{AtlasFullError, ImageAtlas},
+    PipelineInterface, Renderer,
+};
+
+const ENTRY_POINTS: [&str; 2] = ["blit::blit_vertex", "video::video_fragment"];
+
+// Layout of the planes of the decoded frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    // Full resolution y plane followed by a plane of interleaved u and v at
+    // half the width and height
+    Nv12,
+    // Full resolution y plane followed by separate u and v planes at half the
+    // width and height
+    I420,
+}
+
+// Color matrix the frames were encoded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvMatrix {
+    Bt601,
+    Bt709,
+    Bt2020,
+}
+
+impl YuvMatrix {
+    // Weights of red and blue in the luma
+    fn weights(&self) -> (f32, f32) {
+        match self {
+            YuvMatrix::Bt601 => (0.299, 0.114),
+            YuvMatrix::Bt709 => (0.2126, 0.0722),
+            YuvMatrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+// Range of the 8 bit values of the frames. Most video uses the limited range
+// of 16 to 235 for luma and 16 to 240 for chroma.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvRange {
+    Limited,
+    Full,
+}
+
+// Tightly packed planes of a decoded 8 bit frame
+#[derive(Debug, Clone, Copy)]
+pub enum VideoFrame<'a> {
+    Nv12 {
+        y: &'a [u8],
+        uv: &'a [u8],
+    },
+    I420 {
+        y: &'a [u8],
+        u: &'a [u8],
+        v: &'a [u8],
+    },
+}
+
+impl VideoFrame<'_> {
+    fn format(&self) -> VideoFormat {
+        match self {
+            VideoFrame::Nv12 { .. } => VideoFormat::Nv12,
+            VideoFrame::I420 { .. } => VideoFormat::I420,
+        }
+    }
+}
+
+// Why a frame couldn't be written to a video texture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VideoFrameError {
+    // The frame has another format than the texture
+    FormatMismatch {
+        texture: VideoFormat,
+        frame: VideoFormat,
+    },
+    // A plane the format needs wasn't passed
+    MissingPlane(&'static str),
+    // The length of a plane doesn't match the size of the texture
+    PlaneSize {
+        plane: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    // The image atlas has no room for the frame
+    AtlasFull,
+}
+
+impl fmt::Display for VideoFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VideoFrameError::FormatMismatch { texture, frame } => {
+                write!(
+                    f,
+                    "Can't write a {frame:?} frame to a {texture:?} video texture"
+                )
+            }
+            VideoFrameError::MissingPlane(plane) => {
+                write!(f, "The frame has no {plane} plane")
+            }
+            VideoFrameError::PlaneSize {
+                plane,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "The {plane} plane is {actual} bytes instead of the {expected} of the video size"
+            ),
+            VideoFrameError::AtlasFull => write!(f, "{AtlasFullError}"),
+        }
+    }
+}
+
+impl std::error::Error for VideoFrameError {}
+
+impl From<AtlasFullError> for VideoFrameError {
+    fn from(_: AtlasFullError) -> Self {
+        VideoFrameError::AtlasFull
+    }
+}
+
+// Rows of the matrix converting y, u, v and one to rgb
+pub(crate) fn yuv_to_rgb(matrix: YuvMatrix, range: YuvRange) -> [Vec4; 3] {
+    let (y_scale, chroma_scale) = match range {
+        YuvRange::Limited => (255. / 219., 255. / 224.),
+        YuvRange::Full => (1., 1.),
+    };
+    let y_offset = match range {
+        YuvRange::Limited => -16. / 255. * y_scale,
+        YuvRange::Full => 0.,
+    };
+    let chroma_offset = -128. / 255. * chroma_scale;
+
+    let (kr, kb) = matrix.weights();
+    let kg = 1. - kr - kb;
+    let red_v = 2. * (1. - kr);
+    let blue_u = 2. * (1. - kb);
+    let green_u = 2. * kb * (1. - kb) / kg;
+    let green_v = 2. * kr * (1. - kr) / kg;
+
+    [
+        vec4(
+            y_scale,
+            0.,
+            red_v * chroma_scale,
+            y_offset + red_v * chroma_offset,
+        ),
+        vec4(
+            y_scale,
+            -green_u * chroma_scale,
+            -green_v * chroma_scale,
+            y_offset - (green_u + green_v) * chroma_offset,
+        ),
+        vec4(
+            y_scale,
+            blue_u * chroma_scale,
+            0.,
+            y_offset + blue_u * chroma_offset,
+        ),
+    ]
+}
+
+// An image in the image atlas showing decoded video frames. Sprites and
+// pattern fills draw it by the name it was created with. The yuv planes are
+// converted to rgb in a shader when a frame is written. Frames decoded on the
+// gpu or imported from external memory skip the upload with
+// write_frame_from_textures. Frames larger than the atlas are scaled down to
+// fit it. Like other gpu resources, video textures have to be created again
+// after the renderer is recreated.
+pub struct VideoTexture {
+    name: String,
+    width: u32,
+    height: u32,
+    format: VideoFormat,
+    luma: Texture,
+    chroma: Texture,
+    // Only used for I420, a single pixel otherwise
+    chroma_v: Texture,
+    conversion: Buffer,
+    // Linear so that the half resolution chroma is interpolated
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    render_pipeline: RenderPipeline,
+    image_atlas: Arc<Mutex<ImageAtlas>>,
+}
+
+impl VideoTexture {
+    // Frames are converted with the BT.709 matrix and the limited range
+    // unless set otherwise
+    pub fn new(
+        renderer: &Renderer,
+        name: &str,
+        width: u32,
+        height: u32,
+        format: VideoFormat,
+    ) -> Result<Self, AtlasFullError> {
+        let Renderer {
+            device,
+            shader,
+            image_atlas,
+            ..
+        } = renderer;
+
+        let (chroma_width, chroma_height) = chroma_size(width, height);
+        let (chroma_format, chroma_v_size) = match format {
+            VideoFormat::Nv12 => (TextureFormat::Rg8Unorm, (1, 1)),
+            VideoFormat::I420 => (TextureFormat::R8Unorm, (chroma_width, chroma_height)),
+        };
+        let luma = create_plane(device, width, height, TextureFormat::R8Unorm);
+        let chroma = create_plane(device, chroma_width, chroma_height, chroma_format);
+        let chroma_v = create_plane(
+            device,
+            chroma_v_size.0,
+            chroma_v_size.1,
+            TextureFormat::R8Unorm,
+        );
+
+        let conversion = device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some("Video conversion buffer"),
+            contents: bytemuck::cast_slice(&[video_conversion(
+                format,
+                YuvMatrix::Bt709,
+                YuvRange::Limited,
+            )]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Video sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let layout_entries = layout_entries();
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Video bind group layout"),
+            entries: &layout_entries,
+        });
+
+        renderer.verify_pipeline(&PipelineInterface {
+            entry_points: &ENTRY_POINTS,
+            bind_group_layouts: &[&layout_entries],
+            vertex_buffers: &[],
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Video Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Video Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: ENTRY_POINTS[0],
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: ENTRY_POINTS[1],
+                targets: &[Some(ColorTargetState {
+                    format: image_atlas.lock().unwrap().texture().format(),
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+
+        image_atlas
+            .lock()
+            .unwrap()
+            .reserve(name, width, height)
+            .ok_or(AtlasFullError)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            width,
+            height,
+            format,
+            luma,
+            chroma,
+            chroma_v,
+            conversion,
+            sampler,
+            bind_group_layout,
+            render_pipeline,
+            image_atlas: image_atlas.clone(),
+        })
+    }
+
+    pub fn set_color_space(&mut self, renderer: &Renderer, matrix: YuvMatrix, range: YuvRange) {
+        renderer.queue.write_buffer(
+            &self.conversion,
+            0,
+            bytemuck::cast_slice(&[video_conversion(self.format, matrix, range)]),
+        );
+    }
+
+    pub fn with_color_space(
+        mut self,
+        renderer: &Renderer,
+        matrix: YuvMatrix,
+        range: YuvRange,
+    ) -> Self {
+        self.set_color_space(renderer, matrix, range);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    // Uploads the planes of the frame and converts them into the atlas. Fails
+    // when the frame doesn't match the format and size of the texture, or
+    // when the region of the texture was replaced and the atlas has no room
+    // for it.
+    pub fn write_frame(
+        &mut self,
+        renderer: &Renderer,
+        frame: VideoFrame,
+    ) -> Result<(), VideoFrameError> {
+        check_frame(self.format, self.width, self.height, &frame)?;
+        let (chroma_width, chroma_height) = chroma_size(self.width, self.height);
+        match frame {
+            VideoFrame::Nv12 { y, uv } => {
+                write_plane(renderer, &self.luma, y, self.width, self.height, 1);
+                write_plane(renderer, &self.chroma, uv, chroma_width, chroma_height, 2);
+            }
+            VideoFrame::I420 { y, u, v } => {
+                write_plane(renderer, &self.luma, y, self.width, self.height, 1);
+                write_plane(renderer, &self.chroma, u, chroma_width, chroma_height, 1);
+                write_plane(renderer, &self.chroma_v, v, chroma_width, chroma_height, 1);
+            }
+        }
+
+        Ok(self.convert(renderer, &self.luma, &self.chroma, &self.chroma_v)?)
+    }
+
+    // Converts planes which are already on the gpu into the atlas, for
+    // example frames from a hardware decoder imported with
+    // Device::create_texture_from_hal. The luma is R8Unorm, the chroma
+    // Rg8Unorm for NV12 and R8Unorm for I420, where chroma_v is required.
+    pub fn write_frame_from_textures(
+        &mut self,
+        renderer: &Renderer,
+        luma: &Texture,
+        chroma: &Texture,
+        chroma_v: Option<&Texture>,
+    ) -> Result<(), VideoFrameError> {
+        let chroma_v = match (self.format, chroma_v) {
+            (VideoFormat::I420, None) => return Err(VideoFrameError::MissingPlane("v")),
+            (_, Some(chroma_v)) => chroma_v,
+            (_, None) => &self.chroma_v,
+        };
+        Ok(self.convert(renderer, luma, chroma, chroma_v)?)
+    }
+
+    fn convert(
+        &self,
+        renderer: &Renderer,
+        luma: &Texture,
+        chroma: &Texture,
+        chroma_v: &Texture,
+    ) -> Result<(), AtlasFullError> {
+        let luma_view = luma.create_view(&Default::default());
+        let chroma_view = chroma.create_view(&Default::default());
+        let chroma_v_view = chroma_v.create_view(&Default::default());
+        let bind_group = renderer.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Video bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&luma_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&chroma_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&chroma_v_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: self.conversion.as_entire_binding(),
+                },
+            ],
+        });
+
+        // Reserved again in case an image registered with the same name replaced
+        // the region
+        let mut image_atlas = self.image_atlas.lock().unwrap();
+        let rect = image_atlas
+            .reserve(&self.name, self.width, self.height)
+            .ok_or(AtlasFullError)?;
+        let atlas_view = image_atlas.texture().create_view(&Default::default());
+
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Video Encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Video Render Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &atlas_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_viewport(rect.x, rect.y, rect.z, rect.w, 0., 1.);
+            render_pass.set_scissor_rect(
+                rect.x as u32,
+                rect.y as u32,
+                rect.z as u32,
+                rect.w as u32,
+            );
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+}
+
+impl Drop for VideoTexture {
+    fn drop(&mut self) {
+        self.image_atlas.lock().unwrap().release(&self.name);
+    }
+}
+
+fn video_conversion(format: VideoFormat, matrix: YuvMatrix, range: YuvRange) -> VideoConversion {
+    let [red, green, blue] = yuv_to_rgb(matrix, range);
+    VideoConversion {
+        red,
+        green,
+        blue,
+        interleaved_chroma: (format == VideoFormat::Nv12) as u32,
+        _padding: [0; 3],
+    }
+}
+
+// Chroma planes are subsampled by two in both directions, rounding up
+fn chroma_size(width: u32, height: u32) -> (u32, u32) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+// Checks that the frame has the format of the texture and planes of its size
+pub(crate) fn check_frame(
+    format: VideoFormat,
+    width: u32,
+    height: u32,
+    frame: &VideoFrame,
+) -> Result<(), VideoFrameError> {
+    if frame.format() != format {
+        return Err(VideoFrameError::FormatMismatch {
+            texture: format,
+            frame: frame.format(),
+        });
+    }
+    let (chroma_width, chroma_height) = chroma_size(width, height);
+    let luma_size = (width * height) as usize;
+    let chroma_size = (chroma_width * chroma_height) as usize;
+    let planes = match frame {
+        VideoFrame::Nv12 { y, uv } => vec![("y", y, luma_size), ("uv", uv, chroma_size * 2)],
+        VideoFrame::I420 { y, u, v } => vec![
+            ("y", y, luma_size),
+            ("u", u, chroma_size),
+            ("v", v, chroma_size),
+        ],
+    };
+    for (plane, data, expected) in planes {
+        if data.len() != expected {
+            return Err(VideoFrameError::PlaneSize {
+                plane,
+                expected,
+                actual: data.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn create_plane(device: &Device, width: u32, height: u32, format: TextureFormat) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("Video plane texture"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+fn write_plane(
+    renderer: &Renderer,
+    texture: &Texture,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    bytes_per_pixel: u32,
+) {
+    renderer.queue.write_texture(
+        ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        data,
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(width * bytes_per_pixel),
+            rows_per_image: Some(height),
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+fn layout_entries() -> [BindGroupLayoutEntry; 5] {
+    let plane = |binding| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    [
+        plane(0),
+        plane(1),
+        plane(2),
+        BindGroupLayoutEntry {
+            binding: 3,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 4,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ]
+}
@@ -0,0 +1,37 @@
+// Rectangle of the frame in pixels which a scene is drawn into, for split
+// views or picture in picture. The scene sees the viewport as its surface,
+// with the top left of the viewport at the top left of the surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    // Clamps the viewport to a frame of the given size
+    pub(crate) fn clamp(&self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Self::new(x, y, self.width.min(width - x), self.height.min(height - y))
+    }
+
+    // Moves a scissor rect relative to the viewport into the frame
+    pub(crate) fn offset_scissor(&self, [x, y, width, height]: [u32; 4]) -> [u32; 4] {
+        [self.x + x, self.y + y, width, height]
+    }
+}
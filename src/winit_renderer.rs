@@ -1,121 +1,311 @@
-use rust_embed::RustEmbed;
-use wgpu::*;
+use std::{cmp::Reverse, time::Instant};
+
+use wgpu::PresentMode;
 use winit::{
     event::{Event, StartCause, WindowEvent},
-    window::Window,
+    monitor::{MonitorHandle, VideoMode},
+    window::{Fullscreen, Window},
+};
+
+use crate::{
+    raw_window_renderer::{DrawOutcome, RawWindowRenderer, ResizeStrategy, SurfaceErrorEvent},
+    renderer::{Drawable, DrawableError, RendererError},
+    AssetSource, CoordinateOrigin, FramePacer, PacingMode, Renderer, Scene,
 };
 
-use crate::{renderer::Drawable, Renderer, Scene};
+// How the window covers its monitor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    // A borderless window covering the monitor, which keeps the video mode of
+    // the desktop
+    BorderlessFullscreen,
+    // Takes over the monitor with the video mode closest to the size and
+    // refresh rate, or to the current ones of the monitor when unset. Falls
+    // back to borderless fullscreen where there are no video modes to pick
+    // from, such as on Wayland.
+    ExclusiveFullscreen {
+        size: Option<(u32, u32)>,
+        refresh_rate_millihertz: Option<u32>,
+    },
+}
+
+// Size, refresh rate and bit depth of a video mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ModeInfo {
+    pub size: (u32, u32),
+    pub refresh_rate_millihertz: u32,
+    pub bit_depth: u16,
+}
 
+// Thin wrapper around RawWindowRenderer which forwards the relevant winit
+// events
 pub struct WinitRenderer<'a> {
-    pub instance: Instance,
-    pub surface: Option<Surface<'a>>,
-    pub surface_config: SurfaceConfiguration,
     window_initializing: bool,
-    renderer: Renderer,
+    display_mode: DisplayMode,
+    frame_pacer: FramePacer,
+    renderer: RawWindowRenderer<'a>,
 }
 
 impl<'a> WinitRenderer<'a> {
-    // Creating some of the wgpu types requires async code
-    pub async fn new(window: &'a Window) -> Self {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::VULKAN,
-            ..Default::default()
-        });
-
-        let surface = instance.create_surface(window).unwrap();
-
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-
-        let swapchain_capabilities = surface.get_capabilities(&adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
-
+    // Creating some of the wgpu types requires async code. Fails when no
+    // adapter can run the renderer.
+    pub async fn new(window: &'a Window) -> Result<Self, RendererError> {
         let size = window.inner_size();
-        let surface_config = SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
-            format: swapchain_format,
-            width: size.width,
-            height: size.height,
-            present_mode: PresentMode::Fifo,
-            alpha_mode: swapchain_capabilities.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
-        };
+        Ok(Self {
+            window_initializing: false,
+            display_mode: DisplayMode::default(),
+            frame_pacer: FramePacer::new().with_refresh_rate_millihertz(
+                window
+                    .current_monitor()
+                    .and_then(|monitor| monitor.refresh_rate_millihertz()),
+            ),
+            renderer: RawWindowRenderer::new(window, size.width, size.height)
+                .await?
+                .with_scale_factor(window.scale_factor() as f32),
+        })
+    }
 
-        let renderer = Renderer::new(size.width, size.height, adapter, swapchain_format).await;
-        surface.configure(&renderer.device, &surface_config);
+    pub fn raw_window_renderer(&self) -> &RawWindowRenderer<'a> {
+        &self.renderer
+    }
 
-        Self {
-            instance,
-            window_initializing: false,
-            surface: Some(surface),
-            surface_config,
-            renderer,
-        }
+    pub fn raw_window_renderer_mut(&mut self) -> &mut RawWindowRenderer<'a> {
+        &mut self.renderer
+    }
+
+    pub fn renderer(&self) -> &Renderer {
+        self.renderer.renderer()
     }
 
-    pub fn add_drawable<T: Drawable + 'static>(&mut self) {
-        self.renderer.add_drawable::<T>();
+    pub fn renderer_mut(&mut self) -> &mut Renderer {
+        self.renderer.renderer_mut()
+    }
+
+    pub fn add_drawable<T: Drawable + 'static>(&mut self) -> Result<(), DrawableError> {
+        self.renderer.add_drawable::<T>()
     }
 
     pub fn with_drawable<T: Drawable + 'static>(mut self) -> Self {
-        self.add_drawable::<T>();
+        if let Err(error) = self.add_drawable::<T>() {
+            panic!("{error}");
+        }
+        self
+    }
+
+    pub fn set_antialiasing_width(&mut self, width: f32) {
+        self.renderer.set_antialiasing_width(width);
+    }
+
+    pub fn with_antialiasing_width(mut self, width: f32) -> Self {
+        self.set_antialiasing_width(width);
+        self
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.renderer.set_scale_factor(scale_factor);
+    }
+
+    pub fn with_scale_factor(mut self, scale_factor: f32) -> Self {
+        self.set_scale_factor(scale_factor);
+        self
+    }
+
+    pub fn set_coordinate_origin(&mut self, origin: CoordinateOrigin) {
+        self.renderer.set_coordinate_origin(origin);
+    }
+
+    pub fn with_coordinate_origin(mut self, origin: CoordinateOrigin) -> Self {
+        self.set_coordinate_origin(origin);
         self
     }
 
-    pub fn add_default_drawables<A: RustEmbed + 'static>(&mut self) {
-        self.renderer.add_default_drawables::<A>();
+    pub fn set_pixels_per_unit(&mut self, pixels_per_unit: f32) {
+        self.renderer.set_pixels_per_unit(pixels_per_unit);
     }
 
-    pub fn with_default_drawables<A: RustEmbed + 'static>(mut self) -> Self {
-        self.add_default_drawables::<A>();
+    pub fn with_pixels_per_unit(mut self, pixels_per_unit: f32) -> Self {
+        self.set_pixels_per_unit(pixels_per_unit);
         self
     }
 
-    fn update_surface(&mut self, surface: Surface<'a>) {
-        let swapchain_capabilities = surface.get_capabilities(&self.renderer.adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
-        self.surface_config.format = swapchain_format;
-        self.surface_config.alpha_mode = swapchain_capabilities.alpha_modes[0];
-        surface.configure(&self.renderer.device, &self.surface_config);
-        self.surface = Some(surface);
+    pub fn set_fixed_time_step(&mut self, time_step: Option<f32>) {
+        self.renderer.set_fixed_time_step(time_step);
     }
 
-    fn clear_surface(&mut self) {
-        self.surface = None;
+    pub fn with_fixed_time_step(mut self, time_step: Option<f32>) -> Self {
+        self.set_fixed_time_step(time_step);
+        self
     }
 
-    fn resize(&mut self, new_width: u32, new_height: u32) {
-        self.surface_config.width = new_width;
-        self.surface_config.height = new_height;
+    pub fn set_resize_strategy(&mut self, resize_strategy: ResizeStrategy) {
+        self.renderer.set_resize_strategy(resize_strategy);
+    }
 
-        if new_width != 0 && new_height != 0 {
-            if let Some(surface) = &self.surface {
-                surface.configure(&self.renderer.device, &self.surface_config);
+    pub fn with_resize_strategy(mut self, resize_strategy: ResizeStrategy) -> Self {
+        self.set_resize_strategy(resize_strategy);
+        self
+    }
+
+    pub fn set_surface_error_callback(
+        &mut self,
+        callback: impl FnMut(&SurfaceErrorEvent) + 'static,
+    ) {
+        self.renderer.set_surface_error_callback(callback);
+    }
+
+    pub fn with_surface_error_callback(
+        mut self,
+        callback: impl FnMut(&SurfaceErrorEvent) + 'static,
+    ) -> Self {
+        self.set_surface_error_callback(callback);
+        self
+    }
+
+    pub fn add_builtin_drawables(&mut self) -> Result<(), DrawableError> {
+        self.renderer.add_builtin_drawables()
+    }
+
+    pub fn with_builtin_drawables(mut self) -> Self {
+        if let Err(error) = self.add_builtin_drawables() {
+            panic!("{error}");
+        }
+        self
+    }
+
+    pub fn add_default_drawables(
+        &mut self,
+        assets: impl AssetSource + 'static,
+    ) -> Result<(), DrawableError> {
+        self.renderer.add_default_drawables(assets)
+    }
+
+    pub fn with_default_drawables(mut self, assets: impl AssetSource + 'static) -> Self {
+        if let Err(error) = self.add_default_drawables(assets) {
+            panic!("{error}");
+        }
+        self
+    }
+
+    pub fn set_asset_source(&mut self, assets: impl AssetSource + 'static) {
+        self.renderer.set_asset_source(assets);
+    }
+
+    pub fn with_asset_source(mut self, assets: impl AssetSource + 'static) -> Self {
+        self.set_asset_source(assets);
+        self
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.renderer.set_present_mode(present_mode);
+    }
+
+    pub fn with_present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.set_present_mode(present_mode);
+        self
+    }
+
+    // Enters or leaves fullscreen on the current monitor of the window and
+    // renegotiates the surface for the new mode, as exclusive fullscreen can
+    // change what the surface supports. The window is redrawn afterwards.
+    pub fn set_display_mode(&mut self, window: &'a Window, display_mode: DisplayMode) {
+        let monitor = window.current_monitor();
+        let fullscreen = match display_mode {
+            DisplayMode::Windowed => None,
+            DisplayMode::BorderlessFullscreen => Some(Fullscreen::Borderless(monitor)),
+            DisplayMode::ExclusiveFullscreen {
+                size,
+                refresh_rate_millihertz,
+            } => {
+                let video_mode = monitor
+                    .as_ref()
+                    .and_then(|monitor| closest_video_mode(monitor, size, refresh_rate_millihertz));
+                Some(match video_mode {
+                    Some(video_mode) => Fullscreen::Exclusive(video_mode),
+                    None => Fullscreen::Borderless(monitor),
+                })
             }
-            self.renderer.resize(new_width, new_height);
+        };
+        window.set_fullscreen(fullscreen);
+        self.display_mode = display_mode;
+
+        let size = window.inner_size();
+        self.renderer.renegotiate_surface(size.width, size.height);
+        self.update_refresh_rate(window);
+        window.request_redraw();
+    }
+
+    // The last requested display mode. The user can still leave fullscreen
+    // through the window manager.
+    pub fn display_mode(&self) -> DisplayMode {
+        self.display_mode
+    }
+
+    // Refresh rate the window is presented at, from the video mode in
+    // exclusive fullscreen and from the monitor otherwise. Useful for pacing
+    // animations.
+    pub fn refresh_rate_millihertz(&self, window: &Window) -> Option<u32> {
+        match window.fullscreen() {
+            Some(Fullscreen::Exclusive(video_mode)) => Some(video_mode.refresh_rate_millihertz()),
+            _ => window.current_monitor()?.refresh_rate_millihertz(),
         }
     }
 
+    pub fn frame_pacer(&self) -> &FramePacer {
+        &self.frame_pacer
+    }
+
+    pub fn frame_pacer_mut(&mut self) -> &mut FramePacer {
+        &mut self.frame_pacer
+    }
+
+    pub fn set_pacing_mode(&mut self, mode: PacingMode) {
+        self.frame_pacer.set_mode(mode);
+    }
+
+    pub fn with_pacing_mode(mut self, mode: PacingMode) -> Self {
+        self.set_pacing_mode(mode);
+        self
+    }
+
+    // Whether the display refreshes when a frame is presented, which winit
+    // can't tell
+    pub fn set_variable_refresh(&mut self, enabled: bool) {
+        self.frame_pacer.set_variable_refresh(enabled);
+    }
+
+    pub fn with_variable_refresh(mut self, enabled: bool) -> Self {
+        self.set_variable_refresh(enabled);
+        self
+    }
+
+    // When to draw the next frame of an animation, for
+    // `ControlFlow::WaitUntil`. None when it can be drawn right away.
+    pub fn next_frame_time(&self) -> Option<Instant> {
+        self.frame_pacer.next_frame_time()
+    }
+
+    fn update_refresh_rate(&mut self, window: &Window) {
+        let refresh_rate = self.refresh_rate_millihertz(window);
+        self.frame_pacer.set_refresh_rate_millihertz(refresh_rate);
+    }
+
     pub fn handle_event<T>(&mut self, window: &'a Window, event: &Event<T>) {
         match event {
             Event::NewEvents(start_cause) => {
                 self.window_initializing = start_cause == &StartCause::Init;
             }
             Event::Resumed => {
-                let surface = self.instance.create_surface(window).unwrap();
-                self.update_surface(surface);
+                let size = window.inner_size();
+                self.renderer.resume(window, size.width, size.height);
                 window.request_redraw();
             }
             Event::Suspended => {
-                self.clear_surface();
+                self.renderer.suspend();
+            }
+            Event::MemoryWarning => {
+                self.renderer.handle_memory_warning();
             }
             Event::WindowEvent {
                 event: WindowEvent::Resized(new_size),
@@ -125,31 +315,94 @@ impl<'a> WinitRenderer<'a> {
                     return;
                 }
 
-                self.resize(new_size.width, new_size.height);
-
+                // Minimized windows report a zero size on some platforms.
+                // Drawing resumes with the redraw requested on restore.
+                self.renderer.resize(new_size.width, new_size.height);
+                if new_size.width != 0 && new_size.height != 0 {
+                    window.request_redraw();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
+                ..
+            } => {
+                self.renderer.set_scale_factor(*scale_factor as f32);
                 window.request_redraw();
             }
+            // The window may have moved to a monitor with another refresh rate
+            Event::WindowEvent {
+                event: WindowEvent::Moved(_),
+                ..
+            } => {
+                self.update_refresh_rate(window);
+            }
+            // The frames drawn in response are measured from the input when
+            // tracking latency
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput { .. }
+                    | WindowEvent::Ime(_)
+                    | WindowEvent::MouseInput { .. }
+                    | WindowEvent::MouseWheel { .. }
+                    | WindowEvent::CursorMoved { .. }
+                    | WindowEvent::Touch(_),
+                ..
+            } => {
+                self.renderer.renderer_mut().mark_input();
+            }
             _ => {}
         }
     }
 
-    pub fn draw(&mut self, scene: &Scene) -> bool {
-        let Some(surface) = &mut self.surface else {
-            return true;
-        };
-
-        match surface.get_current_texture() {
-            Ok(frame) => {
-                self.renderer.render(scene, &frame.texture);
-                frame.present();
-                true
-            }
-            Err(SurfaceError::Lost) => {
-                surface.configure(&self.renderer.device, &self.surface_config);
-                false
-            }
-            Err(SurfaceError::OutOfMemory) => false,
-            _ => false,
+    pub fn draw(&mut self, scene: &Scene) -> DrawOutcome {
+        let outcome = self.renderer.draw(scene);
+        if outcome.is_presented() {
+            self.frame_pacer.frame_presented(Instant::now());
         }
+        outcome
     }
 }
+
+// The video mode of the monitor closest to the size and refresh rate, which
+// default to the current ones of the monitor
+pub fn closest_video_mode(
+    monitor: &MonitorHandle,
+    size: Option<(u32, u32)>,
+    refresh_rate_millihertz: Option<u32>,
+) -> Option<VideoMode> {
+    let current_size = monitor.size();
+    let size = size.unwrap_or((current_size.width, current_size.height));
+    let refresh_rate_millihertz = refresh_rate_millihertz
+        .or(monitor.refresh_rate_millihertz())
+        .unwrap_or(60_000);
+
+    let video_modes: Vec<_> = monitor.video_modes().collect();
+    let modes: Vec<_> = video_modes
+        .iter()
+        .map(|video_mode| ModeInfo {
+            size: video_mode.size().into(),
+            refresh_rate_millihertz: video_mode.refresh_rate_millihertz(),
+            bit_depth: video_mode.bit_depth(),
+        })
+        .collect();
+    let index = closest_mode(&modes, size, refresh_rate_millihertz)?;
+    Some(video_modes[index].clone())
+}
+
+// Index of the mode closest in size, then in refresh rate, preferring the
+// deepest color among otherwise equal modes
+pub(crate) fn closest_mode(
+    modes: &[ModeInfo],
+    size: (u32, u32),
+    refresh_rate_millihertz: u32,
+) -> Option<usize> {
+    (0..modes.len()).min_by_key(|index| {
+        let mode = &modes[*index];
+        (
+            mode.size.0.abs_diff(size.0) + mode.size.1.abs_diff(size.1),
+            mode.refresh_rate_millihertz
+                .abs_diff(refresh_rate_millihertz),
+            Reverse(mode.bit_depth),
+        )
+    })
+}